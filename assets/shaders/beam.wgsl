@@ -0,0 +1,10 @@
+#import bevy_pbr::forward_io::VertexOutput
+
+@group(1) @binding(0) var<uniform> color: vec4<f32>;
+
+@fragment
+fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
+    // Fade the beam out towards its top
+    let fade = 1.0 - clamp(mesh.uv.y, 0.0, 1.0);
+    return vec4<f32>(color.rgb, color.a * fade);
+}
@@ -0,0 +1,60 @@
+// Vertex shader of chunk meshes in the packed face format. Every vertex has its position and the
+// number of its face (see `Face::as_face_number`), the normal, uv and tangent are derived from
+// them the same way `face_uv_and_tangent` does on the CPU.
+
+#import bevy_pbr::{
+    mesh_functions,
+    forward_io::VertexOutput,
+    view_transformations::position_world_to_clip,
+}
+#import bevy_render::instance_index::get_instance_index
+
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) position: vec3<f32>,
+#ifdef VERTEX_COLORS
+    @location(5) color: vec4<f32>,
+#endif
+    @location(8) face: u32,
+};
+
+// Left, right, bottom, top, back and front
+fn face_normal(face: u32) -> vec3<f32> {
+    var normal = vec3<f32>(0.0);
+    normal[face / 2u] = select(-1.0, 1.0, face % 2u == 1u);
+    return normal;
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    let model = mesh_functions::get_model_matrix(vertex.instance_index);
+    let normal = face_normal(vertex.face);
+
+    // u goes right and v goes down the face, up is towards -z on top and bottom faces
+    let up = select(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(0.0, 0.0, -1.0), normal.y != 0.0);
+    let right = cross(up, normal);
+
+    out.world_normal = mesh_functions::mesh_normal_local_to_world(normal, get_instance_index(vertex.instance_index));
+    out.world_position = mesh_functions::mesh_position_local_to_world(model, vec4<f32>(vertex.position, 1.0));
+    out.position = position_world_to_clip(out.world_position.xyz);
+    out.uv = vec2<f32>(dot(vertex.position, right), -dot(vertex.position, up));
+    out.world_tangent = mesh_functions::mesh_tangent_local_to_world(
+        model,
+        vec4<f32>(right, 1.0),
+        get_instance_index(vertex.instance_index)
+    );
+#ifdef VERTEX_COLORS
+    out.color = vertex.color;
+#endif
+#ifdef VERTEX_OUTPUT_INSTANCE_INDEX
+    out.instance_index = get_instance_index(vertex.instance_index);
+#endif
+
+#ifdef BASE_INSTANCE_WORKAROUND
+    // Same workaround as Bevy's mesh shader, https://github.com/bevyengine/bevy/issues/10509
+    out.position.x += min(f32(get_instance_index(0u)), 0.0);
+#endif
+
+    return out;
+}
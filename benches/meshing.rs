@@ -0,0 +1,87 @@
+//! Baseline numbers for meshing and voxel storage.
+//!
+//! There is no octree in the engine yet, chunk voxel access is benchmarked instead.
+//! Add octree insert/get benches here once one exists.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use voxels_bevy_test::engine::{
+    block_registry::BlockRegistry,
+    chunk::{Chunk, ChunkPosition},
+    coords::LocalVoxelPos,
+    generator::{PerlinHeightmapWorldGenerator, WorldGeneratorConfig},
+    meshing::{mesh_chunk, ChunkMesher, MeshOptions, MeshingStrategy},
+    shapes::ShapeRegistry,
+    voxel::{Block, Voxel},
+};
+
+fn chunk_with(voxel_at: impl Fn(LocalVoxelPos) -> Voxel) -> Chunk {
+    let mut chunk = Chunk::new(ChunkPosition::new(0, 0, 0));
+    chunk.generate_with(|_, pos| voxel_at(pos));
+    chunk
+}
+
+/// Every other voxel is solid, the most faces a chunk can have
+fn checkerboard() -> Chunk {
+    chunk_with(|pos| {
+        if (pos.x + pos.y + pos.z) % 2 == 0 { Voxel::from(Block::Stone) } else { Voxel::Empty }
+    })
+}
+
+fn solid() -> Chunk {
+    chunk_with(|_| Voxel::from(Block::Stone))
+}
+
+/// Surface chunk of the default terrain generator
+fn terrain() -> Chunk {
+    let config = WorldGeneratorConfig::default_with(PerlinHeightmapWorldGenerator::default());
+    config.generate(ChunkPosition::new(0, 0, 0)).0
+}
+
+fn bench_meshing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Chunk::build");
+    let mesher = ChunkMesher::default();
+    for (name, chunk) in [("checkerboard", checkerboard()), ("solid", solid()), ("terrain", terrain())] {
+        group.bench_function(name, |b| b.iter(|| black_box(chunk.build(&mesher))));
+    }
+    group.finish();
+}
+
+fn bench_strategies(c: &mut Criterion) {
+    let (blocks, shapes) = (BlockRegistry::builtin(), ShapeRegistry::builtin());
+    let voxels = checkerboard().reader().voxels().to_slice().into_owned();
+    let mut group = c.benchmark_group("mesh_chunk checkerboard");
+    for strategy in MeshingStrategy::ALL {
+        let opts = MeshOptions { strategy, ..MeshOptions::new(&blocks, &shapes) };
+        group.bench_function(strategy.name(), |b| b.iter(|| black_box(mesh_chunk(&voxels, opts))));
+    }
+    group.finish();
+}
+
+fn bench_voxel_access(c: &mut Criterion) {
+    let chunk = terrain();
+    let positions = LocalVoxelPos::iter().collect::<Vec<_>>();
+
+    c.bench_function("Chunk::get all voxels", |b| {
+        b.iter(|| {
+            for pos in positions.iter() {
+                black_box(chunk.get(*pos));
+            }
+        })
+    });
+
+    c.bench_function("Chunk::set all voxels", |b| {
+        b.iter_batched(
+            || Chunk::new(ChunkPosition::new(0, 0, 0)),
+            |mut chunk| {
+                for pos in positions.iter() {
+                    chunk.set(*pos, Voxel::from(Block::Dirt));
+                }
+                chunk
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, bench_meshing, bench_strategies, bench_voxel_access);
+criterion_main!(benches);
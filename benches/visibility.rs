@@ -0,0 +1,62 @@
+//! Visibility search against synthetic worlds, for comparing culling changes.
+//!
+//! Chunks are described only by their visibility masks, so no voxels are generated.
+//! Every world is fully loaded, the camera stands in chunk (0, 0, 0).
+
+use bevy::{
+    prelude::*,
+    render::{camera::CameraProjection, primitives::Frustum},
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use voxels_bevy_test::engine::{
+    chunk::ChunkPosition,
+    generator::{find_visible_chunks, ChunkLookup, WorldGeneratorConfig},
+};
+
+const OPEN: u8 = 0b000000;
+const SOLID: u8 = 0b111111;
+
+/// Ground at y = 0, air above it
+fn open_plain(chunk: &ChunkPosition) -> ChunkLookup {
+    ChunkLookup::Loaded(if chunk.y < 0 { SOLID } else { OPEN })
+}
+
+/// Every chunk has a random half of its faces opaque, like cave systems underground
+fn dense_caves(chunk: &ChunkPosition) -> ChunkLookup {
+    let hash = (chunk.x as u32).wrapping_mul(0x9e37_79b9) ^ (chunk.y as u32).wrapping_mul(0x85eb_ca6b) ^ (chunk.z as u32).wrapping_mul(0xc2b2_ae35);
+    let hash = hash ^ (hash >> 15);
+    ChunkLookup::Loaded((hash.wrapping_mul(0x2c1b_3c6d) >> 8) as u8 & SOLID)
+}
+
+/// A solid wall two chunks in front of the camera, the search should stop right there
+fn wall(chunk: &ChunkPosition) -> ChunkLookup {
+    ChunkLookup::Loaded(if chunk.z == -2 { SOLID } else { OPEN })
+}
+
+fn camera(looking_at: Vec3) -> (Transform, Frustum) {
+    let transform = Transform::from_xyz(8.0, 8.0, 8.0).looking_at(looking_at, Vec3::Y);
+    let projection = PerspectiveProjection::default();
+    let view_projection = projection.get_projection_matrix() * transform.compute_matrix().inverse();
+    (transform, Frustum::from_view_projection(&view_projection))
+}
+
+fn bench_visibility(c: &mut Criterion) {
+    let config = WorldGeneratorConfig::default_flat();
+    let (forward, forward_frustum) = camera(Vec3::new(8.0, 4.0, -100.0));
+    let (down, down_frustum) = camera(Vec3::new(40.0, -100.0, -40.0));
+
+    let mut group = c.benchmark_group("find_visible_chunks");
+    let worlds: [(&str, fn(&ChunkPosition) -> ChunkLookup, &Transform, &Frustum); 4] = [
+        ("open plain", open_plain, &forward, &forward_frustum),
+        ("dense caves", dense_caves, &forward, &forward_frustum),
+        ("dense caves looking down", dense_caves, &down, &down_frustum),
+        ("wall in front", wall, &forward, &forward_frustum),
+    ];
+    for (name, lookup, camera, frustum) in worlds {
+        group.bench_function(name, |b| b.iter(|| black_box(find_visible_chunks(&config, config.generation_distance, camera, frustum, lookup))));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_visibility);
+criterion_main!(benches);
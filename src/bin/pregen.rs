@@ -0,0 +1,203 @@
+//! Generates a region of a saved world without the game and writes it to disk, so big worlds can
+//! be prepared ahead of playing. Chunks that are saved already are kept.
+//!
+//! ```text
+//! cargo run --release --bin pregen -- --radius 64 --seed 42
+//! ```
+//! Without `--world` the tool uses the world generated with the given generator and seed, creating
+//! it if there is none, or the most recently played world when neither is given. `--radius` is in
+//! chunk columns around `--center`, which defaults to the spawn column of the world. Do not run it
+//! on a world the game has open.
+
+use std::{io::Write, process::ExitCode, sync::Arc, time::Instant};
+
+use voxels_bevy_test::engine::{
+    block_registry::{BlockRegistry, BLOCKS_FILE},
+    chunk::{ChunkPosition, CHUNK_SIZE},
+    generator::PerlinHeightmapWorldGenerator,
+    migration::MigrationRegistry,
+    pregen::{columns, pregenerate_to_storage},
+    world_manager::{OpenedWorld, WorldManager, DEFAULT_WORLD, SAVES_DIR},
+};
+
+/// Seconds between redraws of the progress bar
+const PROGRESS_INTERVAL: f32 = 0.1;
+const PROGRESS_WIDTH: usize = 40;
+
+struct Args {
+    radius: u32,
+    world: Option<String>,
+    generator: Option<String>,
+    seed: Option<u32>,
+    /// Chunk column to center the region on, the spawn column of the world when left out
+    center: Option<(i32, i32)>,
+    /// Chunk levels to generate, the bottom defaults to the world bottom
+    bottom: Option<i32>,
+    top: i32,
+    threads: usize,
+}
+
+impl Args {
+    fn parse() -> Result<Self, String> {
+        let mut args = Self {
+            radius: 16,
+            world: None,
+            generator: None,
+            seed: None,
+            center: None,
+            bottom: None,
+            top: 4,
+            threads: std::thread::available_parallelism().map_or(4, |threads| threads.get()),
+        };
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            let mut value = || iter.next().ok_or_else(|| format!("missing value for {}", arg));
+            match arg.as_str() {
+                "--radius" => args.radius = value()?.parse().map_err(|err| format!("invalid radius: {}", err))?,
+                "--world" => args.world = Some(value()?),
+                "--generator" => args.generator = Some(value()?),
+                "--seed" => args.seed = Some(value()?.parse().map_err(|err| format!("invalid seed: {}", err))?),
+                "--center" => {
+                    let x = value()?.parse().map_err(|err| format!("invalid center: {}", err))?;
+                    let z = value()?.parse().map_err(|err| format!("invalid center: {}", err))?;
+                    args.center = Some((x, z));
+                }
+                "--bottom" => args.bottom = Some(value()?.parse().map_err(|err| format!("invalid bottom: {}", err))?),
+                "--top" => args.top = value()?.parse().map_err(|err| format!("invalid top: {}", err))?,
+                "--threads" => args.threads = value()?.parse().map_err(|err| format!("invalid thread count: {}", err))?,
+                _ => return Err(format!("unknown argument `{}`", arg)),
+            }
+        }
+        if args.threads == 0 {
+            return Err("thread count must be positive".to_string());
+        }
+        Ok(args)
+    }
+}
+
+/// Opens the world named by the arguments, creating it if needed
+fn open_world(args: &Args, manager: &mut WorldManager) -> Result<OpenedWorld, String> {
+    let generator = args.generator.clone().unwrap_or_else(|| "perlin".to_string());
+    let seed = args.seed.unwrap_or(PerlinHeightmapWorldGenerator::default().seed);
+    let worlds = manager.list().map_err(|err| format!("failed to list worlds: {}", err))?;
+    let existing = match (&args.world, &args.generator, args.seed) {
+        (Some(dir), _, _) => worlds.iter().find(|world| &world.dir == dir),
+        (None, None, None) => worlds.first(),
+        (None, _, _) => worlds.iter().find(|world| world.metadata.generator == generator && world.metadata.seed == seed),
+    };
+
+    let dir = match existing {
+        Some(world) => {
+            let mismatch = args.generator.as_ref().is_some_and(|generator| *generator != world.metadata.generator)
+                || args.seed.is_some_and(|seed| seed != world.metadata.seed);
+            if mismatch {
+                return Err(format!(
+                    "world {} is generated by {} with seed {}, pick another world to pregenerate these settings",
+                    world.dir, world.metadata.generator, world.metadata.seed
+                ));
+            }
+            world.dir.clone()
+        }
+        None => {
+            let name = match (&args.world, args.generator.is_some() || args.seed.is_some()) {
+                (Some(name), _) => name.clone(),
+                (None, true) => format!("pregen {}", seed),
+                (None, false) => DEFAULT_WORLD.to_string(),
+            };
+            let dir = manager.create(&name, &generator, seed).map_err(|err| format!("failed to create world {}: {}", name, err))?;
+            println!("Created world {}", dir);
+            dir
+        }
+    };
+    manager.open(&dir).map_err(|err| format!("failed to open world {}: {}", dir, err))
+}
+
+fn draw_progress(done: usize, total: usize, started: Instant) {
+    let fraction = done as f32 / total.max(1) as f32;
+    let filled = (fraction * PROGRESS_WIDTH as f32) as usize;
+    let elapsed = started.elapsed().as_secs_f32();
+    let remaining = if done > 0 { elapsed / done as f32 * (total - done) as f32 } else { 0.0 };
+    eprint!(
+        "\r[{}{}] {:>3.0}% {}/{} columns, {:.0}s left ",
+        "#".repeat(filled),
+        "-".repeat(PROGRESS_WIDTH - filled),
+        fraction * 100.0,
+        done,
+        total,
+        remaining
+    );
+    let _ = std::io::stderr().flush();
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let blocks = BlockRegistry::load(BLOCKS_FILE).unwrap_or_else(|err| {
+        eprintln!("Using the builtin blocks only, {}: {}", BLOCKS_FILE, err);
+        BlockRegistry::builtin()
+    });
+
+    let mut manager = WorldManager::new(SAVES_DIR, Arc::new(MigrationRegistry::builtin()), blocks);
+    let OpenedWorld { mut storage, metadata, config, mut pending_edits, .. } = open_world(args, &mut manager)?;
+
+    let center = args.center.unwrap_or_else(|| {
+        let chunk = |voxel: i64| voxel.div_euclid(CHUNK_SIZE as i64) as i32;
+        (chunk(metadata.spawn[0]), chunk(metadata.spawn[1]))
+    });
+    let bottom = args.bottom.unwrap_or_else(|| config.world_bottom.map_or(-4, |bottom| bottom.div_euclid(CHUNK_SIZE as i32)));
+    if bottom > args.top {
+        return Err(format!("bottom {} is above top {}", bottom, args.top));
+    }
+    let columns = columns(center, args.radius);
+    println!(
+        "Pregenerating {} with {} #{}: {} columns around {:?}, chunk levels {} to {}, {} threads",
+        metadata.name,
+        metadata.generator,
+        metadata.seed,
+        columns.len(),
+        ChunkPosition::new(center.0, 0, center.1),
+        bottom,
+        args.top,
+        args.threads
+    );
+
+    let started = Instant::now();
+    let mut last_drawn = started;
+    let report = pregenerate_to_storage(&config, &mut storage, &mut pending_edits, &columns, bottom..=args.top, args.threads, |done| {
+        if done == columns.len() || last_drawn.elapsed().as_secs_f32() >= PROGRESS_INTERVAL {
+            last_drawn = Instant::now();
+            draw_progress(done, columns.len(), started);
+        }
+    });
+    eprintln!();
+
+    println!("Writing the last chunks to disk");
+    // Blocks until every chunk is written
+    storage.shutdown();
+    // Structures reaching out of the region are finished when the game generates the rest
+    pending_edits.save(storage.root()).map_err(|err| format!("failed to save pending edits: {}", err))?;
+    println!(
+        "Generated {} chunks in {:.1}s ({:.0} chunks/s), {} were saved already, {} edits wait for chunks outside the region",
+        report.generated,
+        started.elapsed().as_secs_f32(),
+        report.generated as f32 / report.elapsed.as_secs_f32().max(f32::EPSILON),
+        report.skipped,
+        report.leftover_edits
+    );
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            eprintln!("usage: pregen [--radius COLUMNS] [--world DIR] [--generator NAME] [--seed S] [--center X Z] [--bottom LEVEL] [--top LEVEL] [--threads N]");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Err(err) = run(&args) {
+        eprintln!("error: {}", err);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,214 @@
+//! Generates (and meshes) a cube of chunks without rendering and prints timing and memory stats.
+//!
+//! ```text
+//! cargo run --release --bin worldgen_bench -- --size 8 --generator perlin --seed 42 --json
+//! ```
+//! Generators: `perlin`, `flat`, `superflat=<layer spec>`, `density[=<params>]`, `test-pattern`, followed by any
+//! pipeline stages joined with `+`, e.g. `perlin+surface+caves`.
+
+use std::{process::ExitCode, time::{Duration, Instant}};
+
+use voxels_bevy_test::engine::{
+    block_registry::BlockRegistry,
+    cache::ChunkCache,
+    chunk::ChunkPosition,
+    generator::{PerlinHeightmapWorldGenerator, WorldGeneratorConfig},
+    meshing::ChunkMesher,
+    serialization,
+};
+
+struct Args {
+    size: i32,
+    generator: String,
+    seed: Option<u32>,
+    mesh: bool,
+    json: bool,
+}
+
+impl Args {
+    fn parse() -> Result<Self, String> {
+        let mut args = Self {
+            size: 8,
+            generator: "perlin".to_string(),
+            seed: None,
+            mesh: true,
+            json: false,
+        };
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            let mut value = || iter.next().ok_or_else(|| format!("missing value for {}", arg));
+            match arg.as_str() {
+                "--size" => args.size = value()?.parse().map_err(|err| format!("invalid size: {}", err))?,
+                "--generator" => args.generator = value()?,
+                "--seed" => args.seed = Some(value()?.parse().map_err(|err| format!("invalid seed: {}", err))?),
+                "--no-mesh" => args.mesh = false,
+                "--json" => args.json = true,
+                _ => return Err(format!("unknown argument `{}`", arg)),
+            }
+        }
+        if args.size <= 0 {
+            return Err("size must be positive".to_string());
+        }
+        Ok(args)
+    }
+
+    fn config(&self) -> Result<WorldGeneratorConfig, String> {
+        let seed = self.seed.unwrap_or(PerlinHeightmapWorldGenerator::default().seed);
+        WorldGeneratorConfig::from_generator_name(&self.generator, seed, &BlockRegistry::builtin())
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    chunks: usize,
+    generation: Vec<Duration>,
+    meshing: Vec<Duration>,
+    empty_meshes: usize,
+    vertices: usize,
+    indices: usize,
+    overflow_edits: usize,
+    encoded_bytes: usize,
+}
+
+fn percentile(samples: &[Duration], p: f64) -> Duration {
+    if samples.is_empty() {
+        return Duration::ZERO;
+    }
+    let mut sorted = samples.to_vec();
+    sorted.sort();
+    sorted[((sorted.len() - 1) as f64 * p).round() as usize]
+}
+
+fn total(samples: &[Duration]) -> Duration {
+    samples.iter().sum()
+}
+
+/// Peak resident memory of the process, only available on Linux
+fn peak_rss_bytes() -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kb = line.split_whitespace().nth(1)?.parse::<usize>().ok()?;
+    Some(kb * 1024)
+}
+
+fn run(args: &Args) -> Result<Stats, String> {
+    let config = args.config()?;
+    let mesher = ChunkMesher::default();
+    let mut stats = Stats::default();
+    let half = args.size / 2;
+
+    for x in -half..args.size - half {
+        for y in -half..args.size - half {
+            for z in -half..args.size - half {
+                let chunk_pos = ChunkPosition::new(x, y, z);
+                if config.is_outside_world(&chunk_pos) {
+                    continue;
+                }
+
+                let start = Instant::now();
+                let (chunk, overflow) = config.generate(chunk_pos);
+                stats.generation.push(start.elapsed());
+                stats.chunks += 1;
+                stats.overflow_edits += overflow.len();
+                stats.encoded_bytes += serialization::encode(&chunk).len();
+
+                if args.mesh {
+                    let start = Instant::now();
+                    let mesh = chunk.build_in(&mesher, config.biome_of(&chunk_pos));
+                    stats.meshing.push(start.elapsed());
+                    match mesh {
+                        Some(mesh) => {
+                            stats.vertices += mesh.count_vertices();
+                            stats.indices += mesh.indices().map_or(0, |indices| indices.len());
+                        }
+                        None => stats.empty_meshes += 1,
+                    }
+                }
+            }
+        }
+    }
+    Ok(stats)
+}
+
+fn print_text(args: &Args, stats: &Stats) {
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    let generation = total(&stats.generation);
+    println!("generator: {} ({}³ chunks, {} generated)", args.generator, args.size, stats.chunks);
+    println!(
+        "generation: {:.1} ms total, {:.3} ms/chunk, p50 {:.3} ms, p95 {:.3} ms, {:.0} chunks/s",
+        ms(generation),
+        ms(generation) / stats.chunks.max(1) as f64,
+        ms(percentile(&stats.generation, 0.5)),
+        ms(percentile(&stats.generation, 0.95)),
+        stats.chunks as f64 / generation.as_secs_f64().max(f64::EPSILON),
+    );
+    if args.mesh {
+        let meshing = total(&stats.meshing);
+        println!(
+            "meshing: {:.1} ms total, {:.3} ms/chunk, p50 {:.3} ms, p95 {:.3} ms, {} empty",
+            ms(meshing),
+            ms(meshing) / stats.meshing.len().max(1) as f64,
+            ms(percentile(&stats.meshing, 0.5)),
+            ms(percentile(&stats.meshing, 0.95)),
+            stats.empty_meshes,
+        );
+        println!("mesh data: {} vertices, {} indices", stats.vertices, stats.indices);
+    }
+    println!(
+        "memory: {:.1} MB voxel data, {:.1} MB encoded, {} overflow edits",
+        (stats.chunks * ChunkCache::chunk_size_bytes()) as f64 / 1024.0 / 1024.0,
+        stats.encoded_bytes as f64 / 1024.0 / 1024.0,
+        stats.overflow_edits,
+    );
+    if let Some(rss) = peak_rss_bytes() {
+        println!("peak rss: {:.1} MB", rss as f64 / 1024.0 / 1024.0);
+    }
+}
+
+/// Single line of JSON, meant to be collected by CI and compared across commits
+fn print_json(args: &Args, stats: &Stats) {
+    let us = |duration: Duration| duration.as_micros();
+    println!(
+        concat!(
+            "{{\"generator\":\"{}\",\"size\":{},\"chunks\":{},",
+            "\"generation_total_us\":{},\"generation_p50_us\":{},\"generation_p95_us\":{},",
+            "\"meshing_total_us\":{},\"meshing_p50_us\":{},\"meshing_p95_us\":{},",
+            "\"vertices\":{},\"indices\":{},\"encoded_bytes\":{},\"voxel_bytes\":{},\"peak_rss_bytes\":{}}}",
+        ),
+        args.generator.replace('\\', "\\\\").replace('"', "\\\""),
+        args.size,
+        stats.chunks,
+        us(total(&stats.generation)),
+        us(percentile(&stats.generation, 0.5)),
+        us(percentile(&stats.generation, 0.95)),
+        us(total(&stats.meshing)),
+        us(percentile(&stats.meshing, 0.5)),
+        us(percentile(&stats.meshing, 0.95)),
+        stats.vertices,
+        stats.indices,
+        stats.encoded_bytes,
+        stats.chunks * ChunkCache::chunk_size_bytes(),
+        peak_rss_bytes().map_or("null".to_string(), |rss| rss.to_string()),
+    );
+}
+
+fn main() -> ExitCode {
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            eprintln!("usage: worldgen_bench [--size N] [--generator perlin|flat|superflat=<spec>|density[=<params>]|test-pattern[+surface][+layers[=<params>]][+caves][+dungeons][+villages]] [--seed S] [--no-mesh] [--json]");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(&args) {
+        Ok(stats) if args.json => print_json(&args, &stats),
+        Ok(stats) => print_text(&args, &stats),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
+}
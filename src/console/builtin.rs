@@ -0,0 +1,227 @@
+//! Commands every console has
+
+use std::fs;
+
+use bevy::prelude::*;
+
+use super::{Console, ConsoleAppExt, ConsoleCommands};
+use crate::{
+    engine::{
+        autosave::{save_dirty_chunks, AutosaveSettings},
+        block_registry::BlockRegistry,
+        chunk_log::ChunkLogLevel,
+        coords::WorldVoxelPos,
+        generator::{remesh_all_chunks, ChunkSource, WorldGeneratorConfig},
+        meshing::{ChunkVertexFormat, MeshingStrategy},
+        world_manager::unload_all_chunks,
+        world_meta::WorldMetadata,
+    },
+    flycam::PlayerCamera,
+    gameplay::spawn::PendingSpawn,
+};
+
+pub fn register(app: &mut App) {
+    app.register_command("help", "help", help)
+        .register_command("echo", "echo <text>", |_, args| Ok(args.join(" ")))
+        .register_command("cls", "cls", |world, _| {
+            world.resource_mut::<Console>().clear_log();
+            Ok(String::new())
+        })
+        .register_command("exec", "exec <file>", exec)
+        .register_command("tp", "tp <x> <y> <z>", teleport)
+        .register_command("respawn", "respawn", respawn)
+        .register_command("spawnpoint", "spawnpoint [here|<x> <z>]", spawnpoint)
+        .register_command("seed", "seed [seed]", seed)
+        .register_command("generator", "generator [name]", generator)
+        .register_command("world_border", "world_border [chunks|off]", world_border)
+        .register_command("autosave", "autosave [seconds|off]", autosave)
+        .register_command("clear_chunks", "clear_chunks", |world, _| {
+            reload_chunks(world)?;
+            Ok("Unloaded every chunk".to_string())
+        })
+        .register_command("chunk_log", "chunk_log [off|summary|chunks]", chunk_log)
+        .register_command("mesher", "mesher [greedy|culled|naive]", mesher)
+        .register_command("vertex_format", "vertex_format [standard|packed]", vertex_format);
+}
+
+fn help(world: &mut World, _args: &[&str]) -> Result<String, String> {
+    let commands = world.resource::<ConsoleCommands>();
+    Ok(commands.iter().map(|(_, command)| command.usage.as_str()).collect::<Vec<_>>().join("\n"))
+}
+
+/// Queues every line of a script, empty lines and lines starting with `#` are skipped
+fn exec(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let [path] = args else {
+        return Err("usage: exec <file>".to_string());
+    };
+    let script = fs::read_to_string(path).map_err(|err| format!("failed to read {}: {}", path, err))?;
+    let mut console = world.resource_mut::<Console>();
+    for line in script.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        console.submit(line);
+    }
+    Ok(String::new())
+}
+
+fn teleport(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let [x, y, z] = args else {
+        return Err("usage: tp <x> <y> <z>".to_string());
+    };
+    let parse = |value: &str| value.parse::<f32>().map_err(|err| format!("`{}` is not a number: {}", value, err));
+    let position = Vec3::new(parse(x)?, parse(y)?, parse(z)?);
+    let mut cameras = world.query_filtered::<&mut Transform, With<PlayerCamera>>();
+    for mut transform in cameras.iter_mut(world) {
+        transform.translation = position;
+    }
+    Ok(format!("Teleported to {} {} {}", position.x, position.y, position.z))
+}
+
+/// Moves the camera onto the surface of the column it is above
+fn respawn(world: &mut World, _args: &[&str]) -> Result<String, String> {
+    let mut cameras = world.query_filtered::<&Transform, With<PlayerCamera>>();
+    let camera = cameras.get_single(world).map_err(|_| "there is no camera".to_string())?;
+    let column = WorldVoxelPos::from_world(camera.translation);
+    world.insert_resource(PendingSpawn::at(column.x, column.z));
+    Ok(format!("Respawning at {} {}", column.x, column.z))
+}
+
+/// The column new sessions of the world start in
+fn spawnpoint(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let [x, z] = match args {
+        [] => {
+            let [x, z] = world.resource::<WorldMetadata>().spawn;
+            return Ok(format!("{} {}", x, z));
+        }
+        ["here"] => {
+            let mut cameras = world.query_filtered::<&Transform, With<PlayerCamera>>();
+            let camera = cameras.get_single(world).map_err(|_| "there is no camera".to_string())?;
+            let column = WorldVoxelPos::from_world(camera.translation);
+            [column.x, column.z]
+        }
+        [x, z] => {
+            let parse = |value: &str| value.parse::<i64>().map_err(|err| format!("invalid coordinate {}: {}", value, err));
+            [parse(x)?, parse(z)?]
+        }
+        _ => return Err("usage: spawnpoint [here|<x> <z>]".to_string()),
+    };
+    world.resource_mut::<WorldMetadata>().spawn = [x, z];
+    Ok(format!("Spawn point set to {} {}", x, z))
+}
+
+fn seed(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let metadata = world.resource::<WorldMetadata>();
+    match args {
+        [] => Ok(metadata.seed.to_string()),
+        [seed] => {
+            let seed = seed.parse::<u32>().map_err(|err| format!("invalid seed: {}", err))?;
+            let generator = metadata.generator.clone();
+            switch_generator(world, &generator, seed)?;
+            Ok(format!("Seed set to {}, chunks saved before keep their terrain", seed))
+        }
+        _ => Err("usage: seed [seed]".to_string()),
+    }
+}
+
+fn generator(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let metadata = world.resource::<WorldMetadata>();
+    match args {
+        [] => Ok(metadata.generator.clone()),
+        [name] => {
+            let seed = metadata.seed;
+            switch_generator(world, name, seed)?;
+            Ok(format!("Generator set to {}, chunks saved before keep their terrain", name))
+        }
+        _ => Err("usage: generator [name]".to_string()),
+    }
+}
+
+/// Chunks already loaded beyond a new border stay until the camera moves away from them
+fn world_border(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let border = match args {
+        [] => return Ok(world.resource::<WorldGeneratorConfig>().world_border.map_or("off".to_string(), |border| border.to_string())),
+        ["off"] => None,
+        [chunks] => Some(chunks.parse::<u32>().map_err(|err| format!("invalid border: {}", err))?),
+        _ => return Err("usage: world_border [chunks|off]".to_string()),
+    };
+    world.resource_mut::<WorldGeneratorConfig>().world_border = border;
+    Ok(match border {
+        Some(border) => format!("World border set to {} chunks from the origin", border),
+        None => "World border removed".to_string(),
+    })
+}
+
+fn autosave(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let mut settings = world.resource_mut::<AutosaveSettings>();
+    match args {
+        [] if settings.enabled => Ok(format!("Autosaving every {} seconds", settings.interval)),
+        [] => Ok("off".to_string()),
+        ["off"] => {
+            settings.enabled = false;
+            Ok("Autosave disabled".to_string())
+        }
+        [seconds] => {
+            let interval = seconds.parse::<f32>().ok().filter(|interval| *interval > 0.0).ok_or(format!("invalid interval: {}", seconds))?;
+            *settings = AutosaveSettings { enabled: true, interval };
+            Ok(format!("Autosaving every {} seconds", interval))
+        }
+        _ => Err("usage: autosave [seconds|off]".to_string()),
+    }
+}
+
+fn chunk_log(world: &mut World, args: &[&str]) -> Result<String, String> {
+    match args {
+        [] => Ok(world.resource::<ChunkLogLevel>().name().to_string()),
+        [level] => {
+            world.insert_resource(level.parse::<ChunkLogLevel>()?);
+            Ok(format!("Chunk pipeline logging set to {}", level))
+        }
+        _ => Err("usage: chunk_log [off|summary|chunks]".to_string()),
+    }
+}
+
+/// Switches the [`MeshingStrategy`] and meshes every loaded chunk again with it
+fn mesher(world: &mut World, args: &[&str]) -> Result<String, String> {
+    match args {
+        [] => Ok(world.resource::<MeshingStrategy>().name().to_string()),
+        [strategy] => {
+            world.insert_resource(strategy.parse::<MeshingStrategy>()?);
+            remesh_all_chunks(world);
+            Ok(format!("Meshing chunks with the {} mesher", strategy))
+        }
+        _ => Err("usage: mesher [greedy|culled|naive]".to_string()),
+    }
+}
+
+fn vertex_format(world: &mut World, args: &[&str]) -> Result<String, String> {
+    match args {
+        [] => Ok(world.resource::<ChunkVertexFormat>().name().to_string()),
+        [format] => {
+            world.insert_resource(format.parse::<ChunkVertexFormat>()?);
+            remesh_all_chunks(world);
+            Ok(format!("Meshing chunks with {} vertices", format))
+        }
+        _ => Err("usage: vertex_format [standard|packed]".to_string()),
+    }
+}
+
+/// Replaces the generator of the open world and generates everything around the camera again
+fn switch_generator(world: &mut World, name: &str, seed: u32) -> Result<(), String> {
+    let config = WorldGeneratorConfig::from_generator_name(name, seed, world.resource::<BlockRegistry>())?
+        .with_view_settings_of(world.resource::<WorldGeneratorConfig>());
+    reload_chunks(world)?;
+    world.insert_resource(config);
+    let mut metadata = world.resource_mut::<WorldMetadata>();
+    metadata.generator = name.to_string();
+    metadata.seed = seed;
+    Ok(())
+}
+
+/// Unloads every chunk, they are loaded or generated again right after. Edited chunks are saved
+/// first, generated chunks that were never edited are dropped so a new generator replaces them.
+fn reload_chunks(world: &mut World) -> Result<(), String> {
+    if *world.resource::<ChunkSource>() == ChunkSource::Remote {
+        return Err("chunks come from the server".to_string());
+    }
+    save_dirty_chunks(world);
+    unload_all_chunks(world);
+    Ok(())
+}
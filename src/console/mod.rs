@@ -0,0 +1,236 @@
+//! In-game console. `` ` `` toggles it, commands are registered by plugins with
+//! [`ConsoleAppExt::register_command`] and run with full access to the [`World`].
+//!
+//! Several commands can be given on one line separated by `;`, `exec <file>` runs a script
+//! with one command per line. The console window needs the `debug-ui` feature, without it commands
+//! can still be queued with [`Console::submit`].
+
+use std::{collections::{BTreeMap, VecDeque}, sync::Arc};
+
+use bevy::prelude::*;
+#[cfg(feature = "debug-ui")]
+use bevy_egui::{egui, EguiContexts};
+
+pub mod builtin;
+
+#[cfg(feature = "debug-ui")]
+const TOGGLE_KEY: KeyCode = KeyCode::Grave;
+/// Output lines kept in the console
+const MAX_LOG_LINES: usize = 500;
+
+/// Runs a command with its arguments, the returned text is printed to the console
+pub type CommandHandler = Arc<dyn Fn(&mut World, &[&str]) -> Result<String, String> + Send + Sync>;
+
+pub struct ConsoleCommand {
+    /// Shown by `help`, e.g. `tp <x> <y> <z>`
+    pub usage: String,
+    handler: CommandHandler,
+}
+
+#[derive(Resource, Default)]
+pub struct ConsoleCommands {
+    commands: BTreeMap<String, ConsoleCommand>,
+}
+
+impl ConsoleCommands {
+    /// Registers a command, replacing any command with the same name
+    pub fn register(
+        &mut self,
+        name: &str,
+        usage: &str,
+        handler: impl Fn(&mut World, &[&str]) -> Result<String, String> + Send + Sync + 'static,
+    ) {
+        self.commands.insert(name.to_string(), ConsoleCommand { usage: usage.to_string(), handler: Arc::new(handler) });
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ConsoleCommand> {
+        self.commands.get(name)
+    }
+
+    /// Every command sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ConsoleCommand)> {
+        self.commands.iter().map(|(name, command)| (name.as_str(), command))
+    }
+}
+
+pub trait ConsoleAppExt {
+    fn register_command(
+        &mut self,
+        name: &str,
+        usage: &str,
+        handler: impl Fn(&mut World, &[&str]) -> Result<String, String> + Send + Sync + 'static,
+    ) -> &mut Self;
+}
+
+impl ConsoleAppExt for App {
+    fn register_command(
+        &mut self,
+        name: &str,
+        usage: &str,
+        handler: impl Fn(&mut World, &[&str]) -> Result<String, String> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.world.get_resource_or_insert_with(ConsoleCommands::default).register(name, usage, handler);
+        self
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Console {
+    pub open: bool,
+    log: VecDeque<String>,
+    /// Commands waiting to run, they run once per frame in order
+    queued: VecDeque<String>,
+    #[cfg(feature = "debug-ui")]
+    input: String,
+    #[cfg(feature = "debug-ui")]
+    history: Vec<String>,
+    /// Entry of `history` shown in the input line while browsing it
+    #[cfg(feature = "debug-ui")]
+    history_index: Option<usize>,
+}
+
+impl Console {
+    /// Queues every `;` separated command of the line
+    pub fn submit(&mut self, line: &str) {
+        self.queued.extend(line.split(';').map(str::trim).filter(|command| !command.is_empty()).map(str::to_string));
+    }
+
+    pub fn print(&mut self, text: impl Into<String>) {
+        for line in text.into().lines() {
+            if self.log.len() >= MAX_LOG_LINES {
+                self.log.pop_front();
+            }
+            self.log.push_back(line.to_string());
+        }
+    }
+
+    pub fn clear_log(&mut self) {
+        self.log.clear();
+    }
+
+    pub fn log(&self) -> impl Iterator<Item = &str> {
+        self.log.iter().map(String::as_str)
+    }
+}
+
+/// Runs a single command right away
+pub fn run_command(world: &mut World, line: &str) -> Result<String, String> {
+    let mut parts = line.split_whitespace();
+    let Some(name) = parts.next() else {
+        return Ok(String::new());
+    };
+    let args = parts.collect::<Vec<_>>();
+    let handler = world
+        .get_resource::<ConsoleCommands>()
+        .and_then(|commands| commands.get(name))
+        .map(|command| command.handler.clone())
+        .ok_or_else(|| format!("unknown command `{}`, try `help`", name))?;
+    handler(world, &args)
+}
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Console>()
+            .init_resource::<ConsoleCommands>()
+            .add_systems(PostUpdate, run_queued_commands);
+        builtin::register(app);
+
+        #[cfg(feature = "debug-ui")]
+        app.add_systems(Update, (toggle_console, show_console.after(toggle_console)));
+    }
+}
+
+#[cfg(feature = "debug-ui")]
+fn toggle_console(mut console: ResMut<Console>, keys: Res<Input<KeyCode>>) {
+    if keys.just_pressed(TOGGLE_KEY) {
+        console.open = !console.open;
+    }
+}
+
+fn run_queued_commands(world: &mut World) {
+    // Commands may queue more commands, those run next frame
+    let queued = std::mem::take(&mut world.resource_mut::<Console>().queued);
+    for line in queued {
+        let result = run_command(world, &line);
+        let mut console = world.resource_mut::<Console>();
+        console.print(format!("> {}", line));
+        match result {
+            Ok(output) if output.is_empty() => {}
+            Ok(output) => console.print(output),
+            Err(err) => console.print(format!("error: {}", err)),
+        }
+    }
+}
+
+#[cfg(feature = "debug-ui")]
+fn show_console(mut contexts: EguiContexts, mut console: ResMut<Console>) {
+    if !console.open {
+        return;
+    }
+    let console = &mut *console;
+    egui::Window::new("Console").default_width(500.0).show(contexts.ctx_mut(), |ui| {
+        egui::ScrollArea::vertical().max_height(300.0).stick_to_bottom(true).show(ui, |ui| {
+            for line in console.log.iter() {
+                ui.monospace(line);
+            }
+        });
+
+        let response = ui.add(egui::TextEdit::singleline(&mut console.input).desired_width(f32::INFINITY).code_editor());
+        if !response.has_focus() && !response.lost_focus() {
+            response.request_focus();
+        }
+
+        let (enter, up, down) = ui.input(|input| {
+            (input.key_pressed(egui::Key::Enter), input.key_pressed(egui::Key::ArrowUp), input.key_pressed(egui::Key::ArrowDown))
+        });
+        if response.lost_focus() && enter {
+            let line = std::mem::take(&mut console.input);
+            if !line.trim().is_empty() {
+                console.submit(&line);
+                console.history.push(line);
+            }
+            console.history_index = None;
+            response.request_focus();
+        } else if up && !console.history.is_empty() {
+            let index = console.history_index.map_or(console.history.len() - 1, |index| index.saturating_sub(1));
+            console.history_index = Some(index);
+            console.input = console.history[index].clone();
+        } else if down {
+            if let Some(index) = console.history_index {
+                let next = index + 1;
+                console.history_index = (next < console.history.len()).then_some(next);
+                console.input = console.history_index.map(|index| console.history[index].clone()).unwrap_or_default();
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_registered_command() {
+        let mut world = World::new();
+        let mut commands = ConsoleCommands::default();
+        commands.register("add", "add <a> <b>", |_, args| {
+            let sum = args.iter().map(|arg| arg.parse::<i32>().map_err(|err| err.to_string())).sum::<Result<i32, _>>()?;
+            Ok(sum.to_string())
+        });
+        world.insert_resource(commands);
+
+        assert_eq!(run_command(&mut world, "  add 2   3 "), Ok("5".to_string()));
+        assert!(run_command(&mut world, "add 2 x").is_err());
+        assert!(run_command(&mut world, "nope").is_err());
+        assert_eq!(run_command(&mut world, ""), Ok(String::new()));
+    }
+
+    #[test]
+    fn test_submit_splits_commands() {
+        let mut console = Console::default();
+        console.submit("tp 0 64 0; seed 3 ;; help");
+        assert_eq!(console.queued, ["tp 0 64 0", "seed 3", "help"]);
+    }
+}
@@ -0,0 +1,202 @@
+//! Teleporting the camera to typed in coordinates and named position bookmarks,
+//! for getting back to problem areas of the world quickly.
+//!
+//! Bookmarks belong to a world and are stored next to its chunks in [`BOOKMARKS_FILE`].
+
+use std::{fs, io, path::{Path, PathBuf}};
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use serde::{Deserialize, Serialize};
+
+use crate::{engine::world_manager::WorldManager, flycam::PlayerCamera};
+
+pub const BOOKMARKS_FILE: &str = "bookmarks.ron";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Bookmark {
+    pub name: String,
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+}
+
+impl Bookmark {
+    pub fn new(name: impl Into<String>, transform: &Transform) -> Self {
+        Self { name: name.into(), translation: transform.translation.to_array(), rotation: transform.rotation.to_array() }
+    }
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct Bookmarks {
+    pub list: Vec<Bookmark>,
+    /// File the list was loaded from, changes when another world is opened
+    path: Option<PathBuf>,
+}
+
+impl Bookmarks {
+    pub fn load(path: &Path) -> io::Result<Vec<Bookmark>> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        ron::from_str(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(());
+        };
+        let text = ron::ser::to_string_pretty(&self.list, ron::ser::PrettyConfig::default())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        fs::write(path, text)
+    }
+
+    /// Adds a bookmark, replacing the one with the same name
+    pub fn set(&mut self, bookmark: Bookmark) {
+        match self.list.iter_mut().find(|existing| existing.name == bookmark.name) {
+            Some(existing) => *existing = bookmark,
+            None => self.list.push(bookmark),
+        }
+    }
+}
+
+/// Moves the camera, rotation is kept if `rotation` is `None`
+#[derive(Event, Debug, Clone, Copy)]
+pub struct Teleport {
+    pub translation: Vec3,
+    pub rotation: Option<Quat>,
+}
+
+/// Parses three numbers separated by spaces and/or commas, e.g. `12 64.5 -3` or `12, 64.5, -3`
+pub fn parse_coordinates(text: &str) -> Result<Vec3, String> {
+    let values = text
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse::<f32>().map_err(|err| format!("`{}` is not a number: {}", part, err)))
+        .collect::<Result<Vec<_>, _>>()?;
+    match values[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(format!("expected 3 coordinates, got {}", values.len())),
+    }
+}
+
+pub struct BookmarksPlugin;
+
+impl Plugin for BookmarksPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Bookmarks>()
+            .add_event::<Teleport>()
+            .add_systems(Update, (load_world_bookmarks, apply_teleports, show_bookmarks_debug_info));
+    }
+}
+
+fn load_world_bookmarks(mut bookmarks: ResMut<Bookmarks>, worlds: Res<WorldManager>) {
+    let path = worlds.current().map(|dir| worlds.root().join(dir).join(BOOKMARKS_FILE));
+    if path == bookmarks.path {
+        return;
+    }
+    bookmarks.list = match path.as_deref().map(Bookmarks::load).transpose() {
+        Ok(list) => list.unwrap_or_default(),
+        Err(err) => {
+            warn!("Failed to load bookmarks from {:?}: {}", path, err);
+            Vec::new()
+        }
+    };
+    bookmarks.path = path;
+}
+
+fn apply_teleports(mut teleports: EventReader<Teleport>, mut camera: Query<&mut Transform, With<PlayerCamera>>) {
+    let Some(teleport) = teleports.read().last() else {
+        return;
+    };
+    for mut transform in camera.iter_mut() {
+        transform.translation = teleport.translation;
+        if let Some(rotation) = teleport.rotation {
+            transform.rotation = rotation;
+        }
+    }
+}
+
+fn show_bookmarks_debug_info(
+    mut contexts: EguiContexts,
+    mut bookmarks: ResMut<Bookmarks>,
+    mut teleports: EventWriter<Teleport>,
+    mut coordinates: Local<String>,
+    mut name: Local<String>,
+    mut status: Local<String>,
+    camera: Query<&Transform, With<PlayerCamera>>,
+) {
+    let camera = camera.iter().next().copied().unwrap_or_default();
+    egui::Window::new("Bookmarks").default_open(false).show(contexts.ctx_mut(), |ui| {
+        ui.label(format!("Camera: {:.1} {:.1} {:.1}", camera.translation.x, camera.translation.y, camera.translation.z));
+        ui.horizontal(|ui| {
+            let response = ui.add(egui::TextEdit::singleline(&mut *coordinates).hint_text("x y z").desired_width(160.0));
+            let submitted = response.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter));
+            if ui.button("Teleport").clicked() || submitted {
+                match parse_coordinates(&coordinates) {
+                    Ok(translation) => {
+                        teleports.send(Teleport { translation, rotation: None });
+                        status.clear();
+                    }
+                    Err(err) => *status = err,
+                }
+            }
+        });
+
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut *name).hint_text("Name").desired_width(160.0));
+            if ui.add_enabled(!name.trim().is_empty(), egui::Button::new("Save Current")).clicked() {
+                bookmarks.set(Bookmark::new(name.trim(), &camera));
+                name.clear();
+                changed = true;
+            }
+        });
+
+        let mut delete = None;
+        egui::Grid::new("bookmarks").striped(true).show(ui, |ui| {
+            for (i, bookmark) in bookmarks.list.iter().enumerate() {
+                let [x, y, z] = bookmark.translation;
+                ui.label(&bookmark.name);
+                ui.label(format!("{:.0} {:.0} {:.0}", x, y, z));
+                if ui.button("Go").clicked() {
+                    teleports.send(Teleport {
+                        translation: Vec3::from_array(bookmark.translation),
+                        rotation: Some(Quat::from_array(bookmark.rotation).normalize()),
+                    });
+                }
+                if ui.button("Delete").clicked() {
+                    delete = Some(i);
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(i) = delete {
+            bookmarks.list.remove(i);
+            changed = true;
+        }
+        if changed {
+            *status = match bookmarks.save() {
+                Ok(()) => String::new(),
+                Err(err) => format!("Failed to save bookmarks: {}", err),
+            };
+        }
+        if !status.is_empty() {
+            ui.label(&*status);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_coordinates() {
+        assert_eq!(parse_coordinates("12 64.5 -3"), Ok(Vec3::new(12.0, 64.5, -3.0)));
+        assert_eq!(parse_coordinates(" 1,2 ,  3 "), Ok(Vec3::new(1.0, 2.0, 3.0)));
+        assert!(parse_coordinates("1 2").is_err());
+        assert!(parse_coordinates("1 two 3").is_err());
+    }
+}
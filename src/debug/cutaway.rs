@@ -0,0 +1,71 @@
+//! Cross-section view. `F3` cuts the terrain open at a horizontal plane so caves and everything
+//! else underground can be inspected without removing voxels.
+//!
+//! `=` / `-` move the plane one voxel up or down, with `Shift` a whole chunk.
+
+use bevy::prelude::*;
+
+use crate::{
+    engine::{chunk::CHUNK_SIZE, chunk_material::ClipPlane},
+    flycam::PlayerCamera,
+};
+
+const TOGGLE_KEY: KeyCode = KeyCode::F3;
+
+pub struct CutawayPlugin;
+
+impl Plugin for CutawayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, cutaway_controls);
+
+        #[cfg(feature = "debug-ui")]
+        app.add_systems(Update, show_cutaway_debug_info);
+    }
+}
+
+fn cutaway_controls(
+    mut clip_plane: ResMut<ClipPlane>,
+    keys: Res<Input<KeyCode>>,
+    camera: Query<&Transform, With<PlayerCamera>>,
+) {
+    if keys.just_pressed(TOGGLE_KEY) {
+        clip_plane.0 = match clip_plane.0 {
+            Some(_) => None,
+            // Start right below the camera so the cut is visible immediately
+            None => camera.iter().next().map(|transform| transform.translation.y.floor() - 1.0).or(Some(0.0)),
+        };
+    }
+    let Some(height) = clip_plane.0 else {
+        return;
+    };
+
+    let step = if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) { CHUNK_SIZE as f32 } else { 1.0 };
+    let mut moved = height;
+    if keys.just_pressed(KeyCode::Equals) {
+        moved += step;
+    }
+    if keys.just_pressed(KeyCode::Minus) {
+        moved -= step;
+    }
+    // Only touch the resource on a change, every chunk material is updated when it changes
+    if moved != height {
+        clip_plane.0 = Some(moved);
+    }
+}
+
+#[cfg(feature = "debug-ui")]
+fn show_cutaway_debug_info(mut contexts: bevy_egui::EguiContexts, mut clip_plane: ResMut<ClipPlane>) {
+    use bevy_egui::egui;
+    egui::Window::new("Cutaway").default_open(false).show(contexts.ctx_mut(), |ui| {
+        ui.label("F3 toggle, = / - move the plane, hold Shift for a whole chunk");
+        let mut enabled = clip_plane.0.is_some();
+        let mut height = clip_plane.0.unwrap_or(0.0);
+        ui.checkbox(&mut enabled, "Enabled");
+        ui.add_enabled(enabled, egui::Slider::new(&mut height, -256.0..=256.0).step_by(1.0).text("Clip Height"));
+
+        let updated = enabled.then_some(height);
+        if updated != clip_plane.0 {
+            clip_plane.0 = updated;
+        }
+    });
+}
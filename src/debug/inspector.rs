@@ -0,0 +1,79 @@
+//! Reflection based inspector, `F8` opens it. Shows and edits the [`WorldGeneratorConfig`], the
+//! chunk counts of [`ChunkData`](crate::engine::ChunkData) and the components of every chunk
+//! entity, so tuning a value does not need a slider of its own. Needs the `inspector` feature.
+
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_egui::{egui, EguiContext};
+use bevy_inspector_egui::bevy_inspector;
+
+use crate::engine::{
+    chunk::{Chunk, ChunkPosition},
+    generator::WorldGeneratorConfig,
+    load_policy::LoadPolicy,
+    stats::{ChunkCounts, ChunkStatistics},
+};
+
+const TOGGLE_KEY: KeyCode = KeyCode::F8;
+
+#[derive(Resource, Default)]
+pub struct Inspector {
+    pub enabled: bool,
+}
+
+/// Copy of the [`ChunkData`](crate::engine::ChunkData) statistics, its maps are not reflected
+#[derive(Resource, Reflect, Default)]
+pub struct InspectedChunkStats {
+    pub counts: ChunkCounts,
+    pub voxel_bytes: usize,
+    pub mesh_bytes: usize,
+}
+
+pub struct InspectorPlugin;
+
+impl Plugin for InspectorPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<bevy_inspector_egui::DefaultInspectorConfigPlugin>() {
+            app.add_plugins(bevy_inspector_egui::DefaultInspectorConfigPlugin);
+        }
+        app.init_resource::<Inspector>()
+            .init_resource::<InspectedChunkStats>()
+            .register_type::<WorldGeneratorConfig>()
+            .register_type::<LoadPolicy>()
+            .register_type::<Chunk>()
+            .register_type::<ChunkPosition>()
+            .register_type::<InspectedChunkStats>()
+            .add_systems(Update, (toggle_inspector, update_inspected_stats.run_if(inspector_enabled)))
+            .add_systems(Update, show_inspector.run_if(inspector_enabled).after(update_inspected_stats));
+    }
+}
+
+fn inspector_enabled(inspector: Res<Inspector>) -> bool {
+    inspector.enabled
+}
+
+fn toggle_inspector(mut inspector: ResMut<Inspector>, keys: Res<Input<KeyCode>>) {
+    if keys.just_pressed(TOGGLE_KEY) {
+        inspector.enabled = !inspector.enabled;
+    }
+}
+
+fn update_inspected_stats(statistics: ChunkStatistics, mut inspected: ResMut<InspectedChunkStats>) {
+    let stats = statistics.get();
+    *inspected = InspectedChunkStats { counts: stats.counts, voxel_bytes: stats.voxel_bytes, mesh_bytes: stats.mesh_bytes.unwrap_or_default() };
+}
+
+/// Exclusive, the inspector reads and writes whatever resource or component it shows
+fn show_inspector(world: &mut World) {
+    let Ok(egui_context) = world.query_filtered::<&mut EguiContext, With<PrimaryWindow>>().get_single(world) else {
+        return;
+    };
+    let mut egui_context = egui_context.clone();
+
+    egui::Window::new("Inspector").default_size([320.0, 480.0]).show(egui_context.get_mut(), |ui| {
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            ui.collapsing("World Generator", |ui| bevy_inspector::ui_for_resource::<WorldGeneratorConfig>(world, ui));
+            ui.collapsing("Chunk Data", |ui| bevy_inspector::ui_for_resource::<InspectedChunkStats>(world, ui));
+            ui.collapsing("Chunks", |ui| bevy_inspector::ui_for_world_entities_filtered::<With<Chunk>>(world, ui, false));
+        });
+    });
+}
@@ -1,11 +1,38 @@
 use bevy::prelude::*;
 
+#[cfg(feature = "debug-ui")]
+pub mod bookmarks;
+pub mod cutaway;
+#[cfg(feature = "inspector")]
+pub mod inspector;
+pub mod pipeline_overlay;
+pub mod screenshot;
+#[cfg(feature = "debug-ui")]
+pub mod session;
+pub mod split_screen;
+pub mod stress_test;
+pub mod top_view;
+
 pub struct DebugPlugin;
 
 impl Plugin for DebugPlugin {
     fn build(&self, app: &mut App) {
+        app.add_plugins(stress_test::StressTestPlugin)
+            .add_plugins(top_view::TopViewPlugin)
+            .add_plugins(cutaway::CutawayPlugin)
+            .add_plugins(pipeline_overlay::PipelineOverlayPlugin)
+            .add_plugins(screenshot::ScreenshotPlugin)
+            .add_plugins(split_screen::SplitScreenPlugin);
+
+        #[cfg(feature = "debug-ui")]
+        app.add_plugins(session::DebugSessionPlugin)
+            .add_plugins(bookmarks::BookmarksPlugin);
+
+        #[cfg(feature = "inspector")]
+        app.add_plugins(inspector::InspectorPlugin);
+
         #[cfg(debug_assertions)]
         app.add_plugins(bevy::diagnostic::FrameTimeDiagnosticsPlugin::default())
             .add_plugins(bevy::diagnostic::LogDiagnosticsPlugin::default());
     }
-}
\ No newline at end of file
+}
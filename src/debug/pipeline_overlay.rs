@@ -0,0 +1,111 @@
+//! In-world view of the chunk pipeline. `F6` outlines every chunk that is still being loaded,
+//! generated or meshed, so the latency of the pipeline can be seen where it happens: chunks
+//! stay outlined for as long as they wait.
+
+use bevy::prelude::*;
+
+use crate::engine::{
+    anchor::StreamingAnchor,
+    chunk::{ChunkPosition, CHUNK_SIZE},
+    generator::{ChunkGenerationTask, MeshingTask},
+    persistence::AwaitingLoad,
+    ChunkData,
+};
+
+const TOGGLE_KEY: KeyCode = KeyCode::F6;
+
+/// Colors match the series of the chunk generation plot
+const GENERATING_COLOR: Color = Color::rgb(1.0, 0.0, 0.0);
+const LOADING_COLOR: Color = Color::rgb(1.0, 0.5, 0.0);
+const MESHING_COLOR: Color = Color::rgb(1.0, 1.0, 0.0);
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct PipelineOverlay {
+    pub enabled: bool,
+    /// Chunks further from every streaming anchor are not outlined
+    pub max_distance: f32,
+}
+
+impl Default for PipelineOverlay {
+    fn default() -> Self {
+        Self { enabled: false, max_distance: 8.0 }
+    }
+}
+
+pub struct PipelineOverlayPlugin;
+
+impl Plugin for PipelineOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PipelineOverlay>()
+            .add_systems(Update, (toggle_pipeline_overlay, draw_pipeline_overlay.run_if(overlay_enabled)));
+
+        #[cfg(feature = "debug-ui")]
+        app.add_systems(Update, show_pipeline_overlay_debug_info);
+    }
+}
+
+fn overlay_enabled(overlay: Res<PipelineOverlay>) -> bool {
+    overlay.enabled
+}
+
+fn toggle_pipeline_overlay(mut overlay: ResMut<PipelineOverlay>, keys: Res<Input<KeyCode>>) {
+    if keys.just_pressed(TOGGLE_KEY) {
+        overlay.enabled = !overlay.enabled;
+    }
+}
+
+fn draw_pipeline_overlay(
+    mut gizmos: Gizmos,
+    overlay: Res<PipelineOverlay>,
+    chunk_data: Res<ChunkData>,
+    generating: Query<(), With<ChunkGenerationTask>>,
+    loading: Query<&AwaitingLoad>,
+    meshing: Query<&MeshingTask>,
+    anchors: Query<&Transform, With<StreamingAnchor>>,
+) {
+    let anchors = anchors.iter().map(|transform| ChunkPosition::from_world_position(transform.translation)).collect::<Vec<_>>();
+    let mut outline = |chunk_pos: ChunkPosition, color: Color| {
+        if anchors.iter().any(|anchor| anchor.distance_to(&chunk_pos) <= overlay.max_distance) {
+            gizmos.cuboid(chunk_box(chunk_pos), color);
+        }
+    };
+
+    for (chunk_pos, entity) in chunk_data.awaiting_generation.iter() {
+        if generating.contains(*entity) {
+            outline(*chunk_pos, GENERATING_COLOR);
+        }
+    }
+    for awaiting_load in loading.iter() {
+        outline(awaiting_load.chunk_pos, LOADING_COLOR);
+    }
+    for task in meshing.iter() {
+        outline(task.0, MESHING_COLOR);
+    }
+}
+
+/// Slightly inside the chunk, so the outlines of neighbouring chunks do not overlap
+fn chunk_box(chunk_pos: ChunkPosition) -> Transform {
+    let size = CHUNK_SIZE as f32;
+    Transform::from_translation(chunk_pos.as_world_position() + Vec3::splat(size / 2.0)).with_scale(Vec3::splat(size - 0.25))
+}
+
+#[cfg(feature = "debug-ui")]
+fn show_pipeline_overlay_debug_info(
+    mut contexts: bevy_egui::EguiContexts,
+    mut overlay: ResMut<PipelineOverlay>,
+    generating: Query<(), With<ChunkGenerationTask>>,
+    loading: Query<(), With<AwaitingLoad>>,
+    meshing: Query<(), With<MeshingTask>>,
+) {
+    use bevy_egui::egui;
+    egui::Window::new("Pipeline Overlay").default_open(false).show(contexts.ctx_mut(), |ui| {
+        ui.label("F6 toggle");
+        ui.checkbox(&mut overlay.enabled, "Outline chunks in the pipeline");
+        ui.add(egui::Slider::new(&mut overlay.max_distance, 1.0..=32.0).text("Max Distance (chunks)"));
+
+        ui.separator();
+        ui.colored_label(egui::Color32::from_rgb(255, 0, 0), format!("Generating: {}", generating.iter().count()));
+        ui.colored_label(egui::Color32::from_rgb(255, 128, 0), format!("Loading: {}", loading.iter().count()));
+        ui.colored_label(egui::Color32::from_rgb(255, 255, 0), format!("Meshing: {}", meshing.iter().count()));
+    });
+}
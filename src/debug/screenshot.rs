@@ -0,0 +1,152 @@
+//! `F2` saves a screenshot of the primary window to [`SCREENSHOTS_DIR`].
+//! `Shift+F2` toggles a timelapse that captures a frame every [`Screenshots::interval`] seconds
+//! into its own directory, for comparing how the world streams in.
+
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{prelude::*, render::view::screenshot::ScreenshotManager, window::PrimaryWindow};
+
+pub const SCREENSHOTS_DIR: &str = "screenshots";
+const CAPTURE_KEY: KeyCode = KeyCode::F2;
+
+#[derive(Resource, Debug, Clone)]
+pub struct Screenshots {
+    /// Seconds between timelapse frames
+    pub interval: f32,
+    /// A screenshot is saved at the end of this frame
+    requested: bool,
+    timelapse: Option<Timelapse>,
+}
+
+#[derive(Debug, Clone)]
+struct Timelapse {
+    dir: PathBuf,
+    frame: u32,
+    since_frame: f32,
+}
+
+impl Default for Screenshots {
+    fn default() -> Self {
+        Self { interval: 1.0, requested: false, timelapse: None }
+    }
+}
+
+impl Screenshots {
+    pub fn request(&mut self) {
+        self.requested = true;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.timelapse.is_some()
+    }
+
+    /// Starts a timelapse in a new directory or stops the running one
+    pub fn toggle_timelapse(&mut self) {
+        self.timelapse = match self.timelapse {
+            Some(_) => None,
+            None => Some(Timelapse {
+                dir: PathBuf::from(SCREENSHOTS_DIR).join(format!("timelapse-{}", unix_millis())),
+                frame: 0,
+                // Capture the first frame right away
+                since_frame: f32::INFINITY,
+            }),
+        };
+    }
+}
+
+fn unix_millis() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_millis())
+}
+
+/// Path of a single screenshot, named after the time it was taken
+pub fn screenshot_path() -> PathBuf {
+    PathBuf::from(SCREENSHOTS_DIR).join(format!("screenshot-{}.png", unix_millis()))
+}
+
+pub struct ScreenshotPlugin;
+
+impl Plugin for ScreenshotPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Screenshots>()
+            .add_systems(Update, (handle_screenshot_input, capture_screenshots.after(handle_screenshot_input)));
+
+        #[cfg(feature = "debug-ui")]
+        app.add_systems(Update, show_screenshot_debug_info);
+    }
+}
+
+fn handle_screenshot_input(mut screenshots: ResMut<Screenshots>, keys: Res<Input<KeyCode>>) {
+    if !keys.just_pressed(CAPTURE_KEY) {
+        return;
+    }
+    if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        screenshots.toggle_timelapse();
+    } else {
+        screenshots.request();
+    }
+}
+
+fn save(manager: &mut ScreenshotManager, window: Entity, path: PathBuf) {
+    if let Some(dir) = path.parent() {
+        if let Err(err) = std::fs::create_dir_all(dir) {
+            error!("Failed to create {}: {}", dir.display(), err);
+            return;
+        }
+    }
+    match manager.save_screenshot_to_disk(window, &path) {
+        Ok(()) => info!("Saving screenshot to {}", path.display()),
+        Err(err) => warn!("Screenshot {} skipped: {}", path.display(), err),
+    }
+}
+
+fn capture_screenshots(
+    mut screenshots: ResMut<Screenshots>,
+    mut manager: ResMut<ScreenshotManager>,
+    time: Res<Time>,
+    window: Query<Entity, With<PrimaryWindow>>,
+) {
+    let Ok(window) = window.get_single() else {
+        return;
+    };
+
+    if screenshots.requested {
+        screenshots.requested = false;
+        save(&mut manager, window, screenshot_path());
+    }
+
+    let interval = screenshots.interval;
+    let Some(timelapse) = screenshots.timelapse.as_mut() else {
+        return;
+    };
+    timelapse.since_frame += time.delta_seconds();
+    if timelapse.since_frame < interval {
+        return;
+    }
+    timelapse.since_frame = 0.0;
+    timelapse.frame += 1;
+    // Numbered frames sort correctly and can be fed to ffmpeg as `frame-%05d.png`
+    let path = timelapse.dir.join(format!("frame-{:05}.png", timelapse.frame));
+    save(&mut manager, window, path);
+}
+
+#[cfg(feature = "debug-ui")]
+fn show_screenshot_debug_info(mut contexts: bevy_egui::EguiContexts, mut screenshots: ResMut<Screenshots>) {
+    use bevy_egui::egui;
+    egui::Window::new("Screenshots").default_open(false).show(contexts.ctx_mut(), |ui| {
+        ui.label("F2 screenshot, Shift+F2 start / stop timelapse");
+        if ui.button("Take Screenshot").clicked() {
+            screenshots.request();
+        }
+        let label = if screenshots.is_recording() { "Stop Timelapse" } else { "Start Timelapse" };
+        if ui.button(label).clicked() {
+            screenshots.toggle_timelapse();
+        }
+        ui.add(egui::Slider::new(&mut screenshots.interval, 0.1..=10.0).text("Seconds per Frame"));
+        if let Some(timelapse) = screenshots.timelapse.as_ref() {
+            ui.label(format!("Recording frame {} to {}", timelapse.frame, timelapse.dir.display()));
+        }
+    });
+}
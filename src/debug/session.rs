@@ -0,0 +1,234 @@
+//! Debug session state kept between runs of debug builds: camera, render modes and which debug
+//! windows are expanded, so a problem spot does not have to be found again after every restart.
+//!
+//! The session is restored once the camera exists and saved every few seconds and on shutdown.
+
+use std::{fs, io, path::{Path, PathBuf}};
+
+use bevy::{pbr::wireframe::WireframeConfig, prelude::*};
+use bevy_egui::{egui, EguiContexts};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    engine::{chunk_material::ClipPlane, shutdown::{shutdown, ShutdownState}},
+    flycam::PlayerCamera,
+    gameplay::spawn::PendingSpawn,
+};
+
+use super::top_view::TopView;
+
+pub const SESSION_FILE: &str = "debug_session.ron";
+
+/// Titles of the debug windows whose expanded state is remembered
+const PANELS: [&str; 11] = [
+    "Chunk Generation", "Stress Test", "Top View", "Cutaway", "Worlds", "Beacons", "Selection", "Super Chunks", "Bookmarks",
+    "Screenshots", "Split Screen",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct DebugSession {
+    pub camera_translation: [f32; 3],
+    pub camera_rotation: [f32; 4],
+    pub wireframe: bool,
+    pub clip_height: Option<f32>,
+    pub top_view: bool,
+    /// Titles of the expanded debug windows
+    pub open_panels: Vec<String>,
+}
+
+impl DebugSession {
+    /// `None` if no session was saved yet
+    pub fn load(path: &Path) -> io::Result<Option<Self>> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        ron::from_str(&text).map(Some).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        fs::write(path, text)
+    }
+
+    pub fn camera_transform(&self) -> Transform {
+        Transform::from_translation(Vec3::from_array(self.camera_translation))
+            .with_rotation(Quat::from_array(self.camera_rotation).normalize())
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct DebugSessionSettings {
+    /// Restore the last session on startup and keep saving it
+    pub enabled: bool,
+    /// Seconds between autosaves, the file is only written when something changed
+    pub interval: f32,
+    pub path: PathBuf,
+}
+
+impl Default for DebugSessionSettings {
+    fn default() -> Self {
+        Self { enabled: true, interval: 5.0, path: PathBuf::from(SESSION_FILE) }
+    }
+}
+
+pub struct DebugSessionPlugin;
+
+impl Plugin for DebugSessionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugSessionSettings>()
+            .add_systems(Update, (restore_debug_session, autosave_debug_session.after(restore_debug_session)))
+            .add_systems(Last, save_debug_session_on_shutdown
+                .before(shutdown)
+                .run_if(resource_equals(ShutdownState::Requested)));
+    }
+}
+
+fn panel_state(ctx: &egui::Context, title: &str) -> egui::collapsing_header::CollapsingState {
+    // Same id `egui::Window` uses for its collapsing header
+    let id = egui::Id::new(title).with("collapsing");
+    egui::collapsing_header::CollapsingState::load_with_default_open(ctx, id, false)
+}
+
+fn capture_session(
+    ctx: &egui::Context,
+    camera: &Transform,
+    top_view: &TopView,
+    clip_plane: &ClipPlane,
+    wireframe: Option<&WireframeConfig>,
+) -> DebugSession {
+    // While the top view is open the camera is looking straight down, keep the position it returns to
+    let camera = top_view.saved_transform().unwrap_or(camera);
+    DebugSession {
+        camera_translation: camera.translation.to_array(),
+        camera_rotation: camera.rotation.to_array(),
+        wireframe: wireframe.is_some_and(|config| config.global),
+        clip_height: clip_plane.0,
+        top_view: top_view.enabled,
+        open_panels: PANELS
+            .iter()
+            .filter(|title| panel_state(ctx, title).is_open())
+            .map(|title| title.to_string())
+            .collect(),
+    }
+}
+
+fn restore_debug_session(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut restored: Local<bool>,
+    mut top_view: ResMut<TopView>,
+    mut clip_plane: ResMut<ClipPlane>,
+    wireframe: Option<ResMut<WireframeConfig>>,
+    settings: Res<DebugSessionSettings>,
+    mut camera: Query<&mut Transform, With<PlayerCamera>>,
+) {
+    if *restored || !settings.enabled {
+        return;
+    }
+    let Ok(mut transform) = camera.get_single_mut() else {
+        return;
+    };
+    *restored = true;
+
+    let session = match DebugSession::load(&settings.path) {
+        Ok(Some(session)) => session,
+        Ok(None) => return,
+        Err(err) => {
+            warn!("Failed to load debug session from {:?}: {}", settings.path, err);
+            return;
+        }
+    };
+
+    // The camera of the last run wins over the spawn point
+    *transform = session.camera_transform();
+    commands.remove_resource::<PendingSpawn>();
+    top_view.enabled = session.top_view;
+    clip_plane.0 = session.clip_height;
+    if let Some(mut wireframe) = wireframe {
+        wireframe.global = session.wireframe;
+    }
+    let ctx = contexts.ctx_mut();
+    for title in session.open_panels.iter() {
+        let mut state = panel_state(ctx, title);
+        state.set_open(true);
+        state.store(ctx);
+    }
+    info!("Restored debug session from {:?}", settings.path);
+}
+
+fn autosave_debug_session(
+    mut contexts: EguiContexts,
+    mut last_saved: Local<Option<DebugSession>>,
+    mut since_save: Local<f32>,
+    settings: Res<DebugSessionSettings>,
+    top_view: Res<TopView>,
+    clip_plane: Res<ClipPlane>,
+    wireframe: Option<Res<WireframeConfig>>,
+    time: Res<Time>,
+    camera: Query<&Transform, With<PlayerCamera>>,
+) {
+    *since_save += time.delta_seconds();
+    if !settings.enabled || *since_save < settings.interval {
+        return;
+    }
+    *since_save = 0.0;
+    let Ok(transform) = camera.get_single() else {
+        return;
+    };
+
+    let session = capture_session(contexts.ctx_mut(), transform, &top_view, &clip_plane, wireframe.as_deref());
+    if last_saved.as_ref() == Some(&session) {
+        return;
+    }
+    match session.save(&settings.path) {
+        Ok(()) => *last_saved = Some(session),
+        Err(err) => warn!("Failed to save debug session to {:?}: {}", settings.path, err),
+    }
+}
+
+fn save_debug_session_on_shutdown(
+    mut contexts: EguiContexts,
+    settings: Res<DebugSessionSettings>,
+    top_view: Res<TopView>,
+    clip_plane: Res<ClipPlane>,
+    wireframe: Option<Res<WireframeConfig>>,
+    camera: Query<&Transform, With<PlayerCamera>>,
+) {
+    let Ok(transform) = camera.get_single() else {
+        return;
+    };
+    if !settings.enabled {
+        return;
+    }
+    let session = capture_session(contexts.ctx_mut(), transform, &top_view, &clip_plane, wireframe.as_deref());
+    if let Err(err) = session.save(&settings.path) {
+        warn!("Failed to save debug session to {:?}: {}", settings.path, err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn test_session_round_trip() {
+        let dir = TempDir::new("debug-session");
+        let path = dir.join("session.ron");
+        assert_eq!(DebugSession::load(&path).unwrap(), None);
+
+        let session = DebugSession {
+            camera_translation: [1.0, 64.5, -3.0],
+            camera_rotation: Quat::from_rotation_y(1.0).to_array(),
+            wireframe: true,
+            clip_height: Some(-12.0),
+            top_view: false,
+            open_panels: vec!["Cutaway".to_string()],
+        };
+        session.save(&path).unwrap();
+        assert_eq!(DebugSession::load(&path).unwrap(), Some(session));
+    }
+}
@@ -0,0 +1,169 @@
+//! Split-screen streaming test. `F5` adds a second player camera on the right half of the window
+//! with its own render distance. Both cameras are streaming anchors, the chunks they both see are
+//! loaded and meshed once and shared by the two views.
+//!
+//! The arrow keys move and turn the second player.
+
+use bevy::{prelude::*, render::camera::Viewport, window::PrimaryWindow};
+
+use crate::engine::anchor::StreamingAnchor;
+
+const TOGGLE_KEY: KeyCode = KeyCode::F5;
+/// The second player starts this far to the right of the first one, in world units
+const SPAWN_OFFSET: f32 = 64.0;
+
+#[derive(Resource, Debug, Clone)]
+pub struct SplitScreen {
+    pub enabled: bool,
+    /// Render distance of the second player
+    pub render_distance: usize,
+    /// World units per second
+    pub speed: f32,
+    /// Radians per second
+    pub turn_speed: f32,
+}
+
+impl Default for SplitScreen {
+    fn default() -> Self {
+        Self { enabled: false, render_distance: 8, speed: 20.0, turn_speed: 1.5 }
+    }
+}
+
+/// Camera of the second player
+#[derive(Component)]
+pub struct SecondPlayer;
+
+pub struct SplitScreenPlugin;
+
+impl Plugin for SplitScreenPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SplitScreen>()
+            .add_systems(Update, (
+                toggle_split_screen,
+                update_viewports.after(toggle_split_screen),
+                move_second_player,
+            ));
+
+        #[cfg(feature = "debug-ui")]
+        app.add_systems(Update, show_split_screen_debug_info);
+    }
+}
+
+fn toggle_split_screen(
+    mut commands: Commands,
+    mut split_screen: ResMut<SplitScreen>,
+    keys: Res<Input<KeyCode>>,
+    second_players: Query<Entity, With<SecondPlayer>>,
+    mut first_player: Query<(&Transform, &mut Camera), (With<StreamingAnchor>, Without<SecondPlayer>)>,
+) {
+    let wants_enabled = if keys.just_pressed(TOGGLE_KEY) { !split_screen.enabled } else { split_screen.enabled };
+    let is_active = !second_players.is_empty();
+    if wants_enabled == is_active {
+        return;
+    }
+    let Some((transform, mut camera)) = first_player.iter_mut().next() else {
+        return;
+    };
+
+    if wants_enabled {
+        let mut start = *transform;
+        start.translation += transform.right() * SPAWN_OFFSET;
+        commands.spawn((
+            Camera3dBundle {
+                camera: Camera { order: 1, ..Default::default() },
+                transform: start,
+                ..Default::default()
+            },
+            // The HUD belongs to the first player
+            UiCameraConfig { show_ui: false },
+            StreamingAnchor::with_render_distance(split_screen.render_distance),
+            SecondPlayer,
+        ));
+    } else {
+        for entity in second_players.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        camera.viewport = None;
+    }
+    split_screen.enabled = wants_enabled;
+}
+
+/// Only assigns the viewport when it changed, every camera change recomputes its projection
+fn set_viewport(camera: &mut Mut<Camera>, physical_position: UVec2, physical_size: UVec2) {
+    let current = camera.viewport.as_ref().map(|viewport| (viewport.physical_position, viewport.physical_size));
+    if current != Some((physical_position, physical_size)) {
+        camera.viewport = Some(Viewport { physical_position, physical_size, ..Default::default() });
+    }
+}
+
+/// Splits the window between the two players and keeps the render distance of the second one up to date
+fn update_viewports(
+    split_screen: Res<SplitScreen>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    mut first_player: Query<&mut Camera, (With<StreamingAnchor>, Without<SecondPlayer>)>,
+    mut second_player: Query<(&mut Camera, &mut StreamingAnchor), With<SecondPlayer>>,
+) {
+    let Ok(window) = window.get_single() else {
+        return;
+    };
+    let Ok((mut second_camera, mut anchor)) = second_player.get_single_mut() else {
+        return;
+    };
+    if anchor.render_distance != Some(split_screen.render_distance) {
+        anchor.render_distance = Some(split_screen.render_distance);
+    }
+
+    let half = UVec2::new((window.physical_width() / 2).max(1), window.physical_height().max(1));
+    for mut camera in first_player.iter_mut() {
+        set_viewport(&mut camera, UVec2::ZERO, half);
+    }
+    set_viewport(&mut second_camera, UVec2::new(half.x, 0), half);
+}
+
+fn move_second_player(
+    split_screen: Res<SplitScreen>,
+    keys: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    mut second_player: Query<&mut Transform, With<SecondPlayer>>,
+) {
+    let Ok(mut transform) = second_player.get_single_mut() else {
+        return;
+    };
+
+    let mut turn = 0.0;
+    if keys.pressed(KeyCode::Left) { turn += 1.0; }
+    if keys.pressed(KeyCode::Right) { turn -= 1.0; }
+    transform.rotate_y(turn * split_screen.turn_speed * time.delta_seconds());
+
+    let forward = transform.forward();
+    let mut direction = 0.0;
+    if keys.pressed(KeyCode::Up) { direction += 1.0; }
+    if keys.pressed(KeyCode::Down) { direction -= 1.0; }
+    transform.translation += forward * direction * split_screen.speed * time.delta_seconds();
+}
+
+#[cfg(feature = "debug-ui")]
+fn show_split_screen_debug_info(
+    mut contexts: bevy_egui::EguiContexts,
+    mut split_screen: ResMut<SplitScreen>,
+    chunk_data: Res<crate::engine::ChunkData>,
+    chunk_index: Res<crate::engine::chunk_index::ChunkIndex>,
+    spawn_queue: Res<crate::engine::spawn_queue::ChunkSpawnQueue>,
+) {
+    use bevy::utils::HashMap;
+    use bevy_egui::egui;
+    egui::Window::new("Split Screen").default_open(false).show(contexts.ctx_mut(), |ui| {
+        ui.label("F5 toggle, arrow keys move the second player");
+        ui.checkbox(&mut split_screen.enabled, "Enabled");
+        ui.add(egui::Slider::new(&mut split_screen.render_distance, 2..=32).text("Second Render Distance"));
+
+        // Chunks seen by both players must not be loaded twice
+        let mut entities_per_chunk = HashMap::<_, usize>::default();
+        for (_, chunk_pos) in chunk_index.iter().filter(|(entity, _)| !spawn_queue.is_despawning(*entity)) {
+            *entities_per_chunk.entry(chunk_pos).or_default() += 1;
+        }
+        let duplicated = entities_per_chunk.values().filter(|count| **count > 1).count();
+        ui.label(format!("Loaded Chunks: {}, Visible Chunks: {}", chunk_data.loaded.len(), chunk_data.visible.len()));
+        ui.label(format!("Chunks with Duplicate Entities: {}", duplicated));
+    });
+}
@@ -0,0 +1,274 @@
+//! Worst-case load test: force-loads and meshes an N×N×N cube of chunks around the camera,
+//! ignoring visibility, and reports totals once every chunk is done. Started from its debug
+//! window or with the `stress <N>` console command, `stress stop` ends it.
+
+use bevy::prelude::*;
+
+use crate::{
+    console::ConsoleAppExt,
+    engine::{
+        chunk::{Chunk, ChunkPosition},
+        generator::{begin_chunk_generation, unload_invisible_chunks, update_visible_chunks, EmptyChunkMarker, WorldGeneratorConfig},
+        spawn_queue::{flush_chunk_spawns, ChunkSpawnQueue},
+        ChunkData,
+    },
+    flycam::PlayerCamera,
+};
+
+/// Largest cube edge length in chunks that can be started
+pub const MAX_SIZE: u32 = 32;
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct StartStressTest {
+    /// Edge length of the cube in chunks
+    pub size: u32,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct StopStressTest;
+
+#[derive(Debug, Clone, Default)]
+pub struct StressTestReport {
+    pub chunks: usize,
+    pub meshed: usize,
+    pub empty: usize,
+    pub vertices: usize,
+    pub indices: usize,
+    pub mesh_bytes: u64,
+    /// Seconds from start until the last chunk was meshed
+    pub duration: f32,
+    pub average_frame_ms: f32,
+}
+
+pub struct StressTestRun {
+    pub size: u32,
+    pub center: ChunkPosition,
+    chunks: Vec<ChunkPosition>,
+    started_at: f32,
+    frames: u32,
+    frame_time_sum: f32,
+    pub report: Option<StressTestReport>,
+}
+
+impl StressTestRun {
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct StressTest {
+    pub run: Option<StressTestRun>,
+}
+
+pub struct StressTestPlugin;
+
+impl Plugin for StressTestPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StressTest>()
+            .add_event::<StartStressTest>()
+            .add_event::<StopStressTest>()
+            .add_systems(Update, (
+                handle_stress_test_events,
+                force_load_stress_test_chunks
+                    .after(handle_stress_test_events)
+                    .after(update_visible_chunks)
+                    .before(flush_chunk_spawns)
+                    .before(begin_chunk_generation)
+                    .before(unload_invisible_chunks),
+                check_stress_test_progress,
+            ))
+            .register_command("stress", "stress <size|stop>", stress);
+
+        #[cfg(feature = "debug-ui")]
+        app.add_systems(Update, show_stress_test_debug_info);
+    }
+}
+
+fn stress(world: &mut World, args: &[&str]) -> Result<String, String> {
+    match args {
+        ["stop"] => {
+            world.send_event(StopStressTest);
+            Ok("Stress test stopped".to_string())
+        }
+        [size] => {
+            let size = size.parse::<u32>().map_err(|err| format!("invalid size: {}", err))?;
+            if !(1..=MAX_SIZE).contains(&size) {
+                return Err(format!("size must be between 1 and {}", MAX_SIZE));
+            }
+            world.send_event(StartStressTest { size });
+            Ok(format!("Stress test of {}³ chunks started", size))
+        }
+        _ => Err("usage: stress <size|stop>".to_string()),
+    }
+}
+
+fn handle_stress_test_events(
+    mut stress_test: ResMut<StressTest>,
+    mut start_events: EventReader<StartStressTest>,
+    mut stop_events: EventReader<StopStressTest>,
+    config: Res<WorldGeneratorConfig>,
+    camera: Query<&Transform, With<PlayerCamera>>,
+    time: Res<Time>,
+) {
+    if stop_events.read().count() > 0 {
+        stress_test.run = None;
+    }
+
+    let Some(event) = start_events.read().last() else {
+        return;
+    };
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+
+    let center = ChunkPosition::from_world_position(camera.translation);
+    let size = event.size.max(1) as i32;
+    let half = size / 2;
+    let mut chunks = Vec::with_capacity((size * size * size) as usize);
+    for x in -half..size - half {
+        for y in -half..size - half {
+            for z in -half..size - half {
+                let chunk_pos = ChunkPosition::new(center.x + x, center.y + y, center.z + z);
+                if !config.is_outside_world(&chunk_pos) {
+                    chunks.push(chunk_pos);
+                }
+            }
+        }
+    }
+
+    info!("Stress test started: {} chunks around {:?}", chunks.len(), center);
+    stress_test.run = Some(StressTestRun {
+        size: event.size,
+        center,
+        chunks,
+        started_at: time.elapsed_seconds(),
+        frames: 0,
+        frame_time_sum: 0.0,
+        report: None,
+    });
+}
+
+/// Keeps the stress test chunks visible so they get generated and meshed and are never unloaded
+fn force_load_stress_test_chunks(
+    mut commands: Commands,
+    mut chunk_data: ResMut<ChunkData>,
+    mut spawn_queue: ResMut<ChunkSpawnQueue>,
+    stress_test: Res<StressTest>,
+    unmeshed_chunks: Query<(), (With<Chunk>, Without<Handle<Mesh>>)>,
+) {
+    let Some(run) = &stress_test.run else {
+        return;
+    };
+
+    for chunk_pos in run.chunks.iter() {
+        chunk_data.visible.insert(*chunk_pos);
+        if let Some(entity) = chunk_data.loaded.get(chunk_pos).copied() {
+            // Meshes of chunks outside the view are removed by the streaming systems, put them back
+            if let Some(mesh) = chunk_data.meshes.get(chunk_pos) {
+                if unmeshed_chunks.contains(entity) {
+                    commands.entity(entity).try_insert(mesh.clone());
+                }
+            }
+        } else if !chunk_data.awaiting_generation.contains_key(chunk_pos) {
+            spawn_queue.queue_spawn(*chunk_pos);
+        }
+    }
+}
+
+fn check_stress_test_progress(
+    mut stress_test: ResMut<StressTest>,
+    chunk_data: Res<ChunkData>,
+    empty_chunks: Query<(), With<EmptyChunkMarker>>,
+    meshes: Res<Assets<Mesh>>,
+    time: Res<Time>,
+) {
+    let Some(run) = &mut stress_test.run else {
+        return;
+    };
+    if run.report.is_some() {
+        return;
+    }
+    run.frames += 1;
+    run.frame_time_sum += time.delta_seconds();
+
+    let mut report = StressTestReport { chunks: run.chunks.len(), ..Default::default() };
+    for chunk_pos in run.chunks.iter() {
+        let Some(entity) = chunk_data.loaded.get(chunk_pos) else {
+            return;
+        };
+        if empty_chunks.contains(*entity) {
+            report.empty += 1;
+            continue;
+        }
+        let Some(mesh) = chunk_data.meshes.get(chunk_pos).and_then(|handle| meshes.get(handle)) else {
+            return;
+        };
+        let indices = mesh.indices().map_or(0, |indices| indices.len());
+        report.meshed += 1;
+        report.vertices += mesh.count_vertices();
+        report.indices += indices;
+        report.mesh_bytes += mesh.attributes().map(|(_, values)| values.get_bytes().len() as u64).sum::<u64>() + indices as u64 * 4;
+    }
+
+    report.duration = time.elapsed_seconds() - run.started_at;
+    report.average_frame_ms = run.frame_time_sum / run.frames as f32 * 1000.0;
+    info!(
+        "Stress test finished in {:.2}s: {} chunks ({} meshed, {} empty), {} vertices, {} indices, {:.1} MB of mesh data, {:.2} ms/frame",
+        report.duration,
+        report.chunks,
+        report.meshed,
+        report.empty,
+        report.vertices,
+        report.indices,
+        report.mesh_bytes as f64 / 1024.0 / 1024.0,
+        report.average_frame_ms,
+    );
+    run.report = Some(report);
+}
+
+#[cfg(feature = "debug-ui")]
+fn show_stress_test_debug_info(
+    mut contexts: bevy_egui::EguiContexts,
+    mut size: Local<Option<u32>>,
+    mut start_events: EventWriter<StartStressTest>,
+    mut stop_events: EventWriter<StopStressTest>,
+    stress_test: Res<StressTest>,
+    chunk_data: Res<ChunkData>,
+) {
+    use bevy_egui::egui;
+    let size = size.get_or_insert(8);
+
+    egui::Window::new("Stress Test").default_open(false).show(contexts.ctx_mut(), |ui| {
+        ui.add(egui::Slider::new(size, 1..=MAX_SIZE).text("Size (chunks)"));
+        ui.horizontal(|ui| {
+            if ui.button("Start").clicked() {
+                start_events.send(StartStressTest { size: *size });
+            }
+            if ui.button("Stop").clicked() {
+                stop_events.send(StopStressTest);
+            }
+        });
+
+        let Some(run) = &stress_test.run else {
+            return;
+        };
+        ui.separator();
+        ui.label(format!("{}³ chunks around {:?}", run.size, run.center));
+        match &run.report {
+            None => {
+                let loaded = run.chunks.iter().filter(|chunk| chunk_data.loaded.contains_key(*chunk)).count();
+                let meshed = run.chunks.iter().filter(|chunk| chunk_data.meshes.contains_key(*chunk)).count();
+                ui.label(format!("Loaded: {}/{}", loaded, run.chunk_count()));
+                ui.label(format!("Meshed: {}", meshed));
+            }
+            Some(report) => {
+                ui.label(format!("Finished in {:.2}s", report.duration));
+                ui.label(format!("Chunks: {} ({} meshed, {} empty)", report.chunks, report.meshed, report.empty));
+                ui.label(format!("Vertices: {}, indices: {}", report.vertices, report.indices));
+                ui.label(format!("Mesh data: {:.1} MB", report.mesh_bytes as f64 / 1024.0 / 1024.0));
+                ui.label(format!("Average frame: {:.2} ms", report.average_frame_ms));
+            }
+        }
+    });
+}
@@ -0,0 +1,235 @@
+//! Editor style top-down view. `F4` switches the camera to an orthographic projection looking
+//! straight down at a horizontal slab of chunks, everything above the slab is hidden.
+//!
+//! `WASD` pans, the mouse wheel zooms and `PageUp` / `PageDown` move the slab up and down.
+
+use bevy::{input::mouse::MouseWheel, prelude::*, utils::HashSet};
+
+use crate::{
+    engine::{
+        chunk::{Chunk, ChunkPosition, CHUNK_SIZE},
+        generator::{begin_chunk_generation, unload_invisible_chunks, update_visible_chunks, VisibilityRefresh, WorldGeneratorConfig},
+        spawn_queue::{flush_chunk_spawns, ChunkSpawnQueue},
+        ChunkData,
+    },
+    flycam::{FlyCam, PlayerCamera},
+};
+
+const TOGGLE_KEY: KeyCode = KeyCode::F4;
+/// The camera hovers this high above the top of the slab
+const CAMERA_HEIGHT: f32 = 32.0;
+/// Limits how many chunks a zoomed out view loads, in chunks from the view center
+const MAX_SLAB_RADIUS: i32 = 24;
+
+#[derive(Resource, Debug, Clone)]
+pub struct TopView {
+    pub enabled: bool,
+    /// Lowest and highest chunk layer of the slab, both inclusive
+    pub min_y: i32,
+    pub max_y: i32,
+    /// World units per pixel
+    pub zoom: f32,
+    /// Screen heights per second
+    pub pan_speed: f32,
+    /// Camera state to return to when the view is closed
+    saved: Option<(Transform, Projection)>,
+}
+
+impl Default for TopView {
+    fn default() -> Self {
+        Self { enabled: false, min_y: -2, max_y: 0, zoom: 0.1, pan_speed: 0.5, saved: None }
+    }
+}
+
+impl TopView {
+    /// Camera transform from before the view was opened, `None` while it is closed
+    pub fn saved_transform(&self) -> Option<&Transform> {
+        self.saved.as_ref().map(|(transform, _)| transform)
+    }
+
+    fn camera_y(&self) -> f32 {
+        ((self.max_y + 1) * CHUNK_SIZE as i32) as f32 + CAMERA_HEIGHT
+    }
+
+    /// Chunks of the slab covering a view centered at `center` with the given half extents in world units
+    pub fn slab(&self, center: Vec3, half_extents: Vec2) -> HashSet<ChunkPosition> {
+        let center_chunk = ChunkPosition::from_world_position(center);
+        let radius = |extent: f32| ((extent / CHUNK_SIZE as f32).ceil() as i32 + 1).min(MAX_SLAB_RADIUS);
+        let (radius_x, radius_z) = (radius(half_extents.x), radius(half_extents.y));
+
+        let mut chunks = HashSet::default();
+        for y in self.min_y..=self.max_y {
+            for x in -radius_x..=radius_x {
+                for z in -radius_z..=radius_z {
+                    chunks.insert(ChunkPosition::new(center_chunk.x + x, y, center_chunk.z + z));
+                }
+            }
+        }
+        chunks
+    }
+}
+
+pub struct TopViewPlugin;
+
+impl Plugin for TopViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TopView>()
+            .add_systems(Update, (
+                toggle_top_view,
+                top_view_controls.after(toggle_top_view),
+                load_top_view_slab
+                    .after(top_view_controls)
+                    .after(update_visible_chunks)
+                    .before(flush_chunk_spawns)
+                    .before(begin_chunk_generation)
+                    .before(unload_invisible_chunks),
+            ));
+
+        #[cfg(feature = "debug-ui")]
+        app.add_systems(Update, show_top_view_debug_info);
+    }
+}
+
+fn toggle_top_view(
+    mut commands: Commands,
+    mut top_view: ResMut<TopView>,
+    keys: Res<Input<KeyCode>>,
+    mut camera: Query<(Entity, &mut Transform, &mut Projection), With<PlayerCamera>>,
+    mut visibility_refresh: ResMut<VisibilityRefresh>,
+) {
+    let wants_enabled = if keys.just_pressed(TOGGLE_KEY) { !top_view.enabled } else { top_view.enabled };
+    let is_active = top_view.saved.is_some();
+    if wants_enabled == is_active {
+        return;
+    }
+    let Ok((entity, mut transform, mut projection)) = camera.get_single_mut() else {
+        return;
+    };
+
+    if wants_enabled {
+        top_view.saved = Some((*transform, projection.clone()));
+        let position = Vec3::new(transform.translation.x, top_view.camera_y(), transform.translation.z);
+        *transform = Transform::from_translation(position).looking_at(position - Vec3::Y, Vec3::NEG_Z);
+        *projection = Projection::Orthographic(OrthographicProjection { scale: top_view.zoom, far: 4096.0, ..Default::default() });
+        // Flycam systems would fight over the transform
+        commands.entity(entity).remove::<FlyCam>();
+    } else if let Some((saved_transform, saved_projection)) = top_view.saved.take() {
+        *transform = saved_transform;
+        *projection = saved_projection;
+        commands.entity(entity).insert(FlyCam);
+        // The slab replaced the visible chunks, the camera may be back where it was before
+        visibility_refresh.invalidate();
+    }
+    top_view.enabled = wants_enabled;
+}
+
+fn top_view_controls(
+    mut top_view: ResMut<TopView>,
+    mut wheel: EventReader<MouseWheel>,
+    keys: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    mut camera: Query<(&mut Transform, &mut Projection), With<PlayerCamera>>,
+) {
+    if top_view.saved.is_none() {
+        wheel.clear();
+        return;
+    }
+    let Ok((mut transform, mut projection)) = camera.get_single_mut() else {
+        return;
+    };
+    let Projection::Orthographic(ortho) = &mut *projection else {
+        return;
+    };
+
+    for event in wheel.read() {
+        top_view.zoom = (top_view.zoom * (1.0 - event.y.clamp(-1.0, 1.0) * 0.1)).clamp(0.01, 2.0);
+    }
+    ortho.scale = top_view.zoom;
+
+    if keys.just_pressed(KeyCode::PageUp) {
+        top_view.min_y += 1;
+        top_view.max_y += 1;
+    }
+    if keys.just_pressed(KeyCode::PageDown) {
+        top_view.min_y -= 1;
+        top_view.max_y -= 1;
+    }
+
+    let mut direction = Vec2::ZERO;
+    if keys.pressed(KeyCode::W) { direction.y -= 1.0; }
+    if keys.pressed(KeyCode::S) { direction.y += 1.0; }
+    if keys.pressed(KeyCode::A) { direction.x -= 1.0; }
+    if keys.pressed(KeyCode::D) { direction.x += 1.0; }
+    // `area` is the visible rectangle in world units
+    let speed = ortho.area.height() * top_view.pan_speed * time.delta_seconds();
+    let offset = direction.normalize_or_zero() * speed;
+    transform.translation += Vec3::new(offset.x, 0.0, offset.y);
+    transform.translation.y = top_view.camera_y();
+}
+
+/// Makes the slab under the view the only visible chunks, so everything above it is hidden
+fn load_top_view_slab(
+    mut commands: Commands,
+    mut chunk_data: ResMut<ChunkData>,
+    mut spawn_queue: ResMut<ChunkSpawnQueue>,
+    top_view: Res<TopView>,
+    config: Res<WorldGeneratorConfig>,
+    camera: Query<(&Transform, &Projection), With<PlayerCamera>>,
+    unmeshed_chunks: Query<(), (With<Chunk>, Without<Handle<Mesh>>)>,
+) {
+    if top_view.saved.is_none() {
+        return;
+    }
+    let Ok((transform, Projection::Orthographic(ortho))) = camera.get_single() else {
+        return;
+    };
+
+    let slab = top_view.slab(transform.translation, ortho.area.half_size());
+    for chunk_pos in slab.iter() {
+        if config.is_outside_world(chunk_pos) {
+            continue;
+        }
+        if let Some(entity) = chunk_data.loaded.get(chunk_pos).copied() {
+            if let Some(mesh) = chunk_data.meshes.get(chunk_pos) {
+                if unmeshed_chunks.contains(entity) {
+                    commands.entity(entity).try_insert(mesh.clone());
+                }
+            }
+        } else if !chunk_data.awaiting_generation.contains_key(chunk_pos) {
+            spawn_queue.queue_spawn(*chunk_pos);
+        }
+    }
+    chunk_data.visible = slab;
+}
+
+#[cfg(feature = "debug-ui")]
+fn show_top_view_debug_info(mut contexts: bevy_egui::EguiContexts, mut top_view: ResMut<TopView>) {
+    use bevy_egui::egui;
+    egui::Window::new("Top View").default_open(false).show(contexts.ctx_mut(), |ui| {
+        ui.label("F4 toggle, WASD pan, wheel zoom, PageUp / PageDown move the slab");
+        ui.checkbox(&mut top_view.enabled, "Enabled");
+        let max_y = top_view.max_y;
+        ui.add(egui::Slider::new(&mut top_view.min_y, -16..=max_y).text("Lowest Chunk Layer"));
+        let min_y = top_view.min_y;
+        ui.add(egui::Slider::new(&mut top_view.max_y, min_y..=16).text("Highest Chunk Layer"));
+        ui.add(egui::Slider::new(&mut top_view.zoom, 0.01..=2.0).logarithmic(true).text("Units per Pixel"));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slab_covers_view() {
+        let top_view = TopView { min_y: -1, max_y: 0, ..Default::default() };
+        let slab = top_view.slab(Vec3::new(8.0, 100.0, 8.0), Vec2::new(20.0, 4.0));
+        // Two chunks of x radius and one of z radius, plus one chunk of margin each
+        assert_eq!(slab.len(), 2 * 7 * 5);
+        assert!(slab.contains(&ChunkPosition::new(-3, -1, -1)));
+        assert!(!slab.contains(&ChunkPosition::new(0, 1, 0)));
+
+        let zoomed_out = top_view.slab(Vec3::ZERO, Vec2::splat(1.0e6));
+        assert_eq!(zoomed_out.len(), 2 * (2 * MAX_SLAB_RADIUS as usize + 1).pow(2));
+    }
+}
@@ -0,0 +1,91 @@
+//! Cameras chunks are streamed around.
+//!
+//! Only cameras with a [`StreamingAnchor`] take part in the visibility search and keep chunks from
+//! being garbage collected, so editor or minimap cameras can exist next to the player camera.
+//! With several anchors the visible chunks are the union of what every anchor sees, and a chunk
+//! is only collected once it is out of range of all of them. Every anchor can have its own render
+//! distance, the chunks themselves are shared between anchors seeing the same part of the world.
+
+use bevy::prelude::*;
+
+use super::{chunk::ChunkPosition, generator::WorldGeneratorConfig, load_policy::LoadPolicy};
+
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct StreamingAnchor {
+    /// Render distance around this anchor, [`WorldGeneratorConfig::render_distance`] when `None`
+    pub render_distance: Option<usize>,
+}
+
+impl StreamingAnchor {
+    pub fn with_render_distance(render_distance: usize) -> Self {
+        Self { render_distance: Some(render_distance) }
+    }
+
+    /// Chunks up to this distance are generated, with the same margin over the render distance the config has
+    pub fn generation_distance(&self, config: &WorldGeneratorConfig) -> usize {
+        match self.render_distance {
+            Some(render_distance) => render_distance + config.generation_distance.saturating_sub(config.render_distance),
+            None => config.generation_distance,
+        }
+    }
+}
+
+/// Chunk an anchor is in and how far around it chunks are kept
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnchorRange {
+    pub chunk: ChunkPosition,
+    pub generation_distance: usize,
+    pub policy: LoadPolicy,
+}
+
+impl AnchorRange {
+    pub fn new(transform: &Transform, anchor: &StreamingAnchor, config: &WorldGeneratorConfig) -> Self {
+        Self {
+            chunk: ChunkPosition::from_world_position(transform.translation),
+            generation_distance: anchor.generation_distance(config),
+            policy: config.load_policy,
+        }
+    }
+
+    /// In reach of the load policy whichever way the anchor looks, so turning around does not unload chunks
+    pub fn contains(&self, chunk: &ChunkPosition) -> bool {
+        self.policy.in_reach(&self.chunk, chunk, self.generation_distance)
+    }
+}
+
+/// Ranges of all anchors, in query order
+pub fn anchor_ranges<'a>(anchors: impl IntoIterator<Item = (&'a Transform, &'a StreamingAnchor)>, config: &WorldGeneratorConfig) -> Vec<AnchorRange> {
+    anchors.into_iter().map(|(transform, anchor)| AnchorRange::new(transform, anchor, config)).collect()
+}
+
+pub fn in_range_of_any(ranges: &[AnchorRange], chunk: &ChunkPosition) -> bool {
+    ranges.iter().any(|range| range.contains(chunk))
+}
+
+/// Squared distance from `chunk` to the closest anchor, `i64::MAX` without anchors
+pub fn distance_squared_to_closest(anchors: &[ChunkPosition], chunk: &ChunkPosition) -> i64 {
+    anchors.iter().map(|anchor| anchor.distance_squared_to(chunk)).min().unwrap_or(i64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anchor_ranges() {
+        let config = WorldGeneratorConfig::default_flat();
+        assert_eq!(StreamingAnchor::default().generation_distance(&config), config.generation_distance);
+        assert_eq!(StreamingAnchor::with_render_distance(4).generation_distance(&config), 4 + config.generation_distance - config.render_distance);
+
+        let near = AnchorRange { chunk: ChunkPosition::new(0, 0, 0), generation_distance: 2, policy: LoadPolicy::Sphere };
+        let far = AnchorRange { chunk: ChunkPosition::new(100, 0, 0), generation_distance: 10, policy: LoadPolicy::Sphere };
+        let ranges = [near, far];
+        assert!(in_range_of_any(&ranges, &ChunkPosition::new(2, 0, 0)));
+        assert!(!in_range_of_any(&ranges, &ChunkPosition::new(2, 1, 0)));
+        assert!(in_range_of_any(&ranges, &ChunkPosition::new(92, 0, 3)));
+        assert!(!in_range_of_any(&[], &ChunkPosition::new(0, 0, 0)));
+
+        let cube = AnchorRange { policy: LoadPolicy::Cube, ..near };
+        assert!(in_range_of_any(&[cube], &ChunkPosition::new(2, 2, -2)));
+    }
+}
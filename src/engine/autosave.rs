@@ -0,0 +1,180 @@
+//! Periodic autosave. Chunks changed since they were last written are tracked in [`DirtyChunks`]
+//! by the systems writing their voxels, every [`AutosaveSettings::interval`] seconds they are handed to the persistence IO thread
+//! together with the world metadata and the pending edits. Whatever is dirty when the app exits is written by the
+//! shutdown sequence, see [`super::shutdown`].
+
+use bevy::{prelude::*, utils::HashSet};
+
+use super::{
+    cache::ChunkCache,
+    chunk::{Chunk, ChunkPosition},
+    generator::ChunkSource,
+    persistence::ChunkStorage,
+    shutdown::{shutdown, write_pending_edits, write_world_metadata},
+    ChunkData,
+};
+
+/// Chunks, loaded or cached, edited since they were generated or read from disk. Generated chunks
+/// that were never edited are not dirty, they are written when they unload if they were never saved.
+#[derive(Resource, Debug, Default)]
+pub struct DirtyChunks {
+    chunks: HashSet<ChunkPosition>,
+}
+
+impl DirtyChunks {
+    pub fn mark(&mut self, chunk: ChunkPosition) {
+        self.chunks.insert(chunk);
+    }
+
+    pub fn is_dirty(&self, chunk: &ChunkPosition) -> bool {
+        self.chunks.contains(chunk)
+    }
+
+    /// Forgets the chunk, returns whether it was dirty
+    pub fn take(&mut self, chunk: &ChunkPosition) -> bool {
+        self.chunks.remove(chunk)
+    }
+
+    /// Whether writing the chunk would change anything: it is dirty or it was never saved
+    pub fn needs_save(&self, chunk: &ChunkPosition, storage: &ChunkStorage) -> bool {
+        self.is_dirty(chunk) || !storage.is_saved(chunk)
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct AutosaveSettings {
+    pub enabled: bool,
+    /// Seconds between autosaves
+    pub interval: f32,
+}
+
+impl Default for AutosaveSettings {
+    fn default() -> Self {
+        Self { enabled: true, interval: 60.0 }
+    }
+}
+
+pub struct AutosavePlugin;
+
+impl Plugin for AutosavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DirtyChunks>()
+            .init_resource::<AutosaveSettings>()
+            .add_systems(Last, autosave.before(shutdown));
+    }
+}
+
+fn autosave(world: &mut World, mut since_save: Local<f32>) {
+    *since_save += world.resource::<Time>().delta_seconds();
+    let settings = world.resource::<AutosaveSettings>();
+    if !settings.enabled || *since_save < settings.interval {
+        return;
+    }
+    *since_save = 0.0;
+
+    let saved = save_dirty_chunks(world);
+    write_world_metadata(world);
+    write_pending_edits(world);
+    if saved > 0 {
+        info!("Autosaved {} chunks", saved);
+    }
+}
+
+/// Hands every dirty chunk, loaded or cached, over to the persistence backend without unloading
+/// it, returns how many were saved. The chunks are written in the background.
+pub fn save_dirty_chunks(world: &mut World) -> usize {
+    let dirty = std::mem::take(&mut world.resource_mut::<DirtyChunks>().chunks);
+    // Chunks received from a server are not ours to save
+    if *world.resource::<ChunkSource>() == ChunkSource::Remote {
+        return 0;
+    }
+
+    let chunk_data = world.resource::<ChunkData>();
+    let cache = world.resource::<ChunkCache>();
+    let chunks = dirty
+        .iter()
+        .filter_map(|position| match chunk_data.loaded.get(position) {
+            Some(entity) => world.get::<Chunk>(*entity),
+            None => cache.get(position),
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let saved = chunks.len();
+    let mut storage = world.resource_mut::<ChunkStorage>();
+    for chunk in chunks {
+        storage.save(chunk);
+    }
+    saved
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::engine::{
+        block_registry::BlockRegistry,
+        coords::{LocalVoxelPos, WorldVoxelPos},
+        generator::apply_pending_edits_to_loaded_chunks,
+        voxel::Block,
+        world_edits::{apply_world_edits, EditRejected, WorldEdits},
+    };
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn test_only_edited_chunks_are_autosaved() {
+        let root = TempDir::new("autosave");
+        let mut storage = ChunkStorage::open(root.path()).unwrap();
+        let (saved, generated, edited) = (ChunkPosition::new(0, 0, 0), ChunkPosition::new(1, 0, 0), ChunkPosition::new(2, 0, 0));
+        storage.save(Chunk::new(saved));
+        storage.save(Chunk::new(edited));
+
+        let mut world = World::new();
+        world.insert_resource(storage);
+        world.init_resource::<DirtyChunks>();
+        world.init_resource::<ChunkCache>();
+        world.init_resource::<ChunkSource>();
+        world.init_resource::<WorldEdits>();
+        world.init_resource::<BlockRegistry>();
+        world.init_resource::<Events<EditRejected>>();
+        let mut chunk_data = ChunkData::default();
+        for chunk in [saved, generated, edited] {
+            chunk_data.loaded.insert(chunk, world.spawn(Chunk::new(chunk)).id());
+        }
+        // An overflowing structure reaches the chunk in the frame it is read from disk
+        chunk_data.pending_edits.push(WorldVoxelPos::from_local(&saved, LocalVoxelPos::new(4, 4, 4)), Block::Dirt.into());
+        world.insert_resource(chunk_data);
+
+        world.run_system_once(apply_pending_edits_to_loaded_chunks);
+        let dirty = world.resource::<DirtyChunks>();
+        assert!(dirty.is_dirty(&saved) && !dirty.is_dirty(&generated) && !dirty.is_dirty(&edited));
+        assert!(dirty.needs_save(&generated, world.resource::<ChunkStorage>()));
+        assert_eq!(save_dirty_chunks(&mut world), 1);
+
+        world.resource_mut::<WorldEdits>().set(WorldVoxelPos::from_local(&edited, LocalVoxelPos::new(1, 2, 3)), Block::Stone.into());
+        world.run_system_once(apply_world_edits);
+        let dirty = world.resource::<DirtyChunks>();
+        assert_eq!(dirty.len(), 1);
+        assert!(dirty.is_dirty(&edited));
+        assert_eq!(save_dirty_chunks(&mut world), 1);
+        assert!(world.resource::<DirtyChunks>().is_empty());
+
+        world.resource_mut::<ChunkStorage>().shutdown();
+        let mut storage = world.resource_mut::<ChunkStorage>();
+        assert_eq!(storage.load_now(saved).unwrap().unwrap().get(LocalVoxelPos::new(4, 4, 4)), Block::Dirt.into());
+        assert_eq!(storage.load_now(edited).unwrap().unwrap().get(LocalVoxelPos::new(1, 2, 3)), Block::Stone.into());
+    }
+}
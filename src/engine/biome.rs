@@ -0,0 +1,118 @@
+//! Biomes split the world into regions that look different, picked from a temperature noise over
+//! the world columns. A chunk is meshed with the tint of its dominant biome, blocks marked
+//! [`biome_tinted`](super::block_registry::BlockDefinition::biome_tinted) like grass take it as
+//! their vertex color unless the voxel has a tint of its own.
+
+use noise::{NoiseFn, Perlin};
+
+use super::{
+    chunk::{ChunkPosition, CHUNK_SIZE},
+    tint::Tint,
+};
+
+/// Voxels per unit of the temperature noise, biomes are a few hundred voxels across
+const TEMPERATURE_SCALE: f64 = 384.0;
+/// Added to the world seed so biomes do not follow the terrain noise
+const TEMPERATURE_SALT: u64 = 0xb10e;
+/// Columns sampled along each axis of a chunk when looking for its dominant biome
+const SAMPLES: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Biome {
+    #[default]
+    Plains,
+    Desert,
+    Snow,
+}
+
+impl Biome {
+    pub const ALL: [Biome; 3] = [Biome::Plains, Biome::Desert, Biome::Snow];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Plains => "plains",
+            Self::Desert => "desert",
+            Self::Snow => "snow",
+        }
+    }
+
+    /// sRGB color of grass and other biome tinted blocks
+    pub fn tint(&self) -> Tint {
+        match self {
+            Self::Plains => [145, 189, 89],
+            Self::Desert => [191, 183, 85],
+            Self::Snow => [200, 220, 215],
+        }
+    }
+
+    /// Biome of the world column at `x`, `z`, see [`BiomeMap`] for many columns
+    pub fn at(seed: u64, x: i64, z: i64) -> Self {
+        BiomeMap::new(seed).biome_at(x, z)
+    }
+
+    /// The biome most of the columns of a chunk are in, ties go to the first in [`Biome::ALL`]
+    pub fn dominant(seed: u64, chunk: &ChunkPosition) -> Self {
+        let map = BiomeMap::new(seed);
+        let step = CHUNK_SIZE / SAMPLES;
+        let (origin_x, origin_z) = (chunk.x as i64 * CHUNK_SIZE as i64, chunk.z as i64 * CHUNK_SIZE as i64);
+        let mut counts = [0; Biome::ALL.len()];
+        for sample_x in 0..SAMPLES {
+            for sample_z in 0..SAMPLES {
+                let x = origin_x + (sample_x * step + step / 2) as i64;
+                let z = origin_z + (sample_z * step + step / 2) as i64;
+                counts[map.biome_at(x, z) as usize] += 1;
+            }
+        }
+        let most = counts.iter().copied().max().unwrap_or(0);
+        Self::ALL[counts.iter().position(|count| *count == most).unwrap_or(0)]
+    }
+
+    fn from_temperature(temperature: f64) -> Self {
+        match temperature {
+            t if t < -0.25 => Self::Snow,
+            t if t > 0.25 => Self::Desert,
+            _ => Self::Plains,
+        }
+    }
+}
+
+/// Biomes of a world, built once for looking up many columns
+pub struct BiomeMap {
+    temperature: Perlin,
+}
+
+impl BiomeMap {
+    pub fn new(seed: u64) -> Self {
+        Self { temperature: Perlin::new(seed.wrapping_add(TEMPERATURE_SALT) as u32) }
+    }
+
+    pub fn biome_at(&self, x: i64, z: i64) -> Biome {
+        Biome::from_temperature(self.temperature(x, z))
+    }
+
+    /// Roughly -1 (cold) to 1 (hot)
+    fn temperature(&self, x: i64, z: i64) -> f64 {
+        self.temperature.get([x as f64 / TEMPERATURE_SCALE, z as f64 / TEMPERATURE_SCALE]) * 1.5
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_biome_is_dominant_somewhere() {
+        let mut found = Vec::new();
+        for x in -64..64 {
+            for z in -64..64 {
+                let biome = Biome::dominant(7, &ChunkPosition::new(x, 0, z));
+                if !found.contains(&biome) {
+                    found.push(biome);
+                }
+            }
+        }
+        assert_eq!(found.len(), Biome::ALL.len(), "found only {:?}", found);
+        // Only columns decide, not the height of the chunk
+        assert_eq!(Biome::dominant(7, &ChunkPosition::new(3, -5, 9)), Biome::dominant(7, &ChunkPosition::new(3, 12, 9)));
+    }
+}
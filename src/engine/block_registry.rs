@@ -0,0 +1,410 @@
+//! Data-driven block definitions.
+//!
+//! Every block kind a voxel can be made of has a [`BlockId`] pointing into the [`BlockRegistry`].
+//! The registry starts with the builtin [`Block`]s, which always keep the same ids so generators
+//! can name them directly, and is extended or overridden by [`BLOCKS_FILE`]:
+//!
+//! ```text
+//! [
+//!     (name: "glass", opaque: false, hardness: 0.3),
+//!     (name: "lamp", textures: Sides(top: 12, bottom: 13, side: 14), emission: 15, color: (250, 220, 140)),
+//! ]
+//! ```
+//!
+//! Definitions with the name of a builtin block replace it, any other name adds a new block after
+//! the builtin ones. The registry is a resource, tasks meshing or generating chunks on other
+//! threads are handed a clone of it.
+//!
+//! Ids of blocks that are not builtin follow the order of the blocks file, which can change
+//! between runs. Every world stores the names of its blocks by id in its [`PALETTE_FILE`], the
+//! world is played with [`BlockRegistry::for_world`] so its saved voxels keep their blocks.
+
+use std::{
+    fs,
+    io,
+    path::Path,
+    sync::{Arc, OnceLock},
+};
+
+use bevy::{prelude::*, utils::{HashMap, HashSet}};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    util::Face,
+    voxel::{Block, BlockId, Voxel},
+};
+
+/// Block definitions loaded at startup, relative to the working directory
+pub const BLOCKS_FILE: &str = "assets/blocks.ron";
+/// Names of the blocks of a world by id, stored in the world directory
+pub const PALETTE_FILE: &str = "blocks.ron";
+
+/// Texture atlas tiles of the faces of a block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlockTextures {
+    All(u16),
+    Sides { top: u16, bottom: u16, side: u16 },
+    /// In face order: left, right, bottom, top, back, front
+    Faces([u16; 6]),
+}
+
+impl Default for BlockTextures {
+    fn default() -> Self {
+        Self::All(0)
+    }
+}
+
+impl BlockTextures {
+    pub fn tile(&self, face: Face) -> u16 {
+        match self {
+            Self::All(tile) => *tile,
+            Self::Sides { top, bottom, side } => match face {
+                Face::Top => *top,
+                Face::Bottom => *bottom,
+                _ => *side,
+            },
+            Self::Faces(tiles) => tiles[face.as_face_number()],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockDefinition {
+    pub name: String,
+    /// Opaque blocks hide the faces of their neighbours and block the visibility search
+    #[serde(default = "default_opaque")]
+    pub opaque: bool,
+    #[serde(default)]
+    pub textures: BlockTextures,
+    /// Seconds it takes to break the block by hand
+    #[serde(default = "default_hardness")]
+    pub hardness: f32,
+    /// Unbreakable blocks can not be removed or replaced by editing
+    #[serde(default)]
+    pub unbreakable: bool,
+    /// Light level the block emits, 0 to 15
+    #[serde(default)]
+    pub emission: u8,
+    /// Representative sRGB color, used when converting colored voxel models
+    #[serde(default = "default_color")]
+    pub color: [u8; 3],
+    /// Name of a [`VoxelShape`](super::shapes::VoxelShape), full cube if `None`
+    #[serde(default)]
+    pub shape: Option<String>,
+    /// Untinted voxels of the block are colored by the biome they are in, like grass
+    #[serde(default)]
+    pub biome_tinted: bool,
+    /// Drawn blended over what is behind it, in a mesh of its own. Should not be opaque, shapes are ignored
+    #[serde(default)]
+    pub translucent: bool,
+}
+
+fn default_opaque() -> bool {
+    true
+}
+
+fn default_hardness() -> f32 {
+    1.0
+}
+
+fn default_color() -> [u8; 3] {
+    [128, 128, 128]
+}
+
+impl BlockDefinition {
+    /// Definition of a builtin block when the blocks file does not override it
+    pub fn builtin(block: Block) -> Self {
+        let (textures, hardness, color) = match block {
+            Block::Bedrock => (BlockTextures::All(0), 0.0, [40, 40, 40]),
+            Block::Stone => (BlockTextures::All(1), 1.5, [128, 128, 128]),
+            Block::Dirt => (BlockTextures::All(2), 0.5, [134, 96, 67]),
+            Block::Grass => (BlockTextures::Sides { top: 3, bottom: 2, side: 4 }, 0.6, [95, 159, 53]),
+            Block::Sand => (BlockTextures::All(5), 0.5, [219, 207, 163]),
+            Block::Gravel => (BlockTextures::All(6), 0.6, [136, 126, 126]),
+            Block::Snow => (BlockTextures::All(7), 0.2, [240, 240, 245]),
+            Block::Glass => (BlockTextures::All(8), 0.3, [200, 230, 240]),
+            Block::Water => (BlockTextures::All(10), 0.0, [52, 96, 196]),
+        };
+        Self {
+            name: block.name().to_string(),
+            opaque: !matches!(block, Block::Glass | Block::Water),
+            textures,
+            hardness,
+            unbreakable: matches!(block, Block::Bedrock),
+            emission: 0,
+            color,
+            shape: None,
+            biome_tinted: matches!(block, Block::Grass),
+            translucent: matches!(block, Block::Water),
+        }
+    }
+
+    /// Stands in for a block a world uses that is no longer defined, so its voxels keep their id
+    pub fn missing(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            opaque: true,
+            textures: BlockTextures::default(),
+            hardness: default_hardness(),
+            unbreakable: false,
+            emission: 0,
+            color: [255, 0, 255],
+            shape: None,
+            biome_tinted: false,
+            translucent: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BlockRegistryError {
+    Io(io::Error),
+    Parse(ron::error::SpannedError),
+    /// Two definitions in the file share a name
+    DuplicateName(String),
+    InvalidEmission { name: String, emission: u8 },
+    /// Ids are stored as `u16` and 0 is the empty voxel
+    TooManyBlocks,
+}
+
+impl std::fmt::Display for BlockRegistryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "failed to read block definitions: {}", err),
+            Self::Parse(err) => write!(f, "invalid block definitions: {}", err),
+            Self::DuplicateName(name) => write!(f, "block `{}` is defined twice", name),
+            Self::InvalidEmission { name, emission } => write!(f, "block `{}` emits light level {}, at most 15 is allowed", name, emission),
+            Self::TooManyBlocks => write!(f, "too many block definitions"),
+        }
+    }
+}
+
+impl std::error::Error for BlockRegistryError {}
+
+/// Every known block, indexed by [`BlockId`]. Cheap to clone
+#[derive(Resource, Debug, Clone)]
+pub struct BlockRegistry {
+    definitions: Arc<Vec<BlockDefinition>>,
+    ids: Arc<HashMap<String, BlockId>>,
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl BlockRegistry {
+    /// Only the builtin blocks, in the order of [`Block::ALL`]
+    pub fn builtin() -> Self {
+        static BUILTIN: OnceLock<BlockRegistry> = OnceLock::new();
+        BUILTIN
+            .get_or_init(|| Self::from_definitions(Block::ALL.into_iter().map(BlockDefinition::builtin).collect()))
+            .clone()
+    }
+
+    fn from_definitions(definitions: Vec<BlockDefinition>) -> Self {
+        // Placeholders of a palette can repeat a name, the first definition keeps it
+        let ids = definitions.iter().enumerate().rev().map(|(index, definition)| (definition.name.trim().to_lowercase(), BlockId::from_index(index))).collect();
+        Self { definitions: Arc::new(definitions), ids: Arc::new(ids) }
+    }
+
+    /// The builtin blocks extended and overridden by the definitions in `ron`
+    pub fn from_ron(ron: &str) -> Result<Self, BlockRegistryError> {
+        let overrides = ron::from_str::<Vec<BlockDefinition>>(ron).map_err(BlockRegistryError::Parse)?;
+
+        let builtin = Self::builtin();
+        let mut definitions = builtin.definitions.as_ref().clone();
+        let mut defined = Vec::new();
+        for definition in overrides {
+            let name = definition.name.trim().to_lowercase();
+            if defined.contains(&name) {
+                return Err(BlockRegistryError::DuplicateName(definition.name));
+            }
+            if definition.emission > 15 {
+                return Err(BlockRegistryError::InvalidEmission { name: definition.name, emission: definition.emission });
+            }
+            match builtin.id(&name) {
+                Some(id) => definitions[id.index()] = definition,
+                None => definitions.push(definition),
+            }
+            defined.push(name);
+        }
+        if definitions.len() >= u16::MAX as usize {
+            return Err(BlockRegistryError::TooManyBlocks);
+        }
+        Ok(Self::from_definitions(definitions))
+    }
+
+    /// Reads the definitions in `path`, only the builtin blocks exist if the file does not
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, BlockRegistryError> {
+        match std::fs::read_to_string(path) {
+            Ok(ron) => Self::from_ron(&ron),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::builtin()),
+            Err(err) => Err(BlockRegistryError::Io(err)),
+        }
+    }
+
+    /// The same blocks with the ids a world saved them with: every block named in `palette` gets
+    /// the id of its place in it, the other blocks are added after them. Names that are not
+    /// defined anymore keep their id with a [`BlockDefinition::missing`] placeholder. The builtin
+    /// blocks always keep their ids.
+    pub fn with_palette(&self, palette: &[String]) -> Self {
+        let builtin = Block::ALL.len();
+        let custom = |name: &str| self.id(name).filter(|id| id.index() >= builtin);
+        let mut definitions = self.definitions[..builtin].to_vec();
+        for name in palette.iter().skip(builtin) {
+            match custom(name) {
+                Some(id) if !definitions.iter().any(|definition| definition.name.trim().eq_ignore_ascii_case(name.trim())) => {
+                    definitions.push(self.definitions[id.index()].clone());
+                }
+                _ => definitions.push(BlockDefinition::missing(name)),
+            }
+        }
+        let palette = palette.iter().map(|name| name.trim().to_lowercase()).collect::<HashSet<_>>();
+        definitions.extend(self.definitions[builtin..].iter().filter(|definition| !palette.contains(&definition.name.trim().to_lowercase())).cloned());
+        Self::from_definitions(definitions)
+    }
+
+    /// Names of the blocks by id, see [`BlockRegistry::with_palette`]
+    pub fn palette(&self) -> Vec<String> {
+        self.definitions.iter().map(|definition| definition.name.trim().to_lowercase()).collect()
+    }
+
+    /// This registry with the ids of the world stored in `root`. The palette of the world is
+    /// written when blocks were added to it, worlds saved without one keep the ids of this registry.
+    pub fn for_world(&self, root: &Path) -> io::Result<Self> {
+        let path = root.join(PALETTE_FILE);
+        let saved = match fs::read_to_string(&path) {
+            Ok(ron) => ron::from_str::<Vec<String>>(&ron).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        let blocks = self.with_palette(&saved);
+        let palette = blocks.palette();
+        if palette != saved {
+            let text = ron::ser::to_string_pretty(&palette, ron::ser::PrettyConfig::default())
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            let tmp = path.with_extension("ron.tmp");
+            fs::write(&tmp, text)?;
+            fs::rename(tmp, path)?;
+        }
+        Ok(blocks)
+    }
+
+    pub fn len(&self) -> usize {
+        self.definitions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.definitions.is_empty()
+    }
+
+    pub fn get(&self, id: BlockId) -> Option<&BlockDefinition> {
+        self.definitions.get(id.index())
+    }
+
+    /// Looks a block up by its name, ignoring case and surrounding whitespace
+    pub fn id(&self, name: &str) -> Option<BlockId> {
+        self.ids.get(&name.trim().to_lowercase()).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BlockId, &BlockDefinition)> {
+        self.definitions.iter().enumerate().map(|(index, definition)| (BlockId::from_index(index), definition))
+    }
+
+    /// Empty voxels, unknown blocks and blocks that are not full cubes are not opaque
+    pub fn is_opaque(&self, voxel: Voxel) -> bool {
+        voxel.block().and_then(|id| self.get(id)).map_or(false, |definition| definition.opaque && definition.shape.is_none())
+    }
+
+    pub fn is_translucent(&self, voxel: Voxel) -> bool {
+        voxel.block().and_then(|id| self.get(id)).map_or(false, |definition| definition.translucent)
+    }
+
+    pub fn is_biome_tinted(&self, voxel: Voxel) -> bool {
+        voxel.block().and_then(|id| self.get(id)).map_or(false, |definition| definition.biome_tinted)
+    }
+
+    pub fn is_unbreakable(&self, voxel: Voxel) -> bool {
+        voxel.block().and_then(|id| self.get(id)).map_or(false, |definition| definition.unbreakable)
+    }
+
+    /// Block name for display, `"empty"` for empty voxels
+    pub fn name(&self, voxel: Voxel) -> &str {
+        match voxel.block() {
+            None => "empty",
+            Some(id) => self.get(id).map_or("unknown", |definition| definition.name.as_str()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn test_builtin_blocks_keep_their_ids() {
+        let registry = BlockRegistry::builtin();
+        for block in Block::ALL {
+            assert_eq!(registry.id(block.name()), Some(block.id()));
+            assert_eq!(registry.get(block.id()).unwrap().name, block.name());
+        }
+        assert!(!registry.is_opaque(Voxel::from(Block::Glass)));
+        assert!(registry.is_opaque(Voxel::from(Block::Stone)));
+        assert!(registry.is_unbreakable(Voxel::from(Block::Bedrock)));
+        assert!(!registry.is_opaque(Voxel::Empty));
+    }
+
+    #[test]
+    fn test_shipped_blocks_file_matches_builtin() {
+        let registry = BlockRegistry::load(BLOCKS_FILE).unwrap();
+        let builtin = BlockRegistry::builtin();
+        assert!(registry.iter().zip(builtin.iter()).all(|(a, b)| a == b));
+    }
+
+    #[test]
+    fn test_blocks_file_overrides_and_extends() {
+        let registry = BlockRegistry::from_ron(
+            r#"[
+                (name: "Glass", opaque: true),
+                (name: "lamp", textures: Sides(top: 12, bottom: 13, side: 14), emission: 15),
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(registry.len(), Block::ALL.len() + 1);
+        assert!(registry.is_opaque(Voxel::from(Block::Glass)));
+
+        let lamp = registry.id(" LAMP ").unwrap();
+        assert_eq!(lamp, BlockId::from_index(Block::ALL.len()));
+        let definition = registry.get(lamp).unwrap();
+        assert_eq!(definition.emission, 15);
+        assert_eq!(definition.textures.tile(Face::Top), 12);
+        assert_eq!(definition.textures.tile(Face::Left), 14);
+        assert!(definition.opaque);
+
+        assert!(matches!(BlockRegistry::from_ron(r#"[(name: "a"), (name: "A")]"#), Err(BlockRegistryError::DuplicateName(_))));
+        assert!(matches!(BlockRegistry::from_ron(r#"[(name: "sun", emission: 16)]"#), Err(BlockRegistryError::InvalidEmission { .. })));
+        assert!(matches!(BlockRegistry::from_ron("[(opaque: false)]"), Err(BlockRegistryError::Parse(_))));
+    }
+
+    #[test]
+    fn test_worlds_keep_their_block_ids() {
+        let played = BlockRegistry::from_ron(r#"[(name: "lamp"), (name: "rope")]"#).unwrap();
+        let root = TempDir::new("block-palette");
+        let rope = played.for_world(&root).unwrap().id("rope").unwrap();
+
+        // The blocks file was reordered, lost the lamp and gained a bell
+        let edited = BlockRegistry::from_ron(r#"[(name: "rope"), (name: "bell")]"#).unwrap();
+        assert_ne!(edited.id("rope"), Some(rope));
+        let blocks = edited.for_world(&root).unwrap();
+        assert_eq!(blocks.id("rope"), Some(rope));
+        assert_eq!(blocks.id("stone"), Some(Block::Stone.id()));
+        let lamp = blocks.get(BlockId::from_index(Block::ALL.len())).unwrap();
+        assert_eq!(lamp, &BlockDefinition::missing("lamp"));
+        assert_eq!(blocks.id("bell"), Some(BlockId::from_index(Block::ALL.len() + 2)));
+        // The bell keeps its new id from now on
+        assert_eq!(edited.for_world(&root).unwrap().palette(), blocks.palette());
+    }
+}
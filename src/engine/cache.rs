@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+
+use bevy::{prelude::*, utils::HashMap};
+
+use super::{chunk::{Chunk, ChunkPosition, CHUNK_SIZE}, voxel::Voxel};
+
+/// In-memory LRU cache of recently unloaded chunks.
+/// Chunks despawned by the garbage collector end up in here, so turning around
+/// quickly restores them instead of generating them again.
+#[derive(Resource)]
+pub struct ChunkCache {
+    capacity_bytes: usize,
+    entries: HashMap<ChunkPosition, (Chunk, u64)>,
+    /// Chunk positions ordered from least to most recently used
+    order: BTreeMap<u64, ChunkPosition>,
+    tick: u64,
+}
+
+impl Default for ChunkCache {
+    fn default() -> Self {
+        Self::with_capacity_mb(64)
+    }
+}
+
+impl ChunkCache {
+    pub fn with_capacity_mb(capacity_mb: usize) -> Self {
+        Self {
+            capacity_bytes: capacity_mb * 1024 * 1024,
+            entries: HashMap::default(),
+            order: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    /// Approximate memory used by a single cached chunk
+    pub fn chunk_size_bytes() -> usize {
+        CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE * std::mem::size_of::<Voxel>() + std::mem::size_of::<Chunk>()
+    }
+
+    pub fn capacity_mb(&self) -> usize {
+        self.capacity_bytes / 1024 / 1024
+    }
+
+    /// Changes the capacity, returning chunks that no longer fit
+    pub fn set_capacity_mb(&mut self, capacity_mb: usize) -> Vec<Chunk> {
+        self.capacity_bytes = capacity_mb * 1024 * 1024;
+        self.evict_over_capacity()
+    }
+
+    pub fn used_bytes(&self) -> usize {
+        self.entries.len() * Self::chunk_size_bytes()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, chunk: &ChunkPosition) -> bool {
+        self.entries.contains_key(chunk)
+    }
+
+    /// Stores a chunk in the cache, returning the least recently used chunks evicted to make room for it
+    pub fn insert(&mut self, chunk: Chunk) -> Vec<Chunk> {
+        let position = chunk.position;
+        self.tick += 1;
+        if let Some((_, old_tick)) = self.entries.insert(position, (chunk, self.tick)) {
+            self.order.remove(&old_tick);
+        }
+        self.order.insert(self.tick, position);
+        self.evict_over_capacity()
+    }
+
+    /// Looks at a cached chunk without counting it as used
+    pub fn get(&self, chunk: &ChunkPosition) -> Option<&Chunk> {
+        self.entries.get(chunk).map(|(chunk, _)| chunk)
+    }
+
+    /// Removes a chunk from the cache so it can be loaded back into the world
+    pub fn take(&mut self, chunk: &ChunkPosition) -> Option<Chunk> {
+        let (chunk, tick) = self.entries.remove(chunk)?;
+        self.order.remove(&tick);
+        Some(chunk)
+    }
+
+    /// Removes every cached chunk
+    pub fn drain(&mut self) -> Vec<Chunk> {
+        self.order.clear();
+        self.entries.drain().map(|(_, (chunk, _))| chunk).collect()
+    }
+
+    fn evict_over_capacity(&mut self) -> Vec<Chunk> {
+        let mut evicted = Vec::new();
+        while self.used_bytes() > self.capacity_bytes {
+            let Some((_, position)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some((chunk, _)) = self.entries.remove(&position) {
+                evicted.push(chunk);
+            }
+        }
+        evicted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{coords::LocalVoxelPos, voxel::Block};
+
+    /// Cache holding exactly `chunks` chunks
+    fn cache_fitting(chunks: usize) -> (ChunkCache, usize) {
+        let cache = ChunkCache::with_capacity_mb(1);
+        let fits = cache.capacity_bytes / ChunkCache::chunk_size_bytes();
+        assert!(fits >= chunks, "a megabyte holds only {} chunks", fits);
+        (cache, fits)
+    }
+
+    fn positions(chunks: &[Chunk]) -> Vec<ChunkPosition> {
+        chunks.iter().map(|chunk| chunk.position).collect()
+    }
+
+    #[test]
+    fn test_least_recently_used_chunk_is_evicted() {
+        let (mut cache, fits) = cache_fitting(3);
+        for x in 0..fits as i32 {
+            assert!(cache.insert(Chunk::new(ChunkPosition::new(x, 0, 0))).is_empty());
+        }
+        assert_eq!(cache.len(), fits);
+        assert!(cache.used_bytes() <= cache.capacity_bytes);
+
+        // Inserting again counts as a use, looking at a chunk does not
+        assert!(cache.insert(Chunk::new(ChunkPosition::new(0, 0, 0))).is_empty());
+        assert!(cache.get(&ChunkPosition::new(1, 0, 0)).is_some());
+        let evicted = cache.insert(Chunk::new(ChunkPosition::new(-1, 0, 0)));
+        assert_eq!(positions(&evicted), vec![ChunkPosition::new(1, 0, 0)]);
+        let evicted = cache.insert(Chunk::new(ChunkPosition::new(-2, 0, 0)));
+        assert_eq!(positions(&evicted), vec![ChunkPosition::new(2, 0, 0)]);
+        assert!(cache.contains(&ChunkPosition::new(0, 0, 0)));
+        assert_eq!(cache.len(), fits);
+    }
+
+    #[test]
+    fn test_shrinking_evicts_oldest_first() {
+        let (mut cache, _) = cache_fitting(3);
+        for x in 0..3 {
+            cache.insert(Chunk::new(ChunkPosition::new(x, 0, 0)));
+        }
+        assert_eq!(cache.capacity_mb(), 1);
+
+        let evicted = cache.set_capacity_mb(0);
+        assert_eq!(positions(&evicted), (0..3).map(|x| ChunkPosition::new(x, 0, 0)).collect::<Vec<_>>());
+        assert!(cache.is_empty());
+        assert_eq!(cache.used_bytes(), 0);
+        // Nothing fits anymore, a new chunk goes straight back out
+        assert_eq!(positions(&cache.insert(Chunk::new(ChunkPosition::new(5, 0, 0)))), vec![ChunkPosition::new(5, 0, 0)]);
+    }
+
+    #[test]
+    fn test_taken_chunk_leaves_the_cache() {
+        let (mut cache, fits) = cache_fitting(2);
+        let mut chunk = Chunk::new(ChunkPosition::new(0, 0, 0));
+        chunk.set(LocalVoxelPos::new(1, 2, 3), Voxel::from(Block::Stone));
+        cache.insert(chunk);
+        cache.insert(Chunk::new(ChunkPosition::new(1, 0, 0)));
+
+        let taken = cache.take(&ChunkPosition::new(0, 0, 0)).unwrap();
+        assert_eq!(taken.get(LocalVoxelPos::new(1, 2, 3)), Voxel::from(Block::Stone));
+        assert!(cache.take(&ChunkPosition::new(0, 0, 0)).is_none());
+        assert!(!cache.contains(&ChunkPosition::new(0, 0, 0)));
+        assert_eq!(cache.len(), 1);
+
+        // The taken chunk no longer holds a place in the eviction order
+        for x in 2..=fits as i32 {
+            assert!(cache.insert(Chunk::new(ChunkPosition::new(x, 0, 0))).is_empty());
+        }
+        let evicted = cache.insert(Chunk::new(ChunkPosition::new(-1, 0, 0)));
+        assert_eq!(positions(&evicted), vec![ChunkPosition::new(1, 0, 0)]);
+    }
+}
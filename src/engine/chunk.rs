@@ -1,60 +1,77 @@
-use std::sync::{RwLock, Arc, RwLockReadGuard, RwLockWriteGuard};
+use std::{borrow::Cow, sync::Arc};
 
-use bevy::{prelude::{Vec3, Component, Mesh}, render::{mesh::VertexAttributeValues, primitives::Aabb}};
-use block_mesh::{ndshape::ConstShape, GreedyQuadsBuffer, greedy_quads, RIGHT_HANDED_Y_UP_CONFIG};
+use bevy::{prelude::{Vec3, Component, Mesh, Transform, Reflect, ReflectComponent}, render::primitives::Aabb, utils::HashMap};
 
-use super::{voxel::Voxel, util::Face};
+use super::{biome::Biome, block_registry::BlockRegistry, meshing::{mesh_chunk, ChunkBorders, ChunkMesher, ChunkMeshes, MeshData, MeshLayer, MeshOptions}, tint::{ChunkTints, Tint}, voxel::{Voxel, VoxelMetadata}, util::Face, coords::{self, LocalVoxelPos}};
 
 pub const CHUNK_SIZE: usize = 16;
-pub type ChunkVoxels = Vec<Voxel>;
+const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+/// Metadata of the voxels that have any, keyed by their index in buffer order
+pub type ChunkMetadata = HashMap<u16, VoxelMetadata>;
+
+/// Bits per axis in a Morton code, positions within ±2^20 chunks on every axis round trip
+const MORTON_BITS: u32 = 21;
+const MORTON_BIAS: i32 = 1 << (MORTON_BITS - 1);
+const MORTON_MASK: u64 = (1 << MORTON_BITS) - 1;
+
+/// Moves the lowest 21 bits of `value` to every third bit
+fn spread_bits(value: u64) -> u64 {
+    let mut x = value & MORTON_MASK;
+    x = (x | x << 32) & 0x001f_0000_0000_ffff;
+    x = (x | x << 16) & 0x001f_0000_ff00_00ff;
+    x = (x | x << 8) & 0x100f_00f0_0f00_f00f;
+    x = (x | x << 4) & 0x10c3_0c30_c30c_30c3;
+    x = (x | x << 2) & 0x1249_2492_4924_9249;
+    x
+}
 
-/// The shape of a chunk with padding of 1 on each side
-type ChunkNDShapePadded = block_mesh::ndshape::ConstShape3u32<{ CHUNK_SIZE as u32 + 2 }, { CHUNK_SIZE as u32 + 2 }, { CHUNK_SIZE as u32 + 2 }>;
+/// Inverse of [`spread_bits`]
+fn compact_bits(value: u64) -> u64 {
+    let mut x = value & 0x1249_2492_4924_9249;
+    x = (x | x >> 2) & 0x10c3_0c30_c30c_30c3;
+    x = (x | x >> 4) & 0x100f_00f0_0f00_f00f;
+    x = (x | x >> 8) & 0x001f_0000_ff00_00ff;
+    x = (x | x >> 16) & 0x001f_0000_0000_ffff;
+    x = (x | x >> 32) & MORTON_MASK;
+    x
+}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
 pub struct ChunkPosition {
     pub x: i32,
     pub y: i32,
     pub z: i32,
 }
 
+/// Hashes the Morton code, chunks close to each other get close hashes
+impl std::hash::Hash for ChunkPosition {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        state.write_u64(self.to_morton());
+    }
+}
+
 impl ChunkPosition {
     pub fn new(x: i32, y: i32, z: i32) -> Self {
         Self { x, y, z }
     }
 
     pub fn from_world_position(pos: Vec3) -> Self {
-        Self {
-            x: pos.x as i32 / CHUNK_SIZE as i32,
-            y: pos.y as i32 / CHUNK_SIZE as i32,
-            z: pos.z as i32 / CHUNK_SIZE as i32,
-        }
+        let (x, y, z) = coords::world_to_chunk(pos);
+        Self { x, y, z }
     }
 
     pub fn as_world_position(&self) -> Vec3 {
-        Vec3::new(
-            self.x as f32 * CHUNK_SIZE as f32,
-            self.y as f32 * CHUNK_SIZE as f32,
-            self.z as f32 * CHUNK_SIZE as f32,
-        )
+        coords::chunk_to_world(self.x, self.y, self.z)
     }
 
     /// Converts a position relative to the chunk to a position in the world.
     pub fn inner_to_world_position(&self, pos: Vec3) -> Vec3 {
-        Vec3::new(
-            self.x as f32 * CHUNK_SIZE as f32 + pos.x,
-            self.y as f32 * CHUNK_SIZE as f32 + pos.y,
-            self.z as f32 * CHUNK_SIZE as f32 + pos.z,
-        )
+        coords::local_to_world((self.x, self.y, self.z), pos)
     }
 
     /// Converts a position in the world to a position relative to the chunk.
     pub fn world_to_inner_position(&self, pos: Vec3) -> Vec3 {
-        Vec3::new(
-            pos.x - self.x as f32 * CHUNK_SIZE as f32,
-            pos.y - self.y as f32 * CHUNK_SIZE as f32,
-            pos.z - self.z as f32 * CHUNK_SIZE as f32,
-        )
+        coords::world_to_local((self.x, self.y, self.z), pos)
     }
 
     pub fn neighbors(&self) -> [(ChunkPosition, Face); 6] {
@@ -75,18 +92,147 @@ impl ChunkPosition {
         (dx * dx + dy * dy + dz * dz).sqrt()
     }
 
+    /// Squared [`Self::distance_to`] without the square root, for comparing distances
+    pub fn distance_squared_to(&self, other: &ChunkPosition) -> i64 {
+        let dx = (self.x - other.x) as i64;
+        let dy = (self.y - other.y) as i64;
+        let dz = (self.z - other.z) as i64;
+        dx * dx + dy * dy + dz * dz
+    }
+
+    /// Z-order code interleaving the bits of all three axes. Positions further than 2^20 chunks
+    /// from the origin wrap around, they still hash fine but [`Self::from_morton`] won't restore them.
+    pub fn to_morton(&self) -> u64 {
+        let biased = |value: i32| value.wrapping_add(MORTON_BIAS) as u32 as u64;
+        spread_bits(biased(self.x)) | spread_bits(biased(self.y)) << 1 | spread_bits(biased(self.z)) << 2
+    }
+
+    pub fn from_morton(code: u64) -> Self {
+        let unbiased = |value: u64| value as i32 - MORTON_BIAS;
+        Self::new(unbiased(compact_bits(code)), unbiased(compact_bits(code >> 1)), unbiased(compact_bits(code >> 2)))
+    }
+
+    /// Every chunk at most `radius` chunks from `center` on every axis, ordered by x, then y, then z
+    pub fn iter_cube(center: ChunkPosition, radius: u32) -> impl Iterator<Item = ChunkPosition> {
+        let radius = radius as i32;
+        (-radius..=radius).flat_map(move |x| {
+            (-radius..=radius).flat_map(move |y| (-radius..=radius).map(move |z| ChunkPosition::new(center.x + x, center.y + y, center.z + z)))
+        })
+    }
+
+    /// The chunks on the surface of the [`Self::iter_cube`] cube, exactly `radius` chunks from `center` on some axis
+    pub fn iter_shell(center: ChunkPosition, radius: u32) -> impl Iterator<Item = ChunkPosition> {
+        let radius = radius as i32;
+        (-radius..=radius).flat_map(move |x| {
+            (-radius..=radius).flat_map(move |y| {
+                // Inside the cube only the two z ends are on the surface
+                let step = if x.abs() == radius || y.abs() == radius { 1 } else { (2 * radius).max(1) as usize };
+                (-radius..=radius).step_by(step).map(move |z| ChunkPosition::new(center.x + x, center.y + y, center.z + z))
+            })
+        })
+    }
+
     pub fn aabb(&self) -> Aabb {
         Aabb::from_min_max(
             self.as_world_position(),
             self.as_world_position() + Vec3::new(CHUNK_SIZE as f32, CHUNK_SIZE as f32, CHUNK_SIZE as f32),
         )
     }
+
+    /// Transform of the entity drawing the chunk mesh, mesh positions are relative to the minimum corner of the chunk
+    pub fn mesh_transform(&self) -> Transform {
+        Transform::from_translation(self.as_world_position())
+    }
+
+    /// Bounds of every chunk mesh in mesh space, the whole chunk whatever the mesh covers.
+    /// Bevy only computes bounds for meshes that have none yet, so bounds taken from the first
+    /// mesh would cull voxels added to the chunk later
+    pub fn mesh_aabb() -> Aabb {
+        Aabb::from_min_max(Vec3::ZERO, Vec3::splat(CHUNK_SIZE as f32))
+    }
+}
+
+/// Voxels of a chunk in buffer order. Chunks where every voxel is the same, like the air above the
+/// terrain, only keep that voxel until something else is written into them.
+#[derive(Debug, Clone)]
+pub enum ChunkVoxels {
+    Uniform(Voxel),
+    Mixed(Vec<Voxel>),
+}
+
+impl ChunkVoxels {
+    pub fn get(&self, index: usize) -> &Voxel {
+        match self {
+            Self::Uniform(voxel) => {
+                debug_assert!(index < CHUNK_VOLUME, "voxel index {} outside of the chunk", index);
+                voxel
+            }
+            Self::Mixed(voxels) => &voxels[index],
+        }
+    }
+
+    /// The voxel at every position, `None` once the buffer is allocated even if its voxels are all the same
+    pub fn uniform(&self) -> Option<Voxel> {
+        match self {
+            Self::Uniform(voxel) => Some(*voxel),
+            Self::Mixed(_) => None,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Voxel> + '_ {
+        (0..CHUNK_VOLUME).map(|index| self.get(index))
+    }
+
+    /// The voxels as a buffer, a uniform chunk gets one allocated
+    pub fn as_mut_slice(&mut self) -> &mut [Voxel] {
+        if let Self::Uniform(voxel) = *self {
+            *self = Self::Mixed(vec![voxel; CHUNK_VOLUME]);
+        }
+        match self {
+            Self::Mixed(voxels) => voxels,
+            Self::Uniform(_) => unreachable!(),
+        }
+    }
+
+    /// The voxels as a slice, borrowed unless the chunk is uniform
+    pub fn to_slice(&self) -> Cow<'_, [Voxel]> {
+        match self {
+            Self::Uniform(voxel) => Cow::Owned(vec![*voxel; CHUNK_VOLUME]),
+            Self::Mixed(voxels) => Cow::Borrowed(voxels),
+        }
+    }
+}
+
+/// Uniform voxels equal a buffer holding the same voxel everywhere
+impl PartialEq for ChunkVoxels {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Uniform(a), Self::Uniform(b)) => a == b,
+            (Self::Mixed(a), Self::Mixed(b)) => a == b,
+            _ => self.iter().eq(other.iter()),
+        }
+    }
 }
 
-#[derive(Debug, Clone, Component)]
+impl Eq for ChunkVoxels {}
+
+/// The voxels, metadata and tints are immutable versions shared between clones. Cloning a chunk
+/// takes a snapshot without copying anything, writing to a chunk copies the parts it changes if a
+/// snapshot still holds them, so a mesher reading a snapshot never blocks an edit or sees half of it.
+///
+/// Only the position and visibility mask are reflected, the voxels are too many to inspect
+#[derive(Debug, Clone, Component, Reflect)]
+#[reflect(Component, from_reflect = false)]
 pub struct Chunk {
     /// The voxel data for this chunk
-    data: Arc<RwLock<ChunkVoxels>>,
+    #[reflect(ignore)]
+    data: Arc<ChunkVoxels>,
+    /// Sparse per voxel metadata
+    #[reflect(ignore)]
+    metadata: Arc<ChunkMetadata>,
+    /// Per voxel color tints
+    #[reflect(ignore)]
+    tints: Arc<ChunkTints>,
     /// The position of this chunk
     pub position: ChunkPosition,
     /// The visibility mask for this chunk
@@ -95,37 +241,95 @@ pub struct Chunk {
     pub visibility_mask: u8,
 }
 
+/// An empty chunk at the origin, reflection needs a way to create the component
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::new(ChunkPosition::new(0, 0, 0))
+    }
+}
+
 impl Chunk {
     pub fn new(position: ChunkPosition) -> Self {
         Self {
-            data: Arc::new(RwLock::new(vec![Voxel::default(); CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE])),
+            data: Arc::new(ChunkVoxels::Uniform(Voxel::default())),
+            metadata: Arc::new(ChunkMetadata::default()),
+            tints: Arc::new(ChunkTints::default()),
             position,
             visibility_mask: 0b000000,
         }
     }
 
-    pub fn get(&self, pos: Vec3) -> Voxel {
-        let (x, y, z) = (pos.x as usize, pos.y as usize, pos.z as usize);
-        self.data.read().unwrap().get(Chunk::linearize_position(x, y, z)).unwrap().clone()
+    pub fn get(&self, pos: LocalVoxelPos) -> Voxel {
+        *self.data.get(pos.index())
+    }
+
+    /// Whether every voxel is empty. Only checks for uniform storage, see [`Chunk::compact`]
+    pub fn is_empty(&self) -> bool {
+        self.data.uniform().map_or(false, |voxel| voxel.is_empty())
+    }
+
+    /// Replaces every voxel with `voxel` without allocating them, dropping all metadata and tints
+    pub fn fill(&mut self, voxel: Voxel) {
+        self.data = Arc::new(ChunkVoxels::Uniform(voxel));
+        self.metadata = Arc::new(ChunkMetadata::default());
+        self.tints = Arc::new(ChunkTints::default());
+    }
+
+    /// Frees the voxel buffer if all of its voxels are the same, generation and loading do this
+    /// for every chunk so the ones that came out empty skip meshing
+    pub fn compact(&mut self) {
+        if let ChunkVoxels::Mixed(voxels) = &*self.data {
+            if voxels.iter().all(|voxel| *voxel == voxels[0]) {
+                self.data = Arc::new(ChunkVoxels::Uniform(voxels[0]));
+            }
+        }
+    }
+
+    /// Replacing a voxel with a different one drops its metadata and tint
+    pub fn set(&mut self, pos: LocalVoxelPos, voxel: Voxel) {
+        self.writer().set(pos.x as usize, pos.y as usize, pos.z as usize, voxel);
+    }
+
+    pub fn metadata(&self, pos: LocalVoxelPos) -> Option<VoxelMetadata> {
+        self.metadata.get(&(pos.index() as u16)).cloned()
+    }
+
+    /// `None` removes the metadata of the voxel
+    pub fn set_metadata(&mut self, pos: LocalVoxelPos, metadata: Option<VoxelMetadata>) {
+        self.writer().set_metadata(pos.x as usize, pos.y as usize, pos.z as usize, metadata);
+    }
+
+    pub fn tint(&self, pos: LocalVoxelPos) -> Option<Tint> {
+        self.tints.get(pos.index())
     }
 
-    pub fn set(&mut self, pos: Vec3, voxel: Voxel) {
-        let (x, y, z) = (pos.x as usize, pos.y as usize, pos.z as usize);
-        self.data.write().unwrap()[Chunk::linearize_position(x, y, z)] = voxel;
+    /// `false` if the chunk has no room for another color, see [`ChunkTints::set`]
+    pub fn set_tint(&mut self, pos: LocalVoxelPos, tint: Option<Tint>) -> bool {
+        self.writer().set_tint(pos.x as usize, pos.y as usize, pos.z as usize, tint)
     }
 
     pub fn reader(&self) -> ChunkDataReader {
         ChunkDataReader {
-            data: self.data.read().unwrap()
+            data: &self.data,
+            metadata: &self.metadata,
+            tints: &self.tints,
         }
     }
 
-    pub fn writer(&self) -> ChunkDataWriter {
+    /// Writes go into a new version of whatever part of the chunk a snapshot still shares
+    pub fn writer(&mut self) -> ChunkDataWriter {
         ChunkDataWriter {
-            data: self.data.write().unwrap()
+            data: &mut self.data,
+            metadata: &mut self.metadata,
+            tints: &mut self.tints,
         }
     }
 
+    /// Whether both chunks still share the same version of their voxels, metadata and tints
+    pub fn shares_data_with(&self, other: &Chunk) -> bool {
+        Arc::ptr_eq(&self.data, &other.data) && Arc::ptr_eq(&self.metadata, &other.metadata) && Arc::ptr_eq(&self.tints, &other.tints)
+    }
+
     pub fn linearize_position(x: usize, y: usize, z: usize) -> usize {
         x + y * CHUNK_SIZE + z * CHUNK_SIZE * CHUNK_SIZE
     }
@@ -137,7 +341,12 @@ impl Chunk {
         (x, y, z)
     }
 
-    pub fn recalculate_visibility_mask(&mut self) {
+    pub fn recalculate_visibility_mask(&mut self, blocks: &BlockRegistry) {
+        // Uniform chunks are opaque on every face or on none of them
+        if let Some(voxel) = self.data.uniform() {
+            self.visibility_mask = if blocks.is_opaque(voxel) { 0b111111 } else { 0b000000 };
+            return;
+        }
         let reader = self.reader();
         let mut mask = 0b000000;
 
@@ -147,11 +356,11 @@ impl Chunk {
                 let left = reader.get(0, y, z);
                 let right = reader.get(CHUNK_SIZE - 1, y, z);
 
-                if !left.is_opaque() {
+                if !blocks.is_opaque(*left) {
                     mask |= 0b1 << 0;
                 }
 
-                if !right.is_opaque() {
+                if !blocks.is_opaque(*right) {
                     mask |= 0b1 << 1;
                 }
             }
@@ -163,11 +372,11 @@ impl Chunk {
                 let bottom = reader.get(x, 0, z);
                 let top = reader.get(x, CHUNK_SIZE - 1, z);
 
-                if !bottom.is_opaque() {
+                if !blocks.is_opaque(*bottom) {
                     mask |= 0b1 << 2;
                 }
 
-                if !top.is_opaque() {
+                if !blocks.is_opaque(*top) {
                     mask |= 0b1 << 3;
                 }
             }
@@ -179,11 +388,11 @@ impl Chunk {
                 let back = reader.get(x, y, 0);
                 let front = reader.get(x, y, CHUNK_SIZE - 1);
 
-                if !back.is_opaque() {
+                if !blocks.is_opaque(*back) {
                     mask |= 0b1 << 4;
                 }
 
-                if !front.is_opaque() {
+                if !blocks.is_opaque(*front) {
                     mask |= 0b1 << 5;
                 }
             }
@@ -198,110 +407,153 @@ impl Chunk {
     }
 
     /// Note: This will return None if the chunk is empty
-    pub fn build(&self) -> Option<Mesh> {
-        let reader = self.reader();
-
-        // Add padding to the chunk data
-        let mut chunk_data = vec![Voxel::Empty; ChunkNDShapePadded::SIZE as usize];
-        let mut is_empty = true;
-        for x in 0..CHUNK_SIZE {
-            for y in 0..CHUNK_SIZE {
-                for z in 0..CHUNK_SIZE {
-                    let index = ChunkNDShapePadded::linearize([x as u32 + 1, y as u32 + 1, z as u32 + 1]);
-                    let voxel = reader.get(x, y, z);
-                    if !voxel.is_empty() {
-                        is_empty = false;
-                    }
-                    chunk_data[index as usize] = voxel.clone();
-                }
-            }
-        }  
+    pub fn build(&self, mesher: &ChunkMesher) -> Option<Mesh> {
+        self.mesh_data(mesher).map(|data| data.into_mesh(mesher.format))
+    }
 
-        if is_empty {
-            return None;
-        }
+    /// Like [`Chunk::build`] with grass and other biome tinted blocks colored for `biome`, the way
+    /// the game meshes the solid part of the chunk
+    pub fn build_in(&self, mesher: &ChunkMesher, biome: Biome) -> Option<Mesh> {
+        self.mesh_data_in(mesher, Some(biome), MeshLayer::Solid, None).map(|data| data.into_mesh(mesher.format))
+    }
 
-        // Generate the mesh
-        let mut buffer = GreedyQuadsBuffer::new(chunk_data.len());
-        let faces = RIGHT_HANDED_Y_UP_CONFIG.faces;
-        greedy_quads(
-            &chunk_data,
-            &ChunkNDShapePadded {},
-            [0; 3],
-            [CHUNK_SIZE as u32 + 1; 3],
-            &faces,
-            &mut buffer,
-        );
-
-        // Convert the mesh to a bevy mesh
-        let mut mesh = Mesh::new(bevy::render::render_resource::PrimitiveTopology::TriangleList);
-
-        let num_indices = buffer.quads.num_quads() * 6;
-        let num_vertices = buffer.quads.num_quads() * 4;
-
-        let mut indices = Vec::with_capacity(num_indices);
-        let mut positions = Vec::with_capacity(num_vertices);
-        let mut normals = Vec::with_capacity(num_vertices);
-
-        for (group, face) in buffer.quads.groups.into_iter().zip(faces.into_iter()) {
-            for quad in group.into_iter() {
-                indices.extend_from_slice(&face.quad_mesh_indices(positions.len() as u32));
-                let _positions = &face.quad_mesh_positions(&quad, 1.0);
-                // Translate positions to remove padding
-                let _positions = _positions.iter().map(|pos| [pos[0] - 1.0, pos[1] - 1.0, pos[2] - 1.0]).collect::<Vec<[f32; 3]>>();
-                positions.extend_from_slice(&_positions);
-                normals.extend_from_slice(&face.quad_mesh_normals()); 
-            }
-        }
+    /// Both meshes of the chunk, with grass and other biome tinted blocks colored for `biome`.
+    /// Water is joined with the water of the neighbours in `borders`. [`Chunk::build`] only builds
+    /// the solid one.
+    pub fn build_meshes(&self, mesher: &ChunkMesher, biome: Biome, borders: &ChunkBorders) -> ChunkMeshes {
+        let build = |layer| self.mesh_data_in(mesher, Some(biome), layer, Some(borders)).map(|data| data.into_mesh(mesher.format));
+        ChunkMeshes { solid: build(MeshLayer::Solid), translucent: build(MeshLayer::Translucent) }
+    }
 
-        mesh.set_indices(Some(bevy::render::mesh::Indices::U32(indices)));
-        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, VertexAttributeValues::Float32x3(positions));
-        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, VertexAttributeValues::Float32x3(normals));
+    /// Vertex buffers of the solid chunk mesh, see [`mesh_chunk`]
+    pub fn mesh_data(&self, mesher: &ChunkMesher) -> Option<MeshData> {
+        self.mesh_data_in(mesher, None, MeshLayer::Solid, None)
+    }
 
-        Some(mesh)
+    fn mesh_data_in(
+        &self,
+        mesher: &ChunkMesher,
+        biome: Option<Biome>,
+        layer: MeshLayer,
+        borders: Option<&ChunkBorders>,
+    ) -> Option<MeshData> {
+        if self.is_empty() {
+            return None;
+        }
+        let reader = self.reader();
+        let opts = MeshOptions {
+            strategy: mesher.strategy,
+            metadata: Some(reader.all_metadata()),
+            tints: Some(reader.tints()),
+            biome_tint: biome.map(|biome| biome.tint()),
+            layer,
+            borders,
+            ..MeshOptions::new(&mesher.blocks, &mesher.shapes)
+        };
+        mesh_chunk(&reader.voxels().to_slice(), opts)
     }
 
-    pub fn generate_with(&mut self, generator: impl Fn(&ChunkPosition, Vec3) -> Voxel) {
-        for x in 0..CHUNK_SIZE {
-            for y in 0..CHUNK_SIZE {
-                for z in 0..CHUNK_SIZE {
-                    self.set(Vec3::new(x as f32, y as f32, z as f32), generator(&self.position, Vec3::new(x as f32, y as f32, z as f32)));
-                }
-            }
+    pub fn generate_with(&mut self, generator: impl Fn(&ChunkPosition, LocalVoxelPos) -> Voxel) {
+        let data = Arc::make_mut(&mut self.data).as_mut_slice();
+        for pos in LocalVoxelPos::iter() {
+            data[pos.index()] = generator(&self.position, pos);
         }
     }
 }
 
 pub struct ChunkDataReader<'a> {
-    data: RwLockReadGuard<'a, ChunkVoxels>
+    data: &'a ChunkVoxels,
+    metadata: &'a ChunkMetadata,
+    tints: &'a ChunkTints,
 }
 
+/// Copies a part of the chunk on its first write if a snapshot shares it
 pub struct ChunkDataWriter<'a> {
-    data: RwLockWriteGuard<'a, ChunkVoxels>
+    data: &'a mut Arc<ChunkVoxels>,
+    metadata: &'a mut Arc<ChunkMetadata>,
+    tints: &'a mut Arc<ChunkTints>,
 }
 
 impl<'a> ChunkDataReader<'a> {
     pub fn get(&self, x: usize, y: usize, z: usize) -> &Voxel {
         let index = Chunk::linearize_position(x, y, z);
-        self.data.get(index).unwrap()
+        self.data.get(index)
+    }
+
+    /// All voxels of the chunk in buffer order
+    pub fn voxels(&self) -> &ChunkVoxels {
+        self.data
+    }
+
+    pub fn metadata(&self, x: usize, y: usize, z: usize) -> Option<&VoxelMetadata> {
+        self.metadata.get(&(Chunk::linearize_position(x, y, z) as u16))
+    }
+
+    /// Metadata of every voxel that has any
+    pub fn all_metadata(&self) -> &ChunkMetadata {
+        self.metadata
+    }
+
+    pub fn tint(&self, x: usize, y: usize, z: usize) -> Option<Tint> {
+        self.tints.get(Chunk::linearize_position(x, y, z))
+    }
+
+    pub fn tints(&self) -> &ChunkTints {
+        self.tints
     }
 }
 
 impl<'a> ChunkDataWriter<'a> {
     pub fn get(&mut self, x: usize, y: usize, z: usize) -> &mut Voxel {
         let index = Chunk::linearize_position(x, y, z);
-        self.data.get_mut(index).unwrap()
+        &mut Arc::make_mut(self.data).as_mut_slice()[index]
     }
 
+    /// Replacing a voxel with a different one drops its metadata and tint
     pub fn set(&mut self, x: usize, y: usize, z: usize, voxel: Voxel) {
         let index = Chunk::linearize_position(x, y, z);
-        self.data[index] = voxel;
+        if *self.data.get(index) == voxel {
+            return;
+        }
+        if self.metadata.contains_key(&(index as u16)) {
+            Arc::make_mut(self.metadata).remove(&(index as u16));
+        }
+        if self.tints.get(index).is_some() {
+            Arc::make_mut(self.tints).set(index, None);
+        }
+        Arc::make_mut(self.data).as_mut_slice()[index] = voxel;
+    }
+
+    pub fn metadata(&self, x: usize, y: usize, z: usize) -> Option<&VoxelMetadata> {
+        self.metadata.get(&(Chunk::linearize_position(x, y, z) as u16))
+    }
+
+    /// `None` removes the metadata of the voxel
+    pub fn set_metadata(&mut self, x: usize, y: usize, z: usize, metadata: Option<VoxelMetadata>) {
+        let index = Chunk::linearize_position(x, y, z) as u16;
+        match metadata {
+            Some(metadata) => Arc::make_mut(self.metadata).insert(index, metadata),
+            None if self.metadata.contains_key(&index) => Arc::make_mut(self.metadata).remove(&index),
+            None => None,
+        };
+    }
+
+    /// `false` if the chunk has no room for another color, see [`ChunkTints::set`]
+    pub fn set_tint(&mut self, x: usize, y: usize, z: usize, tint: Option<Tint>) -> bool {
+        Arc::make_mut(self.tints).set(Chunk::linearize_position(x, y, z), tint)
+    }
+
+    /// Replaces every tint of the chunk
+    pub fn set_tints(&mut self, tints: ChunkTints) {
+        *self.tints = Arc::new(tints);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use bevy::{prelude::Vec4, render::mesh::VertexAttributeValues};
+    use crate::engine::voxel::Block;
 
     #[test]
     fn test_top_opaque() {
@@ -309,14 +561,146 @@ mod tests {
         // Fill the top layer with opaque voxels
         for x in 0..CHUNK_SIZE {
             for z in 0..CHUNK_SIZE {
-                chunk.set(Vec3::new(x as f32, CHUNK_SIZE as f32 - 1.0, z as f32), Voxel::NonEmpty { is_opaque: true });
+                chunk.set(LocalVoxelPos::new(x as u8, CHUNK_SIZE as u8 - 1, z as u8), Voxel::from(Block::Stone));
             }
         }
 
-        chunk.recalculate_visibility_mask();
+        chunk.recalculate_visibility_mask(&BlockRegistry::builtin());
 
         assert!(chunk.is_face_opaque(Face::Top));
         assert!(!chunk.is_face_opaque(Face::Bottom));
         assert!(!chunk.is_face_opaque(Face::Left));
     }
+
+    #[test]
+    fn test_uniform_chunks() {
+        let mut chunk = Chunk::new(ChunkPosition::new(0, 0, 0));
+        assert!(chunk.is_empty());
+        chunk.recalculate_visibility_mask(&BlockRegistry::builtin());
+        assert_eq!(chunk.visibility_mask, 0b000000);
+
+        chunk.fill(Voxel::from(Block::Stone));
+        chunk.recalculate_visibility_mask(&BlockRegistry::builtin());
+        assert_eq!(chunk.visibility_mask, 0b111111);
+        assert_eq!(chunk.reader().voxels().uniform(), Some(Voxel::from(Block::Stone)));
+
+        // Writing allocates the voxels, compacting frees them once they are all the same again
+        chunk.set(LocalVoxelPos::new(0, 0, 0), Voxel::Empty);
+        assert_eq!(chunk.reader().voxels().uniform(), None);
+        chunk.recalculate_visibility_mask(&BlockRegistry::builtin());
+        assert_eq!(chunk.visibility_mask, 0b101010);
+        chunk.set(LocalVoxelPos::new(0, 0, 0), Voxel::from(Block::Stone));
+        chunk.compact();
+        assert_eq!(chunk.reader().voxels().uniform(), Some(Voxel::from(Block::Stone)));
+    }
+
+    #[test]
+    fn test_tints_become_vertex_colors() {
+        let mut chunk = Chunk::new(ChunkPosition::new(0, 0, 0));
+        chunk.set(LocalVoxelPos::new(1, 1, 1), Voxel::from(Block::Grass));
+        chunk.set(LocalVoxelPos::new(2, 1, 1), Voxel::from(Block::Grass));
+        assert!(chunk.build(&ChunkMesher::default()).unwrap().attribute(Mesh::ATTRIBUTE_COLOR).is_none());
+
+        chunk.set_tint(LocalVoxelPos::new(2, 1, 1), Some([255, 0, 0]));
+        let mesh = chunk.build(&ChunkMesher::default()).unwrap();
+        let Some(VertexAttributeValues::Float32x4(colors)) = mesh.attribute(Mesh::ATTRIBUTE_COLOR) else {
+            panic!("tinted chunk mesh has no vertex colors");
+        };
+        assert!(colors.contains(&[1.0, 0.0, 0.0, 1.0]));
+        assert!(colors.contains(&[1.0; 4]));
+
+        // Replacing the voxel drops its tint
+        chunk.set(LocalVoxelPos::new(2, 1, 1), Voxel::from(Block::Stone));
+        assert_eq!(chunk.tint(LocalVoxelPos::new(2, 1, 1)), None);
+    }
+
+    #[test]
+    fn test_clones_are_snapshots() {
+        let mut chunk = Chunk::new(ChunkPosition::new(0, 0, 0));
+        chunk.set(LocalVoxelPos::new(1, 1, 1), Voxel::from(Block::Stone));
+        let snapshot = chunk.clone();
+        assert!(snapshot.shares_data_with(&chunk));
+
+        chunk.set(LocalVoxelPos::new(1, 1, 1), Voxel::from(Block::Dirt));
+        chunk.set_tint(LocalVoxelPos::new(1, 1, 1), Some([1, 2, 3]));
+        assert_eq!(snapshot.get(LocalVoxelPos::new(1, 1, 1)), Voxel::from(Block::Stone));
+        assert_eq!(snapshot.tint(LocalVoxelPos::new(1, 1, 1)), None);
+        assert!(!snapshot.shares_data_with(&chunk));
+
+        // Writing what is already there does not copy anything
+        let snapshot = chunk.clone();
+        chunk.set(LocalVoxelPos::new(1, 1, 1), Voxel::from(Block::Dirt));
+        chunk.set_metadata(LocalVoxelPos::new(2, 2, 2), None);
+        assert!(snapshot.shares_data_with(&chunk));
+    }
+
+    #[test]
+    fn test_tangents_follow_uvs() {
+        let mut chunk = Chunk::new(ChunkPosition::new(0, 0, 0));
+        chunk.set(LocalVoxelPos::new(3, 3, 3), Voxel::from(Block::Stone));
+        chunk.set(LocalVoxelPos::new(4, 3, 3), Voxel::from(Block::Stone));
+        let mesh = chunk.build(&ChunkMesher::default()).unwrap();
+        let positions = mesh.attribute(Mesh::ATTRIBUTE_POSITION).unwrap().as_float3().unwrap();
+        let normals = mesh.attribute(Mesh::ATTRIBUTE_NORMAL).unwrap().as_float3().unwrap();
+        let Some(VertexAttributeValues::Float32x2(uvs)) = mesh.attribute(Mesh::ATTRIBUTE_UV_0) else {
+            panic!("chunk mesh has no uvs");
+        };
+        let Some(VertexAttributeValues::Float32x4(tangents)) = mesh.attribute(Mesh::ATTRIBUTE_TANGENT) else {
+            panic!("chunk mesh has no tangents");
+        };
+
+        // Along u the position moves with the tangent, along v against the bitangent, like mikktspace has it
+        for quad in 0..positions.len() / 4 {
+            for (a, b) in (quad * 4..quad * 4 + 4).flat_map(|a| (quad * 4..quad * 4 + 4).map(move |b| (a, b))) {
+                let step = Vec3::from_array(positions[b]) - Vec3::from_array(positions[a]);
+                let (du, dv) = (uvs[b][0] - uvs[a][0], uvs[b][1] - uvs[a][1]);
+                let tangent = Vec4::from_array(tangents[a]);
+                let bitangent = Vec3::from_array(normals[a]).cross(tangent.truncate()) * tangent.w;
+                if du != 0.0 && dv == 0.0 {
+                    assert!((step / du).abs_diff_eq(tangent.truncate(), 1e-6), "tangent {:?} along {:?}", tangent, step);
+                }
+                if dv != 0.0 && du == 0.0 {
+                    assert!((step / dv).abs_diff_eq(-bitangent, 1e-6), "bitangent {:?} along {:?}", bitangent, step);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_morton_round_trip() {
+        for (x, y, z) in [(0, 0, 0), (1, 2, 3), (-1, -1, -1), (-12345, 678, 1 << 19), (MORTON_BIAS - 1, -MORTON_BIAS, 7)] {
+            let chunk = ChunkPosition::new(x, y, z);
+            assert_eq!(ChunkPosition::from_morton(chunk.to_morton()), chunk);
+        }
+        // Bits interleave as x, y, z from the lowest one up
+        let origin = ChunkPosition::new(0, 0, 0).to_morton();
+        assert_eq!(ChunkPosition::new(1, 0, 0).to_morton() - origin, 0b001);
+        assert_eq!(ChunkPosition::new(0, 1, 0).to_morton() - origin, 0b010);
+        assert_eq!(ChunkPosition::new(0, 0, 1).to_morton() - origin, 0b100);
+    }
+
+    #[test]
+    fn test_cube_and_shell() {
+        let center = ChunkPosition::new(4, -2, 7);
+        let chebyshev = |chunk: &ChunkPosition| (chunk.x - center.x).abs().max((chunk.y - center.y).abs()).max((chunk.z - center.z).abs());
+
+        assert_eq!(ChunkPosition::iter_cube(center, 0).collect::<Vec<_>>(), vec![center]);
+        assert_eq!(ChunkPosition::iter_shell(center, 0).collect::<Vec<_>>(), vec![center]);
+        for radius in 1..4 {
+            let cube = ChunkPosition::iter_cube(center, radius).collect::<Vec<_>>();
+            assert_eq!(cube.len(), (2 * radius as usize + 1).pow(3));
+            assert!(cube.iter().all(|chunk| chebyshev(chunk) <= radius as i32));
+
+            let shell = ChunkPosition::iter_shell(center, radius).collect::<Vec<_>>();
+            let expected = cube.iter().filter(|chunk| chebyshev(chunk) == radius as i32).copied().collect::<Vec<_>>();
+            assert_eq!(shell, expected);
+        }
+    }
+
+    #[test]
+    fn test_from_world_position_negative() {
+        assert_eq!(ChunkPosition::from_world_position(Vec3::new(-1.0, 0.0, 0.0)), ChunkPosition::new(-1, 0, 0));
+        assert_eq!(ChunkPosition::from_world_position(Vec3::new(-0.5, -16.0, -17.0)), ChunkPosition::new(-1, -1, -2));
+        assert_eq!(ChunkPosition::from_world_position(Vec3::new(15.9, 16.0, 0.0)), ChunkPosition::new(0, 1, 0));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,137 @@
+//! Spatial index of chunk entities, grouped into regions of [`REGION_SIZE`]³ chunks.
+//!
+//! The garbage collector asks for chunks outside of a radius around the camera. Regions completely
+//! inside the radius are skipped and regions completely outside are taken as a whole, so only chunks
+//! in regions crossing the sphere are checked one by one. The index follows the [`Chunk`] component,
+//! entities are added when it is inserted and removed when it is removed or the entity is despawned.
+
+use bevy::{prelude::*, utils::HashMap};
+
+use super::{chunk::{Chunk, ChunkPosition}, coords::floor_div};
+
+/// Region edge length in chunks
+pub const REGION_SIZE: i32 = 8;
+
+#[derive(Resource, Debug, Default)]
+pub struct ChunkIndex {
+    regions: HashMap<ChunkPosition, HashMap<Entity, ChunkPosition>>,
+    entities: HashMap<Entity, ChunkPosition>,
+}
+
+/// Position of the region containing a chunk, in regions
+fn region_of(chunk: &ChunkPosition) -> ChunkPosition {
+    ChunkPosition::new(floor_div(chunk.x, REGION_SIZE), floor_div(chunk.y, REGION_SIZE), floor_div(chunk.z, REGION_SIZE))
+}
+
+/// Squared distance from `center` to the closest and to the farthest chunk a region can contain
+fn region_distance_bounds(region: &ChunkPosition, center: &ChunkPosition) -> (i64, i64) {
+    let mut closest = 0;
+    let mut farthest = 0;
+    for (region, center) in [(region.x, center.x), (region.y, center.y), (region.z, center.z)] {
+        let min = (region * REGION_SIZE) as i64;
+        let max = min + REGION_SIZE as i64 - 1;
+        let center = center as i64;
+        let near = (min - center).max(center - max).max(0);
+        let far = (center - min).abs().max((max - center).abs());
+        closest += near * near;
+        farthest += far * far;
+    }
+    (closest, farthest)
+}
+
+impl ChunkIndex {
+    pub fn insert(&mut self, entity: Entity, chunk: ChunkPosition) {
+        self.remove(entity);
+        self.entities.insert(entity, chunk);
+        self.regions.entry(region_of(&chunk)).or_default().insert(entity, chunk);
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<ChunkPosition> {
+        let chunk = self.entities.remove(&entity)?;
+        let region = region_of(&chunk);
+        if let Some(entities) = self.regions.get_mut(&region) {
+            entities.remove(&entity);
+            if entities.is_empty() {
+                self.regions.remove(&region);
+            }
+        }
+        Some(chunk)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.regions.clear();
+        self.entities.clear();
+    }
+
+    /// Chunks farther than `radius` chunks from `center`, the same as `distance_to(center) > radius`
+    pub fn outside_radius(&self, center: ChunkPosition, radius: u32) -> impl Iterator<Item = (Entity, ChunkPosition)> + '_ {
+        let radius_squared = radius as i64 * radius as i64;
+        self.regions
+            .iter()
+            .filter(move |(region, _)| region_distance_bounds(region, &center).1 > radius_squared)
+            .flat_map(move |(region, entities)| {
+                let whole = region_distance_bounds(region, &center).0 > radius_squared;
+                entities
+                    .iter()
+                    .filter(move |(_, chunk)| whole || chunk.distance_squared_to(&center) > radius_squared)
+                    .map(|(entity, chunk)| (*entity, *chunk))
+            })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, ChunkPosition)> + '_ {
+        self.entities.iter().map(|(entity, chunk)| (*entity, *chunk))
+    }
+}
+
+/// Keeps the index in sync with the [`Chunk`] components
+pub fn update_chunk_index(
+    mut index: ResMut<ChunkIndex>,
+    mut removed: RemovedComponents<Chunk>,
+    added: Query<(Entity, &Chunk), Added<Chunk>>,
+) {
+    for entity in removed.read() {
+        index.remove(entity);
+    }
+    for (entity, chunk) in added.iter() {
+        index.insert(entity, chunk.position);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outside_radius_matches_distance() {
+        let mut index = ChunkIndex::default();
+        let mut chunks = Vec::new();
+        for x in -20..20 {
+            for y in -3..3 {
+                for z in -20..20 {
+                    let chunk = ChunkPosition::new(x, y, z);
+                    index.insert(Entity::from_raw(chunks.len() as u32), chunk);
+                    chunks.push(chunk);
+                }
+            }
+        }
+
+        for (center, radius) in [(ChunkPosition::new(0, 0, 0), 10), (ChunkPosition::new(-13, 2, 7), 4), (ChunkPosition::new(3, 0, -9), 0)] {
+            let mut found = index.outside_radius(center, radius).map(|(entity, _)| entity.index()).collect::<Vec<_>>();
+            found.sort();
+            let expected = (0..chunks.len() as u32).filter(|i| chunks[*i as usize].distance_to(&center) > radius as f32).collect::<Vec<_>>();
+            assert_eq!(found, expected);
+        }
+
+        assert_eq!(index.remove(Entity::from_raw(0)), Some(ChunkPosition::new(-20, -3, -20)));
+        assert_eq!(index.remove(Entity::from_raw(0)), None);
+        assert_eq!(index.len(), chunks.len() - 1);
+    }
+}
@@ -0,0 +1,77 @@
+//! Structured logging of the chunk pipeline. Generation, meshing and garbage collection log under
+//! their own targets with the chunk position and how long the work took, so the output can be
+//! filtered with `RUST_LOG=chunk::gen=info` or read by any `tracing` tool. Every generation and
+//! meshing task also runs in a span carrying its chunk position, and the streaming systems are
+//! split into spans for every phase. Build with the `tracy` feature to see them in Tracy next to
+//! the spans Bevy opens for every system.
+//!
+//! Nothing is logged until the [`ChunkLogLevel`] resource is raised, the `chunk_log` console
+//! command switches it at runtime. Tasks are handed the level when they are spawned.
+
+use std::{str::FromStr, time::Duration};
+
+use bevy::prelude::Resource;
+
+pub const GENERATION_TARGET: &str = "chunk::gen";
+pub const MESHING_TARGET: &str = "chunk::mesh";
+pub const GC_TARGET: &str = "chunk::gc";
+/// Only spans, the visibility search runs too often to log
+pub const STREAMING_TARGET: &str = "chunk::stream";
+
+/// What every stage of the chunk pipeline logs
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum ChunkLogLevel {
+    #[default]
+    Off = 0,
+    /// One line for every frame a stage of the pipeline did any work
+    Summary = 1,
+    /// One line for every chunk going through a stage as well
+    Chunks = 2,
+}
+
+impl ChunkLogLevel {
+    pub const ALL: [ChunkLogLevel; 3] = [ChunkLogLevel::Off, ChunkLogLevel::Summary, ChunkLogLevel::Chunks];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Summary => "summary",
+            Self::Chunks => "chunks",
+        }
+    }
+
+    pub fn logs_summary(&self) -> bool {
+        *self >= Self::Summary
+    }
+
+    pub fn logs_chunks(&self) -> bool {
+        *self >= Self::Chunks
+    }
+}
+
+impl FromStr for ChunkLogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|level| level.name() == s).ok_or_else(|| format!("unknown chunk log level `{}`", s))
+    }
+}
+
+/// Milliseconds with a fraction, log fields read better than a [`Duration`]'s debug output
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels_parse_and_order() {
+        for level in ChunkLogLevel::ALL {
+            assert_eq!(level.name().parse::<ChunkLogLevel>(), Ok(level));
+        }
+        assert!("loud".parse::<ChunkLogLevel>().is_err());
+        assert!(ChunkLogLevel::Chunks > ChunkLogLevel::Summary);
+    }
+}
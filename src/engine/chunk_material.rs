@@ -0,0 +1,215 @@
+//! Material of chunk meshes: the standard PBR material extended with a horizontal clipping plane.
+//! Fragments above the plane are discarded, which cuts the terrain open without touching any voxels.
+//!
+//! Chunk meshes carry uvs repeating once per voxel and tangents, so a [`TerrainNormalMap`] adds
+//! surface detail to every face.
+//!
+//! Every chunk draws with one of the few shared [`ChunkMaterials`], so chunk meshes batch together
+//! and moving the plane only touches those materials.
+//!
+//! Meshes in the [`ChunkVertexFormat::PackedFace`](super::meshing::ChunkVertexFormat::PackedFace) format go through `shaders/chunk_vertex.wgsl`,
+//! which turns the face number back into normals, uvs and tangents. Standard meshes keep Bevy's
+//! own vertex shader.
+
+use bevy::{
+    pbr::{ExtendedMaterial, MaterialExtension, MaterialExtensionKey, MaterialExtensionPipeline, MESH_SHADER_HANDLE},
+    prelude::*,
+    render::{
+        mesh::MeshVertexBufferLayout,
+        render_resource::{AsBindGroup, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError},
+    },
+};
+
+use super::meshing::ATTRIBUTE_FACE;
+
+pub type ChunkMaterial = ExtendedMaterial<StandardMaterial, ChunkMaterialExtension>;
+
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
+pub struct ChunkMaterialExtension {
+    /// Nothing above this world height is drawn
+    #[uniform(100)]
+    pub clip_height: f32,
+}
+
+impl MaterialExtension for ChunkMaterialExtension {
+    fn vertex_shader() -> ShaderRef {
+        "shaders/chunk_vertex.wgsl".into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        "shaders/chunk.wgsl".into()
+    }
+
+    fn deferred_fragment_shader() -> ShaderRef {
+        "shaders/chunk.wgsl".into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialExtensionPipeline,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        _key: MaterialExtensionKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        // Prepasses keep their own vertex shader and only need positions
+        if descriptor.vertex.shader_defs.contains(&"PREPASS_PIPELINE".into()) {
+            return Ok(());
+        }
+        if !layout.contains(ATTRIBUTE_FACE) {
+            descriptor.vertex.shader = MESH_SHADER_HANDLE;
+            return Ok(());
+        }
+
+        let mut attributes = vec![Mesh::ATTRIBUTE_POSITION.at_shader_location(0), ATTRIBUTE_FACE.at_shader_location(8)];
+        if layout.contains(Mesh::ATTRIBUTE_COLOR) {
+            attributes.push(Mesh::ATTRIBUTE_COLOR.at_shader_location(5));
+        }
+        descriptor.vertex.buffers = vec![layout.get_layout(&attributes)?];
+        // The vertex shader derives uvs and tangents, the fragment shader uses them as if the mesh had them
+        let defs = ["PACKED_FACE".into(), "VERTEX_UVS".into(), "VERTEX_TANGENTS".into()];
+        descriptor.vertex.shader_defs.extend(defs.clone());
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            fragment.shader_defs.extend(defs);
+        }
+        Ok(())
+    }
+}
+
+/// Base of the opaque chunk material
+pub fn terrain_material() -> StandardMaterial {
+    StandardMaterial { base_color: Color::rgb(0.3, 0.85, 0.4), ..Default::default() }
+}
+
+/// Base of the translucent chunk material, blended over what is behind it. Water is the only
+/// translucent builtin block, so until textures land the material is colored like it.
+pub fn translucent_terrain_material() -> StandardMaterial {
+    StandardMaterial { base_color: Color::rgba(0.2, 0.38, 0.77, 0.6), alpha_mode: AlphaMode::Blend, ..terrain_material() }
+}
+
+/// Base of the debug chunk material, unlit so lighting and shadows do not hide the shape of a mesh
+pub fn debug_terrain_material() -> StandardMaterial {
+    StandardMaterial { unlit: true, ..terrain_material() }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChunkMaterialKind {
+    #[default]
+    Opaque,
+    Translucent,
+    Debug,
+}
+
+/// Material handles shared by every chunk entity. Game code changes how terrain looks with
+/// [`ChunkMaterials::replace`], which keeps the handles so every chunk picks the change up.
+#[derive(Resource, Debug, Clone)]
+pub struct ChunkMaterials {
+    pub opaque: Handle<ChunkMaterial>,
+    pub translucent: Handle<ChunkMaterial>,
+    pub debug: Handle<ChunkMaterial>,
+}
+
+impl FromWorld for ChunkMaterials {
+    fn from_world(world: &mut World) -> Self {
+        let clip_plane = *world.get_resource_or_insert_with(ClipPlane::default);
+        let mut materials = world.resource_mut::<Assets<ChunkMaterial>>();
+        Self {
+            opaque: materials.add(clip_plane.material(terrain_material())),
+            translucent: materials.add(clip_plane.material(translucent_terrain_material())),
+            debug: materials.add(clip_plane.material(debug_terrain_material())),
+        }
+    }
+}
+
+impl ChunkMaterials {
+    pub fn get(&self, kind: ChunkMaterialKind) -> &Handle<ChunkMaterial> {
+        match kind {
+            ChunkMaterialKind::Opaque => &self.opaque,
+            ChunkMaterialKind::Translucent => &self.translucent,
+            ChunkMaterialKind::Debug => &self.debug,
+        }
+    }
+
+    /// Swaps the base of a material. The clip plane and the [`TerrainNormalMap`] stay as they are.
+    pub fn replace(&self, kind: ChunkMaterialKind, base: StandardMaterial, materials: &mut Assets<ChunkMaterial>) {
+        let Some(material) = materials.get_mut(self.get(kind)) else {
+            return;
+        };
+        let normal_map = material.base.normal_map_texture.take();
+        material.base = StandardMaterial { normal_map_texture: normal_map, ..base };
+    }
+}
+
+/// Height of the cutaway plane, `None` draws everything
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq)]
+pub struct ClipPlane(pub Option<f32>);
+
+impl ClipPlane {
+    pub fn clip_height(&self) -> f32 {
+        self.0.unwrap_or(f32::MAX)
+    }
+
+    /// Chunk material cut by this plane
+    pub fn material(&self, base: StandardMaterial) -> ChunkMaterial {
+        ChunkMaterial { base, extension: ChunkMaterialExtension { clip_height: self.clip_height() } }
+    }
+}
+
+/// Tangent space normal map tiled over every voxel face of the terrain, `None` for flat faces
+#[derive(Resource, Debug, Clone, Default, PartialEq)]
+pub struct TerrainNormalMap(pub Option<Handle<Image>>);
+
+pub struct ChunkMaterialPlugin;
+
+impl Plugin for ChunkMaterialPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(MaterialPlugin::<ChunkMaterial>::default())
+            .init_resource::<ClipPlane>()
+            .init_resource::<TerrainNormalMap>()
+            .init_resource::<ChunkMaterials>()
+            .add_systems(PostUpdate, (
+                update_clip_plane.run_if(resource_changed::<ClipPlane>()),
+                update_normal_map.run_if(resource_changed::<TerrainNormalMap>()),
+            ));
+    }
+}
+
+/// Moves the plane of every chunk material
+fn update_clip_plane(clip_plane: Res<ClipPlane>, mut materials: ResMut<Assets<ChunkMaterial>>) {
+    let clip_height = clip_plane.clip_height();
+    for (_, material) in materials.iter_mut() {
+        material.extension.clip_height = clip_height;
+    }
+}
+
+/// Gives every chunk material the normal map when it changes
+fn update_normal_map(normal_map: Res<TerrainNormalMap>, mut materials: ResMut<Assets<ChunkMaterial>>) {
+    let ids = materials.ids().collect::<Vec<_>>();
+    for id in ids {
+        // Looking before writing keeps unchanged materials from being prepared again
+        if materials.get(id).is_some_and(|material| material.base.normal_map_texture != normal_map.0) {
+            materials.get_mut(id).unwrap().base.normal_map_texture = normal_map.0.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_keeps_clip_plane_and_normal_map() {
+        let mut world = World::new();
+        world.init_resource::<Assets<ChunkMaterial>>();
+        world.insert_resource(ClipPlane(Some(12.0)));
+        let chunk_materials = ChunkMaterials::from_world(&mut world);
+        let mut materials = world.resource_mut::<Assets<ChunkMaterial>>();
+        let normal_map = Handle::<Image>::default();
+        materials.get_mut(&chunk_materials.opaque).unwrap().base.normal_map_texture = Some(normal_map.clone());
+
+        chunk_materials.replace(ChunkMaterialKind::Opaque, StandardMaterial { base_color: Color::RED, ..default() }, &mut materials);
+        let material = materials.get(chunk_materials.get(ChunkMaterialKind::Opaque)).unwrap();
+        assert_eq!(material.base.base_color, Color::RED);
+        assert_eq!(material.base.normal_map_texture, Some(normal_map));
+        assert_eq!(material.extension.clip_height, 12.0);
+        assert_eq!(materials.len(), 3);
+    }
+}
@@ -0,0 +1,255 @@
+//! Differences between two versions of a chunk, for sending edits over the network or keeping
+//! them for undo instead of whole chunks. [`Chunk::diff`] lists every voxel, metadata and tint
+//! that changed by voxel index (see [`LocalVoxelPos::index`]), [`Chunk::apply_patch`] writes
+//! them. Undoing a patch is applying the diff the other way around.
+//!
+//! Encoded layout (all integers little endian):
+//! ```text
+//! voxel_count    u16
+//! voxels         voxel_count × (u16 voxel index, u16 voxel code)
+//! metadata_count u16
+//! metadata       metadata_count × (u16 voxel index, u8 0 for removed or 1 and u8 length, length bytes)
+//! tint_count     u16
+//! tints          tint_count × (u16 voxel index, u8 0 for removed or 1 and 3 bytes sRGB)
+//! ```
+
+use super::{
+    block_registry::BlockRegistry,
+    chunk::{Chunk, CHUNK_SIZE},
+    coords::LocalVoxelPos,
+    serialization::{ByteReader, DecodeError},
+    tint::Tint,
+    voxel::{Voxel, VoxelMetadata},
+};
+
+const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
+/// Changes to a chunk, every list is sorted by voxel index
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChunkPatch {
+    pub voxels: Vec<(u16, Voxel)>,
+    /// `None` removes the metadata of the voxel
+    pub metadata: Vec<(u16, Option<VoxelMetadata>)>,
+    /// `None` removes the tint of the voxel
+    pub tints: Vec<(u16, Option<Tint>)>,
+}
+
+impl ChunkPatch {
+    pub fn is_empty(&self) -> bool {
+        self.voxels.is_empty() && self.metadata.is_empty() && self.tints.is_empty()
+    }
+
+    /// Number of changes of any kind
+    pub fn len(&self) -> usize {
+        self.voxels.len() + self.metadata.len() + self.tints.len()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(6 + self.voxels.len() * 4);
+        bytes.extend_from_slice(&(self.voxels.len() as u16).to_le_bytes());
+        for (index, voxel) in self.voxels.iter() {
+            bytes.extend_from_slice(&index.to_le_bytes());
+            bytes.extend_from_slice(&voxel.to_code().to_le_bytes());
+        }
+        bytes.extend_from_slice(&(self.metadata.len() as u16).to_le_bytes());
+        for (index, metadata) in self.metadata.iter() {
+            bytes.extend_from_slice(&index.to_le_bytes());
+            match metadata {
+                Some(metadata) => {
+                    bytes.extend_from_slice(&[1, metadata.as_bytes().len() as u8]);
+                    bytes.extend_from_slice(metadata.as_bytes());
+                }
+                None => bytes.push(0),
+            }
+        }
+        bytes.extend_from_slice(&(self.tints.len() as u16).to_le_bytes());
+        for (index, tint) in self.tints.iter() {
+            bytes.extend_from_slice(&index.to_le_bytes());
+            match tint {
+                Some(tint) => {
+                    bytes.push(1);
+                    bytes.extend_from_slice(tint);
+                }
+                None => bytes.push(0),
+            }
+        }
+        bytes
+    }
+
+    /// Voxels of blocks `blocks` does not know are rejected
+    pub fn decode(bytes: &[u8], blocks: &BlockRegistry) -> Result<Self, DecodeError> {
+        let mut input = ByteReader::new(bytes);
+        let mut patch = Self::default();
+
+        for _ in 0..input.u16()? {
+            let index = voxel_index(&mut input).map_err(|index| index.map_or(DecodeError::UnexpectedEof, DecodeError::InvalidVoxelIndex))?;
+            let code = input.u16()?;
+            patch.voxels.push((index, Voxel::from_code(code, blocks).ok_or(DecodeError::UnknownVoxel(code))?));
+        }
+        for _ in 0..input.u16()? {
+            let index = voxel_index(&mut input).map_err(|index| index.map_or(DecodeError::UnexpectedEof, DecodeError::InvalidMetadataIndex))?;
+            let metadata = match input.u8()? {
+                0 => None,
+                1 => {
+                    let len = input.u8()? as usize;
+                    Some(VoxelMetadata::new(input.take(len)?).ok_or(DecodeError::InvalidMetadata(index))?)
+                }
+                _ => return Err(DecodeError::InvalidMetadata(index)),
+            };
+            patch.metadata.push((index, metadata));
+        }
+        for _ in 0..input.u16()? {
+            let index = voxel_index(&mut input).map_err(|index| index.map_or(DecodeError::UnexpectedEof, |_| DecodeError::InvalidTints))?;
+            let tint = match input.u8()? {
+                0 => None,
+                1 => Some(<Tint>::try_from(input.take(3)?).unwrap()),
+                _ => return Err(DecodeError::InvalidTints),
+            };
+            patch.tints.push((index, tint));
+        }
+        Ok(patch)
+    }
+}
+
+/// `Err(None)` when the input ends, `Err(Some(index))` for an index outside of the chunk
+fn voxel_index(input: &mut ByteReader) -> Result<u16, Option<u16>> {
+    let index = input.u16().map_err(|_| None)?;
+    match (index as usize) < CHUNK_VOLUME {
+        true => Ok(index),
+        false => Err(Some(index)),
+    }
+}
+
+impl Chunk {
+    /// What has to change to turn this chunk into `other`, the positions of the chunks are not compared.
+    /// Applying the patch drops the metadata and tint of every replaced voxel, so those are listed
+    /// for every changed voxel that has them, even when they stay the same.
+    pub fn diff(&self, other: &Chunk) -> ChunkPatch {
+        let (from, to) = (self.reader(), other.reader());
+        let mut patch = ChunkPatch::default();
+
+        for (index, (old, new)) in from.voxels().iter().zip(to.voxels().iter()).enumerate() {
+            if old != new {
+                patch.voxels.push((index as u16, *new));
+            }
+        }
+        let replaced = |index: u16| patch.voxels.binary_search_by_key(&index, |(changed, _)| *changed).is_ok();
+
+        let mut metadata_indices = from.all_metadata().keys().chain(to.all_metadata().keys()).copied().collect::<Vec<_>>();
+        metadata_indices.sort_unstable();
+        metadata_indices.dedup();
+        let mut metadata = Vec::new();
+        for index in metadata_indices {
+            let (old, new) = (from.all_metadata().get(&index), to.all_metadata().get(&index));
+            if old != new || (new.is_some() && replaced(index)) {
+                metadata.push((index, new.cloned()));
+            }
+        }
+
+        // Both untinted is the common case, skip comparing every voxel then
+        let mut tints = Vec::new();
+        if !(from.tints().is_empty() && to.tints().is_empty()) {
+            for index in 0..CHUNK_VOLUME {
+                let (old, new) = (from.tints().get(index), to.tints().get(index));
+                if old != new || (new.is_some() && replaced(index as u16)) {
+                    tints.push((index as u16, new));
+                }
+            }
+        }
+        patch.metadata = metadata;
+        patch.tints = tints;
+        patch
+    }
+
+    /// Writes the changes of a patch. Voxels are written first, so replacing a voxel drops its
+    /// metadata and tint unless the patch sets them again. `false` if a tint did not fit into the
+    /// chunk's palette, see [`ChunkTints::set`](super::tint::ChunkTints::set).
+    pub fn apply_patch(&mut self, patch: &ChunkPatch, blocks: &BlockRegistry) -> bool {
+        let mut writer = self.writer();
+        let position = |index: u16| {
+            let pos = LocalVoxelPos::from_index(index as usize);
+            (pos.x as usize, pos.y as usize, pos.z as usize)
+        };
+        for (index, voxel) in patch.voxels.iter() {
+            let (x, y, z) = position(*index);
+            writer.set(x, y, z, *voxel);
+        }
+        for (index, metadata) in patch.metadata.iter() {
+            let (x, y, z) = position(*index);
+            writer.set_metadata(x, y, z, metadata.clone());
+        }
+        let mut fits = true;
+        for (index, tint) in patch.tints.iter() {
+            let (x, y, z) = position(*index);
+            fits &= writer.set_tint(x, y, z, *tint);
+        }
+        drop(writer);
+        if !patch.voxels.is_empty() {
+            self.recalculate_visibility_mask(blocks);
+        }
+        fits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{chunk::ChunkPosition, voxel::Block};
+
+    #[test]
+    fn test_diff_then_patch_restores_the_chunk() {
+        let mut original = Chunk::new(ChunkPosition::new(0, 0, 0));
+        original.set(LocalVoxelPos::new(1, 2, 3), Block::Stone.into());
+        original.set_metadata(LocalVoxelPos::new(1, 2, 3), VoxelMetadata::new([7]));
+        let mut edited = original.clone();
+        edited.set(LocalVoxelPos::new(1, 2, 3), Block::Dirt.into());
+        edited.set(LocalVoxelPos::new(15, 15, 15), Block::Glass.into());
+        edited.set_tint(LocalVoxelPos::new(15, 15, 15), Some([10, 20, 30]));
+
+        let patch = original.diff(&edited);
+        // Replacing the stone dropped its metadata
+        assert_eq!(patch.voxels.len(), 2);
+        assert_eq!(patch.metadata, vec![(LocalVoxelPos::new(1, 2, 3).index() as u16, None)]);
+        assert_eq!(patch.tints.len(), 1);
+        assert_eq!(ChunkPatch::decode(&patch.encode(), &BlockRegistry::builtin()), Ok(patch.clone()));
+
+        let mut patched = original.clone();
+        assert!(patched.apply_patch(&patch, &BlockRegistry::builtin()));
+        assert!(patched.diff(&edited).is_empty());
+
+        // The reverse diff undoes the edit
+        assert!(patched.apply_patch(&edited.diff(&original), &BlockRegistry::builtin()));
+        assert!(patched.diff(&original).is_empty());
+        assert_eq!(patched.metadata(LocalVoxelPos::new(1, 2, 3)), VoxelMetadata::new([7]));
+    }
+
+    #[test]
+    fn test_replaced_voxels_keep_their_metadata_and_tint() {
+        let pos = LocalVoxelPos::new(4, 5, 6);
+        let mut original = Chunk::new(ChunkPosition::new(0, 0, 0));
+        original.set(pos, Block::Glass.into());
+        original.set_metadata(pos, VoxelMetadata::new([2, 4]));
+        original.set_tint(pos, Some([90, 180, 30]));
+        let mut edited = original.clone();
+        edited.set(pos, Block::Stone.into());
+        edited.set_metadata(pos, VoxelMetadata::new([2, 4]));
+        edited.set_tint(pos, Some([90, 180, 30]));
+
+        let patch = ChunkPatch::decode(&original.diff(&edited).encode(), &BlockRegistry::builtin()).unwrap();
+        assert_eq!(patch.len(), 3);
+        let mut patched = original.clone();
+        assert!(patched.apply_patch(&patch, &BlockRegistry::builtin()));
+        assert!(patched.diff(&edited).is_empty());
+        assert_eq!(patched.metadata(pos), VoxelMetadata::new([2, 4]));
+        assert_eq!(patched.tint(pos), Some([90, 180, 30]));
+    }
+
+    #[test]
+    fn test_decode_rejects_bad_indices() {
+        let patch = ChunkPatch { voxels: vec![(CHUNK_VOLUME as u16, Voxel::Empty)], ..Default::default() };
+        assert_eq!(ChunkPatch::decode(&patch.encode(), &BlockRegistry::builtin()), Err(DecodeError::InvalidVoxelIndex(CHUNK_VOLUME as u16)));
+        assert_eq!(ChunkPatch::decode(&[1, 0], &BlockRegistry::builtin()), Err(DecodeError::UnexpectedEof));
+        // Metadata with an unknown marker is an error instead of being dropped
+        assert_eq!(ChunkPatch::decode(&[0, 0, 1, 0, 7, 0, 2], &BlockRegistry::builtin()), Err(DecodeError::InvalidMetadata(7)));
+    }
+}
@@ -0,0 +1,248 @@
+//! World <-> chunk coordinate math.
+//!
+//! Plain integer division truncates toward zero, which maps e.g. world x = -1 to chunk 0
+//! instead of chunk -1. Everything in here uses floor division so that negative
+//! coordinates end up in the correct chunk.
+
+use bevy::prelude::Vec3;
+
+use super::{chunk::{ChunkPosition, CHUNK_SIZE}, util::Face};
+
+/// Integer division rounding toward negative infinity.
+pub fn floor_div(value: i32, divisor: i32) -> i32 {
+    value.div_euclid(divisor)
+}
+
+/// Remainder of [`floor_div`], always in `0..divisor` for positive divisors.
+pub fn floor_mod(value: i32, divisor: i32) -> i32 {
+    value.rem_euclid(divisor)
+}
+
+/// Converts a single world axis value to the coordinate of the chunk containing it.
+pub fn world_to_chunk_axis(value: f32) -> i32 {
+    floor_div(value.floor() as i32, CHUNK_SIZE as i32)
+}
+
+/// Converts a world position to the coordinates of the chunk containing it.
+pub fn world_to_chunk(pos: Vec3) -> (i32, i32, i32) {
+    (
+        world_to_chunk_axis(pos.x),
+        world_to_chunk_axis(pos.y),
+        world_to_chunk_axis(pos.z),
+    )
+}
+
+/// Returns the world position of the minimum corner of a chunk.
+pub fn chunk_to_world(x: i32, y: i32, z: i32) -> Vec3 {
+    Vec3::new(
+        x as f32 * CHUNK_SIZE as f32,
+        y as f32 * CHUNK_SIZE as f32,
+        z as f32 * CHUNK_SIZE as f32,
+    )
+}
+
+/// Converts a world position to a position relative to the minimum corner of the given chunk.
+pub fn world_to_local(chunk: (i32, i32, i32), pos: Vec3) -> Vec3 {
+    pos - chunk_to_world(chunk.0, chunk.1, chunk.2)
+}
+
+/// Converts a position relative to the minimum corner of the given chunk to a world position.
+pub fn local_to_world(chunk: (i32, i32, i32), pos: Vec3) -> Vec3 {
+    chunk_to_world(chunk.0, chunk.1, chunk.2) + pos
+}
+
+/// Position of a voxel inside of a chunk, every axis is in `0..CHUNK_SIZE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LocalVoxelPos {
+    pub x: u8,
+    pub y: u8,
+    pub z: u8,
+}
+
+impl LocalVoxelPos {
+    pub fn new(x: u8, y: u8, z: u8) -> Self {
+        debug_assert!(
+            (x as usize) < CHUNK_SIZE && (y as usize) < CHUNK_SIZE && (z as usize) < CHUNK_SIZE,
+            "local voxel position ({}, {}, {}) is outside of the chunk", x, y, z
+        );
+        Self { x, y, z }
+    }
+
+    /// Same as [`LocalVoxelPos::new`], but returns None instead of asserting when out of bounds
+    pub fn try_new(x: i64, y: i64, z: i64) -> Option<Self> {
+        let size = CHUNK_SIZE as i64;
+        if (0..size).contains(&x) && (0..size).contains(&y) && (0..size).contains(&z) {
+            Some(Self { x: x as u8, y: y as u8, z: z as u8 })
+        } else {
+            None
+        }
+    }
+
+    /// Index of this voxel in the chunk's voxel buffer
+    pub fn index(&self) -> usize {
+        self.x as usize + self.y as usize * CHUNK_SIZE + self.z as usize * CHUNK_SIZE * CHUNK_SIZE
+    }
+
+    pub fn from_index(index: usize) -> Self {
+        Self::new(
+            (index % CHUNK_SIZE) as u8,
+            ((index / CHUNK_SIZE) % CHUNK_SIZE) as u8,
+            (index / CHUNK_SIZE / CHUNK_SIZE) as u8,
+        )
+    }
+
+    pub fn as_vec3(&self) -> Vec3 {
+        Vec3::new(self.x as f32, self.y as f32, self.z as f32)
+    }
+
+    /// Iterates over every voxel position of a chunk in buffer order
+    pub fn iter() -> impl Iterator<Item = LocalVoxelPos> {
+        (0..CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE).map(Self::from_index)
+    }
+
+    /// Faces of the chunk this voxel lies on, none for voxels inside of it
+    pub fn border_faces(&self) -> impl Iterator<Item = Face> {
+        let last = CHUNK_SIZE as u8 - 1;
+        [
+            (Face::Left, self.x == 0),
+            (Face::Right, self.x == last),
+            (Face::Bottom, self.y == 0),
+            (Face::Top, self.y == last),
+            (Face::Back, self.z == 0),
+            (Face::Front, self.z == last),
+        ]
+        .into_iter()
+        .filter_map(|(face, on_border)| on_border.then_some(face))
+    }
+}
+
+/// Absolute position of a voxel in the world
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WorldVoxelPos {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+}
+
+impl WorldVoxelPos {
+    pub fn new(x: i64, y: i64, z: i64) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Returns the voxel containing the given world position
+    pub fn from_world(pos: Vec3) -> Self {
+        Self {
+            x: pos.x.floor() as i64,
+            y: pos.y.floor() as i64,
+            z: pos.z.floor() as i64,
+        }
+    }
+
+    pub fn from_local(chunk: &ChunkPosition, local: LocalVoxelPos) -> Self {
+        let size = CHUNK_SIZE as i64;
+        Self {
+            x: chunk.x as i64 * size + local.x as i64,
+            y: chunk.y as i64 * size + local.y as i64,
+            z: chunk.z as i64 * size + local.z as i64,
+        }
+    }
+
+    pub fn chunk(&self) -> ChunkPosition {
+        let size = CHUNK_SIZE as i64;
+        ChunkPosition::new(
+            self.x.div_euclid(size) as i32,
+            self.y.div_euclid(size) as i32,
+            self.z.div_euclid(size) as i32,
+        )
+    }
+
+    pub fn local(&self) -> LocalVoxelPos {
+        let size = CHUNK_SIZE as i64;
+        LocalVoxelPos::new(
+            self.x.rem_euclid(size) as u8,
+            self.y.rem_euclid(size) as u8,
+            self.z.rem_euclid(size) as u8,
+        )
+    }
+
+    /// Splits this position into the chunk containing it and the position inside of that chunk
+    pub fn split(&self) -> (ChunkPosition, LocalVoxelPos) {
+        (self.chunk(), self.local())
+    }
+
+    pub fn offset(&self, x: i64, y: i64, z: i64) -> Self {
+        Self::new(self.x + x, self.y + y, self.z + z)
+    }
+
+    /// World position of the minimum corner of this voxel
+    pub fn as_vec3(&self) -> Vec3 {
+        Vec3::new(self.x as f32, self.y as f32, self.z as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: i32 = CHUNK_SIZE as i32;
+
+    #[test]
+    fn test_floor_div_negative() {
+        assert_eq!(floor_div(-1, SIZE), -1);
+        assert_eq!(floor_div(-SIZE, SIZE), -1);
+        assert_eq!(floor_div(-SIZE - 1, SIZE), -2);
+        assert_eq!(floor_div(0, SIZE), 0);
+        assert_eq!(floor_div(SIZE - 1, SIZE), 0);
+        assert_eq!(floor_div(SIZE, SIZE), 1);
+    }
+
+    #[test]
+    fn test_floor_mod_negative() {
+        assert_eq!(floor_mod(-1, SIZE), SIZE - 1);
+        assert_eq!(floor_mod(-SIZE, SIZE), 0);
+        assert_eq!(floor_mod(SIZE + 3, SIZE), 3);
+    }
+
+    #[test]
+    fn test_world_to_chunk_axis() {
+        assert_eq!(world_to_chunk_axis(-0.5), -1);
+        assert_eq!(world_to_chunk_axis(-1.0), -1);
+        assert_eq!(world_to_chunk_axis(0.0), 0);
+        assert_eq!(world_to_chunk_axis(15.99), 0);
+        assert_eq!(world_to_chunk_axis(16.0), 1);
+        assert_eq!(world_to_chunk_axis(-16.0), -1);
+        assert_eq!(world_to_chunk_axis(-16.01), -2);
+    }
+
+    #[test]
+    fn test_world_to_chunk_roundtrip() {
+        for world in [Vec3::new(-1.0, -17.5, 3.0), Vec3::new(40.25, -0.125, -33.0)] {
+            let chunk = world_to_chunk(world);
+            let local = world_to_local(chunk, world);
+
+            assert!(local.min_element() >= 0.0);
+            assert!(local.max_element() < CHUNK_SIZE as f32);
+            assert_eq!(local_to_world(chunk, local), world);
+        }
+    }
+
+    #[test]
+    fn test_world_voxel_split_negative() {
+        let pos = WorldVoxelPos::from_world(Vec3::new(-0.5, -16.0, 17.2));
+        assert_eq!(pos, WorldVoxelPos::new(-1, -16, 17));
+
+        let (chunk, local) = pos.split();
+        assert_eq!(chunk, ChunkPosition::new(-1, -1, 1));
+        assert_eq!(local, LocalVoxelPos::new(SIZE as u8 - 1, 0, 1));
+        assert_eq!(WorldVoxelPos::from_local(&chunk, local), pos);
+    }
+
+    #[test]
+    fn test_local_voxel_index_roundtrip() {
+        for pos in LocalVoxelPos::iter().step_by(37) {
+            assert_eq!(LocalVoxelPos::from_index(pos.index()), pos);
+        }
+        assert_eq!(LocalVoxelPos::try_new(SIZE as i64, 0, 0), None);
+        assert_eq!(LocalVoxelPos::try_new(-1, 0, 0), None);
+    }
+}
@@ -0,0 +1,221 @@
+//! Critical ring: chunks around and below the camera, and the ones it is about to move into.
+//!
+//! They skip the normal generation queue. Whatever is still missing when they are needed
+//! is restored, loaded or generated on the main thread, so the player can never fall
+//! through terrain that does not exist yet.
+
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashSet};
+
+use super::{
+    anchor::StreamingAnchor,
+    autosave::DirtyChunks,
+    cache::ChunkCache,
+    chunk::{Chunk, ChunkPosition},
+    generator::{
+        begin_chunk_generation, AwaitingGeneration, ChunkGenerationTask, ChunkSource, EmptyChunkMarker, GeneratorState,
+        Generating, MeshState, Meshed, MeshingTask, NeedsMesh, WorldGeneratorConfig,
+    },
+    generation_context::GenerationContext,
+    heightmap::HeightmapCache,
+    meshing::{ChunkBorders, MeshingResources},
+    persistence::{AwaitingLoad, ChunkLoadFailed, ChunkStorage},
+    ChunkData,
+};
+
+#[derive(Resource, Debug, Clone)]
+pub struct CriticalRing {
+    pub enabled: bool,
+    /// Chunks this far from the camera chunk on the x and z axes, at the camera level and one below
+    pub radius: i32,
+    /// Seconds of movement to look ahead
+    pub lookahead: f32,
+    /// Chunks that had to be generated or loaded on the main thread since startup
+    pub sync_generated: u64,
+    /// Chunks that had to be meshed on the main thread since startup
+    pub sync_meshed: u64,
+}
+
+impl Default for CriticalRing {
+    fn default() -> Self {
+        Self { enabled: true, radius: 1, lookahead: 0.5, sync_generated: 0, sync_meshed: 0 }
+    }
+}
+
+impl CriticalRing {
+    /// Chunks that must be present for a camera at `position` moving with `velocity`
+    pub fn chunks(&self, position: Vec3, velocity: Vec3) -> HashSet<ChunkPosition> {
+        let mut chunks = HashSet::default();
+        let mut add_around = |center: ChunkPosition| {
+            for dy in -1..=0 {
+                for dx in -self.radius..=self.radius {
+                    for dz in -self.radius..=self.radius {
+                        chunks.insert(ChunkPosition::new(center.x + dx, center.y + dy, center.z + dz));
+                    }
+                }
+            }
+        };
+        add_around(ChunkPosition::from_world_position(position));
+        add_around(ChunkPosition::from_world_position(position + velocity * self.lookahead));
+        chunks
+    }
+
+    /// The camera chunk and the one below, these are meshed on the main thread if their mesh is late
+    pub fn core(position: Vec3) -> [ChunkPosition; 2] {
+        let center = ChunkPosition::from_world_position(position);
+        [center, ChunkPosition::new(center.x, center.y - 1, center.z)]
+    }
+}
+
+pub struct CriticalRingPlugin;
+
+impl Plugin for CriticalRingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CriticalRing>()
+            .add_systems(Update, ensure_critical_chunks.after(begin_chunk_generation));
+    }
+}
+
+/// Where chunks that are not loaded are restored from, with the record of the ones edited since
+#[derive(SystemParam)]
+struct StoredChunks<'w> {
+    cache: ResMut<'w, ChunkCache>,
+    storage: ResMut<'w, ChunkStorage>,
+    dirty: ResMut<'w, DirtyChunks>,
+}
+
+/// Makes sure every critical chunk exists and has a mesh this frame
+fn ensure_critical_chunks(
+    mut commands: Commands,
+    mut ring: ResMut<CriticalRing>,
+    mut chunk_data: ResMut<ChunkData>,
+    mut stored: StoredChunks,
+    mut heightmap: ResMut<HeightmapCache>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut previous_position: Local<Option<Vec3>>,
+    mut load_failures: EventWriter<ChunkLoadFailed>,
+    config: Res<WorldGeneratorConfig>,
+    generator_state: Res<GeneratorState>,
+    chunk_source: Res<ChunkSource>,
+    time: Res<Time>,
+    camera: Query<&Transform, With<StreamingAnchor>>,
+    chunks_query: Query<(Option<&Chunk>, Option<&MeshingTask>, Has<Meshed>, Has<EmptyChunkMarker>, Has<ChunkGenerationTask>)>,
+    meshing: MeshingResources,
+) {
+    let Some(position) = camera.iter().next().map(|transform| transform.translation) else {
+        return;
+    };
+    let velocity = match *previous_position {
+        Some(previous) if time.delta_seconds() > 0.0 => (position - previous) / time.delta_seconds(),
+        _ => Vec3::ZERO,
+    };
+    *previous_position = Some(position);
+
+    if !ring.enabled || *generator_state == GeneratorState::Paused || *chunk_source == ChunkSource::Remote {
+        return;
+    }
+
+    let core = CriticalRing::core(position);
+    for chunk_pos in ring.chunks(position, velocity) {
+        if config.is_outside_world(&chunk_pos) {
+            continue;
+        }
+
+        if let Some(entity) = chunk_data.loaded.get(&chunk_pos).copied() {
+            if !core.contains(&chunk_pos) {
+                continue;
+            }
+            let Ok((Some(chunk), meshing_task, meshed, is_empty, _)) = chunks_query.get(entity) else {
+                continue;
+            };
+            // Meshes finishing this frame are applied as usual
+            let mesh_pending = meshing_task.map_or(true, |task| match &task.1 {
+                MeshState::Loading(task) => !task.is_finished(),
+                MeshState::Loaded(_) => false,
+            });
+            if !mesh_pending || meshed || is_empty {
+                continue;
+            }
+            // `apply_meshes` picks the finished mesh up like any other
+            let borders = ChunkBorders::capture(&chunk_pos, |neighbour| chunks_query.get(*chunk_data.loaded.get(neighbour)?).ok()?.0);
+            let chunk_meshes = chunk.build_meshes(&meshing.mesher(), config.biome_of(&chunk_pos), &borders);
+            match chunk_meshes.is_empty() {
+                false => {
+                    let handles = chunk_meshes.map(|mesh| meshes.add(mesh));
+                    commands.entity(entity).remove::<NeedsMesh>().insert(MeshingTask(chunk_pos, MeshState::Loaded(handles)))
+                }
+                true => commands.entity(entity).remove::<(NeedsMesh, MeshingTask)>().insert(EmptyChunkMarker),
+            };
+            ring.sync_meshed += 1;
+            continue;
+        }
+
+        // Generation already running for the rest of the ring is close enough to done
+        let in_flight = chunk_data
+            .awaiting_generation
+            .get(&chunk_pos)
+            .is_some_and(|entity| chunks_query.get(*entity).is_ok_and(|(.., generating)| generating));
+        if in_flight && !core.contains(&chunk_pos) {
+            continue;
+        }
+
+        let context = || GenerationContext::capture(chunk_pos, &heightmap, |pos| {
+            let entity = chunk_data.loaded.get(&pos)?;
+            chunks_query.get(*entity).ok()?.0
+        });
+        let (mut chunk, overflow) = if let Some(chunk) = stored.cache.take(&chunk_pos) {
+            (chunk, None)
+        } else {
+            match stored.storage.load_now(chunk_pos) {
+                Ok(Some(chunk)) => (chunk, None),
+                Ok(None) => {
+                    let (chunk, overflow) = config.generate_in(&context());
+                    (chunk, Some(overflow))
+                }
+                Err(err) => {
+                    warn!("Failed to load critical chunk {:?}, generating it instead: {}", chunk_pos, err);
+                    load_failures.send(ChunkLoadFailed { chunk: chunk_pos, error: err });
+                    let (chunk, overflow) = config.generate_in(&context());
+                    (chunk, Some(overflow))
+                }
+            }
+        };
+        if let Some(overflow) = overflow {
+            chunk_data.pending_edits.merge(overflow);
+        }
+        // A restored or loaded chunk may be saved already, the edits are only in it from now on
+        if chunk_data.pending_edits.apply(&mut chunk, meshing.blocks()) {
+            chunk.recalculate_visibility_mask(meshing.blocks());
+            stored.dirty.mark(chunk_pos);
+        }
+        heightmap.record_chunk(&chunk);
+        ring.sync_generated += 1;
+
+        // Takes over the entity queued by the normal path, dropping its generation task cancels it
+        let entity = match chunk_data.awaiting_generation.remove(&chunk_pos) {
+            Some(entity) => entity,
+            None => commands.spawn_empty().id(),
+        };
+        commands.entity(entity)
+            .remove::<(AwaitingGeneration, AwaitingLoad, ChunkGenerationTask, Generating)>()
+            .insert((chunk, NeedsMesh));
+        chunk_data.loaded.insert(chunk_pos, entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_looks_ahead() {
+        let ring = CriticalRing::default();
+        let standing = ring.chunks(Vec3::new(8.0, 8.0, 8.0), Vec3::ZERO);
+        assert_eq!(standing.len(), 18);
+        assert!(standing.contains(&ChunkPosition::new(0, -1, 0)));
+
+        // 64 voxels per second for half a second ends two chunks further along x
+        let moving = ring.chunks(Vec3::new(8.0, 8.0, 8.0), Vec3::new(64.0, 0.0, 0.0));
+        assert_eq!(moving.len(), 30);
+        assert!(moving.contains(&ChunkPosition::new(3, -1, 1)));
+    }
+}
@@ -0,0 +1,112 @@
+//! Frustum tests used to cull chunks.
+//!
+//! Every test takes a margin in world units that grows the tested volume, so chunks right at the
+//! edge of the view are kept a little longer and do not pop in and out while the camera turns.
+//! The far plane is never tested, how far chunks are kept is up to the view distances.
+
+use bevy::{
+    prelude::Vec3,
+    render::primitives::{Aabb, Frustum},
+};
+
+use super::chunk::ChunkPosition;
+
+/// Index of the far plane in [`Frustum::half_spaces`]
+const FAR_PLANE: usize = 5;
+
+/// Signed distances of `point` to the planes of the frustum, positive inside
+fn plane_distances(frustum: &Frustum, point: Vec3) -> impl Iterator<Item = (Vec3, f32)> + '_ {
+    frustum.half_spaces[..FAR_PLANE].iter().map(move |half_space| {
+        let normal = Vec3::from(half_space.normal());
+        (normal, normal.dot(point) + half_space.d())
+    })
+}
+
+/// The sphere is at least partly inside the frustum grown by `margin`
+pub fn sphere_in_frustum(frustum: &Frustum, center: Vec3, radius: f32, margin: f32) -> bool {
+    plane_distances(frustum, center).all(|(_, distance)| distance + radius + margin > 0.0)
+}
+
+/// The box is at least partly inside the frustum grown by `margin`
+pub fn aabb_in_frustum(frustum: &Frustum, aabb: &Aabb, margin: f32) -> bool {
+    let half_extents = Vec3::from(aabb.half_extents);
+    plane_distances(frustum, aabb.center.into()).all(|(normal, distance)| {
+        // Distance from the center to the corner furthest along the plane normal
+        let extent = half_extents.dot(normal.abs());
+        distance + extent + margin > 0.0
+    })
+}
+
+/// Cheaper but looser than [`chunk_in_frustum`], the bounding sphere of a chunk reaches past its corners
+pub fn chunk_sphere_in_frustum(chunk: &ChunkPosition, frustum: &Frustum, margin: f32) -> bool {
+    let aabb = chunk.aabb();
+    sphere_in_frustum(frustum, aabb.center.into(), Vec3::from(aabb.half_extents).length(), margin)
+}
+
+pub fn chunk_in_frustum(chunk: &ChunkPosition, frustum: &Frustum, margin: f32) -> bool {
+    aabb_in_frustum(frustum, &chunk.aabb(), margin)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        prelude::{PerspectiveProjection, Transform},
+        render::camera::CameraProjection,
+    };
+
+    use super::*;
+    use crate::engine::chunk::CHUNK_SIZE;
+
+    /// Camera at the origin looking towards -z with a 45° vertical field of view
+    fn forward_frustum() -> Frustum {
+        let camera = Transform::from_xyz(0.0, 0.0, 0.0).looking_to(Vec3::NEG_Z, Vec3::Y);
+        let projection = PerspectiveProjection { aspect_ratio: 1.0, ..Default::default() };
+        Frustum::from_view_projection(&(projection.get_projection_matrix() * camera.compute_matrix().inverse()))
+    }
+
+    #[test]
+    fn test_sphere_against_camera() {
+        let frustum = forward_frustum();
+        assert!(sphere_in_frustum(&frustum, Vec3::new(0.0, 0.0, -10.0), 1.0, 0.0));
+        assert!(!sphere_in_frustum(&frustum, Vec3::new(0.0, 0.0, 10.0), 1.0, 0.0));
+        // Far away things are kept, there is no far plane
+        assert!(sphere_in_frustum(&frustum, Vec3::new(0.0, 0.0, -1.0e6), 1.0, 0.0));
+
+        // At z = -10 the side planes are about 4.14 from the axis, the sphere is about 0.7 outside of them
+        let beside = Vec3::new(6.0, 0.0, -10.0);
+        assert!(!sphere_in_frustum(&frustum, beside, 1.0, 0.0));
+        assert!(sphere_in_frustum(&frustum, beside, 1.0, 1.0));
+    }
+
+    #[test]
+    fn test_aabb_against_camera() {
+        let frustum = forward_frustum();
+        let aabb = |min: Vec3, max: Vec3| Aabb::from_min_max(min, max);
+        assert!(aabb_in_frustum(&frustum, &aabb(Vec3::new(-1.0, -1.0, -11.0), Vec3::new(1.0, 1.0, -9.0)), 0.0));
+        assert!(!aabb_in_frustum(&frustum, &aabb(Vec3::new(-1.0, -1.0, 9.0), Vec3::new(1.0, 1.0, 11.0)), 0.0));
+        // A box around the camera is always visible
+        assert!(aabb_in_frustum(&frustum, &aabb(Vec3::splat(-1.0), Vec3::splat(1.0)), 0.0));
+
+        // The closest corner of the box is about 0.41 outside of the right plane
+        let beside = aabb(Vec3::new(5.0, -1.0, -11.0), Vec3::new(7.0, 1.0, -9.0));
+        assert!(!aabb_in_frustum(&frustum, &beside, 0.0));
+        assert!(!aabb_in_frustum(&frustum, &beside, 0.3));
+        assert!(aabb_in_frustum(&frustum, &beside, 0.5));
+    }
+
+    #[test]
+    fn test_chunks_against_camera() {
+        let frustum = forward_frustum();
+        assert!(chunk_in_frustum(&ChunkPosition::new(-1, -1, -1), &frustum, 0.0));
+        assert!(chunk_in_frustum(&ChunkPosition::new(-1, -1, -3), &frustum, 0.0));
+        assert!(!chunk_in_frustum(&ChunkPosition::new(0, 0, 2), &frustum, 0.0));
+
+        // The chunk starting beside the view at z = -16 is only kept with a margin
+        let beside = ChunkPosition::new(1, 0, -2);
+        assert!(!chunk_in_frustum(&beside, &frustum, 0.0));
+        assert!(chunk_in_frustum(&beside, &frustum, CHUNK_SIZE as f32));
+        // The bounding sphere is looser than the box
+        assert!(chunk_sphere_in_frustum(&beside, &frustum, 0.0));
+        assert!(!chunk_sphere_in_frustum(&ChunkPosition::new(2, 0, -2), &frustum, 0.0));
+    }
+}
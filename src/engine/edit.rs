@@ -0,0 +1,144 @@
+use bevy::prelude::*;
+
+use super::{block_registry::BlockRegistry, coords::WorldVoxelPos, voxel::Voxel, world_edits::WorldEdits};
+
+/// Reasons why a voxel edit was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditError {
+    /// The voxel at this position can not be removed or replaced, e.g. the bedrock world bottom
+    Unbreakable(WorldVoxelPos),
+    /// The chunk of this position is not loaded, so the edit can not be checked
+    NotLoaded(WorldVoxelPos),
+}
+
+impl std::fmt::Display for EditError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unbreakable(pos) => write!(f, "voxel at ({}, {}, {}) is unbreakable", pos.x, pos.y, pos.z),
+            Self::NotLoaded(pos) => write!(f, "voxel at ({}, {}, {}) is not loaded", pos.x, pos.y, pos.z),
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+/// Checks whether the voxel `existing` at `pos` may be replaced with `new`.
+/// Every system that edits the world should go through this.
+pub fn check_edit(blocks: &BlockRegistry, pos: WorldVoxelPos, existing: Voxel, new: Voxel) -> Result<(), EditError> {
+    if blocks.is_unbreakable(existing) && existing != new {
+        return Err(EditError::Unbreakable(pos));
+    }
+    Ok(())
+}
+
+/// Region covered by a brush edit, see [`WorldEdits::fill`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BrushShape {
+    /// Voxels whose centers are at most `radius` away from the center of `center`
+    Sphere { center: WorldVoxelPos, radius: f32 },
+    /// Every voxel between the two corners, both inclusive
+    Box { min: WorldVoxelPos, max: WorldVoxelPos },
+}
+
+impl BrushShape {
+    /// Box reaching `half_extent` voxels from the center in every direction
+    pub fn cube(center: WorldVoxelPos, half_extent: i64) -> Self {
+        Self::Box {
+            min: center.offset(-half_extent, -half_extent, -half_extent),
+            max: center.offset(half_extent, half_extent, half_extent),
+        }
+    }
+
+    /// Smallest and largest voxel position the brush can touch
+    pub fn bounds(&self) -> (WorldVoxelPos, WorldVoxelPos) {
+        match *self {
+            Self::Sphere { center, radius } => {
+                let r = radius.max(0.0).floor() as i64;
+                (center.offset(-r, -r, -r), center.offset(r, r, r))
+            }
+            Self::Box { min, max } => (
+                WorldVoxelPos::new(min.x.min(max.x), min.y.min(max.y), min.z.min(max.z)),
+                WorldVoxelPos::new(min.x.max(max.x), min.y.max(max.y), min.z.max(max.z)),
+            ),
+        }
+    }
+
+    pub fn contains(&self, pos: WorldVoxelPos) -> bool {
+        match *self {
+            Self::Sphere { center, radius } => {
+                let (dx, dy, dz) = ((pos.x - center.x) as f32, (pos.y - center.y) as f32, (pos.z - center.z) as f32);
+                dx * dx + dy * dy + dz * dz <= radius * radius
+            }
+            Self::Box { .. } => {
+                let (min, max) = self.bounds();
+                (min.x..=max.x).contains(&pos.x) && (min.y..=max.y).contains(&pos.y) && (min.z..=max.z).contains(&pos.z)
+            }
+        }
+    }
+
+    /// Every voxel position inside the brush
+    pub fn positions(&self) -> impl Iterator<Item = WorldVoxelPos> + '_ {
+        let (min, max) = self.bounds();
+        (min.x..=max.x)
+            .flat_map(move |x| (min.y..=max.y).flat_map(move |y| (min.z..=max.z).map(move |z| WorldVoxelPos::new(x, y, z))))
+            .filter(|pos| self.contains(*pos))
+    }
+}
+
+/// Fills a region with a voxel, send [`Voxel::Empty`] to erase. Queued as loose writes on [`WorldEdits`]
+#[derive(Event, Debug, Clone, Copy)]
+pub struct FillRegion {
+    pub shape: BrushShape,
+    pub voxel: Voxel,
+}
+
+pub fn apply_fill_regions(mut edits: ResMut<WorldEdits>, mut events: EventReader<FillRegion>) {
+    for event in events.read() {
+        edits.fill(&event.shape, event.voxel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::engine::{
+        autosave::DirtyChunks,
+        chunk::ChunkPosition,
+        voxel::Block,
+        world_edits::{apply_world_edits, EditRejected},
+        ChunkData,
+    };
+
+    #[test]
+    fn test_sphere_brush() {
+        let center = WorldVoxelPos::new(-1, 5, 0);
+        assert_eq!(BrushShape::Sphere { center, radius: 0.0 }.positions().collect::<Vec<_>>(), vec![center]);
+        assert_eq!(BrushShape::Sphere { center, radius: 1.0 }.positions().count(), 7);
+        assert_eq!(BrushShape::Sphere { center, radius: 1.5 }.positions().count(), 19);
+    }
+
+    #[test]
+    fn test_fill_region_across_chunks() {
+        let mut world = World::new();
+        world.init_resource::<ChunkData>();
+        world.init_resource::<WorldEdits>();
+        world.init_resource::<DirtyChunks>();
+        world.init_resource::<BlockRegistry>();
+        world.init_resource::<Events<EditRejected>>();
+        world.init_resource::<Events<FillRegion>>();
+        // Corners are given in the wrong order on purpose
+        let shape = BrushShape::Box { min: WorldVoxelPos::new(1, 1, 1), max: WorldVoxelPos::new(-1, -1, -1) };
+        world.send_event(FillRegion { shape, voxel: Block::Stone.into() });
+
+        world.run_system_once(apply_fill_regions);
+        assert_eq!(world.resource::<WorldEdits>().len(), 27);
+        world.run_system_once(apply_world_edits);
+        // None of the chunks are loaded, the writes wait for them
+        let chunk_data = world.resource::<ChunkData>();
+        assert_eq!(chunk_data.pending_edits.chunks().count(), 8);
+        assert!(chunk_data.pending_edits.contains(&ChunkPosition::new(-1, -1, -1)));
+        assert!(chunk_data.pending_edits.contains(&ChunkPosition::new(0, 0, 0)));
+    }
+}
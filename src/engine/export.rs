@@ -0,0 +1,193 @@
+//! Wavefront OBJ export of chunk meshes, for looking at terrain in Blender and other tools.
+//!
+//! Every mesh becomes its own object with its transform baked into the vertices.
+
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use bevy::{
+    prelude::*,
+    render::mesh::{Indices, VertexAttributeValues},
+};
+
+use super::{
+    chunk::{Chunk, ChunkPosition, CHUNK_SIZE},
+    coords::{LocalVoxelPos, WorldVoxelPos},
+    generator::WorldGeneratorConfig,
+    meshing::ChunkMesher,
+    voxel::Voxel,
+    ChunkData,
+};
+
+pub const EXPORTS_DIR: &str = "exports";
+
+/// A mesh placed in the world
+pub struct ExportMesh<'a> {
+    pub name: String,
+    pub transform: Transform,
+    pub mesh: &'a Mesh,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExportStats {
+    pub objects: usize,
+    pub vertices: usize,
+    pub triangles: usize,
+}
+
+/// Writes the meshes as one OBJ file, meshes without positions or indices are skipped
+pub fn write_obj(out: &mut impl Write, meshes: &[ExportMesh]) -> io::Result<ExportStats> {
+    let mut stats = ExportStats::default();
+    // OBJ indices are 1 based and global over the whole file
+    let mut first_vertex = 1;
+
+    for export in meshes {
+        let Some(VertexAttributeValues::Float32x3(positions)) = export.mesh.attribute(Mesh::ATTRIBUTE_POSITION) else {
+            continue;
+        };
+        let Some(indices) = export.mesh.indices() else {
+            continue;
+        };
+        let normals = match export.mesh.attribute(Mesh::ATTRIBUTE_NORMAL) {
+            Some(VertexAttributeValues::Float32x3(normals)) if normals.len() == positions.len() => Some(normals),
+            _ => None,
+        };
+
+        writeln!(out, "o {}", export.name)?;
+        for position in positions {
+            let p = export.transform.transform_point(Vec3::from_array(*position));
+            writeln!(out, "v {} {} {}", p.x, p.y, p.z)?;
+        }
+        for normal in normals.into_iter().flatten() {
+            let n = (export.transform.rotation * Vec3::from_array(*normal)).normalize_or_zero();
+            writeln!(out, "vn {} {} {}", n.x, n.y, n.z)?;
+        }
+
+        let indices = match indices {
+            Indices::U16(indices) => indices.iter().map(|i| *i as usize).collect::<Vec<_>>(),
+            Indices::U32(indices) => indices.iter().map(|i| *i as usize).collect::<Vec<_>>(),
+        };
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0] + first_vertex, triangle[1] + first_vertex, triangle[2] + first_vertex];
+            if normals.is_some() {
+                writeln!(out, "f {a}//{a} {b}//{b} {c}//{c}")?;
+            } else {
+                writeln!(out, "f {a} {b} {c}")?;
+            }
+            stats.triangles += 1;
+        }
+
+        first_vertex += positions.len();
+        stats.vertices += positions.len();
+        stats.objects += 1;
+    }
+
+    Ok(stats)
+}
+
+pub fn save_obj(path: &Path, meshes: &[ExportMesh]) -> io::Result<ExportStats> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut out = BufWriter::new(fs::File::create(path)?);
+    let stats = write_obj(&mut out, meshes)?;
+    out.flush()?;
+    Ok(stats)
+}
+
+pub fn chunk_object_name(chunk: &ChunkPosition) -> String {
+    format!("chunk_{}_{}_{}", chunk.x, chunk.y, chunk.z)
+}
+
+/// Copy of the chunk with every voxel outside of the region (both corners inclusive) removed.
+/// `None` if nothing of the chunk is inside.
+pub fn clip_chunk(chunk: &Chunk, min: WorldVoxelPos, max: WorldVoxelPos) -> Option<Chunk> {
+    let chunk_min = WorldVoxelPos::from_local(&chunk.position, LocalVoxelPos::new(0, 0, 0));
+    let chunk_max = chunk_min.offset(CHUNK_SIZE as i64 - 1, CHUNK_SIZE as i64 - 1, CHUNK_SIZE as i64 - 1);
+    if chunk_max.x < min.x || chunk_max.y < min.y || chunk_max.z < min.z
+        || chunk_min.x > max.x || chunk_min.y > max.y || chunk_min.z > max.z
+    {
+        return None;
+    }
+
+    let mut clipped = Chunk::new(chunk.position);
+    clipped.generate_with(|chunk_pos, pos| {
+        let world = WorldVoxelPos::from_local(chunk_pos, pos);
+        let inside = (min.x..=max.x).contains(&world.x) && (min.y..=max.y).contains(&world.y) && (min.z..=max.z).contains(&world.z);
+        if inside { chunk.get(pos) } else { Voxel::Empty }
+    });
+    Some(clipped)
+}
+
+/// Exports the meshes the loaded chunks already have
+pub fn export_loaded_chunks(path: &Path, chunk_data: &ChunkData, meshes: &Assets<Mesh>) -> io::Result<ExportStats> {
+    let exports = chunk_data
+        .meshes
+        .iter()
+        .filter_map(|(chunk_pos, handle)| {
+            Some(ExportMesh {
+                name: chunk_object_name(chunk_pos),
+                transform: chunk_pos.mesh_transform(),
+                mesh: meshes.get(handle)?,
+            })
+        })
+        .collect::<Vec<_>>();
+    save_obj(path, &exports)
+}
+
+/// Meshes the part of each chunk inside the region (corners in any order) again,
+/// so the model ends exactly at the region boundary. Grass is tinted for the biomes of `config`.
+pub fn export_region<'a>(
+    path: &Path,
+    a: WorldVoxelPos,
+    b: WorldVoxelPos,
+    chunks: impl Iterator<Item = &'a Chunk>,
+    mesher: &ChunkMesher,
+    config: &WorldGeneratorConfig,
+) -> io::Result<ExportStats> {
+    let min = WorldVoxelPos::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+    let max = WorldVoxelPos::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+    let clipped = chunks
+        .filter_map(|chunk| clip_chunk(chunk, min, max))
+        .filter_map(|chunk| Some((chunk.position, chunk.build_in(mesher, config.biome_of(&chunk.position))?)))
+        .collect::<Vec<_>>();
+    let exports = clipped
+        .iter()
+        .map(|(chunk_pos, mesh)| ExportMesh {
+            name: chunk_object_name(chunk_pos),
+            transform: chunk_pos.mesh_transform(),
+            mesh,
+        })
+        .collect::<Vec<_>>();
+    save_obj(path, &exports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::render::render_resource::PrimitiveTopology;
+
+    #[test]
+    fn test_write_obj_applies_transform() {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0.0f32, 0.0, 1.0]; 3]);
+        mesh.set_indices(Some(Indices::U32(vec![0, 1, 2])));
+
+        let meshes = [
+            ExportMesh { name: "a".to_string(), transform: Transform::from_xyz(16.0, 0.0, 0.0), mesh: &mesh },
+            ExportMesh { name: "b".to_string(), transform: Transform::IDENTITY, mesh: &mesh },
+        ];
+        let mut out = Vec::new();
+        let stats = write_obj(&mut out, &meshes).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        assert_eq!(stats, ExportStats { objects: 2, vertices: 6, triangles: 2 });
+        assert!(text.contains("v 17 0 0"));
+        assert!(text.contains("f 1//1 2//2 3//3"));
+        assert!(text.contains("f 4//4 5//5 6//6"));
+    }
+}
@@ -0,0 +1,131 @@
+//! Read-only view of the world around a chunk that is being generated.
+//!
+//! Generation runs on the async compute pool, so the context is a snapshot taken when the
+//! task is spawned: surface heights already known around the chunk and the border layers
+//! of neighbouring chunks that are loaded at that time.
+
+use bevy::utils::HashMap;
+
+use super::{
+    chunk::{Chunk, ChunkPosition, CHUNK_SIZE},
+    coords::{LocalVoxelPos, WorldVoxelPos},
+    generator::WorldGeneratorConfig,
+    heightmap::HeightmapCache,
+    util::Face,
+    voxel::Voxel,
+};
+
+/// Columns this far around the chunk are included in the height snapshot
+pub const HEIGHT_MARGIN: i64 = CHUNK_SIZE as i64;
+
+const FACES: [Face; 6] = [Face::Left, Face::Right, Face::Bottom, Face::Top, Face::Back, Face::Front];
+
+#[derive(Debug, Clone)]
+pub struct GenerationContext {
+    pub chunk: ChunkPosition,
+    /// Known surface heights of the chunk columns and [`HEIGHT_MARGIN`] around them
+    heights: HashMap<(i64, i64), i64>,
+    /// Layer of each neighbour touching this chunk, indexed by [`Face::as_face_number`]
+    borders: [Option<Vec<Voxel>>; 6],
+}
+
+impl GenerationContext {
+    /// Context without any knowledge of the surroundings
+    pub fn empty(chunk: ChunkPosition) -> Self {
+        Self { chunk, heights: HashMap::default(), borders: Default::default() }
+    }
+
+    /// Takes a snapshot of the heightmap and of the neighbours `neighbor` returns
+    pub fn capture<'a>(chunk: ChunkPosition, heightmap: &HeightmapCache, neighbor: impl Fn(ChunkPosition) -> Option<&'a Chunk>) -> Self {
+        let mut context = Self::empty(chunk);
+
+        let origin = WorldVoxelPos::from_local(&chunk, LocalVoxelPos::new(0, 0, 0));
+        for x in origin.x - HEIGHT_MARGIN..origin.x + CHUNK_SIZE as i64 + HEIGHT_MARGIN {
+            for z in origin.z - HEIGHT_MARGIN..origin.z + CHUNK_SIZE as i64 + HEIGHT_MARGIN {
+                if let Some(height) = heightmap.get(x, z) {
+                    context.heights.insert((x, z), height);
+                }
+            }
+        }
+
+        for ((neighbor_pos, face), border) in chunk.neighbors().into_iter().zip(context.borders.iter_mut()) {
+            let Some(neighbor) = neighbor(neighbor_pos) else {
+                continue;
+            };
+            let reader = neighbor.reader();
+            *border = Some(
+                (0..CHUNK_SIZE * CHUNK_SIZE)
+                    .map(|i| {
+                        let (x, y, z) = border_to_local(face.opposite(), i / CHUNK_SIZE, i % CHUNK_SIZE);
+                        *reader.get(x, y, z)
+                    })
+                    .collect(),
+            );
+        }
+
+        context
+    }
+
+    /// Surface height of a column, from the snapshot or from the generator if it was not known yet
+    pub fn surface_height(&self, config: &WorldGeneratorConfig, x: i64, z: i64) -> Option<i64> {
+        self.heights.get(&(x, z)).copied().or_else(|| config.generator.surface_height(config, x, z))
+    }
+
+    /// Surface height known before generation started, without asking the generator
+    pub fn known_height(&self, x: i64, z: i64) -> Option<i64> {
+        self.heights.get(&(x, z)).copied()
+    }
+
+    pub fn has_neighbor(&self, face: Face) -> bool {
+        self.borders[face.as_face_number()].is_some()
+    }
+
+    /// Voxel of the neighbour on `face` touching this chunk. `a` and `b` are the two remaining axes
+    /// in x, y, z order, e.g. `(x, z)` for [`Face::Top`]. `None` if the neighbour was not loaded.
+    pub fn neighbor_voxel(&self, face: Face, a: usize, b: usize) -> Option<Voxel> {
+        self.borders[face.as_face_number()].as_ref().map(|border| border[a * CHUNK_SIZE + b])
+    }
+
+    /// Every face with a loaded neighbour
+    pub fn neighbor_faces(&self) -> impl Iterator<Item = Face> + '_ {
+        FACES.into_iter().filter(|face| self.has_neighbor(*face))
+    }
+}
+
+/// Local position of the voxel at `(a, b)` in the layer of a chunk on the given face
+fn border_to_local(face: Face, a: usize, b: usize) -> (usize, usize, usize) {
+    let last = CHUNK_SIZE - 1;
+    match face {
+        Face::Left => (0, a, b),
+        Face::Right => (last, a, b),
+        Face::Bottom => (a, 0, b),
+        Face::Top => (a, last, b),
+        Face::Back => (a, b, 0),
+        Face::Front => (a, b, last),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::voxel::Block;
+
+    #[test]
+    fn test_capture_neighbor_borders() {
+        let chunk = ChunkPosition::new(0, 0, 0);
+        let mut above = Chunk::new(ChunkPosition::new(0, 1, 0));
+        // Bottom layer of the chunk above touches the top of this one
+        above.set(LocalVoxelPos::new(3, 0, 5), Voxel::from(Block::Stone));
+        above.set(LocalVoxelPos::new(3, 1, 5), Voxel::from(Block::Dirt));
+
+        let mut heightmap = HeightmapCache::default();
+        heightmap.record_chunk(&above);
+        let context = GenerationContext::capture(chunk, &heightmap, |pos| (pos == above.position).then_some(&above));
+
+        assert_eq!(context.neighbor_faces().collect::<Vec<_>>(), vec![Face::Top]);
+        assert_eq!(context.neighbor_voxel(Face::Top, 3, 5), Some(Voxel::from(Block::Stone)));
+        assert_eq!(context.neighbor_voxel(Face::Top, 5, 3), Some(Voxel::Empty));
+        assert_eq!(context.neighbor_voxel(Face::Bottom, 3, 5), None);
+        assert_eq!(context.known_height(3, 5), Some(17));
+    }
+}
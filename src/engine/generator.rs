@@ -1,55 +1,434 @@
-use std::{collections::VecDeque, sync::Arc};
+use std::{collections::VecDeque, sync::Arc, time::Instant};
 
-use bevy::{prelude::*, utils::HashSet, tasks::{Task, AsyncComputeTaskPool, block_on}, core::FrameCount, render::primitives::Frustum};
+use bevy::{prelude::*, hierarchy::despawn_with_children_recursive, pbr::NotShadowCaster, utils::HashSet, tasks::{Task, AsyncComputeTaskPool, block_on}, core::FrameCount, render::primitives::Frustum};
 
-use super::{chunk::{Chunk, ChunkPosition}, voxel::Voxel, ChunkData, util::intersects_frustum};
+use super::{autosave::DirtyChunks, biome::Biome, chunk::{Chunk, ChunkPosition, CHUNK_SIZE}, voxel::{Voxel, Block, BlockId}, block_registry::BlockRegistry, meshing::{border_voxels, ChunkBorders, ChunkMesher, ChunkMeshes, ChunkVertexFormat, MeshingResources, MeshingStrategy}, shapes::ShapeRegistry, util::Face, ChunkData, culling::chunk_in_frustum, load_policy::LoadPolicy, coords::{LocalVoxelPos, WorldVoxelPos}, cache::ChunkCache, pending_edits::PendingEdits, persistence::{ChunkStorage, ChunkLoadFailed, IoResponse, AwaitingLoad}, heightmap::HeightmapCache, chunk_material::ChunkMaterials, generation_context::GenerationContext, rng::ChunkRng, edit::{apply_fill_regions, FillRegion}, world_edits::{apply_world_edits, EditRejected, WorldEdits}, generators::{test_pattern::TestPatternWorldGenerator, surface::{SurfaceLayers, SurfacePainter}, caves::CaveCarver, density::DensityWorldGenerator, dungeons::DungeonGenerator, villages::VillageGenerator}, spawn_queue::{ChunkSpawnQueue, flush_chunk_spawns, flush_chunk_despawns}, memory_budget::{MemoryBudget, MemoryUsage, select_evictions}, chunk_index::{ChunkIndex, update_chunk_index}, streaming_budget::{StreamingBudget, update_streaming_budget}, anchor::{StreamingAnchor, anchor_ranges, in_range_of_any}, chunk_log::{self, ChunkLogLevel, GENERATION_TARGET, MESHING_TARGET, GC_TARGET, STREAMING_TARGET}};
 
-#[derive(Resource, Clone)]
+/// The generator and stages are not reflected, the rest can be tuned in the inspector
+#[derive(Resource, Clone, Reflect)]
+#[reflect(from_reflect = false)]
 pub struct WorldGeneratorConfig {
+    /// Shapes the terrain, this is the first stage of the pipeline
+    #[reflect(ignore)]
     pub generator: Arc<dyn WorldGenerator>,
+    /// Stages running after the terrain shape, always kept sorted by [`Stage`]
+    #[reflect(ignore)]
+    pub stages: Vec<Arc<dyn GenerationStage>>,
+    /// World seed for everything that is not part of the terrain shape, see [`WorldGeneratorConfig::chunk_rng`]
+    pub seed: u64,
     pub render_distance: usize,
     /// Chunks at this distance will be generated but not meshed
     pub generation_distance: usize,
+    /// Lowest y level of the world, it is filled with unbreakable bedrock and nothing is generated below it.
+    /// `None` means the world goes down forever.
+    pub world_bottom: Option<i32>,
+    /// Chunks are only generated this many chunks around the world origin on x and z, the world
+    /// is a square `2 * world_border` chunks wide. `None` means the world goes on forever sideways.
+    pub world_border: Option<u32>,
+    /// Empty voxels below this y level are filled with water, giving lakes and oceans. Set by the
+    /// `water` stage of the generator name, so it is stored with the world.
+    pub sea_level: Option<i32>,
+    /// World units the view frustum is grown by when looking for visible chunks, see [`culling`](super::culling)
+    pub frustum_margin: f32,
+    /// Shape of the region streamed in around every anchor
+    pub load_policy: LoadPolicy,
+    /// Blocks of the world, generator names are resolved and generated chunks are checked with them
+    #[reflect(ignore)]
+    pub blocks: BlockRegistry,
 }
 
 impl WorldGeneratorConfig {
     pub fn default_flat() -> Self {
         Self {
             generator: Arc::new(FlatWorldGenerator::default()),
+            stages: Vec::new(),
+            seed: 0,
             render_distance: 16,
             generation_distance: 18,
+            world_bottom: Some(-64),
+            world_border: None,
+            sea_level: None,
+            frustum_margin: 4.0,
+            load_policy: LoadPolicy::Sphere,
+            blocks: BlockRegistry::builtin(),
         }
     }
 
+    /// Superflat world built from a layer spec string naming `blocks`, see [`LayerSpec`]
+    pub fn superflat(spec: &str, blocks: &BlockRegistry) -> Result<Self, LayerSpecError> {
+        Ok(Self { blocks: blocks.clone(), ..Self::default_with(FlatWorldGenerator::from_spec(spec, 0, blocks)?) })
+    }
+
+    /// Builds a config from a generator name as stored in world metadata:
+    /// `perlin[=<params>]`, `flat`, `superflat=<layer spec>`, `density[=<params>]` or `test-pattern`, optionally followed
+    /// by stages separated with `+`, e.g. `perlin+surface+caves+dungeons`. Stages taking parameters
+    /// are written like generators, e.g. `perlin+layers=snow_line:40`. `water[=<sea level>]` sets the
+    /// [`WorldGeneratorConfig::sea_level`], 0 if left out
+    pub fn from_generator_name(name: &str, seed: u32, blocks: &BlockRegistry) -> Result<Self, String> {
+        let mut parts = name.split('+').map(str::trim);
+        let shape = parts.next().unwrap_or_default();
+        let mut config = if let Some(spec) = shape.strip_prefix("superflat=") {
+            Self::superflat(spec, blocks).map_err(|err| err.to_string())?
+        } else if let Some(params) = shape.strip_prefix("perlin=") {
+            Self::default_with(PerlinHeightmapWorldGenerator::from_params(params, seed)?)
+        } else if let Some(params) = shape.strip_prefix("density=") {
+            Self::default_with(DensityWorldGenerator::from_params(params, seed)?)
+        } else {
+            match shape {
+                "perlin" => Self::default_with(PerlinHeightmapWorldGenerator { seed, ..Default::default() }),
+                "flat" => Self::default_with(FlatWorldGenerator::default()),
+                "density" => Self::default_with(DensityWorldGenerator { seed, ..Default::default() }),
+                "test-pattern" => Self::default_with(TestPatternWorldGenerator),
+                other => return Err(format!("unknown generator `{}`", other)),
+            }
+        };
+        config.seed = seed as u64;
+        config.blocks = blocks.clone();
+        for stage in parts {
+            if let Some(params) = stage.strip_prefix("layers=") {
+                config = config.with_stage(SurfaceLayers::from_params(params)?);
+                continue;
+            }
+            if let Some(level) = stage.strip_prefix("water=") {
+                config.sea_level = Some(level.trim().parse().map_err(|err| format!("invalid sea level `{}`: {}", level, err))?);
+                continue;
+            }
+            config = match stage {
+                "surface" => config.with_stage(SurfacePainter::default()),
+                "layers" => config.with_stage(SurfaceLayers::default()),
+                "water" => WorldGeneratorConfig { sea_level: Some(0), ..config },
+                "caves" => config.with_stage(CaveCarver { seed, ..Default::default() }),
+                "dungeons" => config.with_stage(DungeonGenerator::default()),
+                "villages" => config.with_stage(VillageGenerator::default()),
+                other => return Err(format!("unknown generation stage `{}`", other)),
+            };
+        }
+        Ok(config)
+    }
+
+    /// Takes the view distances and streaming settings of `previous`, they are user settings
+    /// and stay the same when the world or its generator changes
+    pub fn with_view_settings_of(self, previous: &Self) -> Self {
+        Self {
+            render_distance: previous.render_distance,
+            generation_distance: previous.generation_distance,
+            frustum_margin: previous.frustum_margin,
+            load_policy: previous.load_policy,
+            ..self
+        }
+    }
+
+    /// Random numbers for one feature of one chunk, the same for every run with the same seed
+    /// regardless of the order chunks are generated in. Use a distinct `salt` per feature.
+    pub fn chunk_rng(&self, chunk: ChunkPosition, salt: u64) -> ChunkRng {
+        ChunkRng::for_chunk(self.seed, chunk, salt)
+    }
+
+    /// Dominant biome of a chunk, its mesh is tinted for it
+    pub fn biome_of(&self, chunk: &ChunkPosition) -> Biome {
+        Biome::dominant(self.seed, chunk)
+    }
+
+    /// Adds a stage to the pipeline, it runs after every stage of the same or an earlier [`Stage`]
+    pub fn with_stage(mut self, stage: impl GenerationStage + 'static) -> Self {
+        let index = self.stages.partition_point(|existing| existing.stage() <= stage.stage());
+        self.stages.insert(index, Arc::new(stage));
+        self
+    }
+
     pub fn default_with(generator: impl WorldGenerator + 'static) -> Self {
         Self {
             generator: Arc::new(generator),
+            stages: Vec::new(),
+            seed: 0,
             render_distance: 16,
             generation_distance: 18,
+            world_bottom: Some(-64),
+            world_border: None,
+            sea_level: None,
+            frustum_margin: 4.0,
+            load_policy: LoadPolicy::Sphere,
+            blocks: BlockRegistry::builtin(),
         }
     }
+
+    /// Runs the whole pipeline for a single chunk without knowing anything about its surroundings,
+    /// see [`WorldGeneratorConfig::generate_in`]
+    pub fn generate(&self, chunk_pos: ChunkPosition) -> (Chunk, PendingEdits) {
+        self.generate_in(&GenerationContext::empty(chunk_pos))
+    }
+
+    /// Runs the whole pipeline for a single chunk: terrain shape, water, every stage in order and the world bottom.
+    /// Returns the chunk together with edits that overflowed into other chunks.
+    pub fn generate_in(&self, context: &GenerationContext) -> (Chunk, PendingEdits) {
+        let mut chunk = Chunk::new(context.chunk);
+        let mut overflow = PendingEdits::default();
+        match self.generator.chunk_fill(self, &context.chunk) {
+            ChunkFill::Empty => (),
+            ChunkFill::Solid(voxel) => chunk.fill(voxel),
+            ChunkFill::Mixed => self.generator.generate_chunk(self, context, &mut chunk),
+        }
+        self.apply_sea_level(&mut chunk);
+        for stage in self.stages.iter() {
+            stage.apply(self, context, &mut chunk, &mut overflow);
+        }
+        // Stages may write into their own chunk through the overflow buffer as well
+        overflow.apply(&mut chunk, &self.blocks);
+        self.apply_world_bottom(&mut chunk);
+        chunk.compact();
+        chunk.recalculate_visibility_mask(&self.blocks);
+        (chunk, overflow)
+    }
+
+    /// Returns whether the chunk lies completely below the world bottom
+    pub fn is_below_world(&self, chunk: &ChunkPosition) -> bool {
+        match self.world_bottom {
+            Some(bottom) => (chunk.y + 1) * CHUNK_SIZE as i32 <= bottom,
+            None => false,
+        }
+    }
+
+    /// Returns whether the chunk lies outside of the world border
+    pub fn is_beyond_border(&self, chunk: &ChunkPosition) -> bool {
+        match self.world_border {
+            Some(border) => {
+                let border = border as i64;
+                let inside = |axis: i32| (-border..border).contains(&(axis as i64));
+                !(inside(chunk.x) && inside(chunk.z))
+            }
+            None => false,
+        }
+    }
+
+    /// Returns whether the chunk is never generated, being below the world bottom or beyond the border
+    pub fn is_outside_world(&self, chunk: &ChunkPosition) -> bool {
+        self.is_below_world(chunk) || self.is_beyond_border(chunk)
+    }
+
+    /// Fills every empty voxel below the sea level with water
+    pub fn apply_sea_level(&self, chunk: &mut Chunk) {
+        let Some(sea_level) = self.sea_level else {
+            return;
+        };
+        let chunk_min_y = chunk.position.y as i64 * CHUNK_SIZE as i64;
+        let below = (sea_level as i64 - chunk_min_y).clamp(0, CHUNK_SIZE as i64) as usize;
+        if below == 0 {
+            return;
+        }
+
+        for pos in LocalVoxelPos::iter().filter(|pos| (pos.y as usize) < below) {
+            if chunk.get(pos).is_empty() {
+                chunk.set(pos, Voxel::from(Block::Water));
+            }
+        }
+    }
+
+    /// Replaces the world bottom layer with bedrock and clears everything below it
+    pub fn apply_world_bottom(&self, chunk: &mut Chunk) {
+        let Some(bottom) = self.world_bottom else {
+            return;
+        };
+        let chunk_min_y = chunk.position.y as i64 * CHUNK_SIZE as i64;
+        if chunk_min_y > bottom as i64 {
+            return;
+        }
+
+        let mut writer = chunk.writer();
+        for y in 0..CHUNK_SIZE {
+            let voxel = match (chunk_min_y + y as i64).cmp(&(bottom as i64)) {
+                std::cmp::Ordering::Less => Voxel::Empty,
+                std::cmp::Ordering::Equal => Voxel::from(Block::Bedrock),
+                std::cmp::Ordering::Greater => break,
+            };
+            for x in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    writer.set(x, y, z, voxel);
+                }
+            }
+        }
+    }
+}
+
+/// What a generator knows about a chunk without generating it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkFill {
+    /// Every voxel is empty, e.g. the chunk is above anything the terrain reaches
+    Empty,
+    /// Every voxel is this one, e.g. the chunk is below the lowest the surface goes
+    Solid(Voxel),
+    /// The chunk has to be generated to know
+    Mixed,
 }
 
+/// Shapes the terrain, the first stage of the generation pipeline
 pub trait WorldGenerator: Send + Sync {
-    fn generate_chunk(&self, config: &WorldGeneratorConfig, chunk: &mut Chunk);
+    fn generate_chunk(&self, config: &WorldGeneratorConfig, context: &GenerationContext, chunk: &mut Chunk);
+
+    /// Chunks that are provably [`ChunkFill::Empty`] or [`ChunkFill::Solid`] are stored as a single
+    /// voxel instead of being generated. Stages still run on them.
+    fn chunk_fill(&self, _config: &WorldGeneratorConfig, _chunk: &ChunkPosition) -> ChunkFill {
+        ChunkFill::Mixed
+    }
+
+    /// Height of the highest solid voxel in a column, if the generator can compute it without generating chunks
+    fn surface_height(&self, _config: &WorldGeneratorConfig, _x: i64, _z: i64) -> Option<i64> {
+        None
+    }
+}
+
+/// Pipeline stages running after the terrain shape, in this order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    /// Replaces blocks of the raw terrain, e.g. grass on top and biome specific blocks
+    Surface,
+    /// Removes terrain, e.g. caves and ravines
+    Carving,
+    /// Places structures
+    Decoration,
+}
+
+/// Step of the generation pipeline, see [`WorldGeneratorConfig::with_stage`]
+pub trait GenerationStage: Send + Sync {
+    fn stage(&self) -> Stage;
+
+    /// Voxels belonging to other chunks should be written to `overflow`, they are applied when those chunks generate.
+    fn apply(&self, config: &WorldGeneratorConfig, context: &GenerationContext, chunk: &mut Chunk, overflow: &mut PendingEdits);
+}
+
+/// Error returned when a layer spec string can not be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayerSpecError {
+    UnknownBlock(String),
+    InvalidCount(String),
+}
+
+impl std::fmt::Display for LayerSpecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownBlock(name) => write!(f, "unknown block `{}` in layer spec", name),
+            Self::InvalidCount(layer) => write!(f, "invalid layer count in `{}`", layer),
+        }
+    }
+}
+
+impl std::error::Error for LayerSpecError {}
+
+/// Stack of block layers, ordered from the bottom to the top.
+/// Parsed from strings like `"1×bedrock, 10×stone, 3×dirt, grass"` (`x` and `*` work as well), a
+/// layer without a count is one block high. Counts after the block name, `"stone×10"`, are read too.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LayerSpec {
+    pub layers: Vec<(BlockId, u32)>,
+}
+
+impl LayerSpec {
+    pub fn total_height(&self) -> i32 {
+        self.layers.iter().map(|(_, count)| *count as i32).sum()
+    }
+
+    /// Returns the block at the given height above the bottom of the stack
+    pub fn block_at(&self, height: i32) -> Option<BlockId> {
+        if height < 0 {
+            return None;
+        }
+
+        let mut top = 0;
+        for (block, count) in self.layers.iter() {
+            top += *count as i32;
+            if height < top {
+                return Some(*block);
+            }
+        }
+        None
+    }
+
+    /// Block names are looked up in `blocks`
+    pub fn parse(spec: &str, blocks: &BlockRegistry) -> Result<Self, LayerSpecError> {
+        let mut layers = Vec::new();
+        for layer in spec.split(',').map(str::trim).filter(|layer| !layer.is_empty()) {
+            let (name, count) = Self::split_layer(layer, blocks)?;
+            let block = blocks.id(name).ok_or_else(|| LayerSpecError::UnknownBlock(name.trim().to_string()))?;
+            layers.push((block, count));
+        }
+        Ok(Self { layers })
+    }
+
+    /// Block name and count of a single layer. A leading count ends at the first separator after
+    /// it, so block names containing `x` are left whole.
+    fn split_layer<'a>(layer: &'a str, blocks: &BlockRegistry) -> Result<(&'a str, u32), LayerSpecError> {
+        let is_separator = |c: char| c == '×' || c == '*' || c == 'x' || c == 'X';
+        let digits = layer.find(|c: char| !c.is_ascii_digit()).unwrap_or(layer.len());
+        if digits > 0 {
+            if let Some(name) = layer[digits..].trim_start().strip_prefix(is_separator) {
+                let count = layer[..digits].parse::<u32>().map_err(|_| LayerSpecError::InvalidCount(layer.to_string()))?;
+                return Ok((name, count));
+            }
+        }
+        if blocks.id(layer).is_some() {
+            return Ok((layer, 1));
+        }
+        match layer.rfind(is_separator) {
+            Some(index) => {
+                let separator_len = layer[index..].chars().next().unwrap().len_utf8();
+                let count = layer[index + separator_len..].trim();
+                let count = count.parse::<u32>().map_err(|_| LayerSpecError::InvalidCount(layer.to_string()))?;
+                Ok((&layer[..index], count))
+            }
+            None => Ok((layer, 1)),
+        }
+    }
 }
 
+/// Flat world. Without layers everything below `ground_level` is stone,
+/// otherwise the layer stack is placed so that its top ends at `ground_level`.
 #[derive(Default)]
 pub struct FlatWorldGenerator {
     pub ground_level: i32,
+    pub layers: LayerSpec,
+}
+
+impl FlatWorldGenerator {
+    pub fn from_spec(spec: &str, ground_level: i32, blocks: &BlockRegistry) -> Result<Self, LayerSpecError> {
+        Ok(Self {
+            ground_level,
+            layers: LayerSpec::parse(spec, blocks)?,
+        })
+    }
 }
 
 impl WorldGenerator for FlatWorldGenerator {
-    fn generate_chunk(&self, _config: &WorldGeneratorConfig, chunk: &mut Chunk) {
+    fn generate_chunk(&self, _config: &WorldGeneratorConfig, _context: &GenerationContext, chunk: &mut Chunk) {
+        let bottom = self.ground_level - self.layers.total_height();
         chunk.generate_with(|chunk_pos, pos| {
-            let world_pos = chunk_pos.inner_to_world_position(pos);
-            if world_pos.y < self.ground_level as f32 {
-                Voxel::NonEmpty { is_opaque: true }
-            } else {
-                Voxel::Empty
+            let world_pos = WorldVoxelPos::from_local(chunk_pos, pos);
+            if world_pos.y >= self.ground_level as i64 {
+                return Voxel::Empty;
+            }
+            if self.layers.layers.is_empty() {
+                return Voxel::from(Block::Stone);
+            }
+            match self.layers.block_at((world_pos.y - bottom as i64) as i32) {
+                Some(block) => Voxel::from(block),
+                None => Voxel::Empty,
             }
         })
     }
+
+    fn surface_height(&self, _config: &WorldGeneratorConfig, _x: i64, _z: i64) -> Option<i64> {
+        Some(self.ground_level as i64 - 1)
+    }
+
+    fn chunk_fill(&self, _config: &WorldGeneratorConfig, chunk: &ChunkPosition) -> ChunkFill {
+        let (min_y, max_y) = chunk_y_range(chunk);
+        let bottom = (self.ground_level - self.layers.total_height()) as i64;
+        if min_y >= self.ground_level as i64 || (max_y < bottom && !self.layers.layers.is_empty()) {
+            ChunkFill::Empty
+        } else if max_y < self.ground_level as i64 && self.layers.layers.is_empty() {
+            ChunkFill::Solid(Voxel::from(Block::Stone))
+        } else {
+            ChunkFill::Mixed
+        }
+    }
 }
 
 pub struct PerlinHeightmapWorldGenerator {
@@ -70,24 +449,74 @@ impl Default for PerlinHeightmapWorldGenerator {
     }
 }
 
+impl PerlinHeightmapWorldGenerator {
+    /// Parses `scale:<f64>,height:<f64>,ground_level:<i32>`, every key is optional
+    pub fn from_params(params: &str, seed: u32) -> Result<Self, String> {
+        let mut generator = Self { seed, ..Default::default() };
+        for param in params.split(',').map(str::trim).filter(|param| !param.is_empty()) {
+            let (key, value) = param.split_once(':').ok_or_else(|| format!("expected `key:value`, got `{}`", param))?;
+            let value = value.trim().parse::<f64>().map_err(|err| format!("invalid value for `{}`: {}", key, err))?;
+            match key.trim() {
+                "scale" => generator.scale = value,
+                "height" => generator.height = value,
+                "ground_level" => generator.ground_level = value as i32,
+                other => return Err(format!("unknown perlin parameter `{}`", other)),
+            }
+        }
+        if generator.scale <= 0.0 {
+            return Err("scale must be positive".to_string());
+        }
+        Ok(generator)
+    }
+
+    fn height_at(&self, noise: &noise::Perlin, x: i64, z: i64) -> f64 {
+        use noise::NoiseFn;
+        noise.get([
+            (x as f64) / self.scale,
+            (z as f64) / self.scale,
+        ]) * self.height + self.ground_level as f64
+    }
+}
+
 impl WorldGenerator for PerlinHeightmapWorldGenerator {
-    fn generate_chunk(&self, _config: &WorldGeneratorConfig, chunk: &mut Chunk) {
-        use noise::{NoiseFn, Perlin};
+    fn generate_chunk(&self, _config: &WorldGeneratorConfig, _context: &GenerationContext, chunk: &mut Chunk) {
+        use noise::Perlin;
         let my_noise = Arc::new(Perlin::new(self.seed));
 
         chunk.generate_with(|chunk_pos, pos| {
-            let world_pos = chunk_pos.inner_to_world_position(pos);
-            let height = my_noise.get([
-                (world_pos.x as f64) / self.scale,
-                (world_pos.z as f64) / self.scale,
-            ]) * self.height + self.ground_level as f64;
-            if world_pos.y < height as f32 {
-                Voxel::NonEmpty { is_opaque: true }
+            let world_pos = WorldVoxelPos::from_local(chunk_pos, pos);
+            let height = self.height_at(&my_noise, world_pos.x, world_pos.z);
+            if (world_pos.y as f64) < height {
+                Voxel::from(Block::Stone)
             } else {
                 Voxel::Empty
             }
         })
     }
+
+    fn surface_height(&self, _config: &WorldGeneratorConfig, x: i64, z: i64) -> Option<i64> {
+        // Voxels are solid strictly below the noise height
+        let height = self.height_at(&noise::Perlin::new(self.seed), x, z);
+        Some(height.ceil() as i64 - 1)
+    }
+
+    fn chunk_fill(&self, _config: &WorldGeneratorConfig, chunk: &ChunkPosition) -> ChunkFill {
+        // Perlin noise stays within -1..=1
+        let (min_y, max_y) = chunk_y_range(chunk);
+        if min_y as f64 >= self.ground_level as f64 + self.height.abs() {
+            ChunkFill::Empty
+        } else if (max_y as f64) < self.ground_level as f64 - self.height.abs() {
+            ChunkFill::Solid(Voxel::from(Block::Stone))
+        } else {
+            ChunkFill::Mixed
+        }
+    }
+}
+
+/// Lowest and highest y level of the voxels of a chunk
+pub fn chunk_y_range(chunk: &ChunkPosition) -> (i64, i64) {
+    let min_y = chunk.y as i64 * CHUNK_SIZE as i64;
+    (min_y, min_y + CHUNK_SIZE as i64 - 1)
 }
 
 #[derive(Resource, Debug, PartialEq, Eq, Clone, Copy)]
@@ -96,26 +525,120 @@ pub enum GeneratorState {
     Paused,
 }
 
+/// Where chunks that are not loaded yet come from
+#[derive(Resource, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ChunkSource {
+    /// Restored from the cache, loaded from disk or generated locally
+    #[default]
+    Local,
+    /// Received from a server, chunks awaiting generation are left to the network client
+    Remote,
+}
+
+/// Stops issuing generation tasks while meshing lags behind, so generated chunks don't pile up unmeshed.
+/// Uses two thresholds so generation does not flip on and off every frame.
+#[derive(Resource, Debug, Clone)]
+pub struct GenerationBackpressure {
+    /// Generation is throttled once this many visible chunks wait for a mesh
+    pub pause_threshold: usize,
+    /// and resumes once the backlog drops to this
+    pub resume_threshold: usize,
+    /// Visible chunks that are loaded but not meshed yet
+    pub backlog: usize,
+    pub peak_backlog: usize,
+    pub throttled: bool,
+    /// Frames generation spent throttled since startup
+    pub throttled_frames: u64,
+}
+
+impl Default for GenerationBackpressure {
+    fn default() -> Self {
+        Self {
+            pause_threshold: 256,
+            resume_threshold: 128,
+            backlog: 0,
+            peak_backlog: 0,
+            throttled: false,
+            throttled_frames: 0,
+        }
+    }
+}
+
+impl GenerationBackpressure {
+    pub fn update(&mut self, backlog: usize) {
+        self.backlog = backlog;
+        self.peak_backlog = self.peak_backlog.max(backlog);
+        if backlog >= self.pause_threshold {
+            self.throttled = true;
+        } else if backlog <= self.resume_threshold {
+            self.throttled = false;
+        }
+        if self.throttled {
+            self.throttled_frames += 1;
+        }
+    }
+}
+
 pub struct ChunkGeneratorPlugin;
 
 impl Plugin for ChunkGeneratorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(ChunkStreamingPlugin);
+
+        #[cfg(feature = "debug-ui")]
+        app.add_systems(Update, show_chunk_generation_debug_info);
+        #[cfg(feature = "debug-ui")]
+        app.insert_resource(ChunkGenerationStatsDebugTimeseries::new(100));
+    }
+}
+
+/// The streaming systems of [`ChunkGeneratorPlugin`] without any UI, so they can run headless.
+/// Expects the world resources inserted by [`super::ChunkPlugin`]: the config, storage, caches and [`ChunkData`].
+pub struct ChunkStreamingPlugin;
+
+impl Plugin for ChunkStreamingPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(GeneratorState::Generating);
+        app.init_resource::<ChunkSource>();
+        app.init_resource::<GenerationBackpressure>();
+        app.init_resource::<ChunkSpawnQueue>();
+        app.init_resource::<MemoryBudget>();
+        app.init_resource::<ChunkIndex>();
+        app.init_resource::<StreamingBudget>();
+        app.init_resource::<VisibilityRefresh>();
+        app.init_resource::<WorldEdits>();
+        app.init_resource::<BlockRegistry>();
+        app.init_resource::<ShapeRegistry>();
+        app.init_resource::<MeshingStrategy>();
+        app.init_resource::<ChunkVertexFormat>();
+        app.init_resource::<ChunkLogLevel>();
+        app.add_event::<FillRegion>();
+        app.add_event::<EditRejected>();
+        app.add_event::<ChunkLoadFailed>();
+        app.add_systems(First, update_streaming_budget);
         app.add_systems(Update, (
             update_visible_chunks,
-            begin_chunk_generation.after(update_visible_chunks),
+            flush_chunk_spawns.after(update_visible_chunks),
+            measure_mesh_backlog.after(update_visible_chunks),
+            begin_chunk_generation.after(measure_mesh_backlog),
             update_generated_chunks,
+            receive_loaded_chunks,
+            apply_fill_regions,
+            apply_pending_edits_to_loaded_chunks.after(update_generated_chunks),
             unload_invisible_chunks,
+            remesh_translucent_borders.before(schedule_chunk_meshing),
             schedule_chunk_meshing,
             apply_meshes,
         ));
         
-        app.add_systems(PostUpdate, garbage_collect_chunks);
-
-        #[cfg(debug_assertions)]
-        app.add_systems(Update, show_chunk_generation_debug_info);
+        app.add_systems(PostUpdate, (
+            apply_world_edits.before(garbage_collect_chunks),
+            update_chunk_index,
+            garbage_collect_chunks.after(update_chunk_index),
+            flush_chunk_despawns.after(garbage_collect_chunks),
+        ));
         #[cfg(debug_assertions)]
-        app.insert_resource(ChunkGenerationStatsDebugTimeseries::new(100));
+        app.add_systems(Last, validate_chunk_states);
     }
 }
 
@@ -124,75 +647,142 @@ pub struct AwaitingGeneration {
     pub chunk_pos: ChunkPosition,
 }
 
-/// Updates visible chunks based on the player's position.
-pub fn update_visible_chunks(
-    mut commands: Commands,
-    mut chunk_data: ResMut<ChunkData>,
-    config: Res<WorldGeneratorConfig>,
-    camera_query: Query<(&Transform, &Projection), With<Camera>>,
-    chunks_query: Query<(Entity, &Chunk)>,
-    generator_state: Res<GeneratorState>,
-    unmeshed_chunks_query: Query<Entity, (Without<Handle<Mesh>>, With<Chunk>)>,
-    frustum: Query<&Frustum, With<Camera>>,
-) {
-    if *generator_state == GeneratorState::Paused {
-        return;
+/// What the visibility search knows about a chunk, see [`find_visible_chunks`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkLookup {
+    /// Not loaded yet, the search only goes through it close to the camera
+    Missing,
+    /// Loaded, with its [`Chunk::visibility_mask`]
+    Loaded(u8),
+    /// Known to be loaded but its voxels are not available, the search stops there
+    Unavailable,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct VisibleChunks {
+    /// Every visible chunk in the order the search reached it, closest first
+    pub order: Vec<ChunkPosition>,
+    pub set: HashSet<ChunkPosition>,
+}
+
+impl VisibleChunks {
+    fn insert(&mut self, chunk: ChunkPosition) {
+        self.order.push(chunk);
+        self.set.insert(chunk);
+    }
+
+    /// Adds the chunks only `other` has, after the ones already in here
+    pub fn merge(&mut self, other: VisibleChunks) {
+        for chunk in other.order {
+            if !self.set.contains(&chunk) {
+                self.insert(chunk);
+            }
+        }
     }
+}
 
-    let camera = camera_query.single();
-    let camera_position = camera.0.translation;
-    let camera_forward = camera.0.forward();
+/// Decides when the visibility search has to run again. While the anchors stand still and no chunks
+/// are loaded or unloaded the last result is kept, the search still runs every [`Self::max_interval`]
+/// seconds to pick up edits that changed chunk visibility masks.
+#[derive(Resource, Debug, Clone)]
+pub struct VisibilityRefresh {
+    /// Camera rotation since the last search that triggers a new one, in degrees
+    pub max_angle: f32,
+    /// Longest time between two searches, in seconds
+    pub max_interval: f32,
+    last: Option<LastVisibilitySearch>,
+    forced: bool,
+}
 
-    let mut queue = VecDeque::new();
+/// Where an anchor was and where it looked, see [`VisibilityRefresh`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnchorView {
+    pub anchor: Entity,
+    pub chunk: ChunkPosition,
+    pub forward: Vec3,
+}
+
+#[derive(Debug, Clone)]
+struct LastVisibilitySearch {
+    views: Vec<AnchorView>,
+    loaded: usize,
+    time: f32,
+}
+
+impl Default for VisibilityRefresh {
+    fn default() -> Self {
+        Self { max_angle: 10.0, max_interval: 0.5, last: None, forced: false }
+    }
+}
+
+impl VisibilityRefresh {
+    /// Runs the search in the next frame no matter what, for when the camera view changes in other ways
+    pub fn invalidate(&mut self) {
+        self.forced = true;
+    }
 
-    let current_chunk = ChunkPosition::from_world_position(camera_position);
-    let camera_chunk_position = current_chunk.clone();
-    queue.push_back((current_chunk, None));
+    /// Whether the last search result is out of date for the anchors seeing `views`
+    pub fn is_stale(&self, views: &[AnchorView], loaded: usize, time: f32) -> bool {
+        let Some(last) = &self.last else {
+            return true;
+        };
+        let has_moved = |view: &AnchorView| {
+            let Some(previous) = last.views.iter().find(|previous| previous.anchor == view.anchor) else {
+                return true;
+            };
+            previous.chunk != view.chunk || previous.forward.angle_between(view.forward).to_degrees() > self.max_angle
+        };
+        self.forced
+            || last.views.len() != views.len()
+            || last.loaded != loaded
+            || views.iter().any(has_moved)
+            || time - last.time >= self.max_interval
+    }
+
+    pub fn searched(&mut self, views: Vec<AnchorView>, loaded: usize, time: f32) {
+        self.last = Some(LastVisibilitySearch { views, loaded, time });
+        self.forced = false;
+    }
+}
 
-    let mut already_seen: HashSet<ChunkPosition> = HashSet::default();
-    already_seen.insert(current_chunk);
+/// Breadth first search for the chunks visible from the camera.
+/// Goes from chunk to chunk through faces that are not opaque, towards the camera direction
+/// and only to chunks inside the frustum and the [`LoadPolicy`] with `generation_distance` around the camera.
+pub fn find_visible_chunks(
+    config: &WorldGeneratorConfig,
+    generation_distance: usize,
+    camera: &Transform,
+    frustum: &Frustum,
+    lookup: impl Fn(&ChunkPosition) -> ChunkLookup,
+) -> VisibleChunks {
+    let camera_position = camera.translation;
+    let camera_forward = camera.forward();
+
+    let mut queue = VecDeque::new();
+    let mut visible = VisibleChunks::default();
+
+    let camera_chunk_position = ChunkPosition::from_world_position(camera_position);
+    queue.push_back((camera_chunk_position, None));
+    // The search still starts here when the camera is beyond the border, only nothing is generated there
+    if !config.is_beyond_border(&camera_chunk_position) {
+        visible.insert(camera_chunk_position);
+    }
 
     // Add all immediate neighbors to the queue
-    for (neighbor, face) in current_chunk.neighbors().iter() {
+    for (neighbor, face) in camera_chunk_position.neighbors().iter() {
         queue.push_back((*neighbor, Some(face.opposite())));
-        already_seen.insert(*neighbor);
+        if !config.is_beyond_border(neighbor) {
+            visible.insert(*neighbor);
+        }
     }
 
-    let frustum = frustum.single();
-
     while let Some((chunk_pos, from_face)) = queue.pop_front() {
-        // Get chunk if it exists
-        let current_chunk = chunk_data.loaded.get(&chunk_pos).map(|entity| *entity);
-        if current_chunk.is_none() {
-            // If chunk does not exist, queue it for generation
-            if !chunk_data.awaiting_generation.contains_key(&chunk_pos) {
-                let id = commands.spawn((AwaitingGeneration { chunk_pos },)).id();
-                chunk_data.awaiting_generation.insert(chunk_pos, id);
-            }
+        let visibility_mask = match lookup(&chunk_pos) {
             // Exception: If chunk is close enough to the player, treat it as if it is loaded
-            if camera_chunk_position.distance_to(&chunk_pos) > 2.5 {
-                continue;
-            }
-        } else {
-            // If chunk is loaded, check whether we have meshed it yet
-            if chunk_data.meshes.contains_key(&chunk_pos) {
-                // If chunk was not visible before, add mesh we already have
-                if let Ok(entity) = unmeshed_chunks_query.get(current_chunk.unwrap()) {
-                    let mesh_handle = chunk_data.meshes.get(&chunk_pos);
-                    commands.entity(entity).try_insert(mesh_handle.unwrap().clone());
-                }
-            }
-        }
-
-        let current_chunk = if current_chunk.is_some() {
-            let current_chunk = chunks_query.get(current_chunk.unwrap());
-            if current_chunk.is_err() {
-                continue;
-            }
-
-            Some(current_chunk.unwrap())
-        } else {
-            None
+            ChunkLookup::Missing if camera_chunk_position.distance_to(&chunk_pos) > 2.5 => continue,
+            ChunkLookup::Missing => None,
+            ChunkLookup::Loaded(mask) => Some(mask),
+            ChunkLookup::Unavailable => continue,
         };
 
         // Queue all neighbors
@@ -209,92 +799,393 @@ pub fn update_visible_chunks(
             }
 
             // Filter 2: Check if we can see the chunk using visibility mask
-            if current_chunk.is_some() && current_chunk.unwrap().1.is_face_opaque(*face) {
+            if visibility_mask.is_some_and(|mask| mask & (0b1 << face.as_face_number()) != 0) {
                 continue;
             }
 
-            // Filter 3: Check if we are within generation distance
-            if camera_chunk_position.distance_to(&neighbor) > config.generation_distance as f32 {
+            // Filter 3: Check if we are within generation distance, in the shape of the load policy
+            if !config.load_policy.contains(&camera_chunk_position, camera_forward, neighbor, generation_distance) {
                 continue;
             }
 
             // Filter 4: Ensure we have not already seen this chunk
-            if already_seen.contains(neighbor) {
+            if visible.set.contains(neighbor) {
                 continue;
             }
 
             // Filter 5: Check if chunk is in frustum
-            if !intersects_frustum(neighbor, &frustum) {
+            if !chunk_in_frustum(neighbor, frustum, config.frustum_margin) {
+                continue;
+            }
+
+            // Filter 6: There is nothing below the world bottom or beyond the border
+            if config.is_outside_world(neighbor) {
                 continue;
             }
 
             // If we pass all filters, queue the chunk
             queue.push_back((*neighbor, Some(face.opposite())));
-            already_seen.insert(*neighbor);
+            visible.insert(*neighbor);
+        }
+    }
+    visible
+}
+
+/// Updates visible chunks based on the positions of the [`StreamingAnchor`] cameras.
+pub fn update_visible_chunks(
+    mut commands: Commands,
+    mut chunk_data: ResMut<ChunkData>,
+    mut spawn_queue: ResMut<ChunkSpawnQueue>,
+    config: Res<WorldGeneratorConfig>,
+    anchors: Query<(Entity, &Transform, &Frustum, &StreamingAnchor)>,
+    chunks_query: Query<&Chunk>,
+    generator_state: Res<GeneratorState>,
+    unmeshed_chunks_query: Query<Entity, (With<Meshed>, Without<Handle<Mesh>>)>,
+    mut budget: ResMut<StreamingBudget>,
+    mut refresh: ResMut<VisibilityRefresh>,
+    frame_count: Res<FrameCount>,
+    time: Res<Time>,
+) {
+    if *generator_state == GeneratorState::Paused || frame_count.0 % budget.visibility_interval() != 0 {
+        return;
+    }
+    if anchors.is_empty() {
+        return;
+    }
+    let views = anchors
+        .iter()
+        .map(|(anchor, transform, _, _)| AnchorView { anchor, chunk: ChunkPosition::from_world_position(transform.translation), forward: transform.forward() })
+        .collect::<Vec<_>>();
+    let now = time.elapsed_seconds();
+    if !refresh.is_stale(&views, chunk_data.loaded.len(), now) {
+        return;
+    }
+    let started = Instant::now();
+
+    let lookup = |chunk_pos: &ChunkPosition| match chunk_data.loaded.get(chunk_pos) {
+        None => ChunkLookup::Missing,
+        Some(entity) => chunks_query.get(*entity).map_or(ChunkLookup::Unavailable, |chunk| ChunkLookup::Loaded(chunk.visibility_mask)),
+    };
+    let mut visible = VisibleChunks::default();
+    for (entity, transform, frustum, anchor) in anchors.iter() {
+        let _span = info_span!(target: STREAMING_TARGET, "find_visible_chunks", anchor = ?entity).entered();
+        visible.merge(find_visible_chunks(&config, anchor.generation_distance(&config), transform, frustum, lookup));
+    }
+
+    let _span = info_span!(target: STREAMING_TARGET, "queue_visible_chunks", visible = visible.order.len()).entered();
+    for chunk_pos in visible.order.iter() {
+        match chunk_data.loaded.get(chunk_pos) {
+            // If chunk does not exist, queue it for generation
+            None => {
+                if !chunk_data.awaiting_generation.contains_key(chunk_pos) {
+                    spawn_queue.queue_spawn(*chunk_pos);
+                }
+            }
+            // If chunk was not visible before, add mesh we already have
+            Some(entity) => {
+                if let (Some(mesh_handle), Ok(entity)) = (chunk_data.meshes.get(chunk_pos), unmeshed_chunks_query.get(*entity)) {
+                    commands.entity(entity).try_insert(mesh_handle.clone());
+                }
+            }
         }
     }
 
     // Yup, this number is not arbitrary at all
-    if chunk_data.visible.len() > 7 && already_seen.len() == 7 {
+    if chunk_data.visible.len() > 7 && visible.set.len() == 7 {
+        budget.record(started);
         return; // TODO: This is a hacky fix, find a better way to do this
     }
-    chunk_data.visible = already_seen;
+    chunk_data.visible = visible.set;
+    refresh.searched(views, chunk_data.loaded.len(), now);
+    budget.record(started);
+}
+
+/// Counts visible chunks waiting for a mesh, see [`GenerationBackpressure`]
+pub fn measure_mesh_backlog(
+    mut backpressure: ResMut<GenerationBackpressure>,
+    chunk_data: Res<ChunkData>,
+    unmeshed: Query<&Chunk, Or<(With<NeedsMesh>, With<MeshingTask>)>>,
+) {
+    let backlog = unmeshed.iter().filter(|chunk| chunk_data.visible.contains(&chunk.position)).count();
+    backpressure.update(backlog);
 }
 
 #[derive(Component)]
-pub struct ChunkGenerationTask(pub Task<Chunk>);
+pub struct ChunkGenerationTask(pub Task<(Chunk, PendingEdits)>);
 /// Generates chunks that are awaiting generation
 pub fn begin_chunk_generation(
     mut commands: Commands,
+    mut chunk_data: ResMut<ChunkData>,
+    mut chunk_cache: ResMut<ChunkCache>,
+    mut storage: ResMut<ChunkStorage>,
+    mut dirty_chunks: ResMut<DirtyChunks>,
     config: Res<WorldGeneratorConfig>,
     query: Query<(Entity, &AwaitingGeneration)>,
     generator_state: Res<GeneratorState>,
+    chunk_source: Res<ChunkSource>,
+    backpressure: Res<GenerationBackpressure>,
+    heightmap: Res<HeightmapCache>,
+    chunks: Query<&Chunk>,
+    mut budget: ResMut<StreamingBudget>,
+    blocks: Res<BlockRegistry>,
+    log: Res<ChunkLogLevel>,
 ) {
-    if *generator_state == GeneratorState::Paused {
+    if *generator_state == GeneratorState::Paused || *chunk_source == ChunkSource::Remote {
         return;
     }
+    let started = Instant::now();
 
     let task_pool = AsyncComputeTaskPool::get();
+    let mut remaining_starts = budget.limit(budget.generation_starts_per_frame);
 
     for (entity, awaiting_generation) in query.iter() {
         let chunk_pos = awaiting_generation.chunk_pos;
-        let chunk = Chunk::new(chunk_pos);
-        let config = config.clone();
+
+        // Recently unloaded chunks can be restored without generating them again
+        if let Some(mut chunk) = chunk_cache.take(&chunk_pos) {
+            // The cached chunk may be saved already, the edits are only in it from now on
+            if chunk_data.pending_edits.apply(&mut chunk, &blocks) {
+                chunk.recalculate_visibility_mask(&blocks);
+                dirty_chunks.mark(chunk_pos);
+            }
+            commands.entity(entity)
+                .insert((chunk, NeedsMesh))
+                .remove::<(AwaitingGeneration, Generating)>();
+            chunk_data.loaded.insert(chunk_pos, entity);
+            chunk_data.awaiting_generation.remove(&chunk_pos);
+            if log.logs_chunks() {
+                info!(target: GENERATION_TARGET, chunk = ?chunk_pos, "restored from cache");
+            }
+            continue;
+        }
+
+        // Chunks stored on disk are loaded by the IO thread instead, unless their file could not be read
+        if storage.is_saved(&chunk_pos) && !storage.is_unreadable(&chunk_pos) {
+            if storage.request_load(chunk_pos) {
+                commands.entity(entity)
+                    .insert(AwaitingLoad { chunk_pos })
+                    .remove::<AwaitingGeneration>();
+            }
+            continue;
+        }
+
+        // Restoring is cheap, generating has to wait until meshing catches up and for the frame budget
+        if backpressure.throttled || remaining_starts == 0 {
+            continue;
+        }
+        remaining_starts -= 1;
+
+        let context = info_span!(target: GENERATION_TARGET, "capture_context", chunk = ?chunk_pos).in_scope(|| {
+            GenerationContext::capture(chunk_pos, &heightmap, |pos| {
+                chunk_data.loaded.get(&pos).and_then(|entity| chunks.get(*entity).ok())
+            })
+        });
+        let (config, log) = (config.clone(), *log);
+        let span = info_span!(target: GENERATION_TARGET, "generate_chunk", chunk = ?chunk_pos);
         let task = task_pool.spawn(async move {
-            let mut clone = chunk.clone();
-            config.generator.generate_chunk(&config, &mut clone);
-            clone.recalculate_visibility_mask();
-            clone
+            let _span = span.enter();
+            let started = Instant::now();
+            let generated = config.generate_in(&context);
+            if log.logs_chunks() {
+                info!(target: GENERATION_TARGET, chunk = ?chunk_pos, duration_ms = chunk_log::millis(started.elapsed()), "generated");
+            }
+            generated
         });
         commands.entity(entity)
             .insert(ChunkGenerationTask(task))
             .remove::<AwaitingGeneration>();
     }
+    budget.record(started);
 }
 
 /// Updates chunks that have finished generating
 pub fn update_generated_chunks(
     mut commands: Commands,
     mut chunk_data: ResMut<ChunkData>,
+    mut heightmap: ResMut<HeightmapCache>,
     mut query: Query<(Entity, &mut ChunkGenerationTask)>,
     generator_state: Res<GeneratorState>,
+    mut budget: ResMut<StreamingBudget>,
+    blocks: Res<BlockRegistry>,
+    log: Res<ChunkLogLevel>,
 ) {
     if *generator_state == GeneratorState::Paused {
         return;
     }
+    let started = Instant::now();
 
+    let limit = budget.limit(budget.generated_per_frame);
+    let mut remaining = limit;
     for (entity, mut task) in query.iter_mut() {
-        if let Some(chunk) = block_on(futures_lite::future::poll_once(&mut task.0)) {
+        if remaining == 0 {
+            break;
+        }
+        if let Some((mut chunk, overflow)) = block_on(futures_lite::future::poll_once(&mut task.0)) {
+            remaining -= 1;
             let chunk_pos = chunk.position;
 
+            // Apply edits other chunks left for this one before it gets meshed
+            chunk_data.pending_edits.merge(overflow);
+            if chunk_data.pending_edits.apply(&mut chunk, &blocks) {
+                chunk.recalculate_visibility_mask(&blocks);
+            }
+            heightmap.record_chunk(&chunk);
+
             let id = commands.entity(entity)
-                .remove::<ChunkGenerationTask>()
-                .insert(chunk).id();
+                .remove::<(ChunkGenerationTask, Generating)>()
+                .insert((chunk, NeedsMesh)).id();
 
             chunk_data.loaded.insert(chunk_pos, id);
             chunk_data.awaiting_generation.remove(&chunk_pos);
         }
     }
+    if remaining < limit && log.logs_summary() {
+        info!(target: GENERATION_TARGET, count = limit - remaining, duration_ms = chunk_log::millis(started.elapsed()), "inserted generated chunks");
+    }
+    budget.record(started);
+}
+
+/// Inserts chunks loaded by the IO thread. Chunks that could not be loaded are generated instead.
+pub fn receive_loaded_chunks(
+    mut commands: Commands,
+    mut chunk_data: ResMut<ChunkData>,
+    mut storage: ResMut<ChunkStorage>,
+    mut load_failures: EventWriter<ChunkLoadFailed>,
+    mut dirty_chunks: ResMut<DirtyChunks>,
+    blocks: Res<BlockRegistry>,
+) {
+    storage.pump();
+
+    for response in storage.poll() {
+        match response {
+            IoResponse::Loaded(mut chunk) => {
+                let chunk_pos = chunk.position;
+                // The chunk might have been forgotten while it was loading
+                let Some(entity) = chunk_data.awaiting_generation.get(&chunk_pos).copied() else {
+                    continue;
+                };
+                // The edits are only in the chunk from now on, not in its file
+                if chunk_data.pending_edits.apply(&mut chunk, &blocks) {
+                    chunk.recalculate_visibility_mask(&blocks);
+                    dirty_chunks.mark(chunk_pos);
+                }
+                commands.entity(entity)
+                    .remove::<(AwaitingLoad, Generating)>()
+                    .insert((chunk, NeedsMesh));
+                chunk_data.loaded.insert(chunk_pos, entity);
+                chunk_data.awaiting_generation.remove(&chunk_pos);
+            }
+            IoResponse::Missing(chunk_pos) | IoResponse::LoadFailed(chunk_pos, _) => {
+                if let IoResponse::LoadFailed(_, err) = response {
+                    warn!("Failed to load chunk {:?}, generating it instead: {}", chunk_pos, err);
+                    load_failures.send(ChunkLoadFailed { chunk: chunk_pos, error: err });
+                }
+                if let Some(entity) = chunk_data.awaiting_generation.get(&chunk_pos) {
+                    commands.entity(*entity)
+                        .remove::<AwaitingLoad>()
+                        .insert(AwaitingGeneration { chunk_pos });
+                }
+            }
+            IoResponse::SaveFailed(chunk_pos, err) => {
+                error!("Failed to save chunk {:?}: {}", chunk_pos, err);
+            }
+        }
+    }
+}
+
+/// Applies pending edits targeting chunks that are already loaded, e.g. structures
+/// overflowing from a chunk that generated after its neighbours
+pub fn apply_pending_edits_to_loaded_chunks(
+    mut commands: Commands,
+    mut chunk_data: ResMut<ChunkData>,
+    mut dirty_chunks: ResMut<DirtyChunks>,
+    mut chunks_query: Query<&mut Chunk>,
+    blocks: Res<BlockRegistry>,
+) {
+    if chunk_data.pending_edits.is_empty() {
+        return;
+    }
+
+    let targets = chunk_data.pending_edits.chunks()
+        .filter_map(|chunk_pos| chunk_data.loaded.get(chunk_pos).map(|entity| (*chunk_pos, *entity)))
+        .collect::<Vec<_>>();
+
+    for (chunk_pos, entity) in targets {
+        // The chunk component might not be inserted yet, try again next frame
+        let Ok(mut chunk) = chunks_query.get_mut(entity) else {
+            continue;
+        };
+        if chunk_data.pending_edits.apply(&mut chunk, &blocks) {
+            dirty_chunks.mark(chunk_pos);
+            chunk.recalculate_visibility_mask(&blocks);
+            request_remesh(&mut commands, &mut chunk_data, entity, chunk_pos);
+        }
+    }
+}
+
+/// Drops the current mesh of a loaded chunk so it gets meshed again. Neighbours without a mesh are
+/// checked again as well, an edit can open up a chunk that was buried.
+pub fn request_remesh(commands: &mut Commands, chunk_data: &mut ChunkData, entity: Entity, chunk_pos: ChunkPosition) {
+    chunk_data.meshes.remove(&chunk_pos);
+    commands.entity(entity)
+        .remove::<(Handle<Mesh>, Meshed, MeshingTask, EmptyChunkMarker)>()
+        .insert(NeedsMesh);
+    commands.add(move |world: &mut World| despawn_translucent_mesh(world, entity));
+    for (neighbor, _) in chunk_pos.neighbors() {
+        if let Some(mut neighbor_entity) = chunk_data.loaded.get(&neighbor).and_then(|entity| commands.get_entity(*entity)) {
+            neighbor_entity.add(|mut entity: EntityWorldMut| {
+                if entity.take::<EmptyChunkMarker>().is_some() {
+                    entity.insert(NeedsMesh);
+                }
+            });
+        }
+    }
+}
+
+/// Whether translucent voxels of `chunk` on `face` were meshed against the neighbour there, they
+/// have to be meshed again when it changes
+pub fn has_translucent_border(chunk: &Chunk, face: Face, blocks: &BlockRegistry) -> bool {
+    border_voxels(chunk, face).any(|voxel| blocks.is_translucent(voxel))
+}
+
+/// Meshes the water next to chunks that just arrived again, it was meshed with a wall towards them
+pub fn remesh_translucent_borders(
+    mut commands: Commands,
+    mut chunk_data: ResMut<ChunkData>,
+    added: Query<&Chunk, Added<Chunk>>,
+    chunks: Query<(&Chunk, Has<Meshed>, Has<MeshingTask>)>,
+    blocks: Res<BlockRegistry>,
+) {
+    let mut stale = HashSet::new();
+    for chunk in added.iter() {
+        for (neighbour_pos, face) in chunk.position.neighbors() {
+            let Some(&entity) = chunk_data.loaded.get(&neighbour_pos) else {
+                continue;
+            };
+            let Ok((neighbour, meshed, meshing)) = chunks.get(entity) else {
+                continue;
+            };
+            if (meshed || meshing) && has_translucent_border(neighbour, face.opposite(), &blocks) && border_voxels(chunk, face).any(|voxel| !voxel.is_empty()) {
+                stale.insert((neighbour_pos, entity));
+            }
+        }
+    }
+    for (chunk_pos, entity) in stale {
+        request_remesh(&mut commands, &mut chunk_data, entity, chunk_pos);
+    }
+}
+
+/// Drops the mesh of every loaded chunk so they are all meshed again, e.g. with another
+/// [`MeshingStrategy`](super::meshing::MeshingStrategy)
+pub fn remesh_all_chunks(world: &mut World) {
+    let entities = world.resource::<ChunkData>().loaded.values().copied().collect::<Vec<_>>();
+    world.resource_mut::<ChunkData>().meshes.clear();
+    for entity in entities {
+        if let Some(mut chunk) = world.get_entity_mut(entity).filter(|entity| entity.contains::<Chunk>()) {
+            chunk.remove::<(Handle<Mesh>, Meshed, MeshingTask, EmptyChunkMarker)>().insert(NeedsMesh);
+            despawn_translucent_mesh(world, entity);
+        }
+    }
 }
 
 /// Removes chunks that should no longer be loaded
@@ -309,7 +1200,7 @@ pub fn unload_invisible_chunks(
     }
 
     for (entity, chunk) in chunks_query.iter() {
-        if !chunk_data.visible.contains(&chunk.position) {
+        if !chunk_data.visible.contains(&chunk.position) && !chunk_data.is_pinned(&chunk.position) {
             // commands.entity(entity).despawn();
             commands.entity(entity).remove::<Handle<Mesh>>();
             // chunk_data.loaded.remove(&chunk.position);
@@ -321,47 +1212,150 @@ pub fn unload_invisible_chunks(
 }
 
 pub enum MeshState {
-    /// A mesh that has been loaded from memory
-    Loaded(Handle<Mesh>),
-    /// A mesh that is currently being loaded
-    Loading(Task<Option<Mesh>>),
+    /// Meshes that have been loaded from memory
+    Loaded(ChunkMeshes<Handle<Mesh>>),
+    /// Meshes that are currently being loaded
+    Loading(Task<ChunkMeshes>),
 }
 #[derive(Component)]
 pub struct MeshingTask(pub ChunkPosition, pub MeshState);
+/// A chunk without a mesh because there is nothing to draw, every voxel is empty or the chunk is
+/// [buried](is_buried)
 #[derive(Component)]
 pub struct EmptyChunkMarker;
 
+/// A chunk entity whose voxels are not there yet, it is waiting for generation, the disk or the server
+#[derive(Component)]
+pub struct Generating;
+
+/// A chunk with voxels but without a mesh or a [`MeshingTask`], [`schedule_chunk_meshing`] picks it up
+#[derive(Component)]
+pub struct NeedsMesh;
+
+/// A chunk whose mesh is in [`ChunkData::meshes`], the mesh handle itself is only on the entity
+/// while the chunk is visible. A chunk with only translucent voxels is meshed without a solid mesh.
+#[derive(Component)]
+pub struct Meshed;
+
+/// Child entity of a chunk drawing its translucent mesh, see [`MeshLayer`](super::meshing::MeshLayer)
+#[derive(Component)]
+pub struct TranslucentChunkMesh;
+
+/// Despawns the translucent mesh of a chunk, for when the chunk is meshed again
+pub fn despawn_translucent_mesh(world: &mut World, chunk: Entity) {
+    let Some(children) = world.get::<Children>(chunk) else {
+        return;
+    };
+    let meshes = children.iter().copied().filter(|child| world.get::<TranslucentChunkMesh>(*child).is_some()).collect::<Vec<_>>();
+    for mesh in meshes {
+        despawn_with_children_recursive(world, mesh);
+    }
+}
+
+/// Checks that the state markers of chunk entities agree with [`ChunkData`], which only indexes them.
+/// The checks are debug assertions, the plugin only adds the system to debug builds.
+pub fn validate_chunk_states(
+    chunk_data: Res<ChunkData>,
+    spawn_queue: Res<ChunkSpawnQueue>,
+    states: Query<(Entity, Option<&Chunk>, Has<Generating>, Has<NeedsMesh>, Has<Meshed>, Has<EmptyChunkMarker>)>,
+) {
+    for (entity, chunk, generating, needs_mesh, meshed, empty) in states.iter() {
+        // Unloaded chunks are forgotten right away and despawned over the next frames
+        if spawn_queue.is_despawning(entity) {
+            continue;
+        }
+        let Some(chunk) = chunk else {
+            debug_assert!(!(needs_mesh || meshed || empty), "{:?} has a mesh state without voxels", entity);
+            continue;
+        };
+        debug_assert!(!generating, "chunk {:?} is generating with its voxels already there", chunk.position);
+        debug_assert_eq!(chunk_data.loaded.get(&chunk.position), Some(&entity), "chunk {:?} is not indexed", chunk.position);
+        debug_assert!(meshed || !chunk_data.meshes.contains_key(&chunk.position), "mesh index of chunk {:?}", chunk.position);
+        debug_assert!(
+            [needs_mesh, meshed, empty].into_iter().filter(|state| *state).count() <= 1,
+            "chunk {:?} is in several mesh states", chunk.position
+        );
+    }
+}
+
 impl MeshingTask {
-    pub fn new(chunk: &Chunk) -> Self {
+    /// Grass and other biome tinted blocks are colored for `biome`, water is joined with the water in `borders`
+    pub fn new(chunk: &Chunk, mesher: ChunkMesher, biome: Biome, borders: ChunkBorders, log: ChunkLogLevel) -> Self {
         let task_pool = AsyncComputeTaskPool::get();
+        // A snapshot, edits made while the task runs go into a new version of the chunk
         let chunk = chunk.clone();
         let position = chunk.position.clone();
+        let span = info_span!(target: MESHING_TARGET, "mesh_chunk", chunk = ?position);
         let task = task_pool.spawn(async move {
-            let mesh = chunk.build();
-            mesh
+            let _span = span.enter();
+            let started = Instant::now();
+            let meshes = chunk.build_meshes(&mesher, biome, &borders);
+            if log.logs_chunks() {
+                let vertices = meshes.solid.as_ref().map_or(0, Mesh::count_vertices);
+                let translucent_vertices = meshes.translucent.as_ref().map_or(0, Mesh::count_vertices);
+                info!(target: MESHING_TARGET, chunk = ?position, vertices, translucent_vertices, duration_ms = chunk_log::millis(started.elapsed()), "meshed");
+            }
+            meshes
         });
         Self(position, MeshState::Loading(task))
     }
 }
 
+/// Whether no face of a chunk can be seen from outside of it: the chunk is opaque on every face and
+/// so is every neighbour towards it, like solid chunks deep underground. `None` while a neighbour is
+/// [`ChunkLookup::Unavailable`], a missing neighbour leaves the chunk open.
+pub fn is_buried(chunk_pos: &ChunkPosition, visibility_mask: u8, lookup: impl Fn(&ChunkPosition) -> ChunkLookup) -> Option<bool> {
+    if visibility_mask != 0b111111 {
+        return Some(false);
+    }
+    for (neighbor, face) in chunk_pos.neighbors() {
+        match lookup(&neighbor) {
+            ChunkLookup::Loaded(mask) if mask & (0b1 << face.opposite().as_face_number()) != 0 => (),
+            ChunkLookup::Loaded(_) | ChunkLookup::Missing => return Some(false),
+            ChunkLookup::Unavailable => return None,
+        }
+    }
+    Some(true)
+}
+
 /// Schedules meshing for chunks that have been updated
 pub fn schedule_chunk_meshing(
     mut commands: Commands,
-    mut query: Query<(Entity, &Chunk), (Without<Handle<Mesh>>, Without<MeshingTask>, Without<EmptyChunkMarker>)>,
+    query: Query<(Entity, &Chunk), With<NeedsMesh>>,
+    chunks_query: Query<&Chunk>,
     generator_state: Res<GeneratorState>,
     chunk_data: Res<ChunkData>,
+    spawn_queue: Res<ChunkSpawnQueue>,
+    worldgen_config: Res<WorldGeneratorConfig>,
+    meshing: MeshingResources,
+    log: Res<ChunkLogLevel>,
 ) {
     if *generator_state == GeneratorState::Paused {
         return;
     }
 
-    for (entity, chunk) in query.iter_mut() {
-        // If chunk is meshed, skip it
-        if chunk_data.meshes.contains_key(&chunk.position) {
+    // Neighbours still generating are waited for, they decide whether a chunk is buried
+    let lookup = |chunk_pos: &ChunkPosition| match chunk_data.loaded.get(chunk_pos) {
+        Some(entity) => chunks_query.get(*entity).map_or(ChunkLookup::Unavailable, |chunk| ChunkLookup::Loaded(chunk.visibility_mask)),
+        None if chunk_data.awaiting_generation.contains_key(chunk_pos) => ChunkLookup::Unavailable,
+        None => ChunkLookup::Missing,
+    };
+    for (entity, chunk) in query.iter() {
+        if spawn_queue.is_despawning(entity) {
             continue;
         }
-        let task = MeshingTask::new(chunk);
-        commands.entity(entity).try_insert(task);
+        // Nothing to mesh or nothing to see, skip spawning a task for it
+        let buried = match is_buried(&chunk.position, chunk.visibility_mask, &lookup) {
+            Some(buried) => buried,
+            None => continue,
+        };
+        if chunk.is_empty() || buried {
+            commands.entity(entity).remove::<NeedsMesh>().try_insert(EmptyChunkMarker);
+            continue;
+        }
+        let borders = ChunkBorders::capture(&chunk.position, |neighbour| chunks_query.get(*chunk_data.loaded.get(neighbour)?).ok());
+        let task = MeshingTask::new(chunk, meshing.mesher(), worldgen_config.biome_of(&chunk.position), borders, *log);
+        commands.entity(entity).remove::<NeedsMesh>().try_insert(task);
     } 
 }
 
@@ -371,76 +1365,173 @@ pub fn apply_meshes(
     mut chunk_data: ResMut<ChunkData>,
     mut query: Query<(Entity, &mut MeshingTask)>,
     mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    chunk_materials: Res<ChunkMaterials>,
     generator_state: Res<GeneratorState>,
+    mut budget: ResMut<StreamingBudget>,
+    log: Res<ChunkLogLevel>,
 ) {
     if *generator_state == GeneratorState::Paused {
         return;
     }
+    let started = Instant::now();
 
+    let limit = budget.limit(budget.meshes_per_frame);
+    let mut remaining = limit;
     for (entity, mut task) in query.iter_mut() {
-        let mesh_handle = match &mut task.1 {
-            MeshState::Loaded(ref handle) => Some(handle.clone()),
+        if remaining == 0 {
+            break;
+        }
+        let mesh_handles = match &mut task.1 {
+            MeshState::Loaded(ref handles) => Some(handles.clone()),
             MeshState::Loading(ref mut mesh_task) => {
-                if let Some(mesh) = block_on(futures_lite::future::poll_once(mesh_task)) {
-                    if mesh.is_none() {
-                        commands.entity(entity).remove::<MeshingTask>().try_insert(EmptyChunkMarker);
-                        continue;
-                    }
-                    let mesh = mesh.unwrap();
-                    let mesh_handle = meshes.add(mesh);
-                    Some(mesh_handle)
-                } else { None }
+                block_on(futures_lite::future::poll_once(mesh_task)).map(|chunk_meshes| chunk_meshes.map(|mesh| meshes.add(mesh)))
             },
         };
-        if let Some(mesh_handle) = mesh_handle {
-            commands.entity(entity).remove::<MeshingTask>().try_insert(PbrBundle {
-                mesh: mesh_handle.clone(),
-                transform: Transform::from_translation(task.0.as_world_position()),
-                material: materials.add(StandardMaterial { base_color: Color::rgb(0.3, 0.85, 0.4), ..Default::default() }),
-                ..Default::default()
+        let Some(mesh_handles) = mesh_handles else {
+            continue;
+        };
+        if mesh_handles.is_empty() {
+            commands.entity(entity).remove::<MeshingTask>().try_insert(EmptyChunkMarker);
+            continue;
+        }
+        remaining -= 1;
+        let _span = info_span!(target: MESHING_TARGET, "insert_mesh", chunk = ?task.0).entered();
+        let transform = task.0.mesh_transform();
+        match mesh_handles.solid {
+            Some(mesh_handle) => {
+                commands.entity(entity).remove::<MeshingTask>().try_insert((
+                    MaterialMeshBundle { mesh: mesh_handle.clone(), transform, material: chunk_materials.opaque.clone(), ..Default::default() },
+                    ChunkPosition::mesh_aabb(),
+                    Meshed,
+                ));
+                chunk_data.meshes.insert(task.0, mesh_handle);
+            }
+            // Only translucent voxels, the entity still places its translucent mesh
+            None => {
+                commands.entity(entity).remove::<MeshingTask>().try_insert((SpatialBundle::from_transform(transform), Meshed));
+            }
+        }
+        if let Some(translucent) = mesh_handles.translucent {
+            let material = chunk_materials.translucent.clone();
+            commands.add(move |world: &mut World| {
+                let Some(mut chunk) = world.get_entity_mut(entity) else {
+                    return;
+                };
+                chunk.with_children(|parent| {
+                    parent.spawn((
+                        // Visible on its own, merged super-chunks hide their members but leave water to them
+                        MaterialMeshBundle { mesh: translucent, material, visibility: Visibility::Visible, ..Default::default() },
+                        ChunkPosition::mesh_aabb(),
+                        NotShadowCaster,
+                        TranslucentChunkMesh,
+                    ));
+                });
             });
-            chunk_data.meshes.insert(task.0, mesh_handle);
         }
     }
+    if remaining < limit && log.logs_summary() {
+        info!(target: MESHING_TARGET, count = limit - remaining, duration_ms = chunk_log::millis(started.elapsed()), "inserted chunk meshes");
+    }
+    budget.record(started);
 }
 
 /// Garbage collector :D
 /// Removes chunks and meshes that are too far away or that have other reasons to be removed
 /// This runs every few seconds or if there is enough time left in the frame
 pub fn garbage_collect_chunks(
-    mut commands: Commands,
     mut chunk_data: ResMut<ChunkData>,
+    mut chunk_cache: ResMut<ChunkCache>,
+    mut storage: ResMut<ChunkStorage>,
+    mut dirty_chunks: ResMut<DirtyChunks>,
+    mut spawn_queue: ResMut<ChunkSpawnQueue>,
+    mut memory_budget: ResMut<MemoryBudget>,
+    meshes: Res<Assets<Mesh>>,
+    chunk_index: Res<ChunkIndex>,
     chunks_query: Query<(Entity, &Chunk)>,
     worldgen_config: Res<WorldGeneratorConfig>,
+    chunk_source: Res<ChunkSource>,
     time: Res<Time>,
     frame_count: Res<FrameCount>,
-    camera: Query<&Transform, With<Camera>>,
+    anchors: Query<(&Transform, &StreamingAnchor)>,
+    log: Res<ChunkLogLevel>,
 ) {
+    memory_budget.usage = info_span!(target: GC_TARGET, "measure_memory").in_scope(|| MemoryUsage::measure(&chunk_data, &meshes));
+    let is_over_budget = memory_budget.excess_bytes() > 0;
+
     let is_enough_time_left = time.delta_seconds_f64() < 1.0 / 30.0;
     let is_time_to_collect = frame_count.0 % 60 == 0; // Should force garbage collection every second (60 frames)
-    let should_force_collect = frame_count.0 % 600 == 0; // Should force garbage collection every 10 seconds (600 frames)
+    let should_force_collect = frame_count.0 % 600 == 0 || is_over_budget; // Should force garbage collection every 10 seconds (600 frames)
     if !should_force_collect {
         if !is_enough_time_left && !is_time_to_collect {
             return;
         }
     }
 
-    let camera_position = camera.single().translation;
+    let ranges = anchor_ranges(anchors.iter(), &worldgen_config);
+    let Some(first_range) = ranges.first().copied() else {
+        return;
+    };
+    let started = Instant::now();
+    let select_span = info_span!(target: GC_TARGET, "select_unloads").entered();
+
+    let is_kept = |entity: Entity, chunk_pos: &ChunkPosition| {
+        chunk_data.visible.contains(chunk_pos) || chunk_data.is_pinned(chunk_pos) || spawn_queue.is_despawning(entity)
+    };
+    // Out of range of the first anchor through the index, then of all the others
+    let mut unload = chunk_index
+        .outside_radius(first_range.chunk, first_range.generation_distance as u32)
+        .filter(|(_, chunk_pos)| !in_range_of_any(&ranges, chunk_pos))
+        .filter(|(entity, chunk_pos)| !is_kept(*entity, chunk_pos))
+        .filter_map(|(entity, _)| chunks_query.get(entity).ok())
+        .collect::<Vec<_>>();
+
+    // Chunks that are too far away are not enough, evict the farthest remaining invisible chunks
+    if is_over_budget {
+        let candidates = chunk_index
+            .iter()
+            .filter(|(entity, chunk_pos)| !is_kept(*entity, chunk_pos) && in_range_of_any(&ranges, chunk_pos))
+            .map(|(_, chunk_pos)| (chunk_pos, MemoryUsage::chunk_bytes(&chunk_data, &meshes, &chunk_pos)))
+            .collect::<Vec<_>>();
+        let freed = unload.iter().map(|(_, chunk)| MemoryUsage::chunk_bytes(&chunk_data, &meshes, &chunk.position)).sum::<usize>();
+        let anchor_chunks = ranges.iter().map(|range| range.chunk).collect::<Vec<_>>();
+        let evicted = select_evictions(&anchor_chunks, candidates, memory_budget.excess_bytes().saturating_sub(freed));
+        unload.extend(evicted.iter().filter_map(|chunk_pos| {
+            let entity = *chunk_data.loaded.get(chunk_pos)?;
+            chunks_query.get(entity).ok()
+        }));
+    }
 
-    for (entity, chunk) in chunks_query.iter() {
-        if chunk_data.visible.contains(&chunk.position) {
+    select_span.exit();
+
+    let unloaded = unload.len();
+    let _span = info_span!(target: GC_TARGET, "unload_chunks", unloaded).entered();
+    let mut saved = 0;
+    for (entity, chunk) in unload {
+        // The entity goes away over the next frames, the chunk is forgotten right away
+        spawn_queue.queue_despawn(entity);
+        chunk_data.forget(chunk.position);
+        if log.logs_chunks() {
+            info!(target: GC_TARGET, chunk = ?chunk.position, "unloaded");
+        }
+        // Remote chunks are owned by the server, they are requested again when needed
+        if *chunk_source == ChunkSource::Remote {
             continue;
         }
-        if chunk.position.distance_to(&ChunkPosition::from_world_position(camera_position)) > worldgen_config.generation_distance as f32 {
-            commands.entity(entity).despawn_recursive();
-            chunk_data.forget(chunk.position);
+        // Chunks that no longer fit into the cache are written to disk unless the saved file is up to date
+        for evicted in chunk_cache.insert(chunk.clone()) {
+            if dirty_chunks.take(&evicted.position) || !storage.is_saved(&evicted.position) {
+                saved += 1;
+                storage.save(evicted);
+            }
         }
     }
+    if unloaded > 0 && log.logs_summary() {
+        info!(target: GC_TARGET, unloaded, saved, over_budget = is_over_budget, duration_ms = chunk_log::millis(started.elapsed()), "collected chunks");
+    }
 }
 
 /// Debug resource to keep track of chunk generation stats
-#[cfg(debug_assertions)]
+#[cfg(feature = "debug-ui")]
 #[derive(Resource)]
 pub struct ChunkGenerationStatsDebugTimeseries {
     capacity: usize,
@@ -450,7 +1541,7 @@ pub struct ChunkGenerationStatsDebugTimeseries {
     pub meshes: Vec<[f64; 2]>,
 }
 
-#[cfg(debug_assertions)]
+#[cfg(feature = "debug-ui")]
 impl ChunkGenerationStatsDebugTimeseries {
     pub fn new(capacity: usize) -> Self {
         Self {
@@ -481,16 +1572,23 @@ impl ChunkGenerationStatsDebugTimeseries {
 }
 
 /// Debug system to give stats on chunk generation
-#[cfg(debug_assertions)]
+#[cfg(feature = "debug-ui")]
 pub fn show_chunk_generation_debug_info(
     mut chunk_data: ResMut<ChunkData>,
+    chunk_cache: Res<ChunkCache>,
+    storage: Res<ChunkStorage>,
     mut commands: Commands,
     mut contexts: bevy_egui::EguiContexts,
     mut generator_state: ResMut<GeneratorState>,
     mut world_generator_config: ResMut<WorldGeneratorConfig>,
     mut chunk_generation_series: ResMut<ChunkGenerationStatsDebugTimeseries>,
+    mut backpressure: ResMut<GenerationBackpressure>,
+    mut critical_ring: ResMut<super::critical::CriticalRing>,
+    mut spawn_queue: ResMut<ChunkSpawnQueue>,
+    mut memory_budget: ResMut<MemoryBudget>,
+    mut streaming_budget: ResMut<StreamingBudget>,
     time: Res<Time>,
-    camera: Query<&Transform, With<Camera>>,
+    camera: Query<&Transform, With<StreamingAnchor>>,
 ) {
     use bevy_egui::egui;
     egui::Window::new("Chunk Generation").show(&contexts.ctx_mut(), |ui| {
@@ -546,8 +1644,10 @@ pub fn show_chunk_generation_debug_info(
             );
         });
 
-        ui.label(format!("Player Position: {:?}", camera.single().translation));
-        ui.label(format!("Player forward: {:?}", camera.single().forward()));
+        for (i, transform) in camera.iter().enumerate() {
+            ui.label(format!("Anchor {} Position: {:?}", i, transform.translation));
+            ui.label(format!("Anchor {} Forward: {:?}", i, transform.forward()));
+        }
 
         ui.separator();
 
@@ -565,25 +1665,314 @@ pub fn show_chunk_generation_debug_info(
         ui.horizontal(|ui| {
             if ui.button("Meshes").clicked() {
                 for (_, entity) in chunk_data.loaded.iter() {
-                    commands.entity(*entity).remove::<Handle<Mesh>>();
+                    let entity = *entity;
+                    commands.entity(entity).remove::<(Handle<Mesh>, Meshed, MeshingTask, EmptyChunkMarker)>().insert(NeedsMesh);
+                    commands.add(move |world: &mut World| despawn_translucent_mesh(world, entity));
                 }
                 chunk_data.meshes.clear();
             }
             if ui.button("All").clicked() {
-                chunk_data.meshes.clear();
-                for (_, entity) in chunk_data.loaded.drain() {
-                    commands.entity(entity).despawn_recursive();
-                }
-                chunk_data.awaiting_generation.clear();
-                chunk_data.visible.clear();
+                // Edited chunks are saved first, they are read back from disk when they come into view again
+                commands.add(|world: &mut World| {
+                    super::shutdown::write_world(world);
+                    super::world_manager::unload_all_chunks(world);
+                });
             }
         });
 
         ui.separator();
 
+        ui.label(format!("Pending Edits: {}", chunk_data.pending_edits.len()));
+        ui.label(format!(
+            "Spawn Queue: {} spawns, {} despawns",
+            spawn_queue.pending_spawns(),
+            spawn_queue.pending_despawns()
+        ));
+        ui.add(egui::Slider::new(&mut spawn_queue.max_spawns_per_frame, 1..=512).text("Spawns per Frame"));
+        ui.add(egui::Slider::new(&mut spawn_queue.max_despawns_per_frame, 1..=512).text("Despawns per Frame"));
+        ui.label(format!("Pinned Chunks: {}", chunk_data.pins.len()));
+        ui.label(format!(
+            "Chunk Cache: {} chunks ({:.1} / {} MB)",
+            chunk_cache.len(),
+            chunk_cache.used_bytes() as f64 / 1024.0 / 1024.0,
+            chunk_cache.capacity_mb()
+        ));
+        ui.label(format!(
+            "Chunk Memory: {:.1} / {} MB (voxels {:.1} MB, meshes {:.1} MB)",
+            memory_budget.usage.total_bytes() as f64 / 1024.0 / 1024.0,
+            memory_budget.budget_mb,
+            memory_budget.usage.voxel_bytes as f64 / 1024.0 / 1024.0,
+            memory_budget.usage.mesh_bytes as f64 / 1024.0 / 1024.0
+        ));
+        ui.add(egui::Slider::new(&mut memory_budget.budget_mb, 16..=4096).logarithmic(true).text("Memory Budget (MB)"));
+        ui.label(format!(
+            "Streaming: {:.2} ms of {:.2} ms frame, scale {:.2}",
+            streaming_budget.last_spent().as_secs_f64() * 1000.0,
+            streaming_budget.last_frame_time() * 1000.0,
+            streaming_budget.scale()
+        ));
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut streaming_budget.enabled, "Adaptive");
+            ui.add(egui::Slider::new(&mut streaming_budget.target_fps, 20.0..=240.0).text("Target FPS"));
+        });
+        let mut cache_capacity = chunk_cache.capacity_mb();
+        if ui.add(egui::Slider::new(&mut cache_capacity, 0..=1024).text("Cache Capacity (MB)")).changed() {
+            commands.add(move |world: &mut World| {
+                let evicted = world.resource_mut::<ChunkCache>().set_capacity_mb(cache_capacity);
+                // Remote chunks are owned by the server, they are requested again when needed
+                if *world.resource::<ChunkSource>() == ChunkSource::Remote {
+                    return;
+                }
+                // Evicted chunks are written to disk unless the saved file is up to date
+                world.resource_scope(|world, mut storage: Mut<ChunkStorage>| {
+                    let mut dirty_chunks = world.resource_mut::<DirtyChunks>();
+                    for evicted in evicted {
+                        if dirty_chunks.take(&evicted.position) || !storage.is_saved(&evicted.position) {
+                            storage.save(evicted);
+                        }
+                    }
+                });
+            });
+        }
+        ui.label(format!(
+            "Saved Chunks: {} (save backlog: {})",
+            storage.saved_count(),
+            storage.backlog_len()
+        ));
+
+        ui.separator();
+
+        ui.label(format!(
+            "Mesh Backlog: {} (peak {}){}",
+            backpressure.backlog,
+            backpressure.peak_backlog,
+            if backpressure.throttled { ", generation throttled" } else { "" }
+        ));
+        ui.label(format!("Throttled Frames: {}", backpressure.throttled_frames));
+        ui.add(egui::Slider::new(&mut backpressure.pause_threshold, 16..=2048).text("Pause Backlog"));
+        let pause_threshold = backpressure.pause_threshold;
+        ui.add(egui::Slider::new(&mut backpressure.resume_threshold, 0..=pause_threshold).text("Resume Backlog"));
+
+        ui.separator();
+
+        ui.checkbox(&mut critical_ring.enabled, "Critical Ring");
+        ui.label(format!(
+            "Generated on main thread: {}, meshed on main thread: {}",
+            critical_ring.sync_generated,
+            critical_ring.sync_meshed
+        ));
+
+        ui.separator();
+
         ui.label("Chunk Generation Settings");
         ui.add(egui::Slider::new(&mut world_generator_config.render_distance, 1..=64).text("Render Distance"));
         world_generator_config.generation_distance = world_generator_config.render_distance + 2;
         ui.label(format!("Generation Distance: {}", world_generator_config.generation_distance));
+
+        let current = world_generator_config.load_policy;
+        egui::ComboBox::from_label("Load Policy").selected_text(current.name()).show_ui(ui, |ui| {
+            for policy in [LoadPolicy::Sphere, LoadPolicy::Cube, LoadPolicy::forward_cone()] {
+                if ui.selectable_label(current.name() == policy.name(), policy.name()).clicked() && current != policy {
+                    world_generator_config.load_policy = policy;
+                }
+            }
+        });
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::coords::LocalVoxelPos;
+
+    #[test]
+    fn test_parse_layer_spec() {
+        let blocks = BlockRegistry::builtin();
+        let spec = LayerSpec::parse("bedrock×1, stone×10, dirt x 3, grass", &blocks).unwrap();
+        assert_eq!(spec.layers, vec![(Block::Bedrock.id(), 1), (Block::Stone.id(), 10), (Block::Dirt.id(), 3), (Block::Grass.id(), 1)]);
+        assert_eq!(spec.total_height(), 15);
+        assert_eq!(spec.block_at(0), Some(Block::Bedrock.id()));
+        assert_eq!(spec.block_at(11), Some(Block::Dirt.id()));
+        assert_eq!(spec.block_at(14), Some(Block::Grass.id()));
+        assert_eq!(spec.block_at(15), None);
+
+        let leading = LayerSpec::parse("1×bedrock, 10x stone, 3 * dirt, grass", &blocks).unwrap();
+        assert_eq!(leading, spec);
+    }
+
+    #[test]
+    fn test_parse_layer_spec_block_names_with_x() {
+        let blocks = BlockRegistry::from_ron(r#"[(name: "box"), (name: "sandbox")]"#).unwrap();
+        let (box_block, sandbox) = (blocks.id("box").unwrap(), blocks.id("sandbox").unwrap());
+        let spec = LayerSpec::parse("2×box, 3x sandbox, box, sandbox", &blocks).unwrap();
+        assert_eq!(spec.layers, vec![(box_block, 2), (sandbox, 3), (box_block, 1), (sandbox, 1)]);
+    }
+
+    #[test]
+    fn test_parse_layer_spec_errors() {
+        assert_eq!(LayerSpec::parse("cheese×2", &BlockRegistry::builtin()), Err(LayerSpecError::UnknownBlock("cheese".to_string())));
+        assert_eq!(LayerSpec::parse("stone×many", &BlockRegistry::builtin()), Err(LayerSpecError::InvalidCount("stone×many".to_string())));
+    }
+
+    #[test]
+    fn test_generator_name_stages() {
+        let config = WorldGeneratorConfig::from_generator_name("flat + caves + surface", 1, &BlockRegistry::builtin()).unwrap();
+        let stages = config.stages.iter().map(|stage| stage.stage()).collect::<Vec<_>>();
+        assert_eq!(stages, vec![Stage::Surface, Stage::Carving]);
+        assert!(WorldGeneratorConfig::from_generator_name("flat+lava", 1, &BlockRegistry::builtin()).is_err());
+
+        let (chunk, _) = config.generate(ChunkPosition::new(0, -1, 0));
+        let top = CHUNK_SIZE as u8 - 1;
+        assert_eq!(chunk.get(LocalVoxelPos::new(3, top, 3)), Voxel::from(Block::Grass));
+        assert_eq!(chunk.get(LocalVoxelPos::new(3, top - 1, 3)), Voxel::from(Block::Dirt));
+    }
+
+    #[test]
+    fn test_perlin_params() {
+        let generator = PerlinHeightmapWorldGenerator::from_params("scale:80, ground_level:-8", 1).unwrap();
+        assert_eq!((generator.scale, generator.height, generator.ground_level), (80.0, 32.0, -8));
+        assert!(PerlinHeightmapWorldGenerator::from_params("scale:0", 1).is_err());
+        assert!(PerlinHeightmapWorldGenerator::from_params("gradient:3", 1).is_err());
+        assert!(WorldGeneratorConfig::from_generator_name("perlin=height:8+surface", 1, &BlockRegistry::builtin()).is_ok());
+        assert_eq!(WorldGeneratorConfig::from_generator_name("perlin+layers=snow_line:40,beach:1", 1, &BlockRegistry::builtin()).unwrap().stages.len(), 1);
+        assert!(WorldGeneratorConfig::from_generator_name("perlin+layers=snow:40", 1, &BlockRegistry::builtin()).is_err());
+    }
+
+    #[test]
+    fn test_sea_level_fills_empty_voxels_with_water() {
+        // Stone up to y -1, water from 0 to 3
+        let config = WorldGeneratorConfig::from_generator_name("superflat=stone×4+water=4", 0, &BlockRegistry::builtin()).unwrap();
+        assert_eq!(config.sea_level, Some(4));
+        let (ground, _) = config.generate(ChunkPosition::new(0, -1, 0));
+        assert_eq!(ground.get(LocalVoxelPos::new(2, 15, 2)), Voxel::from(Block::Stone));
+        let (chunk, _) = config.generate(ChunkPosition::new(0, 0, 0));
+        assert_eq!(chunk.get(LocalVoxelPos::new(2, 0, 2)), Voxel::from(Block::Water));
+        assert_eq!(chunk.get(LocalVoxelPos::new(2, 3, 2)), Voxel::from(Block::Water));
+        assert_eq!(chunk.get(LocalVoxelPos::new(2, 4, 2)), Voxel::Empty);
+        assert!(config.generate(ChunkPosition::new(0, 1, 0)).0.is_empty());
+        assert!(WorldGeneratorConfig::from_generator_name("flat+water=deep", 0, &BlockRegistry::builtin()).is_err());
+    }
+
+    #[test]
+    fn test_chunk_fill_matches_generation() {
+        let generator = PerlinHeightmapWorldGenerator::from_params("height:20", 1).unwrap();
+        let config = WorldGeneratorConfig { world_bottom: None, ..WorldGeneratorConfig::default_with(PerlinHeightmapWorldGenerator::default()) };
+        let context = GenerationContext::empty(ChunkPosition::new(0, 0, 0));
+        let mut fills = Vec::new();
+        for y in -3..3 {
+            let position = ChunkPosition::new(2, y, -1);
+            let fill = generator.chunk_fill(&config, &position);
+            let mut generated = Chunk::new(position);
+            generator.generate_chunk(&config, &context, &mut generated);
+            match fill {
+                ChunkFill::Empty => assert!(LocalVoxelPos::iter().all(|pos| generated.get(pos).is_empty())),
+                ChunkFill::Solid(voxel) => assert!(LocalVoxelPos::iter().all(|pos| generated.get(pos) == voxel)),
+                ChunkFill::Mixed => (),
+            }
+            fills.push(fill);
+        }
+        assert_eq!(fills[0], ChunkFill::Solid(Voxel::from(Block::Stone)));
+        assert_eq!(fills[5], ChunkFill::Empty);
+
+        // Filled chunks are never allocated, generated ones that came out uniform are compacted
+        let config = WorldGeneratorConfig { world_bottom: None, ..WorldGeneratorConfig::default_with(generator) };
+        assert!(config.generate(ChunkPosition::new(0, 2, 0)).0.is_empty());
+        let flat = WorldGeneratorConfig { world_bottom: None, ..WorldGeneratorConfig::default_with(FlatWorldGenerator::from_spec("stone×4", 0, &BlockRegistry::builtin()).unwrap()) };
+        assert!(flat.generate(ChunkPosition::new(0, 5, 0)).0.is_empty());
+    }
+
+    #[test]
+    fn test_backpressure_hysteresis() {
+        let mut backpressure = GenerationBackpressure { pause_threshold: 10, resume_threshold: 4, ..Default::default() };
+        backpressure.update(9);
+        assert!(!backpressure.throttled);
+        backpressure.update(10);
+        assert!(backpressure.throttled);
+        backpressure.update(5);
+        assert!(backpressure.throttled);
+        backpressure.update(4);
+        assert!(!backpressure.throttled);
+        assert_eq!(backpressure.peak_backlog, 10);
+        assert_eq!(backpressure.throttled_frames, 2);
+    }
+
+    #[test]
+    fn test_visibility_search_stops_at_opaque_faces() {
+        use bevy::render::camera::CameraProjection;
+
+        let config = WorldGeneratorConfig::default_flat();
+        let camera = Transform::from_xyz(8.0, 8.0, 8.0).looking_at(Vec3::new(8.0, 8.0, -100.0), Vec3::Y);
+        let view_projection = PerspectiveProjection::default().get_projection_matrix() * camera.compute_matrix().inverse();
+        let frustum = Frustum::from_view_projection(&view_projection);
+
+        let open = find_visible_chunks(&config, config.generation_distance, &camera, &frustum, |_| ChunkLookup::Loaded(0));
+        assert_eq!(open.order[0], ChunkPosition::new(0, 0, 0));
+        assert_eq!(open.order.len(), open.set.len());
+        assert!(open.set.contains(&ChunkPosition::new(0, 0, -10)));
+
+        // Nothing behind a solid wall is visible
+        let walled = find_visible_chunks(&config, config.generation_distance, &camera, &frustum, |chunk| ChunkLookup::Loaded(if chunk.z == -2 { 0b111111 } else { 0 }));
+        assert!(walled.set.contains(&ChunkPosition::new(0, 0, -2)));
+        assert!(walled.set.iter().all(|chunk| chunk.z >= -2));
+
+        // Missing chunks are only searched through next to the camera
+        let missing = find_visible_chunks(&config, config.generation_distance, &camera, &frustum, |_| ChunkLookup::Missing);
+        assert!(missing.set.len() < open.set.len());
+        assert!(missing.set.iter().all(|chunk| chunk.z >= -3));
+
+        // Nothing beyond the border is searched for, the chunks right at it still are
+        let bordered = WorldGeneratorConfig { world_border: Some(4), ..config };
+        assert!(bordered.is_beyond_border(&ChunkPosition::new(0, 0, -5)));
+        assert!(!bordered.is_beyond_border(&ChunkPosition::new(-4, 9, 3)));
+        assert!(bordered.is_beyond_border(&ChunkPosition::new(4, 0, 0)));
+        let bordered = find_visible_chunks(&bordered, bordered.generation_distance, &camera, &frustum, |_| ChunkLookup::Loaded(0));
+        assert!(bordered.set.contains(&ChunkPosition::new(0, 0, -4)));
+        assert!(bordered.set.iter().all(|chunk| chunk.z >= -4 && (-4..4).contains(&chunk.x)));
+    }
+
+    #[test]
+    fn test_buried_chunks() {
+        let chunk = ChunkPosition::new(0, 0, 0);
+        let solid = |_: &ChunkPosition| ChunkLookup::Loaded(0b111111);
+        assert_eq!(is_buried(&chunk, 0b111111, solid), Some(true));
+        assert_eq!(is_buried(&chunk, 0b111110, solid), Some(false));
+
+        // Only the face towards the chunk matters, the chunk above is open at its bottom
+        let cave_above = |pos: &ChunkPosition| ChunkLookup::Loaded(if pos.y == 1 { 0b110111 } else { 0b111111 });
+        assert_eq!(is_buried(&chunk, 0b111111, cave_above), Some(true));
+        let cave_above = |pos: &ChunkPosition| ChunkLookup::Loaded(if pos.y == 1 { 0b111011 } else { 0b111111 });
+        assert_eq!(is_buried(&chunk, 0b111111, cave_above), Some(false));
+
+        let lookup = |pos: &ChunkPosition| if pos.x == 1 { ChunkLookup::Unavailable } else { ChunkLookup::Loaded(0b111111) };
+        assert_eq!(is_buried(&chunk, 0b111111, lookup), None);
+        assert_eq!(is_buried(&chunk, 0b111111, |_| ChunkLookup::Missing), Some(false));
+    }
+
+    #[test]
+    fn test_visibility_refresh() {
+        let mut refresh = VisibilityRefresh::default();
+        let anchor = Entity::from_raw(0);
+        let view = |x, forward| AnchorView { anchor, chunk: ChunkPosition::new(x, 0, 0), forward };
+        assert!(refresh.is_stale(&[view(0, Vec3::NEG_Z)], 10, 0.0));
+        refresh.searched(vec![view(0, Vec3::NEG_Z)], 10, 0.0);
+
+        let slightly_turned = Quat::from_rotation_y(5f32.to_radians()) * Vec3::NEG_Z;
+        let turned = Quat::from_rotation_y(15f32.to_radians()) * Vec3::NEG_Z;
+        assert!(!refresh.is_stale(&[view(0, slightly_turned)], 10, 0.1));
+        assert!(refresh.is_stale(&[view(0, turned)], 10, 0.1));
+        assert!(refresh.is_stale(&[view(1, Vec3::NEG_Z)], 10, 0.1));
+        assert!(refresh.is_stale(&[view(0, Vec3::NEG_Z)], 11, 0.1));
+        assert!(refresh.is_stale(&[view(0, Vec3::NEG_Z)], 10, 0.5));
+
+        // Anchors coming and going
+        let other = AnchorView { anchor: Entity::from_raw(1), ..view(0, Vec3::NEG_Z) };
+        assert!(refresh.is_stale(&[view(0, Vec3::NEG_Z), other], 10, 0.1));
+        assert!(refresh.is_stale(&[other], 10, 0.1));
+        assert!(refresh.is_stale(&[], 10, 0.1));
+
+        refresh.invalidate();
+        assert!(refresh.is_stale(&[view(0, Vec3::NEG_Z)], 10, 0.1));
+        refresh.searched(vec![view(0, Vec3::NEG_Z)], 10, 0.1);
+        assert!(!refresh.is_stale(&[view(0, Vec3::NEG_Z)], 10, 0.2));
+    }
+}
@@ -0,0 +1,56 @@
+use noise::{NoiseFn, Perlin};
+
+use crate::engine::{
+    chunk::{Chunk, CHUNK_SIZE},
+    coords::{LocalVoxelPos, WorldVoxelPos},
+    generation_context::GenerationContext,
+    generator::{GenerationStage, Stage, WorldGeneratorConfig},
+    pending_edits::PendingEdits,
+    voxel::Voxel,
+};
+
+/// Carves blobby caves out of 3D noise, the world bottom is restored afterwards so caves never break through it
+pub struct CaveCarver {
+    pub seed: u32,
+    pub scale: f64,
+    /// Noise values above this are carved, higher means fewer and smaller caves
+    pub threshold: f64,
+    /// Voxels this close below the surface are never carved, if the generator knows its surface
+    pub surface_margin: i64,
+}
+
+impl Default for CaveCarver {
+    fn default() -> Self {
+        Self { seed: 0, scale: 24.0, threshold: 0.45, surface_margin: 4 }
+    }
+}
+
+impl GenerationStage for CaveCarver {
+    fn stage(&self) -> Stage {
+        Stage::Carving
+    }
+
+    fn apply(&self, config: &WorldGeneratorConfig, context: &GenerationContext, chunk: &mut Chunk, _overflow: &mut PendingEdits) {
+        // Offset the seed so caves don't line up with terrain using the same seed
+        let noise = Perlin::new(self.seed.wrapping_add(0x5eed));
+        let position = chunk.position;
+        for x in 0..CHUNK_SIZE as u8 {
+            for z in 0..CHUNK_SIZE as u8 {
+                let column = WorldVoxelPos::from_local(&position, LocalVoxelPos::new(x, 0, z));
+                let ceiling = context
+                    .surface_height(config, column.x, column.z)
+                    .map_or(i64::MAX, |surface| surface - self.surface_margin);
+                for y in 0..CHUNK_SIZE as u8 {
+                    let world = column.offset(0, y as i64, 0);
+                    if world.y > ceiling {
+                        break;
+                    }
+                    let value = noise.get([world.x as f64 / self.scale, world.y as f64 / self.scale, world.z as f64 / self.scale]);
+                    if value > self.threshold {
+                        chunk.set(LocalVoxelPos::new(x, y, z), Voxel::Empty);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use noise::{NoiseFn, Perlin};
+
+use crate::engine::{
+    chunk::{Chunk, ChunkPosition},
+    coords::WorldVoxelPos,
+    generation_context::GenerationContext,
+    generator::{chunk_y_range, ChunkFill, WorldGenerator, WorldGeneratorConfig},
+    voxel::{Block, Voxel},
+};
+
+/// Shapes the terrain from 3D noise instead of a heightmap, which allows overhangs, arches and
+/// floating islands. A voxel is solid when `noise + (ground_level - y) * gradient > threshold`,
+/// so the gradient pulls the terrain towards the ground level and the noise breaks it up.
+pub struct DensityWorldGenerator {
+    pub seed: u32,
+    /// Size of terrain features on the x and z axes
+    pub scale: f64,
+    /// Size of terrain features on the y axis, lower than `scale` gives flatter layers
+    pub vertical_scale: f64,
+    pub ground_level: i32,
+    /// Density lost per voxel of height. Lower values give taller terrain and more floating islands.
+    pub gradient: f64,
+    /// Density a voxel needs to be solid, higher values carve away more terrain
+    pub threshold: f64,
+}
+
+impl Default for DensityWorldGenerator {
+    fn default() -> Self {
+        Self { seed: 2138129, scale: 48.0, vertical_scale: 32.0, ground_level: 0, gradient: 1.0 / 24.0, threshold: 0.0 }
+    }
+}
+
+impl DensityWorldGenerator {
+    /// Parses `threshold:<f64>,gradient:<f64>,scale:<f64>,vertical_scale:<f64>`, every key is optional
+    pub fn from_params(params: &str, seed: u32) -> Result<Self, String> {
+        let mut generator = Self { seed, ..Default::default() };
+        for param in params.split(',').map(str::trim).filter(|param| !param.is_empty()) {
+            let (key, value) = param.split_once(':').ok_or_else(|| format!("expected `key:value`, got `{}`", param))?;
+            let value = value.trim().parse::<f64>().map_err(|err| format!("invalid value for `{}`: {}", key, err))?;
+            match key.trim() {
+                "threshold" => generator.threshold = value,
+                "gradient" => generator.gradient = value,
+                "scale" => generator.scale = value,
+                "vertical_scale" => generator.vertical_scale = value,
+                other => return Err(format!("unknown density parameter `{}`", other)),
+            }
+        }
+        if generator.gradient <= 0.0 {
+            return Err("gradient must be positive".to_string());
+        }
+        Ok(generator)
+    }
+
+    fn density(&self, noise: &Perlin, x: i64, y: i64, z: i64) -> f64 {
+        let point = [x as f64 / self.scale, y as f64 / self.vertical_scale, z as f64 / self.scale];
+        // Second octave adds detail, the sum is kept in -1..=1 so the terrain bounds hold
+        let detail = [point[0] * 2.0, point[1] * 2.0, point[2] * 2.0];
+        let value = ((noise.get(point) + noise.get(detail) * 0.5) / 1.5).clamp(-1.0, 1.0);
+        value + (self.ground_level as i64 - y) as f64 * self.gradient
+    }
+
+    /// Every voxel above this is air and every voxel below `lowest_air` is solid
+    fn highest_solid(&self) -> i64 {
+        self.ground_level as i64 + ((1.0 - self.threshold) / self.gradient).ceil() as i64
+    }
+
+    fn lowest_air(&self) -> i64 {
+        self.ground_level as i64 - ((1.0 + self.threshold) / self.gradient).ceil() as i64
+    }
+}
+
+impl WorldGenerator for DensityWorldGenerator {
+    fn generate_chunk(&self, _config: &WorldGeneratorConfig, _context: &GenerationContext, chunk: &mut Chunk) {
+        let noise = Perlin::new(self.seed);
+        let (top, bottom) = (self.highest_solid(), self.lowest_air());
+        chunk.generate_with(|chunk_pos, pos| {
+            let world = WorldVoxelPos::from_local(chunk_pos, pos);
+            let solid = world.y < bottom || (world.y <= top && self.density(&noise, world.x, world.y, world.z) > self.threshold);
+            if solid {
+                Voxel::from(Block::Stone)
+            } else {
+                Voxel::Empty
+            }
+        })
+    }
+
+    fn surface_height(&self, _config: &WorldGeneratorConfig, x: i64, z: i64) -> Option<i64> {
+        let noise = Perlin::new(self.seed);
+        let bottom = self.lowest_air();
+        (bottom..=self.highest_solid())
+            .rev()
+            .find(|y| self.density(&noise, x, *y, z) > self.threshold)
+            .or(Some(bottom - 1))
+    }
+
+    fn chunk_fill(&self, _config: &WorldGeneratorConfig, chunk: &ChunkPosition) -> ChunkFill {
+        let (min_y, max_y) = chunk_y_range(chunk);
+        if min_y > self.highest_solid() {
+            ChunkFill::Empty
+        } else if max_y < self.lowest_air() {
+            ChunkFill::Solid(Voxel::from(Block::Stone))
+        } else {
+            ChunkFill::Mixed
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{
+        block_registry::BlockRegistry,
+        chunk::{ChunkPosition, CHUNK_SIZE},
+        coords::LocalVoxelPos,
+    };
+
+    #[test]
+    fn test_surface_height_matches_chunks() {
+        let config = WorldGeneratorConfig::from_generator_name("density=threshold:0.1,gradient:0.05", 7, &BlockRegistry::builtin()).unwrap();
+        let generator = DensityWorldGenerator::from_params("threshold:0.1,gradient:0.05", 7).unwrap();
+
+        for chunk_x in 0..2 {
+            let column_chunks = (-2..=2)
+                .map(|y| config.generate(ChunkPosition::new(chunk_x, y, 0)).0)
+                .collect::<Vec<_>>();
+            for x in 0..CHUNK_SIZE as u8 {
+                for z in 0..CHUNK_SIZE as u8 {
+                    let highest = column_chunks.iter().rev().find_map(|chunk| {
+                        (0..CHUNK_SIZE as u8).rev().find_map(|y| {
+                            let pos = LocalVoxelPos::new(x, y, z);
+                            (!chunk.get(pos).is_empty()).then(|| WorldVoxelPos::from_local(&chunk.position, pos).y)
+                        })
+                    });
+                    let world = WorldVoxelPos::from_local(&ChunkPosition::new(chunk_x, 0, 0), LocalVoxelPos::new(x, 0, z));
+                    assert_eq!(highest, generator.surface_height(&config, world.x, world.z));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_params() {
+        let generator = DensityWorldGenerator::from_params("gradient:0.1, scale:16", 1).unwrap();
+        assert_eq!(generator.gradient, 0.1);
+        assert_eq!(generator.scale, 16.0);
+        assert!(DensityWorldGenerator::from_params("gradient:0", 1).is_err());
+        assert!(DensityWorldGenerator::from_params("height:3", 1).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+//! Underground dungeons: rooms connected by corridors, laid out by splitting an area in two
+//! over and over (BSP) and joining the halves of every split.
+//!
+//! The world is divided into cells of [`DungeonGenerator::cell_chunks`] chunks on x and z with at most
+//! one dungeon per cell. Only the chunk containing the dungeon origin lays it out, the voxels are written
+//! through the overflow buffer and land in every chunk the dungeon touches when that chunk generates.
+//! Rooms are enclosed in stone, so they stay hidden from the surface until something breaks in.
+
+use crate::engine::{
+    chunk::{Chunk, ChunkPosition, CHUNK_SIZE},
+    coords::{floor_div, WorldVoxelPos},
+    generation_context::GenerationContext,
+    generator::{GenerationStage, Stage, WorldGeneratorConfig},
+    pending_edits::PendingEdits,
+    rng::ChunkRng,
+    voxel::{Block, Voxel},
+};
+
+const DUNGEON_SALT: u64 = 0xd0_6e07;
+
+/// Smallest and largest voxel of a box, both inclusive
+pub type VoxelBox = (WorldVoxelPos, WorldVoxelPos);
+
+pub struct DungeonGenerator {
+    /// Chance of a cell containing a dungeon
+    pub chance: f32,
+    /// Cell size in chunks on x and z
+    pub cell_chunks: i32,
+    /// Size of the dungeon area on x and z in voxels, must fit into a cell
+    pub size: i64,
+    /// Smallest room size on x and z, not counting walls
+    pub min_room: i64,
+    pub room_height: i64,
+    /// Voxels between the surface and the ceiling of the dungeon
+    pub depth: i64,
+    /// Floor level used when the generator does not know its surface height
+    pub fallback_floor: i64,
+}
+
+impl Default for DungeonGenerator {
+    fn default() -> Self {
+        Self { chance: 0.5, cell_chunks: 4, size: 48, min_room: 4, room_height: 4, depth: 12, fallback_floor: -32 }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DungeonLayout {
+    /// Air inside the rooms
+    pub rooms: Vec<VoxelBox>,
+    /// Air inside the corridors
+    pub corridors: Vec<VoxelBox>,
+    /// Pairs of rooms joined by a corridor, indices into `rooms`
+    pub connections: Vec<(usize, usize)>,
+}
+
+impl DungeonLayout {
+    /// Writes stone walls around every room and corridor and carves their insides
+    pub fn write(&self, edits: &mut PendingEdits) {
+        let boxes = || self.rooms.iter().chain(self.corridors.iter());
+        // All walls go first so walls of a corridor never block the room it leads into
+        for &(min, max) in boxes() {
+            for pos in positions(min.offset(-1, -1, -1), max.offset(1, 1, 1)) {
+                edits.push(pos, Voxel::from(Block::Stone));
+            }
+        }
+        for &(min, max) in boxes() {
+            for pos in positions(min, max) {
+                edits.push(pos, Voxel::Empty);
+            }
+        }
+    }
+}
+
+impl DungeonGenerator {
+    /// Lowest corner of the dungeon area in the cell `chunk` belongs to, `None` if the cell has no dungeon
+    pub fn origin(&self, config: &WorldGeneratorConfig, chunk: ChunkPosition) -> Option<WorldVoxelPos> {
+        let cell = ChunkPosition::new(floor_div(chunk.x, self.cell_chunks), 0, floor_div(chunk.z, self.cell_chunks));
+        let mut rng = config.chunk_rng(cell, DUNGEON_SALT);
+        if !rng.chance(self.chance) {
+            return None;
+        }
+        let cell_size = (self.cell_chunks as i64 * CHUNK_SIZE as i64) as i32;
+        let spread = cell_size - self.size as i32 + 1;
+        let x = cell.x as i64 * cell_size as i64 + rng.range(0, spread) as i64;
+        let z = cell.z as i64 * cell_size as i64 + rng.range(0, spread) as i64;
+        // The surface has to come from the generator itself, every chunk of the cell must agree on it
+        let floor = config
+            .generator
+            .surface_height(config, x + self.size / 2, z + self.size / 2)
+            .map_or(self.fallback_floor, |surface| surface - self.depth - self.room_height);
+        Some(WorldVoxelPos::new(x, floor, z))
+    }
+
+    /// Lays out the dungeon with the lowest corner at `origin`, the same for every run with the same seed
+    pub fn layout(&self, config: &WorldGeneratorConfig, origin: WorldVoxelPos) -> DungeonLayout {
+        let mut rng = config.chunk_rng(origin.chunk(), DUNGEON_SALT + 1);
+        let mut layout = DungeonLayout::default();
+        self.split(&mut rng, origin, (0, 0, self.size, self.size), &mut layout);
+        layout
+    }
+
+    /// Splits `area` (x, z, width, depth relative to `origin`) until it is too small, places a room in
+    /// every leaf and connects both halves of every split. Returns one room of the area.
+    fn split(&self, rng: &mut ChunkRng, origin: WorldVoxelPos, area: (i64, i64, i64, i64), layout: &mut DungeonLayout) -> usize {
+        let (x, z, width, depth) = area;
+        // A room plus a wall on both sides
+        let min_leaf = self.min_room + 2;
+        let along_x = width >= depth;
+        let length = if along_x { width } else { depth };
+        if length >= min_leaf * 2 {
+            let at = rng.range(min_leaf as i32, (length - min_leaf + 1) as i32) as i64;
+            let (first, second) = if along_x {
+                ((x, z, at, depth), (x + at, z, width - at, depth))
+            } else {
+                ((x, z, width, at), (x, z + at, width, depth - at))
+            };
+            let a = self.split(rng, origin, first, layout);
+            let b = self.split(rng, origin, second, layout);
+            self.connect(rng, a, b, layout);
+            return if rng.chance(0.5) { a } else { b };
+        }
+
+        let room_width = rng.range(self.min_room as i32, (width - 1) as i32) as i64;
+        let room_depth = rng.range(self.min_room as i32, (depth - 1) as i32) as i64;
+        let min = origin.offset(
+            x + 1 + rng.range(0, (width - 1 - room_width) as i32) as i64,
+            0,
+            z + 1 + rng.range(0, (depth - 1 - room_depth) as i32) as i64,
+        );
+        layout.rooms.push((min, min.offset(room_width - 1, self.room_height - 1, room_depth - 1)));
+        layout.rooms.len() - 1
+    }
+
+    /// L shaped corridor from the center of room `a` to the center of room `b`
+    fn connect(&self, rng: &mut ChunkRng, a: usize, b: usize, layout: &mut DungeonLayout) {
+        let center = |(min, max): VoxelBox| WorldVoxelPos::new((min.x + max.x) / 2, min.y, (min.z + max.z) / 2);
+        let (from, to) = (center(layout.rooms[a]), center(layout.rooms[b]));
+        let corner = if rng.chance(0.5) { WorldVoxelPos::new(to.x, from.y, from.z) } else { WorldVoxelPos::new(from.x, from.y, to.z) };
+        for (start, end) in [(from, corner), (corner, to)] {
+            let min = WorldVoxelPos::new(start.x.min(end.x), start.y, start.z.min(end.z));
+            let max = WorldVoxelPos::new(start.x.max(end.x), start.y + 2, start.z.max(end.z));
+            layout.corridors.push((min, max));
+        }
+        layout.connections.push((a, b));
+    }
+}
+
+impl GenerationStage for DungeonGenerator {
+    fn stage(&self) -> Stage {
+        Stage::Decoration
+    }
+
+    fn apply(&self, config: &WorldGeneratorConfig, _context: &GenerationContext, chunk: &mut Chunk, overflow: &mut PendingEdits) {
+        let Some(origin) = self.origin(config, chunk.position) else {
+            return;
+        };
+        if origin.chunk() != chunk.position {
+            return;
+        }
+        self.layout(config, origin).write(overflow);
+    }
+}
+
+fn positions(min: WorldVoxelPos, max: WorldVoxelPos) -> impl Iterator<Item = WorldVoxelPos> {
+    (min.x..=max.x).flat_map(move |x| (min.y..=max.y).flat_map(move |y| (min.z..=max.z).map(move |z| WorldVoxelPos::new(x, y, z))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::generator::FlatWorldGenerator;
+
+    fn overlaps((a_min, a_max): VoxelBox, (b_min, b_max): VoxelBox) -> bool {
+        a_min.x <= b_max.x && b_min.x <= a_max.x && a_min.z <= b_max.z && b_min.z <= a_max.z
+    }
+
+    #[test]
+    fn test_layout_is_connected() {
+        let dungeons = DungeonGenerator::default();
+        let mut config = WorldGeneratorConfig::default_with(FlatWorldGenerator::default());
+        for seed in 0..16 {
+            config.seed = seed;
+            let origin = WorldVoxelPos::new(-20, -30, 7);
+            let layout = dungeons.layout(&config, origin);
+            assert_eq!(layout, dungeons.layout(&config, origin));
+            assert!(layout.rooms.len() >= 4, "seed {}: only {} rooms", seed, layout.rooms.len());
+            assert_eq!(layout.connections.len(), layout.rooms.len() - 1);
+
+            for (i, &room) in layout.rooms.iter().enumerate() {
+                let (min, max) = room;
+                assert!(min.x > origin.x && max.x < origin.x + dungeons.size - 1);
+                assert!(min.z > origin.z && max.z < origin.z + dungeons.size - 1);
+                assert!(layout.rooms[i + 1..].iter().all(|&other| !overlaps(room, other)));
+            }
+
+            // Every room can be reached from the first one
+            let mut reached = vec![false; layout.rooms.len()];
+            reached[0] = true;
+            for _ in 0..layout.rooms.len() {
+                for &(a, b) in &layout.connections {
+                    let joined = reached[a] || reached[b];
+                    reached[a] = joined;
+                    reached[b] = joined;
+                }
+            }
+            assert!(reached.iter().all(|reached| *reached), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn test_dungeon_spans_multiple_chunks() {
+        let dungeons = DungeonGenerator { chance: 1.0, ..Default::default() };
+        let config = WorldGeneratorConfig::default_with(FlatWorldGenerator::default()).with_stage(DungeonGenerator { chance: 1.0, ..Default::default() });
+        let origin = dungeons.origin(&config, ChunkPosition::new(1, 0, 2)).unwrap();
+        // Every chunk of the cell agrees on the origin
+        assert_eq!(dungeons.origin(&config, ChunkPosition::new(3, -2, 0)), Some(origin));
+
+        let (_, overflow) = config.generate(origin.chunk());
+        assert!(overflow.chunks().count() > 1);
+        let (_, elsewhere) = config.generate(ChunkPosition::new(origin.chunk().x, origin.chunk().y + 1, origin.chunk().z));
+        assert!(elsewhere.is_empty());
+    }
+}
@@ -0,0 +1,16 @@
+//! Additional world generators built on top of [`super::generator::WorldGenerator`]
+//! and stages for the generation pipeline, see [`super::generator::GenerationStage`]
+
+pub mod test_pattern;
+pub mod surface;
+pub mod caves;
+pub mod density;
+pub mod dungeons;
+pub mod villages;
+
+pub use test_pattern::{TestPattern, TestPatternWorldGenerator};
+pub use surface::{SurfaceLayers, SurfacePainter};
+pub use caves::CaveCarver;
+pub use density::DensityWorldGenerator;
+pub use dungeons::DungeonGenerator;
+pub use villages::VillageGenerator;
@@ -0,0 +1,197 @@
+use crate::engine::{
+    biome::{Biome, BiomeMap},
+    chunk::{Chunk, CHUNK_SIZE},
+    coords::{LocalVoxelPos, WorldVoxelPos},
+    generation_context::GenerationContext,
+    generator::{GenerationStage, Stage, WorldGeneratorConfig},
+    pending_edits::PendingEdits,
+    util::Face,
+    voxel::{Block, BlockId, Voxel},
+};
+
+/// Covers stone at the surface with a top block and a few layers of filler underneath
+pub struct SurfacePainter {
+    pub top: BlockId,
+    pub filler: BlockId,
+    /// Filler layers below the top block
+    pub depth: i64,
+}
+
+impl Default for SurfacePainter {
+    fn default() -> Self {
+        Self { top: Block::Grass.id(), filler: Block::Dirt.id(), depth: 3 }
+    }
+}
+
+impl SurfacePainter {
+    fn block_at_depth(&self, depth: i64) -> Option<BlockId> {
+        match depth {
+            0 => Some(self.top),
+            depth if depth > 0 && depth <= self.depth => Some(self.filler),
+            _ => None,
+        }
+    }
+}
+
+impl GenerationStage for SurfacePainter {
+    fn stage(&self) -> Stage {
+        Stage::Surface
+    }
+
+    fn apply(&self, config: &WorldGeneratorConfig, context: &GenerationContext, chunk: &mut Chunk, _overflow: &mut PendingEdits) {
+        paint_columns(config, context, chunk, |_, _, depth, voxel| {
+            (voxel.block() == Some(Block::Stone.id())).then(|| self.block_at_depth(depth)).flatten()
+        });
+    }
+}
+
+/// Replaces the top voxels of the terrain by biome and altitude: sand near the water level and in
+/// deserts, snow caps above the snow line and in snowy biomes, grass and dirt everywhere else.
+/// Configured in the generator name, e.g. `perlin+layers=depth:4,snow_line:40`.
+pub struct SurfaceLayers {
+    /// Layers below the top voxel that are replaced
+    pub depth: i64,
+    /// Surfaces at or above this height get snow on top of bare stone
+    pub snow_line: i64,
+    /// The sea level of the world if `None`, or 0 for a world without water
+    pub water_level: Option<i64>,
+    /// Surfaces at most this far above or below the water level are sand
+    pub beach: i64,
+}
+
+impl Default for SurfaceLayers {
+    fn default() -> Self {
+        Self { depth: 3, snow_line: 48, water_level: None, beach: 2 }
+    }
+}
+
+impl SurfaceLayers {
+    pub fn from_params(params: &str) -> Result<Self, String> {
+        let mut layers = Self::default();
+        for param in params.split(',').map(str::trim).filter(|param| !param.is_empty()) {
+            let (key, value) = param.split_once(':').ok_or_else(|| format!("expected `key:value`, got `{}`", param))?;
+            let value = value.trim().parse::<i64>().map_err(|err| format!("invalid value for `{}`: {}", key, err))?;
+            match key.trim() {
+                "depth" => layers.depth = value,
+                "snow_line" => layers.snow_line = value,
+                "water_level" => layers.water_level = Some(value),
+                "beach" => layers.beach = value,
+                other => return Err(format!("unknown layers parameter `{}`", other)),
+            }
+        }
+        if layers.depth < 0 || layers.beach < 0 {
+            return Err("depth and beach can not be negative".to_string());
+        }
+        Ok(layers)
+    }
+
+    /// Block `depth` voxels below a surface at `surface` in `biome` of a world with water at
+    /// `water_level`, `None` leaves the voxel as it is
+    pub fn block_at(&self, biome: Biome, surface: i64, depth: i64, water_level: i64) -> Option<Block> {
+        if depth < 0 || depth > self.depth {
+            return None;
+        }
+        let on_beach = (surface - water_level).abs() <= self.beach;
+        Some(match (biome, depth) {
+            _ if on_beach => Block::Sand,
+            // Mountain tops are snow on stone
+            (_, 0) if surface >= self.snow_line => Block::Snow,
+            _ if surface >= self.snow_line => return None,
+            (Biome::Desert, _) => Block::Sand,
+            (Biome::Snow, 0) => Block::Snow,
+            (Biome::Plains, 0) => Block::Grass,
+            _ => Block::Dirt,
+        })
+    }
+}
+
+impl GenerationStage for SurfaceLayers {
+    fn stage(&self) -> Stage {
+        Stage::Surface
+    }
+
+    fn apply(&self, config: &WorldGeneratorConfig, context: &GenerationContext, chunk: &mut Chunk, _overflow: &mut PendingEdits) {
+        let biomes = BiomeMap::new(config.seed);
+        let water_level = self.water_level.or(config.sea_level.map(i64::from)).unwrap_or(0);
+        let mut column_biome = (i64::MIN, i64::MIN, Biome::default());
+        paint_columns(config, context, chunk, |pos, surface, depth, voxel| {
+            // Only terrain is replaced, including what the surface painter put there
+            let terrain = [Block::Stone, Block::Dirt, Block::Grass].map(|block| block.id());
+            if !voxel.block().map_or(false, |block| terrain.contains(&block)) {
+                return None;
+            }
+            if (column_biome.0, column_biome.1) != (pos.x, pos.z) {
+                column_biome = (pos.x, pos.z, biomes.biome_at(pos.x, pos.z));
+            }
+            self.block_at(column_biome.2, surface, depth, water_level).map(|block| block.id())
+        });
+    }
+}
+
+/// Walks every column of the chunk from the top and replaces each voxel `block_for` returns a
+/// block for. It is called with the world position of the voxel, the height of the surface above
+/// it, the voxel's depth below that surface (negative above it) and the voxel.
+fn paint_columns(
+    config: &WorldGeneratorConfig,
+    context: &GenerationContext,
+    chunk: &mut Chunk,
+    mut block_for: impl FnMut(WorldVoxelPos, i64, i64, Voxel) -> Option<BlockId>,
+) {
+    let position = chunk.position;
+    for x in 0..CHUNK_SIZE as u8 {
+        for z in 0..CHUNK_SIZE as u8 {
+            let column = WorldVoxelPos::from_local(&position, LocalVoxelPos::new(x, 0, z));
+            // Generators that know their surface paint the same columns no matter which chunk is generated first,
+            // otherwise only surfaces exposed inside this chunk or below an empty neighbour are found
+            let surface = context.surface_height(config, column.x, column.z);
+            let top = column.y + CHUNK_SIZE as i64 - 1;
+            let mut exposed_at = context
+                .neighbor_voxel(Face::Top, x as usize, z as usize)
+                .filter(|above| above.is_empty())
+                .map(|_| top);
+            for y in (0..CHUNK_SIZE as u8).rev() {
+                let pos = LocalVoxelPos::new(x, y, z);
+                let voxel = chunk.get(pos);
+                let world_y = column.y + y as i64;
+                let surface = match surface {
+                    Some(surface) => Some(surface),
+                    None => {
+                        if voxel.is_empty() {
+                            exposed_at = Some(world_y - 1);
+                        }
+                        exposed_at
+                    }
+                };
+                let Some(surface) = surface else {
+                    continue;
+                };
+                let world_pos = WorldVoxelPos::new(column.x, world_y, column.z);
+                if let Some(block) = block_for(world_pos, surface, surface - world_y, voxel) {
+                    chunk.set(pos, Voxel::from(block));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layers_follow_biome_and_altitude() {
+        let layers = SurfaceLayers::from_params("depth:2,snow_line:40,water_level:0,beach:1").unwrap();
+        let column = |biome, surface| (0..=3).map(|depth| layers.block_at(biome, surface, depth, 0)).collect::<Vec<_>>();
+        assert_eq!(column(Biome::Plains, 10), vec![Some(Block::Grass), Some(Block::Dirt), Some(Block::Dirt), None]);
+        assert_eq!(column(Biome::Snow, 10), vec![Some(Block::Snow), Some(Block::Dirt), Some(Block::Dirt), None]);
+        assert_eq!(column(Biome::Desert, 10), vec![Some(Block::Sand), Some(Block::Sand), Some(Block::Sand), None]);
+        // Beaches are sand in every biome, mountain tops are capped with snow
+        assert_eq!(column(Biome::Snow, -1)[0], Some(Block::Sand));
+        assert_eq!(column(Biome::Plains, 45), vec![Some(Block::Snow), None, None, None]);
+        assert_eq!(layers.block_at(Biome::Plains, 10, -1, 0), None);
+        assert_eq!(layers.block_at(Biome::Plains, 10, 0, 9), Some(Block::Sand));
+
+        assert!(SurfaceLayers::from_params("depth:-1").is_err());
+        assert!(SurfaceLayers::from_params("height:3").is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use crate::engine::{
+    chunk::{Chunk, ChunkPosition, CHUNK_SIZE},
+    coords::LocalVoxelPos,
+    generation_context::GenerationContext,
+    generator::{WorldGenerator, WorldGeneratorConfig},
+    voxel::{Block, Voxel},
+};
+
+/// Known structures placed by [`TestPatternWorldGenerator`], one per chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TestPattern {
+    Empty,
+    /// Fully solid chunk, best case for meshing
+    Solid,
+    /// 3D checkerboard, worst case for meshing
+    Checkerboard,
+    /// Single voxel wide column in the middle of the chunk
+    Pillar,
+    /// Hollow box with one voxel thick walls
+    HollowBox,
+    /// Staircase rising along the x axis
+    Staircase,
+    /// One voxel of each block type in a row
+    Gallery,
+}
+
+impl TestPattern {
+    /// Patterns placed in the y = 0 chunk layer, in the order they repeat along the x axis
+    pub const CYCLE: [TestPattern; 6] = [
+        TestPattern::Checkerboard,
+        TestPattern::Pillar,
+        TestPattern::HollowBox,
+        TestPattern::Staircase,
+        TestPattern::Gallery,
+        TestPattern::Solid,
+    ];
+
+    /// Returns the pattern generated in the given chunk.
+    /// Only the y = 0 layer contains patterns, every other chunk is empty.
+    pub fn for_chunk(chunk: &ChunkPosition) -> Self {
+        if chunk.y != 0 {
+            return TestPattern::Empty;
+        }
+        // Shift every row on the z axis by one so neighbouring rows differ
+        let index = (chunk.x + chunk.z).rem_euclid(Self::CYCLE.len() as i32);
+        Self::CYCLE[index as usize]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Empty => "empty",
+            Self::Solid => "solid",
+            Self::Checkerboard => "checkerboard",
+            Self::Pillar => "pillar",
+            Self::HollowBox => "hollow box",
+            Self::Staircase => "staircase",
+            Self::Gallery => "block gallery",
+        }
+    }
+
+    pub fn voxel_at(&self, pos: LocalVoxelPos) -> Voxel {
+        let (x, y, z) = (pos.x as usize, pos.y as usize, pos.z as usize);
+        let last = CHUNK_SIZE - 1;
+        let solid = match self {
+            Self::Empty => false,
+            Self::Solid => true,
+            Self::Checkerboard => (x + y + z) % 2 == 0,
+            Self::Pillar => x == CHUNK_SIZE / 2 && z == CHUNK_SIZE / 2,
+            Self::HollowBox => {
+                let on_edge = |v: usize| v == 1 || v == last - 1;
+                let inside = |v: usize| (1..last).contains(&v);
+                inside(x) && inside(y) && inside(z) && (on_edge(x) || on_edge(y) || on_edge(z))
+            }
+            Self::Staircase => y <= x && z >= 4 && z < CHUNK_SIZE - 4,
+            Self::Gallery => {
+                if y == 0 && z == CHUNK_SIZE / 2 && x % 2 == 0 {
+                    return Block::ALL.get(x / 2).map(|block| Voxel::from(*block)).unwrap_or(Voxel::Empty);
+                }
+                false
+            }
+        };
+
+        if solid {
+            Voxel::from(Block::Stone)
+        } else {
+            Voxel::Empty
+        }
+    }
+}
+
+/// Debug generator producing a predictable [`TestPattern`] per chunk, used for meshing tests and manual QA
+#[derive(Default)]
+pub struct TestPatternWorldGenerator;
+
+impl WorldGenerator for TestPatternWorldGenerator {
+    fn generate_chunk(&self, _config: &WorldGeneratorConfig, _context: &GenerationContext, chunk: &mut Chunk) {
+        let pattern = TestPattern::for_chunk(&chunk.position);
+        chunk.generate_with(|_, pos| pattern.voxel_at(pos));
+    }
+}
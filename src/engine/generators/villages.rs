@@ -0,0 +1,235 @@
+//! Small clusters of prefab buildings on flat enough ground.
+//!
+//! Like dungeons, villages are placed per cell of [`VillageGenerator::cell_chunks`] chunks and laid out by the
+//! chunk containing the village center at the surface. Every building looks at the surface heights under
+//! its footprint (the heightmap snapshot of the generation context, the generator otherwise) and is only
+//! placed where they differ by at most [`VillageGenerator::max_relief`]. The ground is then terraced to
+//! the average height: filled up with dirt below and cut away above.
+
+use bevy::prelude::UVec3;
+
+use crate::engine::{
+    chunk::{Chunk, ChunkPosition, CHUNK_SIZE},
+    coords::{floor_div, WorldVoxelPos},
+    generation_context::GenerationContext,
+    generator::{GenerationStage, Stage, WorldGeneratorConfig},
+    pending_edits::PendingEdits,
+    schematic::Schematic,
+    voxel::{Block, Voxel},
+};
+
+const VILLAGE_SALT: u64 = 0x71_11a6e;
+
+pub struct VillageGenerator {
+    /// Chance of a cell containing a village
+    pub chance: f32,
+    /// Cell size in chunks on x and z
+    pub cell_chunks: i32,
+    /// Buildings are placed at most this far from the village center on x and z
+    pub radius: i64,
+    pub max_buildings: usize,
+    /// Villages with fewer buildings are not placed at all
+    pub min_buildings: usize,
+    /// Largest difference between surface heights under a building
+    pub max_relief: i64,
+}
+
+impl Default for VillageGenerator {
+    fn default() -> Self {
+        Self { chance: 0.35, cell_chunks: 6, radius: 20, max_buildings: 6, min_buildings: 2, max_relief: 3 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Building {
+    /// Minimum corner, the floor of the prefab lies at the terraced ground level
+    pub min: WorldVoxelPos,
+    pub prefab: Schematic,
+}
+
+impl Building {
+    /// Footprint on x and z grown by `margin`, both corners inclusive
+    fn footprint(&self, margin: i64) -> (i64, i64, i64, i64) {
+        let size = self.prefab.size();
+        (self.min.x - margin, self.min.z - margin, self.min.x + size.x as i64 - 1 + margin, self.min.z + size.z as i64 - 1 + margin)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VillagePlan {
+    pub center: WorldVoxelPos,
+    pub buildings: Vec<Building>,
+}
+
+impl VillagePlan {
+    /// Terraces the ground under every building and places the prefabs
+    pub fn write(&self, edits: &mut PendingEdits, max_relief: i64) {
+        for building in self.buildings.iter() {
+            let level = building.min.y;
+            let (min_x, min_z, max_x, max_z) = building.footprint(1);
+            for x in min_x..=max_x {
+                for z in min_z..=max_z {
+                    for y in level - max_relief..level {
+                        edits.push(WorldVoxelPos::new(x, y, z), Voxel::from(Block::Dirt));
+                    }
+                    edits.push(WorldVoxelPos::new(x, level, z), Voxel::from(Block::Grass));
+                    for y in level + 1..=level + max_relief + 1 {
+                        edits.push(WorldVoxelPos::new(x, y, z), Voxel::Empty);
+                    }
+                }
+            }
+            building.prefab.paste(edits, building.min, true);
+        }
+    }
+}
+
+/// Lowest and highest of the given surface heights, `None` if any of them is unknown
+pub fn relief(heights: impl IntoIterator<Item = Option<i64>>) -> Option<(i64, i64)> {
+    heights.into_iter().try_fold((i64::MAX, i64::MIN), |(min, max), height| height.map(|height| (min.min(height), max.max(height))))
+}
+
+/// Prefab buildings, facing -z with the door in the middle of that wall
+pub fn prefabs() -> Vec<Schematic> {
+    vec![house(UVec3::new(5, 5, 5)), house(UVec3::new(7, 5, 5)), house(UVec3::new(3, 8, 3))]
+}
+
+/// Stone walls and roof on a gravel floor, with a door and glass windows
+fn house(size: UVec3) -> Schematic {
+    let mut schematic = Schematic::new(size);
+    let (last_x, last_y, last_z) = (size.x - 1, size.y - 1, size.z - 1);
+    for x in 0..size.x {
+        for z in 0..size.z {
+            let wall = x == 0 || z == 0 || x == last_x || z == last_z;
+            for y in 0..size.y {
+                let voxel = if y == 0 {
+                    Block::Gravel
+                } else if y == last_y {
+                    Block::Stone
+                } else if wall {
+                    let middle = (x == size.x / 2 && (z == 0 || z == last_z)) || (z == size.z / 2 && (x == 0 || x == last_x));
+                    if middle && y == 2 && z != 0 { Block::Glass } else { Block::Stone }
+                } else {
+                    continue;
+                };
+                schematic.set(x, y, z, Voxel::from(voxel));
+            }
+        }
+    }
+    // Door
+    schematic.set(size.x / 2, 1, 0, Voxel::Empty);
+    schematic.set(size.x / 2, 2, 0, Voxel::Empty);
+    schematic
+}
+
+impl VillageGenerator {
+    /// Village center in the cell `chunk` belongs to, `None` if the cell has no village or its surface is not known
+    pub fn center(&self, config: &WorldGeneratorConfig, context: &GenerationContext, chunk: ChunkPosition) -> Option<WorldVoxelPos> {
+        let cell = ChunkPosition::new(floor_div(chunk.x, self.cell_chunks), 0, floor_div(chunk.z, self.cell_chunks));
+        let mut rng = config.chunk_rng(cell, VILLAGE_SALT);
+        if !rng.chance(self.chance) {
+            return None;
+        }
+        // Keep the whole village inside the cell
+        let cell_size = self.cell_chunks as i64 * CHUNK_SIZE as i64;
+        let margin = self.radius + 8;
+        let x = cell.x as i64 * cell_size + margin + rng.range(0, (cell_size - margin * 2).max(1) as i32) as i64;
+        let z = cell.z as i64 * cell_size + margin + rng.range(0, (cell_size - margin * 2).max(1) as i32) as i64;
+        let y = context.surface_height(config, x, z)?;
+        Some(WorldVoxelPos::new(x, y, z))
+    }
+
+    /// Picks building spots around `center` where the ground is flat enough, `height` returns surface heights
+    pub fn plan(&self, config: &WorldGeneratorConfig, center: WorldVoxelPos, height: impl Fn(i64, i64) -> Option<i64>) -> VillagePlan {
+        let mut rng = config.chunk_rng(center.chunk(), VILLAGE_SALT + 1);
+        let prefabs = prefabs();
+        let mut buildings: Vec<Building> = Vec::new();
+        for _ in 0..self.max_buildings * 3 {
+            if buildings.len() >= self.max_buildings {
+                break;
+            }
+            let prefab = rng.pick(&prefabs).unwrap().rotated_y(rng.range(0, 4) as u32);
+            let radius = self.radius as i32;
+            let x = center.x + rng.range(-radius, radius + 1) as i64 - prefab.size().x as i64 / 2;
+            let z = center.z + rng.range(-radius, radius + 1) as i64 - prefab.size().z as i64 / 2;
+            let mut building = Building { min: WorldVoxelPos::new(x, 0, z), prefab };
+
+            // Leave a gap of two voxels between buildings
+            let (min_x, min_z, max_x, max_z) = building.footprint(2);
+            let overlaps = buildings.iter().any(|other| {
+                let (other_min_x, other_min_z, other_max_x, other_max_z) = other.footprint(0);
+                min_x <= other_max_x && other_min_x <= max_x && min_z <= other_max_z && other_min_z <= max_z
+            });
+            if overlaps {
+                continue;
+            }
+
+            let (min_x, min_z, max_x, max_z) = building.footprint(0);
+            let heights = (min_x..=max_x).flat_map(|x| (min_z..=max_z).map(move |z| (x, z))).map(|(x, z)| height(x, z)).collect::<Vec<_>>();
+            let Some((lowest, highest)) = relief(heights.iter().copied()) else {
+                continue;
+            };
+            if highest - lowest > self.max_relief {
+                continue;
+            }
+            let sum = heights.iter().flatten().sum::<i64>();
+            building.min.y = (sum as f64 / heights.len() as f64).round() as i64;
+            buildings.push(building);
+        }
+        VillagePlan { center, buildings }
+    }
+}
+
+impl GenerationStage for VillageGenerator {
+    fn stage(&self) -> Stage {
+        Stage::Decoration
+    }
+
+    fn apply(&self, config: &WorldGeneratorConfig, context: &GenerationContext, chunk: &mut Chunk, overflow: &mut PendingEdits) {
+        let Some(center) = self.center(config, context, chunk.position) else {
+            return;
+        };
+        if center.chunk() != chunk.position {
+            return;
+        }
+        let plan = self.plan(config, center, |x, z| context.surface_height(config, x, z));
+        if plan.buildings.len() >= self.min_buildings {
+            plan.write(overflow, self.max_relief);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::generator::FlatWorldGenerator;
+
+    #[test]
+    fn test_buildings_need_flat_ground() {
+        let villages = VillageGenerator::default();
+        let config = WorldGeneratorConfig::default_with(FlatWorldGenerator::default());
+        let center = WorldVoxelPos::new(100, 10, -40);
+
+        // Gentle slope, every building gets a level spot at the average height of its footprint
+        let plan = villages.plan(&config, center, |x, _| Some(10 + x / 4));
+        assert!(plan.buildings.len() >= villages.min_buildings);
+        for (i, building) in plan.buildings.iter().enumerate() {
+            let (min_x, min_z, max_x, max_z) = building.footprint(0);
+            assert!((10 + min_x / 4..=10 + max_x / 4).contains(&building.min.y));
+            for other in plan.buildings[i + 1..].iter() {
+                let (other_min_x, other_min_z, other_max_x, other_max_z) = other.footprint(0);
+                assert!(max_x < other_min_x || other_max_x < min_x || max_z < other_min_z || other_max_z < min_z);
+            }
+        }
+        assert_eq!(plan, villages.plan(&config, center, |x, _| Some(10 + x / 4)));
+
+        // Too steep, or not known at all
+        assert!(villages.plan(&config, center, |x, _| Some(x * 2)).buildings.is_empty());
+        assert!(villages.plan(&config, center, |_, _| None).buildings.is_empty());
+    }
+
+    #[test]
+    fn test_relief() {
+        assert_eq!(relief([Some(3), Some(1), Some(2)]), Some((1, 3)));
+        assert_eq!(relief([Some(3), None]), None);
+    }
+}
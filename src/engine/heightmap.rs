@@ -0,0 +1,54 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use super::{chunk::{Chunk, CHUNK_SIZE}, coords::{LocalVoxelPos, WorldVoxelPos}, generator::WorldGeneratorConfig};
+
+/// Caches the surface height (y of the highest solid voxel) of world columns.
+/// Heights come from the generator when it can compute them directly,
+/// otherwise from the highest solid voxel seen in generated chunks.
+#[derive(Resource, Default)]
+pub struct HeightmapCache {
+    columns: HashMap<(i64, i64), i64>,
+}
+
+impl HeightmapCache {
+    pub fn get(&self, x: i64, z: i64) -> Option<i64> {
+        self.columns.get(&(x, z)).copied()
+    }
+
+    /// Returns the surface height of a column, asking the generator if it is not cached yet
+    pub fn surface_height(&mut self, config: &WorldGeneratorConfig, x: i64, z: i64) -> Option<i64> {
+        if let Some(height) = self.get(x, z) {
+            return Some(height);
+        }
+        let height = config.generator.surface_height(config, x, z)?;
+        self.columns.insert((x, z), height);
+        Some(height)
+    }
+
+    /// Updates the cached columns with the highest solid voxels of a chunk
+    pub fn record_chunk(&mut self, chunk: &Chunk) {
+        let reader = chunk.reader();
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let Some(y) = (0..CHUNK_SIZE).rev().find(|y| !reader.get(x, *y, z).is_empty()) else {
+                    continue;
+                };
+                let pos = WorldVoxelPos::from_local(&chunk.position, LocalVoxelPos::new(x as u8, y as u8, z as u8));
+                let height = self.columns.entry((pos.x, pos.z)).or_insert(pos.y);
+                *height = (*height).max(pos.y);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.columns.clear();
+    }
+}
@@ -0,0 +1,98 @@
+//! Shape of the region chunks are streamed in around an anchor.
+//!
+//! The visibility search only goes to chunks the [`LoadPolicy`] of the
+//! [`WorldGeneratorConfig`](super::generator::WorldGeneratorConfig) contains, and the garbage
+//! collector keeps chunks in its reach. A sphere treats every direction the same, a cube also
+//! keeps the corners, which suits worlds organized in columns or regions, and a cone streams
+//! far ahead of the camera but only a shorter distance towards the edges of the view.
+
+use bevy::prelude::*;
+
+use super::chunk::ChunkPosition;
+
+#[derive(Debug, Clone, Copy, PartialEq, Reflect)]
+pub enum LoadPolicy {
+    /// Chunks up to the generation distance from the anchor
+    Sphere,
+    /// Chunks up to the generation distance from the anchor along every axis
+    Cube,
+    /// Chunks up to the generation distance inside the cone around the view direction,
+    /// and up to `side_distance` × the generation distance outside of it
+    Cone {
+        /// Angle between the view direction and the side of the cone, in radians
+        half_angle: f32,
+        side_distance: f32,
+    },
+}
+
+impl Default for LoadPolicy {
+    fn default() -> Self {
+        Self::Sphere
+    }
+}
+
+impl LoadPolicy {
+    /// A cone about as wide as the middle third of a default camera view, with half the distance beside it
+    pub fn forward_cone() -> Self {
+        Self::Cone { half_angle: 15f32.to_radians(), side_distance: 0.5 }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Sphere => "Sphere",
+            Self::Cube => "Cube",
+            Self::Cone { .. } => "Cone",
+        }
+    }
+
+    /// `chunk` should be loaded for an anchor in `anchor` looking along `forward`
+    pub fn contains(&self, anchor: &ChunkPosition, forward: Vec3, chunk: &ChunkPosition, distance: usize) -> bool {
+        match self {
+            Self::Sphere | Self::Cube => self.in_reach(anchor, chunk, distance),
+            Self::Cone { half_angle, side_distance } => {
+                let offset = IVec3::new(chunk.x - anchor.x, chunk.y - anchor.y, chunk.z - anchor.z).as_vec3();
+                let inside = offset == Vec3::ZERO || offset.angle_between(forward) <= *half_angle;
+                let limit = if inside { distance as f32 } else { distance as f32 * side_distance };
+                offset.length() <= limit
+            }
+        }
+    }
+
+    /// `chunk` would be loaded for an anchor in `anchor` looking in some direction,
+    /// chunks out of reach of every anchor can be unloaded
+    pub fn in_reach(&self, anchor: &ChunkPosition, chunk: &ChunkPosition, distance: usize) -> bool {
+        match self {
+            Self::Sphere | Self::Cone { .. } => anchor.distance_squared_to(chunk) <= (distance * distance) as i64,
+            Self::Cube => {
+                let distance = distance as i32;
+                (chunk.x - anchor.x).abs() <= distance && (chunk.y - anchor.y).abs() <= distance && (chunk.z - anchor.z).abs() <= distance
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_shapes() {
+        let anchor = ChunkPosition::new(10, 0, -5);
+        let at = |x: i32, y: i32, z: i32| ChunkPosition::new(anchor.x + x, anchor.y + y, anchor.z + z);
+
+        // The corner is too far for the sphere
+        assert!(LoadPolicy::Sphere.contains(&anchor, Vec3::NEG_Z, &at(0, 0, 4), 4));
+        assert!(!LoadPolicy::Sphere.contains(&anchor, Vec3::NEG_Z, &at(4, 4, 4), 4));
+        assert!(LoadPolicy::Cube.contains(&anchor, Vec3::NEG_Z, &at(4, 4, 4), 4));
+        assert!(!LoadPolicy::Cube.contains(&anchor, Vec3::NEG_Z, &at(5, 0, 0), 4));
+
+        let cone = LoadPolicy::forward_cone();
+        assert!(cone.contains(&anchor, Vec3::NEG_Z, &anchor, 8));
+        assert!(cone.contains(&anchor, Vec3::NEG_Z, &at(1, 0, -7), 8));
+        assert!(!cone.contains(&anchor, Vec3::NEG_Z, &at(0, 0, 8), 8));
+        assert!(cone.contains(&anchor, Vec3::NEG_Z, &at(0, 0, 4), 8));
+        assert!(!cone.contains(&anchor, Vec3::NEG_Z, &at(5, 0, -5), 8));
+        // Turning around keeps everything the cone loaded in reach
+        assert!(cone.in_reach(&anchor, &at(1, 0, -7), 8));
+    }
+}
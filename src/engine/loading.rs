@@ -0,0 +1,171 @@
+//! Loading screen shown until the chunks around the spawn point are generated and meshed.
+//! The flycam is only enabled afterwards, so the player never flies into a world that is still streaming in.
+//! The progress window needs the `debug-ui` feature, minimal builds just wait without it.
+
+use bevy::prelude::*;
+#[cfg(feature = "debug-ui")]
+use bevy_egui::{egui, EguiContexts};
+
+use super::{
+    anchor::StreamingAnchor,
+    chunk::ChunkPosition,
+    generator::{EmptyChunkMarker, Meshed, WorldGeneratorConfig},
+    pregen::pregenerate_spawn_area,
+    ChunkData,
+};
+use crate::flycam::FlyCam;
+
+/// The game starts in the main menu, loads the chosen world and is then played until it is paused.
+/// Builds without the `debug-ui` feature have no menus and go straight to loading
+#[derive(States, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum AppState {
+    #[default]
+    Menu,
+    Loading,
+    Playing,
+    Paused,
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct LoadingSettings {
+    /// Chunks this far from the spawn chunk on the x and z axes, one layer above and below it, must be ready
+    pub radius: i32,
+    /// Seconds after which the game starts anyway, so a stuck chunk does not block it forever
+    pub max_wait: f32,
+    /// Generates every chunk this far from the spawn chunk at once when loading starts, see [`pregenerate_region`](super::pregen::pregenerate_region)
+    pub pregenerate_radius: Option<u32>,
+}
+
+impl Default for LoadingSettings {
+    fn default() -> Self {
+        Self { radius: 2, max_wait: 30.0, pregenerate_radius: None }
+    }
+}
+
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct LoadingProgress {
+    pub ready: usize,
+    pub total: usize,
+    /// Seconds spent loading
+    pub elapsed: f32,
+}
+
+impl LoadingProgress {
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        self.ready as f32 / self.total as f32
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.ready >= self.total
+    }
+}
+
+/// Chunks that must be ready before a camera at `position` can start moving
+pub fn spawn_area(position: Vec3, radius: i32) -> impl Iterator<Item = ChunkPosition> {
+    let center = ChunkPosition::from_world_position(position);
+    (-1..=1).flat_map(move |y| {
+        (-radius..=radius).flat_map(move |x| (-radius..=radius).map(move |z| ChunkPosition::new(center.x + x, center.y + y, center.z + z)))
+    })
+}
+
+/// Marks cameras whose [`FlyCam`] was taken away while loading
+#[derive(Component)]
+struct FrozenFlyCam;
+
+pub struct LoadingPlugin;
+
+impl Plugin for LoadingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<AppState>()
+            .init_resource::<LoadingSettings>()
+            .init_resource::<LoadingProgress>()
+            .add_systems(OnEnter(AppState::Loading), (reset_progress, freeze_cameras, pregenerate_spawn_area))
+            .add_systems(OnEnter(AppState::Playing), unfreeze_cameras)
+            .add_systems(Update, track_loading.run_if(in_state(AppState::Loading)));
+
+        #[cfg(feature = "debug-ui")]
+        app.add_systems(Update, show_loading_screen.after(track_loading).run_if(in_state(AppState::Loading)));
+    }
+}
+
+fn reset_progress(mut progress: ResMut<LoadingProgress>) {
+    *progress = LoadingProgress::default();
+}
+
+/// Takes the [`FlyCam`] away from every camera so it stops following input, they get it back once the game is played
+pub(crate) fn freeze_cameras(mut commands: Commands, cameras: Query<Entity, With<FlyCam>>) {
+    for entity in cameras.iter() {
+        commands.entity(entity).remove::<FlyCam>().insert(FrozenFlyCam);
+    }
+}
+
+fn unfreeze_cameras(mut commands: Commands, cameras: Query<Entity, With<FrozenFlyCam>>) {
+    for entity in cameras.iter() {
+        commands.entity(entity).remove::<FrozenFlyCam>().insert(FlyCam);
+    }
+}
+
+fn track_loading(
+    mut progress: ResMut<LoadingProgress>,
+    mut next_state: ResMut<NextState<AppState>>,
+    chunk_data: Res<ChunkData>,
+    settings: Res<LoadingSettings>,
+    config: Res<WorldGeneratorConfig>,
+    time: Res<Time>,
+    camera: Query<&Transform, With<StreamingAnchor>>,
+    ready_chunks: Query<(), Or<(With<Meshed>, With<EmptyChunkMarker>)>>,
+) {
+    let Some(position) = camera.iter().next().map(|transform| transform.translation) else {
+        return;
+    };
+    progress.elapsed += time.delta_seconds();
+
+    let (mut ready, mut total) = (0, 0);
+    for chunk_pos in spawn_area(position, settings.radius).filter(|chunk_pos| !config.is_outside_world(chunk_pos)) {
+        total += 1;
+        // Chunks without any faces never get a mesh
+        let is_ready = chunk_data.loaded.get(&chunk_pos).is_some_and(|entity| ready_chunks.contains(*entity));
+        if is_ready {
+            ready += 1;
+        }
+    }
+    progress.ready = ready;
+    progress.total = total;
+
+    if progress.is_done() {
+        info!("World loaded in {:.1}s", progress.elapsed);
+        next_state.set(AppState::Playing);
+    } else if progress.elapsed > settings.max_wait {
+        warn!("Loading took longer than {}s, starting with {} of {} chunks ready", settings.max_wait, ready, total);
+        next_state.set(AppState::Playing);
+    }
+}
+
+#[cfg(feature = "debug-ui")]
+fn show_loading_screen(mut contexts: EguiContexts, progress: Res<LoadingProgress>) {
+    egui::Window::new("Loading World")
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.add(egui::ProgressBar::new(progress.fraction()).desired_width(300.0).show_percentage());
+            ui.label(format!("{} / {} chunks around the spawn point", progress.ready, progress.total));
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spawn_area() {
+        let area = spawn_area(Vec3::new(-1.0, 20.0, 8.0), 1).collect::<Vec<_>>();
+        assert_eq!(area.len(), 27);
+        assert!(area.contains(&ChunkPosition::new(-1, 0, 0)));
+        assert!(area.contains(&ChunkPosition::new(-2, 2, 1)));
+        assert!(!area.contains(&ChunkPosition::new(0, 3, 0)));
+    }
+}
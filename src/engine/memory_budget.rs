@@ -0,0 +1,99 @@
+//! Upper limit for the memory used by loaded chunks, counting their voxels and their meshes.
+//!
+//! The garbage collector measures the usage every frame. Over budget it evicts chunks that are not
+//! visible, farthest from the camera first, even if they are still within the generation distance.
+//! Visible and pinned chunks are never evicted, so the budget can still be exceeded when too many
+//! of them are loaded. Unloaded chunks kept in the [`ChunkCache`] are limited separately.
+
+use bevy::prelude::*;
+
+use super::{anchor::distance_squared_to_closest, cache::ChunkCache, chunk::ChunkPosition, stats::mesh_bytes, ChunkData};
+
+#[derive(Resource, Debug, Clone)]
+pub struct MemoryBudget {
+    pub budget_mb: usize,
+    /// Usage measured by the last garbage collector run
+    pub usage: MemoryUsage,
+}
+
+impl Default for MemoryBudget {
+    fn default() -> Self {
+        Self { budget_mb: 512, usage: MemoryUsage::default() }
+    }
+}
+
+impl MemoryBudget {
+    pub fn budget_bytes(&self) -> usize {
+        self.budget_mb * 1024 * 1024
+    }
+
+    /// Bytes that have to be freed to get back under budget
+    pub fn excess_bytes(&self) -> usize {
+        self.usage.total_bytes().saturating_sub(self.budget_bytes())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub voxel_bytes: usize,
+    pub mesh_bytes: usize,
+}
+
+impl MemoryUsage {
+    pub fn measure(chunk_data: &ChunkData, meshes: &Assets<Mesh>) -> Self {
+        Self {
+            voxel_bytes: chunk_data.loaded.len() * ChunkCache::chunk_size_bytes(),
+            mesh_bytes: chunk_data.meshes.values().filter_map(|handle| meshes.get(handle)).map(mesh_bytes).sum(),
+        }
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        self.voxel_bytes + self.mesh_bytes
+    }
+
+    /// Memory freed by unloading a single chunk
+    pub fn chunk_bytes(chunk_data: &ChunkData, meshes: &Assets<Mesh>, chunk: &ChunkPosition) -> usize {
+        let mesh = chunk_data.meshes.get(chunk).and_then(|handle| meshes.get(handle)).map_or(0, mesh_bytes);
+        ChunkCache::chunk_size_bytes() + mesh
+    }
+}
+
+/// Picks chunks to evict, farthest from the closest of `anchors` first, until `excess` bytes are freed.
+/// `candidates` are positions with the memory unloading them frees.
+pub fn select_evictions(anchors: &[ChunkPosition], mut candidates: Vec<(ChunkPosition, usize)>, excess: usize) -> Vec<ChunkPosition> {
+    candidates.sort_by_key(|(chunk, _)| std::cmp::Reverse(distance_squared_to_closest(anchors, chunk)));
+    let mut freed = 0;
+    candidates
+        .into_iter()
+        .take_while(|(_, bytes)| {
+            let needed = freed < excess;
+            freed += bytes;
+            needed
+        })
+        .map(|(chunk, _)| chunk)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_farthest_first() {
+        let center = [ChunkPosition::new(0, 0, 0)];
+        let candidates = [(1, 100), (5, 100), (3, 250), (-4, 100)]
+            .into_iter()
+            .map(|(x, bytes)| (ChunkPosition::new(x, 0, 0), bytes))
+            .collect::<Vec<_>>();
+        let at = |x| ChunkPosition::new(x, 0, 0);
+
+        assert!(select_evictions(&center, candidates.clone(), 0).is_empty());
+        assert_eq!(select_evictions(&center, candidates.clone(), 100), vec![at(5)]);
+        assert_eq!(select_evictions(&center, candidates.clone(), 150), vec![at(5), at(-4)]);
+        assert_eq!(select_evictions(&center, candidates.clone(), 10_000).len(), 4);
+
+        // With a second anchor next to it, the chunk at 5 is the closest one
+        let anchors = [ChunkPosition::new(0, 0, 0), ChunkPosition::new(6, 0, 0)];
+        assert_eq!(select_evictions(&anchors, candidates.clone(), 150), vec![at(-4), at(3)]);
+    }
+}
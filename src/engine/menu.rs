@@ -0,0 +1,290 @@
+//! Main menu and pause menu, see [`AppState`].
+//!
+//! The main menu continues the last played world, picks another saved world or starts a new one
+//! with a chosen name, generator and seed, every world is kept in its own directory of the saves.
+//! `Escape` pauses the game: generation stops, the cursor is released and the pause menu offers to
+//! resume, save, go back to the main menu or quit. Generation runs again once the game is resumed.
+//! The menus need the `debug-ui` feature, minimal builds skip the main menu and pause without one.
+
+use bevy::{
+    prelude::*,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+
+use super::{
+    generator::GeneratorState,
+    loading::{freeze_cameras, AppState},
+    shutdown::write_world,
+};
+
+const PAUSE_KEY: KeyCode = KeyCode::Escape;
+
+/// Writes the world to disk without stopping the game
+#[derive(Event, Debug, Clone, Copy, Default)]
+pub struct SaveWorld;
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SaveWorld>()
+            .add_systems(OnEnter(AppState::Menu), (pause_generator, freeze_cameras))
+            .add_systems(OnEnter(AppState::Paused), (pause_generator, freeze_cameras))
+            .add_systems(OnExit(AppState::Paused), resume_generator)
+            .add_systems(OnEnter(AppState::Loading), resume_generator)
+            .add_systems(OnEnter(AppState::Playing), grab_cursor)
+            .add_systems(Update, toggle_pause.run_if(in_state(AppState::Playing).or_else(in_state(AppState::Paused))))
+            // After the flycam, which toggles the cursor with its own key
+            .add_systems(PostUpdate, release_cursor.run_if(in_state(AppState::Menu).or_else(in_state(AppState::Paused))))
+            .add_systems(PostUpdate, save_world_on_request.run_if(on_event::<SaveWorld>()));
+
+        #[cfg(feature = "debug-ui")]
+        app.init_resource::<WorldList>()
+            .add_systems(OnEnter(AppState::Menu), refresh_world_list)
+            .add_systems(Update, (show_main_menu.run_if(in_state(AppState::Menu)), show_pause_menu.run_if(in_state(AppState::Paused))));
+
+        // There is no menu to start from
+        #[cfg(not(feature = "debug-ui"))]
+        app.insert_resource(NextState(Some(AppState::Loading)));
+    }
+}
+
+fn pause_generator(mut generator_state: ResMut<GeneratorState>) {
+    *generator_state = GeneratorState::Paused;
+}
+
+fn resume_generator(mut generator_state: ResMut<GeneratorState>) {
+    *generator_state = GeneratorState::Generating;
+}
+
+fn toggle_pause(keys: Res<Input<KeyCode>>, state: Res<State<AppState>>, mut next_state: ResMut<NextState<AppState>>) {
+    if keys.just_pressed(PAUSE_KEY) {
+        next_state.set(if *state.get() == AppState::Paused { AppState::Playing } else { AppState::Paused });
+    }
+}
+
+fn set_cursor_grab(windows: &mut Query<&mut Window, With<PrimaryWindow>>, grab: bool) {
+    let Ok(mut window) = windows.get_single_mut() else {
+        return;
+    };
+    let grab_mode = if grab { CursorGrabMode::Confined } else { CursorGrabMode::None };
+    // Only touch the window on a change, it is synced to the OS when it changes
+    if window.cursor.grab_mode != grab_mode {
+        window.cursor.grab_mode = grab_mode;
+        window.cursor.visible = !grab;
+    }
+}
+
+fn grab_cursor(mut windows: Query<&mut Window, With<PrimaryWindow>>) {
+    set_cursor_grab(&mut windows, true);
+}
+
+fn release_cursor(mut windows: Query<&mut Window, With<PrimaryWindow>>) {
+    set_cursor_grab(&mut windows, false);
+}
+
+fn save_world_on_request(world: &mut World) {
+    world.resource_mut::<Events<SaveWorld>>().clear();
+    info!("Saving world");
+    write_world(world);
+}
+
+#[cfg(feature = "debug-ui")]
+const GENERATOR_PRESETS: [&str; 6] = ["perlin", "perlin+surface+caves+dungeons", "perlin+layers+water+caves", "density", "flat", "test-pattern"];
+
+/// Saved worlds listed by the main menu, read when the menu opens and after a world is created
+/// instead of every frame
+#[cfg(feature = "debug-ui")]
+#[derive(Resource, Default)]
+struct WorldList {
+    worlds: Vec<super::world_manager::WorldInfo>,
+    error: Option<String>,
+}
+
+#[cfg(feature = "debug-ui")]
+impl WorldList {
+    fn refresh(&mut self, manager: &super::world_manager::WorldManager) {
+        (self.worlds, self.error) = match manager.list() {
+            Ok(worlds) => (worlds, None),
+            Err(err) => (Vec::new(), Some(err.to_string())),
+        };
+    }
+}
+
+#[cfg(feature = "debug-ui")]
+fn refresh_world_list(mut worlds: ResMut<WorldList>, manager: Res<super::world_manager::WorldManager>) {
+    worlds.refresh(&manager);
+}
+
+#[cfg(feature = "debug-ui")]
+struct NewWorldForm {
+    name: String,
+    generator: String,
+    seed: u32,
+    error: Option<String>,
+}
+
+#[cfg(feature = "debug-ui")]
+fn show_main_menu(
+    mut contexts: bevy_egui::EguiContexts,
+    mut manager: ResMut<super::world_manager::WorldManager>,
+    mut worlds: ResMut<WorldList>,
+    mut open_world: EventWriter<super::world_manager::OpenWorld>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut exit: EventWriter<bevy::app::AppExit>,
+    metadata: Res<super::world_meta::WorldMetadata>,
+    mut form: Local<Option<NewWorldForm>>,
+) {
+    use bevy_egui::egui;
+
+    let form = form.get_or_insert_with(|| NewWorldForm {
+        name: "New World".to_string(),
+        generator: GENERATOR_PRESETS[0].to_string(),
+        seed: super::generator::PerlinHeightmapWorldGenerator::default().seed,
+        error: None,
+    });
+
+    egui::Window::new("Main Menu")
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            if ui.button(format!("Continue \"{}\"", metadata.name)).clicked() {
+                next_state.set(AppState::Loading);
+            }
+
+            ui.separator();
+
+            ui.label("Worlds");
+            if let Some(err) = &worlds.error {
+                ui.colored_label(egui::Color32::RED, format!("Failed to list worlds: {}", err));
+            }
+            let mut play = None;
+            egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                egui::Grid::new("menu_worlds").striped(true).show(ui, |ui| {
+                    for world in worlds.worlds.iter() {
+                        ui.label(&world.metadata.name);
+                        ui.label(world.metadata.summary());
+                        if ui.button("Play").clicked() {
+                            play = Some(world.dir.clone());
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+            match play {
+                // The open world only has to be loaded
+                Some(dir) if manager.current() == Some(dir.as_str()) => next_state.set(AppState::Loading),
+                Some(dir) => open_world.send(super::world_manager::OpenWorld { dir }),
+                None => {}
+            }
+
+            ui.separator();
+
+            ui.label("New World");
+            ui.horizontal(|ui| {
+                ui.label("Name");
+                ui.text_edit_singleline(&mut form.name);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Generator");
+                ui.text_edit_singleline(&mut form.generator);
+                egui::ComboBox::from_id_source("generator_presets").selected_text("Presets").show_ui(ui, |ui| {
+                    for preset in GENERATOR_PRESETS {
+                        if ui.selectable_label(form.generator == preset, preset).clicked() {
+                            form.generator = preset.to_string();
+                        }
+                    }
+                });
+            });
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut form.seed).prefix("Seed: "));
+                if ui.button("Random").clicked() {
+                    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |duration| duration.subsec_nanos());
+                    form.seed = nanos.wrapping_mul(0x9E37_79B9);
+                }
+            });
+            if ui.button("Start").clicked() {
+                // Opening the world moves on to loading it
+                match manager.create(&form.name, &form.generator, form.seed) {
+                    Ok(dir) => {
+                        form.error = None;
+                        worlds.refresh(&manager);
+                        open_world.send(super::world_manager::OpenWorld { dir });
+                    }
+                    Err(err) => form.error = Some(err.to_string()),
+                }
+            }
+            if let Some(error) = &form.error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+
+            ui.separator();
+
+            if ui.button("Quit").clicked() {
+                exit.send(bevy::app::AppExit);
+            }
+        });
+}
+
+#[cfg(feature = "debug-ui")]
+fn show_pause_menu(
+    mut contexts: bevy_egui::EguiContexts,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut save: EventWriter<SaveWorld>,
+    mut exit: EventWriter<bevy::app::AppExit>,
+) {
+    use bevy_egui::egui;
+    egui::Window::new("Paused")
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            if ui.button("Resume").clicked() {
+                next_state.set(AppState::Playing);
+            }
+            if ui.button("Save").clicked() {
+                save.send(SaveWorld);
+            }
+            if ui.button("Main Menu").clicked() {
+                save.send(SaveWorld);
+                next_state.set(AppState::Menu);
+            }
+            if ui.button("Quit").clicked() {
+                exit.send(bevy::app::AppExit);
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pausing_stops_the_generator() {
+        let mut app = App::new();
+        app.add_state::<AppState>()
+            .insert_resource(GeneratorState::Generating)
+            .init_resource::<Input<KeyCode>>()
+            .add_systems(OnEnter(AppState::Paused), pause_generator)
+            .add_systems(OnExit(AppState::Paused), resume_generator)
+            .add_systems(Update, toggle_pause.run_if(in_state(AppState::Playing).or_else(in_state(AppState::Paused))));
+        app.world.resource_mut::<NextState<AppState>>().set(AppState::Playing);
+        app.update();
+
+        let press = |app: &mut App| {
+            app.world.resource_mut::<Input<KeyCode>>().press(PAUSE_KEY);
+            app.update();
+            app.world.resource_mut::<Input<KeyCode>>().reset(PAUSE_KEY);
+            // The transition happens at the start of the next frame
+            app.update();
+        };
+        press(&mut app);
+        assert_eq!(*app.world.resource::<State<AppState>>().get(), AppState::Paused);
+        assert_eq!(*app.world.resource::<GeneratorState>(), GeneratorState::Paused);
+
+        press(&mut app);
+        assert_eq!(*app.world.resource::<State<AppState>>().get(), AppState::Playing);
+        assert_eq!(*app.world.resource::<GeneratorState>(), GeneratorState::Generating);
+    }
+}
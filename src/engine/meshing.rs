@@ -0,0 +1,734 @@
+//! Turning the voxels of a chunk into triangles. [`mesh_chunk`] works on plain voxel buffers and
+//! returns plain vertex buffers, [`Chunk::build`](super::chunk::Chunk::build) turns them into a
+//! Bevy [`Mesh`]. Tools and tests can mesh voxels without a chunk, an app or a render world.
+//!
+//! Full blocks go through the greedy mesher, which merges neighbouring faces of the same voxel and
+//! tint, unless the [`MeshingStrategy`] resource says otherwise. Shaped blocks are meshed on their own
+//! afterwards, see [`super::shapes`].
+//!
+//! Every face of a chunk is axis aligned, so with [`ChunkVertexFormat::PackedFace`] a mesh carries
+//! the face number in [`ATTRIBUTE_FACE`] instead of normals, uvs and tangents and the chunk material
+//! derives them in its vertex shader. That is 16 instead of 48 bytes for an untinted vertex.
+
+use std::{cell::RefCell, str::FromStr, sync::Arc};
+
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::{Color, IVec3, Mesh, Res, Resource, Vec3, World},
+    render::{
+        mesh::{Indices, MeshVertexAttribute, VertexAttributeValues},
+        render_resource::{PrimitiveTopology, VertexFormat},
+    },
+};
+use block_mesh::{
+    ndshape::ConstShape, greedy_quads, visible_block_faces, GreedyQuadsBuffer, MergeVoxel, OrientedBlockFace, UnitQuadBuffer,
+    UnorientedQuad, UnorientedUnitQuad, VoxelVisibility, RIGHT_HANDED_Y_UP_CONFIG,
+};
+
+use super::{
+    block_registry::BlockRegistry,
+    chunk::{Chunk, ChunkMetadata, ChunkPosition, CHUNK_SIZE},
+    coords::LocalVoxelPos,
+    shapes::{ShapeQuad, ShapeRegistry, VoxelShape},
+    tint::{ChunkTints, Tint},
+    util::Face,
+    voxel::Voxel,
+};
+
+const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+/// Faces of a chunk by [`Face::as_face_number`]
+const CHUNK_FACES: [Face; 6] = [Face::Left, Face::Right, Face::Bottom, Face::Top, Face::Back, Face::Front];
+
+/// The shape of a chunk with padding of 1 on each side
+type ChunkNDShapePadded = block_mesh::ndshape::ConstShape3u32<{ CHUNK_SIZE as u32 + 2 }, { CHUNK_SIZE as u32 + 2 }, { CHUNK_SIZE as u32 + 2 }>;
+
+/// [`Face::as_face_number`] of the face a vertex belongs to, for [`ChunkVertexFormat::PackedFace`]
+pub const ATTRIBUTE_FACE: MeshVertexAttribute = MeshVertexAttribute::new("Vertex_Face", 640_191_022, VertexFormat::Uint32);
+
+/// How full blocks are turned into quads. Only greedy meshing is meant for playing, the others are
+/// for comparing mesh sizes and meshing times and for telling greedy merge artifacts from others.
+/// Chunks are meshed with the strategy in this resource, meshes that exist already are kept.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MeshingStrategy {
+    /// Merges neighbouring faces of the same voxel and tint into large quads
+    #[default]
+    Greedy = 0,
+    /// One quad for every face that is not hidden by an opaque neighbour
+    Culled = 1,
+    /// One quad for every face of every voxel, hidden or not
+    Naive = 2,
+}
+
+impl MeshingStrategy {
+    pub const ALL: [MeshingStrategy; 3] = [MeshingStrategy::Greedy, MeshingStrategy::Culled, MeshingStrategy::Naive];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Greedy => "greedy",
+            Self::Culled => "culled",
+            Self::Naive => "naive",
+        }
+    }
+}
+
+impl FromStr for MeshingStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|strategy| strategy.name() == s).ok_or_else(|| format!("unknown meshing strategy `{}`", s))
+    }
+}
+
+/// Vertex attributes of chunk meshes. New meshes get the format in this resource, meshes that
+/// exist already are kept.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChunkVertexFormat {
+    /// Positions, normals, uvs and tangents, drawn by the standard PBR pipeline
+    #[default]
+    Standard = 0,
+    /// Positions and [`ATTRIBUTE_FACE`], the chunk material's vertex shader derives the rest.
+    /// Not drawn into normal or deferred prepasses, which expect normals.
+    PackedFace = 1,
+}
+
+impl ChunkVertexFormat {
+    pub const ALL: [ChunkVertexFormat; 2] = [ChunkVertexFormat::Standard, ChunkVertexFormat::PackedFace];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Standard => "standard",
+            Self::PackedFace => "packed",
+        }
+    }
+}
+
+impl FromStr for ChunkVertexFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL.into_iter().find(|format| format.name() == s).ok_or_else(|| format!("unknown vertex format `{}`", s))
+    }
+}
+
+/// The registries and settings chunks are meshed with, a snapshot of their resources meshing tasks
+/// take to their thread. Cheap to clone
+#[derive(Debug, Clone, Default)]
+pub struct ChunkMesher {
+    pub blocks: BlockRegistry,
+    pub shapes: ShapeRegistry,
+    pub strategy: MeshingStrategy,
+    pub format: ChunkVertexFormat,
+}
+
+impl ChunkMesher {
+    /// From the resources, resources that do not exist are left at their defaults
+    pub fn from_world(world: &World) -> Self {
+        Self {
+            blocks: world.get_resource::<BlockRegistry>().cloned().unwrap_or_default(),
+            shapes: world.get_resource::<ShapeRegistry>().cloned().unwrap_or_default(),
+            strategy: world.get_resource::<MeshingStrategy>().copied().unwrap_or_default(),
+            format: world.get_resource::<ChunkVertexFormat>().copied().unwrap_or_default(),
+        }
+    }
+}
+
+/// System parameter reading the resources of a [`ChunkMesher`]
+#[derive(SystemParam)]
+pub struct MeshingResources<'w> {
+    blocks: Res<'w, BlockRegistry>,
+    shapes: Res<'w, ShapeRegistry>,
+    strategy: Res<'w, MeshingStrategy>,
+    format: Res<'w, ChunkVertexFormat>,
+}
+
+impl MeshingResources<'_> {
+    pub fn mesher(&self) -> ChunkMesher {
+        ChunkMesher { blocks: self.blocks.clone(), shapes: self.shapes.clone(), strategy: *self.strategy, format: *self.format }
+    }
+
+    pub fn blocks(&self) -> &BlockRegistry {
+        &self.blocks
+    }
+}
+
+/// Which voxels of a chunk a mesh is made of. Translucent blocks get a mesh of their own so they
+/// can be drawn blended after everything opaque.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MeshLayer {
+    /// Every block that is not [`BlockRegistry::is_translucent`], translucent voxels are left out
+    /// as if they were empty
+    #[default]
+    Solid,
+    /// Only translucent blocks like water, their faces against opaque blocks are hidden
+    Translucent,
+}
+
+/// Both meshes of a chunk, `M` is [`Mesh`] or a handle to one
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkMeshes<M = Mesh> {
+    pub solid: Option<M>,
+    pub translucent: Option<M>,
+}
+
+impl<M> ChunkMeshes<M> {
+    /// Nothing to draw
+    pub fn is_empty(&self) -> bool {
+        self.solid.is_none() && self.translucent.is_none()
+    }
+
+    pub fn map<T>(self, mut f: impl FnMut(M) -> T) -> ChunkMeshes<T> {
+        ChunkMeshes { solid: self.solid.map(&mut f), translucent: self.translucent.map(f) }
+    }
+}
+
+/// Everything [`mesh_chunk`] needs besides the voxels
+#[derive(Clone, Copy)]
+pub struct MeshOptions<'a> {
+    pub blocks: &'a BlockRegistry,
+    pub shapes: &'a ShapeRegistry,
+    pub strategy: MeshingStrategy,
+    /// Shaped voxels are turned by the first byte of their metadata
+    pub metadata: Option<&'a ChunkMetadata>,
+    /// Vertex colors are only added when a voxel is tinted
+    pub tints: Option<&'a ChunkTints>,
+    /// Color of untinted voxels of [`BlockRegistry::is_biome_tinted`] blocks, see [`super::biome`]
+    pub biome_tint: Option<Tint>,
+    pub layer: MeshLayer,
+    /// Voxels of the neighbours, the translucent layer hides its faces against them
+    pub borders: Option<&'a ChunkBorders>,
+}
+
+impl<'a> MeshOptions<'a> {
+    /// Greedy meshing of untinted voxels without metadata
+    pub fn new(blocks: &'a BlockRegistry, shapes: &'a ShapeRegistry) -> Self {
+        Self { blocks, shapes, strategy: MeshingStrategy::Greedy, metadata: None, tints: None, biome_tint: None, layer: MeshLayer::Solid, borders: None }
+    }
+}
+
+/// The layers of voxels of the neighbours that touch a chunk, by [`Face::as_face_number`] of the
+/// face they touch. Water reaching into the next chunk would get a wall where the chunks meet
+/// without them. `None` for neighbours that are not loaded.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChunkBorders {
+    faces: [Option<Vec<Voxel>>; 6],
+}
+
+impl ChunkBorders {
+    /// Copies the touching layer of every neighbour of the chunk at `position` that `neighbour` finds
+    pub fn capture<'c>(position: &ChunkPosition, neighbour: impl Fn(&ChunkPosition) -> Option<&'c Chunk>) -> Self {
+        let mut borders = Self::default();
+        for (neighbour_pos, face) in position.neighbors() {
+            let Some(chunk) = neighbour(&neighbour_pos) else {
+                continue;
+            };
+            borders.faces[face.as_face_number()] = Some(border_voxels(chunk, face.opposite()).collect());
+        }
+        borders
+    }
+
+    /// The neighbour's layer touching `face`
+    pub fn get(&self, face: Face) -> Option<&[Voxel]> {
+        self.faces[face.as_face_number()].as_deref()
+    }
+}
+
+/// The layer of voxels of a chunk on `face`, in the order [`ChunkBorders`] keeps them
+pub fn border_voxels(chunk: &Chunk, face: Face) -> impl Iterator<Item = Voxel> + '_ {
+    border_cells(face, 0).map(|[x, y, z]| chunk.get(LocalVoxelPos::new(x as u8, y as u8, z as u8)))
+}
+
+/// Every voxel of the layer on `face` of a chunk, the same order for opposite faces. `depth` 0 is
+/// the layer inside the chunk, 1 the layer of the neighbour in front of it.
+fn border_cells(face: Face, depth: i32) -> impl Iterator<Item = [i32; 3]> {
+    let (low, high) = (-depth, CHUNK_SIZE as i32 - 1 + depth);
+    (0..CHUNK_SIZE as i32).flat_map(move |u| (0..CHUNK_SIZE as i32).map(move |v| match face {
+        Face::Left => [low, u, v],
+        Face::Right => [high, u, v],
+        Face::Bottom => [u, low, v],
+        Face::Top => [u, high, v],
+        Face::Back => [u, v, low],
+        Face::Front => [u, v, high],
+    }))
+}
+
+/// Vertex buffers of a chunk mesh. Positions are in voxels relative to the chunk origin, every
+/// quad is two triangles.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeshData {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub tangents: Vec<[f32; 4]>,
+    /// Linear vertex colors, `None` when no voxel is tinted
+    pub colors: Option<Vec<[f32; 4]>>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    fn with_capacity(vertices: usize, tinted: bool) -> Self {
+        Self {
+            positions: Vec::with_capacity(vertices),
+            normals: Vec::with_capacity(vertices),
+            uvs: Vec::with_capacity(vertices),
+            tangents: Vec::with_capacity(vertices),
+            colors: tinted.then(|| Vec::with_capacity(vertices)),
+            indices: Vec::with_capacity(vertices / 4 * 6),
+        }
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    pub fn into_mesh(self, format: ChunkVertexFormat) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_indices(Some(Indices::U32(self.indices)));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, VertexAttributeValues::Float32x3(self.positions));
+        match format {
+            ChunkVertexFormat::Standard => {
+                mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, VertexAttributeValues::Float32x3(self.normals));
+                mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, VertexAttributeValues::Float32x2(self.uvs));
+                mesh.insert_attribute(Mesh::ATTRIBUTE_TANGENT, VertexAttributeValues::Float32x4(self.tangents));
+            }
+            ChunkVertexFormat::PackedFace => {
+                let faces = self.normals.iter().map(|normal| face_number(Vec3::from_array(*normal))).collect();
+                mesh.insert_attribute(ATTRIBUTE_FACE, VertexAttributeValues::Uint32(faces));
+            }
+        }
+        if let Some(colors) = self.colors {
+            mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, VertexAttributeValues::Float32x4(colors));
+        }
+        mesh
+    }
+
+    /// Adds a quad of four vertices facing `normal`, `indices` count from its first vertex.
+    /// `color` is only kept by tinted meshes.
+    fn push_quad(&mut self, positions: [[f32; 3]; 4], indices: [u32; 6], normal: Vec3, color: [f32; 4]) {
+        self.indices.extend(indices.map(|index| self.positions.len() as u32 + index));
+        for position in positions {
+            let (uv, tangent) = face_uv_and_tangent(normal, position);
+            self.uvs.push(uv);
+            self.tangents.push(tangent);
+        }
+        self.positions.extend_from_slice(&positions);
+        self.normals.extend([normal.to_array(); 4]);
+        if let Some(colors) = self.colors.as_mut() {
+            colors.extend([color; 4]);
+        }
+    }
+}
+
+/// Meshes the voxels of one chunk, given in buffer order (see [`Chunk::linearize_position`]).
+/// `None` if no voxel of the [`MeshLayer`] is there.
+///
+/// Panics if there are not exactly `CHUNK_SIZE`³ voxels.
+pub fn mesh_chunk(voxels: &[Voxel], opts: MeshOptions) -> Option<MeshData> {
+    assert_eq!(voxels.len(), CHUNK_VOLUME, "a chunk has {} voxels", CHUNK_VOLUME);
+    MESH_SCRATCH.with(|scratch| mesh_in(&mut scratch.borrow_mut(), voxels, opts))
+}
+
+/// Buffers of the mesher, kept by every thread that meshes so remeshing a chunk does not
+/// allocate the padded voxels and the quads again
+struct MeshScratch {
+    /// Voxels of the chunk with padding of 1 on each side, the padding holds the neighbours
+    voxels: Vec<MeshVoxel>,
+    quads: GreedyQuadsBuffer,
+    /// Quads of the strategies that do not merge faces
+    unit_quads: UnitQuadBuffer,
+    /// Shaped voxels of the chunk being meshed, meshed after the greedy quads
+    shaped: Vec<([usize; 3], Arc<VoxelShape>)>,
+}
+
+impl MeshScratch {
+    fn new() -> Self {
+        let size = ChunkNDShapePadded::SIZE as usize;
+        Self { voxels: vec![MeshVoxel::EMPTY; size], quads: GreedyQuadsBuffer::new(size), unit_quads: UnitQuadBuffer::new(), shaped: Vec::new() }
+    }
+}
+
+thread_local! {
+    static MESH_SCRATCH: RefCell<MeshScratch> = RefCell::new(MeshScratch::new());
+}
+
+fn mesh_in(scratch: &mut MeshScratch, voxels: &[Voxel], opts: MeshOptions) -> Option<MeshData> {
+    let MeshOptions { blocks, shapes, strategy, metadata, tints, biome_tint, layer, borders } = opts;
+    let tints = tints.filter(|tints| !tints.is_empty());
+    let palette_index = |index: usize| tints.map_or(0, |tints| tints.palette_index(index));
+    let voxel_at = |x: usize, y: usize, z: usize| voxels[Chunk::linearize_position(x, y, z)];
+
+    let block_shapes = blocks
+        .iter()
+        .map(|(_, definition)| definition.shape.as_deref().and_then(|name| shapes.get(name)))
+        .collect::<Vec<_>>();
+    let shape_of = |voxel: &Voxel| voxel.block().and_then(|block| block_shapes.get(block.index())?.clone());
+
+    // Copy the voxels inside the padding, shaped voxels are left out of the greedy mesher.
+    // Every voxel inside is written, the padding is written below.
+    let MeshScratch { voxels: chunk_data, quads: buffer, unit_quads, shaped } = scratch;
+    shaped.clear();
+    let mut is_empty = true;
+    let mut biome_tinted = false;
+    for x in 0..CHUNK_SIZE {
+        for y in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let index = ChunkNDShapePadded::linearize([x as u32 + 1, y as u32 + 1, z as u32 + 1]);
+                let voxel = voxel_at(x, y, z);
+                let translucent = blocks.is_translucent(voxel);
+                // The solid layer sees through translucent voxels, the translucent one keeps every
+                // voxel so faces against opaque blocks are hidden and drops the other quads later
+                let in_layer = !voxel.is_empty() && translucent == (layer == MeshLayer::Translucent);
+                if in_layer {
+                    is_empty = false;
+                    biome_tinted |= biome_tint.is_some() && blocks.is_biome_tinted(voxel);
+                }
+                match shape_of(&voxel) {
+                    _ if translucent && layer == MeshLayer::Solid => chunk_data[index as usize] = MeshVoxel::EMPTY,
+                    Some(_) if layer == MeshLayer::Translucent => chunk_data[index as usize] = MeshVoxel::EMPTY,
+                    Some(shape) => {
+                        shaped.push(([x, y, z], shape));
+                        chunk_data[index as usize] = MeshVoxel::EMPTY;
+                    }
+                    None => {
+                        let tint = palette_index(Chunk::linearize_position(x, y, z));
+                        chunk_data[index as usize] = MeshVoxel::new(voxel, tint, blocks);
+                    }
+                }
+            }
+        }
+    }
+
+    if is_empty {
+        return None;
+    }
+
+    // Translucent faces against the voxels of a neighbour are hidden like the ones inside the
+    // chunk. The solid layer keeps its faces on the chunk border, its padding stays empty.
+    for face in CHUNK_FACES {
+        let layer_voxels = borders.and_then(|borders| borders.get(face)).filter(|_| layer == MeshLayer::Translucent);
+        for (cell, [x, y, z]) in border_cells(face, 1).enumerate() {
+            let index = ChunkNDShapePadded::linearize([(x + 1) as u32, (y + 1) as u32, (z + 1) as u32]);
+            chunk_data[index as usize] = match layer_voxels.map(|voxels| voxels[cell]) {
+                Some(voxel) if !voxel.is_empty() && shape_of(&voxel).is_none() => MeshVoxel::new(voxel, 0, blocks),
+                _ => MeshVoxel::EMPTY,
+            };
+        }
+    }
+
+    // Generate the quads, the greedy mesher clears the quads of the last chunk itself
+    let faces = RIGHT_HANDED_Y_UP_CONFIG.faces;
+    let num_quads = match strategy {
+        MeshingStrategy::Greedy => {
+            greedy_quads(chunk_data, &ChunkNDShapePadded {}, [0; 3], [CHUNK_SIZE as u32 + 1; 3], &faces, buffer);
+            buffer.quads.num_quads()
+        }
+        MeshingStrategy::Culled => {
+            unit_quads.reset();
+            visible_block_faces(chunk_data, &ChunkNDShapePadded {}, [0; 3], [CHUNK_SIZE as u32 + 1; 3], &faces, unit_quads);
+            unit_quads.num_quads()
+        }
+        MeshingStrategy::Naive => {
+            unit_quads.reset();
+            all_block_faces(chunk_data, unit_quads);
+            unit_quads.num_quads()
+        }
+    };
+
+    let mut mesh = MeshData::with_capacity(num_quads * 4, tints.is_some() || biome_tinted);
+    let biome_color = biome_tint.map_or([1.0; 4], |[r, g, b]| Color::rgb_u8(r, g, b).as_linear_rgba_f32());
+    // A voxel's own tint wins over the biome's
+    let color_of = |index: usize| match palette_index(index) {
+        0 if biome_tinted && blocks.is_biome_tinted(voxels[index]) => biome_color,
+        palette_index => tints.map_or([1.0; 4], |tints| tint_color(tints, palette_index)),
+    };
+    let push_quad = |mesh: &mut MeshData, face: &OrientedBlockFace, quad: &UnorientedQuad| {
+        // Translate positions to remove padding
+        let positions = face.quad_mesh_positions(quad, 1.0).map(|pos| [pos[0] - 1.0, pos[1] - 1.0, pos[2] - 1.0]);
+        // Positions are in voxels relative to the chunk origin, the entity transform moves them into the world
+        debug_assert!(
+            positions.iter().flatten().all(|axis| (0.0..=CHUNK_SIZE as f32).contains(axis)),
+            "mesh vertex outside of the chunk: {:?}", positions
+        );
+        // Merged faces share their tint, the voxel of the face is at the quad minimum
+        let [x, y, z] = quad.minimum.map(|axis| axis as usize - 1);
+        if layer == MeshLayer::Translucent && !blocks.is_translucent(voxel_at(x, y, z)) {
+            return;
+        }
+        let normal = Vec3::from_array(face.quad_mesh_normals()[0]);
+        mesh.push_quad(positions, face.quad_mesh_indices(0), normal, color_of(Chunk::linearize_position(x, y, z)));
+    };
+
+    match strategy {
+        MeshingStrategy::Greedy => {
+            for (group, face) in buffer.quads.groups.iter().zip(faces.iter()) {
+                group.iter().for_each(|quad| push_quad(&mut mesh, face, quad));
+            }
+        }
+        MeshingStrategy::Culled | MeshingStrategy::Naive => {
+            for (group, face) in unit_quads.groups.iter().zip(faces.iter()) {
+                group.iter().for_each(|quad| push_quad(&mut mesh, face, &UnorientedQuad::from(*quad)));
+            }
+        }
+    }
+
+    // Second pass for the shaped voxels, turned by the first byte of their metadata
+    for ([x, y, z], shape) in shaped.drain(..) {
+        let index = Chunk::linearize_position(x, y, z);
+        let quarter_turns = metadata
+            .and_then(|metadata| metadata.get(&(index as u16)))
+            .and_then(|metadata| metadata.as_bytes().first().copied())
+            .unwrap_or(0);
+        let origin = Vec3::new(x as f32, y as f32, z as f32);
+        for quad in shape.quads.iter().map(|quad| quad.rotated(quarter_turns)) {
+            let hidden = quad.cull.map_or(false, |face| {
+                let neighbour = IVec3::new(x as i32, y as i32, z as i32) + face.normal().as_ivec3();
+                let inside = neighbour.cmpge(IVec3::ZERO).all() && neighbour.cmplt(IVec3::splat(CHUNK_SIZE as i32)).all();
+                inside && blocks.is_opaque(voxel_at(neighbour.x as usize, neighbour.y as usize, neighbour.z as usize))
+            });
+            if hidden {
+                continue;
+            }
+            let positions = quad.positions.map(|position| (origin + position).to_array());
+            mesh.push_quad(positions, ShapeQuad::INDICES, quad.normal, color_of(index));
+        }
+    }
+
+    Some(mesh)
+}
+
+/// Every face of every voxel inside the padding that is not empty, for [`MeshingStrategy::Naive`]
+fn all_block_faces(voxels: &[MeshVoxel], output: &mut UnitQuadBuffer) {
+    for x in 1..=CHUNK_SIZE as u32 {
+        for y in 1..=CHUNK_SIZE as u32 {
+            for z in 1..=CHUNK_SIZE as u32 {
+                if voxels[ChunkNDShapePadded::linearize([x, y, z]) as usize].visibility == VoxelVisibility::Empty {
+                    continue;
+                }
+                for group in output.groups.iter_mut() {
+                    group.push(UnorientedUnitQuad { minimum: [x, y, z] });
+                }
+            }
+        }
+    }
+}
+
+/// What the greedy mesher works on, a voxel with the visibility of its block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct MeshVoxel {
+    voxel: Voxel,
+    visibility: VoxelVisibility,
+    /// Palette index + 1 of the tint, faces with different tints are not merged
+    tint: u8,
+}
+
+impl MeshVoxel {
+    const EMPTY: Self = Self { voxel: Voxel::Empty, visibility: VoxelVisibility::Empty, tint: 0 };
+
+    fn new(voxel: Voxel, tint: u8, blocks: &BlockRegistry) -> Self {
+        let visibility = if voxel.is_empty() {
+            VoxelVisibility::Empty
+        } else if blocks.is_opaque(voxel) {
+            VoxelVisibility::Opaque
+        } else {
+            VoxelVisibility::Translucent
+        };
+        Self { voxel, visibility, tint }
+    }
+}
+
+impl block_mesh::Voxel for MeshVoxel {
+    fn get_visibility(&self) -> VoxelVisibility {
+        self.visibility
+    }
+}
+
+impl MergeVoxel for MeshVoxel {
+    type MergeValue = (Voxel, u8);
+
+    fn merge_value(&self) -> Self::MergeValue {
+        (self.voxel, self.tint)
+    }
+}
+
+/// Texture coordinates of a vertex in voxels along its face and the tangent matching them, textures
+/// and normal maps repeat once per voxel. Seen from the front of a face, u goes right and v goes down
+/// the face; on top and bottom faces up is towards -z.
+fn face_uv_and_tangent(normal: Vec3, position: [f32; 3]) -> ([f32; 2], [f32; 4]) {
+    let up = if normal.y != 0.0 { Vec3::NEG_Z } else { Vec3::Y };
+    let right = up.cross(normal);
+    let position = Vec3::from_array(position);
+    // Same handedness mikktspace gives these coordinates, the bitangent `cross(normal, tangent)` is `up`
+    ([position.dot(right), -position.dot(up)], [right.x, right.y, right.z, 1.0])
+}
+
+/// Face number of an axis aligned normal, the vertex shader turns it back into the normal
+fn face_number(normal: Vec3) -> u32 {
+    let face = CHUNK_FACES.into_iter().find(|face| face.normal() == normal);
+    debug_assert!(face.is_some(), "chunk face is not axis aligned: {:?}", normal);
+    face.map_or(0, |face| face.as_face_number() as u32)
+}
+
+/// Linear vertex color of a tint palette index + 1, white when untinted
+fn tint_color(tints: &ChunkTints, palette_index: u8) -> [f32; 4] {
+    match palette_index {
+        0 => [1.0; 4],
+        index => {
+            let [r, g, b] = tints.palette()[index as usize - 1];
+            Color::rgb_u8(r, g, b).as_linear_rgba_f32()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{coords::LocalVoxelPos, voxel::Block};
+
+    fn voxels_with(voxel_at: impl Fn(LocalVoxelPos) -> Voxel) -> Vec<Voxel> {
+        let mut voxels = vec![Voxel::Empty; CHUNK_VOLUME];
+        for pos in LocalVoxelPos::iter() {
+            voxels[pos.index()] = voxel_at(pos);
+        }
+        voxels
+    }
+
+    #[test]
+    fn test_single_voxel_is_a_cube() {
+        let (blocks, shapes) = (BlockRegistry::builtin(), ShapeRegistry::builtin());
+        let opts = MeshOptions::new(&blocks, &shapes);
+        assert!(mesh_chunk(&voxels_with(|_| Voxel::Empty), opts).is_none());
+
+        let single = voxels_with(|pos| if pos == LocalVoxelPos::new(5, 5, 5) { Block::Stone.into() } else { Voxel::Empty });
+        let mesh = mesh_chunk(&single, opts).unwrap();
+        assert_eq!((mesh.vertex_count(), mesh.triangle_count()), (24, 12));
+        assert!(mesh.positions.iter().flatten().all(|axis| (5.0..=6.0).contains(axis)));
+        assert!(mesh.indices.iter().all(|index| (*index as usize) < mesh.vertex_count()));
+        assert_eq!(mesh.colors, None);
+        assert_eq!(mesh.normals.iter().map(|normal| Vec3::from_array(*normal)).sum::<Vec3>(), Vec3::ZERO);
+    }
+
+    #[test]
+    fn test_strategies_agree_on_the_surface() {
+        let (blocks, shapes) = (BlockRegistry::builtin(), ShapeRegistry::builtin());
+        let slab = voxels_with(|pos| if pos.y < 2 && pos.x < 4 && pos.z < 4 { Block::Stone.into() } else { Voxel::Empty });
+        let quads = |strategy| {
+            let mesh = mesh_chunk(&slab, MeshOptions { strategy, ..MeshOptions::new(&blocks, &shapes) }).unwrap();
+            assert!(mesh.indices.iter().all(|index| (*index as usize) < mesh.vertex_count()));
+            mesh.vertex_count() / 4
+        };
+        // 4×2×4 voxels, the outside has 2 × 16 + 4 × 8 unit faces, all of them faces of a voxel 6 × 32
+        assert_eq!(quads(MeshingStrategy::Culled), 64);
+        assert_eq!(quads(MeshingStrategy::Naive), 6 * 32);
+        assert!(quads(MeshingStrategy::Greedy) <= 64);
+
+        for strategy in MeshingStrategy::ALL {
+            assert_eq!(strategy.name().parse::<MeshingStrategy>(), Ok(strategy));
+        }
+    }
+
+    #[test]
+    fn test_packed_faces_replace_normals() {
+        let (blocks, shapes) = (BlockRegistry::builtin(), ShapeRegistry::builtin());
+        let single = voxels_with(|pos| if pos == LocalVoxelPos::new(5, 5, 5) { Block::Stone.into() } else { Voxel::Empty });
+        let data = mesh_chunk(&single, MeshOptions::new(&blocks, &shapes)).unwrap();
+        let standard = data.clone().into_mesh(ChunkVertexFormat::Standard);
+        let packed = data.clone().into_mesh(ChunkVertexFormat::PackedFace);
+
+        assert!(packed.attribute(Mesh::ATTRIBUTE_NORMAL).is_none());
+        assert!(packed.get_vertex_buffer_data().len() * 2 <= standard.get_vertex_buffer_data().len());
+        let Some(VertexAttributeValues::Uint32(faces)) = packed.attribute(ATTRIBUTE_FACE) else {
+            panic!("packed mesh without faces");
+        };
+        for (face, normal) in faces.iter().zip(data.normals.iter()) {
+            let axis = *face as usize / 2;
+            let sign = if face % 2 == 0 { -1.0 } else { 1.0 };
+            assert_eq!(normal[axis], sign);
+        }
+        for format in ChunkVertexFormat::ALL {
+            assert_eq!(format.name().parse::<ChunkVertexFormat>(), Ok(format));
+        }
+    }
+
+    #[test]
+    fn test_biome_tints_only_grass() {
+        let (blocks, shapes) = (BlockRegistry::builtin(), ShapeRegistry::builtin());
+        let opts = MeshOptions { biome_tint: Some([191, 183, 85]), ..MeshOptions::new(&blocks, &shapes) };
+        let stone = voxels_with(|pos| if pos == LocalVoxelPos::new(5, 5, 5) { Block::Stone.into() } else { Voxel::Empty });
+        assert_eq!(mesh_chunk(&stone, opts).unwrap().colors, None);
+
+        let grass_on_stone = voxels_with(|pos| match (pos.x, pos.y, pos.z) {
+            (5, 5, 5) => Block::Stone.into(),
+            (5, 6, 5) => Block::Grass.into(),
+            _ => Voxel::Empty,
+        });
+        let mesh = mesh_chunk(&grass_on_stone, opts).unwrap();
+        let desert = Color::rgb_u8(191, 183, 85).as_linear_rgba_f32();
+        for (position, color) in mesh.positions.chunks(4).zip(mesh.colors.unwrap().chunks(4)) {
+            let on_grass = position.iter().all(|position| position[1] >= 6.0);
+            assert_eq!(color[0], if on_grass { desert } else { [1.0; 4] });
+        }
+    }
+
+    #[test]
+    fn test_water_gets_a_layer_of_its_own() {
+        let (blocks, shapes) = (BlockRegistry::builtin(), ShapeRegistry::builtin());
+        let solid = MeshOptions::new(&blocks, &shapes);
+        let translucent = MeshOptions { layer: MeshLayer::Translucent, ..solid };
+        let pond = voxels_with(|pos| match pos.y {
+            0 => Block::Stone.into(),
+            1 if pos.x < 4 && pos.z < 4 => Block::Water.into(),
+            _ => Voxel::Empty,
+        });
+
+        // The stone under the water is seen through it, the water is not part of the solid mesh
+        let stone = mesh_chunk(&pond, solid).unwrap();
+        assert_eq!(stone, mesh_chunk(&voxels_with(|pos| if pos.y == 0 { Block::Stone.into() } else { Voxel::Empty }), solid).unwrap());
+        // 4×4 water on top of stone: 16 faces on top and 4 on every side, nothing against the stone
+        let water = mesh_chunk(&pond, MeshOptions { strategy: MeshingStrategy::Culled, ..translucent }).unwrap();
+        assert!(water.positions.iter().all(|position| position[1] >= 1.0));
+        assert_eq!(water.normals.iter().filter(|normal| normal[1] < 0.0).count(), 0);
+        assert_eq!(water.vertex_count() / 4, 16 + 4 * 4);
+        assert!(mesh_chunk(&voxels_with(|pos| if pos.y == 0 { Block::Stone.into() } else { Voxel::Empty }), translucent).is_none());
+    }
+
+    #[test]
+    fn test_water_has_no_walls_between_chunks() {
+        let (blocks, shapes) = (BlockRegistry::builtin(), ShapeRegistry::builtin());
+        let mut lake = Chunk::new(ChunkPosition::new(0, 0, 0));
+        lake.generate_with(|_, pos| if pos.y < 8 { Block::Water.into() } else { Voxel::Empty });
+        let neighbours = [ChunkPosition::new(1, 0, 0), ChunkPosition::new(-1, 0, 0)].map(|position| {
+            let mut chunk = lake.clone();
+            chunk.position = position;
+            chunk
+        });
+        let borders = ChunkBorders::capture(&lake.position, |position| neighbours.iter().find(|chunk| chunk.position == *position));
+        assert!(borders.get(Face::Right).is_some() && borders.get(Face::Front).is_none());
+
+        let translucent = MeshOptions { strategy: MeshingStrategy::Culled, layer: MeshLayer::Translucent, ..MeshOptions::new(&blocks, &shapes) };
+        let reader = lake.reader();
+        let water = reader.voxels().to_slice();
+        let walled = mesh_chunk(&water, translucent).unwrap();
+        let open = mesh_chunk(&water, MeshOptions { borders: Some(&borders), ..translucent }).unwrap();
+        let side = CHUNK_SIZE * 8;
+        assert_eq!(walled.vertex_count() - open.vertex_count(), 2 * side * 4);
+        assert!(open.normals.iter().all(|normal| normal[0] == 0.0));
+        // The solid layer keeps its faces on the border
+        let solid = MeshOptions { borders: Some(&borders), ..MeshOptions::new(&blocks, &shapes) };
+        assert_eq!(mesh_chunk(&water, solid), None);
+    }
+
+    #[test]
+    fn test_reused_buffers_start_clean() {
+        let (blocks, shapes) = (BlockRegistry::builtin(), ShapeRegistry::builtin());
+        let opts = MeshOptions::new(&blocks, &shapes);
+        let half = voxels_with(|pos| if pos.y < 8 { Block::Stone.into() } else { Voxel::Empty });
+        let single = voxels_with(|pos| if pos == LocalVoxelPos::new(5, 5, 5) { Block::Stone.into() } else { Voxel::Empty });
+
+        // Meshed on the same thread, nothing of the first chunk is left in the buffers
+        assert!(mesh_chunk(&half, opts).is_some());
+        assert_eq!(mesh_chunk(&single, opts).unwrap().vertex_count(), 6 * 4);
+    }
+}
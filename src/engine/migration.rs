@@ -0,0 +1,357 @@
+//! Upgrades save data written by older versions of the game.
+//!
+//! Chunk files and `world.ron` both carry a format version. When a format changes, bump its
+//! version and register a [`SaveMigration`] upgrading data from the previous version in
+//! [`MigrationRegistry::builtin`]. Old data is run through every migration in order before it is read.
+//!
+//! Saves written by every released version are kept in `tests/fixtures/saves` and loaded by
+//! `tests/save_migration.rs`, add one there whenever a format version is bumped.
+
+use std::{borrow::Cow, collections::BTreeMap, sync::Arc};
+
+use bevy::prelude::*;
+
+use super::{generator::PerlinHeightmapWorldGenerator, serialization, world_meta};
+
+/// Upgrades save data from one version to the next, including the version stored in it: chunk
+/// bytes (`T = [u8]`) including the version in their header, or parsed world metadata
+/// (`T = ron::Value`) including its `format_version`. Functions and closures are migrations.
+pub trait SaveMigration<T: ToOwned + ?Sized>: Send + Sync {
+    fn migrate(&self, data: &T) -> Result<T::Owned, String>;
+}
+
+impl<T: ToOwned + ?Sized, F: Fn(&T) -> Result<T::Owned, String> + Send + Sync> SaveMigration<T> for F {
+    fn migrate(&self, data: &T) -> Result<T::Owned, String> {
+        self(data)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MigrationError {
+    /// No migration is registered for data of this version
+    Missing(u32),
+    /// The data is newer than this build understands
+    TooNew(u32),
+    Failed { from: u32, reason: String },
+}
+
+impl std::fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing(version) => write!(f, "no migration from version {}", version),
+            Self::TooNew(version) => write!(f, "version {} is newer than this build supports", version),
+            Self::Failed { from, reason } => write!(f, "migration from version {} failed: {}", from, reason),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+#[derive(Resource, Clone, Default)]
+pub struct MigrationRegistry {
+    chunk: BTreeMap<u16, Arc<dyn SaveMigration<[u8]>>>,
+    metadata: BTreeMap<u32, Arc<dyn SaveMigration<ron::Value>>>,
+}
+
+impl MigrationRegistry {
+    /// Every migration shipped with the game
+    pub fn builtin() -> Self {
+        let mut registry = Self::default();
+        registry.register_chunk(1, add_chunk_metadata);
+        registry.register_chunk(2, add_chunk_tints);
+        registry.register_chunk(3, make_room_for_water);
+        registry.register_chunk(4, add_chunk_checksum);
+        registry.register_metadata(0, add_metadata_format_version);
+        registry.register_metadata(1, add_metadata_generator);
+        registry.register_metadata(2, add_metadata_world_limits);
+        registry
+    }
+
+    /// Registers a migration from `from_version` to `from_version + 1`, replacing the one registered before
+    pub fn register_chunk(&mut self, from_version: u16, migration: impl SaveMigration<[u8]> + 'static) {
+        self.chunk.insert(from_version, Arc::new(migration));
+    }
+
+    /// Registers a migration from `from_version` to `from_version + 1`, replacing the one registered before
+    pub fn register_metadata(&mut self, from_version: u32, migration: impl SaveMigration<ron::Value> + 'static) {
+        self.metadata.insert(from_version, Arc::new(migration));
+    }
+
+    /// Versions chunk migrations are registered for, oldest first
+    pub fn chunk_versions(&self) -> impl Iterator<Item = u16> + '_ {
+        self.chunk.keys().copied()
+    }
+
+    /// Versions metadata migrations are registered for, oldest first
+    pub fn metadata_versions(&self) -> impl Iterator<Item = u32> + '_ {
+        self.metadata.keys().copied()
+    }
+
+    /// Upgrades encoded chunk data to [`serialization::FORMAT_VERSION`], borrowing it if it already is
+    pub fn migrate_chunk<'a>(&self, bytes: &'a [u8]) -> Result<Cow<'a, [u8]>, MigrationError> {
+        let mut bytes = Cow::Borrowed(bytes);
+        loop {
+            // Data that is not a chunk at all is left for the decoder to report
+            let Ok(version) = serialization::read_version(&bytes) else {
+                return Ok(bytes);
+            };
+            if version == serialization::FORMAT_VERSION {
+                return Ok(bytes);
+            }
+            if version > serialization::FORMAT_VERSION {
+                return Err(MigrationError::TooNew(version as u32));
+            }
+            let migration = self.chunk.get(&version).ok_or(MigrationError::Missing(version as u32))?;
+            let upgraded = migration.migrate(&bytes).map_err(|reason| MigrationError::Failed { from: version as u32, reason })?;
+            if serialization::read_version(&upgraded) != Ok(version + 1) {
+                return Err(MigrationError::Failed { from: version as u32, reason: "version was not increased".to_string() });
+            }
+            bytes = Cow::Owned(upgraded);
+        }
+    }
+
+    /// Upgrades parsed world metadata to [`world_meta::FORMAT_VERSION`]
+    pub fn migrate_metadata(&self, mut value: ron::Value) -> Result<ron::Value, MigrationError> {
+        loop {
+            let version = metadata_version(&value);
+            if version == world_meta::FORMAT_VERSION {
+                return Ok(value);
+            }
+            if version > world_meta::FORMAT_VERSION {
+                return Err(MigrationError::TooNew(version));
+            }
+            let migration = self.metadata.get(&version).ok_or(MigrationError::Missing(version))?;
+            value = migration.migrate(&value).map_err(|reason| MigrationError::Failed { from: version, reason })?;
+            if metadata_version(&value) != version + 1 {
+                return Err(MigrationError::Failed { from: version, reason: "version was not increased".to_string() });
+            }
+        }
+    }
+}
+
+/// Version 1 -> 2: voxel metadata added after the palette, older chunks have none
+fn add_chunk_metadata(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let palette_len = bytes
+        .get(serialization::PALETTE_OFFSET - 2..serialization::PALETTE_OFFSET)
+        .map(|len| u16::from_le_bytes([len[0], len[1]]) as usize)
+        .ok_or("chunk data ends before the palette")?;
+    let metadata_offset = serialization::PALETTE_OFFSET + palette_len * 2;
+    if bytes.len() < metadata_offset {
+        return Err("chunk data ends inside the palette".to_string());
+    }
+
+    let mut upgraded = Vec::with_capacity(bytes.len() + 2);
+    upgraded.extend_from_slice(&bytes[..metadata_offset]);
+    upgraded.extend_from_slice(&0u16.to_le_bytes());
+    upgraded.extend_from_slice(&bytes[metadata_offset..]);
+    serialization::set_version(&mut upgraded, 2);
+    Ok(upgraded)
+}
+
+/// Version 2 -> 3: voxel tints added after the metadata, older chunks have none
+fn add_chunk_tints(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let too_short = || "chunk data ends before the voxel metadata ends".to_string();
+    let palette_len = bytes
+        .get(serialization::PALETTE_OFFSET - 2..serialization::PALETTE_OFFSET)
+        .map(|len| u16::from_le_bytes([len[0], len[1]]) as usize)
+        .ok_or_else(too_short)?;
+    let mut offset = serialization::PALETTE_OFFSET + palette_len * 2;
+    let metadata_len = bytes.get(offset..offset + 2).map(|len| u16::from_le_bytes([len[0], len[1]])).ok_or_else(too_short)?;
+    offset += 2;
+    for _ in 0..metadata_len {
+        // Voxel index, then the payload length
+        offset += 2 + *bytes.get(offset + 2).ok_or_else(too_short)? as usize + 1;
+    }
+    if bytes.len() < offset {
+        return Err(too_short());
+    }
+
+    let mut upgraded = Vec::with_capacity(bytes.len() + 1);
+    upgraded.extend_from_slice(&bytes[..offset]);
+    upgraded.push(0);
+    upgraded.extend_from_slice(&bytes[offset..]);
+    serialization::set_version(&mut upgraded, 3);
+    Ok(upgraded)
+}
+
+/// Builtin blocks before water was added, blocks from the blocks file started right after them
+const BUILTIN_BLOCKS_BEFORE_WATER: u16 = 8;
+
+/// Voxel code of a block saved before water was added. Water took the code after the other
+/// builtin blocks, every block from the blocks file moved up by one.
+pub fn code_before_water(code: u16) -> u16 {
+    if code > BUILTIN_BLOCKS_BEFORE_WATER { code + 1 } else { code }
+}
+
+/// Version 3 -> 4: water became a builtin block, the palette codes of other blocks change
+fn make_room_for_water(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let palette_len = bytes
+        .get(serialization::PALETTE_OFFSET - 2..serialization::PALETTE_OFFSET)
+        .map(|len| u16::from_le_bytes([len[0], len[1]]) as usize)
+        .ok_or("chunk data ends before the palette")?;
+    let palette_end = serialization::PALETTE_OFFSET + palette_len * 2;
+    if bytes.len() < palette_end {
+        return Err("chunk data ends inside the palette".to_string());
+    }
+
+    let mut upgraded = bytes.to_vec();
+    for code in upgraded[serialization::PALETTE_OFFSET..palette_end].chunks_exact_mut(2) {
+        let upgraded_code = code_before_water(u16::from_le_bytes([code[0], code[1]]));
+        code.copy_from_slice(&upgraded_code.to_le_bytes());
+    }
+    serialization::set_version(&mut upgraded, 4);
+    Ok(upgraded)
+}
+
+/// Version 4 -> 5: a checksum of the body is appended, older chunks are trusted as they are
+fn add_chunk_checksum(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let mut upgraded = bytes.to_vec();
+    serialization::set_version(&mut upgraded, 5);
+    serialization::append_checksum(&mut upgraded);
+    Ok(upgraded)
+}
+
+fn format_version_key() -> ron::Value {
+    ron::Value::String("format_version".to_string())
+}
+
+/// Metadata written before versioning was added has no version field, that is version 0
+fn metadata_version(value: &ron::Value) -> u32 {
+    let ron::Value::Map(map) = value else {
+        return 0;
+    };
+    map.iter()
+        .find(|(key, _)| **key == format_version_key())
+        .and_then(|(_, version)| match version {
+            ron::Value::Number(number) => number.as_i64(),
+            _ => None,
+        })
+        .map_or(0, |version| version as u32)
+}
+
+/// Version 0 -> 1: `format_version` field added
+fn add_metadata_format_version(value: &ron::Value) -> Result<ron::Value, String> {
+    let ron::Value::Map(mut map) = value.clone() else {
+        return Err("world metadata is not a struct".to_string());
+    };
+    map.insert(format_version_key(), ron::Value::Number(ron::Number::from(1i64)));
+    Ok(ron::Value::Map(map))
+}
+
+/// Version 1 -> 2: `seed` and `generator` added, older worlds were all generated with the default perlin generator
+fn add_metadata_generator(value: &ron::Value) -> Result<ron::Value, String> {
+    let ron::Value::Map(mut map) = value.clone() else {
+        return Err("world metadata is not a struct".to_string());
+    };
+    let seed = PerlinHeightmapWorldGenerator::default().seed;
+    map.insert(ron::Value::String("seed".to_string()), ron::Value::Number(ron::Number::from(seed as i64)));
+    map.insert(ron::Value::String("generator".to_string()), ron::Value::String("perlin".to_string()));
+    map.insert(format_version_key(), ron::Value::Number(ron::Number::from(2i64)));
+    Ok(ron::Value::Map(map))
+}
+
+/// Version 2 -> 3: world bottom and border, spawn column and playtime added. Worlds only had the
+/// default bottom and no border before, and spawned at the origin.
+fn add_metadata_world_limits(value: &ron::Value) -> Result<ron::Value, String> {
+    let ron::Value::Map(mut map) = value.clone() else {
+        return Err("world metadata is not a struct".to_string());
+    };
+    let key = |name: &str| ron::Value::String(name.to_string());
+    let number = |number: i64| ron::Value::Number(ron::Number::from(number));
+    map.insert(key("world_bottom"), ron::Value::Option(Some(Box::new(number(-64)))));
+    map.insert(key("world_border"), ron::Value::Option(None));
+    map.insert(key("spawn"), ron::Value::Seq(vec![number(0), number(0)]));
+    map.insert(key("playtime"), ron::Value::Number(ron::Number::from(0.0)));
+    map.insert(format_version_key(), number(3));
+    Ok(ron::Value::Map(map))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{block_registry::BlockRegistry, chunk::{Chunk, ChunkPosition}, coords::LocalVoxelPos, voxel::{Block, Voxel, VoxelMetadata}};
+
+    fn without_checksum(bytes: &[u8]) -> Vec<u8> {
+        bytes[..bytes.len() - serialization::CHECKSUM_LEN].to_vec()
+    }
+
+    #[test]
+    fn test_chunk_migration_chain() {
+        let current = serialization::encode(&Chunk::new(ChunkPosition::new(1, 2, 3)));
+        let mut old = current.clone();
+        serialization::set_version(&mut old, serialization::FORMAT_VERSION - 1);
+
+        let registry = MigrationRegistry::default();
+        assert!(matches!(registry.migrate_chunk(&current), Ok(Cow::Borrowed(_))));
+        assert_eq!(registry.migrate_chunk(&old), Err(MigrationError::Missing(serialization::FORMAT_VERSION as u32 - 1)));
+
+        let mut registry = MigrationRegistry::default();
+        registry.register_chunk(serialization::FORMAT_VERSION - 1, |bytes: &[u8]| {
+            let mut bytes = bytes.to_vec();
+            serialization::set_version(&mut bytes, serialization::FORMAT_VERSION);
+            Ok(bytes)
+        });
+        let migrated = registry.migrate_chunk(&old).unwrap();
+        assert_eq!(serialization::decode(&migrated, &BlockRegistry::builtin()).unwrap().position, ChunkPosition::new(1, 2, 3));
+    }
+
+    #[test]
+    fn test_chunks_without_voxel_metadata_or_tints_are_migrated() {
+        let mut chunk = Chunk::new(ChunkPosition::new(-4, 1, 9));
+        chunk.generate_with(|_, pos| if pos.y < 5 { Voxel::from(Block::Dirt) } else { Voxel::Empty });
+        let current = serialization::encode(&chunk);
+        let metadata_offset = serialization::PALETTE_OFFSET + 2 * 2;
+
+        // Version 2 is the current layout without the tint color count and the checksum
+        let mut version_2 = without_checksum(&current);
+        version_2.remove(metadata_offset + 2);
+        serialization::set_version(&mut version_2, 2);
+        // Version 1 has no metadata count either
+        let mut version_1 = version_2.clone();
+        version_1.drain(metadata_offset..metadata_offset + 2);
+        serialization::set_version(&mut version_1, 1);
+
+        let registry = MigrationRegistry::builtin();
+        assert_eq!(*registry.migrate_chunk(&version_2).unwrap(), *current);
+        assert_eq!(*registry.migrate_chunk(&version_1).unwrap(), *current);
+
+        // Metadata written by version 2 is skipped over
+        chunk.set_metadata(LocalVoxelPos::new(1, 2, 3), VoxelMetadata::new([9, 9]));
+        let current = serialization::encode(&chunk);
+        let mut version_2 = without_checksum(&current);
+        version_2.remove(metadata_offset + 2 + 2 + 1 + 2);
+        serialization::set_version(&mut version_2, 2);
+        assert_eq!(*registry.migrate_chunk(&version_2).unwrap(), *current);
+    }
+
+    #[test]
+    fn test_blocks_from_the_blocks_file_make_room_for_water() {
+        let mut chunk = Chunk::new(ChunkPosition::new(0, 0, 0));
+        chunk.generate_with(|_, pos| if pos.y < 5 { Voxel::from(Block::Glass) } else { Voxel::from(Block::Water) });
+        // Read as version 3, the water code was the first block of the blocks file
+        let mut version_3 = without_checksum(&serialization::encode(&chunk));
+        serialization::set_version(&mut version_3, 3);
+
+        let migrated = MigrationRegistry::builtin().migrate_chunk(&version_3).unwrap();
+        let palette = migrated[serialization::PALETTE_OFFSET..serialization::PALETTE_OFFSET + 4]
+            .chunks_exact(2)
+            .map(|code| u16::from_le_bytes([code[0], code[1]]))
+            .collect::<Vec<_>>();
+        assert_eq!(palette, vec![Voxel::from(Block::Glass).to_code(), Voxel::from(Block::Water).to_code() + 1]);
+    }
+
+    #[test]
+    fn test_unversioned_metadata_is_migrated() {
+        let old = r#"(name: "old", created: 1, last_saved: 2, player_position: (1.0, 2.0, 3.0))"#;
+        let value = ron::from_str::<ron::Value>(old).unwrap();
+        assert_eq!(metadata_version(&value), 0);
+
+        let migrated = MigrationRegistry::builtin().migrate_metadata(value).unwrap();
+        let metadata = migrated.into_rust::<world_meta::WorldMetadata>().unwrap();
+        assert_eq!(metadata.format_version, world_meta::FORMAT_VERSION);
+        assert_eq!(metadata.name, "old");
+        assert_eq!(metadata.generator, "perlin");
+        assert_eq!((metadata.world_bottom, metadata.world_border), (Some(-64), None));
+        assert_eq!((metadata.spawn, metadata.playtime), ([0, 0], 0.0));
+    }
+}
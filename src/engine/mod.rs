@@ -1,11 +1,55 @@
-use bevy::{prelude::*, utils::{HashMap, HashSet}};
+use bevy::{prelude::*, render::primitives::Aabb, utils::{HashMap, HashSet}};
 
-use self::{chunk::ChunkPosition, generator::ChunkGeneratorPlugin};
+use self::{chunk::{ChunkPosition, CHUNK_SIZE}, generator::ChunkGeneratorPlugin, pending_edits::PendingEdits};
 
 pub mod chunk;
+pub mod chunk_log;
+pub mod chunk_patch;
+pub mod meshing;
 pub mod voxel;
+pub mod block_registry;
+pub mod shapes;
+pub mod tint;
+pub mod biome;
 pub mod util;
+pub mod culling;
+pub mod load_policy;
 pub mod generator;
+pub mod coords;
+pub mod generators;
+pub mod cache;
+pub mod pending_edits;
+pub mod edit;
+pub mod world_edits;
+pub mod persistence;
+pub mod serialization;
+pub mod heightmap;
+pub mod world_bounds;
+pub mod world_meta;
+pub mod shutdown;
+pub mod raycast;
+pub mod schematic;
+pub mod migration;
+pub mod vox;
+pub mod world_manager;
+pub mod worldgen_file;
+pub mod export;
+pub mod critical;
+pub mod pins;
+pub mod generation_context;
+pub mod rng;
+pub mod chunk_material;
+pub mod loading;
+pub mod menu;
+pub mod super_chunk;
+pub mod stats;
+pub mod spawn_queue;
+pub mod memory_budget;
+pub mod chunk_index;
+pub mod streaming_budget;
+pub mod anchor;
+pub mod pregen;
+pub mod autosave;
 
 #[derive(Debug, Resource)]
 pub struct ChunkData {
@@ -17,6 +61,10 @@ pub struct ChunkData {
     pub awaiting_generation: HashMap<ChunkPosition, Entity>,
     /// Visible chunks around the player, these should be loaded and have meshes
     pub visible: HashSet<ChunkPosition>,
+    /// Voxel writes for chunks that are not generated yet
+    pub pending_edits: PendingEdits,
+    /// Chunks that must stay loaded
+    pub pins: pins::ChunkPins,
 }
 
 impl Default for ChunkData {
@@ -26,6 +74,8 @@ impl Default for ChunkData {
             loaded: HashMap::default(),
             awaiting_generation: HashMap::default(),
             visible: HashSet::default(),
+            pending_edits: PendingEdits::default(),
+            pins: pins::ChunkPins::default(),
         }
     }
 }
@@ -36,18 +86,186 @@ impl ChunkData {
         self.loaded.remove(&chunk);
         self.awaiting_generation.remove(&chunk);
     } 
+
+    /// Keeps a chunk from being unloaded or garbage collected until it is unpinned, pins are counted.
+    /// Systems working on an area over several frames (structure generation, edits, cutscenes) should pin it.
+    #[track_caller]
+    pub fn pin(&mut self, chunk: ChunkPosition) {
+        self.pins.pin(chunk);
+    }
+
+    /// Releases a pin taken with [`ChunkData::pin`], returns false if the chunk was not pinned
+    pub fn unpin(&mut self, chunk: ChunkPosition) -> bool {
+        self.pins.unpin(chunk)
+    }
+
+    pub fn is_pinned(&self, chunk: &ChunkPosition) -> bool {
+        self.pins.is_pinned(chunk)
+    }
+
+    /// Loaded chunks overlapping the box. Chunks that only touch the box on their boundary are left out,
+    /// on axes where the box has no extent the chunk containing it is included.
+    pub fn chunks_in_aabb(&self, aabb: Aabb) -> impl Iterator<Item = (ChunkPosition, Entity)> + '_ {
+        let min = Vec3::from(aabb.min());
+        let max = Vec3::from(aabb.max());
+        let (min_x, max_x) = chunk_range(min.x, max.x);
+        let (min_y, max_y) = chunk_range(min.y, max.y);
+        let (min_z, max_z) = chunk_range(min.z, max.z);
+        let inside = move |chunk: &ChunkPosition| {
+            (min_x..=max_x).contains(&chunk.x) && (min_y..=max_y).contains(&chunk.y) && (min_z..=max_z).contains(&chunk.z)
+        };
+
+        // Small boxes look their chunks up, large ones scan whatever is loaded
+        let volume = (max_x - min_x + 1) as i64 * (max_y - min_y + 1) as i64 * (max_z - min_z + 1) as i64;
+        let lookup = (volume <= self.loaded.len() as i64).then(|| {
+            (min_x..=max_x).flat_map(move |x| {
+                (min_y..=max_y).flat_map(move |y| (min_z..=max_z).map(move |z| ChunkPosition::new(x, y, z)))
+            })
+            .filter_map(|chunk| Some((chunk, *self.loaded.get(&chunk)?)))
+        });
+        let scan = lookup.is_none().then(|| {
+            self.loaded.iter().filter(move |(chunk, _)| inside(chunk)).map(|(chunk, entity)| (*chunk, *entity))
+        });
+        lookup.into_iter().flatten().chain(scan.into_iter().flatten())
+    }
+
+    /// Loaded chunks of every column with a part strictly closer than `radius` to `center` on the x and z axes,
+    /// at any height. The column containing `center` is always included.
+    pub fn columns_in_radius(&self, center: Vec3, radius: f32) -> impl Iterator<Item = (ChunkPosition, Entity)> + '_ {
+        let center_column = ChunkPosition::from_world_position(center);
+        self.loaded
+            .iter()
+            .filter(move |(chunk, _)| {
+                let min = chunk.as_world_position();
+                let size = CHUNK_SIZE as f32;
+                let dx = (min.x - center.x).max(center.x - (min.x + size)).max(0.0);
+                let dz = (min.z - center.z).max(center.z - (min.z + size)).max(0.0);
+                (chunk.x == center_column.x && chunk.z == center_column.z) || dx * dx + dz * dz < radius * radius
+            })
+            .map(|(chunk, entity)| (*chunk, *entity))
+    }
+
+    /// Counts, memory estimates and chunk positions in each streaming state,
+    /// see [`stats::ChunkStatistics`] to include mesh memory
+    pub fn stats(&self) -> stats::ChunkStats {
+        stats::ChunkStats::from_chunk_data(self)
+    }
+
+    /// Voxel at a world position, `None` if its chunk is not loaded
+    pub fn voxel_at(&self, chunks: &Query<&chunk::Chunk>, pos: coords::WorldVoxelPos) -> Option<voxel::Voxel> {
+        let (chunk_pos, local) = pos.split();
+        let entity = self.loaded.get(&chunk_pos)?;
+        chunks.get(*entity).ok().map(|chunk| chunk.get(local))
+    }
+}
+
+/// Chunks overlapping `min..max` on one axis, or the chunk containing `min` if the range is empty
+fn chunk_range(min: f32, max: f32) -> (i32, i32) {
+    let first = coords::world_to_chunk_axis(min);
+    let last = (max / CHUNK_SIZE as f32).ceil() as i32 - 1;
+    (first, last.max(first))
 }
 
 pub struct ChunkPlugin;
 
 impl Plugin for ChunkPlugin {
     fn build(&self, app: &mut App) {
+        // Worlds are opened with the blocks they name already known
+        let blocks = block_registry::BlockRegistry::load(block_registry::BLOCKS_FILE).unwrap_or_else(|err| {
+            error!("Using the builtin blocks only, {}: {}", block_registry::BLOCKS_FILE, err);
+            block_registry::BlockRegistry::builtin()
+        });
+        info!("Loaded {} block definitions", blocks.len());
+
+        let migrations = std::sync::Arc::new(migration::MigrationRegistry::builtin());
+        let mut worlds = world_manager::WorldManager::new(world_manager::SAVES_DIR, migrations.clone(), blocks);
+        let world = worlds.open_last_played().expect("Failed to open or create a world");
+
         app
-            .insert_resource(ChunkData::default())
-            .insert_resource(generator::WorldGeneratorConfig::default_with(generator::PerlinHeightmapWorldGenerator::default()))
-            .add_plugins(ChunkGeneratorPlugin);
+            .insert_resource(world.blocks)
+            .insert_resource(ChunkData { pending_edits: world.pending_edits, ..Default::default() })
+            .insert_resource(cache::ChunkCache::default())
+            .insert_resource(heightmap::HeightmapCache::default())
+            .insert_resource(world.storage)
+            .insert_resource(world.metadata)
+            .insert_resource(world.config)
+            .insert_resource(worlds)
+            .insert_resource((*migrations).clone())
+            .add_plugins(chunk_material::ChunkMaterialPlugin)
+            .add_plugins(ChunkGeneratorPlugin)
+            .add_plugins(world_bounds::WorldBoundsPlugin)
+            .add_plugins(shutdown::ShutdownPlugin)
+            .add_plugins(autosave::AutosavePlugin)
+            .add_plugins(world_manager::WorldManagerPlugin)
+            .add_plugins(worldgen_file::WorldgenFilePlugin)
+            .add_plugins(critical::CriticalRingPlugin)
+            .add_plugins(super_chunk::SuperChunkPlugin);
 
+        app.add_systems(Update, world_meta::count_playtime.run_if(in_state(loading::AppState::Playing)));
         #[cfg(debug_assertions)]
-        app.add_plugins(bevy_egui::EguiPlugin);
+        app.add_systems(Last, pins::detect_leaked_pins);
+
+        // Debug windows of every plugin and the loading screen draw with egui
+        #[cfg(feature = "debug-ui")]
+        if !app.is_plugin_added::<bevy_egui::EguiPlugin>() {
+            app.add_plugins(bevy_egui::EguiPlugin);
+        }
+        app.add_plugins(loading::LoadingPlugin)
+            .add_plugins(menu::MenuPlugin);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk_data(chunks: impl Iterator<Item = ChunkPosition>) -> ChunkData {
+        let mut data = ChunkData::default();
+        for (i, chunk) in chunks.enumerate() {
+            data.loaded.insert(chunk, Entity::from_raw(i as u32));
+        }
+        data
+    }
+
+    fn sorted(chunks: impl Iterator<Item = (ChunkPosition, Entity)>) -> Vec<(i32, i32, i32)> {
+        let mut chunks = chunks.map(|(chunk, _)| (chunk.x, chunk.y, chunk.z)).collect::<Vec<_>>();
+        chunks.sort();
+        chunks
+    }
+
+    #[test]
+    fn test_chunks_in_aabb_boundaries() {
+        let data = chunk_data((-2..2).flat_map(|x| (-2..2).map(move |z| ChunkPosition::new(x, 0, z))));
+
+        // Ends exactly on the boundary of chunk 1, which is left out
+        let aabb = Aabb::from_min_max(Vec3::new(0.0, 0.0, 0.0), Vec3::new(16.0, 16.0, 16.0));
+        assert_eq!(sorted(data.chunks_in_aabb(aabb)), vec![(0, 0, 0)]);
+
+        let aabb = Aabb::from_min_max(Vec3::new(-0.5, 0.0, 0.0), Vec3::new(16.5, 16.0, 1.0));
+        assert_eq!(sorted(data.chunks_in_aabb(aabb)), vec![(-1, 0, 0), (0, 0, 0), (1, 0, 0)]);
+
+        // A flat box on a boundary belongs to the chunk above it, like a point would
+        let aabb = Aabb::from_min_max(Vec3::new(16.0, 0.0, -16.0), Vec3::new(16.0, 1.0, -16.0));
+        assert_eq!(sorted(data.chunks_in_aabb(aabb)), vec![(1, 0, -1)]);
+
+        // Large enough to scan the loaded chunks instead of looking up every position
+        let aabb = Aabb::from_min_max(Vec3::splat(-1000.0), Vec3::new(1000.0, 1000.0, 0.0));
+        assert_eq!(data.chunks_in_aabb(aabb).count(), 8);
+    }
+
+    #[test]
+    fn test_columns_in_radius() {
+        let data = chunk_data((-2..3).flat_map(|x| (-1..2).map(move |y| ChunkPosition::new(x, y, 0))));
+
+        let columns = sorted(data.columns_in_radius(Vec3::new(8.0, 100.0, 8.0), 8.0));
+        assert_eq!(columns, vec![(0, -1, 0), (0, 0, 0), (0, 1, 0)]);
+
+        // Both neighbouring columns are exactly 8 away
+        let columns = sorted(data.columns_in_radius(Vec3::new(8.0, 0.0, 8.0), 8.5));
+        assert_eq!(columns.iter().filter(|(x, ..)| *x == 1).count(), 3);
+        assert_eq!(columns.iter().filter(|(x, ..)| *x == -1).count(), 3);
+        assert!(columns.iter().all(|(x, ..)| (-1..=1).contains(x)));
+
+        assert_eq!(data.columns_in_radius(Vec3::new(-24.0, 0.0, 8.0), 0.0).count(), 3);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,247 @@
+//! Voxel writes for chunks that are not there yet. The edits left when the world is saved are
+//! written to [`PENDING_EDITS_FILE`] in the world directory and read back when it is opened, so
+//! structures overflowing into chunks that were never generated are not cut off.
+//!
+//! File layout (all integers little endian):
+//! ```text
+//! magic          4 bytes  "VXPE"
+//! version        u16
+//! chunk_count    u32
+//! chunks         chunk_count × (3 × i32 position, u32 edit_count, edit_count × (u16 voxel index, u16 voxel code))
+//! checksum       u32 CRC-32 of everything between the version and the checksum
+//! ```
+
+use std::{fs, io, path::Path};
+
+use bevy::utils::HashMap;
+
+use super::{
+    block_registry::BlockRegistry,
+    chunk::{Chunk, ChunkPosition, CHUNK_SIZE},
+    coords::{LocalVoxelPos, WorldVoxelPos},
+    edit::check_edit,
+    serialization::{self, ByteReader, DecodeError},
+    voxel::Voxel,
+};
+
+pub const PENDING_EDITS_FILE: &str = "pending_edits.bin";
+const MAGIC: &[u8; 4] = b"VXPE";
+const FORMAT_VERSION: u16 = 1;
+const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
+/// Voxel writes waiting for their chunk to be generated.
+/// Decorators use this to place structures that overflow into neighbouring chunks,
+/// the edits are applied right after the target chunk generates and before it is meshed.
+#[derive(Debug, Clone, Default)]
+pub struct PendingEdits {
+    edits: HashMap<ChunkPosition, Vec<(LocalVoxelPos, Voxel)>>,
+}
+
+impl PendingEdits {
+    pub fn push(&mut self, pos: WorldVoxelPos, voxel: Voxel) {
+        let (chunk, local) = pos.split();
+        self.edits.entry(chunk).or_default().push((local, voxel));
+    }
+
+    /// Moves all edits from `other` into this buffer, keeping their order
+    pub fn merge(&mut self, other: PendingEdits) {
+        for (chunk, edits) in other.edits {
+            self.edits.entry(chunk).or_default().extend(edits);
+        }
+    }
+
+    pub fn take(&mut self, chunk: &ChunkPosition) -> Option<Vec<(LocalVoxelPos, Voxel)>> {
+        self.edits.remove(chunk)
+    }
+
+    /// Writes all edits for this chunk into it, unbreakable `blocks` are kept. Returns whether anything changed.
+    pub fn apply(&mut self, chunk: &mut Chunk, blocks: &BlockRegistry) -> bool {
+        let Some(edits) = self.take(&chunk.position) else {
+            return false;
+        };
+
+        let chunk_pos = chunk.position;
+        let mut writer = chunk.writer();
+        for (pos, voxel) in edits {
+            let existing = *writer.get(pos.x as usize, pos.y as usize, pos.z as usize);
+            if check_edit(blocks, WorldVoxelPos::from_local(&chunk_pos, pos), existing, voxel).is_err() {
+                continue;
+            }
+            writer.set(pos.x as usize, pos.y as usize, pos.z as usize, voxel);
+        }
+        true
+    }
+
+    pub fn contains(&self, chunk: &ChunkPosition) -> bool {
+        self.edits.contains_key(chunk)
+    }
+
+    /// Positions of chunks that have edits waiting
+    pub fn chunks(&self) -> impl Iterator<Item = &ChunkPosition> {
+        self.edits.keys()
+    }
+
+    /// Total number of voxel writes waiting
+    pub fn len(&self) -> usize {
+        self.edits.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.edits.clear();
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.edits.len() as u32).to_le_bytes());
+        for (chunk, edits) in self.edits.iter() {
+            for value in [chunk.x, chunk.y, chunk.z] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            bytes.extend_from_slice(&(edits.len() as u32).to_le_bytes());
+            for (pos, voxel) in edits {
+                bytes.extend_from_slice(&(pos.index() as u16).to_le_bytes());
+                bytes.extend_from_slice(&voxel.to_code().to_le_bytes());
+            }
+        }
+        serialization::append_checksum(&mut bytes);
+        bytes
+    }
+
+    /// Voxel codes are read as blocks of `blocks`, the ids of the world the edits were saved in
+    pub fn decode(bytes: &[u8], blocks: &BlockRegistry) -> Result<Self, DecodeError> {
+        let mut input = ByteReader::new(bytes);
+        if input.take(4)? != MAGIC {
+            return Err(DecodeError::BadMagic);
+        }
+        let version = input.u16()?;
+        if version != FORMAT_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let mut input = ByteReader::new(serialization::verify_checksum(bytes)?);
+        input.take(4 + 2)?;
+
+        let mut pending = Self::default();
+        for _ in 0..input.u32()? {
+            let chunk = ChunkPosition::new(input.i32()?, input.i32()?, input.i32()?);
+            let count = input.u32()?;
+            let edits = pending.edits.entry(chunk).or_default();
+            for _ in 0..count {
+                let index = input.u16()?;
+                if index as usize >= CHUNK_VOLUME {
+                    return Err(DecodeError::InvalidVoxelIndex(index));
+                }
+                let code = input.u16()?;
+                let voxel = Voxel::from_code(code, blocks).ok_or(DecodeError::UnknownVoxel(code))?;
+                edits.push((LocalVoxelPos::from_index(index as usize), voxel));
+            }
+        }
+        Ok(pending)
+    }
+
+    /// Reads the edits saved in a world directory, none if nothing was saved
+    pub fn load(root: &Path, blocks: &BlockRegistry) -> io::Result<Self> {
+        let bytes = match fs::read(root.join(PENDING_EDITS_FILE)) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+        Self::decode(&bytes, blocks).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Writes the edits into a world directory, the file is removed when there are none
+    pub fn save(&self, root: &Path) -> io::Result<()> {
+        let path = root.join(PENDING_EDITS_FILE);
+        if self.is_empty() {
+            return match fs::remove_file(path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            };
+        }
+        let tmp = path.with_extension("bin.tmp");
+        fs::write(&tmp, self.encode())?;
+        fs::rename(tmp, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::voxel::Block;
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn test_take_returns_the_edits_of_one_chunk_in_order() {
+        let mut pending = PendingEdits::default();
+        let chunk = ChunkPosition::new(0, 0, 0);
+        pending.push(WorldVoxelPos::new(1, 2, 3), Block::Stone.into());
+        pending.push(WorldVoxelPos::new(-1, 2, 3), Block::Dirt.into());
+        pending.push(WorldVoxelPos::new(1, 2, 3), Block::Sand.into());
+        assert_eq!((pending.len(), pending.chunks().count()), (3, 2));
+        assert!(pending.contains(&chunk));
+
+        let edits = pending.take(&chunk).unwrap();
+        assert_eq!(edits, vec![(LocalVoxelPos::new(1, 2, 3), Block::Stone.into()), (LocalVoxelPos::new(1, 2, 3), Block::Sand.into())]);
+        assert!(!pending.contains(&chunk) && pending.take(&chunk).is_none());
+        assert_eq!(pending.len(), 1);
+    }
+
+    #[test]
+    fn test_merged_edits_apply_after_existing_ones() {
+        let blocks = BlockRegistry::builtin();
+        let (first, second) = (WorldVoxelPos::new(4, 4, 4), WorldVoxelPos::new(5, 4, 4));
+        let mut pending = PendingEdits::default();
+        pending.push(first, Block::Stone.into());
+        let mut later = PendingEdits::default();
+        later.push(first, Block::Dirt.into());
+        later.push(second, Block::Sand.into());
+        pending.merge(later);
+        assert_eq!(pending.len(), 3);
+
+        let mut chunk = Chunk::new(ChunkPosition::new(0, 0, 0));
+        chunk.set(second.local(), Block::Bedrock.into());
+        assert!(pending.apply(&mut chunk, &blocks));
+        assert_eq!(chunk.get(first.local()), Block::Dirt.into());
+        // Unbreakable blocks are kept
+        assert_eq!(chunk.get(second.local()), Block::Bedrock.into());
+        assert!(pending.is_empty());
+        assert!(!pending.apply(&mut chunk, &blocks));
+    }
+
+    #[test]
+    fn test_pending_edits_survive_a_save() {
+        let root = TempDir::new("pending-edits");
+        let blocks = BlockRegistry::builtin();
+        let mut pending = PendingEdits::default();
+        pending.push(WorldVoxelPos::new(-1, 40, 17), Block::Stone.into());
+        pending.push(WorldVoxelPos::new(-1, 41, 17), Voxel::Empty);
+        pending.push(WorldVoxelPos::new(100, -3, 2), Block::Dirt.into());
+        pending.save(&root).unwrap();
+
+        let mut loaded = PendingEdits::load(&root, &blocks).unwrap();
+        assert_eq!(loaded.len(), 3);
+        for (chunk, edits) in pending.edits.iter() {
+            assert_eq!(loaded.take(chunk).as_ref(), Some(edits));
+        }
+        assert!(loaded.is_empty());
+
+        // Nothing left to apply, the file goes away
+        PendingEdits::default().save(&root).unwrap();
+        assert!(!root.join(PENDING_EDITS_FILE).exists());
+        assert!(PendingEdits::load(&root, &blocks).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_damaged_pending_edits_are_rejected() {
+        let mut pending = PendingEdits::default();
+        pending.push(WorldVoxelPos::new(3, 4, 5), Block::Stone.into());
+        let mut bytes = pending.encode();
+        let last = bytes.len() - 5;
+        bytes[last] ^= 0xff;
+        assert!(PendingEdits::decode(&bytes, &BlockRegistry::builtin()).is_err());
+    }
+}
@@ -0,0 +1,484 @@
+//! Chunk persistence running on a dedicated IO thread.
+//!
+//! Disk IO never runs on the async compute pool used for generation and meshing.
+//! Requests go through a bounded queue, saves that don't fit are kept in a backlog
+//! and handed over to the IO thread over the next frames.
+//!
+//! A chunk file that fails its checksum or can not be decoded is moved aside with a
+//! [`CORRUPT_FILE_EXTENSION`] and the chunk is generated again, a [`ChunkLoadFailed`] event
+//! reports it. Files written by a newer build or failing to read, e.g. for missing permissions,
+//! are left alone: the chunk is generated for this session but never saved over them.
+
+use std::{
+    collections::VecDeque,
+    fs,
+    io,
+    path::{Path, PathBuf},
+    sync::{mpsc::{self, Receiver, SyncSender, TrySendError}, Arc, Mutex},
+    thread::JoinHandle,
+};
+
+use bevy::{prelude::*, utils::HashSet};
+
+use super::{block_registry::BlockRegistry, chunk::{Chunk, ChunkPosition}, migration::{MigrationError, MigrationRegistry}, serialization};
+
+/// Maximum number of requests waiting for the IO thread
+const IO_QUEUE_CAPACITY: usize = 256;
+const CHUNK_FILE_EXTENSION: &str = "chunk";
+/// Damaged chunk files are renamed to this extension, kept for inspection but never loaded
+pub const CORRUPT_FILE_EXTENSION: &str = "corrupt";
+
+enum IoRequest {
+    Save(Chunk),
+    Load(ChunkPosition),
+    Shutdown,
+}
+
+pub enum IoResponse {
+    Loaded(Chunk),
+    /// The chunk is not stored on disk
+    Missing(ChunkPosition),
+    LoadFailed(ChunkPosition, LoadError),
+    SaveFailed(ChunkPosition, String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    /// The file could not be read, it is kept
+    Io(String),
+    /// Written by a newer build or a version without migration, the file is kept
+    Unsupported(String),
+    /// The file was damaged and moved aside
+    Corrupted(String),
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) | Self::Unsupported(err) => write!(f, "{}", err),
+            Self::Corrupted(err) => write!(f, "{}, discarded", err),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// Sent when a stored chunk could not be loaded and is generated again instead
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct ChunkLoadFailed {
+    pub chunk: ChunkPosition,
+    pub error: LoadError,
+}
+
+/// Handle to the chunk IO thread
+#[derive(Resource)]
+pub struct ChunkStorage {
+    root: PathBuf,
+    migrations: Arc<MigrationRegistry>,
+    /// Stored voxels are checked against these, see [`BlockRegistry::for_world`]
+    blocks: BlockRegistry,
+    requests: SyncSender<IoRequest>,
+    responses: Mutex<Receiver<IoResponse>>,
+    /// Saves that did not fit into the request queue yet
+    save_backlog: VecDeque<Chunk>,
+    /// Chunks known to be stored on disk
+    saved: HashSet<ChunkPosition>,
+    /// Chunks currently being loaded by the IO thread
+    loading: HashSet<ChunkPosition>,
+    /// Stored chunks that could not be read, saves of them are dropped to keep the files
+    unreadable: HashSet<ChunkPosition>,
+    /// Loads answered from the backlog, returned by the next [`ChunkStorage::poll`]
+    answered: Vec<IoResponse>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ChunkStorage {
+    /// Opens chunk storage of the builtin blocks in the given directory, creating it if needed
+    pub fn open(root: impl Into<PathBuf>) -> io::Result<Self> {
+        Self::open_with(root, Arc::new(MigrationRegistry::builtin()), BlockRegistry::builtin())
+    }
+
+    /// Like [`ChunkStorage::open`], chunks saved by older versions are upgraded with the given
+    /// migrations and their voxels are blocks of `blocks`
+    pub fn open_with(root: impl Into<PathBuf>, migrations: Arc<MigrationRegistry>, blocks: BlockRegistry) -> io::Result<Self> {
+        let root = root.into();
+        let chunks_dir = root.join("chunks");
+        fs::create_dir_all(&chunks_dir)?;
+
+        let saved = fs::read_dir(&chunks_dir)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| parse_chunk_file_name(&entry.path()))
+            .collect::<HashSet<_>>();
+
+        let (requests, request_receiver) = mpsc::sync_channel(IO_QUEUE_CAPACITY);
+        let (response_sender, responses) = mpsc::channel();
+        let thread = std::thread::Builder::new()
+            .name("chunk-io".to_string())
+            .spawn({
+                let (migrations, blocks) = (migrations.clone(), blocks.clone());
+                move || run_io_thread(chunks_dir, migrations, blocks, request_receiver, response_sender)
+            })?;
+
+        Ok(Self {
+            root,
+            migrations,
+            blocks,
+            requests,
+            responses: Mutex::new(responses),
+            save_backlog: VecDeque::new(),
+            saved,
+            loading: HashSet::default(),
+            unreadable: HashSet::default(),
+            answered: Vec::new(),
+            thread: Some(thread),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn is_saved(&self, chunk: &ChunkPosition) -> bool {
+        self.saved.contains(chunk)
+    }
+
+    pub fn is_loading(&self, chunk: &ChunkPosition) -> bool {
+        self.loading.contains(chunk)
+    }
+
+    /// Whether the stored chunk failed to load without being damaged, see [`LoadError`]. It is
+    /// generated instead and never saved, so the file is kept.
+    pub fn is_unreadable(&self, chunk: &ChunkPosition) -> bool {
+        self.unreadable.contains(chunk)
+    }
+
+    pub fn saved_count(&self) -> usize {
+        self.saved.len()
+    }
+
+    /// Number of saves waiting to be handed over to the IO thread
+    pub fn backlog_len(&self) -> usize {
+        self.save_backlog.len()
+    }
+
+    /// Queues a chunk to be written to disk. Never drops the chunk, if the IO queue
+    /// is full it is kept in the backlog until there is room. Chunks whose file can
+    /// not be read are not saved, see [`ChunkStorage::is_unreadable`].
+    pub fn save(&mut self, chunk: Chunk) {
+        if self.unreadable.contains(&chunk.position) {
+            return;
+        }
+        self.saved.insert(chunk.position);
+        if !self.save_backlog.is_empty() {
+            self.save_backlog.push_back(chunk);
+            return;
+        }
+        if let Err(TrySendError::Full(IoRequest::Save(chunk))) = self.requests.try_send(IoRequest::Save(chunk)) {
+            self.save_backlog.push_back(chunk);
+        }
+    }
+
+    /// Asks the IO thread to load a chunk. Returns false if the queue is full, try again later in that case.
+    pub fn request_load(&mut self, chunk: ChunkPosition) -> bool {
+        if self.loading.contains(&chunk) {
+            return true;
+        }
+        // The file on disk is older than a save still waiting in the backlog
+        if let Some(saved) = self.save_backlog.iter().rev().find(|saved| saved.position == chunk) {
+            self.answered.push(IoResponse::Loaded(saved.clone()));
+            self.loading.insert(chunk);
+            return true;
+        }
+        match self.requests.try_send(IoRequest::Load(chunk)) {
+            Ok(()) => {
+                self.loading.insert(chunk);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Reads a chunk on the calling thread, for chunks that can't wait for the IO thread.
+    /// `None` if it is not stored on disk or could not be read before.
+    pub fn load_now(&mut self, chunk: ChunkPosition) -> Result<Option<Chunk>, LoadError> {
+        // Saves still waiting in the backlog are newer than what is on disk
+        if let Some(saved) = self.save_backlog.iter().rev().find(|saved| saved.position == chunk) {
+            return Ok(Some(saved.clone()));
+        }
+        if !self.saved.contains(&chunk) || self.unreadable.contains(&chunk) {
+            return Ok(None);
+        }
+        let loaded = read_chunk_file(&self.root.join("chunks"), chunk, &self.migrations, &self.blocks);
+        match &loaded {
+            Err(LoadError::Corrupted(_)) => {
+                self.saved.remove(&chunk);
+            }
+            Err(_) => {
+                self.unreadable.insert(chunk);
+            }
+            Ok(_) => (),
+        }
+        loaded
+    }
+
+    /// Hands backlogged saves over to the IO thread while there is room in the queue
+    pub fn pump(&mut self) {
+        while let Some(chunk) = self.save_backlog.pop_front() {
+            if let Err(TrySendError::Full(IoRequest::Save(chunk))) = self.requests.try_send(IoRequest::Save(chunk)) {
+                self.save_backlog.push_front(chunk);
+                break;
+            }
+        }
+    }
+
+    /// Collects finished IO operations
+    pub fn poll(&mut self) -> Vec<IoResponse> {
+        let mut responses = std::mem::take(&mut self.answered);
+        responses.extend(self.responses.lock().unwrap().try_iter());
+        for response in responses.iter() {
+            match response {
+                IoResponse::Loaded(chunk) => {
+                    self.loading.remove(&chunk.position);
+                }
+                // The file is still there, only a damaged one was moved aside
+                IoResponse::LoadFailed(chunk, LoadError::Unsupported(_) | LoadError::Io(_)) => {
+                    self.loading.remove(chunk);
+                    self.unreadable.insert(*chunk);
+                }
+                IoResponse::Missing(chunk) | IoResponse::LoadFailed(chunk, LoadError::Corrupted(_)) => {
+                    self.loading.remove(chunk);
+                    self.saved.remove(chunk);
+                }
+                IoResponse::SaveFailed(chunk, _) => {
+                    self.saved.remove(chunk);
+                }
+            }
+        }
+        responses
+    }
+
+    /// Blocks until every queued save is written and stops the IO thread
+    pub fn shutdown(&mut self) {
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+        for chunk in self.save_backlog.drain(..) {
+            let _ = self.requests.send(IoRequest::Save(chunk));
+        }
+        let _ = self.requests.send(IoRequest::Shutdown);
+        let _ = thread.join();
+    }
+}
+
+impl Drop for ChunkStorage {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn chunk_file_name(chunk: &ChunkPosition) -> String {
+    format!("{}_{}_{}.{}", chunk.x, chunk.y, chunk.z, CHUNK_FILE_EXTENSION)
+}
+
+fn parse_chunk_file_name(path: &Path) -> Option<ChunkPosition> {
+    if path.extension()? != CHUNK_FILE_EXTENSION {
+        return None;
+    }
+    let mut parts = path.file_stem()?.to_str()?.split('_').map(|part| part.parse::<i32>());
+    let position = ChunkPosition::new(parts.next()?.ok()?, parts.next()?.ok()?, parts.next()?.ok()?);
+    match parts.next() {
+        Some(_) => None,
+        None => Some(position),
+    }
+}
+
+/// Reads, upgrades and decodes a stored chunk. A damaged file is moved aside so the chunk is
+/// generated again and saved over it, instead of failing to load every time.
+fn read_chunk_file(chunks_dir: &Path, position: ChunkPosition, migrations: &MigrationRegistry, blocks: &BlockRegistry) -> Result<Option<Chunk>, LoadError> {
+    let path = chunks_dir.join(chunk_file_name(&position));
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(LoadError::Io(format!("{}: {}", path.display(), err))),
+    };
+    let decoded = match migrations.migrate_chunk(&bytes) {
+        Ok(bytes) => serialization::decode(&bytes, blocks).map_err(|err| err.to_string()).and_then(|chunk| match chunk.position == position {
+            true => Ok(chunk),
+            false => Err(format!("contains chunk {:?}", chunk.position)),
+        }),
+        Err(err @ (MigrationError::TooNew(_) | MigrationError::Missing(_))) => {
+            return Err(LoadError::Unsupported(format!("{}: {}", path.display(), err)));
+        }
+        Err(err) => Err(err.to_string()),
+    };
+    decoded.map(Some).map_err(|err| {
+        let discarded = path.with_extension(CORRUPT_FILE_EXTENSION);
+        if let Err(rename_err) = fs::rename(&path, &discarded) {
+            error!("Failed to move corrupted chunk file {} aside: {}", path.display(), rename_err);
+        }
+        LoadError::Corrupted(format!("{}: {}", path.display(), err))
+    })
+}
+
+fn run_io_thread(
+    chunks_dir: PathBuf,
+    migrations: Arc<MigrationRegistry>,
+    blocks: BlockRegistry,
+    requests: Receiver<IoRequest>,
+    responses: mpsc::Sender<IoResponse>,
+) {
+    while let Ok(request) = requests.recv() {
+        let response = match request {
+            IoRequest::Save(chunk) => {
+                let path = chunks_dir.join(chunk_file_name(&chunk.position));
+                write_atomic(&path, &serialization::encode(&chunk))
+                    .err()
+                    .map(|err| IoResponse::SaveFailed(chunk.position, err.to_string()))
+            }
+            IoRequest::Load(position) => Some(match read_chunk_file(&chunks_dir, position, &migrations, &blocks) {
+                Ok(Some(chunk)) => IoResponse::Loaded(chunk),
+                Ok(None) => IoResponse::Missing(position),
+                Err(err) => IoResponse::LoadFailed(position, err),
+            }),
+            IoRequest::Shutdown => break,
+        };
+
+        if let Some(response) = response {
+            if responses.send(response).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Writes to a temporary file first so a crash never leaves a half written chunk behind
+fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, bytes)?;
+    fs::rename(tmp, path)
+}
+
+/// Marks chunks that wait for the IO thread to load them
+#[derive(Component)]
+pub struct AwaitingLoad {
+    pub chunk_pos: ChunkPosition,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{coords::LocalVoxelPos, voxel::{Block, Voxel}};
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn test_corrupted_chunks_are_moved_aside() {
+        let root = TempDir::new("corrupt-chunks");
+        let chunks_dir = root.join("chunks");
+        fs::create_dir_all(&chunks_dir).unwrap();
+        let migrations = MigrationRegistry::builtin();
+
+        let position = ChunkPosition::new(1, -2, 3);
+        let mut chunk = Chunk::new(position);
+        chunk.fill(Voxel::from(Block::Stone));
+        let mut bytes = serialization::encode(&chunk);
+        let path = chunks_dir.join(chunk_file_name(&position));
+        fs::write(&path, &bytes).unwrap();
+        assert!(read_chunk_file(&chunks_dir, position, &migrations, &BlockRegistry::builtin()).unwrap().is_some());
+
+        // Newer data is kept for the build that wrote it
+        serialization::set_version(&mut bytes, serialization::FORMAT_VERSION + 1);
+        fs::write(&path, &bytes).unwrap();
+        assert!(matches!(read_chunk_file(&chunks_dir, position, &migrations, &BlockRegistry::builtin()), Err(LoadError::Unsupported(_))));
+        assert!(path.exists());
+
+        serialization::set_version(&mut bytes, serialization::FORMAT_VERSION);
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        fs::write(&path, &bytes).unwrap();
+        assert!(matches!(read_chunk_file(&chunks_dir, position, &migrations, &BlockRegistry::builtin()), Err(LoadError::Corrupted(_))));
+        assert!(!path.exists());
+        assert!(path.with_extension(CORRUPT_FILE_EXTENSION).exists());
+        assert!(matches!(read_chunk_file(&chunks_dir, position, &migrations, &BlockRegistry::builtin()), Ok(None)));
+    }
+
+    #[test]
+    fn test_chunks_from_newer_builds_are_never_saved() {
+        let root = TempDir::new("newer-chunks");
+        let chunks_dir = root.join("chunks");
+        fs::create_dir_all(&chunks_dir).unwrap();
+        let position = ChunkPosition::new(0, 0, 0);
+        let mut bytes = serialization::encode(&Chunk::new(position));
+        serialization::set_version(&mut bytes, serialization::FORMAT_VERSION + 1);
+        let path = chunks_dir.join(chunk_file_name(&position));
+        fs::write(&path, &bytes).unwrap();
+
+        let mut storage = ChunkStorage::open(root.path()).unwrap();
+        assert!(storage.request_load(position));
+        let mut responses = Vec::new();
+        while storage.is_loading(&position) {
+            responses.extend(storage.poll());
+        }
+        assert!(matches!(responses[..], [IoResponse::LoadFailed(_, LoadError::Unsupported(_))]));
+        assert!(storage.is_saved(&position) && storage.is_unreadable(&position));
+        assert!(matches!(storage.load_now(position), Ok(None)));
+
+        // The regenerated chunk is not written over the file
+        let mut chunk = Chunk::new(position);
+        chunk.fill(Voxel::from(Block::Stone));
+        storage.save(chunk);
+        storage.shutdown();
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_chunks_failing_to_read_are_never_saved() {
+        let root = TempDir::new("unreadable-chunks");
+        let chunks_dir = root.join("chunks");
+        // Reading a directory fails without the file being missing or damaged
+        let position = ChunkPosition::new(2, 0, -1);
+        let path = chunks_dir.join(chunk_file_name(&position));
+        fs::create_dir_all(&path).unwrap();
+        fs::write(path.join("edits"), b"player edits").unwrap();
+
+        let mut storage = ChunkStorage::open(root.path()).unwrap();
+        assert!(storage.is_saved(&position));
+        assert!(storage.request_load(position));
+        let mut responses = Vec::new();
+        while storage.is_loading(&position) {
+            responses.extend(storage.poll());
+        }
+        assert!(matches!(responses[..], [IoResponse::LoadFailed(_, LoadError::Io(_))]));
+        assert!(storage.is_saved(&position) && storage.is_unreadable(&position));
+
+        storage.save(Chunk::new(position));
+        storage.shutdown();
+        assert!(!path.with_extension("tmp").exists());
+        assert_eq!(fs::read(path.join("edits")).unwrap(), b"player edits");
+        assert!(!path.with_extension(CORRUPT_FILE_EXTENSION).exists());
+    }
+
+    #[test]
+    fn test_loads_see_backlogged_saves() {
+        let root = TempDir::new("backlog-load");
+        let mut storage = ChunkStorage::open(root.path()).unwrap();
+
+        // Fill the queue until saves go to the backlog
+        let position = ChunkPosition::new(0, 1, 0);
+        while storage.backlog_len() == 0 {
+            storage.save(Chunk::new(position));
+        }
+        let mut chunk = Chunk::new(position);
+        chunk.fill(Voxel::from(Block::Stone));
+        storage.save(chunk);
+
+        assert!(storage.request_load(position));
+        let loaded = storage.poll().into_iter().find_map(|response| match response {
+            IoResponse::Loaded(chunk) if chunk.position == position => Some(chunk),
+            _ => None,
+        });
+        assert_eq!(loaded.unwrap().get(LocalVoxelPos::new(0, 0, 0)), Voxel::from(Block::Stone));
+        assert!(!storage.is_loading(&position));
+
+        storage.shutdown();
+    }
+}
@@ -0,0 +1,142 @@
+//! Reference counted chunk pins. Pinned chunks keep their mesh and are never garbage collected,
+//! see [`ChunkData::pin`](super::ChunkData::pin). Pinning does not load a chunk.
+//!
+//! Debug builds remember where a chunk was pinned and warn about pins held for too long.
+
+use bevy::utils::HashMap;
+
+#[cfg(debug_assertions)]
+use bevy::prelude::*;
+#[cfg(debug_assertions)]
+use std::{panic::Location, time::{Duration, Instant}};
+
+use super::chunk::ChunkPosition;
+
+/// Pins held longer than this are reported as leaked
+#[cfg(debug_assertions)]
+pub const PIN_LEAK_AGE: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+struct Pin {
+    count: usize,
+    #[cfg(debug_assertions)]
+    since: Instant,
+    /// Where the chunk was pinned first
+    #[cfg(debug_assertions)]
+    location: &'static Location<'static>,
+    #[cfg(debug_assertions)]
+    reported: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct ChunkPins {
+    pins: HashMap<ChunkPosition, Pin>,
+}
+
+impl ChunkPins {
+    #[track_caller]
+    pub fn pin(&mut self, chunk: ChunkPosition) {
+        self.pins
+            .entry(chunk)
+            .or_insert_with(|| Pin {
+                count: 0,
+                #[cfg(debug_assertions)]
+                since: Instant::now(),
+                #[cfg(debug_assertions)]
+                location: Location::caller(),
+                #[cfg(debug_assertions)]
+                reported: false,
+            })
+            .count += 1;
+    }
+
+    /// Releases one pin, returns false if the chunk was not pinned
+    pub fn unpin(&mut self, chunk: ChunkPosition) -> bool {
+        let Some(pin) = self.pins.get_mut(&chunk) else {
+            return false;
+        };
+        pin.count -= 1;
+        if pin.count == 0 {
+            self.pins.remove(&chunk);
+        }
+        true
+    }
+
+    pub fn is_pinned(&self, chunk: &ChunkPosition) -> bool {
+        self.pins.contains_key(chunk)
+    }
+
+    /// Number of pins held on a chunk
+    pub fn count(&self, chunk: &ChunkPosition) -> usize {
+        self.pins.get(chunk).map_or(0, |pin| pin.count)
+    }
+
+    /// Number of pinned chunks
+    pub fn len(&self) -> usize {
+        self.pins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pins.is_empty()
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = &ChunkPosition> {
+        self.pins.keys()
+    }
+
+    /// Pins older than `max_age` that were not reported yet, every pin is reported once
+    #[cfg(debug_assertions)]
+    pub fn take_leaks(&mut self, max_age: Duration) -> Vec<(ChunkPosition, &'static Location<'static>, Duration)> {
+        let mut leaks = Vec::new();
+        for (chunk, pin) in self.pins.iter_mut() {
+            let age = pin.since.elapsed();
+            if !pin.reported && age >= max_age {
+                pin.reported = true;
+                leaks.push((*chunk, pin.location, age));
+            }
+        }
+        leaks
+    }
+}
+
+/// Warns about pins that were probably forgotten
+#[cfg(debug_assertions)]
+pub fn detect_leaked_pins(mut chunk_data: ResMut<super::ChunkData>) {
+    if chunk_data.pins.is_empty() {
+        return;
+    }
+    for (chunk, location, age) in chunk_data.pins.take_leaks(PIN_LEAK_AGE) {
+        warn!("Chunk {:?} has been pinned for {:.0}s, pinned first at {}", chunk, age.as_secs_f32(), location);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pins_are_counted() {
+        let mut pins = ChunkPins::default();
+        let chunk = ChunkPosition::new(1, -2, 3);
+        pins.pin(chunk);
+        pins.pin(chunk);
+        assert_eq!(pins.count(&chunk), 2);
+
+        assert!(pins.unpin(chunk));
+        assert!(pins.is_pinned(&chunk));
+        assert!(pins.unpin(chunk));
+        assert!(!pins.is_pinned(&chunk));
+        assert!(!pins.unpin(chunk));
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn test_leaks_are_reported_once() {
+        let mut pins = ChunkPins::default();
+        pins.pin(ChunkPosition::new(0, 0, 0));
+        let leaks = pins.take_leaks(Duration::ZERO);
+        assert_eq!(leaks.len(), 1);
+        assert!(leaks[0].1.file().ends_with("pins.rs"));
+        assert!(pins.take_leaks(Duration::ZERO).is_empty());
+    }
+}
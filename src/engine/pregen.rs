@@ -0,0 +1,335 @@
+//! Generates a region of the world right away instead of through the streaming systems.
+//!
+//! [`pregenerate_region`] restores, loads or generates every chunk in a sphere, on the calling
+//! thread and in a fixed order, so the result does not depend on task timing. Edits chunks leave
+//! for their neighbours are applied before anything is meshed. The chunks are spawned with their
+//! meshes ready, [`apply_meshes`](super::generator::apply_meshes) gives them materials as usual.
+//!
+//! With [`LoadingSettings::pregenerate_radius`] set, the spawn area is pregenerated when loading
+//! starts, before the streaming systems run for the first time.
+//!
+//! [`pregenerate_to_storage`] prepares a world without an app, straight into its chunk storage on
+//! worker threads. The `pregen` binary runs it for big regions ahead of playing.
+
+use std::{
+    ops::RangeInclusive,
+    sync::{atomic::{AtomicUsize, Ordering}, mpsc},
+    time::{Duration, Instant},
+};
+
+use bevy::{prelude::*, utils::{HashMap, HashSet}};
+
+use super::{
+    anchor::StreamingAnchor,
+    autosave::DirtyChunks,
+    cache::ChunkCache,
+    chunk::{Chunk, ChunkPosition},
+    generation_context::GenerationContext,
+    generator::{AwaitingGeneration, ChunkGenerationTask, EmptyChunkMarker, Generating, MeshState, MeshingTask, WorldGeneratorConfig},
+    heightmap::HeightmapCache,
+    loading::LoadingSettings,
+    meshing::{ChunkBorders, ChunkMesher},
+    pending_edits::PendingEdits,
+    persistence::{AwaitingLoad, ChunkLoadFailed, ChunkStorage},
+    ChunkData,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PregenerationReport {
+    pub generated: usize,
+    /// Chunks taken from the cache or from disk
+    pub restored: usize,
+    /// Chunks that were loaded already
+    pub skipped: usize,
+    /// Chunks without any faces, they get no mesh
+    pub empty: usize,
+    /// Edits left for chunks that were not generated, see [`pregenerate_to_storage`]
+    pub leftover_edits: usize,
+    pub elapsed: Duration,
+}
+
+/// Chunks of the region in the order they are generated, column by column from the top
+pub fn region(center: ChunkPosition, radius: u32) -> impl Iterator<Item = ChunkPosition> {
+    let radius = radius as i32;
+    let radius_squared = (radius as i64) * (radius as i64);
+    (-radius..=radius).flat_map(move |x| {
+        (-radius..=radius).flat_map(move |z| {
+            (-radius..=radius)
+                .rev()
+                .map(move |y| ChunkPosition::new(center.x + x, center.y + y, center.z + z))
+                .filter(move |chunk| chunk.distance_squared_to(&center) <= radius_squared)
+        })
+    })
+}
+
+/// Restores, loads or generates every chunk within `radius` chunks of `center` and spawns it with its mesh
+pub fn pregenerate_region(world: &mut World, center: ChunkPosition, radius: u32) -> PregenerationReport {
+    let started = Instant::now();
+    let config = world.resource::<WorldGeneratorConfig>().clone();
+    let mesher = ChunkMesher::from_world(world);
+    let mut report = PregenerationReport::default();
+
+    let mut chunks: HashMap<ChunkPosition, Chunk> = HashMap::default();
+    let mut order = Vec::new();
+    for chunk_pos in region(center, radius).filter(|chunk_pos| !config.is_outside_world(chunk_pos)) {
+        if world.resource::<ChunkData>().loaded.contains_key(&chunk_pos) {
+            report.skipped += 1;
+            continue;
+        }
+
+        let cached = world.resource_mut::<ChunkCache>().take(&chunk_pos);
+        let restored = match cached {
+            Some(chunk) => Some(chunk),
+            None => match world.get_resource_mut::<ChunkStorage>().map(|mut storage| storage.load_now(chunk_pos)) {
+                Some(Ok(chunk)) => chunk,
+                Some(Err(err)) => {
+                    warn!("Failed to load chunk {:?} for pregeneration, generating it instead: {}", chunk_pos, err);
+                    world.send_event(ChunkLoadFailed { chunk: chunk_pos, error: err });
+                    None
+                }
+                None => None,
+            },
+        };
+        let chunk = match restored {
+            Some(chunk) => {
+                report.restored += 1;
+                chunk
+            }
+            None => {
+                let chunk_data = world.resource::<ChunkData>();
+                let context = GenerationContext::capture(chunk_pos, world.resource::<HeightmapCache>(), |pos| {
+                    chunks.get(&pos).or_else(|| world.get::<Chunk>(*chunk_data.loaded.get(&pos)?))
+                });
+                let (chunk, overflow) = config.generate_in(&context);
+                world.resource_mut::<ChunkData>().pending_edits.merge(overflow);
+                report.generated += 1;
+                chunk
+            }
+        };
+        world.resource_mut::<HeightmapCache>().record_chunk(&chunk);
+        order.push(chunk_pos);
+        chunks.insert(chunk_pos, chunk);
+    }
+
+    // Edits left by chunks generated later in the region land before anything is meshed
+    for chunk_pos in order {
+        let mut chunk = chunks.remove(&chunk_pos).unwrap();
+        if world.resource_mut::<ChunkData>().pending_edits.apply(&mut chunk, &mesher.blocks) {
+            chunk.recalculate_visibility_mask(&mesher.blocks);
+            // Restored chunks may be saved already
+            world.resource_mut::<DirtyChunks>().mark(chunk_pos);
+        }
+
+        let chunk_data = world.resource::<ChunkData>();
+        let borders = ChunkBorders::capture(&chunk_pos, |pos| chunks.get(pos).or_else(|| world.get::<Chunk>(*chunk_data.loaded.get(pos)?)));
+        let chunk_meshes = chunk.build_meshes(&mesher, config.biome_of(&chunk_pos), &borders);
+        let mesh = (!chunk_meshes.is_empty()).then(|| chunk_meshes.map(|mesh| world.resource_mut::<Assets<Mesh>>().add(mesh)));
+        // Takes over an entity the streaming systems may have queued already
+        let entity = match world.resource_mut::<ChunkData>().awaiting_generation.remove(&chunk_pos) {
+            Some(entity) => entity,
+            None => world.spawn_empty().id(),
+        };
+        let mut entity_mut = world.entity_mut(entity);
+        entity_mut.remove::<(AwaitingGeneration, AwaitingLoad, ChunkGenerationTask, Generating)>().insert(chunk);
+        match mesh {
+            Some(mesh) => entity_mut.insert(MeshingTask(chunk_pos, MeshState::Loaded(mesh))),
+            None => {
+                report.empty += 1;
+                entity_mut.insert(EmptyChunkMarker)
+            }
+        };
+        world.resource_mut::<ChunkData>().loaded.insert(chunk_pos, entity);
+    }
+
+    report.elapsed = started.elapsed();
+    report
+}
+
+/// Chunk columns within `radius` columns of `center` on x and z, row by row
+pub fn columns(center: (i32, i32), radius: u32) -> Vec<(i32, i32)> {
+    let radius = radius as i32;
+    let radius_squared = (radius as i64) * (radius as i64);
+    (-radius..=radius)
+        .flat_map(|x| (-radius..=radius).map(move |z| (x, z)))
+        .filter(|(x, z)| (*x as i64) * (*x as i64) + (*z as i64) * (*z as i64) <= radius_squared)
+        .map(|(x, z)| (center.0 + x, center.1 + z))
+        .collect()
+}
+
+/// The column and the eight around it
+fn neighbourhood(column: (i32, i32)) -> impl Iterator<Item = (i32, i32)> {
+    (-1..=1).flat_map(move |x| (-1..=1).map(move |z| (column.0 + x, column.1 + z)))
+}
+
+/// Generates every chunk of the `columns` between the `layers` chunk levels that is not saved yet
+/// and hands it to `storage`, on `threads` worker threads. `progress` is called with the number of
+/// finished columns after each one.
+///
+/// A column is written once every column next to it is generated, so the edits chunks leave for
+/// their neighbours land before it is saved. `pending_edits` are the edits saved with the world,
+/// the ones for chunks outside of the region or saved before are left in there for the game to
+/// apply, the report counts them.
+pub fn pregenerate_to_storage(
+    config: &WorldGeneratorConfig,
+    storage: &mut ChunkStorage,
+    pending_edits: &mut PendingEdits,
+    columns: &[(i32, i32)],
+    layers: RangeInclusive<i32>,
+    threads: usize,
+    mut progress: impl FnMut(usize),
+) -> PregenerationReport {
+    let started = Instant::now();
+    let mut report = PregenerationReport::default();
+    let work = columns
+        .iter()
+        .map(|&(x, z)| {
+            let (saved, missing) = layers
+                .clone()
+                .map(|y| ChunkPosition::new(x, y, z))
+                .filter(|chunk_pos| !config.is_outside_world(chunk_pos))
+                .partition::<Vec<_>, _>(|chunk_pos| storage.is_saved(chunk_pos));
+            report.skipped += saved.len();
+            ((x, z), missing)
+        })
+        .collect::<Vec<_>>();
+    let region = columns.iter().copied().collect::<HashSet<_>>();
+
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::sync_channel(threads.max(1) * 2);
+    std::thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            let (sender, work, next) = (sender.clone(), &work, &next);
+            scope.spawn(move || {
+                while let Some((column, chunks)) = work.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let generated = chunks.iter().map(|chunk_pos| config.generate(*chunk_pos)).collect::<Vec<_>>();
+                    if sender.send((*column, generated)).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        let mut waiting: HashMap<(i32, i32), Vec<Chunk>> = HashMap::default();
+        let mut finished = HashSet::new();
+        for (column, generated) in receiver {
+            let mut chunks = Vec::with_capacity(generated.len());
+            for (chunk, overflow) in generated {
+                pending_edits.merge(overflow);
+                chunks.push(chunk);
+            }
+            report.generated += chunks.len();
+            finished.insert(column);
+            waiting.insert(column, chunks);
+
+            // This column finished the neighbourhood of itself or of a column around it
+            for neighbour in neighbourhood(column) {
+                let complete = neighbourhood(neighbour).all(|column| !region.contains(&column) || finished.contains(&column));
+                let Some(chunks) = complete.then(|| waiting.remove(&neighbour)).flatten() else {
+                    continue;
+                };
+                for mut chunk in chunks {
+                    if pending_edits.apply(&mut chunk, &config.blocks) {
+                        chunk.recalculate_visibility_mask(&config.blocks);
+                    }
+                    storage.save(chunk);
+                }
+            }
+            storage.pump();
+            progress(finished.len());
+        }
+        report.leftover_edits = pending_edits.len();
+    });
+
+    report.elapsed = started.elapsed();
+    report
+}
+
+/// Pregenerates the area around the first streaming anchor when [`LoadingSettings::pregenerate_radius`] is set
+pub fn pregenerate_spawn_area(world: &mut World) {
+    let Some(radius) = world.resource::<LoadingSettings>().pregenerate_radius else {
+        return;
+    };
+    let center = world
+        .query_filtered::<&Transform, With<StreamingAnchor>>()
+        .iter(world)
+        .next()
+        .map_or(ChunkPosition::new(0, 0, 0), |transform| ChunkPosition::from_world_position(transform.translation));
+
+    let report = pregenerate_region(world, center, radius);
+    info!(
+        "Pregenerated {} chunks around {:?} in {:.1}s ({} restored, {} already loaded)",
+        report.generated,
+        center,
+        report.elapsed.as_secs_f32(),
+        report.restored,
+        report.skipped
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{coords::LocalVoxelPos, generator::PerlinHeightmapWorldGenerator};
+    use crate::temp_dir::TempDir;
+
+    fn pregenerated_world(center: ChunkPosition, radius: u32) -> (World, PregenerationReport) {
+        let mut world = World::new();
+        world.insert_resource(WorldGeneratorConfig::default_with(PerlinHeightmapWorldGenerator::default()));
+        world.init_resource::<ChunkData>();
+        world.init_resource::<ChunkCache>();
+        world.init_resource::<HeightmapCache>();
+        world.init_resource::<DirtyChunks>();
+        world.init_resource::<Assets<Mesh>>();
+        let report = pregenerate_region(&mut world, center, radius);
+        (world, report)
+    }
+
+    #[test]
+    fn test_pregeneration_is_deterministic() {
+        let center = ChunkPosition::new(3, 0, -2);
+        let (first, report) = pregenerated_world(center, 2);
+        let expected = region(center, 2).count();
+        assert_eq!(report.generated, expected);
+        assert_eq!(first.resource::<ChunkData>().loaded.len(), expected);
+
+        let (second, _) = pregenerated_world(center, 2);
+        for chunk_pos in region(center, 2) {
+            let chunk_at = |world: &World| world.get::<Chunk>(world.resource::<ChunkData>().loaded[&chunk_pos]).unwrap().clone();
+            let (a, b) = (chunk_at(&first), chunk_at(&second));
+            assert!(LocalVoxelPos::iter().all(|pos| a.get(pos) == b.get(pos)), "chunk {:?} differs", chunk_pos);
+        }
+
+        // Every chunk is either meshed already or known to be empty
+        let entity = first.resource::<ChunkData>().loaded[&center];
+        assert!(first.get::<MeshingTask>(entity).is_some() || first.get::<EmptyChunkMarker>(entity).is_some());
+    }
+
+    #[test]
+    fn test_pregenerated_chunks_are_saved_once() {
+        let root = TempDir::new("pregen");
+        let config = WorldGeneratorConfig::default_with(PerlinHeightmapWorldGenerator::default());
+        let mut storage = ChunkStorage::open(root.path()).unwrap();
+        let columns = columns((2, -1), 1);
+        assert_eq!(columns.len(), 5);
+
+        let mut finished = 0;
+        let mut pending_edits = PendingEdits::default();
+        let report = pregenerate_to_storage(&config, &mut storage, &mut pending_edits, &columns, -1..=1, 3, |done| finished = done);
+        assert_eq!((report.generated, report.skipped, finished), (15, 0, 5));
+        // Every chunk was handed over, running again has nothing left to do
+        let report = pregenerate_to_storage(&config, &mut storage, &mut pending_edits, &columns, -1..=1, 3, |_| {});
+        assert_eq!((report.generated, report.skipped), (0, 15));
+
+        storage.shutdown();
+        let center = ChunkPosition::new(2, 0, -1);
+        assert!(storage.load_now(center).unwrap().unwrap().diff(&config.generate(center).0).is_empty());
+    }
+
+    #[test]
+    fn test_region_is_a_sphere() {
+        let chunks = region(ChunkPosition::new(0, 0, 0), 1).collect::<Vec<_>>();
+        assert_eq!(chunks.len(), 7);
+        assert_eq!(chunks.iter().filter(|chunk| chunk.x == 0 && chunk.z == 0).map(|chunk| chunk.y).collect::<Vec<_>>(), vec![1, 0, -1]);
+    }
+}
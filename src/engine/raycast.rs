@@ -0,0 +1,94 @@
+//! Voxel traversal (Amanatides & Woo), visits every voxel a ray passes through in order.
+
+use bevy::prelude::*;
+
+use super::coords::WorldVoxelPos;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RaycastHit {
+    pub pos: WorldVoxelPos,
+    /// Normal of the face the ray entered the voxel through, zero if the ray started inside it
+    pub normal: IVec3,
+    pub distance: f32,
+}
+
+impl RaycastHit {
+    /// The empty voxel in front of the hit face, where a block would be placed
+    pub fn adjacent(&self) -> WorldVoxelPos {
+        self.pos.offset(self.normal.x as i64, self.normal.y as i64, self.normal.z as i64)
+    }
+}
+
+/// Returns the first voxel along the ray for which `is_solid` returns true
+pub fn raycast(
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+    mut is_solid: impl FnMut(WorldVoxelPos) -> bool,
+) -> Option<RaycastHit> {
+    let direction = direction.normalize_or_zero();
+    if direction == Vec3::ZERO {
+        return None;
+    }
+
+    let mut pos = WorldVoxelPos::from_world(origin);
+    let step = direction.signum().as_ivec3();
+    // Distance along the ray to cross one voxel on each axis
+    let t_delta = direction.abs().recip();
+    // Distance along the ray to the first voxel boundary on each axis
+    let mut t_max = Vec3::select(
+        direction.cmpgt(Vec3::ZERO),
+        (origin.floor() + Vec3::ONE - origin) * t_delta,
+        (origin - origin.floor()) * t_delta,
+    );
+    let mut normal = IVec3::ZERO;
+    let mut distance = 0.0;
+
+    loop {
+        if is_solid(pos) {
+            return Some(RaycastHit { pos, normal, distance });
+        }
+
+        let axis = if t_max.x < t_max.y && t_max.x < t_max.z {
+            0
+        } else if t_max.y < t_max.z {
+            1
+        } else {
+            2
+        };
+        distance = t_max[axis];
+        if distance > max_distance {
+            return None;
+        }
+        t_max[axis] += t_delta[axis];
+
+        let mut offset = IVec3::ZERO;
+        offset[axis] = step[axis];
+        pos = pos.offset(offset.x as i64, offset.y as i64, offset.z as i64);
+        normal = -offset;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_raycast_hits_first_solid_voxel() {
+        let floor = |pos: WorldVoxelPos| pos.y < -3;
+        let hit = raycast(Vec3::new(0.5, 0.5, 0.5), Vec3::NEG_Y, 16.0, floor).unwrap();
+        assert_eq!(hit.pos, WorldVoxelPos::new(0, -4, 0));
+        assert_eq!(hit.normal, IVec3::Y);
+        assert_eq!(hit.adjacent(), WorldVoxelPos::new(0, -3, 0));
+        assert!((hit.distance - 3.5).abs() < 1e-5);
+
+        assert_eq!(raycast(Vec3::new(0.5, 0.5, 0.5), Vec3::NEG_Y, 2.0, floor), None);
+    }
+
+    #[test]
+    fn test_raycast_diagonal_negative() {
+        let target = WorldVoxelPos::new(-3, 0, -3);
+        let hit = raycast(Vec3::new(0.5, 0.5, 0.5), Vec3::new(-1.0, 0.0, -1.0), 16.0, |pos| pos == target);
+        assert_eq!(hit.map(|hit| hit.pos), Some(target));
+    }
+}
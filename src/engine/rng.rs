@@ -0,0 +1,104 @@
+//! Deterministic per-chunk random numbers for world generation.
+//!
+//! The generator is seeded from the world seed, the chunk position and a salt, so every chunk
+//! gets the same numbers no matter in which order chunks are generated. Use a different salt
+//! for every feature so adding one feature does not shift the placement of another.
+
+use super::chunk::ChunkPosition;
+
+/// SplitMix64, small and good enough for placing things in the world. Not for anything security related.
+#[derive(Debug, Clone)]
+pub struct ChunkRng {
+    state: u64,
+}
+
+impl ChunkRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Generator for one feature of one chunk
+    pub fn for_chunk(world_seed: u64, chunk: ChunkPosition, salt: u64) -> Self {
+        let mut seed = mix(world_seed ^ mix(salt));
+        for axis in [chunk.x, chunk.y, chunk.z] {
+            seed = mix(seed ^ axis as u32 as u64);
+        }
+        Self::new(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        mix(self.state)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// Uniform in `[0, 1)`
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Uniform in `min..max`, `min` if the range is empty
+    pub fn range(&mut self, min: i32, max: i32) -> i32 {
+        if max <= min {
+            return min;
+        }
+        let span = (max as i64 - min as i64) as u64;
+        (min as i64 + (self.next_u64() % span) as i64) as i32
+    }
+
+    /// True with the given probability
+    pub fn chance(&mut self, probability: f32) -> bool {
+        self.next_f32() < probability
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            return None;
+        }
+        items.get(self.range(0, items.len() as i32) as usize)
+    }
+}
+
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_rng_is_deterministic() {
+        let chunk = ChunkPosition::new(-3, 0, 7);
+        let a = (0..8).scan(ChunkRng::for_chunk(42, chunk, 1), |rng, _| Some(rng.next_u64())).collect::<Vec<_>>();
+        let b = (0..8).scan(ChunkRng::for_chunk(42, chunk, 1), |rng, _| Some(rng.next_u64())).collect::<Vec<_>>();
+        assert_eq!(a, b);
+
+        // Neighbours, other seeds and other salts all differ
+        assert_ne!(ChunkRng::for_chunk(42, chunk, 1).next_u64(), ChunkRng::for_chunk(42, ChunkPosition::new(-3, 0, 8), 1).next_u64());
+        assert_ne!(ChunkRng::for_chunk(42, chunk, 1).next_u64(), ChunkRng::for_chunk(43, chunk, 1).next_u64());
+        assert_ne!(ChunkRng::for_chunk(42, chunk, 1).next_u64(), ChunkRng::for_chunk(42, chunk, 2).next_u64());
+        // Axes are not interchangeable
+        assert_ne!(
+            ChunkRng::for_chunk(0, ChunkPosition::new(1, 2, 3), 0).next_u64(),
+            ChunkRng::for_chunk(0, ChunkPosition::new(3, 2, 1), 0).next_u64()
+        );
+    }
+
+    #[test]
+    fn test_ranges() {
+        let mut rng = ChunkRng::new(7);
+        for _ in 0..1000 {
+            let value = rng.range(-5, 5);
+            assert!((-5..5).contains(&value));
+            assert!((0.0..1.0).contains(&rng.next_f32()));
+        }
+        assert_eq!(rng.range(3, 3), 3);
+        assert_eq!(rng.pick::<u8>(&[]), None);
+    }
+}
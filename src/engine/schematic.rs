@@ -0,0 +1,259 @@
+//! Boxes of voxels copied out of the world, pasted back elsewhere and stored in schematic files.
+//!
+//! File layout (all integers little endian):
+//! ```text
+//! magic      4 bytes  "VXSC"
+//! version    u16
+//! size       3 × u32
+//! run_count  u32
+//! runs       run_count × (u16 voxel code, u32 length)
+//! ```
+
+use std::{fs, io, path::Path};
+
+use bevy::prelude::*;
+
+use super::{block_registry::BlockRegistry, coords::WorldVoxelPos, migration::code_before_water, pending_edits::PendingEdits, voxel::Voxel};
+
+pub const MAGIC: &[u8; 4] = b"VXSC";
+/// Version 1 was written before water became a builtin block, see [`code_before_water`]
+pub const FORMAT_VERSION: u16 = 2;
+pub const FILE_EXTENSION: &str = "vxs";
+/// Largest volume read from a file, larger sizes in a header are treated as a damaged file
+pub const MAX_VOLUME: u64 = 256 * 256 * 256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchematicError {
+    UnexpectedEof,
+    BadMagic,
+    UnsupportedVersion(u16),
+    UnknownVoxel(u16),
+    /// Runs don't add up to the volume of the schematic
+    WrongVoxelCount(usize),
+    /// The size in the header is larger than [`MAX_VOLUME`]
+    TooLarge([u32; 3]),
+}
+
+impl std::fmt::Display for SchematicError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnexpectedEof => write!(f, "unexpected end of schematic data"),
+            Self::BadMagic => write!(f, "not a schematic (bad magic)"),
+            Self::UnsupportedVersion(version) => write!(f, "unsupported schematic version {}", version),
+            Self::UnknownVoxel(code) => write!(f, "unknown voxel code {}", code),
+            Self::WrongVoxelCount(count) => write!(f, "schematic contains {} voxels, expected a different amount", count),
+            Self::TooLarge([x, y, z]) => write!(f, "schematic of {}×{}×{} voxels is larger than {} voxels", x, y, z, MAX_VOLUME),
+        }
+    }
+}
+
+impl std::error::Error for SchematicError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schematic {
+    size: UVec3,
+    voxels: Vec<Voxel>,
+}
+
+impl Schematic {
+    pub fn new(size: UVec3) -> Self {
+        Self {
+            size,
+            voxels: vec![Voxel::Empty; (size.x * size.y * size.z) as usize],
+        }
+    }
+
+    /// Copies the box between two corners (both inclusive, in any order)
+    pub fn copy(a: WorldVoxelPos, b: WorldVoxelPos, voxel_at: impl Fn(WorldVoxelPos) -> Voxel) -> Self {
+        let min = WorldVoxelPos::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+        let max = WorldVoxelPos::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+        let size = UVec3::new((max.x - min.x + 1) as u32, (max.y - min.y + 1) as u32, (max.z - min.z + 1) as u32);
+
+        let mut schematic = Self::new(size);
+        for (x, y, z) in schematic.positions() {
+            schematic.set(x, y, z, voxel_at(min.offset(x as i64, y as i64, z as i64)));
+        }
+        schematic
+    }
+
+    pub fn size(&self) -> UVec3 {
+        self.size
+    }
+
+    fn index(&self, x: u32, y: u32, z: u32) -> usize {
+        (x + z * self.size.x + y * self.size.x * self.size.z) as usize
+    }
+
+    pub fn get(&self, x: u32, y: u32, z: u32) -> Voxel {
+        self.voxels[self.index(x, y, z)]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, z: u32, voxel: Voxel) {
+        let index = self.index(x, y, z);
+        self.voxels[index] = voxel;
+    }
+
+    fn positions(&self) -> impl Iterator<Item = (u32, u32, u32)> {
+        let size = self.size;
+        (0..size.y).flat_map(move |y| (0..size.z).flat_map(move |z| (0..size.x).map(move |x| (x, y, z))))
+    }
+
+    /// Rotates clockwise around the Y axis when looking down, in steps of 90 degrees
+    pub fn rotated_y(&self, quarter_turns: u32) -> Self {
+        let mut result = self.clone();
+        for _ in 0..quarter_turns % 4 {
+            let source = result;
+            result = Self::new(UVec3::new(source.size.z, source.size.y, source.size.x));
+            for (x, y, z) in source.positions() {
+                result.set(source.size.z - 1 - z, y, x, source.get(x, y, z));
+            }
+        }
+        result
+    }
+
+    /// Queues writes placing the schematic with its minimum corner at `origin`.
+    /// Empty voxels are skipped unless `include_air` is set. Returns the number of voxels written.
+    pub fn paste(&self, edits: &mut PendingEdits, origin: WorldVoxelPos, include_air: bool) -> usize {
+        let mut written = 0;
+        for (x, y, z) in self.positions() {
+            let voxel = self.get(x, y, z);
+            if voxel.is_empty() && !include_air {
+                continue;
+            }
+            edits.push(origin.offset(x as i64, y as i64, z as i64), voxel);
+            written += 1;
+        }
+        written
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut runs: Vec<(u16, u32)> = Vec::new();
+        for voxel in self.voxels.iter() {
+            let code = voxel.to_code();
+            match runs.last_mut() {
+                Some((run_code, length)) if *run_code == code => *length += 1,
+                _ => runs.push((code, 1)),
+            }
+        }
+
+        let mut bytes = Vec::with_capacity(22 + runs.len() * 6);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        for axis in self.size.to_array() {
+            bytes.extend_from_slice(&axis.to_le_bytes());
+        }
+        bytes.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+        for (code, length) in runs {
+            bytes.extend_from_slice(&code.to_le_bytes());
+            bytes.extend_from_slice(&length.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8], blocks: &BlockRegistry) -> Result<Self, SchematicError> {
+        let mut offset = 0;
+        let mut take = |len: usize| -> Result<&[u8], SchematicError> {
+            let slice = bytes.get(offset..offset + len).ok_or(SchematicError::UnexpectedEof)?;
+            offset += len;
+            Ok(slice)
+        };
+
+        if take(4)? != MAGIC {
+            return Err(SchematicError::BadMagic);
+        }
+        let version = u16::from_le_bytes(take(2)?.try_into().unwrap());
+        if version != FORMAT_VERSION && version != 1 {
+            return Err(SchematicError::UnsupportedVersion(version));
+        }
+        let mut size = [0; 3];
+        for axis in size.iter_mut() {
+            *axis = u32::from_le_bytes(take(4)?.try_into().unwrap());
+        }
+        let volume = size
+            .iter()
+            .try_fold(1u64, |volume, axis| volume.checked_mul(*axis as u64))
+            .filter(|volume| *volume <= MAX_VOLUME)
+            .ok_or(SchematicError::TooLarge(size))? as usize;
+
+        let run_count = u32::from_le_bytes(take(4)?.try_into().unwrap());
+        let mut voxels = Vec::with_capacity(volume);
+        for _ in 0..run_count {
+            let mut code = u16::from_le_bytes(take(2)?.try_into().unwrap());
+            if version == 1 {
+                code = code_before_water(code);
+            }
+            let length = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+            let voxel = Voxel::from_code(code, blocks).ok_or(SchematicError::UnknownVoxel(code))?;
+            if voxels.len() + length > volume {
+                return Err(SchematicError::WrongVoxelCount(voxels.len() + length));
+            }
+            voxels.extend(std::iter::repeat(voxel).take(length));
+        }
+        if voxels.len() != volume {
+            return Err(SchematicError::WrongVoxelCount(voxels.len()));
+        }
+
+        Ok(Self { size: UVec3::from_array(size), voxels })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_bytes())
+    }
+
+    pub fn load(path: &Path, blocks: &BlockRegistry) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?, blocks).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::voxel::Block;
+
+    /// 3×1×2 schematic with a different voxel in every position
+    fn sample() -> Schematic {
+        Schematic::copy(WorldVoxelPos::new(-1, 4, 0), WorldVoxelPos::new(1, 4, 1), |pos| {
+            Voxel::from_code(((pos.x + 1) + pos.z * 3) as u16, &BlockRegistry::builtin()).unwrap()
+        })
+    }
+
+    #[test]
+    fn test_rotation() {
+        let schematic = sample();
+        let rotated = schematic.rotated_y(1);
+        assert_eq!(rotated.size(), UVec3::new(2, 1, 3));
+        // The x axis turns into the z axis, z into -x
+        assert_eq!(rotated.get(1, 0, 0), schematic.get(0, 0, 0));
+        assert_eq!(rotated.get(0, 0, 2), schematic.get(2, 0, 1));
+        assert_eq!(schematic.rotated_y(4), schematic);
+        assert_eq!(rotated.rotated_y(3), schematic);
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let schematic = sample();
+        assert_eq!(Schematic::from_bytes(&schematic.to_bytes(), &BlockRegistry::builtin()).unwrap(), schematic);
+
+        let bytes = Schematic::new(UVec3::new(2, 2, 2)).to_bytes();
+        assert_eq!(Schematic::from_bytes(&bytes[..bytes.len() - 1], &BlockRegistry::builtin()), Err(SchematicError::UnexpectedEof));
+
+        // A damaged header must not allocate the size it claims
+        let mut bytes = Schematic::new(UVec3::new(2, 2, 2)).to_bytes();
+        bytes[6..18].copy_from_slice(&[0xff; 12]);
+        assert_eq!(Schematic::from_bytes(&bytes, &BlockRegistry::builtin()), Err(SchematicError::TooLarge([u32::MAX; 3])));
+    }
+
+    #[test]
+    fn test_paste_skips_air() {
+        let mut schematic = Schematic::new(UVec3::new(2, 2, 2));
+        schematic.set(1, 1, 1, Voxel::from(Block::Glass));
+
+        let mut edits = PendingEdits::default();
+        assert_eq!(schematic.paste(&mut edits, WorldVoxelPos::new(-1, -1, -1), false), 1);
+        assert!(edits.contains(&WorldVoxelPos::new(0, 0, 0).chunk()));
+        assert_eq!(schematic.paste(&mut edits, WorldVoxelPos::new(-1, -1, -1), true), 8);
+    }
+}
@@ -0,0 +1,531 @@
+//! Compact binary chunk format shared by persistence and (future) network replication.
+//!
+//! Layout (all integers little endian):
+//! ```text
+//! magic          4 bytes  "VXCH"
+//! version        u16
+//! position       3 × i32
+//! palette_len    u16
+//! palette        palette_len × u16 voxel codes
+//! metadata_len   u16
+//! metadata       metadata_len × (u16 voxel index, u8 length, length bytes), by increasing index
+//! tint_colors    u8
+//! tint_palette   tint_colors × 3 bytes sRGB
+//! tint_runs      only if tint_colors > 0: u16 run count, runs × (u8 palette index + 1, u16 run length)
+//! run_count      u32
+//! index_bits     u8
+//! runs           run_count × (index_bits palette index + LENGTH_BITS run length - 1), bit packed
+//! checksum       u32 CRC-32 of everything between the version and the checksum
+//! ```
+//! Runs go over the voxels in buffer order (see [`Chunk::linearize_position`]). The checksum
+//! leaves out the header, so migrations only have to update it when they change the body.
+
+use super::{block_registry::BlockRegistry, chunk::{Chunk, ChunkPosition, CHUNK_SIZE}, coords::LocalVoxelPos, tint::ChunkTints, voxel::{Voxel, VoxelMetadata}};
+
+pub const MAGIC: &[u8; 4] = b"VXCH";
+/// Bump when the layout changes and register a migration, see [`MigrationRegistry`](super::migration::MigrationRegistry)
+pub const FORMAT_VERSION: u16 = 5;
+/// Where the palette starts, right after the header and position
+pub(crate) const PALETTE_OFFSET: usize = 4 + 2 + 3 * 4 + 2;
+/// The checksum covers the bytes from here to the trailing checksum
+const CHECKSUM_START: usize = 4 + 2;
+pub(crate) const CHECKSUM_LEN: usize = 4;
+
+const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+/// Bits needed to store `run length - 1`, a single run can cover the whole chunk
+const LENGTH_BITS: u32 = bits_needed(CHUNK_VOLUME as u32 - 1);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    UnexpectedEof,
+    BadMagic,
+    UnsupportedVersion(u16),
+    UnknownVoxel(u16),
+    InvalidPaletteIndex(u32),
+    /// Metadata for a voxel index outside of the chunk
+    InvalidMetadataIndex(u16),
+    /// Metadata for a voxel that can not be read back, see [`ChunkPatch`](super::chunk_patch::ChunkPatch)
+    InvalidMetadata(u16),
+    /// A patch changes a voxel outside of the chunk, see [`ChunkPatch`](super::chunk_patch::ChunkPatch)
+    InvalidVoxelIndex(u16),
+    /// Tint runs don't add up to one chunk or point outside the tint palette
+    InvalidTints,
+    /// Runs don't add up to exactly one chunk of voxels
+    WrongVoxelCount(usize),
+    /// The data was damaged after it was written
+    ChecksumMismatch { stored: u32, computed: u32 },
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnexpectedEof => write!(f, "unexpected end of chunk data"),
+            Self::BadMagic => write!(f, "not chunk data (bad magic)"),
+            Self::UnsupportedVersion(version) => write!(f, "unsupported chunk format version {}", version),
+            Self::UnknownVoxel(code) => write!(f, "unknown voxel code {}", code),
+            Self::InvalidPaletteIndex(index) => write!(f, "palette index {} out of range", index),
+            Self::InvalidMetadataIndex(index) => write!(f, "metadata for voxel {} out of range", index),
+            Self::InvalidMetadata(index) => write!(f, "invalid metadata for voxel {}", index),
+            Self::InvalidVoxelIndex(index) => write!(f, "voxel {} out of range", index),
+            Self::InvalidTints => write!(f, "invalid voxel tints"),
+            Self::WrongVoxelCount(count) => write!(f, "chunk data contains {} voxels instead of {}", count, CHUNK_VOLUME),
+            Self::ChecksumMismatch { stored, computed } => {
+                write!(f, "chunk data is corrupted (checksum {:08x}, expected {:08x})", computed, stored)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Reads the format version from the header without decoding the chunk
+pub fn read_version(bytes: &[u8]) -> Result<u16, DecodeError> {
+    let mut input = ByteReader { bytes, offset: 0 };
+    if input.take(4)? != MAGIC {
+        return Err(DecodeError::BadMagic);
+    }
+    input.u16()
+}
+
+/// Overwrites the format version in the header, used by migrations
+pub fn set_version(bytes: &mut [u8], version: u16) {
+    bytes[4..6].copy_from_slice(&version.to_le_bytes());
+}
+
+/// CRC-32 (IEEE) lookup table, one entry per byte value
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, byte| CRC_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+/// Appends the checksum of encoded chunk data that does not have one yet
+pub(crate) fn append_checksum(bytes: &mut Vec<u8>) {
+    let checksum = crc32(bytes.get(CHECKSUM_START..).unwrap_or_default());
+    bytes.extend_from_slice(&checksum.to_le_bytes());
+}
+
+/// Splits off the trailing checksum and checks it against the rest of the data
+pub(crate) fn verify_checksum(bytes: &[u8]) -> Result<&[u8], DecodeError> {
+    if bytes.len() < CHECKSUM_START + CHECKSUM_LEN {
+        return Err(DecodeError::UnexpectedEof);
+    }
+    let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+    let stored = u32::from_le_bytes(checksum.try_into().unwrap());
+    let computed = crc32(&body[CHECKSUM_START..]);
+    match stored == computed {
+        true => Ok(body),
+        false => Err(DecodeError::ChecksumMismatch { stored, computed }),
+    }
+}
+
+const fn bits_needed(max_value: u32) -> u32 {
+    u32::BITS - max_value.leading_zeros()
+}
+
+pub fn encode(chunk: &Chunk) -> Vec<u8> {
+    let reader = chunk.reader();
+
+    // Build palette and runs in a single pass
+    let mut palette: Vec<Voxel> = Vec::new();
+    let mut runs: Vec<(u32, u32)> = Vec::new();
+    for pos in LocalVoxelPos::iter() {
+        let voxel = *reader.get(pos.x as usize, pos.y as usize, pos.z as usize);
+        let index = match palette.iter().position(|v| *v == voxel) {
+            Some(index) => index,
+            None => {
+                palette.push(voxel);
+                palette.len() - 1
+            }
+        } as u32;
+
+        match runs.last_mut() {
+            Some((run_index, length)) if *run_index == index => *length += 1,
+            _ => runs.push((index, 1)),
+        }
+    }
+    // Sorted so the same chunk always encodes to the same bytes
+    let mut metadata = reader.all_metadata().iter().map(|(index, metadata)| (*index, metadata.clone())).collect::<Vec<_>>();
+    metadata.sort_unstable_by_key(|(index, _)| *index);
+    let mut tints = reader.tints().clone();
+    drop(reader);
+    tints.compact();
+
+    let index_bits = bits_needed(palette.len() as u32 - 1);
+
+    let mut bytes = Vec::with_capacity(32 + palette.len() * 2 + runs.len() * 3);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&chunk.position.x.to_le_bytes());
+    bytes.extend_from_slice(&chunk.position.y.to_le_bytes());
+    bytes.extend_from_slice(&chunk.position.z.to_le_bytes());
+    bytes.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+    for voxel in palette.iter() {
+        bytes.extend_from_slice(&voxel.to_code().to_le_bytes());
+    }
+    bytes.extend_from_slice(&(metadata.len() as u16).to_le_bytes());
+    for (index, metadata) in metadata.iter() {
+        bytes.extend_from_slice(&index.to_le_bytes());
+        bytes.push(metadata.as_bytes().len() as u8);
+        bytes.extend_from_slice(metadata.as_bytes());
+    }
+    bytes.push(tints.palette().len() as u8);
+    for color in tints.palette() {
+        bytes.extend_from_slice(color);
+    }
+    if !tints.is_empty() {
+        let mut tint_runs: Vec<(u8, u16)> = Vec::new();
+        for index in 0..CHUNK_VOLUME {
+            let palette_index = tints.palette_index(index);
+            match tint_runs.last_mut() {
+                Some((run_index, length)) if *run_index == palette_index => *length += 1,
+                _ => tint_runs.push((palette_index, 1)),
+            }
+        }
+        bytes.extend_from_slice(&(tint_runs.len() as u16).to_le_bytes());
+        for (palette_index, length) in tint_runs {
+            bytes.push(palette_index);
+            bytes.extend_from_slice(&length.to_le_bytes());
+        }
+    }
+    bytes.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+    bytes.push(index_bits as u8);
+
+    let mut writer = BitWriter::new(&mut bytes);
+    for (index, length) in runs {
+        writer.write(index, index_bits);
+        writer.write(length - 1, LENGTH_BITS);
+    }
+    writer.finish();
+    append_checksum(&mut bytes);
+
+    bytes
+}
+
+/// Voxels of blocks `blocks` does not know are rejected
+pub fn decode(bytes: &[u8], blocks: &BlockRegistry) -> Result<Chunk, DecodeError> {
+    let mut input = ByteReader { bytes, offset: 0 };
+
+    if input.take(4)? != MAGIC {
+        return Err(DecodeError::BadMagic);
+    }
+    let version = input.u16()?;
+    if version != FORMAT_VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    let mut input = ByteReader { bytes: verify_checksum(bytes)?, offset: input.offset };
+    let position = ChunkPosition::new(input.i32()?, input.i32()?, input.i32()?);
+
+    let palette_len = input.u16()? as usize;
+    let mut palette = Vec::with_capacity(palette_len);
+    for _ in 0..palette_len {
+        let code = input.u16()?;
+        palette.push(Voxel::from_code(code, blocks).ok_or(DecodeError::UnknownVoxel(code))?);
+    }
+
+    let metadata_len = input.u16()? as usize;
+    let mut metadata = Vec::with_capacity(metadata_len);
+    for _ in 0..metadata_len {
+        let index = input.u16()?;
+        if index as usize >= CHUNK_VOLUME {
+            return Err(DecodeError::InvalidMetadataIndex(index));
+        }
+        let length = input.u8()? as usize;
+        // A single length byte can not exceed `VoxelMetadata::MAX_LEN`
+        metadata.push((index as usize, VoxelMetadata::new(input.take(length)?).unwrap()));
+    }
+
+    let tint_colors = input.u8()? as usize;
+    let mut tint_palette = Vec::with_capacity(tint_colors);
+    for _ in 0..tint_colors {
+        let color = input.take(3)?;
+        tint_palette.push([color[0], color[1], color[2]]);
+    }
+    let tints = if tint_colors > 0 {
+        let mut indices = Vec::with_capacity(CHUNK_VOLUME);
+        for _ in 0..input.u16()? {
+            let palette_index = input.u8()?;
+            let length = input.u16()? as usize;
+            if indices.len() + length > CHUNK_VOLUME {
+                return Err(DecodeError::InvalidTints);
+            }
+            indices.extend(std::iter::repeat(palette_index).take(length));
+        }
+        ChunkTints::from_parts(tint_palette, indices.into_boxed_slice()).ok_or(DecodeError::InvalidTints)?
+    } else {
+        ChunkTints::default()
+    };
+
+    let run_count = input.u32()? as usize;
+    let index_bits = input.u8()? as u32;
+    let mut bits = BitReader::new(input.rest());
+
+    let mut chunk = Chunk::new(position);
+    {
+        let mut writer = chunk.writer();
+        let mut offset = 0;
+        for _ in 0..run_count {
+            let index = bits.read(index_bits)?;
+            let length = bits.read(LENGTH_BITS)? as usize + 1;
+            let voxel = *palette.get(index as usize).ok_or(DecodeError::InvalidPaletteIndex(index))?;
+            if offset + length > CHUNK_VOLUME {
+                return Err(DecodeError::WrongVoxelCount(offset + length));
+            }
+            for i in offset..offset + length {
+                let (x, y, z) = Chunk::delinearize_position(i);
+                writer.set(x, y, z, voxel);
+            }
+            offset += length;
+        }
+        if offset != CHUNK_VOLUME {
+            return Err(DecodeError::WrongVoxelCount(offset));
+        }
+        for (index, metadata) in metadata {
+            let (x, y, z) = Chunk::delinearize_position(index);
+            writer.set_metadata(x, y, z, Some(metadata));
+        }
+        writer.set_tints(tints);
+    }
+    chunk.compact();
+    chunk.recalculate_visibility_mask(blocks);
+
+    Ok(chunk)
+}
+
+pub(crate) struct ByteReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let slice = self.bytes.get(self.offset..self.offset + len).ok_or(DecodeError::UnexpectedEof)?;
+        self.offset += len;
+        Ok(slice)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, DecodeError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn i32(&mut self) -> Result<i32, DecodeError> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.bytes[self.offset..]
+    }
+}
+
+/// Packs values least significant bit first
+struct BitWriter<'a> {
+    bytes: &'a mut Vec<u8>,
+    buffer: u64,
+    buffered_bits: u32,
+}
+
+impl<'a> BitWriter<'a> {
+    fn new(bytes: &'a mut Vec<u8>) -> Self {
+        Self { bytes, buffer: 0, buffered_bits: 0 }
+    }
+
+    fn write(&mut self, value: u32, bits: u32) {
+        if bits == 0 {
+            return;
+        }
+        self.buffer |= (value as u64 & ((1 << bits) - 1)) << self.buffered_bits;
+        self.buffered_bits += bits;
+        while self.buffered_bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.buffered_bits -= 8;
+        }
+    }
+
+    fn finish(self) {
+        if self.buffered_bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    buffer: u64,
+    buffered_bits: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0, buffer: 0, buffered_bits: 0 }
+    }
+
+    fn read(&mut self, bits: u32) -> Result<u32, DecodeError> {
+        if bits == 0 {
+            return Ok(0);
+        }
+        while self.buffered_bits < bits {
+            let byte = *self.bytes.get(self.offset).ok_or(DecodeError::UnexpectedEof)?;
+            self.buffer |= (byte as u64) << self.buffered_bits;
+            self.buffered_bits += 8;
+            self.offset += 1;
+        }
+        let value = (self.buffer & ((1 << bits) - 1)) as u32;
+        self.buffer >>= bits;
+        self.buffered_bits -= bits;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::voxel::Block;
+
+    fn chunk_with(position: ChunkPosition, voxel_at: impl Fn(LocalVoxelPos) -> Voxel) -> Chunk {
+        let mut chunk = Chunk::new(position);
+        chunk.generate_with(|_, pos| voxel_at(pos));
+        chunk
+    }
+
+    fn assert_roundtrip(chunk: &Chunk) -> usize {
+        let bytes = encode(chunk);
+        let decoded = decode(&bytes, &BlockRegistry::builtin()).unwrap();
+
+        assert_eq!(decoded.position, chunk.position);
+        assert_eq!(*decoded.reader().voxels(), *chunk.reader().voxels());
+        assert_eq!(*decoded.reader().all_metadata(), *chunk.reader().all_metadata());
+        assert!(LocalVoxelPos::iter().all(|pos| decoded.tint(pos) == chunk.tint(pos)));
+        bytes.len()
+    }
+
+    #[test]
+    fn test_roundtrip_empty() {
+        let size = assert_roundtrip(&Chunk::new(ChunkPosition::new(0, 0, 0)));
+        // Header, one palette entry, no metadata or tints, a single run and the checksum
+        assert!(size <= 36);
+    }
+
+    #[test]
+    fn test_roundtrip_solid_negative_position() {
+        let chunk = chunk_with(ChunkPosition::new(-3, -1, 7), |_| Voxel::from(Block::Stone));
+        assert_roundtrip(&chunk);
+    }
+
+    #[test]
+    fn test_roundtrip_checkerboard() {
+        let chunk = chunk_with(ChunkPosition::new(1, 2, 3), |pos| {
+            if (pos.x + pos.y + pos.z) % 2 == 0 { Voxel::from(Block::Dirt) } else { Voxel::Empty }
+        });
+        assert_roundtrip(&chunk);
+    }
+
+    #[test]
+    fn test_roundtrip_every_block() {
+        let chunk = chunk_with(ChunkPosition::new(0, -5, 0), |pos| {
+            let code = (pos.index() * 7919 % (Block::ALL.len() + 1)) as u16;
+            Voxel::from_code(code, &BlockRegistry::builtin()).unwrap()
+        });
+        assert_roundtrip(&chunk);
+    }
+
+    #[test]
+    fn test_roundtrip_metadata() {
+        let mut chunk = chunk_with(ChunkPosition::new(2, 0, -1), |pos| {
+            if pos.y < 4 { Voxel::from(Block::Stone) } else { Voxel::Empty }
+        });
+        let corner = LocalVoxelPos::new(15, 15, 15);
+        chunk.set_metadata(LocalVoxelPos::new(0, 0, 0), VoxelMetadata::new([3]));
+        chunk.set_metadata(corner, VoxelMetadata::new(vec![7; VoxelMetadata::MAX_LEN]));
+        chunk.set_metadata(LocalVoxelPos::new(4, 2, 9), VoxelMetadata::new([]));
+        assert_roundtrip(&chunk);
+        assert_eq!(decode(&encode(&chunk), &BlockRegistry::builtin()).unwrap().metadata(corner), chunk.metadata(corner));
+
+        // Replacing a voxel drops its metadata, setting the same voxel again keeps it
+        chunk.set(LocalVoxelPos::new(0, 0, 0), Voxel::from(Block::Stone));
+        chunk.set(corner, Voxel::from(Block::Dirt));
+        assert!(chunk.metadata(LocalVoxelPos::new(0, 0, 0)).is_some());
+        assert!(chunk.metadata(corner).is_none());
+        assert_roundtrip(&chunk);
+    }
+
+    #[test]
+    fn test_roundtrip_tints() {
+        let mut chunk = chunk_with(ChunkPosition::new(0, 1, 0), |_| Voxel::from(Block::Grass));
+        for pos in LocalVoxelPos::iter().filter(|pos| pos.y == 15) {
+            chunk.set_tint(pos, Some([pos.x * 16, 200, 40]));
+        }
+        // Colors no voxel uses anymore are not written
+        chunk.set_tint(LocalVoxelPos::new(0, 0, 0), Some([1, 2, 3]));
+        chunk.set_tint(LocalVoxelPos::new(0, 0, 0), None);
+        // Far less than a byte per voxel
+        assert!(assert_roundtrip(&chunk) < 1024);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let bytes = encode(&Chunk::new(ChunkPosition::new(0, 0, 0)));
+
+        assert_eq!(decode(&bytes[..8], &BlockRegistry::builtin()).unwrap_err(), DecodeError::UnexpectedEof);
+        assert_eq!(decode(b"nope", &BlockRegistry::builtin()).unwrap_err(), DecodeError::BadMagic);
+        // A flipped bit anywhere after the header, or a cut off end, is caught by the checksum
+        let mut flipped = bytes.clone();
+        flipped[PALETTE_OFFSET] ^= 0x10;
+        assert!(matches!(decode(&flipped, &BlockRegistry::builtin()), Err(DecodeError::ChecksumMismatch { .. })));
+        assert!(matches!(decode(&bytes[..bytes.len() - 1], &BlockRegistry::builtin()), Err(DecodeError::ChecksumMismatch { .. })));
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+
+        let mut future = bytes.clone();
+        future[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert_eq!(decode(&future, &BlockRegistry::builtin()).unwrap_err(), DecodeError::UnsupportedVersion(FORMAT_VERSION + 1));
+
+        let mut chunk = Chunk::new(ChunkPosition::new(0, 0, 0));
+        chunk.set_metadata(LocalVoxelPos::new(0, 0, 0), VoxelMetadata::new([1]));
+        let mut bad_index = encode(&chunk);
+        let index_offset = PALETTE_OFFSET + 2 + 2;
+        bad_index[index_offset..index_offset + 2].copy_from_slice(&(CHUNK_VOLUME as u16).to_le_bytes());
+        bad_index.truncate(bad_index.len() - CHECKSUM_LEN);
+        append_checksum(&mut bad_index);
+        assert_eq!(decode(&bad_index, &BlockRegistry::builtin()).unwrap_err(), DecodeError::InvalidMetadataIndex(CHUNK_VOLUME as u16));
+
+        // Tint runs claiming more voxels than a chunk has are rejected before they are read
+        let mut chunk = chunk_with(ChunkPosition::new(0, 0, 0), |_| Voxel::from(Block::Glass));
+        for pos in LocalVoxelPos::iter() {
+            chunk.set_tint(pos, Some([9, 9, 9]));
+        }
+        let mut long_tints = encode(&chunk);
+        let runs_offset = PALETTE_OFFSET + 2 + 2 + 1 + 3;
+        long_tints[runs_offset..runs_offset + 2].copy_from_slice(&u16::MAX.to_le_bytes());
+        long_tints[runs_offset + 3..runs_offset + 5].copy_from_slice(&u16::MAX.to_le_bytes());
+        long_tints.truncate(long_tints.len() - CHECKSUM_LEN);
+        append_checksum(&mut long_tints);
+        assert_eq!(decode(&long_tints, &BlockRegistry::builtin()).unwrap_err(), DecodeError::InvalidTints);
+    }
+}
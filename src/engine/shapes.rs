@@ -0,0 +1,195 @@
+//! Voxel shapes other than full cubes, like slabs, stairs and fences.
+//!
+//! A block with `shape: Some("slab")` in its [`BlockDefinition`](super::block_registry::BlockDefinition)
+//! is left out of the greedy mesher, which only merges full cubes, and its quads are appended by a
+//! second pass instead. Shaped blocks never count as opaque, the faces of their neighbours are
+//! always meshed. Shapes are turned around the vertical axis by a quarter turn per unit of the
+//! first byte of the voxel metadata.
+//!
+//! Game code registers its own shapes by name in the [`ShapeRegistry`] resource:
+//! ```ignore
+//! let mut shapes = world.resource_mut::<ShapeRegistry>();
+//! shapes.register("post", VoxelShape::from_boxes(&[(Vec3::new(0.4, 0.0, 0.4), Vec3::new(0.6, 1.0, 0.6))]));
+//! ```
+//! Blocks naming a shape that is not registered are meshed as cubes.
+
+use std::sync::{Arc, OnceLock};
+
+use bevy::{prelude::*, utils::HashMap};
+
+use super::util::Face;
+
+const FACES: [Face; 6] = [Face::Left, Face::Right, Face::Bottom, Face::Top, Face::Back, Face::Front];
+
+/// One quad of a shape, corners counter-clockwise seen from the front, in voxel units from the voxel corner
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShapeQuad {
+    pub positions: [Vec3; 4],
+    pub normal: Vec3,
+    /// The quad lies on this face of the voxel and is hidden by an opaque neighbour there
+    pub cull: Option<Face>,
+}
+
+impl ShapeQuad {
+    pub const INDICES: [u32; 6] = [0, 1, 2, 0, 2, 3];
+
+    /// Quad of one face of the box from `min` to `max`
+    pub fn box_face(min: Vec3, max: Vec3, face: Face) -> Self {
+        // Tangent axes with u × v pointing along the face normal
+        let (normal_axis, u, v, at_max) = match face {
+            Face::Left => (0, 2, 1, false),
+            Face::Right => (0, 1, 2, true),
+            Face::Bottom => (1, 0, 2, false),
+            Face::Top => (1, 2, 0, true),
+            Face::Back => (2, 1, 0, false),
+            Face::Front => (2, 0, 1, true),
+        };
+        let plane = if at_max { max[normal_axis] } else { min[normal_axis] };
+        let corner = |u_value: f32, v_value: f32| {
+            let mut corner = Vec3::ZERO;
+            corner[normal_axis] = plane;
+            corner[u] = u_value;
+            corner[v] = v_value;
+            corner
+        };
+        let on_boundary = if at_max { plane >= 1.0 } else { plane <= 0.0 };
+        Self {
+            positions: [corner(min[u], min[v]), corner(max[u], min[v]), corner(max[u], max[v]), corner(min[u], max[v])],
+            normal: face.normal(),
+            cull: on_boundary.then_some(face),
+        }
+    }
+
+    /// Turned around the vertical center line of the voxel by `quarter_turns` × 90°, counter-clockwise seen from above
+    pub fn rotated(&self, quarter_turns: u8) -> Self {
+        // Swapping coordinates keeps the corners exact, they must not leave the chunk by a rounding error
+        let turn = |vector: Vec3| (0..quarter_turns % 4).fold(vector, |vector, _| Vec3::new(vector.z, vector.y, -vector.x));
+        let center = Vec3::new(0.5, 0.0, 0.5);
+        let normal = turn(self.normal);
+        Self {
+            positions: self.positions.map(|position| center + turn(position - center)),
+            normal,
+            cull: self.cull.and_then(|_| FACES.into_iter().find(|face| face.normal() == normal)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VoxelShape {
+    pub quads: Vec<ShapeQuad>,
+}
+
+impl VoxelShape {
+    /// Every face of every box, boxes go from `min` to `max` inside the unit voxel
+    pub fn from_boxes(boxes: &[(Vec3, Vec3)]) -> Self {
+        let quads = boxes.iter().flat_map(|(min, max)| FACES.map(|face| ShapeQuad::box_face(*min, *max, face))).collect();
+        Self { quads }
+    }
+
+    pub fn slab() -> Self {
+        Self::from_boxes(&[(Vec3::ZERO, Vec3::new(1.0, 0.5, 1.0))])
+    }
+
+    /// Rises towards the back of the voxel
+    pub fn stair() -> Self {
+        Self::from_boxes(&[(Vec3::ZERO, Vec3::new(1.0, 0.5, 1.0)), (Vec3::new(0.0, 0.5, 0.0), Vec3::new(1.0, 1.0, 0.5))])
+    }
+
+    /// A single post, it does not connect to its neighbours
+    pub fn fence() -> Self {
+        Self::from_boxes(&[(Vec3::new(0.375, 0.0, 0.375), Vec3::new(0.625, 1.0, 0.625))])
+    }
+}
+
+/// Shapes blocks can name, chunks meshed from now on use the shapes in the resource. Cheap to clone
+#[derive(Resource, Debug, Clone)]
+pub struct ShapeRegistry {
+    shapes: Arc<HashMap<String, Arc<VoxelShape>>>,
+}
+
+impl Default for ShapeRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl ShapeRegistry {
+    /// `slab`, `stair` and `fence`
+    pub fn builtin() -> Self {
+        static BUILTIN: OnceLock<ShapeRegistry> = OnceLock::new();
+        BUILTIN
+            .get_or_init(|| {
+                let mut shapes = Self { shapes: Arc::default() };
+                shapes.register("slab", VoxelShape::slab());
+                shapes.register("stair", VoxelShape::stair());
+                shapes.register("fence", VoxelShape::fence());
+                shapes
+            })
+            .clone()
+    }
+
+    /// Adds or replaces a shape
+    pub fn register(&mut self, name: impl Into<String>, shape: VoxelShape) {
+        Arc::make_mut(&mut self.shapes).insert(name.into().to_lowercase(), Arc::new(shape));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<VoxelShape>> {
+        self.shapes.get(&name.trim().to_lowercase()).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{
+        block_registry::BlockRegistry,
+        chunk::{Chunk, ChunkPosition},
+        coords::LocalVoxelPos,
+        meshing::ChunkMesher,
+        voxel::{Block, Voxel, VoxelMetadata},
+    };
+
+    fn quad_count(mesh: &Mesh) -> usize {
+        mesh.indices().unwrap().len() / 6
+    }
+
+    #[test]
+    fn test_box_faces_point_outwards() {
+        for face in FACES {
+            let quad = ShapeQuad::box_face(Vec3::ZERO, Vec3::ONE, face);
+            let [a, b, c, _] = quad.positions;
+            assert_eq!((b - a).cross(c - a).normalize(), face.normal(), "{:?} winds the wrong way", face);
+            assert_eq!(quad.cull, Some(face));
+        }
+        // The top of a slab is inside the voxel, nothing hides it
+        assert_eq!(ShapeQuad::box_face(Vec3::ZERO, Vec3::new(1.0, 0.5, 1.0), Face::Top).cull, None);
+
+        let turned = ShapeQuad::box_face(Vec3::ZERO, Vec3::ONE, Face::Right).rotated(1);
+        assert_eq!(turned.cull, Some(Face::Back));
+        assert!(turned.positions.iter().all(|position| position.z == 0.0));
+    }
+
+    #[test]
+    fn test_shaped_blocks_are_meshed_in_a_second_pass() {
+        let blocks = BlockRegistry::from_ron(r#"[(name: "stone_slab", shape: Some("slab")), (name: "stone_stair", shape: Some("stair"))]"#).unwrap();
+        let mesher = ChunkMesher { blocks: blocks.clone(), ..ChunkMesher::default() };
+        let slab = Voxel::from(blocks.id("stone_slab").unwrap());
+        assert!(!blocks.is_opaque(slab));
+
+        let mut chunk = Chunk::new(ChunkPosition::new(0, 0, 0));
+        chunk.set(LocalVoxelPos::new(4, 4, 4), slab);
+        assert_eq!(quad_count(&chunk.build(&mesher).unwrap()), 6);
+
+        // The stone below hides the bottom of the slab, the slab does not hide the top of the stone
+        chunk.set(LocalVoxelPos::new(4, 3, 4), Voxel::from(Block::Stone));
+        assert_eq!(quad_count(&chunk.build(&mesher).unwrap()), 5 + 6);
+
+        // The stone hides both back faces of the stair, turned around it only hides the lower front one
+        let mut chunk = Chunk::new(ChunkPosition::new(0, 0, 0));
+        chunk.set(LocalVoxelPos::new(4, 4, 4), Voxel::from(blocks.id("stone_stair").unwrap()));
+        chunk.set(LocalVoxelPos::new(4, 4, 3), Voxel::from(Block::Stone));
+        let facing_stone = quad_count(&chunk.build(&mesher).unwrap());
+        chunk.set_metadata(LocalVoxelPos::new(4, 4, 4), VoxelMetadata::new([2]));
+        assert_eq!(quad_count(&chunk.build(&mesher).unwrap()), facing_stone + 1);
+    }
+}
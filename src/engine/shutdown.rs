@@ -0,0 +1,145 @@
+//! Shutdown sequence: stops generation, cancels in-flight tasks, flushes chunks
+//! through the persistence backend and writes world metadata and pending edits before the app exits.
+//! Closing the window and sending [`AppExit`] both start it.
+//!
+//! Add `WindowPlugin { close_when_requested: false, .. }` so closing the window waits for the
+//! flush, the window is closed by [`close_windows_after_shutdown`] instead.
+
+use bevy::{app::AppExit, prelude::*, window::WindowCloseRequested};
+
+use super::{
+    autosave::DirtyChunks,
+    cache::ChunkCache,
+    chunk::Chunk,
+    generator::{ChunkGenerationTask, ChunkSource, GeneratorState, MeshingTask, WorldGeneratorConfig},
+    persistence::ChunkStorage,
+    world_meta::WorldMetadata,
+    ChunkData,
+};
+use crate::flycam::PlayerCamera;
+
+#[derive(Resource, Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum ShutdownState {
+    #[default]
+    Running,
+    Requested,
+    Done,
+}
+
+pub struct ShutdownPlugin;
+
+impl Plugin for ShutdownPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ShutdownState>()
+            .add_systems(PostUpdate, detect_shutdown_request)
+            .add_systems(Last, (
+                shutdown.run_if(resource_equals(ShutdownState::Requested)),
+                close_windows_after_shutdown.after(shutdown),
+            ));
+    }
+}
+
+fn detect_shutdown_request(
+    mut state: ResMut<ShutdownState>,
+    mut close_requests: EventReader<WindowCloseRequested>,
+    mut exits: EventReader<AppExit>,
+) {
+    let requested = close_requests.read().count() > 0;
+    let exiting = exits.read().count() > 0;
+    if (requested || exiting) && *state == ShutdownState::Running {
+        *state = ShutdownState::Requested;
+    }
+}
+
+/// Blocks until every chunk is written, runs once
+pub fn shutdown(world: &mut World) {
+    info!("Shutting down, saving world");
+    save_world(world);
+    world.resource_mut::<ChunkStorage>().shutdown();
+    *world.resource_mut::<ShutdownState>() = ShutdownState::Done;
+    info!("World saved");
+}
+
+/// Pauses generation, cancels in-flight tasks and hands every chunk and the world metadata
+/// over to the persistence backend. Chunks are written once [`ChunkStorage`] is shut down or dropped.
+pub fn save_world(world: &mut World) {
+    world.insert_resource(GeneratorState::Paused);
+
+    // Dropping a task cancels it
+    let busy = world
+        .query_filtered::<Entity, Or<(With<ChunkGenerationTask>, With<MeshingTask>)>>()
+        .iter(world)
+        .collect::<Vec<_>>();
+    for entity in busy {
+        world.entity_mut(entity).remove::<(ChunkGenerationTask, MeshingTask)>();
+    }
+    write_world(world);
+}
+
+/// Hands every loaded and cached chunk that is dirty or was never saved and the world metadata
+/// over to the persistence backend while the game keeps running. Chunks that are still
+/// generating are saved by the next save.
+pub fn write_world(world: &mut World) {
+    let dirty = std::mem::take(&mut *world.resource_mut::<DirtyChunks>());
+    // Chunks received from a server are not ours to save
+    if *world.resource::<ChunkSource>() == ChunkSource::Local {
+        let mut loaded = world.query::<&Chunk>();
+        let storage = world.resource::<ChunkStorage>();
+        let mut chunks = loaded
+            .iter(world)
+            .filter(|chunk| dirty.needs_save(&chunk.position, storage))
+            .cloned()
+            .collect::<Vec<_>>();
+        let cached = world.resource_mut::<ChunkCache>().drain();
+        let storage = world.resource::<ChunkStorage>();
+        chunks.extend(cached.into_iter().filter(|chunk| dirty.needs_save(&chunk.position, storage)));
+        let mut storage = world.resource_mut::<ChunkStorage>();
+        for chunk in chunks {
+            storage.save(chunk);
+        }
+    }
+    write_world_metadata(world);
+    write_pending_edits(world);
+}
+
+/// Saves the edits waiting for chunks that were not generated yet, see [`PendingEdits`](super::pending_edits::PendingEdits)
+pub fn write_pending_edits(world: &mut World) {
+    // Edits of a server world are the server's to keep
+    if *world.resource::<ChunkSource>() == ChunkSource::Remote {
+        return;
+    }
+    let root = world.resource::<ChunkStorage>().root();
+    if let Err(err) = world.resource::<ChunkData>().pending_edits.save(root) {
+        error!("Failed to save pending edits: {}", err);
+    }
+}
+
+/// Saves the world metadata with the current camera position
+pub fn write_world_metadata(world: &mut World) {
+    let camera_position = world
+        .query_filtered::<&Transform, With<PlayerCamera>>()
+        .iter(world)
+        .next()
+        .map_or(Vec3::ZERO, |transform| transform.translation);
+    let root = world.resource::<ChunkStorage>().root().to_path_buf();
+    let config = world.resource::<WorldGeneratorConfig>().clone();
+    let mut metadata = world.resource_mut::<WorldMetadata>();
+    metadata.touch(camera_position, &config);
+    if let Err(err) = metadata.save(&root) {
+        error!("Failed to save world metadata: {}", err);
+    }
+}
+
+/// Closes windows whose close was requested once the world is saved
+fn close_windows_after_shutdown(
+    mut commands: Commands,
+    state: Res<ShutdownState>,
+    windows: Query<Entity, With<Window>>,
+) {
+    if *state != ShutdownState::Done {
+        return;
+    }
+    for window in windows.iter() {
+        commands.entity(window).despawn();
+    }
+}
@@ -0,0 +1,331 @@
+//! Spreads spawning and despawning of chunk entities over several frames.
+//!
+//! Flying into unexplored terrain, teleporting or turning around can queue hundreds of chunks in a single
+//! frame and applying that many spawn or despawn commands at once shows up as a spike. Chunks are queued
+//! here instead and handed to the world in batches, each batch as a single command.
+//!
+//! The entities of a batch are reserved and recorded in [`ChunkData::awaiting_generation`] right away,
+//! before the command spawning them is applied. Until then the chunk would otherwise be neither queued
+//! nor awaiting generation, and a visibility search in between would spawn it a second time.
+
+use std::collections::VecDeque;
+
+use bevy::{
+    ecs::{entity::Entities, world::World},
+    hierarchy::despawn_with_children_recursive,
+    prelude::*,
+    utils::HashSet,
+};
+
+use super::{chunk::ChunkPosition, generator::{AwaitingGeneration, Generating}, streaming_budget::StreamingBudget, ChunkData};
+
+#[derive(Resource, Debug)]
+pub struct ChunkSpawnQueue {
+    pub max_spawns_per_frame: usize,
+    pub max_despawns_per_frame: usize,
+    /// Closest chunks first, in the order they were queued
+    spawns: VecDeque<ChunkPosition>,
+    queued: HashSet<ChunkPosition>,
+    despawns: VecDeque<Entity>,
+    /// Entities that are no longer part of [`ChunkData`] but still exist
+    despawning: HashSet<Entity>,
+}
+
+impl Default for ChunkSpawnQueue {
+    fn default() -> Self {
+        Self {
+            max_spawns_per_frame: 64,
+            max_despawns_per_frame: 64,
+            spawns: VecDeque::new(),
+            queued: HashSet::default(),
+            despawns: VecDeque::new(),
+            despawning: HashSet::default(),
+        }
+    }
+}
+
+impl ChunkSpawnQueue {
+    /// Queues an entity awaiting generation for the chunk. Returns false if it is already queued.
+    pub fn queue_spawn(&mut self, chunk: ChunkPosition) -> bool {
+        if !self.queued.insert(chunk) {
+            return false;
+        }
+        self.spawns.push_back(chunk);
+        true
+    }
+
+    pub fn is_queued(&self, chunk: &ChunkPosition) -> bool {
+        self.queued.contains(chunk)
+    }
+
+    /// Queues a chunk entity for despawning, the chunk should already be forgotten by [`ChunkData`]
+    pub fn queue_despawn(&mut self, entity: Entity) -> bool {
+        if !self.despawning.insert(entity) {
+            return false;
+        }
+        self.despawns.push_back(entity);
+        true
+    }
+
+    /// Whether the entity is waiting to be despawned, systems should leave it alone
+    pub fn is_despawning(&self, entity: Entity) -> bool {
+        self.despawning.contains(&entity)
+    }
+
+    pub fn pending_spawns(&self) -> usize {
+        self.spawns.len()
+    }
+
+    pub fn pending_despawns(&self) -> usize {
+        self.despawns.len()
+    }
+
+    /// Forgets every queued spawn and despawn, for when all chunk entities are despawned at once
+    pub fn clear(&mut self) {
+        self.spawns.clear();
+        self.queued.clear();
+        self.despawns.clear();
+        self.despawning.clear();
+    }
+
+    /// Takes up to `limit` chunks, dropping the ones `wanted` rejects on the way
+    pub fn next_spawn_batch(&mut self, limit: usize, wanted: impl Fn(&ChunkPosition) -> bool) -> Vec<ChunkPosition> {
+        let mut batch = Vec::new();
+        while batch.len() < limit {
+            let Some(chunk) = self.spawns.pop_front() else {
+                break;
+            };
+            self.queued.remove(&chunk);
+            if wanted(&chunk) {
+                batch.push(chunk);
+            }
+        }
+        batch
+    }
+
+    pub fn next_despawn_batch(&mut self, limit: usize) -> Vec<Entity> {
+        let count = limit.min(self.despawns.len());
+        let batch = self.despawns.drain(..count).collect::<Vec<_>>();
+        for entity in batch.iter() {
+            self.despawning.remove(entity);
+        }
+        batch
+    }
+}
+
+/// Spawns the next batch of queued chunks that are still wanted, scaled by the [`StreamingBudget`]
+pub fn flush_chunk_spawns(
+    mut commands: Commands,
+    mut queue: ResMut<ChunkSpawnQueue>,
+    mut chunk_data: ResMut<ChunkData>,
+    budget: Res<StreamingBudget>,
+    entities: &Entities,
+) {
+    let limit = budget.limit(queue.max_spawns_per_frame);
+    // Something else may have taken care of a chunk since it was queued, e.g. the critical ring
+    let batch = queue.next_spawn_batch(limit, |chunk| {
+        (chunk_data.visible.contains(chunk) || chunk_data.is_pinned(chunk))
+            && !chunk_data.loaded.contains_key(chunk)
+            && !chunk_data.awaiting_generation.contains_key(chunk)
+    });
+    if batch.is_empty() {
+        return;
+    }
+    let reserved = entities.reserve_entities(batch.len() as u32);
+    let batch = batch.into_iter().zip(reserved).collect::<Vec<_>>();
+    chunk_data.awaiting_generation.extend(batch.iter().copied());
+    commands.add(move |world: &mut World| spawn_chunk_batch(world, batch));
+}
+
+/// Despawns the next batch of chunk entities the garbage collector let go of
+pub fn flush_chunk_despawns(mut commands: Commands, mut queue: ResMut<ChunkSpawnQueue>, budget: Res<StreamingBudget>) {
+    let limit = budget.limit(queue.max_despawns_per_frame);
+    let batch = queue.next_despawn_batch(limit);
+    if !batch.is_empty() {
+        commands.add(move |world: &mut World| {
+            for entity in batch {
+                despawn_with_children_recursive(world, entity);
+            }
+        });
+    }
+}
+
+/// Gives the reserved entities of a batch their components. A reserved entity that was taken over
+/// keeps what it has, one that was dropped with its chunk (e.g. all chunks were unloaded) is despawned
+/// unless the garbage collector already queued it for despawning.
+fn spawn_chunk_batch(world: &mut World, batch: Vec<(ChunkPosition, Entity)>) {
+    let chunk_data = world.resource::<ChunkData>();
+    let queue = world.resource::<ChunkSpawnQueue>();
+    let (awaiting, dropped): (Vec<_>, Vec<_>) =
+        batch.into_iter().partition(|(chunk, entity)| chunk_data.awaiting_generation.get(chunk) == Some(entity));
+    let dropped = dropped
+        .into_iter()
+        .filter(|(chunk, entity)| chunk_data.loaded.get(chunk) != Some(entity) && !queue.is_despawning(*entity))
+        .map(|(_, entity)| entity)
+        .collect::<Vec<_>>();
+
+    let bundles = awaiting.into_iter().map(|(chunk_pos, entity)| (entity, (AwaitingGeneration { chunk_pos }, Generating)));
+    if let Err(invalid) = world.insert_or_spawn_batch(bundles) {
+        error!("Reserved chunk entities {:?} no longer exist", invalid);
+    }
+    for entity in dropped {
+        world.despawn(entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        core::FrameCount,
+        ecs::system::{RunSystemOnce, System},
+        render::{camera::CameraProjection, primitives::Frustum},
+    };
+
+    use super::*;
+    use crate::engine::{
+        anchor::StreamingAnchor,
+        autosave::DirtyChunks,
+        cache::ChunkCache,
+        chunk::Chunk,
+        chunk_index::ChunkIndex,
+        chunk_log::ChunkLogLevel,
+        generator::{
+            garbage_collect_chunks, update_visible_chunks, ChunkSource, FlatWorldGenerator, GeneratorState, VisibilityRefresh,
+            WorldGeneratorConfig,
+        },
+        memory_budget::MemoryBudget,
+        persistence::ChunkStorage,
+    };
+    use crate::temp_dir::TempDir;
+
+    fn spawn_world(chunk_data: ChunkData, queue: ChunkSpawnQueue) -> (World, impl System<In = (), Out = ()>) {
+        let mut world = World::new();
+        world.insert_resource(chunk_data);
+        world.insert_resource(queue);
+        world.init_resource::<StreamingBudget>();
+        world.init_resource::<FrameCount>();
+        world.init_resource::<Time>();
+        world.insert_resource(WorldGeneratorConfig::default_with(FlatWorldGenerator::default()));
+        world.insert_resource(GeneratorState::Generating);
+        let mut flush = IntoSystem::into_system(flush_chunk_spawns);
+        flush.initialize(&mut world);
+        (world, flush)
+    }
+
+    #[test]
+    fn test_spawns_are_batched() {
+        let mut chunk_data = ChunkData::default();
+        let chunks = (0..10).map(|x| ChunkPosition::new(x, 0, 0)).collect::<Vec<_>>();
+        chunk_data.visible.extend(chunks.iter().take(9));
+        chunk_data.loaded.insert(chunks[1], Entity::from_raw(100));
+
+        let mut queue = ChunkSpawnQueue { max_spawns_per_frame: 4, ..Default::default() };
+        for chunk in chunks.iter() {
+            assert!(queue.queue_spawn(*chunk));
+        }
+        assert!(!queue.queue_spawn(chunks[0]));
+
+        let (mut world, mut flush) = spawn_world(chunk_data, queue);
+        let mut frames = 0;
+        while world.resource::<ChunkSpawnQueue>().pending_spawns() > 0 {
+            let before = world.resource::<ChunkData>().awaiting_generation.len();
+            flush.run((), &mut world);
+            flush.apply_deferred(&mut world);
+            assert!(world.resource::<ChunkData>().awaiting_generation.len() - before <= 4);
+            frames += 1;
+        }
+        assert_eq!(frames, 3);
+
+        // The loaded chunk and the one that is no longer visible are skipped
+        let chunk_data = world.resource::<ChunkData>();
+        assert_eq!(chunk_data.awaiting_generation.len(), 8);
+        assert!(!chunk_data.awaiting_generation.contains_key(&chunks[1]));
+        assert!(!chunk_data.awaiting_generation.contains_key(&chunks[9]));
+        let entity = chunk_data.awaiting_generation[&chunks[0]];
+        assert_eq!(world.get::<AwaitingGeneration>(entity).map(|awaiting| awaiting.chunk_pos), Some(chunks[0]));
+        assert!(!world.resource::<ChunkSpawnQueue>().is_queued(&chunks[0]));
+        assert_eq!(world.entities().len(), 8);
+    }
+
+    #[test]
+    fn test_chunk_is_spawned_once_before_commands_apply() {
+        let (mut world, mut flush) = spawn_world(ChunkData::default(), ChunkSpawnQueue::default());
+        world.init_resource::<VisibilityRefresh>();
+        let transform = Transform::from_xyz(8.0, 8.0, 8.0).looking_to(Vec3::X, Vec3::Y);
+        let view_projection = PerspectiveProjection::default().get_projection_matrix() * transform.compute_matrix().inverse();
+        world.spawn((transform, Frustum::from_view_projection(&view_projection), StreamingAnchor::default()));
+        let mut search = IntoSystem::into_system(update_visible_chunks);
+        search.initialize(&mut world);
+
+        // The batch is taken but the command spawning it waits for the next sync point, a visibility
+        // search in between must not queue the reserved chunks again
+        for _ in 0..2 {
+            world.resource_mut::<VisibilityRefresh>().invalidate();
+            search.run((), &mut world);
+            flush.run((), &mut world);
+        }
+        search.apply_deferred(&mut world);
+        flush.apply_deferred(&mut world);
+
+        let reserved = world.resource::<ChunkData>().awaiting_generation.clone();
+        assert!(!reserved.is_empty());
+        let awaiting = world.query::<(Entity, &AwaitingGeneration)>().iter(&world).map(|(entity, awaiting)| (awaiting.chunk_pos, entity)).collect();
+        assert_eq!(reserved, awaiting);
+        // Nothing but the anchor and one entity per chunk
+        assert_eq!(world.entities().len() as usize, reserved.len() + 1);
+    }
+
+    #[test]
+    fn test_collected_reservation_is_despawned_once() {
+        let root = TempDir::new("spawn-queue");
+        let chunk = ChunkPosition::new(0, 0, 0);
+        let mut chunk_data = ChunkData::default();
+        chunk_data.visible.insert(chunk);
+        let mut queue = ChunkSpawnQueue::default();
+        queue.queue_spawn(chunk);
+        let (mut world, mut flush) = spawn_world(chunk_data, queue);
+        world.insert_resource(ChunkStorage::open(root.path()).unwrap());
+        world.init_resource::<ChunkCache>();
+        world.init_resource::<DirtyChunks>();
+        world.init_resource::<MemoryBudget>();
+        world.init_resource::<ChunkIndex>();
+        world.init_resource::<ChunkSource>();
+        world.init_resource::<ChunkLogLevel>();
+        world.init_resource::<Assets<Mesh>>();
+
+        flush.run((), &mut world);
+        let entity = world.resource::<ChunkData>().awaiting_generation[&chunk];
+        // The critical ring takes the reserved entity over and the camera leaves before the batch is spawned
+        world.resource_mut::<ChunkData>().awaiting_generation.remove(&chunk);
+        world.resource_mut::<ChunkData>().loaded.insert(chunk, entity);
+        world.get_or_spawn(entity).unwrap().insert(Chunk::new(chunk));
+        world.resource_mut::<ChunkIndex>().insert(entity, chunk);
+        world.resource_mut::<ChunkData>().visible.clear();
+        world.spawn((Transform::from_xyz(10_000.0, 0.0, 0.0), StreamingAnchor::default()));
+        world.run_system_once(garbage_collect_chunks);
+        assert!(world.resource::<ChunkSpawnQueue>().is_despawning(entity));
+
+        // The batch leaves the collected entity to the despawn queue
+        flush.apply_deferred(&mut world);
+        assert!(world.get_entity(entity).is_some());
+        world.run_system_once(flush_chunk_despawns);
+        assert!(world.get_entity(entity).is_none());
+        assert_eq!(world.entities().len(), 1);
+    }
+
+    #[test]
+    fn test_dropped_reservations_are_despawned() {
+        let chunk = ChunkPosition::new(0, 0, 0);
+        let mut chunk_data = ChunkData::default();
+        chunk_data.visible.insert(chunk);
+        let mut queue = ChunkSpawnQueue::default();
+        queue.queue_spawn(chunk);
+        let (mut world, mut flush) = spawn_world(chunk_data, queue);
+
+        flush.run((), &mut world);
+        // Every chunk is unloaded before the batch is spawned
+        world.resource_mut::<ChunkData>().awaiting_generation.clear();
+        flush.apply_deferred(&mut world);
+        assert_eq!(world.entities().len(), 0);
+    }
+}
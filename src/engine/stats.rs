@@ -0,0 +1,137 @@
+//! Snapshot of the chunk streaming state, so tools and tests can check what is loaded,
+//! meshed and waiting without reaching into the maps of [`ChunkData`].
+
+use bevy::{ecs::system::SystemParam, prelude::*, render::mesh::Indices};
+
+use super::{cache::ChunkCache, chunk::ChunkPosition, ChunkData};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Reflect)]
+pub struct ChunkCounts {
+    pub loaded: usize,
+    pub awaiting_generation: usize,
+    pub visible: usize,
+    pub meshed: usize,
+    pub pinned: usize,
+    /// Chunks with edits waiting for them to be generated or loaded
+    pub pending_edits: usize,
+}
+
+/// Chunk positions in each streaming state, sorted by x, y and z
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChunkStates {
+    pub loaded: Vec<ChunkPosition>,
+    pub awaiting_generation: Vec<ChunkPosition>,
+    pub meshed: Vec<ChunkPosition>,
+    pub pinned: Vec<ChunkPosition>,
+    /// Loaded chunks without a mesh yet, including chunks with nothing to draw
+    pub unmeshed: Vec<ChunkPosition>,
+    /// Visible chunks that are not loaded yet
+    pub missing: Vec<ChunkPosition>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChunkStats {
+    pub counts: ChunkCounts,
+    pub states: ChunkStates,
+    /// Estimated memory used by the voxels of loaded chunks
+    pub voxel_bytes: usize,
+    /// Memory used by chunk mesh vertices and indices. `None` unless gathered through [`ChunkStatistics`],
+    /// [`ChunkData`] alone has no access to the meshes.
+    pub mesh_bytes: Option<usize>,
+}
+
+impl ChunkStats {
+    pub fn from_chunk_data(chunk_data: &ChunkData) -> Self {
+        let sorted = |chunks: &mut dyn Iterator<Item = &ChunkPosition>| {
+            let mut chunks = chunks.copied().collect::<Vec<_>>();
+            chunks.sort_by_key(|chunk| (chunk.x, chunk.y, chunk.z));
+            chunks
+        };
+        let states = ChunkStates {
+            loaded: sorted(&mut chunk_data.loaded.keys()),
+            awaiting_generation: sorted(&mut chunk_data.awaiting_generation.keys()),
+            meshed: sorted(&mut chunk_data.meshes.keys()),
+            pinned: sorted(&mut chunk_data.pins.chunks()),
+            unmeshed: sorted(&mut chunk_data.loaded.keys().filter(|chunk| !chunk_data.meshes.contains_key(*chunk))),
+            missing: sorted(&mut chunk_data.visible.iter().filter(|chunk| !chunk_data.loaded.contains_key(*chunk))),
+        };
+        let counts = ChunkCounts {
+            loaded: chunk_data.loaded.len(),
+            awaiting_generation: chunk_data.awaiting_generation.len(),
+            visible: chunk_data.visible.len(),
+            meshed: chunk_data.meshes.len(),
+            pinned: chunk_data.pins.len(),
+            pending_edits: chunk_data.pending_edits.len(),
+        };
+        Self { counts, states, voxel_bytes: counts.loaded * ChunkCache::chunk_size_bytes(), mesh_bytes: None }
+    }
+}
+
+/// Bytes of vertex and index data of a mesh
+pub fn mesh_bytes(mesh: &Mesh) -> usize {
+    let indices = match mesh.indices() {
+        Some(Indices::U16(indices)) => indices.len() * 2,
+        Some(Indices::U32(indices)) => indices.len() * 4,
+        None => 0,
+    };
+    mesh.attributes().map(|(_, values)| values.get_bytes().len()).sum::<usize>() + indices
+}
+
+/// System parameter gathering [`ChunkStats`] including mesh memory
+#[derive(SystemParam)]
+pub struct ChunkStatistics<'w> {
+    chunk_data: Res<'w, ChunkData>,
+    meshes: Res<'w, Assets<Mesh>>,
+}
+
+impl ChunkStatistics<'_> {
+    pub fn get(&self) -> ChunkStats {
+        let mut stats = self.chunk_data.stats();
+        let mesh_bytes = self.chunk_data.meshes.values().filter_map(|handle| self.meshes.get(handle)).map(mesh_bytes).sum();
+        stats.mesh_bytes = Some(mesh_bytes);
+        stats
+    }
+
+    pub fn chunk_data(&self) -> &ChunkData {
+        &self.chunk_data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{ecs::system::RunSystemOnce, render::render_resource::PrimitiveTopology};
+
+    use super::*;
+
+    #[test]
+    fn test_stats_states() {
+        let mut world = World::new();
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0.0f32; 3]; 3]);
+        mesh.set_indices(Some(Indices::U32(vec![0, 1, 2])));
+        let mut meshes = Assets::<Mesh>::default();
+        let handle = meshes.add(mesh);
+
+        let mut chunk_data = ChunkData::default();
+        let (a, b, c) = (ChunkPosition::new(0, 0, 0), ChunkPosition::new(1, 0, 0), ChunkPosition::new(-1, 2, 0));
+        chunk_data.loaded.insert(b, Entity::from_raw(1));
+        chunk_data.loaded.insert(a, Entity::from_raw(0));
+        chunk_data.meshes.insert(a, handle);
+        chunk_data.awaiting_generation.insert(c, Entity::from_raw(2));
+        chunk_data.visible.extend([a, b, c]);
+        chunk_data.pin(b);
+        world.insert_resource(chunk_data);
+        world.insert_resource(meshes);
+
+        let stats = world.run_system_once(|statistics: ChunkStatistics| statistics.get());
+        assert_eq!(stats.counts, ChunkCounts { loaded: 2, awaiting_generation: 1, visible: 3, meshed: 1, pinned: 1, pending_edits: 0 });
+        assert_eq!(stats.states.loaded, vec![a, b]);
+        assert_eq!(stats.states.unmeshed, vec![b]);
+        assert_eq!(stats.states.missing, vec![c]);
+        assert_eq!(stats.states.pinned, vec![b]);
+        assert_eq!(stats.voxel_bytes, 2 * ChunkCache::chunk_size_bytes());
+        // Three 12 byte positions and three 4 byte indices
+        assert_eq!(stats.mesh_bytes, Some(48));
+        assert_eq!(world.resource::<ChunkData>().stats().mesh_bytes, None);
+    }
+}
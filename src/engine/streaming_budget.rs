@@ -0,0 +1,143 @@
+//! Scales the per-frame work of the chunk streaming systems with the frame time.
+//!
+//! The visibility search, generation task polling and mesh application add the time they took to
+//! [`StreamingBudget`]. At the start of every frame the budget compares the last frame time to the
+//! target: when the frame was too slow and streaming had a noticeable share in it the per-frame limits
+//! shrink quickly, when there is headroom they grow back slowly. The limits are the base values
+//! multiplied by [`StreamingBudget::scale`], the visibility search runs less often below a scale of one.
+
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+
+#[derive(Resource, Debug, Clone)]
+pub struct StreamingBudget {
+    /// Disabled budgets keep the scale at one
+    pub enabled: bool,
+    pub target_fps: f32,
+    /// Streaming only gets throttled when it took at least this share of a slow frame
+    pub min_share: f32,
+    pub min_scale: f32,
+    pub max_scale: f32,
+    /// Generation tasks started per frame at a scale of one, restoring cached or saved chunks is not limited
+    pub generation_starts_per_frame: usize,
+    /// Finished generation tasks taken per frame at a scale of one
+    pub generated_per_frame: usize,
+    /// Meshes applied per frame at a scale of one
+    pub meshes_per_frame: usize,
+    scale: f32,
+    spent: Duration,
+    /// Time streaming systems took in the last finished frame
+    last_spent: Duration,
+    last_frame_time: f32,
+}
+
+impl Default for StreamingBudget {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            target_fps: 60.0,
+            min_share: 0.1,
+            min_scale: 0.125,
+            max_scale: 4.0,
+            generation_starts_per_frame: 32,
+            generated_per_frame: 32,
+            meshes_per_frame: 32,
+            scale: 1.0,
+            spent: Duration::ZERO,
+            last_spent: Duration::ZERO,
+            last_frame_time: 0.0,
+        }
+    }
+}
+
+impl StreamingBudget {
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// `base` scaled to the current frame time, streaming never stops completely
+    pub fn limit(&self, base: usize) -> usize {
+        ((base as f32 * self.scale).round() as usize).max(1)
+    }
+
+    /// The visibility search runs every this many frames
+    pub fn visibility_interval(&self) -> u32 {
+        (1.0 / self.scale).ceil().clamp(1.0, 4.0) as u32
+    }
+
+    /// Adds the time since `started` to the time spent on streaming this frame
+    pub fn record(&mut self, started: Instant) {
+        self.spent += started.elapsed();
+    }
+
+    pub fn last_spent(&self) -> Duration {
+        self.last_spent
+    }
+
+    pub fn last_frame_time(&self) -> f32 {
+        self.last_frame_time
+    }
+
+    /// Closes the last frame that took `frame_time` seconds and adjusts the scale for the next one
+    pub fn end_frame(&mut self, frame_time: f32) {
+        self.last_spent = std::mem::take(&mut self.spent);
+        self.last_frame_time = frame_time;
+        if !self.enabled {
+            self.scale = 1.0;
+            return;
+        }
+        if frame_time <= 0.0 {
+            return;
+        }
+
+        let target = 1.0 / self.target_fps;
+        let share = self.last_spent.as_secs_f32() / frame_time;
+        if frame_time > target * 1.1 && share >= self.min_share {
+            self.scale *= 0.75;
+        } else if frame_time < target * 0.9 {
+            self.scale *= 1.05;
+        }
+        self.scale = self.scale.clamp(self.min_scale, self.max_scale);
+    }
+}
+
+pub fn update_streaming_budget(mut budget: ResMut<StreamingBudget>, time: Res<Time>) {
+    budget.end_frame(time.delta_seconds());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scale_follows_frame_time() {
+        let mut budget = StreamingBudget::default();
+        let slow = 1.0 / 30.0;
+        let fast = 1.0 / 120.0;
+
+        // Slow frames caused by something else leave streaming alone
+        budget.end_frame(slow);
+        assert_eq!(budget.scale(), 1.0);
+
+        for _ in 0..100 {
+            budget.spent = Duration::from_secs_f32(slow / 2.0);
+            budget.end_frame(slow);
+        }
+        assert_eq!(budget.scale(), budget.min_scale);
+        assert_eq!(budget.limit(32), 4);
+        assert_eq!(budget.limit(1), 1);
+        assert_eq!(budget.visibility_interval(), 4);
+
+        for _ in 0..1000 {
+            budget.end_frame(fast);
+        }
+        assert_eq!(budget.scale(), budget.max_scale);
+        assert_eq!(budget.limit(32), 128);
+        assert_eq!(budget.visibility_interval(), 1);
+
+        budget.enabled = false;
+        budget.end_frame(slow);
+        assert_eq!(budget.scale(), 1.0);
+    }
+}
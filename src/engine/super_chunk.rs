@@ -0,0 +1,430 @@
+//! Distant horizon: far away chunk meshes are merged into one mesh per super-chunk of
+//! [`SUPER_CHUNK_SIZE`]³ chunks, which cuts entity and draw counts at large render distances.
+//!
+//! Member chunks keep their own meshes but are hidden while their super-chunk is merged. Only
+//! meshes of one [`ChunkVertexFormat`] are merged, members in another one stay visible.
+//! Merged meshes are rebuilt lazily, once their members stopped changing for a moment.
+
+use bevy::{
+    prelude::*,
+    render::{mesh::{Indices, VertexAttributeValues}, render_resource::PrimitiveTopology},
+    utils::{HashMap, HashSet},
+};
+
+use super::{
+    anchor::StreamingAnchor,
+    chunk::{Chunk, ChunkPosition},
+    chunk_material::ChunkMaterials,
+    coords::floor_div,
+    generator::apply_meshes,
+    meshing::{ChunkVertexFormat, ATTRIBUTE_FACE},
+    ChunkData,
+};
+
+/// Super-chunk edge length in chunks
+pub const SUPER_CHUNK_SIZE: i32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SuperChunkPosition {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl SuperChunkPosition {
+    pub fn from_chunk(chunk: &ChunkPosition) -> Self {
+        Self {
+            x: floor_div(chunk.x, SUPER_CHUNK_SIZE),
+            y: floor_div(chunk.y, SUPER_CHUNK_SIZE),
+            z: floor_div(chunk.z, SUPER_CHUNK_SIZE),
+        }
+    }
+
+    /// Chunk with the lowest coordinates in this super-chunk
+    pub fn min_chunk(&self) -> ChunkPosition {
+        ChunkPosition::new(self.x * SUPER_CHUNK_SIZE, self.y * SUPER_CHUNK_SIZE, self.z * SUPER_CHUNK_SIZE)
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = ChunkPosition> {
+        let min = self.min_chunk();
+        (0..SUPER_CHUNK_SIZE).flat_map(move |x| {
+            (0..SUPER_CHUNK_SIZE).flat_map(move |y| (0..SUPER_CHUNK_SIZE).map(move |z| ChunkPosition::new(min.x + x, min.y + y, min.z + z)))
+        })
+    }
+
+    /// Distance in chunks on the x and z axes from `chunk` to the nearest member chunk
+    pub fn horizontal_distance(&self, chunk: &ChunkPosition) -> i32 {
+        let min = self.min_chunk();
+        let axis = |value: i32, min: i32| (min - value).max(value - (min + SUPER_CHUNK_SIZE - 1)).max(0);
+        axis(chunk.x, min.x).max(axis(chunk.z, min.z))
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct SuperChunkSettings {
+    pub enabled: bool,
+    /// Super-chunks with every member at least this many chunks away from the camera are merged
+    pub min_distance: i32,
+    /// Seconds a super-chunk has to stay unchanged before it is rebuilt
+    pub rebuild_delay: f32,
+    pub max_rebuilds_per_frame: usize,
+}
+
+impl Default for SuperChunkSettings {
+    fn default() -> Self {
+        Self { enabled: true, min_distance: 8, rebuild_delay: 0.5, max_rebuilds_per_frame: 4 }
+    }
+}
+
+/// Entity drawing a merged super-chunk
+#[derive(Component, Debug)]
+pub struct SuperChunkMesh(pub SuperChunkPosition);
+
+struct MergedSuperChunk {
+    entity: Entity,
+    /// Chunk entities hidden in favour of the merged mesh
+    members: Vec<Entity>,
+}
+
+#[derive(Resource, Default)]
+pub struct SuperChunks {
+    merged: HashMap<SuperChunkPosition, MergedSuperChunk>,
+    member_of: HashMap<Entity, SuperChunkPosition>,
+    /// Super-chunks to rebuild and the time they last changed
+    dirty: HashMap<SuperChunkPosition, f32>,
+    camera_chunk: Option<ChunkPosition>,
+}
+
+impl SuperChunks {
+    pub fn merged_count(&self) -> usize {
+        self.merged.len()
+    }
+
+    pub fn hidden_chunk_count(&self) -> usize {
+        self.member_of.len()
+    }
+
+    pub fn pending_rebuilds(&self) -> usize {
+        self.dirty.len()
+    }
+}
+
+/// Vertex format of a chunk mesh, `None` if [`merge_meshes`] can not merge it
+pub fn mesh_format(mesh: &Mesh) -> Option<ChunkVertexFormat> {
+    if mesh.indices().is_none() || !matches!(mesh.attribute(Mesh::ATTRIBUTE_POSITION), Some(VertexAttributeValues::Float32x3(_))) {
+        return None;
+    }
+    match (mesh.attribute(Mesh::ATTRIBUTE_NORMAL), mesh.attribute(ATTRIBUTE_FACE)) {
+        (Some(VertexAttributeValues::Float32x3(_)), _) => Some(ChunkVertexFormat::Standard),
+        (_, Some(VertexAttributeValues::Uint32(_))) => Some(ChunkVertexFormat::PackedFace),
+        _ => None,
+    }
+}
+
+/// Concatenates chunk meshes placed at the given offsets into one mesh, `None` if there is nothing to draw.
+/// The merged mesh has the [`mesh_format`] of the first part, parts in another format are left out.
+/// Uvs, tangents and vertex colors are kept if any part has them, parts without get defaults
+pub fn merge_meshes<'a>(parts: impl Iterator<Item = (Vec3, &'a Mesh)>) -> Option<Mesh> {
+    let mut format = None;
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut faces = Vec::new();
+    let mut uvs = Vec::new();
+    let mut tangents = Vec::new();
+    let mut colors = Vec::new();
+    let (mut has_uvs, mut has_tangents, mut has_colors) = (false, false, false);
+    let mut indices = Vec::new();
+
+    for (offset, mesh) in parts {
+        let Some(part_format) = mesh_format(mesh) else {
+            continue;
+        };
+        if *format.get_or_insert(part_format) != part_format {
+            continue;
+        }
+        let Some(VertexAttributeValues::Float32x3(part_positions)) = mesh.attribute(Mesh::ATTRIBUTE_POSITION) else {
+            continue;
+        };
+        match (mesh.attribute(Mesh::ATTRIBUTE_NORMAL), mesh.attribute(ATTRIBUTE_FACE), part_format) {
+            (Some(VertexAttributeValues::Float32x3(part_normals)), _, ChunkVertexFormat::Standard) => normals.extend_from_slice(part_normals),
+            (_, Some(VertexAttributeValues::Uint32(part_faces)), ChunkVertexFormat::PackedFace) => faces.extend_from_slice(part_faces),
+            _ => continue,
+        }
+        let first = positions.len() as u32;
+        match mesh.indices() {
+            Some(Indices::U32(part)) => indices.extend(part.iter().map(|i| first + i)),
+            Some(Indices::U16(part)) => indices.extend(part.iter().map(|i| first + *i as u32)),
+            None => continue,
+        }
+        positions.extend(part_positions.iter().map(|p| (Vec3::from_array(*p) + offset).to_array()));
+
+        // Chunk offsets are whole voxels, uvs repeating per voxel line up without moving them
+        let vertex_count = part_positions.len();
+        match mesh.attribute(Mesh::ATTRIBUTE_UV_0) {
+            Some(VertexAttributeValues::Float32x2(part_uvs)) => {
+                has_uvs = true;
+                uvs.extend_from_slice(part_uvs);
+            }
+            _ => uvs.extend(std::iter::repeat([0.0; 2]).take(vertex_count)),
+        }
+        match mesh.attribute(Mesh::ATTRIBUTE_TANGENT) {
+            Some(VertexAttributeValues::Float32x4(part_tangents)) => {
+                has_tangents = true;
+                tangents.extend_from_slice(part_tangents);
+            }
+            _ => tangents.extend(std::iter::repeat([1.0, 0.0, 0.0, 1.0]).take(vertex_count)),
+        }
+        match mesh.attribute(Mesh::ATTRIBUTE_COLOR) {
+            Some(VertexAttributeValues::Float32x4(part_colors)) => {
+                has_colors = true;
+                colors.extend_from_slice(part_colors);
+            }
+            _ => colors.extend(std::iter::repeat([1.0; 4]).take(vertex_count)),
+        }
+    }
+
+    if indices.is_empty() {
+        return None;
+    }
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, VertexAttributeValues::Float32x3(positions));
+    match format {
+        Some(ChunkVertexFormat::PackedFace) => mesh.insert_attribute(ATTRIBUTE_FACE, VertexAttributeValues::Uint32(faces)),
+        _ => mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, VertexAttributeValues::Float32x3(normals)),
+    }
+    if has_uvs {
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, VertexAttributeValues::Float32x2(uvs));
+    }
+    if has_tangents {
+        mesh.insert_attribute(Mesh::ATTRIBUTE_TANGENT, VertexAttributeValues::Float32x4(tangents));
+    }
+    if has_colors {
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, VertexAttributeValues::Float32x4(colors));
+    }
+    Some(mesh)
+}
+
+pub struct SuperChunkPlugin;
+
+impl Plugin for SuperChunkPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SuperChunkSettings>()
+            .init_resource::<SuperChunks>()
+            .add_systems(Update, (mark_changed_super_chunks, rebuild_super_chunks.after(mark_changed_super_chunks)).after(apply_meshes));
+
+        #[cfg(feature = "debug-ui")]
+        app.add_systems(Update, show_super_chunk_debug_info);
+    }
+}
+
+/// Marks super-chunks whose members got a new mesh or were unloaded, and the ones the camera moved towards or away from
+fn mark_changed_super_chunks(
+    mut super_chunks: ResMut<SuperChunks>,
+    mut removed_meshes: RemovedComponents<Handle<Mesh>>,
+    settings: Res<SuperChunkSettings>,
+    chunk_data: Res<ChunkData>,
+    time: Res<Time>,
+    camera: Query<&Transform, With<StreamingAnchor>>,
+    changed: Query<&Chunk, Changed<Handle<Mesh>>>,
+) {
+    let now = time.elapsed_seconds();
+    let super_chunks = &mut *super_chunks;
+
+    for chunk in changed.iter() {
+        super_chunks.dirty.insert(SuperChunkPosition::from_chunk(&chunk.position), now);
+    }
+    for entity in removed_meshes.read() {
+        if let Some(position) = super_chunks.member_of.get(&entity) {
+            super_chunks.dirty.insert(*position, now);
+        }
+    }
+
+    let Some(camera_chunk) = camera.iter().next().map(|transform| ChunkPosition::from_world_position(transform.translation)) else {
+        return;
+    };
+    if super_chunks.camera_chunk == Some(camera_chunk) {
+        return;
+    }
+    super_chunks.camera_chunk = Some(camera_chunk);
+
+    // Merged super-chunks the camera got close to have to be split right away, far ones built soon
+    let disabled = !settings.enabled;
+    for position in super_chunks.merged.keys() {
+        if disabled || position.horizontal_distance(&camera_chunk) < settings.min_distance {
+            super_chunks.dirty.insert(*position, f32::NEG_INFINITY);
+        }
+    }
+    if disabled {
+        return;
+    }
+    let meshed = chunk_data
+        .meshes
+        .keys()
+        .map(SuperChunkPosition::from_chunk)
+        .collect::<HashSet<_>>();
+    for position in meshed {
+        if position.horizontal_distance(&camera_chunk) >= settings.min_distance && !super_chunks.merged.contains_key(&position) {
+            super_chunks.dirty.entry(position).or_insert(f32::NEG_INFINITY);
+        }
+    }
+}
+
+fn rebuild_super_chunks(
+    mut commands: Commands,
+    mut super_chunks: ResMut<SuperChunks>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut visibility: Query<&mut Visibility, With<Chunk>>,
+    settings: Res<SuperChunkSettings>,
+    chunk_data: Res<ChunkData>,
+    chunk_materials: Res<ChunkMaterials>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds();
+    let Some(camera_chunk) = super_chunks.camera_chunk else {
+        return;
+    };
+    let ready = super_chunks
+        .dirty
+        .iter()
+        .filter(|(_, changed)| now - **changed >= settings.rebuild_delay)
+        .map(|(position, _)| *position)
+        .take(settings.max_rebuilds_per_frame)
+        .collect::<Vec<_>>();
+
+    for position in ready {
+        super_chunks.dirty.remove(&position);
+
+        // Split the old merged mesh, members are hidden again below if it is rebuilt
+        if let Some(old) = super_chunks.merged.remove(&position) {
+            commands.entity(old.entity).despawn();
+            for member in old.members {
+                super_chunks.member_of.remove(&member);
+                if let Ok(mut member_visibility) = visibility.get_mut(member) {
+                    *member_visibility = Visibility::Inherited;
+                }
+            }
+        }
+        if !settings.enabled || position.horizontal_distance(&camera_chunk) < settings.min_distance {
+            continue;
+        }
+
+        let origin = position.min_chunk().as_world_position();
+        // Members merge_meshes leaves out are not hidden
+        let mut format = None;
+        let members = position
+            .chunks()
+            .filter_map(|chunk| Some((chunk, *chunk_data.loaded.get(&chunk)?, meshes.get(chunk_data.meshes.get(&chunk)?)?)))
+            .filter(|(_, _, mesh)| mesh_format(mesh).is_some_and(|part_format| *format.get_or_insert(part_format) == part_format))
+            .collect::<Vec<_>>();
+        let Some(mesh) = merge_meshes(members.iter().map(|(chunk, _, mesh)| (chunk.as_world_position() - origin, *mesh))) else {
+            continue;
+        };
+        let members = members.into_iter().map(|(_, entity, _)| entity).collect::<Vec<_>>();
+
+        let entity = commands
+            .spawn((
+                MaterialMeshBundle {
+                    mesh: meshes.add(mesh),
+                    transform: Transform::from_translation(origin),
+                    material: chunk_materials.opaque.clone(),
+                    ..Default::default()
+                },
+                SuperChunkMesh(position),
+            ))
+            .id();
+        for member in members.iter() {
+            super_chunks.member_of.insert(*member, position);
+            if let Ok(mut member_visibility) = visibility.get_mut(*member) {
+                *member_visibility = Visibility::Hidden;
+            }
+        }
+        super_chunks.merged.insert(position, MergedSuperChunk { entity, members });
+    }
+}
+
+#[cfg(feature = "debug-ui")]
+fn show_super_chunk_debug_info(
+    mut contexts: bevy_egui::EguiContexts,
+    mut settings: ResMut<SuperChunkSettings>,
+    mut super_chunks: ResMut<SuperChunks>,
+) {
+    use bevy_egui::egui;
+    egui::Window::new("Super Chunks").default_open(false).show(contexts.ctx_mut(), |ui| {
+        let before = (settings.enabled, settings.min_distance);
+        ui.checkbox(&mut settings.enabled, "Merge Distant Chunks");
+        ui.add(egui::Slider::new(&mut settings.min_distance, 2..=64).text("Merge Distance"));
+        ui.add(egui::Slider::new(&mut settings.rebuild_delay, 0.0..=5.0).text("Rebuild Delay"));
+        ui.add(egui::Slider::new(&mut settings.max_rebuilds_per_frame, 1..=32).text("Rebuilds per Frame"));
+        // Forces the camera pass to run again with the new settings
+        if before != (settings.enabled, settings.min_distance) {
+            super_chunks.camera_chunk = None;
+        }
+        ui.label(format!("Merged: {}", super_chunks.merged_count()));
+        ui.label(format!("Hidden Chunks: {}", super_chunks.hidden_chunk_count()));
+        ui.label(format!("Pending Rebuilds: {}", super_chunks.pending_rebuilds()));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_super_chunk_position() {
+        let position = SuperChunkPosition::from_chunk(&ChunkPosition::new(-1, 4, 3));
+        assert_eq!(position, SuperChunkPosition { x: -1, y: 1, z: 0 });
+        assert_eq!(position.min_chunk(), ChunkPosition::new(-4, 4, 0));
+        assert_eq!(position.chunks().count(), 64);
+        assert_eq!(position.horizontal_distance(&ChunkPosition::new(-2, 100, 2)), 0);
+        assert_eq!(position.horizontal_distance(&ChunkPosition::new(5, 0, 2)), 6);
+    }
+
+    #[test]
+    fn test_merge_meshes_offsets_indices() {
+        let mut part = Mesh::new(PrimitiveTopology::TriangleList);
+        part.insert_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]);
+        part.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0.0f32, 0.0, 1.0]; 3]);
+        part.set_indices(Some(Indices::U32(vec![0, 1, 2])));
+
+        let merged = merge_meshes([(Vec3::ZERO, &part), (Vec3::new(16.0, 0.0, 0.0), &part)].into_iter()).unwrap();
+        let Some(VertexAttributeValues::Float32x3(positions)) = merged.attribute(Mesh::ATTRIBUTE_POSITION) else {
+            panic!("merged mesh has no positions");
+        };
+        assert_eq!(positions[4], [17.0, 0.0, 0.0]);
+        assert!(matches!(merged.indices(), Some(Indices::U32(indices)) if indices == &vec![0, 1, 2, 3, 4, 5]));
+        assert!(merged.attribute(Mesh::ATTRIBUTE_COLOR).is_none());
+
+        // A tinted part gives the whole merged mesh colors, white for the other parts
+        let mut tinted = part.clone();
+        tinted.insert_attribute(Mesh::ATTRIBUTE_COLOR, vec![[1.0f32, 0.0, 0.0, 1.0]; 3]);
+        let merged = merge_meshes([(Vec3::ZERO, &part), (Vec3::ZERO, &tinted)].into_iter()).unwrap();
+        let Some(VertexAttributeValues::Float32x4(colors)) = merged.attribute(Mesh::ATTRIBUTE_COLOR) else {
+            panic!("merged mesh has no colors");
+        };
+        assert_eq!(colors[0], [1.0; 4]);
+        assert_eq!(colors[3], [1.0, 0.0, 0.0, 1.0]);
+
+        assert!(merge_meshes(std::iter::empty()).is_none());
+    }
+
+    #[test]
+    fn test_merge_meshes_keeps_the_vertex_format() {
+        let mut packed = Mesh::new(PrimitiveTopology::TriangleList);
+        packed.insert_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]);
+        packed.insert_attribute(ATTRIBUTE_FACE, vec![5u32; 3]);
+        packed.set_indices(Some(Indices::U32(vec![0, 1, 2])));
+        let mut standard = packed.clone();
+        standard.remove_attribute(ATTRIBUTE_FACE);
+        standard.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0.0f32, 0.0, 1.0]; 3]);
+        assert_eq!(mesh_format(&packed), Some(ChunkVertexFormat::PackedFace));
+        assert_eq!(mesh_format(&standard), Some(ChunkVertexFormat::Standard));
+
+        // The standard part is left out of a mesh started with a packed one
+        let merged = merge_meshes([(Vec3::ZERO, &packed), (Vec3::X, &standard), (Vec3::Y, &packed)].into_iter()).unwrap();
+        assert_eq!(mesh_format(&merged), Some(ChunkVertexFormat::PackedFace));
+        assert_eq!(merged.count_vertices(), 6);
+        assert!(merged.attribute(Mesh::ATTRIBUTE_NORMAL).is_none());
+        assert!(matches!(merged.attribute(ATTRIBUTE_FACE), Some(VertexAttributeValues::Uint32(faces)) if faces == &vec![5; 6]));
+    }
+}
@@ -0,0 +1,140 @@
+//! Optional color tint of single voxels, for grass varying by biome or a painting tool.
+//!
+//! Tints are sRGB colors multiplied into the vertex colors of the faces of the voxel. A chunk keeps
+//! a palette of at most [`ChunkTints::MAX_COLORS`] colors and one palette index per voxel, the
+//! indices are only allocated once a voxel of the chunk is tinted.
+
+use super::chunk::CHUNK_SIZE;
+
+const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
+/// sRGB color
+pub type Tint = [u8; 3];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChunkTints {
+    palette: Vec<Tint>,
+    /// Palette index + 1 per voxel in buffer order, 0 is untinted
+    indices: Option<Box<[u8]>>,
+}
+
+impl ChunkTints {
+    pub const MAX_COLORS: usize = u8::MAX as usize;
+
+    /// No voxel is tinted
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_none()
+    }
+
+    pub fn get(&self, index: usize) -> Option<Tint> {
+        let palette_index = self.indices.as_ref()?[index];
+        (palette_index > 0).then(|| self.palette[palette_index as usize - 1])
+    }
+
+    /// Index into [`ChunkTints::palette`] + 1, 0 for untinted voxels
+    pub fn palette_index(&self, index: usize) -> u8 {
+        self.indices.as_ref().map_or(0, |indices| indices[index])
+    }
+
+    pub fn palette(&self) -> &[Tint] {
+        &self.palette
+    }
+
+    /// `false` if the chunk already uses [`ChunkTints::MAX_COLORS`] other colors, the voxel is left as it was
+    pub fn set(&mut self, index: usize, tint: Option<Tint>) -> bool {
+        let Some(tint) = tint else {
+            if let Some(indices) = self.indices.as_mut() {
+                indices[index] = 0;
+            }
+            return true;
+        };
+
+        let palette_index = match self.palette.iter().position(|color| *color == tint) {
+            Some(position) => position,
+            None => {
+                if self.palette.len() == Self::MAX_COLORS {
+                    self.compact();
+                }
+                if self.palette.len() == Self::MAX_COLORS {
+                    return false;
+                }
+                self.palette.push(tint);
+                self.palette.len() - 1
+            }
+        };
+        self.indices.get_or_insert_with(|| vec![0; CHUNK_VOLUME].into_boxed_slice())[index] = palette_index as u8 + 1;
+        true
+    }
+
+    /// Drops palette colors no voxel uses anymore, and the indices if no voxel is tinted
+    pub fn compact(&mut self) {
+        let Some(indices) = self.indices.as_mut() else {
+            self.palette.clear();
+            return;
+        };
+        let mut used = vec![false; self.palette.len()];
+        for palette_index in indices.iter().filter(|index| **index > 0) {
+            used[*palette_index as usize - 1] = true;
+        }
+
+        let mut remap = vec![0u8; self.palette.len() + 1];
+        let mut palette = Vec::new();
+        for (position, color) in self.palette.iter().enumerate().filter(|(position, _)| used[*position]) {
+            palette.push(*color);
+            remap[position + 1] = palette.len() as u8;
+        }
+        for palette_index in indices.iter_mut() {
+            *palette_index = remap[*palette_index as usize];
+        }
+
+        self.palette = palette;
+        if self.palette.is_empty() {
+            self.indices = None;
+        }
+    }
+
+    /// Rebuilds tints from a palette and per voxel palette indices + 1, `None` if an index is out of range
+    pub fn from_parts(palette: Vec<Tint>, indices: Box<[u8]>) -> Option<Self> {
+        if palette.len() > Self::MAX_COLORS || indices.len() != CHUNK_VOLUME || indices.iter().any(|index| *index as usize > palette.len()) {
+            return None;
+        }
+        let mut tints = Self { palette, indices: Some(indices) };
+        tints.compact();
+        Some(tints)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_palette_is_shared_and_compacted() {
+        let mut tints = ChunkTints::default();
+        assert!(tints.is_empty());
+        assert!(tints.set(0, None));
+        assert!(tints.is_empty());
+
+        assert!(tints.set(3, Some([10, 20, 30])));
+        assert!(tints.set(4, Some([10, 20, 30])));
+        assert_eq!(tints.palette().len(), 1);
+        assert_eq!(tints.get(4), Some([10, 20, 30]));
+        assert_eq!(tints.get(5), None);
+
+        // A full palette makes room by dropping colors no voxel uses anymore
+        for color in 0..ChunkTints::MAX_COLORS as u8 - 1 {
+            assert!(tints.set(100, Some([color, 0, 0])));
+        }
+        assert_eq!(tints.palette().len(), ChunkTints::MAX_COLORS);
+        assert!(tints.set(101, Some([255, 255, 255])));
+        assert_eq!(tints.palette().len(), 3);
+        assert_eq!(tints.get(3), Some([10, 20, 30]));
+        assert_eq!(tints.get(100), Some([253, 0, 0]));
+
+        for index in [3, 4, 100, 101] {
+            tints.set(index, None);
+        }
+        tints.compact();
+        assert!(tints.is_empty());
+    }
+}
@@ -1,4 +1,4 @@
-use bevy::{prelude::Vec3, render::primitives::Frustum, math::Affine3A};
+use bevy::prelude::Vec3;
 
 use super::chunk::{ChunkPosition, CHUNK_SIZE};
 
@@ -57,14 +57,3 @@ impl Face {
         } * CHUNK_SIZE as f32
     }
 }
-
-pub fn intersects_frustum(chunk: &ChunkPosition, frustum: &Frustum) -> bool {
-    let chunk_aabb = chunk.aabb();
-    
-    frustum.intersects_obb(
-        &chunk_aabb,
-        &Affine3A::IDENTITY,
-        true,
-        false
-    )
-}
\ No newline at end of file
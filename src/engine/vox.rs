@@ -0,0 +1,167 @@
+//! MagicaVoxel `.vox` importer. Models are converted into [`Schematic`]s which can be
+//! pasted into the world like copied regions.
+//!
+//! Only the models themselves are read, the scene graph (transforms, groups) is ignored.
+//! Colors are mapped to the block of the given [`BlockRegistry`] with the closest [`BlockDefinition::color`].
+
+use std::{fs, io, path::Path};
+
+use bevy::prelude::*;
+
+use super::{block_registry::{BlockDefinition, BlockRegistry}, schematic::Schematic, voxel::{Block, BlockId, Voxel}};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VoxError {
+    UnexpectedEof,
+    BadMagic,
+    /// XYZI chunk without a preceding SIZE chunk
+    MissingSize,
+    VoxelOutOfBounds([u8; 3]),
+}
+
+impl std::fmt::Display for VoxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnexpectedEof => write!(f, "unexpected end of .vox data"),
+            Self::BadMagic => write!(f, "not a .vox file (bad magic)"),
+            Self::MissingSize => write!(f, "voxel data without model size"),
+            Self::VoxelOutOfBounds([x, y, z]) => write!(f, "voxel ({}, {}, {}) is outside of the model", x, y, z),
+        }
+    }
+}
+
+impl std::error::Error for VoxError {}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], VoxError> {
+        if self.bytes.len() < len {
+            return Err(VoxError::UnexpectedEof);
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32, VoxError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+/// Block with the color closest to the given one
+fn closest_block(blocks: &BlockRegistry, color: [u8; 3]) -> BlockId {
+    let distance = |(_, definition): &(BlockId, &BlockDefinition)| {
+        let other = definition.color;
+        (0..3).map(|i| (color[i] as i32 - other[i] as i32).pow(2)).sum::<i32>()
+    };
+    blocks.iter().min_by_key(distance).unwrap().0
+}
+
+/// Reads every model of a `.vox` file
+pub fn parse_vox(bytes: &[u8], blocks: &BlockRegistry) -> Result<Vec<Schematic>, VoxError> {
+    let mut reader = Reader { bytes };
+    if reader.take(4)? != b"VOX " {
+        return Err(VoxError::BadMagic);
+    }
+    let _version = reader.u32()?;
+
+    // Chunks are read flat, children of MAIN simply follow its (empty) content
+    let mut sizes = Vec::new();
+    let mut models: Vec<(UVec3, Vec<[u8; 4]>)> = Vec::new();
+    let mut palette: Option<Vec<[u8; 3]>> = None;
+    while !reader.bytes.is_empty() {
+        let id = reader.take(4)?;
+        let content_len = reader.u32()? as usize;
+        let _children_len = reader.u32()?;
+        let mut content = Reader { bytes: reader.take(content_len)? };
+
+        match id {
+            b"SIZE" => sizes.push(UVec3::new(content.u32()?, content.u32()?, content.u32()?)),
+            b"XYZI" => {
+                let size = *sizes.get(models.len()).ok_or(VoxError::MissingSize)?;
+                let count = content.u32()? as usize;
+                let voxels = (0..count)
+                    .map(|_| content.take(4).map(|voxel| voxel.try_into().unwrap()))
+                    .collect::<Result<Vec<[u8; 4]>, _>>()?;
+                models.push((size, voxels));
+            }
+            b"RGBA" => {
+                palette = Some((0..256).map(|_| content.take(4).map(|c| [c[0], c[1], c[2]])).collect::<Result<_, _>>()?);
+            }
+            _ => {}
+        }
+    }
+
+    // Palette entry i is used by color index i + 1, files without a palette use the default one
+    // which is not bundled here, everything becomes stone in that case
+    let block_for = |color_index: u8| match &palette {
+        Some(palette) => closest_block(blocks, palette[(color_index as usize + 255) % 256]),
+        None => Block::Stone.id(),
+    };
+
+    models
+        .into_iter()
+        .map(|(size, voxels)| {
+            // MagicaVoxel is Z up, flip its Y axis into our Z to keep the handedness
+            let mut schematic = Schematic::new(UVec3::new(size.x, size.z, size.y));
+            for [x, y, z, color_index] in voxels {
+                if x as u32 >= size.x || y as u32 >= size.y || z as u32 >= size.z {
+                    return Err(VoxError::VoxelOutOfBounds([x, y, z]));
+                }
+                schematic.set(x as u32, z as u32, size.y - 1 - y as u32, Voxel::from(block_for(color_index)));
+            }
+            Ok(schematic)
+        })
+        .collect()
+}
+
+pub fn load_vox(path: &Path, blocks: &BlockRegistry) -> io::Result<Vec<Schematic>> {
+    parse_vox(&fs::read(path)?, blocks).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(content);
+        bytes
+    }
+
+    #[test]
+    fn test_parse_single_model() {
+        let size = [2u32, 3, 4].iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<_>>();
+        let mut xyzi = 2u32.to_le_bytes().to_vec();
+        xyzi.extend_from_slice(&[0, 0, 0, 1, 1, 2, 3, 2]);
+        let mut rgba = vec![0u8; 1024];
+        rgba[0..4].copy_from_slice(&[250, 250, 250, 255]);
+        rgba[4..8].copy_from_slice(&[90, 160, 50, 255]);
+
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend_from_slice(&150u32.to_le_bytes());
+        bytes.extend(chunk(b"MAIN", &[]));
+        bytes.extend(chunk(b"SIZE", &size));
+        bytes.extend(chunk(b"XYZI", &xyzi));
+        bytes.extend(chunk(b"RGBA", &rgba));
+
+        let models = parse_vox(&bytes, &BlockRegistry::builtin()).unwrap();
+        assert_eq!(models.len(), 1);
+        let model = &models[0];
+        assert_eq!(model.size(), UVec3::new(2, 4, 3));
+        assert_eq!(model.get(0, 0, 2), Voxel::from(Block::Snow));
+        assert_eq!(model.get(1, 3, 0), Voxel::from(Block::Grass));
+        assert_eq!(model.get(0, 0, 0), Voxel::Empty);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse_vox(b"RIFF", &BlockRegistry::builtin()), Err(VoxError::BadMagic));
+        assert_eq!(parse_vox(b"VOX ", &BlockRegistry::builtin()), Err(VoxError::UnexpectedEof));
+    }
+}
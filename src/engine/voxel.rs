@@ -1,8 +1,98 @@
+use std::{fmt, str::FromStr};
+
+use super::block_registry::BlockRegistry;
+
+/// Blocks every [`BlockRegistry`](super::block_registry::BlockRegistry) starts with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Block {
+    Bedrock,
+    Stone,
+    Dirt,
+    Grass,
+    Sand,
+    Gravel,
+    Snow,
+    Glass,
+    Water,
+}
+
+impl Block {
+    pub const ALL: [Block; 9] = [
+        Block::Bedrock,
+        Block::Stone,
+        Block::Dirt,
+        Block::Grass,
+        Block::Sand,
+        Block::Gravel,
+        Block::Snow,
+        Block::Glass,
+        Block::Water,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Bedrock => "bedrock",
+            Self::Stone => "stone",
+            Self::Dirt => "dirt",
+            Self::Grass => "grass",
+            Self::Sand => "sand",
+            Self::Gravel => "gravel",
+            Self::Snow => "snow",
+            Self::Glass => "glass",
+            Self::Water => "water",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|block| block.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// Builtin blocks always have the same id, whatever the blocks file defines
+    pub fn id(&self) -> BlockId {
+        BlockId::from_index(Block::ALL.iter().position(|block| block == self).unwrap())
+    }
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Block {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_name(s).ok_or_else(|| format!("unknown block `{}`", s.trim()))
+    }
+}
+
+/// Index of a block in the [`BlockRegistry`](super::block_registry::BlockRegistry).
+/// Ids start at 1, code 0 is the empty voxel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(u16);
+
+impl BlockId {
+    pub fn from_index(index: usize) -> Self {
+        Self(index as u16 + 1)
+    }
+
+    pub fn index(&self) -> usize {
+        self.0 as usize - 1
+    }
+}
+
+impl From<Block> for BlockId {
+    fn from(block: Block) -> Self {
+        block.id()
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Voxel {
     Empty,
     NonEmpty {
-        is_opaque: bool,
+        block: BlockId,
     }
 }
 
@@ -13,40 +103,66 @@ impl Default for Voxel {
 }
 
 impl Voxel {
-    pub fn is_opaque(&self) -> bool {
+    pub fn is_empty(&self) -> bool {
         match self {
-            Self::Empty => false,
-            Self::NonEmpty { is_opaque } => *is_opaque,
+            Self::Empty => true,
+            Self::NonEmpty { .. } => false,
         }
     }
 
-    pub fn is_empty(&self) -> bool {
+    /// Stable numeric code used when storing voxels, 0 is always empty
+    pub fn to_code(&self) -> u16 {
         match self {
-            Self::Empty => true,
-            Self::NonEmpty { .. } => false,
+            Self::Empty => 0,
+            Self::NonEmpty { block } => block.0,
         }
     }
-}
 
-impl block_mesh::Voxel for Voxel {
-    fn get_visibility(&self) -> block_mesh::VoxelVisibility {
+    /// `None` for blocks `blocks` does not know
+    pub fn from_code(code: u16, blocks: &BlockRegistry) -> Option<Self> {
+        if code == 0 {
+            return Some(Self::Empty);
+        }
+        let block = BlockId(code);
+        blocks.get(block).map(|_| Self::from(block))
+    }
+
+    pub fn block(&self) -> Option<BlockId> {
         match self {
-            Self::Empty => block_mesh::VoxelVisibility::Empty,
-            Self::NonEmpty { is_opaque } => {
-                if *is_opaque {
-                    block_mesh::VoxelVisibility::Opaque
-                } else {
-                    block_mesh::VoxelVisibility::Translucent
-                }
-            }
+            Self::Empty => None,
+            Self::NonEmpty { block } => Some(*block),
         }
     }
 }
 
-impl block_mesh::MergeVoxel for Voxel {
-    type MergeValue = Self;
+/// State of a single voxel beyond its block, like its orientation, growth stage or damage.
+/// Chunks only store it for the few voxels that have any
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct VoxelMetadata(Vec<u8>);
+
+impl VoxelMetadata {
+    /// Longest payload a voxel can carry, it is stored with a single length byte
+    pub const MAX_LEN: usize = u8::MAX as usize;
+
+    /// `None` if the payload is longer than [`VoxelMetadata::MAX_LEN`]
+    pub fn new(bytes: impl Into<Vec<u8>>) -> Option<Self> {
+        let bytes = bytes.into();
+        (bytes.len() <= Self::MAX_LEN).then_some(Self(bytes))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Block> for Voxel {
+    fn from(block: Block) -> Self {
+        Self::NonEmpty { block: block.id() }
+    }
+}
 
-    fn merge_value(&self) -> Self::MergeValue {
-        *self
+impl From<BlockId> for Voxel {
+    fn from(block: BlockId) -> Self {
+        Self::NonEmpty { block }
     }
-}
\ No newline at end of file
+}
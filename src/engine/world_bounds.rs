@@ -0,0 +1,191 @@
+use bevy::{pbr::NotShadowCaster, prelude::*};
+
+use super::{chunk::CHUNK_SIZE, generator::WorldGeneratorConfig, heightmap::HeightmapCache};
+use crate::flycam::PlayerCamera;
+
+/// Height of the border walls, they move up and down with the camera
+const BORDER_WALL_HEIGHT: f32 = 512.0;
+const BORDER_WALL_COLOR: Color = Color::rgba(0.45, 0.65, 1.0, 0.25);
+
+/// What happens to a camera that falls below the world bottom
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoidBehavior {
+    /// Move back above the terrain surface at the same x and z
+    TeleportToSurface,
+    /// Keep the camera at the lowest allowed height
+    ClampToBottom,
+    /// Only send [`FellIntoVoid`], gameplay code decides what happens
+    Nothing,
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct VoidConfig {
+    pub behavior: VoidBehavior,
+    /// How far below the world bottom the camera has to be to count as fallen into the void
+    pub margin: f32,
+    /// Height above the surface used when teleporting back
+    pub surface_offset: f32,
+}
+
+impl Default for VoidConfig {
+    fn default() -> Self {
+        Self {
+            behavior: VoidBehavior::TeleportToSurface,
+            margin: 16.0,
+            surface_offset: 2.0,
+        }
+    }
+}
+
+/// Sent when a camera falls below the world bottom
+#[derive(Event, Debug, Clone)]
+pub struct FellIntoVoid {
+    pub entity: Entity,
+    pub position: Vec3,
+    pub behavior: VoidBehavior,
+}
+
+/// One of the four translucent walls drawn at the [`WorldGeneratorConfig::world_border`]
+#[derive(Component)]
+pub struct BorderWall(usize);
+
+pub struct WorldBoundsPlugin;
+
+impl Plugin for WorldBoundsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<VoidConfig>()
+            .add_event::<FellIntoVoid>()
+            .add_systems(Update, (handle_void_fall, spawn_border_walls, move_border_walls.after(spawn_border_walls)));
+    }
+}
+
+/// Transforms of the four walls around a border `border` chunks from the origin, for a quad
+/// facing +z. Every wall faces into the world.
+pub fn border_wall_transforms(border: u32, y: f32) -> [Transform; 4] {
+    let half = (border as usize * CHUNK_SIZE) as f32;
+    [
+        Transform::from_xyz(0.0, y, -half),
+        Transform::from_xyz(0.0, y, half).with_rotation(Quat::from_rotation_y(std::f32::consts::PI)),
+        Transform::from_xyz(-half, y, 0.0).with_rotation(Quat::from_rotation_y(std::f32::consts::FRAC_PI_2)),
+        Transform::from_xyz(half, y, 0.0).with_rotation(Quat::from_rotation_y(-std::f32::consts::FRAC_PI_2)),
+    ]
+}
+
+/// Replaces the walls whenever the generator config changes, the border might have moved
+fn spawn_border_walls(
+    mut commands: Commands,
+    config: Res<WorldGeneratorConfig>,
+    walls: Query<Entity, With<BorderWall>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    for entity in walls.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let Some(border) = config.world_border else {
+        return;
+    };
+
+    let width = (2 * border as usize * CHUNK_SIZE) as f32;
+    let mesh = meshes.add(Mesh::from(shape::Quad::new(Vec2::new(width, BORDER_WALL_HEIGHT))));
+    let material = materials.add(StandardMaterial {
+        base_color: BORDER_WALL_COLOR,
+        alpha_mode: AlphaMode::Blend,
+        unlit: true,
+        // Seen from outside of the world as well
+        cull_mode: None,
+        double_sided: true,
+        ..Default::default()
+    });
+    for (index, transform) in border_wall_transforms(border, 0.0).into_iter().enumerate() {
+        commands.spawn((
+            PbrBundle { mesh: mesh.clone(), material: material.clone(), transform, ..Default::default() },
+            NotShadowCaster,
+            BorderWall(index),
+        ));
+    }
+}
+
+/// Keeps the walls centered on the camera height, so they never end above or below it
+fn move_border_walls(
+    config: Res<WorldGeneratorConfig>,
+    camera: Query<&Transform, (With<PlayerCamera>, Without<BorderWall>)>,
+    mut walls: Query<(&BorderWall, &mut Transform)>,
+) {
+    let (Some(border), Ok(camera)) = (config.world_border, camera.get_single()) else {
+        return;
+    };
+    let transforms = border_wall_transforms(border, camera.translation.y);
+    for (wall, mut transform) in walls.iter_mut() {
+        if *transform != transforms[wall.0] {
+            *transform = transforms[wall.0];
+        }
+    }
+}
+
+/// Detects cameras below the world bottom and applies the configured [`VoidBehavior`]
+pub fn handle_void_fall(
+    mut cameras: Query<(Entity, &mut Transform), With<Camera>>,
+    mut heightmap: ResMut<HeightmapCache>,
+    mut events: EventWriter<FellIntoVoid>,
+    mut in_void: Local<Vec<Entity>>,
+    void_config: Res<VoidConfig>,
+    worldgen_config: Res<WorldGeneratorConfig>,
+) {
+    let Some(bottom) = worldgen_config.world_bottom else {
+        return;
+    };
+    let limit = bottom as f32 - void_config.margin;
+
+    for (entity, mut transform) in cameras.iter_mut() {
+        if transform.translation.y >= limit {
+            in_void.retain(|e| *e != entity);
+            continue;
+        }
+
+        // Only report each fall once, `Nothing` would otherwise send an event every frame
+        if !in_void.contains(&entity) {
+            events.send(FellIntoVoid {
+                entity,
+                position: transform.translation,
+                behavior: void_config.behavior,
+            });
+        }
+
+        match void_config.behavior {
+            VoidBehavior::TeleportToSurface => {
+                let (x, z) = (transform.translation.x.floor() as i64, transform.translation.z.floor() as i64);
+                // Without a known surface fall back to just above the world bottom
+                let surface = heightmap.surface_height(&worldgen_config, x, z).unwrap_or(bottom as i64);
+                transform.translation.y = surface as f32 + 1.0 + void_config.surface_offset;
+            }
+            VoidBehavior::ClampToBottom => {
+                transform.translation.y = limit;
+                in_void.push(entity);
+            }
+            VoidBehavior::Nothing => {
+                in_void.push(entity);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_border_walls_face_into_the_world() {
+        for transform in border_wall_transforms(2, 10.0) {
+            assert_eq!(transform.translation.y, 10.0);
+            // Each wall is half the world width from the origin and a quad faces +z before rotating
+            let horizontal = transform.translation * Vec3::new(1.0, 0.0, 1.0);
+            let inwards = -horizontal.normalize();
+            assert!((horizontal.length() - 32.0).abs() < 1e-4);
+            assert!((transform.rotation * Vec3::Z).dot(inwards) > 0.999);
+        }
+    }
+}
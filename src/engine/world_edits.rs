@@ -0,0 +1,239 @@
+//! Voxel writes queued by gameplay systems during the frame. [`apply_world_edits`] writes all of
+//! them in `PostUpdate`, and remeshes every changed chunk once, however many of its voxels changed.
+//!
+//! Loose writes ([`WorldEdits::set`], [`WorldEdits::fill`]) are checked one by one: a refused
+//! write is skipped and a write into a chunk that is not loaded waits in the pending edits until
+//! it is. An [`EditTransaction`] is committed as a whole or not at all, see [`WorldEdits::commit`].
+
+use bevy::{prelude::*, utils::HashSet};
+
+use super::{
+    autosave::DirtyChunks,
+    block_registry::BlockRegistry,
+    chunk::Chunk,
+    coords::WorldVoxelPos,
+    edit::{check_edit, BrushShape, EditError},
+    generator::{has_translucent_border, request_remesh},
+    voxel::Voxel,
+    ChunkData,
+};
+
+/// Writes that are applied together or not at all
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EditTransaction {
+    edits: Vec<(WorldVoxelPos, Voxel)>,
+}
+
+impl EditTransaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, pos: WorldVoxelPos, voxel: Voxel) -> &mut Self {
+        self.edits.push((pos, voxel));
+        self
+    }
+
+    pub fn fill(&mut self, shape: &BrushShape, voxel: Voxel) -> &mut Self {
+        self.edits.extend(shape.positions().map(|pos| (pos, voxel)));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.edits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+}
+
+/// A transaction that was not applied
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EditRejected(pub EditError);
+
+#[derive(Debug, Clone)]
+struct EditBatch {
+    edits: Vec<(WorldVoxelPos, Voxel)>,
+    /// Committed as a transaction
+    atomic: bool,
+}
+
+/// Voxel writes waiting for [`apply_world_edits`], in the order they were queued
+#[derive(Resource, Debug, Default)]
+pub struct WorldEdits {
+    batches: Vec<EditBatch>,
+}
+
+impl WorldEdits {
+    pub fn set(&mut self, pos: WorldVoxelPos, voxel: Voxel) {
+        self.loose().push((pos, voxel));
+    }
+
+    /// Sets every voxel inside the brush, returns the number of voxels queued
+    pub fn fill(&mut self, shape: &BrushShape, voxel: Voxel) -> usize {
+        let edits = self.loose();
+        let before = edits.len();
+        edits.extend(shape.positions().map(|pos| (pos, voxel)));
+        edits.len() - before
+    }
+
+    /// Queues a transaction. It is only applied if every chunk it writes to is loaded and no
+    /// write is refused, otherwise nothing is written and an [`EditRejected`] is sent.
+    pub fn commit(&mut self, transaction: EditTransaction) {
+        if !transaction.is_empty() {
+            self.batches.push(EditBatch { edits: transaction.edits, atomic: true });
+        }
+    }
+
+    /// Number of voxel writes queued
+    pub fn len(&self) -> usize {
+        self.batches.iter().map(|batch| batch.edits.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.batches.is_empty()
+    }
+
+    /// Loose writes go into the last batch unless a transaction was committed after it
+    fn loose(&mut self) -> &mut Vec<(WorldVoxelPos, Voxel)> {
+        if self.batches.last().map_or(true, |batch| batch.atomic) {
+            self.batches.push(EditBatch { edits: Vec::new(), atomic: false });
+        }
+        &mut self.batches.last_mut().unwrap().edits
+    }
+}
+
+/// Checks a transaction against the loaded chunks
+fn check_transaction(edits: &[(WorldVoxelPos, Voxel)], chunk_data: &ChunkData, chunks: &Query<&mut Chunk>, blocks: &BlockRegistry) -> Result<(), EditError> {
+    for (pos, voxel) in edits.iter() {
+        let (chunk_pos, local) = pos.split();
+        let chunk = chunk_data.loaded.get(&chunk_pos).and_then(|entity| chunks.get(*entity).ok());
+        let Some(chunk) = chunk else {
+            return Err(EditError::NotLoaded(*pos));
+        };
+        check_edit(blocks, *pos, chunk.get(local), *voxel)?;
+    }
+    Ok(())
+}
+
+/// Writes every queued edit, then remeshes each chunk that changed once
+pub fn apply_world_edits(
+    mut commands: Commands,
+    mut edits: ResMut<WorldEdits>,
+    mut chunk_data: ResMut<ChunkData>,
+    mut chunks: Query<&mut Chunk>,
+    mut dirty_chunks: ResMut<DirtyChunks>,
+    mut rejected: EventWriter<EditRejected>,
+    blocks: Res<BlockRegistry>,
+) {
+    if edits.is_empty() {
+        return;
+    }
+
+    let mut changed = HashSet::new();
+    let mut changed_borders = HashSet::new();
+    for batch in std::mem::take(&mut edits.batches) {
+        if batch.atomic {
+            if let Err(err) = check_transaction(&batch.edits, &chunk_data, &chunks, &blocks) {
+                rejected.send(EditRejected(err));
+                continue;
+            }
+        }
+        for (pos, voxel) in batch.edits {
+            let (chunk_pos, local) = pos.split();
+            let chunk = chunk_data.loaded.get(&chunk_pos).and_then(|entity| chunks.get_mut(*entity).ok());
+            // The chunk component might not be inserted yet, its pending edits are applied once it is
+            let Some(mut chunk) = chunk else {
+                chunk_data.pending_edits.push(pos, voxel);
+                continue;
+            };
+            let existing = chunk.get(local);
+            // A transaction was checked as a whole, its own writes may replace each other
+            if existing == voxel || (!batch.atomic && check_edit(&blocks, pos, existing, voxel).is_err()) {
+                continue;
+            }
+            chunk.set(local, voxel);
+            changed.insert(chunk_pos);
+            changed_borders.extend(local.border_faces().map(|face| (chunk_pos, face)));
+        }
+    }
+
+    // Water next door was meshed against the voxels on the border
+    let mut borders = HashSet::new();
+    for (chunk_pos, face) in changed_borders {
+        let neighbour = chunk_pos.neighbors()[face.as_face_number()].0;
+        let Some(&entity) = chunk_data.loaded.get(&neighbour) else {
+            continue;
+        };
+        if !changed.contains(&neighbour) && chunks.get(entity).is_ok_and(|chunk| has_translucent_border(chunk, face.opposite(), &blocks)) {
+            borders.insert((neighbour, entity));
+        }
+    }
+
+    for chunk_pos in changed {
+        dirty_chunks.mark(chunk_pos);
+        let entity = chunk_data.loaded[&chunk_pos];
+        if let Ok(mut chunk) = chunks.get_mut(entity) {
+            chunk.recalculate_visibility_mask(&blocks);
+        }
+        request_remesh(&mut commands, &mut chunk_data, entity, chunk_pos);
+    }
+    for (chunk_pos, entity) in borders {
+        request_remesh(&mut commands, &mut chunk_data, entity, chunk_pos);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::engine::{chunk::ChunkPosition, coords::LocalVoxelPos, generator::NeedsMesh, voxel::Block};
+
+    #[test]
+    fn test_edits_apply_together() {
+        let mut world = World::new();
+        world.init_resource::<WorldEdits>();
+        world.init_resource::<DirtyChunks>();
+        world.init_resource::<BlockRegistry>();
+        world.init_resource::<Events<EditRejected>>();
+        let origin = ChunkPosition::new(0, 0, 0);
+        let mut bedrock = Chunk::new(origin);
+        bedrock.set(LocalVoxelPos::new(0, 0, 0), Block::Bedrock.into());
+        let entity = world.spawn((bedrock, Handle::<Mesh>::default())).id();
+        let mut chunk_data = ChunkData::default();
+        chunk_data.loaded.insert(origin, entity);
+        chunk_data.meshes.insert(origin, Handle::default());
+        world.insert_resource(chunk_data);
+
+        let mut edits = world.resource_mut::<WorldEdits>();
+        edits.set(WorldVoxelPos::new(1, 1, 1), Block::Stone.into());
+        // Refused on its own, the rest of the loose writes still go through
+        edits.set(WorldVoxelPos::new(0, 0, 0), Voxel::Empty);
+        assert_eq!(edits.fill(&BrushShape::cube(WorldVoxelPos::new(4, 4, 4), 1), Block::Dirt.into()), 27);
+        // Outside of the loaded chunk, waits for it
+        edits.set(WorldVoxelPos::new(-1, 0, 0), Block::Stone.into());
+        let mut transaction = EditTransaction::new();
+        transaction.set(WorldVoxelPos::new(2, 2, 2), Block::Stone.into()).set(WorldVoxelPos::new(0, 0, 0), Voxel::Empty);
+        edits.commit(transaction);
+        world.run_system_once(apply_world_edits);
+
+        let chunk = world.get::<Chunk>(entity).unwrap();
+        assert_eq!(chunk.get(LocalVoxelPos::new(1, 1, 1)), Block::Stone.into());
+        assert_eq!(chunk.get(LocalVoxelPos::new(4, 4, 4)), Block::Dirt.into());
+        assert_eq!(chunk.get(LocalVoxelPos::new(0, 0, 0)), Block::Bedrock.into());
+        // Nothing of the rejected transaction was written
+        assert_eq!(chunk.get(LocalVoxelPos::new(2, 2, 2)), Voxel::Empty);
+        let rejected = world.resource::<Events<EditRejected>>().iter_current_update_events().copied().collect::<Vec<_>>();
+        assert_eq!(rejected, vec![EditRejected(EditError::Unbreakable(WorldVoxelPos::new(0, 0, 0)))]);
+
+        assert!(world.get::<Handle<Mesh>>(entity).is_none());
+        assert!(world.get::<NeedsMesh>(entity).is_some());
+        let chunk_data = world.resource::<ChunkData>();
+        assert!(chunk_data.meshes.is_empty());
+        assert!(chunk_data.pending_edits.contains(&ChunkPosition::new(-1, 0, 0)));
+        assert!(world.resource::<WorldEdits>().is_empty());
+        assert!(world.resource::<DirtyChunks>().is_dirty(&origin));
+    }
+}
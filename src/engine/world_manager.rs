@@ -0,0 +1,429 @@
+//! Saved worlds, each one lives in its own directory under [`SAVES_DIR`] with its
+//! chunks, a `world.ron` describing it (see [`WorldMetadata`]), the palette of its blocks
+//! (see [`BlockRegistry::for_world`]) and the edits waiting for chunks that were not generated
+//! yet (see [`PendingEdits::load`]).
+//!
+//! [`OpenWorld`] switches the running app to another world, the current one is saved first.
+
+use std::{
+    fs,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use bevy::prelude::*;
+
+use super::{
+    autosave::DirtyChunks,
+    block_registry::BlockRegistry,
+    cache::ChunkCache,
+    chunk::Chunk,
+    generator::{AwaitingGeneration, ChunkGenerationTask, ChunkSource, GeneratorState, MeshingTask, PerlinHeightmapWorldGenerator, WorldGeneratorConfig},
+    heightmap::HeightmapCache,
+    loading::AppState,
+    migration::MigrationRegistry,
+    pending_edits::PendingEdits,
+    persistence::{AwaitingLoad, ChunkStorage},
+    shutdown::save_world,
+    spawn_queue::ChunkSpawnQueue,
+    super_chunk::{SuperChunkMesh, SuperChunks},
+    world_meta::{WorldMetadata, WORLD_META_FILE},
+    ChunkData,
+};
+use crate::{flycam::PlayerCamera, gameplay::spawn::PendingSpawn};
+
+pub const SAVES_DIR: &str = "saves";
+/// World opened when there are no saves yet
+pub const DEFAULT_WORLD: &str = "default";
+
+/// A world found on disk
+#[derive(Debug, Clone)]
+pub struct WorldInfo {
+    /// Directory name inside the saves directory, this is what identifies the world
+    pub dir: String,
+    pub metadata: WorldMetadata,
+}
+
+/// Everything needed to start playing a world
+pub struct OpenedWorld {
+    pub storage: ChunkStorage,
+    pub metadata: WorldMetadata,
+    pub config: WorldGeneratorConfig,
+    /// The blocks with the ids the world saved them with
+    pub blocks: BlockRegistry,
+    /// Edits for chunks that were not generated when the world was saved
+    pub pending_edits: PendingEdits,
+}
+
+#[derive(Resource)]
+pub struct WorldManager {
+    root: PathBuf,
+    migrations: Arc<MigrationRegistry>,
+    /// Blocks of the blocks file, every world gets them with its own ids
+    blocks: BlockRegistry,
+    /// Directory of the world that is currently open
+    current: Option<String>,
+}
+
+impl WorldManager {
+    pub fn new(root: impl Into<PathBuf>, migrations: Arc<MigrationRegistry>, blocks: BlockRegistry) -> Self {
+        Self { root: root.into(), migrations, blocks, current: None }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn current(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    /// Every saved world, most recently played first
+    pub fn list(&self) -> io::Result<Vec<WorldInfo>> {
+        let entries = match fs::read_dir(&self.root) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut worlds = Vec::new();
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            if !entry.path().is_dir() {
+                continue;
+            }
+            let Some(dir) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            match self.read_metadata(&dir) {
+                Ok(Some(metadata)) => worlds.push(WorldInfo { dir, metadata }),
+                Ok(None) => {}
+                Err(err) => warn!("Skipping world {}: {}", dir, err),
+            }
+        }
+        worlds.sort_by(|a, b| b.metadata.last_saved.cmp(&a.metadata.last_saved).then_with(|| a.dir.cmp(&b.dir)));
+        Ok(worlds)
+    }
+
+    /// Creates a new world and returns its directory name. The generator name is checked
+    /// with [`WorldGeneratorConfig::from_generator_name`].
+    pub fn create(&mut self, name: &str, generator: &str, seed: u32) -> io::Result<String> {
+        WorldGeneratorConfig::from_generator_name(generator, seed, &self.blocks)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+        let base = dir_name(name);
+        let mut dir = base.clone();
+        let mut suffix = 2;
+        while self.root.join(&dir).exists() {
+            dir = format!("{}-{}", base, suffix);
+            suffix += 1;
+        }
+
+        let path = self.root.join(&dir);
+        fs::create_dir_all(&path)?;
+        WorldMetadata::new(name, generator, seed).save(&path)?;
+        Ok(dir)
+    }
+
+    /// Opens a world for playing, it becomes the current world
+    pub fn open(&mut self, dir: &str) -> io::Result<OpenedWorld> {
+        let metadata = self
+            .read_metadata(dir)?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no world named {}", dir)))?;
+        let blocks = self.blocks.for_world(&self.root.join(dir))?;
+        let config = metadata.generator_config(&blocks).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let storage = ChunkStorage::open_with(self.root.join(dir), self.migrations.clone(), blocks.clone())?;
+        // Losing the edits only cuts off structures, the world can still be played
+        let pending_edits = PendingEdits::load(&self.root.join(dir), &blocks).unwrap_or_else(|err| {
+            warn!("Dropping the pending edits of world {}: {}", dir, err);
+            PendingEdits::default()
+        });
+        self.current = Some(dir.to_string());
+        Ok(OpenedWorld { storage, metadata, config, blocks, pending_edits })
+    }
+
+    /// Opens the most recently played world that can be opened, creating the default one if none can
+    pub fn open_last_played(&mut self) -> io::Result<OpenedWorld> {
+        let worlds = self.list().unwrap_or_else(|err| {
+            warn!("Failed to list worlds: {}", err);
+            Vec::new()
+        });
+        for world in worlds {
+            match self.open(&world.dir) {
+                Ok(opened) => return Ok(opened),
+                Err(err) => warn!("Skipping world {}: {}", world.dir, err),
+            }
+        }
+        let dir = self.create(DEFAULT_WORLD, "perlin", PerlinHeightmapWorldGenerator::default().seed)?;
+        self.open(&dir)
+    }
+
+    /// Deletes a world with all of its chunks. The current world can not be deleted.
+    pub fn delete(&mut self, dir: &str) -> io::Result<()> {
+        if self.current.as_deref() == Some(dir) {
+            return Err(io::Error::new(io::ErrorKind::Other, "can not delete the world that is currently open"));
+        }
+        let path = self.root.join(dir);
+        if !path.join(WORLD_META_FILE).exists() && !path.join("chunks").exists() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("no world named {}", dir)));
+        }
+        fs::remove_dir_all(path)
+    }
+
+    /// Directories with chunks but without `world.ron` were saved before metadata existed,
+    /// those were always generated with the default perlin generator
+    fn read_metadata(&self, dir: &str) -> io::Result<Option<WorldMetadata>> {
+        let path = self.root.join(dir);
+        if let Some(metadata) = WorldMetadata::load(&path, &self.migrations)? {
+            return Ok(Some(metadata));
+        }
+        if path.join("chunks").is_dir() {
+            return Ok(Some(WorldMetadata::new(dir, "perlin", PerlinHeightmapWorldGenerator::default().seed)));
+        }
+        Ok(None)
+    }
+}
+
+/// Turns a display name into a directory name that is safe on every platform
+fn dir_name(name: &str) -> String {
+    let dir = name
+        .trim()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c.to_ascii_lowercase() } else { '_' })
+        .collect::<String>();
+    if dir.is_empty() { "world".to_string() } else { dir }
+}
+
+/// Saves the current world and switches to another one
+#[derive(Event, Debug, Clone)]
+pub struct OpenWorld {
+    pub dir: String,
+}
+
+pub struct WorldManagerPlugin;
+
+impl Plugin for WorldManagerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<OpenWorld>()
+            .add_systems(PreUpdate, switch_world.run_if(on_event::<OpenWorld>()));
+
+        #[cfg(feature = "debug-ui")]
+        app.add_systems(Update, show_worlds_debug_window);
+    }
+}
+
+fn switch_world(world: &mut World) {
+    let Some(event) = world.resource_mut::<Events<OpenWorld>>().drain().last() else {
+        return;
+    };
+    if *world.resource::<ChunkSource>() == ChunkSource::Remote {
+        warn!("Can not open world {} while connected to a server", event.dir);
+        return;
+    }
+    if world.resource::<WorldManager>().current() == Some(event.dir.as_str()) {
+        return;
+    }
+
+    let opened = match world.resource_mut::<WorldManager>().open(&event.dir) {
+        Ok(opened) => opened,
+        Err(err) => {
+            error!("Failed to open world {}: {}", event.dir, err);
+            return;
+        }
+    };
+    info!("Opening world {}", opened.metadata.name);
+    save_world(world);
+
+    unload_all_chunks(world);
+
+    let config = opened.config.with_view_settings_of(world.resource::<WorldGeneratorConfig>());
+
+    // Replacing the storage drops the old one, which blocks until its chunks are written
+    world.insert_resource(opened.storage);
+    world.insert_resource(opened.blocks);
+    world.insert_resource(config);
+    world.insert_resource(ChunkData { pending_edits: opened.pending_edits, ..Default::default() });
+    world.insert_resource(GeneratorState::Generating);
+
+    let position = Vec3::from_array(opened.metadata.player_position);
+    // Worlds that were never played have no position saved, they start on the surface of their spawn column
+    let [x, z] = opened.metadata.spawn;
+    match opened.metadata.playtime == 0.0 {
+        true => world.insert_resource(PendingSpawn::at(x, z)),
+        false => drop(world.remove_resource::<PendingSpawn>()),
+    }
+    world.insert_resource(opened.metadata);
+    let mut cameras = world.query_filtered::<&mut Transform, With<PlayerCamera>>();
+    for mut transform in cameras.iter_mut(world) {
+        transform.translation = position;
+    }
+    world.resource_mut::<NextState<AppState>>().set(AppState::Loading);
+}
+
+/// Despawns every chunk entity and forgets every loaded chunk and mesh without saving them,
+/// call [`save_world`] first to keep them. Pins and pending edits are kept.
+pub fn unload_all_chunks(world: &mut World) {
+    let chunk_entities = world
+        .query_filtered::<Entity, Or<(With<Chunk>, With<AwaitingGeneration>, With<AwaitingLoad>, With<ChunkGenerationTask>, With<MeshingTask>, With<SuperChunkMesh>)>>()
+        .iter(world)
+        .collect::<Vec<_>>();
+    for entity in chunk_entities {
+        despawn_with_children_recursive(world, entity);
+    }
+
+    let mut chunk_data = world.resource_mut::<ChunkData>();
+    chunk_data.loaded.clear();
+    chunk_data.awaiting_generation.clear();
+    chunk_data.meshes.clear();
+    chunk_data.visible.clear();
+
+    let cache_capacity = world.resource::<ChunkCache>().capacity_mb();
+    world.insert_resource(ChunkCache::with_capacity_mb(cache_capacity));
+    world.insert_resource(SuperChunks::default());
+    if let Some(mut dirty_chunks) = world.get_resource_mut::<DirtyChunks>() {
+        dirty_chunks.clear();
+    }
+    if let Some(mut spawn_queue) = world.get_resource_mut::<ChunkSpawnQueue>() {
+        spawn_queue.clear();
+    }
+    world.resource_mut::<HeightmapCache>().clear();
+}
+
+#[cfg(feature = "debug-ui")]
+#[derive(Default)]
+struct NewWorldForm {
+    name: String,
+    generator: String,
+    seed: u32,
+    error: Option<String>,
+}
+
+/// Every saved world while playing, the main menu only continues the last played one or creates a new one
+#[cfg(feature = "debug-ui")]
+fn show_worlds_debug_window(
+    mut contexts: bevy_egui::EguiContexts,
+    mut manager: ResMut<WorldManager>,
+    mut open_world: EventWriter<OpenWorld>,
+    mut form: Local<Option<NewWorldForm>>,
+) {
+    use bevy_egui::egui;
+
+    let form = form.get_or_insert_with(|| NewWorldForm {
+        name: "New World".to_string(),
+        generator: "perlin".to_string(),
+        seed: PerlinHeightmapWorldGenerator::default().seed,
+        error: None,
+    });
+
+    egui::Window::new("Worlds").default_open(false).show(contexts.ctx_mut(), |ui| {
+        let worlds = match manager.list() {
+            Ok(worlds) => worlds,
+            Err(err) => {
+                ui.label(format!("Failed to list worlds: {}", err));
+                Vec::new()
+            }
+        };
+
+        let mut delete = None;
+        egui::Grid::new("worlds").striped(true).show(ui, |ui| {
+            for world in worlds.iter() {
+                let current = manager.current() == Some(world.dir.as_str());
+                ui.label(if current { format!("{} (open)", world.metadata.name) } else { world.metadata.name.clone() });
+                ui.label(world.metadata.summary());
+                ui.add_enabled_ui(!current, |ui| {
+                    if ui.button("Open").clicked() {
+                        open_world.send(OpenWorld { dir: world.dir.clone() });
+                    }
+                    if ui.button("Delete").clicked() {
+                        delete = Some(world.dir.clone());
+                    }
+                });
+                ui.end_row();
+            }
+        });
+        if let Some(dir) = delete {
+            if let Err(err) = manager.delete(&dir) {
+                error!("Failed to delete world {}: {}", dir, err);
+            }
+        }
+
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.label("Name");
+            ui.text_edit_singleline(&mut form.name);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Generator");
+            ui.text_edit_singleline(&mut form.generator);
+        });
+        ui.add(egui::DragValue::new(&mut form.seed).prefix("Seed: "));
+        if ui.button("Create").clicked() {
+            match manager.create(&form.name, &form.generator, form.seed) {
+                Ok(dir) => {
+                    form.error = None;
+                    open_world.send(OpenWorld { dir });
+                }
+                Err(err) => form.error = Some(err.to_string()),
+            }
+        }
+        if let Some(error) = &form.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn test_create_list_delete() {
+        let root = TempDir::new("world-manager");
+        let mut manager = WorldManager::new(root.path(), Arc::new(MigrationRegistry::builtin()), BlockRegistry::builtin());
+
+        let first = manager.create("My World", "flat", 1).unwrap();
+        let second = manager.create("My World", "perlin", 2).unwrap();
+        assert_eq!(first, "my_world");
+        assert_eq!(second, "my_world-2");
+        assert!(manager.create("Broken", "nope", 0).is_err());
+
+        let worlds = manager.list().unwrap();
+        assert_eq!(worlds.len(), 2);
+        assert!(worlds.iter().any(|world| world.dir == second && world.metadata.seed == 2));
+
+        let opened = manager.open(&first).unwrap();
+        assert_eq!(opened.metadata.generator, "flat");
+        assert!(manager.delete(&first).is_err());
+        drop(opened);
+
+        manager.delete(&second).unwrap();
+        assert_eq!(manager.list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_worlds_that_fail_to_open_are_skipped() {
+        // Played last, by a build with a generator this one does not know
+        let save_newer_world = |root: &Path| {
+            let mut metadata = WorldMetadata::new("Newer", "islands", 2);
+            metadata.last_saved += 60;
+            fs::create_dir_all(root.join("newer")).unwrap();
+            metadata.save(&root.join("newer")).unwrap();
+        };
+
+        let root = TempDir::new("world-manager-skip");
+        let mut manager = WorldManager::new(root.path(), Arc::new(MigrationRegistry::builtin()), BlockRegistry::builtin());
+        manager.create("Playable", "flat", 1).unwrap();
+        save_newer_world(&root);
+        assert_eq!(manager.list().unwrap()[0].dir, "newer");
+        assert_eq!(manager.open_last_played().unwrap().metadata.name, "Playable");
+
+        // Nothing else to open, a new world is created
+        let root = TempDir::new("world-manager-skip");
+        let mut manager = WorldManager::new(root.path(), Arc::new(MigrationRegistry::builtin()), BlockRegistry::builtin());
+        save_newer_world(&root);
+        assert_eq!(manager.open_last_played().unwrap().metadata.name, DEFAULT_WORLD);
+        assert_eq!(manager.list().unwrap().len(), 2);
+    }
+}
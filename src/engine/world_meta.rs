@@ -0,0 +1,139 @@
+use std::{fs, io, path::Path, time::{SystemTime, UNIX_EPOCH}};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{block_registry::BlockRegistry, generator::WorldGeneratorConfig, migration::MigrationRegistry};
+
+pub const WORLD_META_FILE: &str = "world.ron";
+/// Bump when the fields change and register a migration, see [`MigrationRegistry`]
+pub const FORMAT_VERSION: u32 = 3;
+
+/// Information about a saved world, stored next to its chunks in `world.ron`. Everything the
+/// generator needs is in here, so chunks that were never saved come out the same whenever they
+/// are generated, see [`WorldMetadata::generator_config`].
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorldMetadata {
+    pub format_version: u32,
+    pub name: String,
+    pub seed: u32,
+    /// Generator name, see [`WorldGeneratorConfig::from_generator_name`]
+    ///
+    /// [`WorldGeneratorConfig::from_generator_name`]: super::generator::WorldGeneratorConfig::from_generator_name
+    pub generator: String,
+    /// Lowest y level of the world, see [`WorldGeneratorConfig::world_bottom`]
+    pub world_bottom: Option<i32>,
+    /// See [`WorldGeneratorConfig::world_border`]
+    pub world_border: Option<u32>,
+    /// Column the camera is put on the surface of when the world starts
+    pub spawn: [i64; 2],
+    /// Seconds spent playing the world
+    pub playtime: f64,
+    /// Unix timestamps in seconds
+    pub created: u64,
+    pub last_saved: u64,
+    /// Camera position when the world was last saved
+    pub player_position: [f32; 3],
+}
+
+impl WorldMetadata {
+    pub fn new(name: impl Into<String>, generator: impl Into<String>, seed: u32) -> Self {
+        let now = unix_time();
+        Self {
+            format_version: FORMAT_VERSION,
+            name: name.into(),
+            seed,
+            generator: generator.into(),
+            world_bottom: Some(-64),
+            world_border: None,
+            spawn: [0, 0],
+            playtime: 0.0,
+            created: now,
+            last_saved: now,
+            player_position: [0.0; 3],
+        }
+    }
+
+    /// Reads the metadata of the world stored in `root`, upgrading it if it was written by an older version.
+    /// `None` if there is none yet.
+    pub fn load(root: &Path, migrations: &MigrationRegistry) -> io::Result<Option<Self>> {
+        let text = match fs::read_to_string(root.join(WORLD_META_FILE)) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let invalid_data = |err: String| io::Error::new(io::ErrorKind::InvalidData, err);
+        let value = ron::from_str::<ron::Value>(&text).map_err(|err| invalid_data(err.to_string()))?;
+        let value = migrations.migrate_metadata(value).map_err(|err| invalid_data(err.to_string()))?;
+        value.into_rust().map(Some).map_err(|err| invalid_data(err.to_string()))
+    }
+
+    pub fn save(&self, root: &Path) -> io::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let path = root.join(WORLD_META_FILE);
+        let tmp = path.with_extension("ron.tmp");
+        fs::write(&tmp, text)?;
+        fs::rename(tmp, path)
+    }
+
+    /// The generator config the world is generated with, view settings are left at their defaults.
+    /// `blocks` should have the ids of the world, see [`BlockRegistry::for_world`]
+    pub fn generator_config(&self, blocks: &BlockRegistry) -> Result<WorldGeneratorConfig, String> {
+        let config = WorldGeneratorConfig::from_generator_name(&self.generator, self.seed, blocks)?;
+        Ok(WorldGeneratorConfig { world_bottom: self.world_bottom, world_border: self.world_border, ..config })
+    }
+
+    /// Updates the fields that change while playing, call before saving
+    pub fn touch(&mut self, player_position: Vec3, config: &WorldGeneratorConfig) {
+        self.last_saved = unix_time();
+        self.player_position = player_position.to_array();
+        self.world_bottom = config.world_bottom;
+        self.world_border = config.world_border;
+    }
+
+    /// Generator, seed and playtime on one line for world lists
+    pub fn summary(&self) -> String {
+        format!("{} #{}, played {}", self.generator, self.seed, playtime_text(self.playtime))
+    }
+}
+
+/// Playtime as hours and minutes, like `3h 05m`
+pub fn playtime_text(seconds: f64) -> String {
+    let minutes = (seconds / 60.0) as u64;
+    format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
+
+/// Adds the frame time to the playtime of the open world
+pub fn count_playtime(time: Res<Time>, mut metadata: ResMut<WorldMetadata>) {
+    metadata.playtime += time.delta_seconds_f64();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generator_config_keeps_the_world_limits() {
+        let mut metadata = WorldMetadata::new("bordered", "flat", 3);
+        metadata.world_bottom = None;
+        metadata.world_border = Some(12);
+        let config = metadata.generator_config(&BlockRegistry::builtin()).unwrap();
+        assert_eq!((config.seed, config.world_bottom, config.world_border), (3, None, Some(12)));
+
+        metadata.generator = "marble".to_string();
+        assert!(metadata.generator_config(&BlockRegistry::builtin()).is_err());
+    }
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(playtime_text(3.0 * 3600.0 + 5.0 * 60.0 + 59.0), "3h 05m");
+        let mut metadata = WorldMetadata::new("island", "perlin+water", 7);
+        metadata.playtime = 90.0;
+        assert_eq!(metadata.summary(), "perlin+water #7, played 0h 01m");
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs())
+}
@@ -0,0 +1,165 @@
+//! Live terrain tuning. A [`WORLDGEN_FILE`] next to the chunks of the open world overrides its
+//! generator, and the file is checked for changes while playing: saving it applies the new
+//! parameters right away and everything around the camera is generated and meshed again.
+//!
+//! ```ron
+//! (
+//!     generator: "perlin=scale:80,height:40+layers=snow_line:32+caves",
+//!     world_bottom: Some(-64),
+//!     world_border: Some(32),
+//! )
+//! ```
+//!
+//! This is meant for designing terrain, chunks are dropped without saving them when the file
+//! changes. Chunks already saved to disk keep the terrain they were saved with.
+
+use std::{fs, io, path::{Path, PathBuf}, time::SystemTime};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    generator::{ChunkSource, WorldGeneratorConfig},
+    loading::AppState,
+    persistence::ChunkStorage,
+    world_manager::unload_all_chunks,
+    world_meta::WorldMetadata,
+};
+
+pub const WORLDGEN_FILE: &str = "worldgen.ron";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WorldgenParameters {
+    /// Generator name with its parameters and stages, see [`WorldGeneratorConfig::from_generator_name`]
+    pub generator: String,
+    /// The seed of the world when left out
+    #[serde(default)]
+    pub seed: Option<u32>,
+    /// Lowest y level of the world, `None` for a world without a bottom
+    #[serde(default = "default_world_bottom")]
+    pub world_bottom: Option<i32>,
+    /// Chunks around the origin that are generated on x and z, `None` for a world without a border
+    #[serde(default)]
+    pub world_border: Option<u32>,
+}
+
+fn default_world_bottom() -> Option<i32> {
+    Some(-64)
+}
+
+impl WorldgenParameters {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        ron::from_str(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// The generator config these parameters describe, with the view settings and blocks of `previous`
+    pub fn config(&self, seed: u32, previous: &WorldGeneratorConfig) -> Result<WorldGeneratorConfig, String> {
+        let config = WorldGeneratorConfig::from_generator_name(&self.generator, seed, &previous.blocks)?;
+        Ok(WorldGeneratorConfig { world_bottom: self.world_bottom, world_border: self.world_border, ..config }.with_view_settings_of(previous))
+    }
+}
+
+#[derive(Resource)]
+pub struct WorldgenWatcher {
+    /// How often the file is checked for changes
+    pub poll: Timer,
+    /// File of the open world, changes when another world is opened
+    path: Option<PathBuf>,
+    /// Modification time of the file when it was last read
+    modified: Option<SystemTime>,
+}
+
+impl Default for WorldgenWatcher {
+    fn default() -> Self {
+        Self { poll: Timer::from_seconds(1.0, TimerMode::Repeating), path: None, modified: None }
+    }
+}
+
+pub struct WorldgenFilePlugin;
+
+impl Plugin for WorldgenFilePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WorldgenWatcher>()
+            .add_systems(Update, watch_worldgen_file.run_if(in_state(AppState::Playing)));
+    }
+}
+
+/// Exclusive, applying new parameters unloads every chunk
+fn watch_worldgen_file(world: &mut World) {
+    let delta = world.resource::<Time>().delta();
+    let path = world.resource::<ChunkStorage>().root().join(WORLDGEN_FILE);
+    let mut watcher = world.resource_mut::<WorldgenWatcher>();
+    if !watcher.poll.tick(delta).just_finished() {
+        return;
+    }
+    if watcher.path.as_ref() != Some(&path) {
+        watcher.path = Some(path.clone());
+        watcher.modified = None;
+    }
+    // No file means the world keeps its own generator
+    let Ok(modified) = fs::metadata(&path).and_then(|metadata| metadata.modified()) else {
+        return;
+    };
+    if watcher.modified == Some(modified) {
+        return;
+    }
+    watcher.modified = Some(modified);
+
+    let parameters = match WorldgenParameters::load(&path) {
+        Ok(parameters) => parameters,
+        Err(err) => {
+            error!("Failed to read {}: {}", path.display(), err);
+            return;
+        }
+    };
+    if let Err(err) = apply_parameters(world, &parameters) {
+        error!("Failed to apply {}: {}", path.display(), err);
+    }
+}
+
+/// Switches the open world over to `parameters` and generates its chunks again, unless it already uses them
+pub fn apply_parameters(world: &mut World, parameters: &WorldgenParameters) -> Result<(), String> {
+    let metadata = world.resource::<WorldMetadata>();
+    let seed = parameters.seed.unwrap_or(metadata.seed);
+    let previous = world.resource::<WorldGeneratorConfig>();
+    let unchanged = metadata.generator == parameters.generator
+        && metadata.seed == seed
+        && previous.world_bottom == parameters.world_bottom
+        && previous.world_border == parameters.world_border;
+    if unchanged {
+        return Ok(());
+    }
+    if *world.resource::<ChunkSource>() == ChunkSource::Remote {
+        return Err("chunks come from the server".to_string());
+    }
+    let config = parameters.config(seed, previous)?;
+
+    unload_all_chunks(world);
+    world.insert_resource(config);
+    let mut metadata = world.resource_mut::<WorldMetadata>();
+    metadata.generator = parameters.generator.clone();
+    metadata.seed = seed;
+    info!("Regenerating with {}", parameters.generator);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parameters_keep_view_settings() {
+        let parameters: WorldgenParameters = ron::from_str(r#"(generator: "perlin=height:8+caves")"#).unwrap();
+        assert_eq!(parameters.seed, None);
+        assert_eq!(parameters.world_bottom, Some(-64));
+        assert_eq!(parameters.world_border, None);
+
+        let previous = WorldGeneratorConfig { render_distance: 5, ..WorldGeneratorConfig::default_flat() };
+        let config = parameters.config(7, &previous).unwrap();
+        assert_eq!((config.render_distance, config.seed, config.stages.len()), (5, 7, 1));
+
+        let parameters = WorldgenParameters { generator: "marble".to_string(), ..parameters };
+        assert!(parameters.config(7, &previous).is_err());
+    }
+}
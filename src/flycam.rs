@@ -3,6 +3,8 @@ use bevy::input::mouse::MouseMotion;
 use bevy::prelude::*;
 use bevy::window::{CursorGrabMode, PrimaryWindow};
 
+use crate::engine::anchor::StreamingAnchor;
+
 pub mod prelude {
     pub use crate::*;
 }
@@ -29,6 +31,18 @@ impl Default for MovementSettings {
     }
 }
 
+/// Whether UI windows have taken the mouse or the keyboard, the flycam ignores that input while they have it.
+///
+/// Filled from egui when it is in the app, other UI can set it as well
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputFocus {
+    /// The pointer is over a window or dragging something in one. Never set while the cursor is
+    /// grabbed, the hidden pointer passing over a window must not stop mouse look
+    pub pointer_over_ui: bool,
+    /// A text field has keyboard focus
+    pub keyboard_over_ui: bool,
+}
+
 /// Key configuration
 #[derive(Resource)]
 pub struct KeyBindings {
@@ -50,16 +64,21 @@ impl Default for KeyBindings {
             move_right: KeyCode::D,
             move_ascend: KeyCode::Space,
             move_descend: KeyCode::ShiftLeft,
-            toggle_grab_cursor: KeyCode::Escape,
+            // Escape pauses the game
+            toggle_grab_cursor: KeyCode::AltLeft,
         }
     }
 }
 
-/// Used in queries when you want flycams and not other cameras
 /// A marker component used in queries when you want flycams and not other cameras
 #[derive(Component)]
 pub struct FlyCam;
 
+/// The camera of the player, kept while [`FlyCam`] is taken away. Debug views like split screen
+/// add more cameras, query this one for where the player is.
+#[derive(Component)]
+pub struct PlayerCamera;
+
 /// Grabs/ungrabs mouse cursor
 fn toggle_grab_cursor(window: &mut Window) {
     match window.cursor.grab_mode {
@@ -83,7 +102,7 @@ fn initial_grab_cursor(mut primary_window: Query<&mut Window, With<PrimaryWindow
     }
 }
 
-/// Spawns the `Camera3dBundle` to be controlled
+/// Spawns the `Camera3dBundle` to be controlled, chunks are streamed around it
 fn setup_player(mut commands: Commands) {
     commands.spawn((
         Camera3dBundle {
@@ -91,6 +110,8 @@ fn setup_player(mut commands: Commands) {
             ..Default::default()
         },
         FlyCam,
+        PlayerCamera,
+        StreamingAnchor::default(),
     ));
 }
 
@@ -101,8 +122,12 @@ fn player_move(
     primary_window: Query<&Window, With<PrimaryWindow>>,
     settings: Res<MovementSettings>,
     key_bindings: Res<KeyBindings>,
+    focus: Res<InputFocus>,
     mut query: Query<(&FlyCam, &mut Transform)>, //    mut query: Query<&mut Transform, With<FlyCam>>,
 ) {
+    if focus.keyboard_over_ui {
+        return;
+    }
     if let Ok(window) = primary_window.get_single() {
         for (_camera, mut transform) in query.iter_mut() {
             let mut velocity = Vec3::ZERO;
@@ -154,6 +179,7 @@ fn player_look(
             for ev in state.reader_motion.read(&motion) {
                 let (mut yaw, mut pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
                 match window.cursor.grab_mode {
+                    // UI focus is ignored while the cursor is grabbed
                     CursorGrabMode::None => (),
                     _ => {
                         // Using smallest of height or width ensures equal vertical and horizontal sensitivity
@@ -178,10 +204,11 @@ fn player_look(
 fn cursor_grab(
     keys: Res<Input<KeyCode>>,
     key_bindings: Res<KeyBindings>,
+    focus: Res<InputFocus>,
     mut primary_window: Query<&mut Window, With<PrimaryWindow>>,
 ) {
     if let Ok(mut window) = primary_window.get_single_mut() {
-        if keys.just_pressed(key_bindings.toggle_grab_cursor) {
+        if keys.just_pressed(key_bindings.toggle_grab_cursor) && !focus.keyboard_over_ui {
             toggle_grab_cursor(&mut window);
         }
     } else {
@@ -189,6 +216,39 @@ fn cursor_grab(
     }
 }
 
+/// Takes the input focus from the egui context, which knows about the previous frame's windows
+#[cfg(feature = "debug-ui")]
+fn update_input_focus(
+    mut contexts: bevy_egui::EguiContexts,
+    mut focus: ResMut<InputFocus>,
+    primary_window: Query<&Window, With<PrimaryWindow>>,
+) {
+    let Some(ctx) = contexts.try_ctx_mut() else {
+        return;
+    };
+    let grabbed = primary_window.get_single().is_ok_and(|window| window.cursor.grab_mode != CursorGrabMode::None);
+    let new_focus = InputFocus {
+        pointer_over_ui: !grabbed && (ctx.wants_pointer_input() || ctx.is_pointer_over_area()),
+        keyboard_over_ui: ctx.wants_keyboard_input(),
+    };
+    if *focus != new_focus {
+        *focus = new_focus;
+    }
+}
+
+/// Adds the input focus and keeps it up to date when egui is in the app
+fn add_input_focus(app: &mut App) {
+    app.init_resource::<InputFocus>();
+
+    #[cfg(feature = "debug-ui")]
+    app.add_systems(
+        PreUpdate,
+        update_input_focus
+            .after(bevy_egui::EguiSet::ProcessInput)
+            .run_if(resource_exists::<bevy_egui::EguiSettings>()),
+    );
+}
+
 // Grab cursor when an entity with FlyCam is added
 fn initial_grab_on_flycam_spawn(
     mut primary_window: Query<&mut Window, With<PrimaryWindow>>,
@@ -209,6 +269,7 @@ fn initial_grab_on_flycam_spawn(
 pub struct PlayerPlugin;
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
+        add_input_focus(app);
         app.init_resource::<InputState>()
             .init_resource::<MovementSettings>()
             .init_resource::<KeyBindings>()
@@ -224,6 +285,7 @@ impl Plugin for PlayerPlugin {
 pub struct NoCameraPlayerPlugin;
 impl Plugin for NoCameraPlayerPlugin {
     fn build(&self, app: &mut App) {
+        add_input_focus(app);
         app.init_resource::<InputState>()
             .init_resource::<MovementSettings>()
             .init_resource::<KeyBindings>()
@@ -233,4 +295,38 @@ impl Plugin for NoCameraPlayerPlugin {
             .add_systems(Update, player_look)
             .add_systems(Update, cursor_grab);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grabbed_cursor_looks_around_over_ui() {
+        let mut app = App::new();
+        app.add_event::<MouseMotion>()
+            .init_resource::<InputState>()
+            .init_resource::<InputFocus>()
+            .init_resource::<MovementSettings>()
+            .add_systems(Update, player_look);
+        let mut window = Window::default();
+        window.cursor.grab_mode = CursorGrabMode::Confined;
+        let window = app.world.spawn((window, PrimaryWindow)).id();
+        let camera = app.world.spawn((Transform::default(), FlyCam)).id();
+        let turn = |app: &mut App| {
+            app.world.send_event(MouseMotion { delta: Vec2::new(100.0, 0.0) });
+            app.update();
+            app.world.get::<Transform>(camera).unwrap().rotation
+        };
+
+        // The hidden pointer passing over a window does not stop the camera
+        app.world.resource_mut::<InputFocus>().pointer_over_ui = true;
+        let rotation = turn(&mut app);
+        let (yaw, _, _) = rotation.to_euler(EulerRot::YXZ);
+        assert!((yaw.to_degrees() + 100.0 * MovementSettings::default().sensitivity * 720.0).abs() < 1e-3);
+
+        // A free cursor never turns it
+        app.world.get_mut::<Window>(window).unwrap().cursor.grab_mode = CursorGrabMode::None;
+        assert_eq!(turn(&mut app), rotation);
+    }
+}
@@ -0,0 +1,174 @@
+//! Waypoint beacons, vertical light beams drawn on top of the terrain
+//! to help finding places again in large generated worlds.
+
+use bevy::{
+    pbr::{wireframe::NoWireframe, MaterialPipeline, MaterialPipelineKey, NotShadowCaster},
+    prelude::*,
+    render::{
+        mesh::MeshVertexBufferLayout,
+        render_resource::{AsBindGroup, CompareFunction, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError},
+    },
+};
+
+/// Height of the beam above the beacon position
+const BEAM_HEIGHT: f32 = 256.0;
+const BEAM_RADIUS: f32 = 0.25;
+
+/// Unlit, translucent material ignoring the depth buffer so the beam is visible through terrain
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct BeamMaterial {
+    #[uniform(0)]
+    pub color: Color,
+}
+
+impl Material for BeamMaterial {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/beam.wgsl".into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Blend
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        descriptor.primitive.cull_mode = None;
+        if let Some(depth_stencil) = descriptor.depth_stencil.as_mut() {
+            depth_stencil.depth_compare = CompareFunction::Always;
+            depth_stencil.depth_write_enabled = false;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct Beacon {
+    pub name: String,
+    pub color: Color,
+}
+
+/// Places a beacon at the given position
+#[derive(Event, Debug, Clone)]
+pub struct PlaceBeacon {
+    pub name: String,
+    pub position: Vec3,
+    pub color: Color,
+}
+
+/// Shared beam mesh, scaled by the beacon transform
+#[derive(Resource)]
+struct BeamMesh(Handle<Mesh>);
+
+pub struct BeaconPlugin;
+
+impl Plugin for BeaconPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(MaterialPlugin::<BeamMaterial>::default())
+            .add_event::<PlaceBeacon>()
+            .add_systems(Startup, setup_beam_mesh)
+            .add_systems(Update, spawn_beacons);
+
+        #[cfg(feature = "debug-ui")]
+        app.add_systems(Update, show_beacons_debug_info);
+    }
+}
+
+fn setup_beam_mesh(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+    let mesh = Mesh::from(shape::Cylinder {
+        radius: BEAM_RADIUS,
+        height: BEAM_HEIGHT,
+        resolution: 8,
+        segments: 1,
+    });
+    commands.insert_resource(BeamMesh(meshes.add(mesh)));
+}
+
+fn spawn_beacons(
+    mut commands: Commands,
+    mut events: EventReader<PlaceBeacon>,
+    mut materials: ResMut<Assets<BeamMaterial>>,
+    beam_mesh: Res<BeamMesh>,
+) {
+    for event in events.read() {
+        // The cylinder is centered, move it up so it starts at the beacon position
+        let transform = Transform::from_translation(event.position + Vec3::Y * BEAM_HEIGHT / 2.0);
+        commands.spawn((
+            MaterialMeshBundle {
+                mesh: beam_mesh.0.clone(),
+                material: materials.add(BeamMaterial { color: event.color }),
+                transform,
+                ..Default::default()
+            },
+            Beacon {
+                name: event.name.clone(),
+                color: event.color,
+            },
+            Name::new(format!("Beacon {}", event.name)),
+            NotShadowCaster,
+            NoWireframe,
+        ));
+    }
+}
+
+impl Beacon {
+    /// World position the beacon was placed at
+    pub fn base(transform: &Transform) -> Vec3 {
+        transform.translation - Vec3::Y * BEAM_HEIGHT / 2.0
+    }
+}
+
+#[cfg(feature = "debug-ui")]
+fn show_beacons_debug_info(
+    mut commands: Commands,
+    mut contexts: bevy_egui::EguiContexts,
+    mut events: EventWriter<PlaceBeacon>,
+    mut next_name: Local<usize>,
+    beacons: Query<(Entity, &Beacon, &Transform), Without<crate::flycam::PlayerCamera>>,
+    mut camera: Query<&mut Transform, With<crate::flycam::PlayerCamera>>,
+) {
+    use bevy_egui::egui;
+    let Ok(mut camera_transform) = camera.get_single_mut() else {
+        return;
+    };
+
+    egui::Window::new("Beacons").show(contexts.ctx_mut(), |ui| {
+        if ui.button("Place at camera").clicked() {
+            // Cycle through a few easy to tell apart hues
+            let hue = (*next_name as f32 * 67.0) % 360.0;
+            events.send(PlaceBeacon {
+                name: format!("#{}", *next_name),
+                position: camera_transform.translation.floor(),
+                color: Color::hsla(hue, 0.9, 0.6, 0.6),
+            });
+            *next_name += 1;
+        }
+
+        ui.separator();
+        let mut beacons = beacons.iter().collect::<Vec<_>>();
+        beacons.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+        for (entity, beacon, transform) in beacons {
+            let base = Beacon::base(transform);
+            let [r, g, b, _] = beacon.color.as_rgba_u8();
+            ui.horizontal(|ui| {
+                ui.colored_label(egui::Color32::from_rgb(r, g, b), &beacon.name);
+                ui.label(format!(
+                    "({:.0}, {:.0}, {:.0}) {:.0}m",
+                    base.x,
+                    base.y,
+                    base.z,
+                    base.distance(camera_transform.translation),
+                ));
+                if ui.button("Go to").clicked() {
+                    camera_transform.translation = base + Vec3::Y * 2.0;
+                }
+                if ui.button("Remove").clicked() {
+                    commands.entity(entity).despawn_recursive();
+                }
+            });
+        }
+    });
+}
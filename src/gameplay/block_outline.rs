@@ -0,0 +1,121 @@
+//! Outline around the voxel the camera is looking at and a highlight on the face the picking ray
+//! hit, which is the side a placed block goes. The outline is drawn with gizmos, the highlight is
+//! a translucent quad moved onto the face every frame.
+
+use bevy::{pbr::NotShadowCaster, prelude::*};
+
+use crate::engine::raycast::RaycastHit;
+
+use super::selection::TargetedVoxel;
+
+/// The outline is slightly larger than the voxel so the terrain does not hide it
+const OUTLINE_SCALE: f32 = 1.004;
+/// Distance of the highlight from the face, enough to keep it out of the terrain's depth
+const HIGHLIGHT_OFFSET: f32 = 0.003;
+
+#[derive(Resource)]
+pub struct BlockOutline {
+    pub enabled: bool,
+    pub outline_color: Color,
+    pub highlight_color: Color,
+}
+
+impl Default for BlockOutline {
+    fn default() -> Self {
+        Self { enabled: true, outline_color: Color::BLACK, highlight_color: Color::rgba(1.0, 1.0, 1.0, 0.2) }
+    }
+}
+
+/// The quad drawn on the targeted face
+#[derive(Component)]
+struct FaceHighlight;
+
+pub struct BlockOutlinePlugin;
+
+impl Plugin for BlockOutlinePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BlockOutline>()
+            .add_systems(Startup, spawn_face_highlight)
+            .add_systems(Update, (draw_block_outline, update_face_highlight));
+    }
+}
+
+/// Transform of a unit quad facing +z that covers the face of the voxel the ray entered through,
+/// `None` if the ray started inside the voxel
+pub fn face_highlight_transform(hit: &RaycastHit) -> Option<Transform> {
+    if hit.normal == IVec3::ZERO {
+        return None;
+    }
+    let normal = hit.normal.as_vec3();
+    let center = hit.pos.as_vec3() + Vec3::splat(0.5) + normal * (0.5 + HIGHLIGHT_OFFSET);
+    Some(Transform::from_translation(center).with_rotation(Quat::from_rotation_arc(Vec3::Z, normal)))
+}
+
+fn spawn_face_highlight(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    outline: Res<BlockOutline>,
+) {
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::Quad::new(Vec2::ONE))),
+            material: materials.add(StandardMaterial {
+                base_color: outline.highlight_color,
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..Default::default()
+            }),
+            visibility: Visibility::Hidden,
+            ..Default::default()
+        },
+        NotShadowCaster,
+        FaceHighlight,
+    ));
+}
+
+fn draw_block_outline(mut gizmos: Gizmos, outline: Res<BlockOutline>, target: Res<TargetedVoxel>) {
+    let (true, Some(hit)) = (outline.enabled, target.0) else {
+        return;
+    };
+    let transform = Transform::from_translation(hit.pos.as_vec3() + Vec3::splat(0.5)).with_scale(Vec3::splat(OUTLINE_SCALE));
+    gizmos.cuboid(transform, outline.outline_color);
+}
+
+fn update_face_highlight(
+    outline: Res<BlockOutline>,
+    target: Res<TargetedVoxel>,
+    mut highlight: Query<(&mut Transform, &mut Visibility), With<FaceHighlight>>,
+) {
+    let Ok((mut transform, mut visibility)) = highlight.get_single_mut() else {
+        return;
+    };
+    let face = target.0.as_ref().filter(|_| outline.enabled).and_then(face_highlight_transform);
+    let new_visibility = if face.is_some() { Visibility::Inherited } else { Visibility::Hidden };
+    if *visibility != new_visibility {
+        *visibility = new_visibility;
+    }
+    if let Some(face) = face.filter(|face| *face != *transform) {
+        *transform = face;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::coords::WorldVoxelPos;
+
+    #[test]
+    fn test_highlight_covers_the_hit_face() {
+        let hit = |normal: IVec3| RaycastHit { pos: WorldVoxelPos::new(2, -3, 4), normal, distance: 1.0 };
+        assert!(face_highlight_transform(&hit(IVec3::ZERO)).is_none());
+
+        for normal in [IVec3::X, IVec3::NEG_Y, IVec3::Z, IVec3::NEG_Z] {
+            let transform = face_highlight_transform(&hit(normal)).unwrap();
+            // The quad faces away from the voxel, half a voxel from its center
+            assert!(transform.forward().dot(-normal.as_vec3()) > 0.999);
+            let from_center = transform.translation - Vec3::new(2.5, -2.5, 4.5);
+            assert!((from_center - normal.as_vec3() * (0.5 + HIGHLIGHT_OFFSET)).length() < 1e-5);
+        }
+    }
+}
@@ -0,0 +1,303 @@
+//! Time of day and weather. The [`WorldClock`] advances game time every frame and the [`Weather`]
+//! resource holds the current weather, systems changing it do not need to know who listens:
+//! a [`WeatherChanged`] event is sent whenever it changes. Ambient light follows both, particle
+//! effects and sky color can bind to the same resources.
+//!
+//! Until there is voxel lighting, [`SkyExposure`] stands in for it: the columns around the camera
+//! are checked for blocks above it, and ambient light fades towards cave darkness the fewer of
+//! them are open to the sky.
+
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+
+use crate::{
+    engine::{block_registry::BlockRegistry, chunk::Chunk, coords::WorldVoxelPos, ChunkData},
+    flycam::PlayerCamera,
+};
+
+pub const HOURS_PER_DAY: f32 = 24.0;
+
+/// Ambient light brightness at noon and at midnight in clear weather
+const DAY_BRIGHTNESS: f32 = 0.7;
+const NIGHT_BRIGHTNESS: f32 = 0.08;
+/// Ambient light brightness with no open sky around the camera, whatever the time
+const CAVE_BRIGHTNESS: f32 = 0.02;
+/// How fast the exposure follows the sampled value, per second. Eyes take a moment to adapt.
+const EXPOSURE_ADAPT_RATE: f32 = 1.5;
+
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct WorldClock {
+    /// Days passed since the world was created
+    pub day: u32,
+    /// Hours since midnight, in `0.0..24.0`
+    pub hour: f32,
+    /// Real seconds a game day lasts
+    pub day_length: f32,
+    pub paused: bool,
+}
+
+impl Default for WorldClock {
+    fn default() -> Self {
+        // Start in the morning, the world is easier to look at in daylight
+        Self { day: 0, hour: 8.0, day_length: 20.0 * 60.0, paused: false }
+    }
+}
+
+impl WorldClock {
+    /// Moves the clock forward by `seconds` of real time
+    pub fn advance(&mut self, seconds: f32) {
+        if self.paused || self.day_length <= 0.0 {
+            return;
+        }
+        let hour = self.hour + seconds / self.day_length * HOURS_PER_DAY;
+        self.day += (hour / HOURS_PER_DAY).floor() as u32;
+        self.hour = hour.rem_euclid(HOURS_PER_DAY);
+    }
+
+    /// 1 at noon, 0 at midnight, changing smoothly in between
+    pub fn daylight(&self) -> f32 {
+        0.5 - 0.5 * (self.hour / HOURS_PER_DAY * TAU).cos()
+    }
+}
+
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Weather {
+    #[default]
+    Clear,
+    Rain,
+    Snow,
+}
+
+impl Weather {
+    pub const ALL: [Weather; 3] = [Weather::Clear, Weather::Rain, Weather::Snow];
+
+    /// Share of the daylight that gets through the clouds
+    pub fn light_factor(&self) -> f32 {
+        match self {
+            Self::Clear => 1.0,
+            Self::Rain => 0.6,
+            Self::Snow => 0.8,
+        }
+    }
+}
+
+/// Share of the sky the camera can see, sampled from the loaded chunks around it
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct SkyExposure {
+    /// Smoothed towards the sampled exposure, 1 in the open and 0 deep underground
+    pub exposure: f32,
+    /// Columns sampled in each direction from the camera's column
+    pub radius: i64,
+    /// Voxels above the camera checked in each column, anything higher is treated as sky
+    pub max_height: i64,
+    pub enabled: bool,
+}
+
+impl Default for SkyExposure {
+    fn default() -> Self {
+        Self { exposure: 1.0, radius: 2, max_height: 48, enabled: true }
+    }
+}
+
+impl SkyExposure {
+    /// Moves the exposure towards `sampled` over `seconds`
+    pub fn adapt(&mut self, sampled: f32, seconds: f32) {
+        let step = 1.0 - (-seconds * EXPOSURE_ADAPT_RATE).exp();
+        self.exposure += (sampled - self.exposure) * step;
+    }
+}
+
+/// Share of the columns around `origin` with nothing blocking the sky above it, in `0.0..=1.0`.
+/// Voxels of chunks that are not loaded should not block, or leaving the loaded area goes dark.
+pub fn sky_exposure(origin: WorldVoxelPos, radius: i64, max_height: i64, mut is_blocking: impl FnMut(WorldVoxelPos) -> bool) -> f32 {
+    let mut open = 0;
+    let mut columns = 0;
+    for x in -radius..=radius {
+        for z in -radius..=radius {
+            columns += 1;
+            let column = origin.offset(x, 0, z);
+            if !(1..=max_height).any(|y| is_blocking(column.offset(0, y, 0))) {
+                open += 1;
+            }
+        }
+    }
+    open as f32 / columns as f32
+}
+
+/// Sent once for every change of the [`Weather`] resource
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WeatherChanged {
+    pub from: Weather,
+    pub to: Weather,
+}
+
+pub struct EnvironmentPlugin;
+
+impl Plugin for EnvironmentPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WorldClock>()
+            .init_resource::<Weather>()
+            .init_resource::<SkyExposure>()
+            .add_event::<WeatherChanged>()
+            .add_systems(
+                Update,
+                (
+                    advance_world_clock,
+                    send_weather_changes,
+                    update_sky_exposure,
+                    apply_ambient_light.after(advance_world_clock).after(update_sky_exposure),
+                ),
+            );
+
+        #[cfg(feature = "debug-ui")]
+        app.add_systems(Update, show_environment_debug_info);
+    }
+}
+
+fn advance_world_clock(mut clock: ResMut<WorldClock>, time: Res<Time>) {
+    clock.advance(time.delta_seconds());
+}
+
+fn send_weather_changes(weather: Res<Weather>, mut previous: Local<Option<Weather>>, mut events: EventWriter<WeatherChanged>) {
+    let from = previous.replace(*weather);
+    // The first frame only records the initial weather
+    if let Some(from) = from.filter(|from| from != &*weather) {
+        events.send(WeatherChanged { from, to: *weather });
+    }
+}
+
+fn update_sky_exposure(
+    mut sky: ResMut<SkyExposure>,
+    time: Res<Time>,
+    chunk_data: Res<ChunkData>,
+    chunks: Query<&Chunk>,
+    camera: Query<&Transform, With<PlayerCamera>>,
+    blocks: Res<BlockRegistry>,
+) {
+    let sampled = match camera.get_single() {
+        Ok(camera) if sky.enabled => {
+            let origin = WorldVoxelPos::from_world(camera.translation);
+            sky_exposure(origin, sky.radius, sky.max_height, |pos| {
+                chunk_data.voxel_at(&chunks, pos).is_some_and(|voxel| blocks.is_opaque(voxel))
+            })
+        }
+        _ => 1.0,
+    };
+    if (sky.exposure - sampled).abs() > 1e-4 {
+        sky.adapt(sampled, time.delta_seconds());
+    }
+}
+
+fn apply_ambient_light(clock: Res<WorldClock>, weather: Res<Weather>, sky: Res<SkyExposure>, mut ambient_light: ResMut<AmbientLight>) {
+    let daylight = NIGHT_BRIGHTNESS + (DAY_BRIGHTNESS - NIGHT_BRIGHTNESS) * clock.daylight() * weather.light_factor();
+    let brightness = CAVE_BRIGHTNESS + (daylight - CAVE_BRIGHTNESS) * sky.exposure;
+    // Only touch the resource on a change, lights are extracted again when it changes
+    if (ambient_light.brightness - brightness).abs() > 1e-4 {
+        ambient_light.brightness = brightness;
+    }
+}
+
+#[cfg(feature = "debug-ui")]
+fn show_environment_debug_info(
+    mut contexts: bevy_egui::EguiContexts,
+    mut clock: ResMut<WorldClock>,
+    mut weather: ResMut<Weather>,
+    mut sky: ResMut<SkyExposure>,
+) {
+    use bevy_egui::egui;
+    egui::Window::new("Environment").default_open(false).show(contexts.ctx_mut(), |ui| {
+        let minutes = (clock.hour.fract() * 60.0) as u32;
+        ui.label(format!("Day {} {:02}:{:02}", clock.day, clock.hour as u32, minutes));
+
+        let mut hour = clock.hour;
+        ui.add(egui::Slider::new(&mut hour, 0.0..=23.99).text("Hour"));
+        if hour != clock.hour {
+            clock.hour = hour;
+        }
+        let mut minutes_per_day = clock.day_length / 60.0;
+        ui.add(egui::Slider::new(&mut minutes_per_day, 1.0..=120.0).text("Day Length (minutes)"));
+        if minutes_per_day != clock.day_length / 60.0 {
+            clock.day_length = minutes_per_day * 60.0;
+        }
+        let mut paused = clock.paused;
+        ui.checkbox(&mut paused, "Paused");
+        if paused != clock.paused {
+            clock.paused = paused;
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            for option in Weather::ALL {
+                if ui.selectable_label(*weather == option, format!("{:?}", option)).clicked() && *weather != option {
+                    *weather = option;
+                }
+            }
+        });
+
+        ui.separator();
+        ui.label(format!("Sky exposure: {:.0}%", sky.exposure * 100.0));
+        let mut enabled = sky.enabled;
+        ui.checkbox(&mut enabled, "Darken underground");
+        if enabled != sky.enabled {
+            sky.enabled = enabled;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_wraps_into_the_next_day() {
+        let mut clock = WorldClock { day: 0, hour: 23.0, day_length: 240.0, paused: false };
+        // 10 real seconds are one game hour
+        clock.advance(20.0);
+        assert_eq!(clock.day, 1);
+        assert!((clock.hour - 1.0).abs() < 1e-4);
+
+        clock.paused = true;
+        clock.advance(20.0);
+        assert!((clock.hour - 1.0).abs() < 1e-4);
+
+        assert!(WorldClock { hour: 12.0, ..clock.clone() }.daylight() > 0.999);
+        assert!(WorldClock { hour: 0.0, ..clock }.daylight() < 0.001);
+    }
+
+    #[test]
+    fn test_weather_changes_are_sent_once() {
+        let mut app = App::new();
+        app.init_resource::<Weather>().add_event::<WeatherChanged>().add_systems(Update, send_weather_changes);
+        let read = |app: &mut App| app.world.resource_mut::<Events<WeatherChanged>>().drain().collect::<Vec<_>>();
+
+        app.update();
+        assert!(read(&mut app).is_empty());
+
+        *app.world.resource_mut::<Weather>() = Weather::Rain;
+        app.update();
+        app.update();
+        assert_eq!(read(&mut app), vec![WeatherChanged { from: Weather::Clear, to: Weather::Rain }]);
+    }
+
+    #[test]
+    fn test_sky_exposure_counts_open_columns() {
+        let origin = WorldVoxelPos::new(0, 10, 0);
+        assert_eq!(sky_exposure(origin, 1, 16, |_| false), 1.0);
+        // A ceiling right above the camera covers every column
+        assert_eq!(sky_exposure(origin, 1, 16, |pos| pos.y == 11), 0.0);
+        // Too high up to count, and a floor below does not matter either
+        assert_eq!(sky_exposure(origin, 1, 16, |pos| pos.y == 27 || pos.y == 9), 1.0);
+        // An overhang over one of the 9 columns
+        let exposure = sky_exposure(origin, 1, 16, |pos| pos.x == 1 && pos.z == 1 && pos.y == 20);
+        assert!((exposure - 8.0 / 9.0).abs() < 1e-6);
+
+        let mut sky = SkyExposure::default();
+        sky.adapt(0.0, 0.1);
+        assert!(sky.exposure < 1.0 && sky.exposure > 0.5);
+        for _ in 0..100 {
+            sky.adapt(0.0, 0.1);
+        }
+        assert!(sky.exposure < 0.01);
+    }
+}
@@ -0,0 +1,230 @@
+//! Nine slots of blocks to place. The number keys pick a slot and the mouse wheel steps through
+//! them, the selected slot sets the [`PlacementTool`] block and an empty slot places nothing.
+//! Slots hold blocks of the registry by name and are kept between sessions in [`HOTBAR_FILE`],
+//! saved on shutdown.
+
+use std::{fs, io, path::{Path, PathBuf}};
+
+use bevy::{
+    input::mouse::MouseWheel,
+    prelude::*,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    engine::{
+        block_registry::BlockRegistry,
+        shutdown::{shutdown, ShutdownState},
+        voxel::{BlockId, Voxel},
+    },
+    flycam::InputFocus,
+};
+
+use super::placement::PlacementTool;
+
+pub const HOTBAR_FILE: &str = "hotbar.ron";
+pub const HOTBAR_SLOTS: usize = 9;
+
+const SLOT_KEYS: [KeyCode; HOTBAR_SLOTS] = [
+    KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4, KeyCode::Key5, KeyCode::Key6, KeyCode::Key7, KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+/// What is written to the hotbar file, blocks by name so the file survives changes to the blocks file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct HotbarFile {
+    pub slots: Vec<Option<String>>,
+    pub selected: usize,
+}
+
+impl HotbarFile {
+    /// `None` if no hotbar was saved yet
+    pub fn load(path: &Path) -> io::Result<Option<Self>> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        ron::from_str(&text).map(Some).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        fs::write(path, text)
+    }
+}
+
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct Hotbar {
+    pub slots: [Option<BlockId>; HOTBAR_SLOTS],
+    pub selected: usize,
+    pub path: PathBuf,
+}
+
+impl Default for Hotbar {
+    fn default() -> Self {
+        Self { slots: [None; HOTBAR_SLOTS], selected: 0, path: PathBuf::from(HOTBAR_FILE) }
+    }
+}
+
+impl Hotbar {
+    /// The first blocks of the registry that can be broken again after placing them
+    pub fn from_registry(blocks: &BlockRegistry) -> Self {
+        let mut hotbar = Self::default();
+        let placeable = blocks.iter().filter(|(_, definition)| !definition.unbreakable).map(|(id, _)| Some(id));
+        for (slot, block) in hotbar.slots.iter_mut().zip(placeable) {
+            *slot = block;
+        }
+        hotbar
+    }
+
+    /// Restores saved slots, names missing from the registry leave their slot empty
+    pub fn from_file(file: &HotbarFile, blocks: &BlockRegistry) -> Self {
+        let mut hotbar = Self::default();
+        for (slot, name) in hotbar.slots.iter_mut().zip(file.slots.iter()) {
+            *slot = name.as_deref().and_then(|name| blocks.id(name));
+        }
+        hotbar.selected = file.selected.min(HOTBAR_SLOTS - 1);
+        hotbar
+    }
+
+    pub fn to_file(&self, blocks: &BlockRegistry) -> HotbarFile {
+        let name = |id: BlockId| blocks.name(Voxel::from(id)).to_string();
+        HotbarFile { slots: self.slots.iter().map(|slot| slot.map(name)).collect(), selected: self.selected }
+    }
+
+    pub fn selected_block(&self) -> Option<BlockId> {
+        self.slots[self.selected]
+    }
+
+    /// Moves the selection by `steps` slots, wrapping around at both ends
+    pub fn scroll(&mut self, steps: i32) {
+        self.selected = (self.selected as i32 + steps).rem_euclid(HOTBAR_SLOTS as i32) as usize;
+    }
+}
+
+pub struct HotbarPlugin;
+
+impl Plugin for HotbarPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Hotbar>()
+            .add_systems(Startup, load_hotbar)
+            .add_systems(Update, (handle_hotbar_input, apply_hotbar_selection.after(handle_hotbar_input)))
+            .add_systems(Last, save_hotbar_on_shutdown
+                .before(shutdown)
+                .run_if(resource_equals(ShutdownState::Requested)));
+    }
+}
+
+fn load_hotbar(mut hotbar: ResMut<Hotbar>, blocks: Res<BlockRegistry>) {
+    let path = hotbar.path.clone();
+    *hotbar = match HotbarFile::load(&path) {
+        Ok(Some(file)) => Hotbar::from_file(&file, &blocks),
+        Ok(None) => Hotbar::from_registry(&blocks),
+        Err(err) => {
+            warn!("Failed to load the hotbar from {:?}: {}", path, err);
+            Hotbar::from_registry(&blocks)
+        }
+    };
+    hotbar.path = path;
+}
+
+fn handle_hotbar_input(
+    keys: Res<Input<KeyCode>>,
+    mut wheel: EventReader<MouseWheel>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    focus: Res<InputFocus>,
+    mut hotbar: ResMut<Hotbar>,
+) {
+    // Digits typed into the console are not slot picks
+    let slot = SLOT_KEYS.iter().position(|key| keys.just_pressed(*key));
+    if let Some(slot) = slot.filter(|_| !focus.keyboard_over_ui) {
+        hotbar.selected = slot;
+    }
+    // The wheel scrolls the debug windows while the cursor is free
+    let grabbed = window.get_single().is_ok_and(|window| window.cursor.grab_mode != CursorGrabMode::None);
+    let steps = wheel.read().map(|event| -event.y.signum() as i32).sum::<i32>();
+    if grabbed && steps != 0 {
+        hotbar.scroll(steps);
+    }
+}
+
+fn apply_hotbar_selection(hotbar: Res<Hotbar>, mut tool: ResMut<PlacementTool>) {
+    if !hotbar.is_changed() {
+        return;
+    }
+    // An empty slot empties the tool, placing nothing rather than a block that is not shown
+    let block = hotbar.selected_block();
+    if tool.block != block {
+        tool.block = block;
+    }
+}
+
+fn save_hotbar_on_shutdown(hotbar: Res<Hotbar>, blocks: Res<BlockRegistry>) {
+    if let Err(err) = hotbar.to_file(&blocks).save(&hotbar.path) {
+        warn!("Failed to save the hotbar to {:?}: {}", hotbar.path, err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::voxel::Block;
+    use crate::temp_dir::TempDir;
+
+    #[test]
+    fn test_hotbar_round_trips_by_block_name() {
+        let blocks = BlockRegistry::builtin();
+        let mut hotbar = Hotbar::from_registry(&blocks);
+        // Bedrock could not be broken again
+        assert!(!hotbar.slots.contains(&Some(Block::Bedrock.id())));
+        assert_eq!(hotbar.slots.iter().flatten().count(), Block::ALL.len() - 1);
+
+        hotbar.scroll(-1);
+        assert_eq!(hotbar.selected, HOTBAR_SLOTS - 1);
+        hotbar.scroll(3);
+        assert_eq!(hotbar.selected, 2);
+
+        let mut file = hotbar.to_file(&blocks);
+        assert_eq!(Hotbar::from_file(&file, &blocks).slots, hotbar.slots);
+        file.slots[2] = Some("no such block".to_string());
+        let restored = Hotbar::from_file(&file, &blocks);
+        assert_eq!(restored.selected, 2);
+        assert_eq!(restored.selected_block(), None);
+        file.selected = 40;
+        assert_eq!(Hotbar::from_file(&file, &blocks).selected, HOTBAR_SLOTS - 1);
+
+        let dir = TempDir::new("hotbar");
+        let path = dir.join(HOTBAR_FILE);
+        file.save(&path).unwrap();
+        assert_eq!(HotbarFile::load(&path).unwrap(), Some(file));
+    }
+
+    #[test]
+    fn test_empty_slot_empties_the_tool() {
+        let mut app = App::new();
+        app.init_resource::<Input<KeyCode>>()
+            .init_resource::<InputFocus>()
+            .init_resource::<PlacementTool>()
+            .insert_resource(Hotbar { slots: [Some(Block::Glass.id()), None, None, None, None, None, None, None, None], ..Default::default() })
+            .add_event::<MouseWheel>()
+            .add_systems(Update, (handle_hotbar_input, apply_hotbar_selection.after(handle_hotbar_input)));
+        app.update();
+        assert_eq!(app.world.resource::<PlacementTool>().block, Some(Block::Glass.id()));
+
+        // Typed into the console
+        app.world.resource_mut::<InputFocus>().keyboard_over_ui = true;
+        app.world.resource_mut::<Input<KeyCode>>().press(KeyCode::Key2);
+        app.update();
+        assert_eq!(app.world.resource::<Hotbar>().selected, 0);
+
+        app.world.resource_mut::<InputFocus>().keyboard_over_ui = false;
+        app.world.resource_mut::<Input<KeyCode>>().reset_all();
+        app.world.resource_mut::<Input<KeyCode>>().press(KeyCode::Key2);
+        app.update();
+        assert_eq!(app.world.resource::<Hotbar>().selected, 1);
+        assert_eq!(app.world.resource::<PlacementTool>().block, None);
+    }
+}
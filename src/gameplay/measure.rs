@@ -0,0 +1,94 @@
+//! Tape measure between two picked voxels. `M` picks the targeted voxel, a third pick
+//! starts a new measurement and `Shift+M` clears it. Results are shown in the HUD.
+
+use bevy::prelude::*;
+
+use crate::engine::coords::WorldVoxelPos;
+
+use super::selection::{handle_selection_input, TargetedVoxel};
+
+const PICK_KEY: KeyCode = KeyCode::M;
+
+#[derive(Resource, Default)]
+pub struct Measurement {
+    pub points: [Option<WorldVoxelPos>; 2],
+}
+
+impl Measurement {
+    /// Sets the second point if only the first one is set, otherwise starts over at `pos`
+    pub fn pick(&mut self, pos: WorldVoxelPos) {
+        self.points = match self.points {
+            [Some(first), None] => [Some(first), Some(pos)],
+            _ => [Some(pos), None],
+        };
+    }
+
+    /// Voxels from the first point to the second on each axis
+    pub fn delta(&self) -> Option<[i64; 3]> {
+        let [Some(a), Some(b)] = self.points else {
+            return None;
+        };
+        Some([b.x - a.x, b.y - a.y, b.z - a.z])
+    }
+
+    /// Straight line distance between the voxel centers
+    pub fn distance(&self) -> Option<f32> {
+        self.delta().map(|[x, y, z]| Vec3::new(x as f32, y as f32, z as f32).length())
+    }
+
+    /// Voxels walked when moving along one axis at a time
+    pub fn manhattan_distance(&self) -> Option<u64> {
+        self.delta().map(|delta| delta.iter().map(|axis| axis.unsigned_abs()).sum())
+    }
+}
+
+pub struct MeasurePlugin;
+
+impl Plugin for MeasurePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Measurement>()
+            .add_systems(Update, (handle_measure_input.after(handle_selection_input), draw_measurement.after(handle_measure_input)));
+    }
+}
+
+fn handle_measure_input(keys: Res<Input<KeyCode>>, target: Res<TargetedVoxel>, mut measurement: ResMut<Measurement>) {
+    if !keys.just_pressed(PICK_KEY) {
+        return;
+    }
+    if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        measurement.points = [None, None];
+    } else if let Some(hit) = target.0 {
+        measurement.pick(hit.pos);
+    }
+}
+
+fn draw_measurement(mut gizmos: Gizmos, measurement: Res<Measurement>) {
+    let center = |pos: WorldVoxelPos| pos.as_vec3() + Vec3::splat(0.5);
+    for point in measurement.points.iter().flatten() {
+        gizmos.cuboid(Transform::from_translation(center(*point)).with_scale(Vec3::splat(1.03)), Color::LIME_GREEN);
+    }
+    if let [Some(a), Some(b)] = measurement.points {
+        gizmos.line(center(a), center(b), Color::LIME_GREEN);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_measurement() {
+        let mut measurement = Measurement::default();
+        measurement.pick(WorldVoxelPos::new(1, 2, 3));
+        assert_eq!(measurement.delta(), None);
+
+        measurement.pick(WorldVoxelPos::new(4, -2, 3));
+        assert_eq!(measurement.delta(), Some([3, -4, 0]));
+        assert_eq!(measurement.distance(), Some(5.0));
+        assert_eq!(measurement.manhattan_distance(), Some(7));
+
+        // A third pick starts over
+        measurement.pick(WorldVoxelPos::new(0, 0, 0));
+        assert_eq!(measurement.points, [Some(WorldVoxelPos::new(0, 0, 0)), None]);
+    }
+}
@@ -0,0 +1,25 @@
+use bevy::prelude::*;
+
+pub mod beacon;
+pub mod block_outline;
+pub mod environment;
+pub mod hotbar;
+pub mod measure;
+pub mod placement;
+pub mod selection;
+pub mod spawn;
+
+pub struct GameplayPlugin;
+
+impl Plugin for GameplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(beacon::BeaconPlugin)
+            .add_plugins(environment::EnvironmentPlugin)
+            .add_plugins(selection::SelectionPlugin)
+            .add_plugins(block_outline::BlockOutlinePlugin)
+            .add_plugins(measure::MeasurePlugin)
+            .add_plugins(placement::PlacementPlugin)
+            .add_plugins(hotbar::HotbarPlugin)
+            .add_plugins(spawn::SpawnPlugin);
+    }
+}
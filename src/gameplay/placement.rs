@@ -0,0 +1,93 @@
+//! Placing and breaking blocks. With the cursor grabbed, the right mouse button places the
+//! [`PlacementTool`] block in front of the targeted face and the left one breaks the targeted voxel.
+//! Edits go through [`WorldEdits`], so unbreakable blocks stay. The block is picked in the
+//! [`Hotbar`](super::hotbar::Hotbar).
+
+use bevy::{
+    prelude::*,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+
+use crate::engine::{
+    coords::WorldVoxelPos,
+    raycast::RaycastHit,
+    voxel::{Block, BlockId, Voxel},
+    world_edits::WorldEdits,
+};
+
+use super::selection::{update_targeted_voxel, TargetedVoxel};
+
+const PLACE_BUTTON: MouseButton = MouseButton::Right;
+const BREAK_BUTTON: MouseButton = MouseButton::Left;
+
+/// Block placed with the right mouse button, nothing is placed without one
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlacementTool {
+    pub block: Option<BlockId>,
+}
+
+impl Default for PlacementTool {
+    fn default() -> Self {
+        Self { block: Some(Block::Stone.id()) }
+    }
+}
+
+pub struct PlacementPlugin;
+
+impl Plugin for PlacementPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlacementTool>()
+            .add_systems(Update, handle_placement_input.after(update_targeted_voxel));
+    }
+}
+
+/// The write a click makes at the targeted voxel, `None` if placing from inside a voxel or without a block
+pub fn placement_edit(hit: &RaycastHit, place: bool, block: Option<BlockId>) -> Option<(WorldVoxelPos, Voxel)> {
+    match place {
+        true => block.filter(|_| hit.normal != IVec3::ZERO).map(|block| (hit.adjacent(), Voxel::from(block))),
+        false => Some((hit.pos, Voxel::Empty)),
+    }
+}
+
+fn handle_placement_input(
+    buttons: Res<Input<MouseButton>>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    target: Res<TargetedVoxel>,
+    tool: Res<PlacementTool>,
+    mut edits: ResMut<WorldEdits>,
+) {
+    // Clicks on a free cursor belong to the debug windows
+    if window.get_single().map_or(true, |window| window.cursor.grab_mode == CursorGrabMode::None) {
+        return;
+    }
+    let Some(hit) = target.0 else {
+        return;
+    };
+    let place = match (buttons.just_pressed(PLACE_BUTTON), buttons.just_pressed(BREAK_BUTTON)) {
+        (true, _) => true,
+        (false, true) => false,
+        (false, false) => return,
+    };
+    if let Some((pos, voxel)) = placement_edit(&hit, place, tool.block) {
+        edits.set(pos, voxel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clicks_edit_the_targeted_voxel() {
+        let hit = RaycastHit { pos: WorldVoxelPos::new(1, 2, 3), normal: IVec3::Y, distance: 2.0 };
+        let glass = Some(Block::Glass.id());
+        assert_eq!(placement_edit(&hit, true, glass), Some((WorldVoxelPos::new(1, 3, 3), Voxel::from(Block::Glass))));
+        assert_eq!(placement_edit(&hit, false, glass), Some((WorldVoxelPos::new(1, 2, 3), Voxel::Empty)));
+        // Inside of a voxel there is no face to place against
+        let inside = RaycastHit { normal: IVec3::ZERO, ..hit };
+        assert_eq!(placement_edit(&inside, true, glass), None);
+        // An empty hotbar slot places nothing, breaking still works
+        assert_eq!(placement_edit(&hit, true, None), None);
+        assert_eq!(placement_edit(&hit, false, None), Some((WorldVoxelPos::new(1, 2, 3), Voxel::Empty)));
+    }
+}
@@ -0,0 +1,269 @@
+//! Region selection with copy/paste, for building test scenes repeatedly.
+//!
+//! `[` and `]` set the selection corners at the targeted voxel, `Ctrl+C` copies the selection,
+//! `Ctrl+V` pastes on top of the targeted face and `Ctrl+R` rotates the clipboard.
+//! The size of the selection is shown in the HUD.
+//! Schematics are exported and imported through the debug UI, names ending in `.vox`
+//! are imported from MagicaVoxel models in the schematics directory instead.
+//! The debug UI also exports the loaded chunks or the selected region as an OBJ model.
+
+use std::{io, path::PathBuf};
+
+use bevy::prelude::*;
+
+use crate::{
+    engine::{
+        block_registry::BlockRegistry,
+        chunk::Chunk,
+        coords::WorldVoxelPos,
+        raycast::{raycast, RaycastHit},
+        schematic::{self, Schematic},
+        vox,
+        voxel::Voxel,
+        ChunkData,
+    },
+    flycam::PlayerCamera,
+};
+
+/// How far away voxels can be targeted
+const REACH: f32 = 64.0;
+const SCHEMATICS_DIR: &str = "schematics";
+
+#[derive(Resource, Default)]
+pub struct Selection {
+    pub corners: [Option<WorldVoxelPos>; 2],
+}
+
+impl Selection {
+    /// Both corners, if they are set
+    pub fn region(&self) -> Option<(WorldVoxelPos, WorldVoxelPos)> {
+        Some((self.corners[0]?, self.corners[1]?))
+    }
+
+    /// Minimum and maximum corner of the selection box, both inclusive
+    pub fn bounds(&self) -> Option<(WorldVoxelPos, WorldVoxelPos)> {
+        let (a, b) = self.region()?;
+        Some((
+            WorldVoxelPos::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            WorldVoxelPos::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        ))
+    }
+
+    /// Size of the selection box in voxels on the x, y and z axes
+    pub fn size(&self) -> Option<[u64; 3]> {
+        let (min, max) = self.bounds()?;
+        Some([max.x.abs_diff(min.x) + 1, max.y.abs_diff(min.y) + 1, max.z.abs_diff(min.z) + 1])
+    }
+
+    pub fn volume(&self) -> Option<u64> {
+        self.size().map(|[x, y, z]| x * y * z)
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Clipboard {
+    pub schematic: Option<Schematic>,
+}
+
+/// Voxel the camera is looking at
+#[derive(Resource, Default)]
+pub struct TargetedVoxel(pub Option<RaycastHit>);
+
+pub fn schematic_path(name: &str) -> PathBuf {
+    PathBuf::from(SCHEMATICS_DIR).join(format!("{}.{}", name, schematic::FILE_EXTENSION))
+}
+
+/// Loads a schematic by name, `.vox` names are imported from MagicaVoxel files (first model only)
+pub fn load_schematic(name: &str, blocks: &BlockRegistry) -> io::Result<Schematic> {
+    if name.ends_with(".vox") {
+        let path = PathBuf::from(SCHEMATICS_DIR).join(name);
+        return vox::load_vox(&path, blocks)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{} contains no models", path.display())));
+    }
+    Schematic::load(&schematic_path(name), blocks)
+}
+
+pub struct SelectionPlugin;
+
+impl Plugin for SelectionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Selection>()
+            .init_resource::<Clipboard>()
+            .init_resource::<TargetedVoxel>()
+            .add_systems(Update, (
+                update_targeted_voxel,
+                handle_selection_input.after(update_targeted_voxel),
+                draw_selection.after(handle_selection_input),
+            ));
+
+        #[cfg(feature = "debug-ui")]
+        app.add_systems(Update, show_selection_debug_info);
+    }
+}
+
+pub fn update_targeted_voxel(
+    mut target: ResMut<TargetedVoxel>,
+    chunk_data: Res<ChunkData>,
+    chunks: Query<&Chunk>,
+    camera: Query<&Transform, With<PlayerCamera>>,
+    blocks: Res<BlockRegistry>,
+) {
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    // The ray passes through water to the blocks under it
+    target.0 = raycast(camera.translation, camera.forward(), REACH, |pos| {
+        chunk_data.voxel_at(&chunks, pos).is_some_and(|voxel| !voxel.is_empty() && !blocks.is_translucent(voxel))
+    });
+}
+
+pub fn handle_selection_input(
+    keys: Res<Input<KeyCode>>,
+    target: Res<TargetedVoxel>,
+    mut selection: ResMut<Selection>,
+    mut clipboard: ResMut<Clipboard>,
+    mut chunk_data: ResMut<ChunkData>,
+    chunks: Query<&Chunk>,
+) {
+    if let Some(hit) = target.0 {
+        if keys.just_pressed(KeyCode::BracketLeft) {
+            selection.corners[0] = Some(hit.pos);
+        }
+        if keys.just_pressed(KeyCode::BracketRight) {
+            selection.corners[1] = Some(hit.pos);
+        }
+    }
+
+    if !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+
+    if keys.just_pressed(KeyCode::C) {
+        if let Some((a, b)) = selection.region() {
+            // Parts of the selection that are not loaded are copied as air
+            let schematic = Schematic::copy(a, b, |pos| chunk_data.voxel_at(&chunks, pos).unwrap_or(Voxel::Empty));
+            info!("Copied {:?} voxels", schematic.size());
+            clipboard.schematic = Some(schematic);
+        }
+    }
+    if keys.just_pressed(KeyCode::R) {
+        if let Some(schematic) = &clipboard.schematic {
+            clipboard.schematic = Some(schematic.rotated_y(1));
+        }
+    }
+    if keys.just_pressed(KeyCode::V) {
+        if let (Some(schematic), Some(hit)) = (&clipboard.schematic, target.0) {
+            let written = schematic.paste(&mut chunk_data.pending_edits, hit.adjacent(), false);
+            info!("Pasted {} voxels at {:?}", written, hit.adjacent());
+        }
+    }
+}
+
+fn voxel_box(min: WorldVoxelPos, size: Vec3) -> Transform {
+    Transform::from_translation(min.as_vec3() + size / 2.0).with_scale(size)
+}
+
+fn draw_selection(
+    mut gizmos: Gizmos,
+    selection: Res<Selection>,
+    clipboard: Res<Clipboard>,
+    target: Res<TargetedVoxel>,
+) {
+    // The targeted voxel itself is outlined by the block outline
+    for corner in selection.corners.iter().flatten() {
+        gizmos.cuboid(voxel_box(*corner, Vec3::ONE * 1.02), Color::YELLOW);
+    }
+    if let Some((min, max)) = selection.bounds() {
+        gizmos.cuboid(voxel_box(min, max.as_vec3() - min.as_vec3() + Vec3::ONE), Color::ORANGE);
+    }
+
+    // Preview where the clipboard would be pasted
+    if let (Some(schematic), Some(hit)) = (&clipboard.schematic, target.0) {
+        gizmos.cuboid(voxel_box(hit.adjacent(), schematic.size().as_vec3()), Color::CYAN);
+    }
+}
+
+#[cfg(feature = "debug-ui")]
+fn show_selection_debug_info(
+    mut contexts: bevy_egui::EguiContexts,
+    mut name: Local<String>,
+    mut status: Local<String>,
+    mut clipboard: ResMut<Clipboard>,
+    selection: Res<Selection>,
+    chunk_data: Res<ChunkData>,
+    chunks: Query<&Chunk>,
+    meshes: Res<Assets<Mesh>>,
+    meshing: crate::engine::meshing::MeshingResources,
+    config: Res<crate::engine::generator::WorldGeneratorConfig>,
+) {
+    use bevy_egui::egui;
+    use crate::engine::export;
+    egui::Window::new("Selection").default_open(false).show(contexts.ctx_mut(), |ui| {
+        ui.label("[ / ] set corners, Ctrl+C copy, Ctrl+V paste, Ctrl+R rotate");
+        for (i, corner) in selection.corners.iter().enumerate() {
+            match corner {
+                Some(pos) => ui.label(format!("Corner {}: ({}, {}, {})", i + 1, pos.x, pos.y, pos.z)),
+                None => ui.label(format!("Corner {}: not set", i + 1)),
+            };
+        }
+        match &clipboard.schematic {
+            Some(schematic) => {
+                let size = schematic.size();
+                ui.label(format!("Clipboard: {}×{}×{}", size.x, size.y, size.z))
+            }
+            None => ui.label("Clipboard: empty"),
+        };
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Schematic");
+            ui.text_edit_singleline(&mut *name);
+        });
+        ui.horizontal(|ui| {
+            let path = schematic_path(name.trim());
+            let enabled = !name.trim().is_empty();
+            if ui.add_enabled(enabled && clipboard.schematic.is_some(), egui::Button::new("Export")).clicked() {
+                *status = match clipboard.schematic.as_ref().unwrap().save(&path) {
+                    Ok(()) => format!("Saved {}", path.display()),
+                    Err(err) => format!("Failed to save {}: {}", path.display(), err),
+                };
+            }
+            if ui.add_enabled(enabled, egui::Button::new("Import")).clicked() {
+                *status = match load_schematic(name.trim(), meshing.blocks()) {
+                    Ok(schematic) => {
+                        clipboard.schematic = Some(schematic);
+                        format!("Loaded {}", name.trim())
+                    }
+                    Err(err) => format!("Failed to load {}: {}", name.trim(), err),
+                };
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Export OBJ");
+            let path = PathBuf::from(export::EXPORTS_DIR).join(format!("{}.obj", if name.trim().is_empty() { "world" } else { name.trim() }));
+            let result = if ui.button("Loaded Chunks").clicked() {
+                Some(export::export_loaded_chunks(&path, &chunk_data, &meshes))
+            } else if ui.add_enabled(selection.region().is_some(), egui::Button::new("Selection")).clicked() {
+                let (a, b) = selection.region().unwrap();
+                let (min, max) = selection.bounds().unwrap();
+                let loaded = chunk_data
+                    .chunks_in_aabb(bevy::render::primitives::Aabb::from_min_max(min.as_vec3(), max.as_vec3() + Vec3::ONE))
+                    .filter_map(|(_, entity)| chunks.get(entity).ok());
+                Some(export::export_region(&path, a, b, loaded, &meshing.mesher(), &config))
+            } else {
+                None
+            };
+            if let Some(result) = result {
+                *status = match result {
+                    Ok(stats) => format!("Exported {} chunks, {} triangles to {}", stats.objects, stats.triangles, path.display()),
+                    Err(err) => format!("Failed to export {}: {}", path.display(), err),
+                };
+            }
+        });
+        if !status.is_empty() {
+            ui.label(&*status);
+        }
+    });
+}
@@ -0,0 +1,115 @@
+//! Puts the camera on the surface of the world's spawn column when it starts, or when a world that
+//! was never played is opened, instead of at a fixed height, which is inside the terrain in hilly worlds. The surface is found with a ray down through the loaded
+//! chunks, or asked from the generator before they are loaded. The `respawn` command does the
+//! same for the column the camera is above.
+
+use bevy::prelude::*;
+
+use crate::{
+    engine::{
+        chunk::Chunk,
+        coords::WorldVoxelPos,
+        generator::WorldGeneratorConfig,
+        heightmap::HeightmapCache,
+        loading::AppState,
+        raycast::raycast,
+        world_meta::WorldMetadata,
+        ChunkData,
+    },
+    flycam::PlayerCamera,
+};
+
+/// Height of the camera above the block it stands on
+const EYE_HEIGHT: f32 = 1.7;
+/// The ray looking for the surface starts this far above the expected height
+const SEARCH_HEIGHT: f32 = 64.0;
+const SEARCH_DISTANCE: f32 = 256.0;
+/// Seconds to wait for a surface to show up before leaving the camera where it is
+const SPAWN_TIMEOUT: f32 = 10.0;
+
+/// Column the camera is moved to once its surface is known
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct PendingSpawn {
+    pub x: i64,
+    pub z: i64,
+    /// Seconds spent waiting for the surface
+    pub waited: f32,
+}
+
+impl PendingSpawn {
+    pub fn at(x: i64, z: i64) -> Self {
+        Self { x, z, waited: 0.0 }
+    }
+}
+
+pub struct SpawnPlugin;
+
+impl Plugin for SpawnPlugin {
+    fn build(&self, app: &mut App) {
+        // The camera is only placed in game, the menu is shown wherever it is
+        let in_game = in_state(AppState::Loading).or_else(in_state(AppState::Playing));
+        app.add_systems(Startup, queue_world_spawn)
+            .add_systems(PreUpdate, place_camera_at_spawn.run_if(resource_exists::<PendingSpawn>().and_then(in_game)));
+    }
+}
+
+fn queue_world_spawn(mut commands: Commands, metadata: Option<Res<WorldMetadata>>) {
+    let [x, z] = metadata.map_or([0, 0], |metadata| metadata.spawn);
+    commands.insert_resource(PendingSpawn::at(x, z));
+}
+
+/// Camera position standing on a surface at `height`, in the middle of the column. Water
+/// counts as the surface where it is above the ground.
+pub fn spawn_position(x: i64, z: i64, height: i64, sea_level: Option<i32>) -> Vec3 {
+    let ground = sea_level.map_or(height, |level| height.max(level as i64));
+    Vec3::new(x as f32 + 0.5, (ground + 1) as f32 + EYE_HEIGHT, z as f32 + 0.5)
+}
+
+fn place_camera_at_spawn(
+    mut commands: Commands,
+    mut pending: ResMut<PendingSpawn>,
+    time: Res<Time>,
+    config: Res<WorldGeneratorConfig>,
+    mut heightmap: ResMut<HeightmapCache>,
+    chunk_data: Res<ChunkData>,
+    chunks: Query<&Chunk>,
+    mut camera: Query<&mut Transform, With<PlayerCamera>>,
+) {
+    let Ok(mut transform) = camera.get_single_mut() else {
+        return;
+    };
+    let (x, z) = (pending.x, pending.z);
+    let expected = heightmap.surface_height(&config, x, z);
+
+    // Loaded chunks know about edits and structures the generator does not
+    let top = expected.map_or(transform.translation.y, |height| height as f32) + SEARCH_HEIGHT;
+    let origin = Vec3::new(x as f32 + 0.5, top, z as f32 + 0.5);
+    let hit = raycast(origin, Vec3::NEG_Y, SEARCH_DISTANCE, |pos: WorldVoxelPos| {
+        chunk_data.voxel_at(&chunks, pos).is_some_and(|voxel| !voxel.is_empty())
+    });
+    let Some(height) = hit.map(|hit| hit.pos.y).or(expected) else {
+        pending.waited += time.delta_seconds();
+        if pending.waited > SPAWN_TIMEOUT {
+            warn!("No surface found at {} {}, the camera stays where it is", x, z);
+            commands.remove_resource::<PendingSpawn>();
+        }
+        return;
+    };
+
+    transform.translation = spawn_position(x, z, height, config.sea_level);
+    info!("Spawned at {} {} {}", x, height + 1, z);
+    commands.remove_resource::<PendingSpawn>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spawn_stands_on_the_surface_or_the_water() {
+        assert_eq!(spawn_position(3, -2, 10, None), Vec3::new(3.5, 11.0 + EYE_HEIGHT, -1.5));
+        assert_eq!(spawn_position(3, -2, 10, Some(4)), spawn_position(3, -2, 10, None));
+        // Under the sea the camera is put on the water instead
+        assert_eq!(spawn_position(0, 0, -8, Some(0)).y, 1.0 + EYE_HEIGHT);
+    }
+}
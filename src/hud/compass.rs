@@ -0,0 +1,196 @@
+//! Compass strip at the top of the screen. Shows cardinal directions and bearings
+//! to spawn and beacons, relative to where the camera is looking.
+//! North is towards -Z.
+
+use std::f32::consts::{PI, TAU};
+
+use bevy::prelude::*;
+
+use crate::{flycam::PlayerCamera, gameplay::beacon::Beacon};
+
+const STRIP_WIDTH: f32 = 480.0;
+const STRIP_HEIGHT: f32 = 24.0;
+/// Horizontal angle covered by the strip
+const COMPASS_FOV: f32 = PI;
+/// The world origin is where the camera spawns
+const SPAWN_POSITION: Vec3 = Vec3::ZERO;
+
+const CARDINALS: [&str; 8] = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+
+#[derive(Component)]
+struct CompassStrip;
+
+#[derive(Component)]
+struct CompassHeading;
+
+#[derive(Component)]
+enum CompassMarker {
+    /// Fixed bearing in radians
+    Direction(f32),
+    /// Bearing towards a world position, labelled with the distance to it
+    Position(&'static str, Vec3),
+    /// Bearing towards a beacon
+    Beacon(Entity),
+}
+
+pub struct CompassPlugin;
+
+impl Plugin for CompassPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, spawn_compass)
+            .add_systems(Update, (
+                add_beacon_markers,
+                remove_beacon_markers,
+                update_compass.after(add_beacon_markers),
+            ));
+    }
+}
+
+/// Bearing of a direction in radians, clockwise from north
+pub fn bearing(direction: Vec3) -> f32 {
+    direction.x.atan2(-direction.z).rem_euclid(TAU)
+}
+
+/// Wraps an angle into `-PI..PI`
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(TAU) - PI
+}
+
+fn cardinal_name(bearing: f32) -> &'static str {
+    CARDINALS[((bearing / (TAU / 8.0)).round() as usize) % 8]
+}
+
+fn marker_text(text: &str, color: Color) -> TextBundle {
+    TextBundle::from_section(text, TextStyle { font_size: 16.0, color, ..Default::default() })
+        .with_style(Style { position_type: PositionType::Absolute, ..Default::default() })
+}
+
+fn spawn_compass(mut commands: Commands) {
+    commands.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(8.0),
+            width: Val::Percent(100.0),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        ..Default::default()
+    }).with_children(|root| {
+        root.spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Px(STRIP_WIDTH),
+                    height: Val::Px(STRIP_HEIGHT),
+                    overflow: Overflow::clip(),
+                    ..Default::default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.4).into(),
+                ..Default::default()
+            },
+            CompassStrip,
+        )).with_children(|strip| {
+            for (i, name) in CARDINALS.iter().enumerate() {
+                let color = if i % 2 == 0 { Color::WHITE } else { Color::GRAY };
+                strip.spawn((marker_text(name, color), CompassMarker::Direction(i as f32 * TAU / 8.0)));
+            }
+            strip.spawn((marker_text("Spawn", Color::GOLD), CompassMarker::Position("Spawn", SPAWN_POSITION)));
+        });
+        root.spawn((
+            TextBundle::from_section("", TextStyle { font_size: 14.0, color: Color::WHITE, ..Default::default() }),
+            CompassHeading,
+        ));
+    });
+}
+
+fn add_beacon_markers(
+    mut commands: Commands,
+    strip: Query<Entity, With<CompassStrip>>,
+    beacons: Query<(Entity, &Beacon), Added<Beacon>>,
+) {
+    let Ok(strip) = strip.get_single() else {
+        return;
+    };
+    for (entity, beacon) in beacons.iter() {
+        let marker = commands.spawn((marker_text(&beacon.name, beacon.color.with_a(1.0)), CompassMarker::Beacon(entity))).id();
+        commands.entity(strip).add_child(marker);
+    }
+}
+
+fn remove_beacon_markers(
+    mut commands: Commands,
+    mut removed: RemovedComponents<Beacon>,
+    markers: Query<(Entity, &CompassMarker)>,
+) {
+    for beacon in removed.read() {
+        for (marker, _) in markers.iter().filter(|(_, m)| matches!(m, CompassMarker::Beacon(e) if *e == beacon)) {
+            commands.entity(marker).despawn_recursive();
+        }
+    }
+}
+
+fn update_compass(
+    camera: Query<&Transform, With<PlayerCamera>>,
+    targets: Query<&GlobalTransform>,
+    mut markers: Query<(&CompassMarker, &Node, &mut Style, &mut Visibility, &mut Text)>,
+    mut heading_text: Query<&mut Text, (With<CompassHeading>, Without<CompassMarker>)>,
+) {
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    let heading = bearing(camera.forward());
+
+    if let Ok(mut text) = heading_text.get_single_mut() {
+        text.sections[0].value = format!("{:03.0}° {}", heading.to_degrees(), cardinal_name(heading));
+    }
+
+    for (marker, node, mut style, mut visibility, mut text) in markers.iter_mut() {
+        let target_bearing = match marker {
+            CompassMarker::Direction(bearing) => *bearing,
+            CompassMarker::Position(label, position) => {
+                let offset = *position - camera.translation;
+                text.sections[0].value = format!("{} {:.0}m", label, offset.length());
+                bearing(offset)
+            }
+            CompassMarker::Beacon(entity) => {
+                let Ok(target) = targets.get(*entity) else {
+                    continue;
+                };
+                bearing(target.translation() - camera.translation)
+            }
+        };
+
+        let relative = wrap_angle(target_bearing - heading);
+        if relative.abs() > COMPASS_FOV / 2.0 {
+            *visibility = Visibility::Hidden;
+            continue;
+        }
+        *visibility = Visibility::Inherited;
+        // Center the marker on its bearing, the node size is known from the previous layout
+        let x = (relative / COMPASS_FOV + 0.5) * STRIP_WIDTH;
+        style.left = Val::Px(x - node.size().x / 2.0);
+        style.top = Val::Px((STRIP_HEIGHT - node.size().y) / 2.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bearing() {
+        assert!((bearing(Vec3::NEG_Z) - 0.0).abs() < 1e-5);
+        assert!((bearing(Vec3::X) - PI / 2.0).abs() < 1e-5);
+        assert!((bearing(Vec3::Z) - PI).abs() < 1e-5);
+        assert!((bearing(Vec3::NEG_X) - 3.0 * PI / 2.0).abs() < 1e-5);
+        assert_eq!(cardinal_name(bearing(Vec3::new(1.0, 0.0, -1.0))), "NE");
+        assert_eq!(cardinal_name(bearing(Vec3::new(-0.1, 0.0, -1.0))), "N");
+    }
+
+    #[test]
+    fn test_wrap_angle() {
+        assert!((wrap_angle(3.0 * PI / 2.0) + PI / 2.0).abs() < 1e-5);
+        assert!((wrap_angle(-3.0 * PI / 2.0) - PI / 2.0).abs() < 1e-5);
+        assert!((wrap_angle(0.25) - 0.25).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,87 @@
+//! Row of hotbar slots at the bottom of the screen, each showing its key and block, with the
+//! selected slot highlighted
+
+use bevy::prelude::*;
+
+use crate::{
+    engine::{block_registry::BlockRegistry, voxel::Voxel},
+    gameplay::hotbar::{Hotbar, HOTBAR_SLOTS},
+};
+
+const SLOT_WIDTH: f32 = 64.0;
+const SLOT_HEIGHT: f32 = 40.0;
+const SELECTED_BORDER: Color = Color::WHITE;
+const BORDER: Color = Color::rgba(0.0, 0.0, 0.0, 0.6);
+
+/// Slot shown by the node, the index into [`Hotbar::slots`]
+#[derive(Component)]
+struct HotbarSlot(usize);
+
+pub struct HotbarHudPlugin;
+
+impl Plugin for HotbarHudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, spawn_hotbar)
+            .add_systems(Update, update_hotbar);
+    }
+}
+
+fn spawn_hotbar(mut commands: Commands) {
+    commands.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(8.0),
+            width: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            column_gap: Val::Px(4.0),
+            ..Default::default()
+        },
+        ..Default::default()
+    }).with_children(|root| {
+        for slot in 0..HOTBAR_SLOTS {
+            root.spawn((
+                NodeBundle {
+                    style: Style {
+                        width: Val::Px(SLOT_WIDTH),
+                        height: Val::Px(SLOT_HEIGHT),
+                        border: UiRect::all(Val::Px(2.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    border_color: BORDER.into(),
+                    background_color: Color::rgba(0.0, 0.0, 0.0, 0.4).into(),
+                    ..Default::default()
+                },
+                HotbarSlot(slot),
+            )).with_children(|node| {
+                node.spawn(TextBundle::from_section("", TextStyle { font_size: 12.0, color: Color::WHITE, ..Default::default() })
+                    .with_text_alignment(TextAlignment::Center));
+            });
+        }
+    });
+}
+
+fn update_hotbar(
+    hotbar: Res<Hotbar>,
+    blocks: Res<BlockRegistry>,
+    mut slots: Query<(&HotbarSlot, &Children, &mut BorderColor, &mut BackgroundColor)>,
+    mut texts: Query<&mut Text>,
+) {
+    if !hotbar.is_changed() {
+        return;
+    }
+    for (slot, children, mut border, mut background) in slots.iter_mut() {
+        let block = hotbar.slots[slot.0];
+        border.0 = if slot.0 == hotbar.selected { SELECTED_BORDER } else { BORDER };
+        // Tinted with the block's color so slots can be told apart at a glance
+        let [r, g, b] = block.and_then(|id| blocks.get(id)).map_or([0, 0, 0], |definition| definition.color);
+        background.0 = Color::rgba_u8(r, g, b, 160);
+        let name = block.map_or("", |id| blocks.name(Voxel::from(id)));
+        for child in children.iter() {
+            if let Ok(mut text) = texts.get_mut(*child) {
+                text.sections[0].value = format!("{}\n{}", slot.0 + 1, name);
+            }
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Crosshair in the middle of the screen and a readout under it with the targeted voxel and the
+//! block the placement tool puts down
+
+use bevy::prelude::*;
+
+use crate::{
+    engine::{block_registry::BlockRegistry, chunk::Chunk, coords::WorldVoxelPos, voxel::{BlockId, Voxel}, ChunkData},
+    gameplay::{placement::PlacementTool, selection::TargetedVoxel},
+};
+
+const CROSSHAIR_SIZE: f32 = 16.0;
+const CROSSHAIR_THICKNESS: f32 = 2.0;
+
+#[derive(Component)]
+struct InteractionText;
+
+pub struct InteractionHudPlugin;
+
+impl Plugin for InteractionHudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, spawn_interaction_hud)
+            .add_systems(Update, update_interaction_text);
+    }
+}
+
+/// Readout lines, the targeted voxel is left out when nothing is in reach
+pub fn interaction_text(blocks: &BlockRegistry, target: Option<(WorldVoxelPos, Voxel)>, tool: Option<BlockId>) -> String {
+    let placing = match tool {
+        Some(block) => format!("Placing {}", blocks.name(Voxel::from(block))),
+        None => "Placing nothing".to_string(),
+    };
+    match target {
+        Some((pos, voxel)) => format!("{} at {} {} {}\n{}", blocks.name(voxel), pos.x, pos.y, pos.z, placing),
+        None => placing,
+    }
+}
+
+fn crosshair_bar(width: f32, height: f32) -> NodeBundle {
+    NodeBundle {
+        style: Style { position_type: PositionType::Absolute, width: Val::Px(width), height: Val::Px(height), ..Default::default() },
+        background_color: Color::rgba(1.0, 1.0, 1.0, 0.8).into(),
+        ..Default::default()
+    }
+}
+
+fn spawn_interaction_hud(mut commands: Commands) {
+    // Full screen root so the crosshair sits in the exact center whatever the window size
+    commands.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        ..Default::default()
+    }).with_children(|root| {
+        root.spawn(crosshair_bar(CROSSHAIR_SIZE, CROSSHAIR_THICKNESS));
+        root.spawn(crosshair_bar(CROSSHAIR_THICKNESS, CROSSHAIR_SIZE));
+        root.spawn((
+            TextBundle::from_section("", TextStyle { font_size: 14.0, color: Color::WHITE, ..Default::default() })
+                .with_text_alignment(TextAlignment::Center)
+                .with_style(Style { position_type: PositionType::Absolute, top: Val::Percent(50.0), margin: UiRect::top(Val::Px(CROSSHAIR_SIZE)), ..Default::default() })
+                .with_background_color(Color::rgba(0.0, 0.0, 0.0, 0.4)),
+            InteractionText,
+        ));
+    });
+}
+
+fn update_interaction_text(
+    mut text: Query<&mut Text, With<InteractionText>>,
+    target: Res<TargetedVoxel>,
+    tool: Res<PlacementTool>,
+    chunk_data: Res<ChunkData>,
+    chunks: Query<&Chunk>,
+    blocks: Res<BlockRegistry>,
+) {
+    let Ok(mut text) = text.get_single_mut() else {
+        return;
+    };
+    let target = target.0.and_then(|hit| chunk_data.voxel_at(&chunks, hit.pos).map(|voxel| (hit.pos, voxel)));
+    let value = interaction_text(&blocks, target, tool.block);
+    if text.sections[0].value != value {
+        text.sections[0].value = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::voxel::Block;
+
+    #[test]
+    fn test_readout_names_the_target_and_the_tool() {
+        let blocks = BlockRegistry::builtin();
+        let target = Some((WorldVoxelPos::new(4, -2, 7), Voxel::from(Block::Dirt)));
+        assert_eq!(interaction_text(&blocks, target, Some(Block::Glass.id())), "dirt at 4 -2 7\nPlacing glass");
+        assert_eq!(interaction_text(&blocks, None, Some(Block::Stone.id())), "Placing stone");
+        assert_eq!(interaction_text(&blocks, None, None), "Placing nothing");
+    }
+}
@@ -0,0 +1,93 @@
+//! Readout in the bottom left corner with the current measurement and the size of the selection box
+
+use bevy::prelude::*;
+
+use crate::{
+    engine::{chunk::Chunk, coords::WorldVoxelPos, ChunkData},
+    gameplay::{measure::Measurement, selection::Selection},
+};
+
+/// Larger selections are not counted voxel by voxel
+const MAX_COUNTED_VOLUME: u64 = 64 * 64 * 64;
+
+#[derive(Component)]
+struct MeasureText;
+
+pub struct MeasureHudPlugin;
+
+impl Plugin for MeasureHudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, spawn_measure_text)
+            .add_systems(Update, update_measure_text);
+    }
+}
+
+fn spawn_measure_text(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section("", TextStyle { font_size: 16.0, color: Color::WHITE, ..Default::default() })
+            .with_style(Style { position_type: PositionType::Absolute, left: Val::Px(8.0), bottom: Val::Px(8.0), ..Default::default() })
+            .with_background_color(Color::rgba(0.0, 0.0, 0.0, 0.4)),
+        MeasureText,
+    ));
+}
+
+fn update_measure_text(
+    mut text: Query<(&mut Text, &mut Visibility), With<MeasureText>>,
+    // Solid voxels of the selection, counted again when the selection changes
+    mut solid_count: Local<Option<((WorldVoxelPos, WorldVoxelPos), Option<u64>)>>,
+    measurement: Res<Measurement>,
+    selection: Res<Selection>,
+    chunk_data: Res<ChunkData>,
+    chunks: Query<&Chunk>,
+) {
+    let Ok((mut text, mut visibility)) = text.get_single_mut() else {
+        return;
+    };
+    let mut lines = Vec::new();
+
+    match measurement.points {
+        [Some(a), None] => lines.push(format!("Measure from {} {} {}, pick the second voxel", a.x, a.y, a.z)),
+        [Some(_), Some(_)] => {
+            let [x, y, z] = measurement.delta().unwrap_or_default();
+            lines.push(format!(
+                "Distance {:.2} (dx {} dy {} dz {}, {} blocks walked)",
+                measurement.distance().unwrap_or_default(),
+                x,
+                y,
+                z,
+                measurement.manhattan_distance().unwrap_or_default(),
+            ));
+        }
+        _ => {}
+    }
+
+    if let (Some(bounds), Some([x, y, z]), Some(volume)) = (selection.bounds(), selection.size(), selection.volume()) {
+        if solid_count.as_ref().map(|(counted, _)| *counted) != Some(bounds) {
+            let (min, max) = bounds;
+            let count = (volume <= MAX_COUNTED_VOLUME).then(|| {
+                let mut count = 0;
+                for vx in min.x..=max.x {
+                    for vy in min.y..=max.y {
+                        for vz in min.z..=max.z {
+                            let voxel = chunk_data.voxel_at(&chunks, WorldVoxelPos::new(vx, vy, vz));
+                            count += voxel.is_some_and(|voxel| !voxel.is_empty()) as u64;
+                        }
+                    }
+                }
+                count
+            });
+            *solid_count = Some((bounds, count));
+        }
+        let solid = match solid_count.as_ref().and_then(|(_, count)| *count) {
+            Some(count) => format!(", {} solid", count),
+            None => String::new(),
+        };
+        lines.push(format!("Selection {} x {} x {}, {} voxels{}", x, y, z, volume, solid));
+    }
+
+    *visibility = if lines.is_empty() { Visibility::Hidden } else { Visibility::Inherited };
+    let value = lines.join("\n");
+    if text.sections[0].value != value {
+        text.sections[0].value = value;
+    }
+}
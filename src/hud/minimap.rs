@@ -0,0 +1,138 @@
+//! Top-down minimap in the top right corner, drawn with egui. Every pixel is a world column
+//! colored by its surface height, from the [`HeightmapCache`] which holds the generator's heights
+//! and the highest solid voxel of every generated chunk. Columns nothing is known about stay dark.
+//! North is up, `F7` shows or hides the map.
+
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{engine::heightmap::HeightmapCache, flycam::PlayerCamera};
+
+const TOGGLE_KEY: KeyCode = KeyCode::F7;
+/// Columns from the center to the edge of the map, the map is twice as wide
+const MAP_RADIUS: i64 = 64;
+/// Screen pixels per map pixel
+const MAP_SCALE: f32 = 2.0;
+const UNKNOWN_COLOR: [u8; 4] = [0, 0, 0, 160];
+
+#[derive(Resource)]
+pub struct Minimap {
+    pub enabled: bool,
+    /// The map is redrawn this often, walking the heightmap for every pixel is not free
+    pub refresh: Timer,
+    image: Option<Handle<Image>>,
+}
+
+impl Default for Minimap {
+    fn default() -> Self {
+        Self { enabled: true, refresh: Timer::from_seconds(0.25, TimerMode::Repeating), image: None }
+    }
+}
+
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Minimap>()
+            .add_systems(Update, (toggle_minimap, update_minimap.after(toggle_minimap), show_minimap.after(update_minimap)));
+    }
+}
+
+/// Low ground is green, high ground brown and the highest white, `t` goes from 0 to 1
+pub fn height_color(t: f32) -> [u8; 4] {
+    const STOPS: [(f32, [f32; 3]); 4] = [(0.0, [40.0, 110.0, 50.0]), (0.4, [120.0, 170.0, 70.0]), (0.75, [140.0, 100.0, 60.0]), (1.0, [245.0, 245.0, 245.0])];
+    let t = t.clamp(0.0, 1.0);
+    let upper = STOPS.iter().position(|(stop, _)| *stop >= t).unwrap_or(STOPS.len() - 1).max(1);
+    let ((from, low), (to, high)) = (STOPS[upper - 1], STOPS[upper]);
+    let f = (t - from) / (to - from);
+    let [r, g, b] = [0, 1, 2].map(|channel| (low[channel] + (high[channel] - low[channel]) * f).round() as u8);
+    [r, g, b, 255]
+}
+
+fn toggle_minimap(mut minimap: ResMut<Minimap>, keys: Res<Input<KeyCode>>) {
+    if keys.just_pressed(TOGGLE_KEY) {
+        minimap.enabled = !minimap.enabled;
+    }
+}
+
+fn update_minimap(
+    mut minimap: ResMut<Minimap>,
+    mut images: ResMut<Assets<Image>>,
+    heightmap: Res<HeightmapCache>,
+    camera: Query<&Transform, With<PlayerCamera>>,
+    time: Res<Time>,
+) {
+    if !minimap.enabled || !minimap.refresh.tick(time.delta()).just_finished() {
+        return;
+    }
+    let Some(camera) = camera.iter().next() else {
+        return;
+    };
+
+    let size = (MAP_RADIUS * 2) as u32;
+    let handle = minimap
+        .image
+        .get_or_insert_with(|| {
+            let extent = Extent3d { width: size, height: size, depth_or_array_layers: 1 };
+            images.add(Image::new_fill(extent, TextureDimension::D2, &UNKNOWN_COLOR, TextureFormat::Rgba8UnormSrgb))
+        })
+        .clone();
+    let Some(image) = images.get_mut(&handle) else {
+        return;
+    };
+
+    let (center_x, center_z) = (camera.translation.x.floor() as i64, camera.translation.z.floor() as i64);
+    let heights = (0..MAP_RADIUS * 2)
+        .flat_map(|row| (0..MAP_RADIUS * 2).map(move |column| (column, row)))
+        .map(|(column, row)| heightmap.get(center_x - MAP_RADIUS + column, center_z - MAP_RADIUS + row))
+        .collect::<Vec<_>>();
+    // Stretch the colors over the heights in view, flat land would be a single color otherwise
+    let (min, max) = heights.iter().flatten().fold((i64::MAX, i64::MIN), |(min, max), height| (min.min(*height), max.max(*height)));
+    let range = (max - min).max(16) as f32;
+
+    for (pixel, height) in image.data.chunks_exact_mut(4).zip(heights) {
+        let color = height.map_or(UNKNOWN_COLOR, |height| height_color((height - min) as f32 / range));
+        pixel.copy_from_slice(&color);
+    }
+}
+
+fn show_minimap(mut contexts: EguiContexts, minimap: Res<Minimap>, camera: Query<&Transform, With<PlayerCamera>>) {
+    let (Some(handle), true) = (minimap.image.clone(), minimap.enabled) else {
+        return;
+    };
+    let texture = contexts.add_image(handle);
+    let heading = camera.iter().next().map_or(Vec3::NEG_Z, |transform| transform.forward());
+
+    egui::Area::new("minimap").anchor(egui::Align2::RIGHT_TOP, egui::vec2(-8.0, 40.0)).show(contexts.ctx_mut(), |ui| {
+        let size = egui::Vec2::splat(MAP_RADIUS as f32 * 2.0 * MAP_SCALE);
+        let rect = ui.image((texture, size)).rect;
+
+        // The camera is always in the center, the line shows where it looks
+        let center = rect.center();
+        let direction = egui::vec2(heading.x, heading.z).normalized();
+        let stroke = egui::Stroke::new(2.0, egui::Color32::WHITE);
+        if direction.is_finite() {
+            ui.painter().line_segment([center, center + direction * 10.0], stroke);
+        }
+        ui.painter().circle_filled(center, 3.0, egui::Color32::RED);
+        ui.painter().text(rect.center_top() + egui::vec2(0.0, 2.0), egui::Align2::CENTER_TOP, "N", egui::FontId::proportional(12.0), egui::Color32::WHITE);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_height_color_follows_the_stops() {
+        assert_eq!(height_color(0.0), [40, 110, 50, 255]);
+        assert_eq!(height_color(1.0), [245, 245, 245, 255]);
+        assert_eq!(height_color(2.0), height_color(1.0));
+        assert_eq!(height_color(-1.0), height_color(0.0));
+        // Half way between the first two stops
+        assert_eq!(height_color(0.2), [80, 140, 60, 255]);
+    }
+}
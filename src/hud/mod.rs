@@ -0,0 +1,23 @@
+use bevy::prelude::*;
+
+pub mod compass;
+pub mod hotbar;
+pub mod interaction;
+pub mod measure;
+#[cfg(feature = "debug-ui")]
+pub mod minimap;
+
+/// On-screen overlays drawn with bevy_ui, the minimap with egui
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(compass::CompassPlugin)
+            .add_plugins(hotbar::HotbarHudPlugin)
+            .add_plugins(interaction::InteractionHudPlugin)
+            .add_plugins(measure::MeasureHudPlugin);
+
+        #[cfg(feature = "debug-ui")]
+        app.add_plugins(minimap::MinimapPlugin);
+    }
+}
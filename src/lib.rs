@@ -0,0 +1,10 @@
+pub mod console;
+pub mod engine;
+pub mod flycam;
+pub mod debug;
+pub mod gameplay;
+pub mod hud;
+#[cfg(feature = "net")]
+pub mod net;
+#[cfg(test)]
+mod temp_dir;
@@ -1,15 +1,12 @@
 use bevy::{prelude::*, pbr::wireframe::{WireframePlugin, WireframeConfig}};
-use flycam::{prelude::debug::DebugPlugin, MovementSettings};
-
-mod flycam;
-pub mod engine;
-mod debug;
+use voxels_bevy_test::{console, flycam::{self, prelude::debug::DebugPlugin, MovementSettings}, engine, gameplay, hud};
+#[cfg(feature = "net")]
+use voxels_bevy_test::net;
 
 fn setup(
     mut commands: Commands, 
     mut meshes: ResMut<Assets<Mesh>>, 
-    mut materials: ResMut<Assets<StandardMaterial>>, 
-    mut ambient_light: ResMut<AmbientLight>) {
+    mut materials: ResMut<Assets<StandardMaterial>>) {
 
     // Insert cube to mark origin
     commands.spawn(PbrBundle {
@@ -20,13 +17,29 @@ fn setup(
         material: materials.add(Color::rgb(0.8, 0.7, 0.6).into()),
         ..Default::default()
     });
+}
 
-    ambient_light.brightness = 0.7;
+/// `--pregenerate <radius>` generates the spawn area before the world is shown
+fn pregenerate_radius_from_args() -> Option<u32> {
+    let mut args = std::env::args().skip_while(|arg| arg != "--pregenerate").skip(1);
+    args.next().and_then(|radius| radius.parse().ok())
 }
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
+    #[cfg(feature = "net")]
+    let net_args = net::NetArgs::from_env();
+    #[cfg(feature = "net")]
+    if let Some(addr) = &net_args.server {
+        net::server::run_headless(addr);
+        return;
+    }
+
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins.set(WindowPlugin {
+            // The window is closed by the shutdown sequence once the world is saved
+            close_when_requested: false,
+            ..Default::default()
+        }))
         .add_plugins(WireframePlugin)
         .insert_resource(WireframeConfig {
             global: true,
@@ -39,6 +52,19 @@ fn main() {
         })
         .add_plugins(flycam::PlayerPlugin)
         .add_plugins(engine::ChunkPlugin)
-        .add_systems(Startup, setup)
-        .run();
+        .insert_resource(engine::loading::LoadingSettings {
+            pregenerate_radius: pregenerate_radius_from_args(),
+            ..Default::default()
+        })
+        .add_plugins(gameplay::GameplayPlugin)
+        .add_plugins(hud::HudPlugin)
+        .add_plugins(console::ConsolePlugin)
+        .add_systems(Startup, setup);
+
+    #[cfg(feature = "net")]
+    if let Some(addr) = net_args.connect {
+        app.add_plugins(net::client::NetClientPlugin { addr });
+    }
+
+    app.run();
 }
@@ -0,0 +1,216 @@
+//! Client side of the networking. Chunks awaiting generation are requested from the server
+//! instead of being generated locally, voxel edits are sent to the server and only applied
+//! once it confirms them.
+
+use std::{
+    io,
+    net::{TcpStream, ToSocketAddrs},
+    sync::{mpsc::{self, Receiver, Sender, TryRecvError}, Mutex},
+};
+
+use bevy::prelude::*;
+
+use crate::engine::{
+    block_registry::BlockRegistry,
+    chunk::{Chunk, ChunkPosition},
+    coords::WorldVoxelPos,
+    generator::{request_remesh, AwaitingGeneration, ChunkSource, Generating, NeedsMesh},
+    serialization,
+    voxel::Voxel,
+    world_edits::WorldEdits,
+    ChunkData,
+};
+
+use super::protocol::{read_frame, write_frame, ClientMessage, ServerMessage, PROTOCOL_VERSION};
+
+/// Connection to the server
+#[derive(Resource)]
+pub struct NetClient {
+    outgoing: Sender<ClientMessage>,
+    incoming: Mutex<Receiver<ServerMessage>>,
+    connected: bool,
+}
+
+impl NetClient {
+    /// Changes sent by the server are read with the given blocks
+    pub fn connect(addr: impl ToSocketAddrs, blocks: BlockRegistry) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        let mut reader = stream.try_clone()?;
+        let mut writer = stream;
+
+        let (outgoing, outgoing_receiver) = mpsc::channel::<ClientMessage>();
+        std::thread::Builder::new()
+            .name("net-write".to_string())
+            .spawn(move || {
+                for message in outgoing_receiver {
+                    if write_frame(&mut writer, &message.encode()).is_err() {
+                        break;
+                    }
+                }
+            })?;
+
+        let (incoming_sender, incoming) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("net-read".to_string())
+            .spawn(move || {
+                while let Ok(message) = read_frame(&mut reader).and_then(|frame| ServerMessage::decode(&frame, &blocks)) {
+                    if incoming_sender.send(message).is_err() {
+                        break;
+                    }
+                }
+            })?;
+
+        let client = Self {
+            outgoing,
+            incoming: Mutex::new(incoming),
+            connected: true,
+        };
+        client.send(ClientMessage::Hello { version: PROTOCOL_VERSION });
+        Ok(client)
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    pub fn send(&self, message: ClientMessage) {
+        let _ = self.outgoing.send(message);
+    }
+}
+
+/// Marks chunks requested from the server
+#[derive(Component)]
+pub struct AwaitingRemote {
+    pub chunk_pos: ChunkPosition,
+}
+
+/// Asks the server to change a voxel
+#[derive(Event, Debug, Clone)]
+pub struct EditVoxel {
+    pub pos: WorldVoxelPos,
+    pub voxel: Voxel,
+}
+
+pub struct NetClientPlugin {
+    pub addr: String,
+}
+
+impl Plugin for NetClientPlugin {
+    fn build(&self, app: &mut App) {
+        let blocks = app.world.get_resource::<BlockRegistry>().cloned().unwrap_or_default();
+        let client = NetClient::connect(&self.addr, blocks).expect("Failed to connect to server");
+        info!("Connected to {}", self.addr);
+
+        app.insert_resource(client)
+            .insert_resource(ChunkSource::Remote)
+            .add_event::<EditVoxel>()
+            .add_systems(Update, (
+                request_remote_chunks,
+                receive_server_messages,
+                send_voxel_edits,
+            ));
+    }
+}
+
+fn request_remote_chunks(
+    mut commands: Commands,
+    client: Res<NetClient>,
+    query: Query<(Entity, &AwaitingGeneration)>,
+) {
+    if !client.is_connected() {
+        return;
+    }
+    for (entity, awaiting_generation) in query.iter() {
+        let chunk_pos = awaiting_generation.chunk_pos;
+        client.send(ClientMessage::RequestChunk(chunk_pos));
+        commands.entity(entity)
+            .insert(AwaitingRemote { chunk_pos })
+            .remove::<AwaitingGeneration>();
+    }
+}
+
+fn receive_server_messages(
+    mut commands: Commands,
+    mut client: ResMut<NetClient>,
+    mut chunk_data: ResMut<ChunkData>,
+    mut chunk_source: ResMut<ChunkSource>,
+    mut world_edits: ResMut<WorldEdits>,
+    mut chunks: Query<&mut Chunk>,
+    awaiting_remote: Query<(Entity, &AwaitingRemote)>,
+    blocks: Res<BlockRegistry>,
+) {
+    let mut messages = Vec::new();
+    let mut disconnected = false;
+    {
+        let incoming = client.incoming.lock().unwrap();
+        loop {
+            match incoming.try_recv() {
+                Ok(message) => messages.push(message),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    disconnected = true;
+                    break;
+                }
+            }
+        }
+    }
+    if disconnected && client.connected {
+        error!("Lost connection to the server, generating chunks locally");
+        client.connected = false;
+        *chunk_source = ChunkSource::Local;
+        for (entity, awaiting_remote) in awaiting_remote.iter() {
+            commands.entity(entity)
+                .remove::<AwaitingRemote>()
+                .insert(AwaitingGeneration { chunk_pos: awaiting_remote.chunk_pos });
+        }
+    }
+
+    for message in messages {
+        match message {
+            ServerMessage::Welcome { version } => {
+                if version != PROTOCOL_VERSION {
+                    error!("Server uses protocol version {}, expected {}", version, PROTOCOL_VERSION);
+                }
+            }
+            ServerMessage::Chunk(data) => {
+                let chunk = match serialization::decode(&data, &blocks) {
+                    Ok(chunk) => chunk,
+                    Err(err) => {
+                        warn!("Received invalid chunk: {}", err);
+                        continue;
+                    }
+                };
+                let chunk_pos = chunk.position;
+                if let Some(entity) = chunk_data.awaiting_generation.get(&chunk_pos).copied() {
+                    commands.entity(entity)
+                        .remove::<(AwaitingRemote, Generating)>()
+                        .insert((chunk, NeedsMesh));
+                    chunk_data.loaded.insert(chunk_pos, entity);
+                    chunk_data.awaiting_generation.remove(&chunk_pos);
+                } else if let Some(entity) = chunk_data.loaded.get(&chunk_pos).copied() {
+                    // The whole chunk changed on the server
+                    if let Ok(mut existing) = chunks.get_mut(entity) {
+                        *existing = chunk;
+                        request_remesh(&mut commands, &mut chunk_data, entity, chunk_pos);
+                    }
+                }
+            }
+            ServerMessage::VoxelChanged(pos, voxel) => {
+                // Chunks that are not loaded come from the server with the change already in them
+                if chunk_data.loaded.contains_key(&pos.split().0) {
+                    world_edits.set(pos, voxel);
+                }
+            }
+            ServerMessage::EditRejected(pos) => {
+                warn!("Server rejected edit at {:?}", pos);
+            }
+        }
+    }
+}
+
+fn send_voxel_edits(client: Res<NetClient>, mut events: EventReader<EditVoxel>) {
+    for event in events.read() {
+        client.send(ClientMessage::SetVoxel(event.pos, event.voxel));
+    }
+}
@@ -0,0 +1,35 @@
+//! LAN prototype of a server/client mode, enabled with the `net` feature.
+//!
+//! Run `--server [addr]` to start a headless server and `--connect <addr>` to join it.
+
+pub mod protocol;
+pub mod server;
+pub mod client;
+
+pub const DEFAULT_ADDR: &str = "127.0.0.1:7878";
+
+/// Networking related command line arguments
+#[derive(Debug, Default)]
+pub struct NetArgs {
+    /// Address to run a headless server on
+    pub server: Option<String>,
+    /// Address of the server to connect to
+    pub connect: Option<String>,
+}
+
+impl NetArgs {
+    pub fn from_env() -> Self {
+        let mut args = Self::default();
+        let mut iter = std::env::args().skip(1).peekable();
+        while let Some(arg) = iter.next() {
+            // The address is optional, `--server` alone uses the default one
+            let value = iter.next_if(|next| !next.starts_with("--"));
+            match arg.as_str() {
+                "--server" => args.server = Some(value.unwrap_or_else(|| DEFAULT_ADDR.to_string())),
+                "--connect" => args.connect = Some(value.unwrap_or_else(|| DEFAULT_ADDR.to_string())),
+                _ => {}
+            }
+        }
+        args
+    }
+}
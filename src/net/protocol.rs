@@ -0,0 +1,228 @@
+//! Messages exchanged between the server and its clients.
+//!
+//! Every message is sent as a frame: payload length as u32 little endian followed by the payload.
+//! The first payload byte is the message tag, chunks are sent in the [`serialization`] format.
+//!
+//! [`serialization`]: crate::engine::serialization
+
+use std::io::{self, Read, Write};
+
+use crate::engine::{block_registry::BlockRegistry, chunk::ChunkPosition, coords::WorldVoxelPos, voxel::Voxel};
+
+/// Raised whenever messages or voxel codes change, e.g. when builtin blocks are added
+pub const PROTOCOL_VERSION: u16 = 2;
+/// Frames larger than this are treated as a broken connection
+const MAX_FRAME_LEN: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientMessage {
+    Hello { version: u16 },
+    RequestChunk(ChunkPosition),
+    SetVoxel(WorldVoxelPos, Voxel),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerMessage {
+    Welcome { version: u16 },
+    /// Encoded chunk, sent as a reply to a request and again whenever the whole chunk changes
+    Chunk(Vec<u8>),
+    VoxelChanged(WorldVoxelPos, Voxel),
+    EditRejected(WorldVoxelPos),
+}
+
+impl ClientMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            Self::Hello { version } => {
+                bytes.push(0);
+                bytes.extend_from_slice(&version.to_le_bytes());
+            }
+            Self::RequestChunk(chunk) => {
+                bytes.push(1);
+                write_chunk_position(&mut bytes, chunk);
+            }
+            Self::SetVoxel(pos, voxel) => {
+                bytes.push(2);
+                write_voxel_pos(&mut bytes, pos);
+                bytes.extend_from_slice(&voxel.to_code().to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8], blocks: &BlockRegistry) -> io::Result<Self> {
+        let mut input = Input(bytes);
+        let message = match input.u8()? {
+            0 => Self::Hello { version: input.u16()? },
+            1 => Self::RequestChunk(input.chunk_position()?),
+            2 => Self::SetVoxel(input.voxel_pos()?, input.voxel(blocks)?),
+            tag => return Err(invalid_data(format!("unknown client message {}", tag))),
+        };
+        input.finish()?;
+        Ok(message)
+    }
+}
+
+impl ServerMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            Self::Welcome { version } => {
+                bytes.push(0);
+                bytes.extend_from_slice(&version.to_le_bytes());
+            }
+            Self::Chunk(data) => {
+                bytes.push(1);
+                bytes.extend_from_slice(data);
+            }
+            Self::VoxelChanged(pos, voxel) => {
+                bytes.push(2);
+                write_voxel_pos(&mut bytes, pos);
+                bytes.extend_from_slice(&voxel.to_code().to_le_bytes());
+            }
+            Self::EditRejected(pos) => {
+                bytes.push(3);
+                write_voxel_pos(&mut bytes, pos);
+            }
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8], blocks: &BlockRegistry) -> io::Result<Self> {
+        let mut input = Input(bytes);
+        let message = match input.u8()? {
+            0 => Self::Welcome { version: input.u16()? },
+            1 => return Ok(Self::Chunk(input.0.to_vec())),
+            2 => Self::VoxelChanged(input.voxel_pos()?, input.voxel(blocks)?),
+            3 => Self::EditRejected(input.voxel_pos()?),
+            tag => return Err(invalid_data(format!("unknown server message {}", tag))),
+        };
+        input.finish()?;
+        Ok(message)
+    }
+}
+
+pub fn write_frame(writer: &mut impl Write, payload: &[u8]) -> io::Result<()> {
+    writer.write_all(&(payload.len() as u32).to_le_bytes())?;
+    writer.write_all(payload)?;
+    writer.flush()
+}
+
+pub fn read_frame(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(invalid_data(format!("frame of {} bytes is too large", len)));
+    }
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload)?;
+    Ok(payload)
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_chunk_position(bytes: &mut Vec<u8>, chunk: &ChunkPosition) {
+    bytes.extend_from_slice(&chunk.x.to_le_bytes());
+    bytes.extend_from_slice(&chunk.y.to_le_bytes());
+    bytes.extend_from_slice(&chunk.z.to_le_bytes());
+}
+
+fn write_voxel_pos(bytes: &mut Vec<u8>, pos: &WorldVoxelPos) {
+    bytes.extend_from_slice(&pos.x.to_le_bytes());
+    bytes.extend_from_slice(&pos.y.to_le_bytes());
+    bytes.extend_from_slice(&pos.z.to_le_bytes());
+}
+
+struct Input<'a>(&'a [u8]);
+
+impl<'a> Input<'a> {
+    fn take<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        if self.0.len() < N {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let (head, rest) = self.0.split_at(N);
+        self.0 = rest;
+        Ok(head.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    fn chunk_position(&mut self) -> io::Result<ChunkPosition> {
+        Ok(ChunkPosition::new(
+            i32::from_le_bytes(self.take()?),
+            i32::from_le_bytes(self.take()?),
+            i32::from_le_bytes(self.take()?),
+        ))
+    }
+
+    fn voxel_pos(&mut self) -> io::Result<WorldVoxelPos> {
+        Ok(WorldVoxelPos::new(
+            i64::from_le_bytes(self.take()?),
+            i64::from_le_bytes(self.take()?),
+            i64::from_le_bytes(self.take()?),
+        ))
+    }
+
+    fn voxel(&mut self, blocks: &BlockRegistry) -> io::Result<Voxel> {
+        let code = self.u16()?;
+        Voxel::from_code(code, blocks).ok_or_else(|| invalid_data(format!("unknown voxel code {}", code)))
+    }
+
+    fn finish(&self) -> io::Result<()> {
+        match self.0.is_empty() {
+            true => Ok(()),
+            false => Err(invalid_data(format!("{} trailing bytes", self.0.len()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::voxel::Block;
+
+    #[test]
+    fn test_message_roundtrip() {
+        let blocks = BlockRegistry::builtin();
+        let client_messages = [
+            ClientMessage::Hello { version: PROTOCOL_VERSION },
+            ClientMessage::RequestChunk(ChunkPosition::new(-1, 2, -3)),
+            ClientMessage::SetVoxel(WorldVoxelPos::new(-40, 7, 1 << 40), Voxel::from(Block::Glass)),
+        ];
+        for message in client_messages {
+            assert_eq!(ClientMessage::decode(&message.encode(), &blocks).unwrap(), message);
+        }
+
+        let server_messages = [
+            ServerMessage::Welcome { version: PROTOCOL_VERSION },
+            ServerMessage::Chunk(vec![1, 2, 3]),
+            ServerMessage::VoxelChanged(WorldVoxelPos::new(0, -64, 5), Voxel::Empty),
+            ServerMessage::EditRejected(WorldVoxelPos::new(1, 2, 3)),
+        ];
+        for message in server_messages {
+            assert_eq!(ServerMessage::decode(&message.encode(), &blocks).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn test_frames() {
+        let mut stream = Vec::new();
+        write_frame(&mut stream, b"first").unwrap();
+        write_frame(&mut stream, b"").unwrap();
+
+        let mut reader = stream.as_slice();
+        assert_eq!(read_frame(&mut reader).unwrap(), b"first");
+        assert_eq!(read_frame(&mut reader).unwrap(), b"");
+        assert!(read_frame(&mut reader).is_err());
+    }
+}
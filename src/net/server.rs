@@ -0,0 +1,274 @@
+//! Server owning the authoritative voxel data. It generates chunks requested by clients,
+//! validates their edits and sends every change to the clients holding the chunk.
+//! Chunks only live in memory for now, nothing is saved when the server stops.
+
+use std::{
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    io,
+    sync::{mpsc::{self, Receiver, Sender}, Mutex},
+    time::Duration,
+};
+
+use bevy::{
+    app::ScheduleRunnerPlugin,
+    log::LogPlugin,
+    prelude::*,
+    tasks::{block_on, AsyncComputeTaskPool, Task},
+    utils::{HashMap, HashSet},
+};
+
+use crate::engine::{
+    block_registry::BlockRegistry,
+    chunk::{Chunk, ChunkPosition},
+    edit::check_edit,
+    generator::{PerlinHeightmapWorldGenerator, WorldGeneratorConfig},
+    pending_edits::PendingEdits,
+    serialization,
+};
+
+use super::protocol::{read_frame, write_frame, ClientMessage, ServerMessage, PROTOCOL_VERSION};
+
+pub type ClientId = u32;
+
+enum ConnectionEvent {
+    Connected(ClientId, Sender<ServerMessage>),
+    Message(ClientId, ClientMessage),
+    Disconnected(ClientId),
+}
+
+/// Accepts client connections, every client gets its own reader and writer thread
+#[derive(Resource)]
+pub struct NetServer {
+    local_addr: SocketAddr,
+    events: Mutex<Receiver<ConnectionEvent>>,
+    clients: HashMap<ClientId, Sender<ServerMessage>>,
+}
+
+impl NetServer {
+    /// Edits of the clients are read with the given blocks
+    pub fn bind(addr: impl ToSocketAddrs, blocks: BlockRegistry) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let (sender, events) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("net-accept".to_string())
+            .spawn(move || accept_connections(listener, sender, blocks))?;
+
+        Ok(Self {
+            local_addr,
+            events: Mutex::new(events),
+            clients: HashMap::default(),
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    pub fn send(&self, client: ClientId, message: ServerMessage) {
+        if let Some(sender) = self.clients.get(&client) {
+            // A closed channel means the client is disconnecting, it is removed once that is reported
+            let _ = sender.send(message);
+        }
+    }
+}
+
+fn accept_connections(listener: TcpListener, events: Sender<ConnectionEvent>, blocks: BlockRegistry) {
+    let mut next_id: ClientId = 0;
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                warn!("Failed to accept connection: {}", err);
+                continue;
+            }
+        };
+        let id = next_id;
+        next_id += 1;
+        if let Err(err) = spawn_connection(id, stream, events.clone(), blocks.clone()) {
+            warn!("Failed to set up connection {}: {}", id, err);
+        }
+    }
+}
+
+fn spawn_connection(id: ClientId, stream: TcpStream, events: Sender<ConnectionEvent>, blocks: BlockRegistry) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    let mut reader = stream.try_clone()?;
+    let mut writer = stream;
+
+    let (sender, outgoing) = mpsc::channel::<ServerMessage>();
+    std::thread::Builder::new()
+        .name(format!("net-write-{}", id))
+        .spawn(move || {
+            for message in outgoing {
+                if write_frame(&mut writer, &message.encode()).is_err() {
+                    break;
+                }
+            }
+            let _ = writer.shutdown(std::net::Shutdown::Both);
+        })?;
+
+    let _ = events.send(ConnectionEvent::Connected(id, sender));
+    std::thread::Builder::new()
+        .name(format!("net-read-{}", id))
+        .spawn(move || {
+            while let Ok(message) = read_frame(&mut reader).and_then(|frame| ClientMessage::decode(&frame, &blocks)) {
+                if events.send(ConnectionEvent::Message(id, message)).is_err() {
+                    return;
+                }
+            }
+            let _ = events.send(ConnectionEvent::Disconnected(id));
+        })?;
+    Ok(())
+}
+
+/// Authoritative chunk data of the server
+#[derive(Resource, Default)]
+pub struct ServerWorld {
+    chunks: HashMap<ChunkPosition, Chunk>,
+    generating: HashMap<ChunkPosition, Task<(Chunk, PendingEdits)>>,
+    /// Clients that requested each chunk, they receive every change to it
+    subscribers: HashMap<ChunkPosition, HashSet<ClientId>>,
+    pending_edits: PendingEdits,
+}
+
+impl ServerWorld {
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    fn send_chunk(&self, server: &NetServer, chunk: &Chunk) {
+        let Some(subscribers) = self.subscribers.get(&chunk.position) else {
+            return;
+        };
+        let data = serialization::encode(chunk);
+        for client in subscribers {
+            server.send(*client, ServerMessage::Chunk(data.clone()));
+        }
+    }
+}
+
+pub struct NetServerPlugin {
+    pub addr: String,
+}
+
+impl Plugin for NetServerPlugin {
+    fn build(&self, app: &mut App) {
+        // Clients edit the blocks the chunks are generated with
+        let blocks = app.world.resource::<WorldGeneratorConfig>().blocks.clone();
+        let server = NetServer::bind(&self.addr, blocks).expect("Failed to start server");
+        info!("Listening on {}", server.local_addr());
+
+        app.insert_resource(server)
+            .init_resource::<ServerWorld>()
+            .add_systems(Update, (
+                handle_client_messages,
+                finish_generation.after(handle_client_messages),
+            ));
+    }
+}
+
+/// Runs a server without any rendering until the process is stopped
+pub fn run_headless(addr: &str) {
+    App::new()
+        .add_plugins(MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(1.0 / 60.0))))
+        .add_plugins(LogPlugin::default())
+        .insert_resource(WorldGeneratorConfig::default_with(PerlinHeightmapWorldGenerator::default()))
+        .add_plugins(NetServerPlugin { addr: addr.to_string() })
+        .run();
+}
+
+fn handle_client_messages(
+    mut server: ResMut<NetServer>,
+    mut world: ResMut<ServerWorld>,
+    config: Res<WorldGeneratorConfig>,
+) {
+    let events = server.events.lock().unwrap().try_iter().collect::<Vec<_>>();
+    for event in events {
+        match event {
+            ConnectionEvent::Connected(client, sender) => {
+                server.clients.insert(client, sender);
+                info!("Client {} connected, {} clients online", client, server.client_count());
+            }
+            ConnectionEvent::Disconnected(client) => {
+                server.clients.remove(&client);
+                info!("Client {} disconnected, {} clients online, {} chunks in memory", client, server.client_count(), world.chunk_count());
+                for subscribers in world.subscribers.values_mut() {
+                    subscribers.remove(&client);
+                }
+            }
+            ConnectionEvent::Message(client, ClientMessage::Hello { version }) => {
+                server.send(client, ServerMessage::Welcome { version: PROTOCOL_VERSION });
+                if version != PROTOCOL_VERSION {
+                    warn!("Client {} uses protocol version {}, expected {}", client, version, PROTOCOL_VERSION);
+                    server.clients.remove(&client);
+                }
+            }
+            ConnectionEvent::Message(client, ClientMessage::RequestChunk(chunk_pos)) => {
+                world.subscribers.entry(chunk_pos).or_default().insert(client);
+                if let Some(chunk) = world.chunks.get(&chunk_pos) {
+                    server.send(client, ServerMessage::Chunk(serialization::encode(chunk)));
+                } else if !world.generating.contains_key(&chunk_pos) {
+                    let config = config.clone();
+                    let task = AsyncComputeTaskPool::get().spawn(async move { config.generate(chunk_pos) });
+                    world.generating.insert(chunk_pos, task);
+                }
+            }
+            ConnectionEvent::Message(client, ClientMessage::SetVoxel(pos, voxel)) => {
+                let (chunk_pos, local) = pos.split();
+                let Some(chunk) = world.chunks.get_mut(&chunk_pos) else {
+                    server.send(client, ServerMessage::EditRejected(pos));
+                    continue;
+                };
+                if check_edit(&config.blocks, pos, chunk.get(local), voxel).is_err() {
+                    server.send(client, ServerMessage::EditRejected(pos));
+                    continue;
+                }
+                chunk.set(local, voxel);
+                chunk.recalculate_visibility_mask(&config.blocks);
+                for subscriber in world.subscribers.get(&chunk_pos).into_iter().flatten() {
+                    server.send(*subscriber, ServerMessage::VoxelChanged(pos, voxel));
+                }
+            }
+        }
+    }
+}
+
+/// Stores generated chunks and sends them to the clients waiting for them
+fn finish_generation(server: Res<NetServer>, mut world: ResMut<ServerWorld>, config: Res<WorldGeneratorConfig>) {
+    let finished = world.generating.iter_mut()
+        .filter_map(|(chunk_pos, task)| block_on(futures_lite::future::poll_once(task)).map(|result| (*chunk_pos, result)))
+        .collect::<Vec<_>>();
+    if finished.is_empty() {
+        return;
+    }
+
+    for (chunk_pos, (mut chunk, overflow)) in finished {
+        world.generating.remove(&chunk_pos);
+        world.pending_edits.merge(overflow);
+        if world.pending_edits.apply(&mut chunk, &config.blocks) {
+            chunk.recalculate_visibility_mask(&config.blocks);
+        }
+        world.send_chunk(&server, &chunk);
+        world.chunks.insert(chunk_pos, chunk);
+    }
+
+    // Structures overflowing into chunks clients already have are sent as whole chunks again
+    let world = &mut *world;
+    let targets = world.pending_edits.chunks()
+        .filter(|chunk_pos| world.chunks.contains_key(*chunk_pos))
+        .copied()
+        .collect::<Vec<_>>();
+    for chunk_pos in targets {
+        let chunk = world.chunks.get_mut(&chunk_pos).unwrap();
+        if world.pending_edits.apply(chunk, &config.blocks) {
+            chunk.recalculate_visibility_mask(&config.blocks);
+        }
+        let chunk = &world.chunks[&chunk_pos];
+        world.send_chunk(&server, chunk);
+    }
+}
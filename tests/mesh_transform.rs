@@ -0,0 +1,161 @@
+//! Meshes chunks through the streaming systems and checks that mesh vertices moved by the chunk entity
+//! transform land exactly on the world space corners of their voxels, for chunks on both sides of the origin.
+//! Voxels sit on the chunk borders, where padding and off-by-one mistakes in the mesher show up first.
+//! The bounds of chunk meshes must cover exactly their chunk, in every octant.
+
+use std::{collections::BTreeSet, time::Duration};
+
+use bevy::{asset::AssetPlugin, prelude::*, render::{mesh::VertexAttributeValues, primitives::Aabb}};
+use voxels_bevy_test::engine::{
+    block_registry::BlockRegistry,
+    chunk::{Chunk, ChunkPosition, CHUNK_SIZE},
+    chunk_log::ChunkLogLevel,
+    chunk_material::{ChunkMaterial, ChunkMaterials, ClipPlane},
+    coords::{LocalVoxelPos, WorldVoxelPos},
+    generator::{apply_meshes, schedule_chunk_meshing, GeneratorState, NeedsMesh, WorldGeneratorConfig},
+    meshing::{ChunkVertexFormat, MeshingStrategy},
+    shapes::ShapeRegistry,
+    spawn_queue::ChunkSpawnQueue,
+    streaming_budget::StreamingBudget,
+    voxel::{Block, Voxel},
+    ChunkData,
+};
+
+const LAST: u8 = CHUNK_SIZE as u8 - 1;
+/// No two of these touch, so none of their faces can be merged
+const VOXELS: [(u8, u8, u8); 3] = [(0, 0, 0), (LAST, LAST, LAST), (LAST, 0, 0)];
+
+fn meshing_app() -> App {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+        .init_asset::<Mesh>()
+        .init_asset::<ChunkMaterial>()
+        .init_resource::<ChunkData>()
+        .init_resource::<ChunkSpawnQueue>()
+        .init_resource::<ClipPlane>()
+        .init_resource::<ChunkMaterials>()
+        .init_resource::<StreamingBudget>()
+        .init_resource::<BlockRegistry>()
+        .init_resource::<ShapeRegistry>()
+        .init_resource::<MeshingStrategy>()
+        .init_resource::<ChunkVertexFormat>()
+        .init_resource::<ChunkLogLevel>()
+        .insert_resource(GeneratorState::Generating)
+        .insert_resource(WorldGeneratorConfig::default_flat())
+        .add_systems(Update, (schedule_chunk_meshing, apply_meshes).chain());
+    app
+}
+
+fn spawn_chunk(app: &mut App, position: ChunkPosition) -> Entity {
+    let mut chunk = Chunk::new(position);
+    for (x, y, z) in VOXELS {
+        chunk.set(LocalVoxelPos::new(x, y, z), Voxel::from(Block::Stone));
+    }
+    let entity = app.world.spawn((chunk, NeedsMesh)).id();
+    app.world.resource_mut::<ChunkData>().loaded.insert(position, entity);
+    entity
+}
+
+/// Runs frames until every entity got its mesh from the meshing tasks
+fn wait_for_meshes(app: &mut App, entities: &[Entity]) {
+    for _ in 0..1000 {
+        app.update();
+        if entities.iter().all(|entity| app.world.get::<Handle<Mesh>>(*entity).is_some()) {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    panic!("chunks were not meshed in time");
+}
+
+/// Mesh vertices in world space, asserting they lie on whole world units
+fn world_vertices(app: &App, entity: Entity) -> (usize, BTreeSet<[i64; 3]>) {
+    let transform = app.world.get::<Transform>(entity).unwrap();
+    let handle = app.world.get::<Handle<Mesh>>(entity).unwrap();
+    let mesh = app.world.resource::<Assets<Mesh>>().get(handle).unwrap();
+    let Some(VertexAttributeValues::Float32x3(positions)) = mesh.attribute(Mesh::ATTRIBUTE_POSITION) else {
+        panic!("chunk mesh has no positions");
+    };
+    let vertices = positions
+        .iter()
+        .map(|position| {
+            let world = transform.transform_point(Vec3::from_array(*position));
+            assert_eq!(world, world.round(), "vertex {:?} is not on a voxel corner", world);
+            [world.x as i64, world.y as i64, world.z as i64]
+        })
+        .collect();
+    (positions.len(), vertices)
+}
+
+fn voxel_corners(pos: WorldVoxelPos) -> impl Iterator<Item = [i64; 3]> {
+    (0..8).map(move |corner| [pos.x + (corner & 1), pos.y + (corner >> 1 & 1), pos.z + (corner >> 2 & 1)])
+}
+
+#[test]
+fn test_mesh_vertices_match_world_voxel_corners() {
+    // One chunk in every octant, and the chunks touching the origin
+    let positions = [
+        ChunkPosition::new(0, 0, 0),
+        ChunkPosition::new(2, 1, 3),
+        ChunkPosition::new(-1, -1, -1),
+        ChunkPosition::new(-3, 2, 2),
+        ChunkPosition::new(1, -2, -4),
+        ChunkPosition::new(-2, -3, 1),
+        ChunkPosition::new(3, -1, 2),
+        ChunkPosition::new(-4, 1, -2),
+        ChunkPosition::new(2, 3, -1),
+    ];
+    let mut app = meshing_app();
+    let entities = positions.iter().map(|position| spawn_chunk(&mut app, *position)).collect::<Vec<_>>();
+    wait_for_meshes(&mut app, &entities);
+
+    for (position, entity) in positions.iter().zip(entities) {
+        let expected = VOXELS
+            .iter()
+            .flat_map(|&(x, y, z)| voxel_corners(WorldVoxelPos::from_local(position, LocalVoxelPos::new(x, y, z))))
+            .collect::<BTreeSet<_>>();
+        let (vertex_count, vertices) = world_vertices(&app, entity);
+        // Six separate faces of four vertices for every voxel
+        assert_eq!(vertex_count, VOXELS.len() * 6 * 4, "chunk {:?}", position);
+        assert_eq!(vertices, expected, "chunk {:?}", position);
+    }
+}
+
+#[test]
+fn test_mesh_bounds_cover_the_chunk() {
+    let positions = [ChunkPosition::new(-1, -1, -1), ChunkPosition::new(1, -2, 3), ChunkPosition::new(-3, 2, -1)];
+    let mut app = meshing_app();
+    let entities = positions.iter().map(|position| spawn_chunk(&mut app, *position)).collect::<Vec<_>>();
+    wait_for_meshes(&mut app, &entities);
+
+    for (position, entity) in positions.iter().zip(entities) {
+        let transform = app.world.get::<Transform>(entity).unwrap();
+        let aabb = app.world.get::<Aabb>(entity).expect("chunk mesh has no bounds");
+        let world_min = transform.transform_point(Vec3::from(aabb.min()));
+        let world_max = transform.transform_point(Vec3::from(aabb.max()));
+        assert_eq!(world_min, position.aabb().min().into(), "chunk {:?}", position);
+        assert_eq!(world_max, position.aabb().max().into(), "chunk {:?}", position);
+
+        let (_, vertices) = world_vertices(&app, entity);
+        for vertex in vertices {
+            let vertex = Vec3::new(vertex[0] as f32, vertex[1] as f32, vertex[2] as f32);
+            assert!(vertex.cmpge(world_min).all() && vertex.cmple(world_max).all(), "vertex {:?} outside of chunk {:?}", vertex, position);
+        }
+    }
+}
+
+#[test]
+fn test_neighbouring_chunk_meshes_meet() {
+    // The last voxel of one chunk and the first voxel of the next share a world space face
+    let (left, right) = (ChunkPosition::new(-1, 0, 0), ChunkPosition::new(0, 0, 0));
+    let mut app = meshing_app();
+    let entities = [spawn_chunk(&mut app, left), spawn_chunk(&mut app, right)];
+    wait_for_meshes(&mut app, &entities);
+
+    let (_, left_vertices) = world_vertices(&app, entities[0]);
+    let (_, right_vertices) = world_vertices(&app, entities[1]);
+    let shared = left_vertices.intersection(&right_vertices).copied().collect::<BTreeSet<_>>();
+    let border = WorldVoxelPos::from_local(&right, LocalVoxelPos::new(0, 0, 0));
+    assert!(!shared.is_empty());
+    assert!(shared.iter().all(|vertex| vertex[0] == border.x), "{:?}", shared);
+}
@@ -0,0 +1,123 @@
+//! Loads the saves written by every older format version from `tests/fixtures/saves` and checks
+//! the migrations turn them into what the current version would have saved. Each fixture is a
+//! world directory holding a `world.ron` and a single chunk written by that version.
+
+use std::{fs, path::Path, sync::Arc};
+
+use voxels_bevy_test::engine::{
+    block_registry::BlockRegistry,
+    chunk::{Chunk, ChunkPosition},
+    coords::LocalVoxelPos,
+    generator::PerlinHeightmapWorldGenerator,
+    migration::MigrationRegistry,
+    persistence::ChunkStorage,
+    serialization,
+    voxel::{Block, Voxel, VoxelMetadata},
+    world_meta::{self, WorldMetadata},
+};
+
+#[path = "../src/temp_dir.rs"]
+mod temp_dir;
+use temp_dir::TempDir;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/saves");
+
+struct Fixture {
+    dir: &'static str,
+    /// Version of the saved chunk, the metadata is in the version of the same release
+    chunk_version: u16,
+    metadata_version: u32,
+    chunk: ChunkPosition,
+}
+
+const FIXTURES_BY_VERSION: [Fixture; 4] = [
+    Fixture { dir: "v1", chunk_version: 1, metadata_version: 0, chunk: ChunkPosition { x: 0, y: 0, z: 0 } },
+    Fixture { dir: "v2", chunk_version: 2, metadata_version: 1, chunk: ChunkPosition { x: -1, y: 0, z: 2 } },
+    Fixture { dir: "v3", chunk_version: 3, metadata_version: 2, chunk: ChunkPosition { x: 3, y: -1, z: -2 } },
+    Fixture { dir: "v4", chunk_version: 4, metadata_version: 2, chunk: ChunkPosition { x: 0, y: 1, z: 0 } },
+];
+
+/// The chunk each fixture saved, built with what its version could store
+fn saved_chunk(version: u16, position: ChunkPosition) -> Chunk {
+    let mut chunk = Chunk::new(position);
+    chunk.generate_with(|_, pos| match pos.y {
+        y if y < 4 => Voxel::from(Block::Dirt),
+        4 => Voxel::from(Block::Grass),
+        // Water became a builtin block in version 4
+        y if y < 8 && version >= 4 => Voxel::from(Block::Water),
+        _ => Voxel::Empty,
+    });
+    chunk.set(LocalVoxelPos::new(1, 5, 1), Block::Stone.into());
+    // Voxel metadata was added in version 2
+    if version >= 2 {
+        chunk.set_metadata(LocalVoxelPos::new(1, 5, 1), VoxelMetadata::new([3, 1]));
+    }
+    // Tints were added in version 3
+    if version >= 3 {
+        chunk.set(LocalVoxelPos::new(6, 5, 6), Block::Glass.into());
+        assert!(chunk.set_tint(LocalVoxelPos::new(6, 5, 6), Some([200, 40, 40])));
+    }
+    chunk
+}
+
+/// Loading moves damaged chunk files aside, so every test works on a copy of the fixture
+fn copy_fixture(fixture: &Fixture) -> TempDir {
+    fn copy_dir(from: &Path, to: &Path) {
+        fs::create_dir_all(to).unwrap();
+        for entry in fs::read_dir(from).unwrap() {
+            let entry = entry.unwrap();
+            match entry.file_type().unwrap().is_dir() {
+                true => copy_dir(&entry.path(), &to.join(entry.file_name())),
+                false => drop(fs::copy(entry.path(), to.join(entry.file_name())).unwrap()),
+            }
+        }
+    }
+
+    let root = TempDir::new(&format!("save-migration-{}", fixture.dir));
+    copy_dir(&Path::new(FIXTURES).join(fixture.dir), &root);
+    root
+}
+
+#[test]
+fn test_every_old_version_has_a_migration_and_a_fixture() {
+    let registry = MigrationRegistry::builtin();
+    assert_eq!(registry.chunk_versions().collect::<Vec<_>>(), (1..serialization::FORMAT_VERSION).collect::<Vec<_>>());
+    assert_eq!(registry.metadata_versions().collect::<Vec<_>>(), (0..world_meta::FORMAT_VERSION).collect::<Vec<_>>());
+
+    for version in 1..serialization::FORMAT_VERSION {
+        assert!(FIXTURES_BY_VERSION.iter().any(|fixture| fixture.chunk_version == version), "no fixture for chunk version {}", version);
+    }
+    for version in 0..world_meta::FORMAT_VERSION {
+        assert!(FIXTURES_BY_VERSION.iter().any(|fixture| fixture.metadata_version == version), "no fixture for metadata version {}", version);
+    }
+}
+
+#[test]
+fn test_old_saves_load_as_the_current_version() {
+    let registry = Arc::new(MigrationRegistry::builtin());
+    for fixture in FIXTURES_BY_VERSION.iter() {
+        let root = copy_fixture(fixture);
+
+        let chunk_file = fs::read(root.join("chunks").join(format!("{}_{}_{}.chunk", fixture.chunk.x, fixture.chunk.y, fixture.chunk.z))).unwrap();
+        assert_eq!(u16::from_le_bytes([chunk_file[4], chunk_file[5]]), fixture.chunk_version, "{}", fixture.dir);
+
+        let metadata = WorldMetadata::load(&root, &registry).unwrap().unwrap();
+        assert_eq!(metadata.format_version, world_meta::FORMAT_VERSION);
+        assert_eq!(metadata.name, format!("saved by {}", fixture.dir));
+        assert_eq!(metadata.player_position, [1.0, 20.0, -3.0]);
+        assert_eq!(metadata.generator_config(&BlockRegistry::builtin()).unwrap().world_bottom, Some(-64));
+        // Worlds from before the generator was stored were all generated by the perlin generator
+        let generator = if fixture.metadata_version < 2 { "perlin" } else { "flat" };
+        assert_eq!(metadata.generator, generator, "{}", fixture.dir);
+        if fixture.metadata_version < 2 {
+            assert_eq!(metadata.seed, PerlinHeightmapWorldGenerator::default().seed);
+        }
+
+        let mut storage = ChunkStorage::open_with(root.path(), registry.clone(), BlockRegistry::builtin()).unwrap();
+        let chunk = storage.load_now(fixture.chunk).unwrap().unwrap();
+        assert_eq!(chunk.position, fixture.chunk);
+        assert!(chunk.diff(&saved_chunk(fixture.chunk_version, fixture.chunk)).is_empty(), "{} did not load as saved", fixture.dir);
+        // The file was not moved aside
+        assert!(root.join("chunks").join(format!("{}_{}_{}.chunk", fixture.chunk.x, fixture.chunk.y, fixture.chunk.z)).exists());
+    }
+}
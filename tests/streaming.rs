@@ -0,0 +1,211 @@
+//! Runs the chunk streaming systems headless, moving the anchor camera along a scripted path,
+//! and checks the invariants of the streaming state machine every frame:
+//! no chunk is awaiting generation and loaded at once, every loaded entity holds its chunk,
+//! visible chunks end up meshed once the camera stops, and chunks left behind are collected.
+
+use std::{path::Path, time::Duration};
+
+use bevy::{
+    asset::AssetPlugin,
+    prelude::*,
+    render::{camera::CameraProjection, primitives::Frustum},
+    time::TimeUpdateStrategy,
+};
+use voxels_bevy_test::engine::{
+    anchor::StreamingAnchor,
+    autosave::DirtyChunks,
+    cache::ChunkCache,
+    chunk::{Chunk, ChunkPosition},
+    chunk_material::{ChunkMaterial, ChunkMaterials, ClipPlane},
+    generator::{update_visible_chunks, ChunkStreamingPlugin, EmptyChunkMarker, FlatWorldGenerator, WorldGeneratorConfig},
+    heightmap::HeightmapCache,
+    persistence::{ChunkLoadFailed, ChunkStorage},
+    serialization,
+    spawn_queue::ChunkSpawnQueue,
+    voxel::{Block, Voxel},
+    ChunkData,
+};
+
+#[path = "../src/temp_dir.rs"]
+mod temp_dir;
+use temp_dir::TempDir;
+
+const GENERATION_DISTANCE: usize = 5;
+
+/// `ChunkStreamingPlugin` with the world resources `ChunkPlugin` would insert, saving into `root`
+fn streaming_app(root: &Path) -> App {
+    let mut config = WorldGeneratorConfig::default_with(FlatWorldGenerator::default());
+    config.render_distance = GENERATION_DISTANCE - 2;
+    config.generation_distance = GENERATION_DISTANCE;
+
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(16)))
+        .init_asset::<Mesh>()
+        .init_asset::<ChunkMaterial>()
+        .insert_resource(config)
+        .insert_resource(ChunkStorage::open(root).unwrap())
+        .init_resource::<ChunkData>()
+        .init_resource::<ChunkCache>()
+        .init_resource::<DirtyChunks>()
+        .init_resource::<HeightmapCache>()
+        .init_resource::<ClipPlane>()
+        .init_resource::<ChunkMaterials>()
+        .add_plugins(ChunkStreamingPlugin)
+        .add_systems(Update, update_anchor_frustum.before(update_visible_chunks));
+
+    app.world.spawn((
+        Transform::from_xyz(8.0, 12.0, 8.0).looking_to(Vec3::X, Vec3::Y),
+        Frustum::default(),
+        StreamingAnchor::default(),
+    ));
+    app
+}
+
+/// There is no renderer to compute the frustum from the camera projection
+fn update_anchor_frustum(mut anchors: Query<(&Transform, &mut Frustum), With<StreamingAnchor>>) {
+    let projection = PerspectiveProjection::default();
+    for (transform, mut frustum) in anchors.iter_mut() {
+        let view_projection = projection.get_projection_matrix() * transform.compute_matrix().inverse();
+        *frustum = Frustum::from_view_projection(&view_projection);
+    }
+}
+
+fn camera_chunk(app: &mut App) -> ChunkPosition {
+    let transform = app.world.query_filtered::<&Transform, With<StreamingAnchor>>().single(&app.world);
+    ChunkPosition::from_world_position(transform.translation)
+}
+
+fn move_camera(app: &mut App, offset: Vec3) {
+    let mut transform = app.world.query_filtered::<&mut Transform, With<StreamingAnchor>>().single_mut(&mut app.world);
+    transform.translation += offset;
+}
+
+/// Runs a frame and checks the invariants that must hold after every frame
+fn update_and_check(app: &mut App) {
+    app.update();
+
+    let chunk_data = app.world.resource::<ChunkData>();
+    for chunk_pos in chunk_data.awaiting_generation.keys() {
+        assert!(!chunk_data.loaded.contains_key(chunk_pos), "chunk {:?} is awaiting generation and loaded", chunk_pos);
+    }
+    for (chunk_pos, entity) in chunk_data.loaded.iter() {
+        let chunk = app.world.get::<Chunk>(*entity);
+        assert_eq!(chunk.map(|chunk| chunk.position), Some(*chunk_pos), "loaded entity of {:?} does not hold it", chunk_pos);
+    }
+}
+
+/// Visible chunks that are not loaded yet, or loaded but neither meshed nor known to be empty
+fn unfinished_visible_chunks(app: &App) -> Vec<ChunkPosition> {
+    let chunk_data = app.world.resource::<ChunkData>();
+    chunk_data
+        .visible
+        .iter()
+        .filter(|chunk_pos| match chunk_data.loaded.get(*chunk_pos) {
+            None => true,
+            Some(entity) => {
+                let is_empty = app.world.get::<EmptyChunkMarker>(*entity).is_some();
+                let has_mesh = app.world.get::<Handle<Mesh>>(*entity).is_some();
+                !is_empty && !has_mesh
+            }
+        })
+        .copied()
+        .collect()
+}
+
+/// Runs frames until the visible chunks are all meshed, generation and meshing run on task pools
+fn settle(app: &mut App) {
+    for _ in 0..2000 {
+        update_and_check(app);
+        if !app.world.resource::<ChunkData>().visible.is_empty() && unfinished_visible_chunks(app).is_empty() {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    panic!("visible chunks were never finished: {:?}", unfinished_visible_chunks(app));
+}
+
+#[test]
+fn test_visible_chunks_get_meshed() {
+    let root = TempDir::new("streaming-meshed");
+    let mut app = streaming_app(root.path());
+    settle(&mut app);
+
+    let chunk_data = app.world.resource::<ChunkData>();
+    let camera = ChunkPosition::new(0, 0, 0);
+    assert!(chunk_data.visible.contains(&camera));
+    assert!(chunk_data.visible.contains(&ChunkPosition::new(GENERATION_DISTANCE as i32, 0, 0)));
+    // The flat ground is opaque, nothing below its top layer is visible and that layer is meshed
+    assert!(chunk_data.visible.iter().all(|chunk| chunk.y >= -1));
+    assert!(chunk_data.visible.iter().any(|chunk| chunk.y == -1 && chunk_data.meshes.contains_key(chunk)));
+}
+
+#[test]
+fn test_chunks_left_behind_are_collected() {
+    let root = TempDir::new("streaming-collected");
+    let mut app = streaming_app(root.path());
+    settle(&mut app);
+    let start = camera_chunk(&mut app);
+
+    // Fly away along x, half a chunk per frame, and let the streaming catch up at the end
+    for _ in 0..GENERATION_DISTANCE * 8 {
+        move_camera(&mut app, Vec3::new(8.0, 0.0, 0.0));
+        update_and_check(&mut app);
+    }
+    settle(&mut app);
+
+    let camera = camera_chunk(&mut app);
+    assert!(camera.x - start.x > GENERATION_DISTANCE as i32 * 2);
+    // The garbage collector forces a collection at least every 600 frames
+    for _ in 0..600 {
+        update_and_check(&mut app);
+    }
+
+    let chunk_data = app.world.resource::<ChunkData>();
+    let too_far = chunk_data
+        .loaded
+        .keys()
+        .filter(|chunk_pos| chunk_pos.distance_to(&camera) > GENERATION_DISTANCE as f32 && !chunk_data.visible.contains(*chunk_pos))
+        .collect::<Vec<_>>();
+    assert!(too_far.is_empty(), "chunks out of range are still loaded: {:?}", too_far);
+    assert!(!chunk_data.loaded.contains_key(&start));
+    let loaded = chunk_data.loaded.len();
+    assert_eq!(app.world.resource::<ChunkSpawnQueue>().pending_despawns(), 0);
+    assert_eq!(app.world.query::<&Chunk>().iter(&app.world).count(), loaded);
+    assert!(unfinished_visible_chunks(&app).is_empty());
+}
+
+#[derive(Resource, Default)]
+struct LoadFailures(usize);
+
+#[test]
+fn test_chunks_from_newer_builds_are_generated_once() {
+    let root = TempDir::new("streaming-newer");
+    // Ground under the camera, saved by a build with a newer chunk format
+    let position = ChunkPosition::new(0, -1, 0);
+    let mut stored = Chunk::new(position);
+    stored.fill(Voxel::from(Block::Glass));
+    let mut bytes = serialization::encode(&stored);
+    serialization::set_version(&mut bytes, serialization::FORMAT_VERSION + 1);
+    let path = root.join("chunks").join(format!("{}_{}_{}.chunk", position.x, position.y, position.z));
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, &bytes).unwrap();
+
+    let mut app = streaming_app(root.path());
+    app.init_resource::<LoadFailures>().add_systems(Update, |mut events: EventReader<ChunkLoadFailed>, mut failures: ResMut<LoadFailures>| {
+        failures.0 += events.read().count();
+    });
+    settle(&mut app);
+    for _ in 0..100 {
+        update_and_check(&mut app);
+    }
+
+    assert_eq!(app.world.resource::<LoadFailures>().0, 1);
+    let entity = app.world.resource::<ChunkData>().loaded[&position];
+    let chunk = app.world.get::<Chunk>(entity).unwrap();
+    assert!(!chunk.diff(&stored).is_empty(), "the chunk was not generated");
+    assert!(app.world.resource::<ChunkStorage>().is_unreadable(&position));
+
+    app.world.resource_mut::<ChunkStorage>().shutdown();
+    assert_eq!(std::fs::read(&path).unwrap(), bytes);
+}